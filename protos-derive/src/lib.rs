@@ -0,0 +1,472 @@
+//! `#[derive(ProtoSchema)]`, re-exported as `protos::schema::ProtoSchema`.
+//!
+//! Structs with named fields become messages, enums of unit variants
+//! become enums, and enums whose variants each hold one value become
+//! oneofs. The input is parsed directly from the token stream, so only
+//! those shapes are accepted, without generics.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+/// Describes a struct or enum as a protobuf message, enum or oneof.
+///
+/// Every struct field needs `#[proto(tag = N)]` unless it has
+/// `#[proto(skip)]`. `#[proto(type = "sint32")]` picks a protobuf type for
+/// a field where the Rust type allows several, `#[proto(oneof)]` marks an
+/// `Option` of a oneof enum, and `#[proto(name = "...")]` renames a type,
+/// field or value. Enum values take their numbers from `tag`, their
+/// discriminant, or the previous value plus one.
+#[proc_macro_derive(ProtoSchema, attributes(proto))]
+pub fn derive_proto_schema(input: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let code = match expand(&tokens) {
+        Ok(code) => code,
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    code.parse().unwrap()
+}
+
+/// The contents of `#[proto(...)]` attributes.
+#[derive(Default)]
+struct Attrs {
+    tag: Option<i64>,
+    name: Option<String>,
+    proto_type: Option<String>,
+    oneof: bool,
+    skip: bool,
+}
+
+struct Field {
+    attrs: Attrs,
+    ident: String,
+    ty: String,
+}
+
+struct Variant {
+    attrs: Attrs,
+    ident: String,
+    /// The type held by a oneof variant.
+    ty: Option<String>,
+    discriminant: Option<i64>,
+}
+
+fn expand(tokens: &[TokenTree]) -> Result<String, String> {
+    let mut i = 0;
+    let attrs = attributes(tokens, &mut i)?;
+    visibility(tokens, &mut i);
+    let keyword = ident_at(tokens, i).ok_or("expected a struct or enum")?;
+    let name = ident_at(tokens, i + 1).ok_or("expected a type name")?;
+    i += 2;
+    if matches!(tokens.get(i), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        return Err(format!(
+            "ProtoSchema does not support generic types like {}",
+            name
+        ));
+    }
+    let body = match tokens.get(i) {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+        _ => {
+            return Err(format!(
+                "ProtoSchema needs {} to have named fields or variants",
+                name
+            ))
+        }
+    };
+    let body: Vec<TokenTree> = body.stream().into_iter().collect();
+    let proto_name = attrs.name.unwrap_or_else(|| name.clone());
+    match keyword.as_str() {
+        "struct" => message(&name, &proto_name, &body),
+        "enum" => {
+            let variants = split(&body)
+                .into_iter()
+                .map(|tokens| variant(&tokens))
+                .collect::<Result<Vec<_>, _>>()?;
+            if variants.iter().any(|variant| variant.ty.is_some()) {
+                oneof(&name, &variants)
+            } else {
+                enumeration(&name, &proto_name, &variants)
+            }
+        }
+        _ => Err("ProtoSchema can only be derived for structs and enums".to_string()),
+    }
+}
+
+fn message(name: &str, proto_name: &str, body: &[TokenTree]) -> Result<String, String> {
+    let mut statements = String::new();
+    for tokens in split(body) {
+        let field = field(&tokens)?;
+        if field.attrs.skip {
+            continue;
+        }
+        let field_name = field.attrs.name.unwrap_or_else(|| field.ident.clone());
+        if field.attrs.oneof {
+            let inner = option_inner(&field.ty)
+                .ok_or_else(|| format!("oneof field {} must be an Option", field.ident))?;
+            statements.push_str(&format!(
+                "message.oneofs.push(<{} as ::protos::schema::ProtoOneof>::oneof({:?}));\n",
+                inner, field_name
+            ));
+            continue;
+        }
+        let tag = field
+            .attrs
+            .tag
+            .ok_or_else(|| format!("field {} needs #[proto(tag = N)]", field.ident))?;
+        statements.push_str(&format!(
+            "message.fields.push(<{} as ::protos::schema::ProtoField>::field({:?}.to_string(), {}, {}));\n",
+            field.ty,
+            field_name,
+            tag,
+            scalar(field.attrs.proto_type.as_deref())?
+        ));
+    }
+    let binding = if statements.is_empty() {
+        "message"
+    } else {
+        "mut message"
+    };
+    Ok(format!(
+        "impl ::protos::schema::ProtoType for {name} {{
+            const MESSAGE: bool = true;
+            fn proto_type() -> ::protos::Type {{
+                ::protos::Type::Named({proto_name:?}.to_string())
+            }}
+        }}
+        impl ::protos::schema::ProtoField for {name} {{
+            fn field(name: String, tag: u32, scalar: Option<::protos::Type>) -> ::protos::MessageField {{
+                ::protos::schema::singular::<Self>(name, tag, scalar)
+            }}
+        }}
+        impl ::protos::schema::ProtoSchema for {name} {{
+            fn schema() -> ::protos::schema::Schema {{
+                let {binding} = ::protos::Message {{
+                    name: {proto_name:?}.to_string(),
+                    ..::std::default::Default::default()
+                }};
+                {statements}
+                ::protos::schema::Schema::Message(message)
+            }}
+        }}"
+    ))
+}
+
+fn enumeration(name: &str, proto_name: &str, variants: &[Variant]) -> Result<String, String> {
+    let prefix = screaming(proto_name);
+    let mut values = String::new();
+    let mut next = 0;
+    for variant in variants {
+        let number = variant.attrs.tag.or(variant.discriminant).unwrap_or(next);
+        if number < 0 {
+            return Err(format!("enum value {} must not be negative", variant.ident));
+        }
+        next = number + 1;
+        let value_name = match &variant.attrs.name {
+            Some(name) => name.clone(),
+            None => format!("{}_{}", prefix, screaming(&variant.ident)),
+        };
+        values.push_str(&format!(
+            "::protos::EnumField {{ name: {:?}.to_string(), position: {} }},\n",
+            value_name, number
+        ));
+    }
+    Ok(format!(
+        "impl ::protos::schema::ProtoType for {name} {{
+            fn proto_type() -> ::protos::Type {{
+                ::protos::Type::Named({proto_name:?}.to_string())
+            }}
+        }}
+        impl ::protos::schema::ProtoField for {name} {{
+            fn field(name: String, tag: u32, scalar: Option<::protos::Type>) -> ::protos::MessageField {{
+                ::protos::schema::singular::<Self>(name, tag, scalar)
+            }}
+        }}
+        impl ::protos::schema::ProtoSchema for {name} {{
+            fn schema() -> ::protos::schema::Schema {{
+                ::protos::schema::Schema::Enum(::protos::Enum {{
+                    name: {proto_name:?}.to_string(),
+                    fields: vec![{values}],
+                    ..::std::default::Default::default()
+                }})
+            }}
+        }}"
+    ))
+}
+
+fn oneof(name: &str, variants: &[Variant]) -> Result<String, String> {
+    let mut statements = String::new();
+    for variant in variants {
+        let ty = variant
+            .ty
+            .as_ref()
+            .ok_or_else(|| format!("oneof variant {} must hold one value", variant.ident))?;
+        let tag = variant
+            .attrs
+            .tag
+            .ok_or_else(|| format!("oneof variant {} needs #[proto(tag = N)]", variant.ident))?;
+        let field_name = match &variant.attrs.name {
+            Some(name) => name.clone(),
+            None => screaming(&variant.ident).to_lowercase(),
+        };
+        statements.push_str(&format!(
+            "oneof.fields.push(<{} as ::protos::schema::ProtoField>::field({:?}.to_string(), {}, {}));\n",
+            ty,
+            field_name,
+            tag,
+            scalar(variant.attrs.proto_type.as_deref())?
+        ));
+    }
+    Ok(format!(
+        "impl ::protos::schema::ProtoOneof for {name} {{
+            fn oneof(name: &str) -> ::protos::Oneof {{
+                let mut oneof = ::protos::Oneof {{
+                    name: name.to_string(),
+                    ..::std::default::Default::default()
+                }};
+                {statements}
+                oneof
+            }}
+        }}"
+    ))
+}
+
+/// The `Option<Type>` expression for a `type = "..."` override.
+fn scalar(proto_type: Option<&str>) -> Result<String, String> {
+    const SCALARS: &[(&str, &str)] = &[
+        ("double", "Double"),
+        ("float", "Float"),
+        ("int32", "Int32"),
+        ("int64", "Int64"),
+        ("uint32", "Uint32"),
+        ("uint64", "Uint64"),
+        ("sint32", "Sint32"),
+        ("sint64", "Sint64"),
+        ("fixed32", "Fixed32"),
+        ("fixed64", "Fixed64"),
+        ("sfixed32", "Sfixed32"),
+        ("sfixed64", "Sfixed64"),
+        ("bool", "Bool"),
+        ("string", "String"),
+        ("bytes", "Bytes"),
+    ];
+    match proto_type {
+        None => Ok("None".to_string()),
+        Some(name) => SCALARS
+            .iter()
+            .find(|(proto, _)| *proto == name)
+            .map(|(_, variant)| format!("Some(::protos::Type::{})", variant))
+            .ok_or_else(|| format!("unknown protobuf type {}", name)),
+    }
+}
+
+fn field(tokens: &[TokenTree]) -> Result<Field, String> {
+    let mut i = 0;
+    let attrs = attributes(tokens, &mut i)?;
+    visibility(tokens, &mut i);
+    let ident = ident_at(tokens, i).ok_or("expected a field name")?;
+    match tokens.get(i + 1) {
+        Some(TokenTree::Punct(p)) if p.as_char() == ':' => {}
+        _ => return Err(format!("expected a type after {}", ident)),
+    }
+    let ty: TokenStream = tokens[i + 2..].iter().cloned().collect();
+    Ok(Field {
+        attrs,
+        ident: ident.trim_start_matches("r#").to_string(),
+        ty: ty.to_string(),
+    })
+}
+
+fn variant(tokens: &[TokenTree]) -> Result<Variant, String> {
+    let mut i = 0;
+    let attrs = attributes(tokens, &mut i)?;
+    let ident = ident_at(tokens, i).ok_or("expected a variant name")?;
+    i += 1;
+    let mut ty = None;
+    if let Some(TokenTree::Group(group)) = tokens.get(i) {
+        let fields: Vec<TokenTree> = group.stream().into_iter().collect();
+        if group.delimiter() != Delimiter::Parenthesis || split(&fields).len() != 1 {
+            return Err(format!(
+                "variant {} must be a unit or hold one value",
+                ident
+            ));
+        }
+        ty = Some(group.stream().to_string());
+        i += 1;
+    }
+    let discriminant = match tokens.get(i) {
+        Some(TokenTree::Punct(p)) if p.as_char() == '=' => Some(integer(&tokens[i + 1..])?),
+        _ => None,
+    };
+    Ok(Variant {
+        attrs,
+        ident,
+        ty,
+        discriminant,
+    })
+}
+
+/// Reads the attributes at `tokens[*i..]`, keeping those of `#[proto]`.
+fn attributes(tokens: &[TokenTree], i: &mut usize) -> Result<Attrs, String> {
+    let mut attrs = Attrs::default();
+    while let (Some(TokenTree::Punct(p)), Some(TokenTree::Group(group))) =
+        (tokens.get(*i), tokens.get(*i + 1))
+    {
+        if p.as_char() != '#' {
+            break;
+        }
+        *i += 2;
+        let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+        let args = match (inner.first(), inner.get(1)) {
+            (Some(TokenTree::Ident(name)), Some(TokenTree::Group(args)))
+                if name.to_string() == "proto" =>
+            {
+                args
+            }
+            _ => continue,
+        };
+        let args: Vec<TokenTree> = args.stream().into_iter().collect();
+        for arg in split(&args) {
+            let key = ident_at(&arg, 0).ok_or("expected a proto attribute name")?;
+            let value = arg.get(2..).unwrap_or_default();
+            match key.as_str() {
+                "tag" => attrs.tag = Some(integer(value)?),
+                "name" => attrs.name = Some(string(value)?),
+                "type" => attrs.proto_type = Some(string(value)?),
+                "oneof" => attrs.oneof = true,
+                "skip" => attrs.skip = true,
+                _ => return Err(format!("unknown proto attribute {}", key)),
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+fn visibility(tokens: &[TokenTree], i: &mut usize) {
+    if ident_at(tokens, *i).as_deref() == Some("pub") {
+        *i += 1;
+        if let Some(TokenTree::Group(group)) = tokens.get(*i) {
+            if group.delimiter() == Delimiter::Parenthesis {
+                *i += 1;
+            }
+        }
+    }
+}
+
+fn ident_at(tokens: &[TokenTree], i: usize) -> Option<String> {
+    match tokens.get(i) {
+        Some(TokenTree::Ident(ident)) => Some(ident.to_string()),
+        _ => None,
+    }
+}
+
+fn integer(tokens: &[TokenTree]) -> Result<i64, String> {
+    let text: String = tokens.iter().map(|token| token.to_string()).collect();
+    text.replace('_', "")
+        .parse()
+        .map_err(|_| format!("expected an integer, found {}", text))
+}
+
+fn string(tokens: &[TokenTree]) -> Result<String, String> {
+    let text = match tokens {
+        [TokenTree::Literal(literal)] => literal.to_string(),
+        _ => String::new(),
+    };
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .map(str::to_string)
+        .ok_or_else(|| format!("expected a string, found {}", text))
+}
+
+/// Splits at top-level commas, which are not inside `<...>`. The `>` of
+/// an `->` closes nothing.
+fn split(tokens: &[TokenTree]) -> Vec<Vec<TokenTree>> {
+    let mut items = vec![vec![]];
+    let mut depth = 0;
+    let mut arrow = false;
+    for token in tokens {
+        if let TokenTree::Punct(p) = token {
+            match p.as_char() {
+                '<' => depth += 1,
+                '>' if !arrow => depth -= 1,
+                ',' if depth == 0 => {
+                    items.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        arrow = matches!(token, TokenTree::Punct(p) if p.as_char() == '-' && p.spacing() == Spacing::Joint);
+        items.last_mut().unwrap().push(token.clone());
+    }
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// The `T` of a type written `Option<T>`.
+fn option_inner(ty: &str) -> Option<&str> {
+    let ty = ty.trim();
+    let rest = [
+        "Option",
+        "std :: option :: Option",
+        ":: std :: option :: Option",
+    ]
+    .iter()
+    .find_map(|prefix| ty.strip_prefix(prefix))?;
+    rest.trim()
+        .strip_prefix('<')?
+        .strip_suffix('>')
+        .map(str::trim)
+}
+
+/// `SCREAMING_SNAKE_CASE`: `OrderStatus` becomes `ORDER_STATUS`.
+fn screaming(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut ret = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|c| c.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                ret.push('_');
+            }
+        }
+        ret.push(c.to_ascii_uppercase());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screaming_names() {
+        assert_eq!(screaming("OrderStatus"), "ORDER_STATUS");
+        assert_eq!(screaming("HTTPRule"), "HTTP_RULE");
+        assert_eq!(screaming("V2Api"), "V2_API");
+    }
+
+    #[test]
+    fn option_types() {
+        assert_eq!(option_inner("Option < Payment >"), Some("Payment"));
+        assert_eq!(
+            option_inner(":: std :: option :: Option < a :: B >"),
+            Some("a :: B")
+        );
+        assert_eq!(option_inner("Vec < Payment >"), None);
+    }
+
+    #[test]
+    fn scalar_overrides() {
+        assert_eq!(scalar(None).unwrap(), "None");
+        assert_eq!(
+            scalar(Some("sfixed64")).unwrap(),
+            "Some(::protos::Type::Sfixed64)"
+        );
+        assert_eq!(
+            scalar(Some("int128")).unwrap_err(),
+            "unknown protobuf type int128"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Expands `#[derive(ProtoSchema)]` on the shapes it accepts.
+
+use protos::schema::{self, ProtoSchema};
+use std::collections::HashMap;
+
+#[derive(ProtoSchema)]
+#[allow(dead_code)]
+struct Blob {
+    #[proto(tag = 1)]
+    hash: Option<Vec<u8>>,
+    #[proto(skip)]
+    check: Option<fn(&[u8]) -> bool>,
+    #[proto(tag = 2)]
+    chunks: Vec<Vec<u8>>,
+    #[proto(skip)]
+    on_write: Box<dyn Fn(u32) -> u32>,
+    #[proto(tag = 3)]
+    parts: HashMap<String, Vec<u8>>,
+}
+
+#[derive(ProtoSchema)]
+#[allow(dead_code)]
+enum Body {
+    #[proto(tag = 1)]
+    Raw(Vec<u8>),
+    #[proto(tag = 2, type = "sint32")]
+    Offset(i32),
+}
+
+#[derive(ProtoSchema)]
+#[allow(dead_code)]
+struct Upload {
+    #[proto(oneof)]
+    body: Option<Body>,
+}
+
+#[test]
+fn bytes_fields() {
+    let file = schema::file("blob.proto", None, vec![Blob::schema()]);
+    assert_eq!(
+        protos::codegen::format(&file),
+        "syntax = \"proto3\";\n\nmessage Blob {\n  optional bytes hash = 1;\n  repeated bytes chunks = 2;\n  map<string, bytes> parts = 3;\n}\n"
+    );
+}
+
+#[test]
+fn oneofs() {
+    let file = schema::file("upload.proto", None, vec![Upload::schema()]);
+    assert_eq!(
+        protos::codegen::format(&file),
+        "syntax = \"proto3\";\n\nmessage Upload {\n  oneof body {\n    bytes raw = 1;\n    sint32 offset = 2;\n  }\n}\n"
+    );
+}
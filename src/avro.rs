@@ -0,0 +1,806 @@
+//! Conversion between protobuf schemas and Avro schemas (`.avsc`), in
+//! both directions.
+//!
+//! Exporting turns messages into records, enums into enums, repeated
+//! fields into arrays and maps into maps. Fields with presence, such as
+//! submessages and `optional` scalars, are unions with `null` that default
+//! to it; other fields default to their zero value. A oneof is one field,
+//! a union of `null` and its members' types.
+//!
+//! Importing reverses this into a proto3 [`ProtoFile`]: every named type
+//! becomes a top-level message or enum, unions with `null` become
+//! `optional` fields and other unions oneofs.
+//!
+//! Neither direction is exact. Both return the [`Loss`]es they made, such
+//! as field numbers, which Avro doesn't have, or unsigned integers, which
+//! it can't hold.
+
+use crate::codegen::json_schema::{self, Definition, Kind};
+use crate::codegen::snake;
+use crate::json::{self, Json};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Something a conversion could not carry over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loss {
+    /// The full name of the type or field concerned.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input is not valid JSON.
+    Json(json::Error),
+    /// The input is not a valid Avro schema: where, and why.
+    Invalid(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(err) => write!(f, "{}", err),
+            Error::Invalid(path, message) => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<json::Error> for Error {
+    fn from(err: json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+/// Generates `package.Message.avsc` for each top-level message in the
+/// named files, with the types it uses defined inline, and what they lose.
+pub fn generate(set: &FileSet, files: &[&str]) -> (BTreeMap<String, String>, Vec<Loss>) {
+    let mut ret = BTreeMap::new();
+    let mut losses = vec![];
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(&file.name.as_str()))
+    {
+        for message in &file.messages {
+            let name = match &file.package {
+                Some(package) => format!("{}.{}", package, message.name),
+                None => message.name.clone(),
+            };
+            if let Some((schema, lost)) = export(set, &name) {
+                ret.insert(format!("{}.avsc", name), schema);
+                for loss in lost {
+                    // Types shared between messages lose the same things.
+                    if !losses.contains(&loss) {
+                        losses.push(loss);
+                    }
+                }
+            }
+        }
+    }
+    (ret, losses)
+}
+
+/// The Avro schema of a message, by full name with or without the leading
+/// dot, and what it loses; `None` if there is no such message.
+pub fn export(set: &FileSet, name: &str) -> Option<(String, Vec<Loss>)> {
+    let name = format!(".{}", name.strip_prefix('.').unwrap_or(name));
+    set.find_message(&name)?;
+    let definitions = json_schema::definitions(set);
+    let mut exporter = Exporter {
+        set,
+        definitions: &definitions,
+        defined: BTreeSet::new(),
+        losses: vec![],
+    };
+    let schema = exporter.named(&name);
+    Some((schema.pretty(), exporter.losses))
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+/// A comment as an Avro `doc`, with each line trimmed.
+fn doc(comment: &str) -> Json {
+    let lines: Vec<&str> = comment.trim().lines().map(str::trim).collect();
+    string(&lines.join("\n"))
+}
+
+struct Exporter<'a> {
+    set: &'a FileSet,
+    definitions: &'a BTreeMap<String, Definition<'a>>,
+    /// The named types already defined, which later uses refer to by name.
+    defined: BTreeSet<String>,
+    losses: Vec<Loss>,
+}
+
+impl Exporter<'_> {
+    fn loss(&mut self, path: &str, message: impl Into<String>) {
+        self.losses.push(Loss {
+            path: path.strip_prefix('.').unwrap_or(path).to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// A message or enum, defined in full where first used.
+    fn named(&mut self, full_name: &str) -> Json {
+        if !self.defined.insert(full_name.to_string()) {
+            return string(&full_name[1..]);
+        }
+        let definition = &self.definitions[full_name];
+        let (namespace, name) = full_name[1..]
+            .rsplit_once('.')
+            .unwrap_or(("", &full_name[1..]));
+        let kind = match definition.kind {
+            Kind::Message(_) => "record",
+            Kind::Enum(_) => "enum",
+        };
+        let mut members = vec![("type", string(kind)), ("name", string(name))];
+        if !namespace.is_empty() {
+            members.push(("namespace", string(namespace)));
+        }
+        if let Some(comment) = definition.comment {
+            members.push(("doc", doc(comment)));
+        }
+        match definition.kind {
+            Kind::Message(message) => {
+                let fields = self.fields(full_name, message, definition);
+                members.push(("fields", Json::Array(fields)));
+            }
+            Kind::Enum(e) => {
+                let symbols = e.fields.iter().map(|value| string(&value.name)).collect();
+                members.push(("symbols", Json::Array(symbols)));
+                if let Some(zero) = e.fields.iter().find(|value| value.position == 0) {
+                    members.push(("default", string(&zero.name)));
+                }
+                if e.fields
+                    .iter()
+                    .enumerate()
+                    .any(|(i, value)| value.position as usize != i)
+                {
+                    self.loss(
+                        full_name,
+                        "enum numbers are not kept, only the order of values",
+                    );
+                }
+            }
+        }
+        object(members)
+    }
+
+    fn fields(&mut self, full_name: &str, message: &Message, definition: &Definition) -> Vec<Json> {
+        if !message.fields.is_empty() || !message.oneofs.is_empty() {
+            self.loss(full_name, "field numbers are not kept");
+        }
+        let mut ret = vec![];
+        for field in &message.fields {
+            let comment = definition.fields.get(&field.position()).copied();
+            ret.push(self.field(full_name, field, comment));
+        }
+        for oneof in &message.oneofs {
+            let path = format!("{}.{}", full_name, oneof.name);
+            let mut types = vec![string("null")];
+            for field in &oneof.fields {
+                let field_path = format!("{}.{}", full_name, field.name());
+                let t = self.value(&field_path, &field.field_type());
+                types.push(t);
+            }
+            // A union can't hold the same type twice.
+            let mut seen = BTreeSet::new();
+            let distinct = oneof.fields.iter().all(|field| match field.field_type() {
+                Type::Message(name) | Type::Enum(name) => seen.insert(name),
+                t => seen.insert(primitive(&t).to_string()),
+            });
+            if distinct {
+                ret.push(object(vec![
+                    ("name", string(&oneof.name)),
+                    ("type", Json::Array(types)),
+                    ("default", Json::Null),
+                ]));
+                continue;
+            }
+            self.loss(
+                &path,
+                "oneof members of the same type become separate fields",
+            );
+            for (field, t) in oneof.fields.iter().zip(types.into_iter().skip(1)) {
+                let comment = definition.fields.get(&field.position()).copied();
+                let mut members = vec![
+                    ("name", string(field.name())),
+                    ("type", Json::Array(vec![string("null"), t])),
+                    ("default", Json::Null),
+                ];
+                if let Some(comment) = comment {
+                    members.push(("doc", doc(comment)));
+                }
+                ret.push(object(members));
+            }
+        }
+        ret
+    }
+
+    fn field(&mut self, owner: &str, field: &MessageField, comment: Option<&String>) -> Json {
+        let path = format!("{}.{}", owner, field.name());
+        let t = field.field_type();
+        let (schema, default) = match &t {
+            Type::Map(key, value) => {
+                if **key != Type::String {
+                    self.loss(
+                        &path,
+                        format!("{} map keys become strings", String::from(*key.clone())),
+                    );
+                }
+                let values = self.value(&path, value);
+                (
+                    object(vec![("type", string("map")), ("values", values)]),
+                    Some(object(vec![])),
+                )
+            }
+            t if field.is_repeated() => {
+                let items = self.value(&path, t);
+                (
+                    object(vec![("type", string("array")), ("items", items)]),
+                    Some(Json::Array(vec![])),
+                )
+            }
+            Type::Message(_) => (
+                Json::Array(vec![string("null"), self.value(&path, &t)]),
+                Some(Json::Null),
+            ),
+            t => match field.frequency() {
+                Some(Frequency::Optional) => (
+                    Json::Array(vec![string("null"), self.value(&path, t)]),
+                    Some(Json::Null),
+                ),
+                Some(Frequency::Required) => (self.value(&path, t), None),
+                _ => {
+                    let zero = self.zero(t);
+                    (self.value(&path, t), Some(zero))
+                }
+            },
+        };
+        let mut members = vec![("name", string(field.name())), ("type", schema)];
+        if let Some(default) = default {
+            members.push(("default", default));
+        }
+        if let Some(comment) = comment {
+            members.push(("doc", doc(comment)));
+        }
+        object(members)
+    }
+
+    /// The schema of a single value of type `t`.
+    fn value(&mut self, path: &str, t: &Type) -> Json {
+        match t {
+            Type::Message(name) | Type::Enum(name) => self.named(name),
+            t => {
+                if matches!(t, Type::Uint64 | Type::Fixed64) {
+                    let message = format!(
+                        "{} values above 2^63 - 1 do not fit a long",
+                        String::from(t.clone())
+                    );
+                    self.loss(path, message);
+                }
+                string(primitive(t))
+            }
+        }
+    }
+
+    /// The default of a field without presence.
+    fn zero(&self, t: &Type) -> Json {
+        match t {
+            Type::Bool => Json::Bool(false),
+            Type::Float | Type::Double => Json::Number("0.0".to_string()),
+            Type::String | Type::Bytes => string(""),
+            Type::Enum(name) => {
+                let e = self.set.find_enum(name).expect("enum types are linked");
+                match e
+                    .fields
+                    .iter()
+                    .find(|value| value.position == 0)
+                    .or(e.fields.first())
+                {
+                    Some(value) => string(&value.name),
+                    None => Json::Null,
+                }
+            }
+            _ => Json::Number("0".to_string()),
+        }
+    }
+}
+
+/// Converts an Avro schema, a named type or a list of them, into a proto3
+/// file named `name` whose package is the namespace of the first type.
+pub fn import(source: &str, name: &str) -> Result<(ProtoFile, Vec<Loss>), Error> {
+    let json = Json::parse(source)?;
+    let schemas = match &json {
+        Json::Array(schemas) => schemas.iter().collect(),
+        json => vec![json],
+    };
+    let mut importer = Importer {
+        package: None,
+        types: BTreeMap::new(),
+        file: ProtoFile {
+            name: name.to_string(),
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        },
+        losses: vec![],
+    };
+    for schema in schemas {
+        if !matches!(schema, Json::Object(_)) {
+            return Err(Error::Invalid(
+                name.to_string(),
+                "a schema file must define named types".to_string(),
+            ));
+        }
+        importer.schema(schema, "", name)?;
+    }
+    importer.file.package = importer.package.filter(|package| !package.is_empty());
+    Ok((importer.file, importer.losses))
+}
+
+/// An Avro type as far as protobuf can follow it.
+enum Avro {
+    Null,
+    Type(Type),
+    Array(Box<Avro>),
+    Map(Box<Avro>),
+    Union(Vec<Avro>),
+}
+
+fn member<'a>(members: &'a [(String, Json)], key: &str) -> Option<&'a Json> {
+    members
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value)
+}
+
+fn text<'a>(members: &'a [(String, Json)], key: &str) -> Option<&'a str> {
+    match member(members, key) {
+        Some(Json::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether a default is what protobuf assumes anyway.
+fn is_zero(json: &Json) -> bool {
+    match json {
+        Json::Null | Json::Bool(false) => true,
+        Json::Number(n) => n.parse::<f64>() == Ok(0.0),
+        Json::String(s) => s.is_empty(),
+        Json::Array(values) => values.is_empty(),
+        Json::Object(members) => members.is_empty(),
+        Json::Bool(true) => false,
+    }
+}
+
+struct Importer {
+    /// The namespace of the first named type, which becomes the package.
+    package: Option<String>,
+    /// What each named type, by full name, becomes.
+    types: BTreeMap<String, Type>,
+    file: ProtoFile,
+    losses: Vec<Loss>,
+}
+
+impl Importer {
+    fn loss(&mut self, path: &str, message: impl Into<String>) {
+        self.losses.push(Loss {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn invalid(path: &str, message: impl Into<String>) -> Error {
+        Error::Invalid(path.to_string(), message.into())
+    }
+
+    fn schema(&mut self, json: &Json, namespace: &str, path: &str) -> Result<Avro, Error> {
+        let members = match json {
+            Json::String(name) => return self.reference(name, namespace, path),
+            Json::Array(types) => {
+                let types = types
+                    .iter()
+                    .map(|t| self.schema(t, namespace, path))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Avro::Union(types));
+            }
+            Json::Object(members) => members,
+            _ => return Err(Self::invalid(path, "expected a type")),
+        };
+        let t = match member(members, "type") {
+            Some(Json::String(t)) => t.as_str(),
+            Some(t) => return self.schema(t, namespace, path),
+            None => return Err(Self::invalid(path, "type has no \"type\"")),
+        };
+        let ret = match t {
+            "record" | "error" => self.record(members, namespace, path)?,
+            "enum" => self.enumeration(members, namespace, path)?,
+            "fixed" => {
+                let (full_name, _) = self.name(members, namespace, path)?;
+                self.loss(&full_name, "fixed becomes bytes of any size");
+                self.types.insert(full_name, Type::Bytes);
+                Avro::Type(Type::Bytes)
+            }
+            "array" => {
+                let items = member(members, "items")
+                    .ok_or_else(|| Self::invalid(path, "array has no \"items\""))?;
+                Avro::Array(Box::new(self.schema(items, namespace, path)?))
+            }
+            "map" => {
+                let values = member(members, "values")
+                    .ok_or_else(|| Self::invalid(path, "map has no \"values\""))?;
+                Avro::Map(Box::new(self.schema(values, namespace, path)?))
+            }
+            primitive => self.reference(primitive, namespace, path)?,
+        };
+        if let Some(logical) = text(members, "logicalType") {
+            self.loss(
+                path,
+                format!("logical type {} becomes its underlying type", logical),
+            );
+        }
+        Ok(ret)
+    }
+
+    /// A primitive type or a named type defined earlier.
+    fn reference(&mut self, name: &str, namespace: &str, path: &str) -> Result<Avro, Error> {
+        Ok(Avro::Type(match name {
+            "null" => return Ok(Avro::Null),
+            "boolean" => Type::Bool,
+            "int" => Type::Int32,
+            "long" => Type::Int64,
+            "float" => Type::Float,
+            "double" => Type::Double,
+            "bytes" => Type::Bytes,
+            "string" => Type::String,
+            name => {
+                let full_name = match name.contains('.') || namespace.is_empty() {
+                    true => name.to_string(),
+                    false => format!("{}.{}", namespace, name),
+                };
+                match self.types.get(&full_name).or_else(|| self.types.get(name)) {
+                    Some(t) => t.clone(),
+                    None => return Err(Self::invalid(path, format!("unknown type {}", name))),
+                }
+            }
+        }))
+    }
+
+    /// The full name and namespace of a named type.
+    fn name(
+        &mut self,
+        members: &[(String, Json)],
+        namespace: &str,
+        path: &str,
+    ) -> Result<(String, String), Error> {
+        let name = text(members, "name")
+            .ok_or_else(|| Self::invalid(path, "named type has no \"name\""))?;
+        let namespace = match (name.rsplit_once('.'), text(members, "namespace")) {
+            (Some((namespace, _)), _) => namespace.to_string(),
+            (None, Some(namespace)) => namespace.to_string(),
+            (None, None) => namespace.to_string(),
+        };
+        let full_name = match (namespace.is_empty(), name.rsplit_once('.')) {
+            (_, Some(_)) | (true, _) => name.to_string(),
+            (false, None) => format!("{}.{}", namespace, name),
+        };
+        // Every type is top-level, so simple names must differ.
+        if self
+            .types
+            .keys()
+            .any(|other| simple(other) == simple(&full_name))
+        {
+            let message = format!("two types are named {}", simple(&full_name));
+            return Err(Self::invalid(&full_name, message));
+        }
+        let package = self.package.get_or_insert_with(|| namespace.clone());
+        if *package != namespace {
+            let message = format!("namespace {} becomes package {}", namespace, package);
+            self.loss(&full_name, message);
+        }
+        if text(members, "doc").is_some() {
+            self.loss(&full_name, "doc is not kept");
+        }
+        Ok((full_name, namespace))
+    }
+
+    fn record(
+        &mut self,
+        members: &[(String, Json)],
+        namespace: &str,
+        path: &str,
+    ) -> Result<Avro, Error> {
+        let (full_name, namespace) = self.name(members, namespace, path)?;
+        let t = Type::Named(simple(&full_name).to_string());
+        self.types.insert(full_name.clone(), t.clone());
+        // Fields may refer to the record itself, so it exists before them.
+        let index = self.file.messages.len();
+        self.file.messages.push(Message {
+            name: simple(&full_name).to_string(),
+            ..Default::default()
+        });
+        let fields = match member(members, "fields") {
+            Some(Json::Array(fields)) => fields,
+            _ => return Err(Self::invalid(&full_name, "record has no \"fields\"")),
+        };
+        if !fields.is_empty() {
+            self.loss(&full_name, "field numbers are assigned in order");
+        }
+        let mut message = Message::default();
+        let mut number = 0;
+        for field in fields {
+            let Json::Object(field) = field else {
+                return Err(Self::invalid(&full_name, "expected a field"));
+            };
+            let name = text(field, "name")
+                .ok_or_else(|| Self::invalid(&full_name, "field has no \"name\""))?;
+            let path = format!("{}.{}", full_name, name);
+            let t = member(field, "type")
+                .ok_or_else(|| Self::invalid(&path, "field has no \"type\""))?;
+            let t = self.schema(t, &namespace, &path)?;
+            if let Some(default) = member(field, "default").filter(|default| !is_zero(default)) {
+                self.loss(
+                    &path,
+                    format!("default {} is not kept", default.pretty().trim_end()),
+                );
+            }
+            if text(field, "doc").is_some() {
+                self.loss(&path, "doc is not kept");
+            }
+            self.field(&mut message, &path, name, t, &mut number);
+        }
+        let target = &mut self.file.messages[index];
+        target.fields = message.fields;
+        target.oneofs = message.oneofs;
+        Ok(Avro::Type(t))
+    }
+
+    fn field(&mut self, message: &mut Message, path: &str, name: &str, t: Avro, number: &mut u32) {
+        let mut next = || {
+            *number += 1;
+            *number
+        };
+        match t {
+            Avro::Type(t) => {
+                message
+                    .fields
+                    .push(MessageField::new(None, t, name.to_string(), next()))
+            }
+            Avro::Array(items) => match *items {
+                Avro::Type(t) => message.fields.push(MessageField::new(
+                    Some(Frequency::Repeated),
+                    t,
+                    name.to_string(),
+                    next(),
+                )),
+                _ => self.loss(path, "arrays of arrays, maps or unions are dropped"),
+            },
+            Avro::Map(values) => match *values {
+                Avro::Type(t) => message.fields.push(MessageField::new(
+                    None,
+                    Type::Map(Box::new(Type::String), Box::new(t)),
+                    name.to_string(),
+                    next(),
+                )),
+                _ => self.loss(path, "maps of arrays, maps or unions are dropped"),
+            },
+            Avro::Null => self.loss(path, "null fields are dropped"),
+            Avro::Union(types) => {
+                let nullable = types.iter().any(|t| matches!(t, Avro::Null));
+                let mut types: Vec<Avro> = types
+                    .into_iter()
+                    .filter(|t| !matches!(t, Avro::Null))
+                    .collect();
+                match types.len() {
+                    0 => self.loss(path, "null fields are dropped"),
+                    1 => match types.remove(0) {
+                        Avro::Type(t) => {
+                            // Messages have presence without `optional`.
+                            let is_message = matches!(&t, Type::Named(name)
+                                if self.file.messages.iter().any(|m| m.name == *name));
+                            let frequency =
+                                (nullable && !is_message).then_some(Frequency::Optional);
+                            message.fields.push(MessageField::new(
+                                frequency,
+                                t,
+                                name.to_string(),
+                                next(),
+                            ));
+                        }
+                        t => {
+                            self.loss(path, "null becomes empty");
+                            self.field(message, path, name, t, number);
+                        }
+                    },
+                    _ => {
+                        let mut oneof = Oneof {
+                            name: name.to_string(),
+                            ..Default::default()
+                        };
+                        for t in types {
+                            let Avro::Type(t) = t else {
+                                self.loss(path, "arrays and maps in unions are dropped");
+                                continue;
+                            };
+                            let suffix = match &t {
+                                Type::Named(name) => snake(name),
+                                t => String::from(t.clone()),
+                            };
+                            let member = format!("{}_{}", name, suffix);
+                            oneof
+                                .fields
+                                .push(MessageField::new(None, t, member, next()));
+                        }
+                        message.oneofs.push(oneof);
+                    }
+                }
+            }
+        }
+    }
+
+    fn enumeration(
+        &mut self,
+        members: &[(String, Json)],
+        namespace: &str,
+        path: &str,
+    ) -> Result<Avro, Error> {
+        let (full_name, _) = self.name(members, namespace, path)?;
+        let symbols = match member(members, "symbols") {
+            Some(Json::Array(symbols)) => symbols,
+            _ => return Err(Self::invalid(&full_name, "enum has no \"symbols\"")),
+        };
+        let mut e = Enum {
+            name: simple(&full_name).to_string(),
+            ..Default::default()
+        };
+        for (i, symbol) in symbols.iter().enumerate() {
+            let Json::String(symbol) = symbol else {
+                return Err(Self::invalid(&full_name, "expected a symbol"));
+            };
+            e.fields.push(EnumField {
+                name: symbol.clone(),
+                position: i as u32,
+            });
+        }
+        let t = Type::Named(e.name.clone());
+        self.types.insert(full_name, t.clone());
+        self.file.enums.push(e);
+        Ok(Avro::Type(t))
+    }
+}
+
+/// The Avro type of a scalar.
+fn primitive(t: &Type) -> &'static str {
+    match t {
+        Type::Int32 | Type::Sint32 | Type::Sfixed32 => "int",
+        Type::Float => "float",
+        Type::Double => "double",
+        Type::Bool => "boolean",
+        Type::String => "string",
+        Type::Bytes => "bytes",
+        Type::Message(_) | Type::Enum(_) | Type::Named(_) | Type::Map(..) => {
+            unreachable!("fields are linked and maps have no values of their own")
+        }
+        // The other integers, which need 64 bits or more.
+        _ => "long",
+    }
+}
+
+/// The last part of a dotted name.
+fn simple(full_name: &str) -> &str {
+    full_name.rsplit('.').next().unwrap_or(full_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/avro")
+            .load(&["events.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let set = load();
+        let (generated, losses) = generate(&set, &["events.proto"]);
+        let expected = std::fs::read_to_string("tests/avro/events.Click.avsc").unwrap();
+        assert_eq!(generated["events.Click.avsc"], expected);
+        assert_eq!(export(&set, "events.Click").unwrap().1, losses);
+        let losses: Vec<String> = losses.iter().map(Loss::to_string).collect();
+        assert_eq!(
+            losses,
+            [
+                "events.Click: field numbers are not kept",
+                "events.Click.user_id: uint64 values above 2^63 - 1 do not fit a long",
+                "events.Page: field numbers are not kept",
+                "events.Page.Kind: enum numbers are not kept, only the order of values",
+                "events.Click.labels: int32 map keys become strings",
+                "events.Click.target: oneof members of the same type become separate fields",
+            ]
+        );
+        assert!(export(&set, "events.Missing").is_none());
+    }
+
+    #[test]
+    fn imports_what_it_exports() {
+        let set = load();
+        let (schema, _) = export(&set, ".events.Click").unwrap();
+        let (file, losses) = import(&schema, "click.proto").unwrap();
+        let expected = std::fs::read_to_string("tests/avro/click.proto").unwrap();
+        assert_eq!(codegen::format(&file), expected);
+        assert!(losses.contains(&Loss {
+            path: "events.Click".to_string(),
+            message: "field numbers are assigned in order".to_string(),
+        }));
+        // What comes back links and passes the checks.
+        let set = FileSet::new(vec![file]).unwrap();
+        assert_eq!(set.validate(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn imports_avro_only_types() {
+        let source = r#"[
+            {"type": "fixed", "name": "Hash", "namespace": "org.example", "size": 16},
+            {"type": "record", "name": "org.example.Blob", "doc": "Stored bytes.", "fields": [
+                {"name": "hash", "type": "Hash"},
+                {"name": "created", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                {"name": "value", "type": ["null", "string", "long", "Blob"], "default": null},
+                {"name": "chunks", "type": {"type": "array", "items": {"type": "array", "items": "bytes"}}},
+                {"name": "retries", "type": "int", "default": 3},
+                {"name": "tags", "type": ["null", {"type": "map", "values": "string"}]}
+            ]}
+        ]"#;
+        let (file, losses) = import(source, "blob.proto").unwrap();
+        assert_eq!(file.package.as_deref(), Some("org.example"));
+        assert_eq!(
+            codegen::format(&file),
+            "syntax = \"proto3\";\n\npackage org.example;\n\nmessage Blob {\n  bytes hash = 1;\n  int64 created = 2;\n  int32 retries = 6;\n  map<string, string> tags = 7;\n\n  oneof value {\n    string value_string = 3;\n    int64 value_int64 = 4;\n    Blob value_blob = 5;\n  }\n}\n"
+        );
+        let losses: Vec<String> = losses.iter().map(Loss::to_string).collect();
+        assert_eq!(
+            losses,
+            [
+                "org.example.Hash: fixed becomes bytes of any size",
+                "org.example.Blob: doc is not kept",
+                "org.example.Blob: field numbers are assigned in order",
+                "org.example.Blob.created: logical type timestamp-millis becomes its underlying type",
+                "org.example.Blob.chunks: arrays of arrays, maps or unions are dropped",
+                "org.example.Blob.retries: default 3 is not kept",
+                "org.example.Blob.tags: null becomes empty",
+            ]
+        );
+        assert_eq!(
+            import("{\"type\": \"record\", \"name\": \"A\", \"fields\": [{\"name\": \"b\", \"type\": \"B\"}]}", "a.proto")
+                .unwrap_err()
+                .to_string(),
+            "A.b: unknown type B"
+        );
+        assert!(matches!(import("[1", "a.proto"), Err(Error::Json(_))));
+    }
+}
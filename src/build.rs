@@ -0,0 +1,366 @@
+//! Compiles `.proto` files to Rust from a build script.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     protos::build::Config::new()
+//!         .include("protos")
+//!         .compile(&["shop/order.proto"])
+//!         .unwrap();
+//! }
+//!
+//! // src/lib.rs, for files in `package shop;`
+//! pub mod shop {
+//!     protos::include_proto!("shop");
+//! }
+//! ```
+//!
+//! Packages are mounted at the same level, as generated code refers to
+//! other packages by relative path. The well-known types the files use,
+//! such as `google.protobuf.Timestamp`, are generated too:
+//!
+//! ```ignore
+//! pub mod google {
+//!     pub mod protobuf {
+//!         protos::include_proto!("google.protobuf");
+//!     }
+//! }
+//! ```
+
+use crate::codegen::rust;
+use crate::resolver::{self, Resolver};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Includes the module generated for `package` by [`Config::compile`].
+#[macro_export]
+macro_rules! include_proto {
+    ($package:expr) => {
+        include!(concat!(env!("OUT_DIR"), "/", $package, ".rs"));
+    };
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Resolve(resolver::Error),
+    /// Problems found by [`FileSet::validate`](resolver::FileSet::validate).
+    Invalid(Vec<String>),
+    /// No output directory was given and `OUT_DIR` is unset, as it is
+    /// outside build scripts.
+    NoOutDir,
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Resolve(err) => write!(f, "{}", err),
+            Error::Invalid(problems) => write!(f, "{}", problems.join("\n")),
+            Error::NoOutDir => write!(f, "OUT_DIR is not set"),
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<resolver::Error> for Error {
+    fn from(err: resolver::Error) -> Self {
+        Error::Resolve(err)
+    }
+}
+
+/// Settings for generating Rust from a build script. Methods that take a
+/// path select types and fields as described on [`rust::Options`].
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    resolver: Resolver,
+    out_dir: Option<PathBuf>,
+    options: rust::Options,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    /// Adds a directory to search for files and their imports.
+    pub fn include(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.resolver.include(path);
+        self
+    }
+
+    /// Writes to `path` instead of `OUT_DIR`.
+    pub fn out_dir(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.out_dir = Some(path.into());
+        self
+    }
+
+    /// Sets the path generated code uses for this crate, for crates that
+    /// depend on it under another name or through a re-export.
+    pub fn runtime(&mut self, path: &str) -> &mut Self {
+        self.options.runtime = path.to_string();
+        self
+    }
+
+    /// Adds an attribute, such as `#[derive(serde::Serialize)]`, to the
+    /// messages, enums and oneofs `path` selects.
+    pub fn type_attribute(&mut self, path: &str, attribute: &str) -> &mut Self {
+        let entry = (path.to_string(), attribute.to_string());
+        self.options.type_attributes.push(entry);
+        self
+    }
+
+    /// Adds an attribute to the fields and oneof variants `path` selects.
+    pub fn field_attribute(&mut self, path: &str, attribute: &str) -> &mut Self {
+        let entry = (path.to_string(), attribute.to_string());
+        self.options.field_attributes.push(entry);
+        self
+    }
+
+    /// Stores the selected bytes fields as `bytes::Bytes`, which the
+    /// crate using the generated code must depend on.
+    pub fn bytes(&mut self, paths: &[&str]) -> &mut Self {
+        self.options
+            .bytes
+            .extend(paths.iter().map(|path| path.to_string()));
+        self
+    }
+
+    /// Stores the selected map fields as `BTreeMap` instead of `HashMap`.
+    pub fn btree_map(&mut self, paths: &[&str]) -> &mut Self {
+        self.options
+            .btree_map
+            .extend(paths.iter().map(|path| path.to_string()));
+        self
+    }
+
+    /// Generates a module per package for `files`, and tells Cargo to
+    /// rebuild when any of them, their imports or the include directories
+    /// change, even if loading them fails.
+    pub fn compile(&self, files: &[&str]) -> Result<(), Error> {
+        self.compile_to(files, &mut io::stdout())
+    }
+
+    /// Like [`compile`](Self::compile), writing Cargo's instructions to
+    /// `cargo` instead of standard output.
+    pub fn compile_to(&self, files: &[&str], cargo: &mut dyn Write) -> Result<(), Error> {
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::var_os("OUT_DIR").ok_or(Error::NoOutDir)?.into(),
+        };
+        let stdout = |err| Error::Io(PathBuf::from("<stdout>"), err);
+        // Watched before loading, so that a file which fails to load, or an
+        // import added later, still triggers a rebuild.
+        let mut watched: Vec<PathBuf> = self.resolver.include_paths().to_vec();
+        watched.extend(files.iter().filter_map(|file| self.resolver.find(file)));
+        for path in &watched {
+            writeln!(cargo, "cargo:rerun-if-changed={}", path.display()).map_err(stdout)?;
+        }
+        let set = self.resolver.load(files)?;
+        for file in &set.files {
+            // Bundled well-known types have no path to watch.
+            match self.resolver.find(&file.name) {
+                Some(path) if !watched.contains(&path) => {
+                    writeln!(cargo, "cargo:rerun-if-changed={}", path.display()).map_err(stdout)?;
+                }
+                _ => {}
+            }
+        }
+        let problems = set.validate();
+        if !problems.is_empty() {
+            return Err(Error::Invalid(problems));
+        }
+        for (name, code) in rust::generate(&set, files, &self.options) {
+            let path = out_dir.join(name);
+            std::fs::write(&path, code).map_err(|err| Error::Io(path, err))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("protos-build-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_modules_and_rerun_instructions() {
+        let dir = temp_dir("modules");
+        std::fs::write(
+            dir.join("a.proto"),
+            "syntax = \"proto3\"; package shop.v1; import \"b.proto\";
+             import \"google/protobuf/timestamp.proto\";
+             message Order { b.Item item = 1; bytes note = 2; map<string, int32> counts = 3; }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("b.proto"),
+            "syntax = \"proto3\"; package b; message Item {}",
+        )
+        .unwrap();
+
+        let mut cargo = vec![];
+        Config::new()
+            .include(&dir)
+            .out_dir(&dir)
+            .type_attribute(".shop", "#[derive(Hash)]")
+            .field_attribute("Order.note", "#[doc(hidden)]")
+            .bytes(&["."])
+            .btree_map(&[".shop.v1.Order.counts"])
+            .compile_to(&["a.proto"], &mut cargo)
+            .unwrap();
+
+        let cargo = String::from_utf8(cargo).unwrap();
+        let expected = format!(
+            "cargo:rerun-if-changed={}\ncargo:rerun-if-changed={}\ncargo:rerun-if-changed={}\n",
+            dir.display(),
+            dir.join("a.proto").display(),
+            dir.join("b.proto").display()
+        );
+        assert_eq!(cargo, expected);
+        let code = std::fs::read_to_string(dir.join("shop.v1.rs")).unwrap();
+        assert!(code.contains(
+            "#[derive(Debug, Clone, PartialEq, Default)]\n#[derive(Hash)]\npub struct Order {"
+        ));
+        assert!(code.contains("    #[doc(hidden)]\n    pub note: ::bytes::Bytes,\n"));
+        assert!(
+            code.contains("pub counts: ::std::collections::BTreeMap<::std::string::String, i32>,")
+        );
+        assert!(code.contains("pub item: ::core::option::Option<super::super::b::Item>,"));
+        assert!(!dir.join("b.rs").exists());
+        // Imported but unused, so not generated.
+        assert!(!dir.join("google.protobuf.rs").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_invalid_files() {
+        let dir = temp_dir("invalid");
+        std::fs::write(
+            dir.join("a.proto"),
+            "syntax = \"proto3\"; message A { int32 x = 1; int32 y = 1; }",
+        )
+        .unwrap();
+        let err = Config::new()
+            .include(&dir)
+            .out_dir(&dir)
+            .compile_to(&["a.proto"], &mut io::sink())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a.proto: A.y: number 1 is already used by \"x\""
+        );
+        let mut cargo = vec![];
+        let err = Config::new()
+            .include(&dir)
+            .out_dir(&dir)
+            .compile_to(&["b.proto"], &mut cargo);
+        assert!(matches!(err, Err(Error::Resolve(_))));
+        // Creating the missing file reruns the build.
+        assert_eq!(
+            String::from_utf8(cargo).unwrap(),
+            format!("cargo:rerun-if-changed={}\n", dir.display())
+        );
+        std::fs::write(dir.join("c.proto"), "message C {").unwrap();
+        let mut cargo = vec![];
+        let err = Config::new()
+            .include(&dir)
+            .out_dir(&dir)
+            .compile_to(&["c.proto"], &mut cargo);
+        assert!(matches!(err, Err(Error::Resolve(_))));
+        assert!(String::from_utf8(cargo).unwrap().ends_with(&format!(
+            "cargo:rerun-if-changed={}\n",
+            dir.join("c.proto").display()
+        )));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Builds a crate whose build script uses `Config` and whose code uses
+    /// `include_proto!`, checking the generated code compiles against this
+    /// crate as a dependency.
+    #[test]
+    fn builds_a_dependent_crate() {
+        let dir = temp_dir("crate");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("protos")).unwrap();
+        let manifest = format!(
+            "[package]\nname = \"shop\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\nprotos = {{ path = {:?} }}\n\n\
+             [build-dependencies]\nprotos = {{ path = {:?} }}\n",
+            env!("CARGO_MANIFEST_DIR"),
+            env!("CARGO_MANIFEST_DIR")
+        );
+        std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        std::fs::write(
+            dir.join("protos/order.proto"),
+            "syntax = \"proto3\"; package shop; import \"google/protobuf/timestamp.proto\";
+             message Order {
+               string id = 1; repeated Line lines = 2; Status status = 3;
+               google.protobuf.Timestamp placed = 4;
+             }
+             message Line { string sku = 1; uint32 quantity = 2; }
+             enum Status { STATUS_UNKNOWN = 0; STATUS_PAID = 1; }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("build.rs"),
+            "fn main() {
+                 protos::build::Config::new()
+                     .include(\"protos\")
+                     .type_attribute(\".shop.Status\", \"#[allow(dead_code)]\")
+                     .compile(&[\"order.proto\"])
+                     .unwrap();
+             }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/main.rs"),
+            "use protos::wire::WireMessage;
+             pub mod shop {
+                 protos::include_proto!(\"shop\");
+             }
+             pub mod google {
+                 pub mod protobuf {
+                     protos::include_proto!(\"google.protobuf\");
+                 }
+             }
+             fn main() {
+                 let mut order = shop::Order { id: \"o-1\".to_string(), ..Default::default() };
+                 order.placed = Some(google::protobuf::Timestamp { seconds: 5, nanos: 0 });
+                 order.lines.push(shop::Line { sku: \"tea\".to_string(), quantity: 2 });
+                 order.set_status(shop::Status::Paid);
+                 let bytes = order.encode_to_vec();
+                 assert_eq!(shop::Order::decode(&bytes).unwrap(), order);
+                 print!(\"{:?}\", bytes);
+             }",
+        )
+        .unwrap();
+
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let output = Command::new(cargo)
+            .args(["run", "--quiet", "--offline"])
+            .current_dir(&dir)
+            .env("CARGO_TARGET_DIR", dir.join("target"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "[10, 3, 111, 45, 49, 18, 7, 10, 3, 116, 101, 97, 16, 2, 24, 1, 34, 2, 8, 5]"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,156 +1,1306 @@
+use crate::protofmt::Config;
+use crate::resolver::FileSet;
 use crate::*;
+use std::ops::Range;
 
-trait PrettyPrint {
-    fn pretty_print(&self, depth: u64) -> String;
+pub mod c;
+pub mod go;
+pub mod graphql;
+pub mod json_schema;
+pub mod openapi;
+pub mod python;
+pub mod rust;
+pub mod sql;
+pub mod typescript;
+
+/// Formats AST nodes as `.proto` source. The output is canonical: parsing
+/// it gives back an equal AST and declarations appear in a fixed order.
+pub trait PrettyPrint {
+    /// Formats with the default [`Config`] and no comments.
+    fn pretty_print(&self, depth: u64) -> String {
+        self.print(&mut Printer::default(), depth)
+    }
+
+    /// Formats in `printer`'s style. Parents push the path segment of each
+    /// child before printing it, so the printer can find its comments.
+    fn print(&self, printer: &mut Printer, depth: u64) -> String;
+}
+
+/// Formats a whole file with the default [`Config`].
+pub fn format(file: &ProtoFile) -> String {
+    file.pretty_print(0)
 }
 
-fn indent_string(s: &mut String, depth: u64) {
+fn indent_string(s: &mut String, depth: u64, config: &Config) {
     for _ in 0..depth {
-        s.push('\t');
+        if config.use_tabs {
+            s.push('\t');
+        } else {
+            s.extend(std::iter::repeat_n(' ', config.indent_width));
+        }
     }
 }
 
-impl PrettyPrint for Message {
-    fn pretty_print(&self, depth: u64) -> String {
-        let mut s = String::default();
+/// The comments the parser attached to one declaration.
+#[derive(Debug, Clone, Default)]
+struct Comments {
+    detached: Vec<String>,
+    leading: Option<String>,
+    trailing: Option<String>,
+}
 
-        let Message {
-            name,
-            messages,
-            enums,
-            fields,
-            oneofs,
-        } = self;
+impl Comments {
+    fn merge(&mut self, other: &Comments) {
+        self.detached.extend(other.detached.iter().cloned());
+        for (mine, theirs) in [
+            (&mut self.leading, &other.leading),
+            (&mut self.trailing, &other.trailing),
+        ] {
+            match (mine.as_mut(), theirs) {
+                (Some(mine), Some(theirs)) => mine.push_str(theirs),
+                (None, Some(theirs)) => *mine = Some(theirs.clone()),
+                _ => {}
+            }
+        }
+    }
+}
 
-        s.push_str(&format!("message {} {{\n", name));
+/// Prints AST nodes in the style of a [`Config`], putting back comments
+/// found by the [`Parser`] that produced them.
+#[derive(Debug, Default)]
+pub struct Printer {
+    config: Config,
+    comments: BTreeMap<Vec<PathSegment>, Comments>,
+    dangling: BTreeMap<Vec<PathSegment>, Vec<String>>,
+    path: Vec<PathSegment>,
+}
 
-        for field in fields {
-            s.push_str(&field.pretty_print(depth));
+impl Printer {
+    pub fn new(config: Config) -> Self {
+        Printer {
+            config,
+            ..Default::default()
         }
+    }
 
-        for e in enums {
-            s.push_str(&e.pretty_print(depth + 1));
+    /// Attaches the comments of a parsed file. `extensions` and `reserved`
+    /// statements are printed differently from how they may be written,
+    /// so their comments move to their first range or name.
+    pub fn with_comments(mut self, parser: &Parser) -> Self {
+        let mut statement: Option<(&[PathSegment], Comments)> = None;
+        for location in parser.locations() {
+            let comments = Comments {
+                detached: location.leading_detached_comments.clone(),
+                leading: location.leading_comments.clone(),
+                trailing: location.trailing_comments.clone(),
+            };
+            let path = &location.path[..];
+            match path.last() {
+                Some(
+                    PathSegment::Extensions | PathSegment::Reserved | PathSegment::ReservedNames,
+                ) => {
+                    statement = Some((path, comments));
+                    continue;
+                }
+                Some(
+                    segment @ (PathSegment::ExtensionRange(_)
+                    | PathSegment::ReservedRange(_)
+                    | PathSegment::ReservedName(_)),
+                ) => {
+                    if let Some((parent, comments)) =
+                        statement.take_if(|(parent, _)| *parent == &path[..path.len() - 1])
+                    {
+                        let mut key = parent[..parent.len() - 1].to_vec();
+                        key.push(*segment);
+                        self.comments.insert(key, comments);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if comments.leading.is_some()
+                || comments.trailing.is_some()
+                || !comments.detached.is_empty()
+            {
+                self.comments.insert(location.path.clone(), comments);
+            }
+        }
+        for (path, texts) in parser.dangling_comments() {
+            self.dangling
+                .entry(path.clone())
+                .or_default()
+                .extend(texts.iter().cloned());
         }
+        self
+    }
+
+    fn indented(&self, depth: u64) -> String {
+        let mut s = String::new();
+        indent_string(&mut s, depth, &self.config);
+        s
+    }
+
+    /// The display width of `line`, counting indentation tabs as
+    /// `indent_width` columns.
+    fn width(&self, line: &str) -> usize {
+        line.chars()
+            .map(|c| {
+                if c == '\t' {
+                    self.config.indent_width
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
 
-        for message in messages {
-            s.push_str(&message.pretty_print(depth + 1));
+    /// Prints a child declaration with its comments. `brace_trailing` puts
+    /// the trailing comment after the opening brace, where the parser
+    /// looks for it on blocks.
+    fn child(
+        &mut self,
+        segment: PathSegment,
+        depth: u64,
+        brace_trailing: bool,
+        print: impl FnOnce(&mut Self) -> String,
+    ) -> String {
+        self.path.push(segment);
+        let text = print(self);
+        let comments = self.comments.get(&self.path).cloned();
+        self.path.pop();
+        match comments {
+            Some(comments) => self.decorate(text, &comments, depth, brace_trailing),
+            None => text,
         }
+    }
+
+    fn child_node<T: PrettyPrint>(
+        &mut self,
+        segment: PathSegment,
+        node: &T,
+        depth: u64,
+        brace_trailing: bool,
+    ) -> String {
+        self.child(segment, depth, brace_trailing, |printer| {
+            node.print(printer, depth)
+        })
+    }
 
-        for oneof in oneofs {
-            s.push_str(&oneof.pretty_print(depth + 1));
+    fn decorate(
+        &self,
+        text: String,
+        comments: &Comments,
+        depth: u64,
+        brace_trailing: bool,
+    ) -> String {
+        let mut s = String::new();
+        for detached in &comments.detached {
+            s.push_str(&self.comment(detached, depth));
+            s.push('\n');
+        }
+        if let Some(leading) = &comments.leading {
+            s.push_str(&self.comment(leading, depth));
+        }
+        match &comments.trailing {
+            Some(trailing) => {
+                let at = if brace_trailing {
+                    text.find('\n').unwrap_or(text.len())
+                } else {
+                    text.len() - 1
+                };
+                let inner = if brace_trailing { depth + 1 } else { depth };
+                s.push_str(&text[..at]);
+                s.push(' ');
+                s.push_str(self.comment(trailing, inner).trim_start());
+                s.pop();
+                s.push_str(&text[at..]);
+            }
+            None => s.push_str(&text),
         }
+        s
+    }
 
-        s.push('}');
+    /// Renders comment text as the parser keeps it: line comments end in
+    /// a newline, block comments don't.
+    fn comment(&self, text: &str, depth: u64) -> String {
+        let indent = self.indented(depth);
+        let Some(body) = text.strip_suffix('\n') else {
+            return format!("{}/*{}*/\n", indent, text);
+        };
+        let lines: Vec<String> = if self.config.reflow_comments {
+            let width = self
+                .config
+                .max_line_width
+                .saturating_sub(self.width(&indent) + 3)
+                .max(20);
+            reflow(body, width)
+        } else {
+            body.split('\n').map(str::to_string).collect()
+        };
+        lines
+            .iter()
+            .map(|line| format!("{}//{}\n", indent, line))
+            .collect()
+    }
+
+    fn dangling_section(&self, depth: u64) -> String {
+        match self.dangling.get(&self.path) {
+            Some(texts) => texts
+                .iter()
+                .map(|text| self.comment(text, depth))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+
+    /// Joins the non-empty sections of a scope with blank lines in between.
+    fn join_sections(&self, sections: Vec<String>) -> String {
+        let sections = sections.into_iter().filter(|section| !section.is_empty());
+        if self.config.blank_lines == 0 {
+            return concat_items(sections);
+        }
+        sections
+            .collect::<Vec<_>>()
+            .join(&"\n".repeat(self.config.blank_lines))
+    }
 
+    /// Wraps the sections of a block in braces, closing at `depth`.
+    fn block(&self, header: &str, mut sections: Vec<String>, depth: u64) -> String {
+        sections.push(self.dangling_section(depth + 1));
+        let body = self.join_sections(sections);
+        let mut s = self.indented(depth);
+        s.push_str(header);
+        if body.is_empty() {
+            s.push_str(" {}\n");
+        } else {
+            s.push_str(" {\n");
+            s.push_str(&body);
+            indent_string(&mut s, depth, &self.config);
+            s.push_str("}\n");
+        }
         s
     }
-}
 
-impl PrettyPrint for Oneof {
-    fn pretty_print(&self, depth: u64) -> String {
-        let mut s = String::default();
+    /// Every item on its own, as blocks separated by blank lines.
+    fn blocks<T: PrettyPrint>(
+        &mut self,
+        items: &[T],
+        segment: fn(usize) -> PathSegment,
+        depth: u64,
+        brace_trailing: bool,
+    ) -> Vec<String> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.child_node(segment(i), item, depth, brace_trailing))
+            .collect()
+    }
 
-        let Oneof {
-            name,
-            messages,
-            enums,
-            fields,
-            oneofs,
-        } = self;
+    fn option_statements(&mut self, options: &[ProtoOption], depth: u64) -> String {
+        let items: Vec<String> = (0..options.len())
+            .map(|i| self.option_statement(options, i, depth))
+            .collect();
+        concat_items(items)
+    }
 
-        s.push_str(&format!("oneof {} {{\n", name));
+    fn option_statement(&mut self, options: &[ProtoOption], i: usize, depth: u64) -> String {
+        let option = &options[i];
+        self.child(PathSegment::Option(i), depth, false, |printer| {
+            format!(
+                "{}option {} = {};\n",
+                printer.indented(depth),
+                option.name,
+                printer.constant(&option.value, Some(depth))
+            )
+        })
+    }
 
-        for field in fields {
-            s.push_str(&field.pretty_print(depth + 1));
+    /// Ends a field or enum value line after `prefix`: bracketed options,
+    /// moved one per line when they would not fit in `max_line_width`.
+    fn finish_line(
+        &self,
+        prefix: String,
+        options: Option<&Vec<ProtoOption>>,
+        depth: u64,
+    ) -> String {
+        let options = match options {
+            Some(options) if !options.is_empty() => options,
+            _ => return format!("{};\n", prefix),
+        };
+        // Comments written between the options, as the parser found them.
+        let comments: Vec<Vec<String>> = (0..options.len())
+            .map(|i| {
+                let mut path = self.path.clone();
+                path.extend([PathSegment::Options, PathSegment::Option(i)]);
+                self.comments.get(&path).map_or(vec![], |comments| {
+                    let mut texts = comments.detached.clone();
+                    texts.extend(comments.leading.clone());
+                    texts
+                })
+            })
+            .collect();
+        let block_comments = |texts: &[String]| -> String {
+            texts
+                .iter()
+                .filter(|text| !text.ends_with('\n'))
+                .map(|text| format!("/*{}*/ ", text))
+                .collect()
+        };
+        let line_comments = comments.iter().flatten().any(|text| text.ends_with('\n'));
+
+        let inline: Vec<String> = options
+            .iter()
+            .zip(&comments)
+            .map(|(option, texts)| {
+                let value = self.constant(&option.value, None);
+                format!("{}{} = {}", block_comments(texts), option.name, value)
+            })
+            .collect();
+        let line = format!("{} [{}];\n", prefix, inline.join(", "));
+        if !line_comments && self.width(&line) <= self.config.max_line_width + 1 {
+            return line;
         }
+        let inner = self.indented(depth + 1);
+        let wrapped: Vec<String> = options
+            .iter()
+            .zip(&comments)
+            .map(|(option, texts)| {
+                let mut s: String = texts
+                    .iter()
+                    .filter(|text| text.ends_with('\n'))
+                    .map(|text| self.comment(text, depth + 1))
+                    .collect();
+                let value = self.constant(&option.value, Some(depth + 1));
+                s.push_str(&format!(
+                    "{}{}{} = {}",
+                    inner,
+                    block_comments(texts),
+                    option.name,
+                    value
+                ));
+                s
+            })
+            .collect();
+        format!(
+            "{} [\n{}\n{}];\n",
+            prefix,
+            wrapped.join(",\n"),
+            self.indented(depth)
+        )
+    }
 
-        for e in enums {
-            s.push_str(&e.pretty_print(depth + 1));
+    /// Prints an option value. Aggregates are spread over lines indented from
+    /// `depth`, or kept on one line when `depth` is `None`.
+    fn constant(&self, value: &Constant, depth: Option<u64>) -> String {
+        match value {
+            Constant::Ident(ident) => ident.clone(),
+            Constant::Int(int) => int.to_string(),
+            Constant::Float(float) => float.clone(),
+            Constant::String(string) => quote(string),
+            Constant::Bytes(bytes) => quote_bytes(bytes),
+            Constant::Bool(b) => b.to_string(),
+            Constant::Aggregate(fields) if fields.is_empty() => "{}".to_string(),
+            Constant::Aggregate(fields) => match depth {
+                Some(depth) => {
+                    let mut s = "{\n".to_string();
+                    for (name, value) in fields {
+                        indent_string(&mut s, depth + 1, &self.config);
+                        s.push_str(&format!(
+                            "{}: {}\n",
+                            name,
+                            self.constant(value, Some(depth + 1))
+                        ));
+                    }
+                    indent_string(&mut s, depth, &self.config);
+                    s.push('}');
+                    s
+                }
+                None => {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}", name, self.constant(value, None)))
+                        .collect();
+                    format!("{{ {} }}", fields.join(" "))
+                }
+            },
         }
+    }
+
+    fn fields(
+        &mut self,
+        fields: &[MessageField],
+        options: &BTreeMap<u32, Vec<ProtoOption>>,
+        depth: u64,
+    ) -> String {
+        let pad = self.field_alignment(fields);
+        let items: Vec<String> = (0..fields.len())
+            .map(|i| self.field(fields, i, options, pad, depth))
+            .collect();
+        concat_items(items)
+    }
+
+    fn field_alignment(&self, fields: &[MessageField]) -> usize {
+        let prefixes: Vec<String> = fields.iter().map(field_prefix).collect();
+        self.alignment(&prefixes)
+    }
 
-        for message in messages {
-            s.push_str(&message.pretty_print(depth + 1));
+    fn field(
+        &mut self,
+        fields: &[MessageField],
+        i: usize,
+        options: &BTreeMap<u32, Vec<ProtoOption>>,
+        pad: usize,
+        depth: u64,
+    ) -> String {
+        let field = &fields[i];
+        self.child(PathSegment::Field(i), depth, false, |printer| {
+            let prefix = format!(
+                "{}{:pad$} = {}",
+                printer.indented(depth),
+                field_prefix(field),
+                field.position()
+            );
+            printer.finish_line(prefix, options.get(&field.position()), depth)
+        })
+    }
+
+    /// The width to pad names to before ` = `, when aligning.
+    fn alignment(&self, prefixes: &[String]) -> usize {
+        if self.config.align_fields {
+            prefixes
+                .iter()
+                .map(|p| p.chars().count())
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
         }
+    }
 
-        for oneof in oneofs {
-            s.push_str(&oneof.pretty_print(depth + 1));
+    /// One `reserved` statement of the ranges at `indices`.
+    fn reserved_ranges(
+        &mut self,
+        ranges: &[(u32, u32)],
+        indices: Range<usize>,
+        depth: u64,
+    ) -> String {
+        if indices.is_empty() {
+            return String::new();
         }
+        let line = format!(
+            "{}reserved {};\n",
+            self.indented(depth),
+            print_ranges(&ranges[indices.clone()])
+        );
+        let comments = self.gather(indices, PathSegment::ReservedRange);
+        self.decorate(line, &comments, depth, false)
+    }
 
-        s.push('}');
+    /// One `reserved` statement of the names at `indices`.
+    fn reserved_names(&mut self, names: &[String], indices: Range<usize>, depth: u64) -> String {
+        if indices.is_empty() {
+            return String::new();
+        }
+        let names: Vec<String> = names[indices.clone()]
+            .iter()
+            .map(|name| quote(name))
+            .collect();
+        let line = format!("{}reserved {};\n", self.indented(depth), names.join(", "));
+        let comments = self.gather(indices, PathSegment::ReservedName);
+        self.decorate(line, &comments, depth, false)
+    }
 
-        s
+    /// One `extensions` statement of the ranges at `indices`, with the
+    /// options of the first.
+    fn extensions(
+        &mut self,
+        ranges: &[(u32, u32)],
+        options: &BTreeMap<u32, Vec<ProtoOption>>,
+        indices: Range<usize>,
+        depth: u64,
+    ) -> String {
+        let first = indices.start;
+        let prefix = format!(
+            "{}extensions {}",
+            self.indented(depth),
+            print_ranges(&ranges[indices.clone()])
+        );
+        self.path.push(PathSegment::ExtensionRange(first));
+        let line = self.finish_line(prefix, options.get(&ranges[first].0), depth);
+        self.path.pop();
+        let comments = self.gather(indices, PathSegment::ExtensionRange);
+        self.decorate(line, &comments, depth, false)
+    }
+
+    /// The comments of every statement that listed some of the ranges or
+    /// names at `indices`, which are printed as a single statement.
+    fn gather(&mut self, indices: Range<usize>, segment: fn(usize) -> PathSegment) -> Comments {
+        let mut comments = Comments::default();
+        for i in indices {
+            self.path.push(segment(i));
+            if let Some(found) = self.comments.get(&self.path) {
+                comments.merge(found);
+            }
+            self.path.pop();
+        }
+        comments
     }
 }
 
-impl PrettyPrint for Enum {
-    fn pretty_print(&self, depth: u64) -> String {
-        let mut s = String::default();
+/// Concatenates printed items. A blank line goes between two items when
+/// a trailing line comment would otherwise run into the next item's leading
+/// comment, which the parser would read as one comment.
+fn concat_items(items: impl IntoIterator<Item = String>) -> String {
+    let mut s = String::new();
+    for item in items {
+        let last_line = s.trim_end_matches('\n').rsplit('\n').next().unwrap_or("");
+        let trailing = last_line.contains("//") && !last_line.trim_start().starts_with("//");
+        if trailing && item.trim_start().starts_with("//") {
+            s.push('\n');
+        }
+        s.push_str(&item);
+    }
+    s
+}
 
-        let Enum { name, fields } = self;
+/// How a printed statement is set apart: statements of the same kind of
+/// line follow one another, and anything else starts a new section.
+#[derive(PartialEq)]
+enum Run {
+    Syntax,
+    Package,
+    Imports,
+    Options,
+    Fields,
+    Reserved,
+    Extensions,
+    Block,
+}
 
-        s.push_str(&format!("enum {} {{\n", name));
+/// Groups printed statements into the sections of their scope.
+fn sections(statements: Vec<(Run, String)>) -> Vec<String> {
+    let mut sections: Vec<String> = vec![];
+    let mut last = Run::Block;
+    for (run, text) in statements {
+        match sections.last_mut() {
+            Some(section) if run == last && run != Run::Block => {
+                *section = concat_items([std::mem::take(section), text]);
+            }
+            _ => sections.push(text),
+        }
+        last = run;
+    }
+    sections
+}
 
-        for field in fields {
-            s.push_str(&field.pretty_print(depth + 1));
+/// Wraps the words of a comment to `width` columns, keeping blank lines
+/// between paragraphs. Each line starts with a space after the `//`.
+fn reflow(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.split("\n\n").flat_map(|p| p.split("\n \n")) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.len() + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(' ');
+            line.push_str(word);
         }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
 
-        s.push('}');
+fn field_prefix(field: &MessageField) -> String {
+    let mut s = String::new();
+    if let Some(frequency) = field.frequency() {
+        s.push_str(&String::from(frequency.clone()));
+        s.push(' ');
+    }
+    s.push_str(&format!(
+        "{} {}",
+        String::from(field.field_type()),
+        field.name()
+    ));
+    s
+}
 
-        s
+/// Quotes a string literal, escaping whatever the parser would not read
+/// back as itself.
+fn quote(value: &str) -> String {
+    quote_bytes(value.as_bytes())
+}
+
+/// Quotes a literal, escaping bytes that are not part of valid UTF-8.
+fn quote_bytes(value: &[u8]) -> String {
+    let mut s = String::from('"');
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => s.push_str("\\\""),
+                '\\' => s.push_str("\\\\"),
+                '\n' => s.push_str("\\n"),
+                '\r' => s.push_str("\\r"),
+                '\t' => s.push_str("\\t"),
+                c if c.is_ascii_control() => s.push_str(&format!("\\{:03o}", c as u32)),
+                c => s.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            s.push_str(&format!("\\{:03o}", byte));
+        }
     }
+    s.push('"');
+    s
 }
 
-impl PrettyPrint for EnumField {
-    fn pretty_print(&self, depth: u64) -> String {
-        let EnumField { name, position } = self;
+fn print_ranges(ranges: &[(u32, u32)]) -> String {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|&(start, end)| match end {
+            _ if end == start => start.to_string(),
+            Parser::MAX_FIELD_NUMBER => format!("{} to max", start),
+            _ => format!("{} to {}", start, end),
+        })
+        .collect();
+    ranges.join(", ")
+}
 
-        let mut s = String::default();
+/// Generated source built line by line, shared by the language
+/// generators in the submodules.
+pub(crate) struct Code {
+    pub text: String,
+    pub depth: usize,
+    indent: &'static str,
+}
 
-        indent_string(&mut s, depth);
+impl Code {
+    pub fn new(indent: &'static str) -> Self {
+        Code {
+            text: String::new(),
+            depth: 0,
+            indent,
+        }
+    }
 
-        s.push_str(&format!("{} = {};\n", name, position));
+    /// Adds a line at the current depth; empty lines get no indentation.
+    pub fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+        if !line.is_empty() {
+            self.text.push_str(&self.indent.repeat(self.depth));
+            self.text.push_str(line);
+        }
+        self.text.push('\n');
+    }
 
-        s
+    /// Starts a brace-delimited block.
+    pub fn open(&mut self, header: impl AsRef<str>) {
+        self.line(format!("{} {{", header.as_ref()));
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self) {
+        self.close_with("}");
+    }
+
+    /// Ends a block with `end`, such as `};` or `})`.
+    pub fn close_with(&mut self, end: &str) {
+        self.depth -= 1;
+        self.line(end);
+    }
+
+    /// Writes a schema comment with each line behind `prefix`.
+    pub fn comment(&mut self, prefix: &str, comment: Option<&String>) {
+        let Some(comment) = comment else { return };
+        for line in comment.trim_end().lines() {
+            self.line(format!("{}{}", prefix, line.trim_end()).trim_end());
+        }
     }
 }
 
-impl PrettyPrint for MessageField {
-    fn pretty_print(&self, depth: u64) -> String {
-        let mut s = String::default();
-        indent_string(&mut s, depth);
-
-        let rest = match self {
-            MessageField::Int32(frequency, name, position) => {
-                if let Some(freq) = frequency {
-                    format!(
-                        "{} int32 {} {};\n",
-                        std::convert::Into::<String>::into(freq.clone()),
-                        name,
-                        position
-                    )
-                } else {
-                    format!("int32 {} {};\n", name, position)
+/// The leading comments of the file being generated, and the path of the
+/// declaration being generated within it.
+pub(crate) struct Context<'a> {
+    comments: BTreeMap<&'a [PathSegment], &'a String>,
+    pub proto3: bool,
+    pub path: Vec<PathSegment>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(set: &'a FileSet, file: &ProtoFile) -> Self {
+        let comments = set
+            .locations
+            .get(&file.name)
+            .into_iter()
+            .flatten()
+            .filter_map(|location| Some((&location.path[..], location.leading_comments.as_ref()?)))
+            .collect();
+        Context {
+            comments,
+            proto3: file.syntax.as_deref() == Some("proto3"),
+            path: vec![],
+        }
+    }
+
+    /// The comment of the declaration at `relative` to the current path.
+    pub fn comment(&self, relative: &[PathSegment]) -> Option<&'a String> {
+        let mut path = self.path.clone();
+        path.extend_from_slice(relative);
+        self.comments.get(&path[..]).copied()
+    }
+}
+
+/// Where a message or enum is declared, for generators that refer to
+/// types across files.
+#[derive(Debug)]
+pub(crate) struct Declared {
+    /// The name in generated code, from the file's top level.
+    pub name: String,
+    pub file: String,
+    /// The protobuf package of the file.
+    pub package: String,
+}
+
+/// Declares every message and enum in `set` by full name, named by
+/// `ident` and, when nested, behind their parent's name and `separator`.
+pub(crate) fn declare_all(
+    set: &FileSet,
+    ident: impl Fn(&str) -> String,
+    separator: &str,
+) -> BTreeMap<String, Declared> {
+    fn declare(
+        types: &mut BTreeMap<String, Declared>,
+        file: &ProtoFile,
+        scope: &str,
+        prefix: &str,
+        name: &str,
+        ident: &dyn Fn(&str) -> String,
+    ) -> (String, String) {
+        let full_name = format!("{}.{}", scope, name);
+        let declared = Declared {
+            name: format!("{}{}", prefix, ident(name)),
+            file: file.name.clone(),
+            package: file.package.clone().unwrap_or_default(),
+        };
+        let ret = (full_name.clone(), declared.name.clone());
+        types.insert(full_name, declared);
+        ret
+    }
+
+    fn declare_message(
+        types: &mut BTreeMap<String, Declared>,
+        file: &ProtoFile,
+        scope: &str,
+        prefix: &str,
+        message: &Message,
+        ident: &dyn Fn(&str) -> String,
+        separator: &str,
+    ) {
+        let (full_name, name) = declare(types, file, scope, prefix, &message.name, ident);
+        let prefix = format!("{}{}", name, separator);
+        for nested in &message.messages {
+            declare_message(types, file, &full_name, &prefix, nested, ident, separator);
+        }
+        for e in &message.enums {
+            declare(types, file, &full_name, &prefix, &e.name, ident);
+        }
+    }
+
+    let mut types = BTreeMap::new();
+    for file in &set.files {
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for message in &file.messages {
+            declare_message(&mut types, file, &scope, "", message, &ident, separator);
+        }
+        for e in &file.enums {
+            declare(&mut types, file, &scope, "", &e.name, &ident);
+        }
+    }
+    types
+}
+
+/// The comma-separated items of a generator parameter, trimmed, with
+/// empty ones skipped.
+pub(crate) fn parameters(parameter: &str) -> impl Iterator<Item = &str> {
+    parameter
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// The fields set by the custom option `(extension)` among `options`,
+/// whether written whole, `(ext) = { a: 1 }`, as descriptors decode them,
+/// or field by field, `(ext).a = 1`.
+pub(crate) fn extension_fields(
+    options: &[ProtoOption],
+    extension: &str,
+) -> Vec<(String, Constant)> {
+    let mut ret = vec![];
+    for option in options {
+        match option.extension() {
+            Some((name, "")) if name.trim_start_matches('.') == extension => {
+                if let Constant::Aggregate(fields) = &option.value {
+                    ret.extend(fields.iter().cloned());
                 }
             }
-            MessageField::String(frequency, name, position) => {
-                if let Some(freq) = frequency {
-                    format!(
-                        "{} string {} {};\n",
-                        std::convert::Into::<String>::into(freq.clone()),
-                        name,
-                        position
-                    )
+            Some((name, field)) if name.trim_start_matches('.') == extension => {
+                ret.push((field.to_string(), option.value.clone()))
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+/// How a field is declared and coded by generators that write their own
+/// encoding, against a runtime with a `Reader` and `Writer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Label {
+    /// A proto3 scalar without presence, omitted when it is the default.
+    Implicit,
+    /// A field with presence, left unset when absent.
+    Optional,
+    Repeated,
+    Packed,
+    Map,
+}
+
+impl Label {
+    pub(crate) fn of(
+        field: &MessageField,
+        options: Option<&Vec<ProtoOption>>,
+        proto3: bool,
+    ) -> Label {
+        let t = field.field_type();
+        match (&t, field.frequency()) {
+            (Type::Map(..), _) => Label::Map,
+            (_, Some(Frequency::Repeated)) => {
+                let packed = options
+                    .into_iter()
+                    .flatten()
+                    .find(|option| option.name == "packed")
+                    .map_or(proto3, |option| option.value == Constant::Bool(true));
+                if packed && is_packable(&t) {
+                    Label::Packed
                 } else {
-                    format!("string {} {};\n", name, position)
+                    Label::Repeated
                 }
             }
+            (Type::Message(_), _) => Label::Optional,
+            (_, None) if proto3 => Label::Implicit,
+            _ => Label::Optional,
+        }
+    }
+}
+
+pub(crate) fn is_packable(t: &Type) -> bool {
+    !matches!(t, Type::String | Type::Bytes | Type::Message(_))
+}
+
+/// The `Reader` and `Writer` method for one value of `t`.
+pub(crate) fn method(t: &Type) -> &'static str {
+    match t {
+        Type::Double => "double",
+        Type::Float => "float",
+        Type::Int32 | Type::Enum(_) => "int32",
+        Type::Int64 => "int64",
+        Type::Uint32 => "uint32",
+        Type::Uint64 => "uint64",
+        Type::Sint32 => "sint32",
+        Type::Sint64 => "sint64",
+        Type::Fixed32 => "fixed32",
+        Type::Fixed64 => "fixed64",
+        Type::Sfixed32 => "sfixed32",
+        Type::Sfixed64 => "sfixed64",
+        Type::Bool => "bool",
+        Type::String => "string",
+        Type::Bytes | Type::Message(_) => "bytes",
+        Type::Named(name) => panic!("type {} is not linked", name),
+        Type::Map(..) => unreachable!("map values are not maps"),
+    }
+}
+
+pub(crate) fn wire_type(t: &Type) -> u32 {
+    match t {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => 1,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => 5,
+        Type::String | Type::Bytes | Type::Message(_) => 2,
+        _ => 0,
+    }
+}
+
+/// `UpperCamelCase` for type and variant names: `PHONE_TYPE` and
+/// `phone_type` both become `PhoneType`, while `PhoneType` is unchanged.
+pub(crate) fn upper_camel(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            let rest: String = chars.collect();
+            if word.chars().all(|c| !c.is_ascii_lowercase()) {
+                format!("{}{}", first, rest.to_ascii_lowercase())
+            } else {
+                format!("{}{}", first, rest)
+            }
+        })
+        .collect()
+}
+
+/// `snake_case`: `HTTPRule` becomes `http_rule`.
+pub(crate) fn snake(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut ret = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|c| c.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                ret.push('_');
+            }
+        }
+        ret.push(c.to_ascii_lowercase());
+    }
+    ret
+}
+
+impl PrettyPrint for ProtoFile {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let ProtoFile {
+            name: _,
+            syntax,
+            package,
+            imports,
+            options,
+            messages,
+            enums,
+            services,
+            extends,
+            order: _,
+        } = self;
+
+        let mut sorted: Vec<usize> = (0..imports.len()).collect();
+        if printer.config.sort_imports {
+            sorted.sort_by_key(|&i| &imports[i].path);
+        }
+        let mut statements = vec![];
+        for declaration in self.declarations() {
+            statements.push(match declaration {
+                Declaration::Syntax => (
+                    Run::Syntax,
+                    printer.child(PathSegment::Syntax, depth, false, |_| {
+                        format!(
+                            "syntax = {};\n",
+                            quote(syntax.as_deref().unwrap_or_default())
+                        )
+                    }),
+                ),
+                Declaration::Package => (
+                    Run::Package,
+                    printer.child(PathSegment::Package, depth, false, |_| {
+                        format!("package {};\n", package.as_deref().unwrap_or_default())
+                    }),
+                ),
+                // Sorted imports are printed together, where the first was.
+                Declaration::Import(0) if printer.config.sort_imports => (
+                    Run::Imports,
+                    concat_items(sorted.iter().map(|&i| {
+                        printer.child_node(PathSegment::Import(i), &imports[i], depth, false)
+                    })),
+                ),
+                Declaration::Import(_) if printer.config.sort_imports => continue,
+                Declaration::Import(i) => (
+                    Run::Imports,
+                    printer.child_node(PathSegment::Import(i), &imports[i], depth, false),
+                ),
+                Declaration::Option(i) => {
+                    (Run::Options, printer.option_statement(options, i, depth))
+                }
+                Declaration::Message(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Message(i), &messages[i], depth, true),
+                ),
+                Declaration::Enum(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Enum(i), &enums[i], depth, true),
+                ),
+                Declaration::Service(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Service(i), &services[i], depth, true),
+                ),
+                Declaration::Extend(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Extend(i), &extends[i], depth, true),
+                ),
+                _ => continue,
+            });
+        }
+        let mut sections = sections(statements);
+        sections.push(printer.dangling_section(depth));
+        printer.join_sections(sections)
+    }
+}
+
+impl PrettyPrint for Import {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let modifier = if self.public {
+            "public "
+        } else if self.weak {
+            "weak "
+        } else {
+            ""
         };
+        format!(
+            "{}import {}{};\n",
+            printer.indented(depth),
+            modifier,
+            quote(&self.path)
+        )
+    }
+}
+
+impl PrettyPrint for Message {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Message {
+            name,
+            messages,
+            enums,
+            fields,
+            oneofs,
+            options,
+            field_options,
+            extends,
+            extension_ranges,
+            extension_range_options,
+            reserved_ranges,
+            reserved_names,
+            order: _,
+        } = self;
+
+        let inner = depth + 1;
+        let pad = printer.field_alignment(fields);
+        let mut statements = vec![];
+        for declaration in self.declarations() {
+            statements.push(match declaration {
+                Declaration::Option(i) => {
+                    (Run::Options, printer.option_statement(options, i, inner))
+                }
+                Declaration::Field(i) => (
+                    Run::Fields,
+                    printer.field(fields, i, field_options, pad, inner),
+                ),
+                Declaration::Reserved { first, count } => (
+                    Run::Reserved,
+                    printer.reserved_ranges(reserved_ranges, first..first + count, inner),
+                ),
+                Declaration::ReservedNames { first, count } => (
+                    Run::Reserved,
+                    printer.reserved_names(reserved_names, first..first + count, inner),
+                ),
+                Declaration::Extensions { first, count } => (
+                    Run::Extensions,
+                    printer.extensions(
+                        extension_ranges,
+                        extension_range_options,
+                        first..first + count,
+                        inner,
+                    ),
+                ),
+                Declaration::Oneof(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Oneof(i), &oneofs[i], inner, true),
+                ),
+                Declaration::Extend(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Extend(i), &extends[i], inner, true),
+                ),
+                Declaration::Enum(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Enum(i), &enums[i], inner, true),
+                ),
+                Declaration::Message(i) => (
+                    Run::Block,
+                    printer.child_node(PathSegment::Message(i), &messages[i], inner, true),
+                ),
+                Declaration::Syntax
+                | Declaration::Package
+                | Declaration::Import(_)
+                | Declaration::Service(_) => continue,
+            });
+        }
+        let sections = sections(statements);
+        printer.block(&format!("message {}", name), sections, depth)
+    }
+}
+
+impl PrettyPrint for Oneof {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Oneof {
+            name,
+            messages,
+            enums,
+            fields,
+            oneofs,
+            options,
+            field_options,
+        } = self;
+
+        let mut sections = vec![
+            printer.option_statements(options, depth + 1),
+            printer.fields(fields, field_options, depth + 1),
+        ];
+        sections.extend(printer.blocks(oneofs, PathSegment::Oneof, depth + 1, true));
+        sections.extend(printer.blocks(enums, PathSegment::Enum, depth + 1, true));
+        sections.extend(printer.blocks(messages, PathSegment::Message, depth + 1, true));
+        printer.block(&format!("oneof {}", name), sections, depth)
+    }
+}
+
+impl PrettyPrint for Extend {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Extend {
+            extendee,
+            fields,
+            field_options,
+        } = self;
+
+        let sections = vec![printer.fields(fields, field_options, depth + 1)];
+        printer.block(&format!("extend {}", extendee), sections, depth)
+    }
+}
+
+impl PrettyPrint for Enum {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Enum {
+            name,
+            fields,
+            options,
+            value_options,
+            reserved_ranges,
+            reserved_names,
+            order: _,
+        } = self;
+
+        let inner = depth + 1;
+        let names: Vec<String> = fields.iter().map(|field| field.name.clone()).collect();
+        let pad = printer.alignment(&names);
+        let mut statements = vec![];
+        for declaration in self.declarations() {
+            statements.push(match declaration {
+                Declaration::Option(i) => {
+                    (Run::Options, printer.option_statement(options, i, inner))
+                }
+                Declaration::Field(i) => (
+                    Run::Fields,
+                    printer.child(PathSegment::EnumValue(i), inner, false, |printer| {
+                        let field = &fields[i];
+                        let prefix = format!(
+                            "{}{:pad$} = {}",
+                            printer.indented(inner),
+                            field.name,
+                            field.position
+                        );
+                        printer.finish_line(prefix, value_options.get(&field.name), inner)
+                    }),
+                ),
+                Declaration::Reserved { first, count } => (
+                    Run::Reserved,
+                    printer.reserved_ranges(reserved_ranges, first..first + count, inner),
+                ),
+                Declaration::ReservedNames { first, count } => (
+                    Run::Reserved,
+                    printer.reserved_names(reserved_names, first..first + count, inner),
+                ),
+                _ => continue,
+            });
+        }
+        printer.block(&format!("enum {}", name), sections(statements), depth)
+    }
+}
+
+impl PrettyPrint for EnumField {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let EnumField { name, position } = self;
+
+        let mut s = String::default();
+
+        indent_string(&mut s, depth, &printer.config);
+
+        s.push_str(&format!("{} = {};\n", name, position));
 
-        s.push_str(&rest);
         s
     }
 }
 
+impl PrettyPrint for MessageField {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let prefix = format!(
+            "{}{} = {}",
+            printer.indented(depth),
+            field_prefix(self),
+            self.position()
+        );
+        printer.finish_line(prefix, None, depth)
+    }
+}
+
+impl PrettyPrint for Service {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Service {
+            name,
+            methods,
+            options,
+        } = self;
+
+        let sections = vec![
+            printer.option_statements(options, depth + 1),
+            concat_items(printer.blocks(methods, PathSegment::Method, depth + 1, false)),
+        ];
+        printer.block(&format!("service {}", name), sections, depth)
+    }
+}
+
+impl PrettyPrint for Method {
+    fn print(&self, printer: &mut Printer, depth: u64) -> String {
+        let Method {
+            name,
+            input_type,
+            output_type,
+            client_streaming,
+            server_streaming,
+            options,
+        } = self;
+
+        let stream = |streaming: &bool| if *streaming { "stream " } else { "" };
+        let header = format!(
+            "rpc {}({}{}) returns ({}{})",
+            name,
+            stream(client_streaming),
+            input_type,
+            stream(server_streaming),
+            output_type
+        );
+        let options = printer.option_statements(options, depth + 1);
+        if options.is_empty() && !printer.dangling.contains_key(&printer.path) {
+            format!("{}{};\n", printer.indented(depth), header)
+        } else {
+            printer.block(&header, vec![options], depth)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,11 +1309,20 @@ mod tests {
     fn codegen() {
         let input = Message {
             name: "SampleMessage".to_string(),
+            options: vec![],
+            field_options: BTreeMap::new(),
+            extends: vec![],
+            extension_ranges: vec![],
+            extension_range_options: BTreeMap::new(),
+            reserved_ranges: vec![],
+            reserved_names: vec![],
             messages: vec![],
             enums: vec![],
             fields: vec![],
             oneofs: vec![Oneof {
                 name: "test_oneof".to_string(),
+                options: vec![],
+                field_options: BTreeMap::new(),
                 fields: vec![
                     MessageField::String(None, "name".to_string(), 4),
                     MessageField::Int32(None, "sub_message".to_string(), 9),
@@ -172,11 +1331,12 @@ mod tests {
                 enums: vec![],
                 oneofs: vec![],
             }],
+            order: vec![],
         };
 
         assert_eq!(
             input.pretty_print(0),
-            "message SampleMessage {\noneof test_oneof {\n\t\tstring name 4;\n\t\tint32 sub_message 9;\n}}"
+            "message SampleMessage {\n  oneof test_oneof {\n    string name = 4;\n    int32 sub_message = 9;\n  }\n}\n"
         );
     }
 
@@ -184,6 +1344,13 @@ mod tests {
     fn basic_message() {
         let input = Message {
             name: "Person".to_string(),
+            options: vec![],
+            field_options: BTreeMap::new(),
+            extends: vec![],
+            extension_ranges: vec![],
+            extension_range_options: BTreeMap::new(),
+            reserved_ranges: vec![],
+            reserved_names: vec![],
             messages: vec![],
             enums: vec![],
             fields: vec![
@@ -192,11 +1359,12 @@ mod tests {
                 MessageField::Int32(Some(Frequency::Required), "age".to_string(), 3),
             ],
             oneofs: vec![],
+            order: vec![],
         };
 
         assert_eq!(
             input.pretty_print(0),
-            "message Person {\nstring name 1;\nint32 id 2;\nrequired int32 age 3;\n}"
+            "message Person {\n  string name = 1;\n  int32 id = 2;\n  required int32 age = 3;\n}\n"
         );
     }
 
@@ -204,6 +1372,10 @@ mod tests {
     fn enum_f() {
         let input = Enum {
             name: "Person".to_string(),
+            options: vec![],
+            value_options: BTreeMap::new(),
+            reserved_ranges: vec![],
+            reserved_names: vec![],
             fields: vec![
                 EnumField {
                     name: "name".to_string(),
@@ -218,11 +1390,12 @@ mod tests {
                     position: 3,
                 },
             ],
+            order: vec![],
         };
 
         assert_eq!(
             input.pretty_print(0),
-            "enum Person {\n\tname = 1;\n\tid = 2;\n\tage = 3;\n}"
+            "enum Person {\n  name = 1;\n  id = 2;\n  age = 3;\n}\n"
         );
     }
 
@@ -230,20 +1403,46 @@ mod tests {
     fn recursive_message() {
         let input = Message {
             name: "blah".to_string(),
+            options: vec![],
+            field_options: BTreeMap::new(),
+            extends: vec![],
+            extension_ranges: vec![],
+            extension_range_options: BTreeMap::new(),
+            reserved_ranges: vec![],
+            reserved_names: vec![],
             oneofs: vec![],
             messages: vec![Message {
                 name: "inner".to_string(),
+                options: vec![],
+                field_options: BTreeMap::new(),
+                extends: vec![],
+                extension_ranges: vec![],
+                extension_range_options: BTreeMap::new(),
+                reserved_ranges: vec![],
+                reserved_names: vec![],
                 oneofs: vec![],
                 messages: vec![
                     Message {
                         name: "inner_inner".to_string(),
+                        options: vec![],
+                        field_options: BTreeMap::new(),
+                        extends: vec![],
+                        extension_ranges: vec![],
+                        extension_range_options: BTreeMap::new(),
+                        reserved_ranges: vec![],
+                        reserved_names: vec![],
                         messages: vec![],
                         enums: vec![Enum {
                             name: "inner_inner_enum".to_string(),
+                            options: vec![],
+                            value_options: BTreeMap::new(),
+                            reserved_ranges: vec![],
+                            reserved_names: vec![],
                             fields: vec![EnumField {
                                 name: "one".to_string(),
                                 position: 1,
                             }],
+                            order: vec![],
                         }],
                         fields: vec![
                             MessageField::String(
@@ -258,12 +1457,24 @@ mod tests {
                             ),
                         ],
                         oneofs: vec![],
+                        order: vec![],
                     },
                     Message {
                         name: "second_inner_inner".to_string(),
+                        options: vec![],
+                        field_options: BTreeMap::new(),
+                        extends: vec![],
+                        extension_ranges: vec![],
+                        extension_range_options: BTreeMap::new(),
+                        reserved_ranges: vec![],
+                        reserved_names: vec![],
                         messages: vec![],
                         enums: vec![Enum {
                             name: "inner_inner_inner_enum".to_string(),
+                            options: vec![],
+                            value_options: BTreeMap::new(),
+                            reserved_ranges: vec![],
+                            reserved_names: vec![],
                             fields: vec![
                                 EnumField {
                                     name: "one".to_string(),
@@ -274,6 +1485,7 @@ mod tests {
                                     position: 2,
                                 },
                             ],
+                            order: vec![],
                         }],
                         fields: vec![MessageField::Int32(
                             Some(Frequency::Repeated),
@@ -281,14 +1493,20 @@ mod tests {
                             1,
                         )],
                         oneofs: vec![],
+                        order: vec![],
                     },
                 ],
                 enums: vec![Enum {
                     name: "inner_enum".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![EnumField {
                         name: "one".to_string(),
                         position: 1,
                     }],
+                    order: vec![],
                 }],
                 fields: vec![
                     MessageField::String(Some(Frequency::Optional), "inner_field".to_string(), 1),
@@ -298,10 +1516,15 @@ mod tests {
                         2,
                     ),
                 ],
+                order: vec![],
             }],
             enums: vec![
                 Enum {
                     name: "Person".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![
                         EnumField {
                             name: "name".to_string(),
@@ -316,9 +1539,14 @@ mod tests {
                             position: 3,
                         },
                     ],
+                    order: vec![],
                 },
                 Enum {
                     name: "Other".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![
                         EnumField {
                             name: "one".to_string(),
@@ -333,6 +1561,7 @@ mod tests {
                             position: 3,
                         },
                     ],
+                    order: vec![],
                 },
             ],
             fields: vec![
@@ -340,9 +1569,431 @@ mod tests {
                 MessageField::String(Some(Frequency::Repeated), "second".to_string(), 2),
                 MessageField::String(Some(Frequency::Optional), "third".to_string(), 3),
             ],
+            order: vec![],
         };
 
-        assert_eq!(input.pretty_print(0),
-        "message blah {\nrepeated int32 first 1;\nrepeated string second 2;\noptional string third 3;\nenum Person {\n\t\tname = 1;\n\t\tid = 2;\n\t\tage = 3;\n}enum Other {\n\t\tone = 1;\n\t\ttwo = 2;\n\t\tthree = 3;\n}message inner {\n\toptional string inner_field 1;\n\trepeated int32 second_inner_field 2;\nenum inner_enum {\n\t\t\tone = 1;\n}message inner_inner {\n\t\toptional string inner_inner_field 1;\n\t\trepeated int32 second_inner_inner_field 2;\nenum inner_inner_enum {\n\t\t\t\tone = 1;\n}}message second_inner_inner {\n\t\trepeated int32 inner_field 1;\nenum inner_inner_inner_enum {\n\t\t\t\tone = 1;\n\t\t\t\ttwo = 2;\n}}}}");
+        assert_eq!(
+            input.pretty_print(0),
+            r#"message blah {
+  repeated int32 first = 1;
+  repeated string second = 2;
+  optional string third = 3;
+
+  enum Person {
+    name = 1;
+    id = 2;
+    age = 3;
+  }
+
+  enum Other {
+    one = 1;
+    two = 2;
+    three = 3;
+  }
+
+  message inner {
+    optional string inner_field = 1;
+    repeated int32 second_inner_field = 2;
+
+    enum inner_enum {
+      one = 1;
+    }
+
+    message inner_inner {
+      optional string inner_inner_field = 1;
+      repeated int32 second_inner_inner_field = 2;
+
+      enum inner_inner_enum {
+        one = 1;
+      }
+    }
+
+    message second_inner_inner {
+      repeated int32 inner_field = 1;
+
+      enum inner_inner_inner_enum {
+        one = 1;
+        two = 2;
+      }
+    }
+  }
+}
+"#
+        );
+    }
+
+    fn parse(source: &str) -> ProtoFile {
+        Parser::new(source).consume_file().unwrap()
+    }
+
+    #[test]
+    fn canonical_file() {
+        let source = r#"
+            syntax = "proto2"; package a.b;
+            import public "x.proto"; import "y.proto";
+            option java_package = "a\"b\n";
+            option (http) = { get: "/v1/{name}" additional_bindings { post: "/v2" } };
+            service S { rpc Get(stream Req) returns (Resp); rpc Put(.a.b.Req) returns (stream Resp) { option deprecated = true; } }
+            message Req {
+              extensions 100 to max [verification = UNVERIFIED];
+              reserved "foo"; reserved 2, 4 to 6;
+              map<string, Resp> items = 1 [deprecated = true, (x) = -1.5e+3];
+              oneof kind { int32 id = 3; }
+              option deprecated = true;
+              message Empty {}
+            }
+            enum Resp { option allow_alias = true; A = 0; B = 0x10 [(y) = -2]; reserved 3 to max; }
+            extend Req { optional int32 ext = 100; }
+        "#;
+        let expected = r#"syntax = "proto2";
+
+package a.b;
+
+import public "x.proto";
+import "y.proto";
+
+option java_package = "a\"b\n";
+option (http) = {
+  get: "/v1/{name}"
+  additional_bindings: {
+    post: "/v2"
+  }
+};
+
+service S {
+  rpc Get(stream Req) returns (Resp);
+  rpc Put(.a.b.Req) returns (stream Resp) {
+    option deprecated = true;
+  }
+}
+
+message Req {
+  extensions 100 to max [verification = UNVERIFIED];
+
+  reserved "foo";
+  reserved 2, 4 to 6;
+
+  map<string, Resp> items = 1 [deprecated = true, (x) = -1.5e+3];
+
+  oneof kind {
+    int32 id = 3;
+  }
+
+  option deprecated = true;
+
+  message Empty {}
+}
+
+enum Resp {
+  option allow_alias = true;
+
+  A = 0;
+  B = 16 [(y) = -2];
+
+  reserved 3 to max;
+}
+
+extend Req {
+  optional int32 ext = 100;
+}
+"#;
+        let file = parse(source);
+        let formatted = format(&file);
+        assert_eq!(formatted, expected);
+        assert_eq!(parse(&formatted), file);
+        assert_eq!(format(&parse(&formatted)), formatted);
+    }
+
+    /// A xorshift generator, so the property test needs no dependencies and
+    /// failures reproduce from the seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn chance(&mut self) -> bool {
+            self.below(3) == 0
+        }
+
+        fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())].clone()
+        }
+    }
+
+    /// Builds random ASTs within what the parser can produce. Names carry a
+    /// counter so map keys like field numbers and value names stay unique.
+    struct Gen {
+        rng: Rng,
+        next: u32,
+    }
+
+    impl Gen {
+        fn number(&mut self) -> u32 {
+            self.next += 1;
+            self.next
+        }
+
+        fn name(&mut self) -> String {
+            let stem = self.rng.pick(&["x", "Field", "snake_case", "a_b2"]);
+            format!("{}{}", stem, self.number())
+        }
+
+        fn type_name(&mut self) -> String {
+            self.rng
+                .pick(&["Foo", "a.Bar", ".pkg.Baz", "google.protobuf.Any"])
+                .to_string()
+        }
+
+        fn string(&mut self) -> String {
+            (0..self.rng.below(6))
+                .map(|_| {
+                    self.rng
+                        .pick(&['a', ' ', '"', '\'', '\\', '\n', '\t', '\0', 'é', '{'])
+                })
+                .collect()
+        }
+
+        fn constant(&mut self, depth: u32) -> Constant {
+            match self.rng.below(if depth < 2 { 8 } else { 7 }) {
+                0 => Constant::Ident(self.rng.pick(&["FOO", "a.B", "-inf", "nan"]).to_string()),
+                1 => Constant::Int(self.rng.next() as i64 as i128),
+                2 => Constant::Float(
+                    self.rng
+                        .pick(&["1.5", "-0.25", "2e+10", "1E-3"])
+                        .to_string(),
+                ),
+                3 => Constant::String(self.string()),
+                4 => Constant::Bool(self.rng.chance()),
+                5 => Constant::Int(self.rng.below(100) as i128),
+                6 => {
+                    // A lone 0xff keeps the literal from being valid UTF-8.
+                    let mut bytes = self.string().into_bytes();
+                    let at = self.rng.below(bytes.len() + 1);
+                    bytes.insert(at, 0xff);
+                    Constant::Bytes(bytes)
+                }
+                _ => Constant::Aggregate(
+                    (0..self.rng.below(4))
+                        .map(|_| {
+                            let name = if self.rng.chance() {
+                                format!("[{}]", self.type_name().trim_start_matches('.'))
+                            } else {
+                                self.name()
+                            };
+                            (name, self.constant(depth + 1))
+                        })
+                        .collect(),
+                ),
+            }
+        }
+
+        fn options(&mut self) -> Vec<ProtoOption> {
+            (0..self.rng.below(3))
+                .map(|_| ProtoOption {
+                    name: self
+                        .rng
+                        .pick(&["deprecated", "(custom)", "(a.b).c"])
+                        .to_string(),
+                    value: self.constant(0),
+                })
+                .collect()
+        }
+
+        fn ranges(&mut self) -> Vec<(u32, u32)> {
+            (0..self.rng.below(3))
+                .map(|_| {
+                    let start = self.number();
+                    match self.rng.below(3) {
+                        0 => (start, start),
+                        1 => (start, Parser::MAX_FIELD_NUMBER),
+                        _ => (start, start + self.rng.below(50) as u32),
+                    }
+                })
+                .collect()
+        }
+
+        fn scalar(&mut self) -> Type {
+            Type::from(self.rng.pick(TYPES).to_string())
+        }
+
+        fn fields(
+            &mut self,
+            labelled: bool,
+        ) -> (Vec<MessageField>, BTreeMap<u32, Vec<ProtoOption>>) {
+            let mut fields = vec![];
+            let mut options = BTreeMap::new();
+            for _ in 0..self.rng.below(4) {
+                let frequency = if labelled {
+                    self.rng.pick(&[
+                        None,
+                        Some(Frequency::Optional),
+                        Some(Frequency::Repeated),
+                        Some(Frequency::Required),
+                    ])
+                } else {
+                    None
+                };
+                let t = match self.rng.below(3) {
+                    0 => self.scalar(),
+                    1 => Type::Named(self.type_name()),
+                    _ if labelled => Type::Map(
+                        Box::new(self.scalar()),
+                        Box::new(Type::Named(self.type_name())),
+                    ),
+                    _ => self.scalar(),
+                };
+                let field = MessageField::new(frequency, t, self.name(), self.number());
+                let field_options = self.options();
+                if !field_options.is_empty() {
+                    options.insert(field.position(), field_options);
+                }
+                fields.push(field);
+            }
+            (fields, options)
+        }
+
+        fn enumeration(&mut self) -> Enum {
+            let mut value_options = BTreeMap::new();
+            let fields = (0..self.rng.below(4))
+                .map(|_| {
+                    let name = self.name().to_uppercase();
+                    let options = self.options();
+                    if !options.is_empty() {
+                        value_options.insert(name.clone(), options);
+                    }
+                    EnumField {
+                        name,
+                        position: self.number(),
+                    }
+                })
+                .collect();
+            Enum {
+                name: self.name(),
+                fields,
+                options: self.options(),
+                value_options,
+                reserved_ranges: self.ranges(),
+                reserved_names: (0..self.rng.below(2)).map(|_| self.name()).collect(),
+                order: vec![],
+            }
+        }
+
+        fn extend(&mut self) -> Extend {
+            let (fields, field_options) = self.fields(true);
+            Extend {
+                extendee: self.type_name(),
+                fields,
+                field_options,
+            }
+        }
+
+        fn message(&mut self, depth: u32) -> Message {
+            let (fields, field_options) = self.fields(true);
+            let extension_ranges = self.ranges();
+            let mut extension_range_options = BTreeMap::new();
+            for (start, _) in &extension_ranges {
+                let options = self.options();
+                if !options.is_empty() {
+                    extension_range_options.insert(*start, options);
+                }
+            }
+            let nested = if depth < 2 { 3 } else { 1 };
+            Message {
+                name: self.name(),
+                messages: (0..self.rng.below(nested))
+                    .map(|_| self.message(depth + 1))
+                    .collect(),
+                enums: (0..self.rng.below(nested))
+                    .map(|_| self.enumeration())
+                    .collect(),
+                fields,
+                oneofs: (0..self.rng.below(2))
+                    .map(|_| {
+                        let (fields, field_options) = self.fields(false);
+                        Oneof {
+                            name: self.name(),
+                            fields,
+                            field_options,
+                            options: self.options(),
+                            ..Default::default()
+                        }
+                    })
+                    .collect(),
+                options: self.options(),
+                field_options,
+                extends: (0..self.rng.below(2)).map(|_| self.extend()).collect(),
+                extension_ranges,
+                extension_range_options,
+                reserved_ranges: self.ranges(),
+                reserved_names: (0..self.rng.below(2)).map(|_| self.name()).collect(),
+                order: vec![],
+            }
+        }
+
+        fn service(&mut self) -> Service {
+            Service {
+                name: self.name(),
+                methods: (0..self.rng.below(3))
+                    .map(|_| Method {
+                        name: self.name(),
+                        input_type: self.type_name(),
+                        output_type: self.type_name(),
+                        client_streaming: self.rng.chance(),
+                        server_streaming: self.rng.chance(),
+                        options: self.options(),
+                    })
+                    .collect(),
+                options: self.options(),
+            }
+        }
+
+        fn file(&mut self) -> ProtoFile {
+            ProtoFile {
+                name: String::new(),
+                syntax: self
+                    .rng
+                    .chance()
+                    .then(|| self.rng.pick(&["proto2", "proto3"]).to_string()),
+                package: self.rng.chance().then(|| "a.b_c".to_string()),
+                imports: (0..self.rng.below(3))
+                    .map(|_| {
+                        let public = self.rng.chance();
+                        Import {
+                            path: format!("{}.proto", self.name()),
+                            public,
+                            weak: !public && self.rng.chance(),
+                        }
+                    })
+                    .collect(),
+                options: self.options(),
+                messages: (0..self.rng.below(3)).map(|_| self.message(0)).collect(),
+                enums: (0..self.rng.below(3)).map(|_| self.enumeration()).collect(),
+                services: (0..self.rng.below(2)).map(|_| self.service()).collect(),
+                extends: (0..self.rng.below(2)).map(|_| self.extend()).collect(),
+                order: vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn format_round_trips() {
+        for seed in 1..=500u64 {
+            let mut gen = Gen {
+                rng: Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+                next: 0,
+            };
+            let file = gen.file();
+            let formatted = format(&file);
+            assert_eq!(parse(&formatted), file, "seed {}:\n{}", seed, formatted);
+        }
     }
 }
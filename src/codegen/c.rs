@@ -0,0 +1,1309 @@
+//! Generates C headers and sources in the style of nanopb: every message
+//! is a fixed-size struct, so encoding and decoding never allocate.
+//!
+//! Strings, bytes and repeated fields are arrays, sized by the
+//! `(nanopb).max_size` and `(nanopb).max_count` field options, declared by
+//! the bundled `nanopb.proto` as in nanopb, or by the generator's
+//! defaults. A string's `max_size` includes its NUL terminator. Optional
+//! scalars and submessages get a `has_` flag, repeated fields a `_count`
+//! and oneofs a `which_` holding the number of the member set. Each file
+//! becomes `x.pb.h` and `x.pb.c`, which use the runtime in `protos.h` and
+//! `protos.c`. The well-known types a file imports are generated with it,
+//! at their import paths.
+
+use super::{extension_fields, parameters, upper_camel, wire_type, Code, Context};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+const RUNTIME_HEADER: &str = include_str!("c/protos.h");
+const RUNTIME_SOURCE: &str = include_str!("c/protos.c");
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The `max_size` of string and bytes fields that don't set one.
+    pub max_size: Option<u32>,
+    /// The `max_count` of repeated and map fields that don't set one.
+    pub max_count: Option<u32>,
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters: `max_size=N` and
+    /// `max_count=N`.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            let unknown = || format!("unknown c generator parameter {}", item);
+            let (name, value) = item.split_once('=').ok_or_else(unknown)?;
+            let value = value.parse().map_err(|_| unknown())?;
+            match name {
+                "max_size" => options.max_size = Some(value),
+                "max_count" => options.max_count = Some(value),
+                _ => return Err(unknown()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A string or bytes field with no `max_size`, or a repeated or map
+    /// field with no `max_count`: the field's full name and the option.
+    Unbounded(String, &'static str),
+    /// A message that contains itself, which no fixed-size struct can.
+    Recursive(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unbounded(field, option) => {
+                write!(f, "field {} has no (nanopb).{}", field, option)
+            }
+            Error::Recursive(message) => {
+                write!(
+                    f,
+                    "message {} contains itself and has no fixed size",
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Generates `x.pb.h` and `x.pb.c` for each named file and the well-known
+/// files it imports, along with the runtime. Fails if a message in them
+/// can't have a fixed size.
+pub fn generate(
+    set: &FileSet,
+    files: &[&str],
+    options: &Options,
+) -> Result<BTreeMap<String, String>, Error> {
+    let mut types = BTreeMap::new();
+    for file in &set.files {
+        let (scope, prefix) = match &file.package {
+            Some(package) => (
+                format!(".{}", package),
+                format!("{}_", package.replace('.', "_")),
+            ),
+            None => (String::new(), String::new()),
+        };
+        for message in &file.messages {
+            declare_message(&mut types, &scope, &prefix, message);
+        }
+        for e in &file.enums {
+            declare(&mut types, &scope, &prefix, &e.name);
+        }
+    }
+    let mut flattener = Flattener {
+        types,
+        options,
+        structs: BTreeMap::new(),
+    };
+    let files = with_well_known(set, files);
+    let mut files_generated = vec![];
+    for file in &set.files {
+        let mut context = Context::new(set, file);
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        let mut layout = Layout::default();
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            layout
+                .enums
+                .push(flattener.enumeration(&context, e, &scope));
+            context.path.pop();
+        }
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            flattener.message(&mut context, &mut layout, message, &file.name, &scope);
+            context.path.pop();
+        }
+        if files.contains(file.name.as_str()) {
+            files_generated.push((file, layout));
+        }
+    }
+
+    let generator = Generator {
+        types: &flattener.types,
+        structs: &flattener.structs,
+    };
+    let mut sizes = BTreeMap::new();
+    for (_, layout) in &files_generated {
+        for name in &layout.structs {
+            generator.size(name, &mut vec![], &mut sizes)?;
+        }
+    }
+    let mut ret = BTreeMap::new();
+    ret.insert("protos.h".to_string(), RUNTIME_HEADER.to_string());
+    ret.insert("protos.c".to_string(), RUNTIME_SOURCE.to_string());
+    for (file, layout) in &files_generated {
+        let stem = file.name.strip_suffix(".proto").unwrap_or(&file.name);
+        let structs = generator.order(&file.name, &layout.structs);
+        ret.insert(
+            format!("{}.pb.h", stem),
+            generator.header(file, stem, layout, &structs, &sizes),
+        );
+        ret.insert(
+            format!("{}.pb.c", stem),
+            generator.source(file, stem, &structs),
+        );
+    }
+    Ok(ret)
+}
+
+/// Whether `path` is an option schema, which only annotates the files
+/// importing it and declares no C types.
+fn options_only(path: &str) -> bool {
+    path == "google/protobuf/descriptor.proto"
+        || well_known::OPTIONS.iter().any(|(name, _)| *name == path)
+}
+
+/// `files` and the bundled well-known files they import, directly or
+/// through one another.
+fn with_well_known<'a>(set: &'a FileSet, files: &[&'a str]) -> BTreeSet<&'a str> {
+    let mut ret = BTreeSet::new();
+    let mut pending = files.to_vec();
+    while let Some(name) = pending.pop() {
+        let Some(file) = set.files.iter().find(|file| file.name == name) else {
+            continue;
+        };
+        if !ret.insert(file.name.as_str()) {
+            continue;
+        }
+        pending.extend(
+            file.imports
+                .iter()
+                .map(|import| import.path.as_str())
+                .filter(|path| !options_only(path))
+                .filter(|path| well_known::FILES.iter().any(|(name, _)| name == path)),
+        );
+    }
+    ret
+}
+
+fn declare(
+    types: &mut BTreeMap<String, String>,
+    scope: &str,
+    prefix: &str,
+    name: &str,
+) -> (String, String) {
+    let full_name = format!("{}.{}", scope, name);
+    let c_name = format!("{}{}", prefix, name);
+    types.insert(full_name.clone(), c_name.clone());
+    (full_name, c_name)
+}
+
+fn declare_message(
+    types: &mut BTreeMap<String, String>,
+    scope: &str,
+    prefix: &str,
+    message: &Message,
+) {
+    let (full_name, name) = declare(types, scope, prefix, &message.name);
+    let prefix = format!("{}_", name);
+    for nested in &message.messages {
+        declare_message(types, &full_name, &prefix, nested);
+    }
+    for e in &message.enums {
+        declare(types, &full_name, &prefix, &e.name);
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "auto", "bool", "break", "case", "char", "const", "continue", "default", "do", "double",
+    "else", "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while",
+];
+
+/// A field name as a C identifier, with keywords escaped by a trailing
+/// underscore.
+fn identifier(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// How a field is stored and when it's written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Label {
+    /// Written unless zero or empty.
+    Implicit,
+    /// Written if its `has_` flag is set.
+    Optional,
+    /// Always written.
+    Required,
+    Repeated,
+    /// Repeated and written packed.
+    Packed,
+    /// A member of the message's oneof at this index.
+    Oneof(usize),
+}
+
+/// A field as laid out in a struct.
+struct Field<'a> {
+    /// The field's full name, for errors.
+    full_name: String,
+    /// The field's name in the schema.
+    name: String,
+    number: u32,
+    /// A map field's type is its entry struct.
+    t: Type,
+    label: Label,
+    max_size: Option<u32>,
+    max_count: Option<u32>,
+    comment: Option<&'a String>,
+}
+
+impl Field<'_> {
+    fn is_packable(&self) -> bool {
+        !matches!(self.t, Type::String | Type::Bytes | Type::Message(_))
+    }
+}
+
+/// A message, or the entry of a map field, as a struct.
+struct Struct<'a> {
+    name: String,
+    file: &'a str,
+    fields: Vec<Field<'a>>,
+    /// Oneof names, which are also the names of their unions.
+    oneofs: Vec<(String, Option<&'a String>)>,
+    comment: Option<&'a String>,
+}
+
+struct EnumLayout<'a> {
+    name: String,
+    e: &'a Enum,
+    comment: Option<&'a String>,
+    value_comments: Vec<Option<&'a String>>,
+}
+
+/// The enums and structs declared in a file, in declaration order.
+#[derive(Default)]
+struct Layout<'a> {
+    enums: Vec<EnumLayout<'a>>,
+    /// Full names of the file's structs.
+    structs: Vec<String>,
+}
+
+/// Turns the messages of every file into structs, keyed by full name.
+struct Flattener<'a> {
+    /// C names by full name.
+    types: BTreeMap<String, String>,
+    options: &'a Options,
+    structs: BTreeMap<String, Struct<'a>>,
+}
+
+impl<'a> Flattener<'a> {
+    fn enumeration(&self, context: &Context<'a>, e: &'a Enum, scope: &str) -> EnumLayout<'a> {
+        EnumLayout {
+            name: self.types[&format!("{}.{}", scope, e.name)].clone(),
+            e,
+            comment: context.comment(&[]),
+            value_comments: (0..e.fields.len())
+                .map(|i| context.comment(&[PathSegment::EnumValue(i)]))
+                .collect(),
+        }
+    }
+
+    fn message(
+        &mut self,
+        context: &mut Context<'a>,
+        layout: &mut Layout<'a>,
+        message: &'a Message,
+        file: &'a str,
+        scope: &str,
+    ) {
+        let full_name = format!("{}.{}", scope, message.name);
+        let name = self.types[&full_name].clone();
+        let mut fields = vec![];
+        for (i, field) in message.fields.iter().enumerate() {
+            let options = message.field_options.get(&field.position());
+            let comment = context.comment(&[PathSegment::Field(i)]);
+            let field = self.field(
+                layout, file, &full_name, field, options, None, context, comment,
+            );
+            fields.push(field);
+        }
+        let mut oneofs = vec![];
+        for (i, oneof) in message.oneofs.iter().enumerate() {
+            oneofs.push((
+                oneof.name.clone(),
+                context.comment(&[PathSegment::Oneof(i)]),
+            ));
+            context.path.push(PathSegment::Oneof(i));
+            for (j, field) in oneof.fields.iter().enumerate() {
+                let options = oneof.field_options.get(&field.position());
+                let comment = context.comment(&[PathSegment::Field(j)]);
+                let field = self.field(
+                    layout,
+                    file,
+                    &full_name,
+                    field,
+                    options,
+                    Some(i),
+                    context,
+                    comment,
+                );
+                fields.push(field);
+            }
+            context.path.pop();
+        }
+        layout.structs.push(full_name.clone());
+        self.structs.insert(
+            full_name.clone(),
+            Struct {
+                name,
+                file,
+                fields,
+                oneofs,
+                comment: context.comment(&[]),
+            },
+        );
+
+        for (i, e) in message.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            layout.enums.push(self.enumeration(context, e, &full_name));
+            context.path.pop();
+        }
+        for (i, nested) in message.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            self.message(context, layout, nested, file, &full_name);
+            context.path.pop();
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn field(
+        &mut self,
+        layout: &mut Layout<'a>,
+        file: &'a str,
+        owner: &str,
+        field: &MessageField,
+        options: Option<&Vec<ProtoOption>>,
+        oneof: Option<usize>,
+        context: &Context,
+        comment: Option<&'a String>,
+    ) -> Field<'a> {
+        let option = |name: &str| {
+            options
+                .into_iter()
+                .flatten()
+                .find(|option| option.name == name)
+                .map(|option| &option.value)
+        };
+        let nanopb = extension_fields(options.map_or(&[], |options| &options[..]), "nanopb");
+        let bound = |name: &str| {
+            nanopb.iter().rev().find_map(|(key, value)| match value {
+                Constant::Int(value) if key == name => u32::try_from(*value).ok(),
+                _ => None,
+            })
+        };
+        let max_size = bound("max_size").or(self.options.max_size);
+        let max_count = bound("max_count").or(self.options.max_count);
+        let full_name = format!("{}.{}", &owner[1..], field.name());
+        let mut ret = Field {
+            full_name: full_name.clone(),
+            name: field.name().to_string(),
+            number: field.position(),
+            t: field.field_type(),
+            label: Label::Implicit,
+            max_size,
+            max_count,
+            comment,
+        };
+        ret.label = match (oneof, &ret.t, field.frequency()) {
+            (Some(i), _, _) => Label::Oneof(i),
+            (_, Type::Map(key, value), _) => {
+                let entry_name = format!("{}Entry", upper_camel(field.name()));
+                let entry_full_name = format!("{}.{}", owner, entry_name);
+                let entry = |name: &str, number, t: &Type| Field {
+                    full_name: full_name.clone(),
+                    name: name.to_string(),
+                    number,
+                    t: t.clone(),
+                    label: Label::Required,
+                    max_size,
+                    max_count: None,
+                    comment: None,
+                };
+                let name = format!("{}_{}", self.types[owner], entry_name);
+                self.types.insert(entry_full_name.clone(), name.clone());
+                layout.structs.push(entry_full_name.clone());
+                self.structs.insert(
+                    entry_full_name.clone(),
+                    Struct {
+                        name,
+                        file,
+                        fields: vec![entry("key", 1, key), entry("value", 2, value)],
+                        oneofs: vec![],
+                        comment: None,
+                    },
+                );
+                ret.t = Type::Message(entry_full_name);
+                Label::Repeated
+            }
+            (_, _, Some(Frequency::Repeated)) => {
+                let packed =
+                    option("packed").map_or(context.proto3, |value| *value == Constant::Bool(true));
+                if ret.is_packable() && packed {
+                    Label::Packed
+                } else {
+                    Label::Repeated
+                }
+            }
+            (_, _, Some(Frequency::Required)) => Label::Required,
+            (_, _, Some(Frequency::Optional)) | (_, Type::Message(_), None) => Label::Optional,
+            (_, _, None) if !context.proto3 => Label::Optional,
+            (_, _, None) => Label::Implicit,
+        };
+        ret
+    }
+}
+
+/// The number of bytes `value` takes as a varint.
+fn varint_len(value: usize) -> usize {
+    let mut len = 1;
+    let mut value = value >> 7;
+    while value > 0 {
+        len += 1;
+        value >>= 7;
+    }
+    len
+}
+
+/// Writes a statement failing the function unless every call succeeds.
+fn require(code: &mut Code, calls: &[String]) {
+    let conditions: Vec<String> = calls.iter().map(|call| format!("!{}", call)).collect();
+    code.open(format!("if ({})", conditions.join(" || ")));
+    code.line("return false;");
+    code.close();
+}
+
+struct Generator<'a> {
+    types: &'a BTreeMap<String, String>,
+    structs: &'a BTreeMap<String, Struct<'a>>,
+}
+
+impl<'a> Generator<'a> {
+    /// The most bytes an encoded message can take, checking that it has
+    /// a fixed size. `stack` holds the messages being sized.
+    fn size(
+        &self,
+        full_name: &str,
+        stack: &mut Vec<String>,
+        sizes: &mut BTreeMap<String, usize>,
+    ) -> Result<usize, Error> {
+        if let Some(size) = sizes.get(full_name) {
+            return Ok(*size);
+        }
+        if stack.iter().any(|name| name == full_name) {
+            return Err(Error::Recursive(full_name[1..].to_string()));
+        }
+        stack.push(full_name.to_string());
+        let s = &self.structs[full_name];
+        let mut size = 0;
+        let mut oneofs = vec![0; s.oneofs.len()];
+        for field in &s.fields {
+            let bounded = |bound: Option<u32>, option| {
+                bound
+                    .map(|bound| bound as usize)
+                    .ok_or_else(|| Error::Unbounded(field.full_name.clone(), option))
+            };
+            let value = match &field.t {
+                Type::Int32 | Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Enum(_) => 10,
+                Type::Uint32 | Type::Sint32 => 5,
+                Type::Bool => 1,
+                Type::Fixed32 | Type::Sfixed32 | Type::Float => 4,
+                Type::Fixed64 | Type::Sfixed64 | Type::Double => 8,
+                Type::String => {
+                    let len = bounded(field.max_size, "max_size")?.saturating_sub(1);
+                    varint_len(len) + len
+                }
+                Type::Bytes => {
+                    let len = bounded(field.max_size, "max_size")?;
+                    varint_len(len) + len
+                }
+                Type::Message(name) => {
+                    let len = self.size(name, stack, sizes)?;
+                    varint_len(len) + len
+                }
+                Type::Named(name) => panic!("type {} is not linked", name),
+                Type::Map(..) => unreachable!("maps are entry structs"),
+            };
+            let key = varint_len((field.number as usize) << 3);
+            match field.label {
+                Label::Repeated => size += bounded(field.max_count, "max_count")? * (key + value),
+                Label::Packed => {
+                    let values = bounded(field.max_count, "max_count")? * value;
+                    if values > 0 {
+                        size += key + varint_len(values) + values;
+                    }
+                }
+                Label::Oneof(i) => oneofs[i] = oneofs[i].max(key + value),
+                _ => size += key + value,
+            }
+        }
+        size += oneofs.iter().sum::<usize>();
+        stack.pop();
+        sizes.insert(full_name.to_string(), size);
+        Ok(size)
+    }
+
+    /// The structs declared in `file`, each after those in the file it
+    /// contains.
+    fn order(&self, file: &str, names: &'a [String]) -> Vec<&'a Struct<'a>> {
+        fn visit<'a>(
+            generator: &Generator<'a>,
+            file: &str,
+            name: &str,
+            visited: &mut BTreeSet<String>,
+            ret: &mut Vec<&'a Struct<'a>>,
+        ) {
+            if !visited.insert(name.to_string()) {
+                return;
+            }
+            let s = &generator.structs[name];
+            for field in &s.fields {
+                if let Type::Message(dependency) = &field.t {
+                    if generator.structs[dependency].file == file {
+                        visit(generator, file, dependency, visited, ret);
+                    }
+                }
+            }
+            ret.push(s);
+        }
+        let mut visited = BTreeSet::new();
+        let mut ret = vec![];
+        for name in names {
+            visit(self, file, name, &mut visited, &mut ret);
+        }
+        ret
+    }
+
+    /// The C type of one value of a field.
+    fn c_type(&self, owner: &Struct, field: &Field) -> String {
+        match &field.t {
+            Type::Double => "double".to_string(),
+            Type::Float => "float".to_string(),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => "int32_t".to_string(),
+            Type::Int64 | Type::Sint64 | Type::Sfixed64 => "int64_t".to_string(),
+            Type::Uint32 | Type::Fixed32 => "uint32_t".to_string(),
+            Type::Uint64 | Type::Fixed64 => "uint64_t".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "char".to_string(),
+            Type::Bytes => format!("{}_{}_t", owner.name, field.name),
+            Type::Enum(name) | Type::Message(name) => self.types[name].clone(),
+            Type::Named(name) => panic!("type {} is not linked", name),
+            Type::Map(..) => unreachable!("maps are entry structs"),
+        }
+    }
+
+    /// The runtime function suffix for a scalar type.
+    fn method(t: &Type) -> &'static str {
+        match t {
+            Type::Int32 => "int32",
+            Type::Int64 => "int64",
+            Type::Uint32 => "uint32",
+            Type::Uint64 => "uint64",
+            Type::Sint32 => "sint32",
+            Type::Sint64 => "sint64",
+            Type::Fixed32 => "fixed32",
+            Type::Fixed64 => "fixed64",
+            Type::Sfixed32 => "sfixed32",
+            Type::Sfixed64 => "sfixed64",
+            Type::Bool => "bool",
+            Type::Float => "float",
+            Type::Double => "double",
+            _ => unreachable!("{:?} is not a scalar", t),
+        }
+    }
+
+    /// A call writing the value `value` of a field to `writer`.
+    fn write_value(&self, t: &Type, value: &str, writer: &str) -> String {
+        match t {
+            Type::String => format!("protos_write_string({}, {})", writer, value),
+            Type::Bytes => format!(
+                "protos_write_bytes({}, {}.bytes, {}.size)",
+                writer, value, value
+            ),
+            Type::Enum(_) => format!("protos_write_int32({}, (int32_t){})", writer, value),
+            Type::Message(name) => {
+                format!(
+                    "{}_write_delimited(&{}, {})",
+                    self.types[name], value, writer
+                )
+            }
+            t => format!("protos_write_{}({}, {})", Self::method(t), writer, value),
+        }
+    }
+
+    /// A call reading a value of a field from `reader` into `value`.
+    fn read_value(&self, t: &Type, value: &str, reader: &str) -> String {
+        match t {
+            Type::String => format!(
+                "protos_read_string({}, {}, sizeof {})",
+                reader, value, value
+            ),
+            Type::Bytes => format!(
+                "protos_read_bytes({}, {}.bytes, sizeof {}.bytes, &{}.size)",
+                reader, value, value, value
+            ),
+            Type::Enum(name) | Type::Message(name) => {
+                let suffix = if matches!(t, Type::Message(_)) {
+                    "_delimited"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}_read{}(&{}, {})",
+                    self.types[name], suffix, value, reader
+                )
+            }
+            t => format!("protos_read_{}({}, &{})", Self::method(t), reader, value),
+        }
+    }
+
+    fn header(
+        &self,
+        file: &ProtoFile,
+        stem: &str,
+        layout: &Layout,
+        structs: &[&Struct],
+        sizes: &BTreeMap<String, usize>,
+    ) -> String {
+        let guard: String = format!("PROTOS_{}_PB_H", stem)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut code = Code::new("    ");
+        code.line(format!(
+            "// Generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        code.line("");
+        code.line(format!("#ifndef {}", guard));
+        code.line(format!("#define {}", guard));
+        code.line("");
+        code.line("#include \"protos.h\"");
+        for import in file
+            .imports
+            .iter()
+            .filter(|import| !options_only(&import.path))
+        {
+            let stem = import.path.strip_suffix(".proto").unwrap_or(&import.path);
+            code.line(format!("#include \"{}.pb.h\"", stem));
+        }
+
+        for e in &layout.enums {
+            code.line("");
+            code.comment("//", e.comment);
+            code.open(format!("typedef enum _{}", e.name));
+            for (value, comment) in e.e.fields.iter().zip(&e.value_comments) {
+                code.comment("//", *comment);
+                code.line(format!(
+                    "{}_{} = {},",
+                    e.name, value.name, value.position as i32
+                ));
+            }
+            code.close_with(&format!("}} {};", e.name));
+            code.line("");
+            code.open(format!(
+                "static inline bool {}_read({} *value, protos_reader *reader)",
+                e.name, e.name
+            ));
+            code.line("int32_t number;");
+            require(
+                &mut code,
+                &["protos_read_int32(reader, &number)".to_string()],
+            );
+            code.line(format!("*value = ({})number;", e.name));
+            code.line("return true;");
+            code.close();
+        }
+
+        for s in structs {
+            for field in s.fields.iter().filter(|field| field.t == Type::Bytes) {
+                code.line("");
+                code.open("typedef struct");
+                code.line("size_t size;");
+                code.line(format!("uint8_t bytes[{}];", field.max_size.unwrap()));
+                code.close_with(&format!("}} {};", self.c_type(s, field)));
+            }
+            code.line("");
+            code.comment("//", s.comment);
+            code.open(format!("typedef struct _{}", s.name));
+            if s.fields.is_empty() {
+                code.line("// C structs can't be empty.");
+                code.line("char dummy_field;");
+            }
+            for field in s
+                .fields
+                .iter()
+                .filter(|field| !matches!(field.label, Label::Oneof(_)))
+            {
+                code.comment("//", field.comment);
+                self.member(&mut code, s, field);
+            }
+            for (i, (oneof, comment)) in s.oneofs.iter().enumerate() {
+                code.comment("//", *comment);
+                code.line(format!("uint32_t which_{};", oneof));
+                code.open("union");
+                for field in s
+                    .fields
+                    .iter()
+                    .filter(|field| field.label == Label::Oneof(i))
+                {
+                    code.comment("//", field.comment);
+                    self.member(&mut code, s, field);
+                }
+                code.close_with(&format!("}} {};", identifier(oneof)));
+            }
+            code.close_with(&format!("}} {};", s.name));
+        }
+
+        if !structs.is_empty() {
+            code.line("");
+            code.line("// The most bytes an encoded message of each type can take.");
+            for (full_name, s) in self.structs.iter().filter(|(_, s)| s.file == file.name) {
+                code.line(format!("#define {}_size {}", s.name, sizes[full_name]));
+            }
+        }
+        if !structs.is_empty() {
+            code.line("");
+            code.line("// encode and decode work on whole buffers, write and read on the");
+            code.line("// runtime's writers and readers. decode clears the message first,");
+            code.line("// while read merges into it. All fail rather than overrun a buffer");
+            code.line("// or a field's bounds.");
+        }
+        for s in structs {
+            let name = &s.name;
+            code.line("");
+            code.line(format!(
+                "bool {}_encode(const {} *message, uint8_t *buf, size_t size, size_t *written);",
+                name, name
+            ));
+            code.line(format!(
+                "bool {}_decode({} *message, const uint8_t *buf, size_t size);",
+                name, name
+            ));
+            code.line(format!(
+                "bool {}_write(const {} *message, protos_writer *writer);",
+                name, name
+            ));
+            code.line(format!(
+                "bool {}_read({} *message, protos_reader *reader);",
+                name, name
+            ));
+            code.line(format!(
+                "bool {}_write_delimited(const {} *message, protos_writer *writer);",
+                name, name
+            ));
+            code.line(format!(
+                "bool {}_read_delimited({} *message, protos_reader *reader);",
+                name, name
+            ));
+        }
+        code.line("");
+        code.line("#endif");
+        code.text
+    }
+
+    /// The struct members holding a field.
+    fn member(&self, code: &mut Code, s: &Struct, field: &Field) {
+        let name = identifier(&field.name);
+        let mut dimensions = String::new();
+        if matches!(field.label, Label::Repeated | Label::Packed) {
+            let count = field.max_count.unwrap();
+            code.line(format!("size_t {}_count;", field.name));
+            dimensions.push_str(&format!("[{}]", count));
+        }
+        if field.t == Type::String {
+            dimensions.push_str(&format!("[{}]", field.max_size.unwrap()));
+        }
+        if field.label == Label::Optional {
+            code.line(format!("bool has_{};", field.name));
+        }
+        code.line(format!("{} {}{};", self.c_type(s, field), name, dimensions));
+    }
+
+    fn source(&self, file: &ProtoFile, stem: &str, structs: &[&Struct]) -> String {
+        let mut code = Code::new("    ");
+        code.line(format!(
+            "// Generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        code.line("");
+        code.line(format!("#include \"{}.pb.h\"", stem));
+        code.line("");
+        code.line("#include <string.h>");
+        for s in structs {
+            let name = &s.name;
+            code.line("");
+            code.open(format!(
+                "bool {}_encode(const {} *message, uint8_t *buf, size_t size, size_t *written)",
+                name, name
+            ));
+            code.line("protos_writer writer = {buf, size, 0};");
+            require(&mut code, &[format!("{}_write(message, &writer)", name)]);
+            code.line("*written = writer.pos;");
+            code.line("return true;");
+            code.close();
+
+            code.line("");
+            code.open(format!(
+                "bool {}_decode({} *message, const uint8_t *buf, size_t size)",
+                name, name
+            ));
+            code.line("protos_reader reader = {buf, size, 0};");
+            code.line("memset(message, 0, sizeof *message);");
+            code.line(format!("return {}_read(message, &reader);", name));
+            code.close();
+
+            code.line("");
+            code.open(format!(
+                "bool {}_write_delimited(const {} *message, protos_writer *writer)",
+                name, name
+            ));
+            code.line("protos_writer counter = PROTOS_COUNTER;");
+            require(
+                &mut code,
+                &[
+                    format!("{}_write(message, &counter)", name),
+                    "protos_write_varint(writer, counter.pos)".to_string(),
+                ],
+            );
+            code.line(format!("return {}_write(message, writer);", name));
+            code.close();
+
+            code.line("");
+            code.open(format!(
+                "bool {}_read_delimited({} *message, protos_reader *reader)",
+                name, name
+            ));
+            code.line("protos_reader value;");
+            code.line(format!(
+                "return protos_read_delimited(reader, &value) && {}_read(message, &value);",
+                name
+            ));
+            code.close();
+
+            self.write(&mut code, s);
+            self.read(&mut code, s);
+        }
+        code.text
+    }
+
+    /// The expression naming a field's value, or its array.
+    fn value(s: &Struct, field: &Field) -> String {
+        match field.label {
+            Label::Oneof(i) => format!(
+                "message->{}.{}",
+                identifier(&s.oneofs[i].0),
+                identifier(&field.name)
+            ),
+            _ => format!("message->{}", identifier(&field.name)),
+        }
+    }
+
+    fn write(&self, code: &mut Code, s: &Struct) {
+        code.line("");
+        code.open(format!(
+            "bool {}_write(const {} *message, protos_writer *writer)",
+            s.name, s.name
+        ));
+        if s.fields.is_empty() {
+            code.line("(void)message;");
+            code.line("(void)writer;");
+        }
+        let mut fields: Vec<&Field> = s.fields.iter().collect();
+        fields.sort_by_key(|field| field.number);
+        for field in fields {
+            let value = Self::value(s, field);
+            let tag =
+                |wire_type| format!("protos_write_tag(writer, {}, {})", field.number, wire_type);
+            let count = format!("message->{}_count", field.name);
+            match field.label {
+                Label::Repeated => {
+                    code.open(format!("for (size_t i = 0; i < {}; i++)", count));
+                    let element = format!("{}[i]", value);
+                    require(
+                        code,
+                        &[
+                            tag(wire_type(&field.t)),
+                            self.write_value(&field.t, &element, "writer"),
+                        ],
+                    );
+                    code.close();
+                }
+                Label::Packed => {
+                    let element = format!("{}[i]", value);
+                    code.open(format!("if ({} > 0)", count));
+                    code.line("protos_writer counter = PROTOS_COUNTER;");
+                    code.open(format!("for (size_t i = 0; i < {}; i++)", count));
+                    code.line(format!(
+                        "{};",
+                        self.write_value(&field.t, &element, "&counter")
+                    ));
+                    code.close();
+                    require(
+                        code,
+                        &[
+                            tag(2),
+                            "protos_write_varint(writer, counter.pos)".to_string(),
+                        ],
+                    );
+                    code.open(format!("for (size_t i = 0; i < {}; i++)", count));
+                    require(code, &[self.write_value(&field.t, &element, "writer")]);
+                    code.close();
+                    code.close();
+                }
+                label => {
+                    let condition = match label {
+                        Label::Implicit => Some(match field.t {
+                            Type::String => format!("{}[0] != '\\0'", value),
+                            Type::Bytes => format!("{}.size != 0", value),
+                            _ => format!("{} != 0", value),
+                        }),
+                        Label::Optional => Some(format!("message->has_{}", field.name)),
+                        Label::Oneof(i) => Some(format!(
+                            "message->which_{} == {}",
+                            s.oneofs[i].0, field.number
+                        )),
+                        _ => None,
+                    };
+                    let calls = [
+                        tag(wire_type(&field.t)),
+                        self.write_value(&field.t, &value, "writer"),
+                    ];
+                    match condition {
+                        Some(condition) => {
+                            code.open(format!("if ({})", condition));
+                            require(code, &calls);
+                            code.close();
+                        }
+                        None => require(code, &calls),
+                    }
+                }
+            }
+        }
+        code.line("return true;");
+        code.close();
+    }
+
+    fn read(&self, code: &mut Code, s: &Struct) {
+        code.line("");
+        code.open(format!(
+            "bool {}_read({} *message, protos_reader *reader)",
+            s.name, s.name
+        ));
+        if s.fields.is_empty() {
+            code.line("(void)message;");
+        }
+        code.open("while (reader->pos < reader->size)");
+        code.line("uint32_t number, wire_type;");
+        require(
+            code,
+            &["protos_read_tag(reader, &number, &wire_type)".to_string()],
+        );
+        code.line("switch (number) {");
+        let mut fields: Vec<&Field> = s.fields.iter().collect();
+        fields.sort_by_key(|field| field.number);
+        for field in fields {
+            let value = Self::value(s, field);
+            let wire = wire_type(&field.t);
+            code.line(format!("case {}:", field.number));
+            code.depth += 1;
+            match field.label {
+                Label::Repeated | Label::Packed => {
+                    let count = format!("message->{}_count", field.name);
+                    let full = format!("{} == {}", count, field.max_count.unwrap());
+                    let element = format!("{}[{}]", value, count);
+                    // Packable fields are read packed or not, whichever
+                    // they were written as.
+                    if field.is_packable() {
+                        code.open("if (wire_type == 2)");
+                        code.line("protos_reader packed;");
+                        require(
+                            code,
+                            &["protos_read_delimited(reader, &packed)".to_string()],
+                        );
+                        code.open("while (packed.pos < packed.size)");
+                        code.open(format!("if ({})", full));
+                        code.line("return false;");
+                        code.close();
+                        require(code, &[self.read_value(&field.t, &element, "&packed")]);
+                        code.line(format!("{}++;", count));
+                        code.close();
+                        code.close_with("} else {");
+                        code.depth += 1;
+                    }
+                    code.open(format!("if (wire_type != {} || {})", wire, full));
+                    code.line("return false;");
+                    code.close();
+                    require(code, &[self.read_value(&field.t, &element, "reader")]);
+                    code.line(format!("{}++;", count));
+                    if field.is_packable() {
+                        code.close();
+                    }
+                }
+                label => {
+                    if let (Label::Oneof(i), Type::Message(_)) = (label, &field.t) {
+                        code.open(format!(
+                            "if (message->which_{} != {})",
+                            s.oneofs[i].0, field.number
+                        ));
+                        code.line(format!("memset(&{}, 0, sizeof {});", value, value));
+                        code.close();
+                    }
+                    code.open(format!(
+                        "if (wire_type != {} || !{})",
+                        wire,
+                        self.read_value(&field.t, &value, "reader")
+                    ));
+                    code.line("return false;");
+                    code.close();
+                    match label {
+                        Label::Optional => {
+                            code.line(format!("message->has_{} = true;", field.name))
+                        }
+                        Label::Oneof(i) => code.line(format!(
+                            "message->which_{} = {};",
+                            s.oneofs[i].0, field.number
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+            code.line("break;");
+            code.depth -= 1;
+        }
+        code.line("default:");
+        code.depth += 1;
+        require(code, &["protos_skip(reader, wire_type)".to_string()]);
+        code.depth -= 1;
+        code.line("}");
+        code.close();
+        code.line("return true;");
+        code.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic::DynamicMessage;
+    use crate::resolver::Resolver;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/c")
+            .load(&["sensor.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["sensor.proto"], &Options::default()).unwrap();
+        let header = std::fs::read_to_string("tests/c/sensor.pb.h").unwrap();
+        let source = std::fs::read_to_string("tests/c/sensor.pb.c").unwrap();
+        assert_eq!(generated["sensor.pb.h"], header);
+        assert_eq!(generated["sensor.pb.c"], source);
+        assert_eq!(generated["protos.h"], RUNTIME_HEADER);
+    }
+
+    #[test]
+    fn compiles_and_round_trips() {
+        let dir = std::env::temp_dir().join(format!("protos-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let set = load();
+        for (name, code) in generate(&set, &["sensor.proto"], &Options::default()).unwrap() {
+            std::fs::write(dir.join(name), code).unwrap();
+        }
+        let mut reading = DynamicMessage::from_file_set(Arc::new(set), "sensor.Reading").unwrap();
+        text_format::merge(
+            r#"device: "probe-7" sequence: 42 offset: -9000000000 temperature: 21.5
+               samples: [3, -1, 2147483647] signature: "\x00\xffsig"
+               location { lat: 48.85 lng: 2.35 } status: STATUS_FAULT
+               origin { lat: -1 } counters { key: "a" value: -5 }
+               tags: ["x", "yz"] urgent: true trail { lng: 7 } trail {}"#,
+            &mut reading,
+        )
+        .unwrap();
+        std::fs::write(dir.join("in.bin"), reading.encode_to_vec()).unwrap();
+        std::fs::write(
+            dir.join("main.c"),
+            r#"#include <stdio.h>
+#include <string.h>
+
+#include "sensor.pb.h"
+
+int main(void) {
+    uint8_t in[sensor_Reading_size], out[sensor_Reading_size];
+    FILE *file = fopen("in.bin", "rb");
+    size_t size = fread(in, 1, sizeof in, file), written;
+    fclose(file);
+    sensor_Reading reading;
+    if (!sensor_Reading_decode(&reading, in, size) || reading.samples_count != 3 ||
+        reading.which_payload != 10 || !reading.has_temperature ||
+        reading.status != sensor_Status_STATUS_FAULT || reading.offset != -9000000000 ||
+        strcmp(reading.counters[0].key, "a") != 0 || reading.signature.size != 5) {
+        return 1;
+    }
+    if (!sensor_Reading_encode(&reading, out, sizeof out, &written) ||
+        sensor_Reading_encode(&reading, out, written - 1, &written)) {
+        return 2;
+    }
+    sensor_Reading_encode(&reading, out, sizeof out, &written);
+    file = fopen("out.bin", "wb");
+    fwrite(out, 1, written, file);
+    fclose(file);
+    /* Too many samples for the struct. */
+    const uint8_t overflow[] = {0x2a, 9, 1, 2, 3, 4, 5, 6, 7, 8, 9};
+    return sensor_Reading_decode(&reading, overflow, sizeof overflow) ? 3 : 0;
+}
+"#,
+        )
+        .unwrap();
+        let output = match Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I."])
+            .args(["main.c", "sensor.pb.c", "protos.c", "-o", "main"])
+            .current_dir(&dir)
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping compiles_and_round_trips: cc is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let status = Command::new(dir.join("main"))
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(0));
+        let mut decoded = reading.new_message("sensor.Reading").unwrap();
+        decoded
+            .merge(&std::fs::read(dir.join("out.bin")).unwrap())
+            .unwrap();
+        assert_eq!(decoded, reading);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Imported well-known types are generated at their import paths.
+    #[test]
+    fn compiles_well_known_types() {
+        let dir = std::env::temp_dir().join(format!("protos-c-wkt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("event.proto"),
+            "syntax = \"proto3\"; import \"google/protobuf/timestamp.proto\";
+             message Event { google.protobuf.Timestamp at = 1; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["event.proto"])
+            .unwrap();
+        let generated = generate(&set, &["event.proto"], &Options::default()).unwrap();
+        assert!(generated["event.pb.h"].contains("#include \"google/protobuf/timestamp.pb.h\"\n"));
+        for (name, code) in generated {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, code).unwrap();
+        }
+        std::fs::write(
+            dir.join("main.c"),
+            r#"#include "event.pb.h"
+
+int main(void) {
+    uint8_t out[Event_size];
+    size_t written;
+    Event event = {.has_at = true, .at = {.seconds = 5, .nanos = 7}};
+    return Event_encode(&event, out, sizeof out, &written) && written == 6 ? 0 : 1;
+}
+"#,
+        )
+        .unwrap();
+        let output = match Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I."])
+            .args(["main.c", "event.pb.c", "google/protobuf/timestamp.pb.c"])
+            .args(["protos.c", "-o", "main"])
+            .current_dir(&dir)
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping compiles_well_known_types: cc is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let status = Command::new(dir.join("main"))
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bounds_and_recursion() {
+        let parse = |source: &str| {
+            let file = Parser::new(source).consume_file().unwrap();
+            let file = ProtoFile {
+                name: "x.proto".to_string(),
+                ..file
+            };
+            FileSet::new(vec![file]).unwrap()
+        };
+        let set = parse("syntax = \"proto3\"; package x; message A { repeated string names = 1 [(nanopb).max_count = 2]; }");
+        assert_eq!(
+            generate(&set, &["x.proto"], &Options::default()).unwrap_err(),
+            Error::Unbounded("x.A.names".to_string(), "max_size")
+        );
+        let options = Options::parse("max_size=8, max_count=4").unwrap();
+        let generated = generate(&set, &["x.proto"], &options).unwrap();
+        assert!(generated["x.pb.h"].contains("    size_t names_count;\n    char names[2][8];\n"));
+        assert!(generated["x.pb.h"].contains("#define x_A_size 18\n"));
+
+        let set = parse("syntax = \"proto2\"; message Node { optional Node next = 1; }");
+        let err = generate(&set, &["x.proto"], &Options::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "message Node contains itself and has no fixed size"
+        );
+        assert_eq!(
+            Options::parse("max_size=big").unwrap_err(),
+            "unknown c generator parameter max_size=big"
+        );
+    }
+}
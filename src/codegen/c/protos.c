@@ -0,0 +1,294 @@
+/* Runtime for code generated by `protos gen --lang c`. */
+
+#include "protos.h"
+
+#include <string.h>
+
+static bool write_raw(protos_writer *writer, const uint8_t *data, size_t size) {
+    if (size > writer->size - writer->pos) {
+        return false;
+    }
+    if (writer->buf != NULL) {
+        memcpy(writer->buf + writer->pos, data, size);
+    }
+    writer->pos += size;
+    return true;
+}
+
+bool protos_write_varint(protos_writer *writer, uint64_t value) {
+    uint8_t bytes[10];
+    size_t size = 0;
+    while (value > 0x7f) {
+        bytes[size++] = (uint8_t)((value & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes[size++] = (uint8_t)value;
+    return write_raw(writer, bytes, size);
+}
+
+bool protos_write_tag(protos_writer *writer, uint32_t number, uint32_t wire_type) {
+    return protos_write_varint(writer, ((uint64_t)number << 3) | wire_type);
+}
+
+bool protos_write_int32(protos_writer *writer, int32_t value) {
+    return protos_write_varint(writer, (uint64_t)(int64_t)value);
+}
+
+bool protos_write_int64(protos_writer *writer, int64_t value) {
+    return protos_write_varint(writer, (uint64_t)value);
+}
+
+bool protos_write_uint32(protos_writer *writer, uint32_t value) {
+    return protos_write_varint(writer, value);
+}
+
+bool protos_write_uint64(protos_writer *writer, uint64_t value) {
+    return protos_write_varint(writer, value);
+}
+
+bool protos_write_sint32(protos_writer *writer, int32_t value) {
+    uint32_t zigzag = ((uint32_t)value << 1) ^ (uint32_t)(value < 0 ? -1 : 0);
+    return protos_write_varint(writer, zigzag);
+}
+
+bool protos_write_sint64(protos_writer *writer, int64_t value) {
+    uint64_t zigzag = ((uint64_t)value << 1) ^ (uint64_t)(value < 0 ? -1 : 0);
+    return protos_write_varint(writer, zigzag);
+}
+
+bool protos_write_bool(protos_writer *writer, bool value) {
+    return protos_write_varint(writer, value ? 1 : 0);
+}
+
+bool protos_write_fixed32(protos_writer *writer, uint32_t value) {
+    uint8_t bytes[4];
+    for (size_t i = 0; i < 4; i++) {
+        bytes[i] = (uint8_t)(value >> (8 * i));
+    }
+    return write_raw(writer, bytes, 4);
+}
+
+bool protos_write_fixed64(protos_writer *writer, uint64_t value) {
+    uint8_t bytes[8];
+    for (size_t i = 0; i < 8; i++) {
+        bytes[i] = (uint8_t)(value >> (8 * i));
+    }
+    return write_raw(writer, bytes, 8);
+}
+
+bool protos_write_sfixed32(protos_writer *writer, int32_t value) {
+    return protos_write_fixed32(writer, (uint32_t)value);
+}
+
+bool protos_write_sfixed64(protos_writer *writer, int64_t value) {
+    return protos_write_fixed64(writer, (uint64_t)value);
+}
+
+bool protos_write_float(protos_writer *writer, float value) {
+    uint32_t bits;
+    memcpy(&bits, &value, 4);
+    return protos_write_fixed32(writer, bits);
+}
+
+bool protos_write_double(protos_writer *writer, double value) {
+    uint64_t bits;
+    memcpy(&bits, &value, 8);
+    return protos_write_fixed64(writer, bits);
+}
+
+bool protos_write_string(protos_writer *writer, const char *value) {
+    return protos_write_bytes(writer, (const uint8_t *)value, strlen(value));
+}
+
+bool protos_write_bytes(protos_writer *writer, const uint8_t *value, size_t size) {
+    return protos_write_varint(writer, size) && write_raw(writer, value, size);
+}
+
+static bool read_raw(protos_reader *reader, size_t size, const uint8_t **data) {
+    if (size > reader->size - reader->pos) {
+        return false;
+    }
+    *data = reader->buf + reader->pos;
+    reader->pos += size;
+    return true;
+}
+
+bool protos_read_varint(protos_reader *reader, uint64_t *value) {
+    *value = 0;
+    for (unsigned shift = 0; shift < 70; shift += 7) {
+        const uint8_t *byte;
+        if (!read_raw(reader, 1, &byte)) {
+            return false;
+        }
+        *value |= (uint64_t)(*byte & 0x7f) << shift;
+        if (*byte < 0x80) {
+            return true;
+        }
+    }
+    return false;
+}
+
+bool protos_read_tag(protos_reader *reader, uint32_t *number, uint32_t *wire_type) {
+    uint64_t key;
+    if (!protos_read_varint(reader, &key) || key >> 3 == 0 || key >> 3 > 0x1fffffff) {
+        return false;
+    }
+    *number = (uint32_t)(key >> 3);
+    *wire_type = (uint32_t)(key & 7);
+    return true;
+}
+
+bool protos_read_int32(protos_reader *reader, int32_t *value) {
+    uint64_t raw;
+    if (!protos_read_varint(reader, &raw)) {
+        return false;
+    }
+    *value = (int32_t)raw;
+    return true;
+}
+
+bool protos_read_int64(protos_reader *reader, int64_t *value) {
+    return protos_read_varint(reader, (uint64_t *)value);
+}
+
+bool protos_read_uint32(protos_reader *reader, uint32_t *value) {
+    uint64_t raw;
+    if (!protos_read_varint(reader, &raw)) {
+        return false;
+    }
+    *value = (uint32_t)raw;
+    return true;
+}
+
+bool protos_read_uint64(protos_reader *reader, uint64_t *value) {
+    return protos_read_varint(reader, value);
+}
+
+bool protos_read_sint32(protos_reader *reader, int32_t *value) {
+    uint32_t raw;
+    if (!protos_read_uint32(reader, &raw)) {
+        return false;
+    }
+    *value = (int32_t)(raw >> 1) ^ -(int32_t)(raw & 1);
+    return true;
+}
+
+bool protos_read_sint64(protos_reader *reader, int64_t *value) {
+    uint64_t raw;
+    if (!protos_read_varint(reader, &raw)) {
+        return false;
+    }
+    *value = (int64_t)(raw >> 1) ^ -(int64_t)(raw & 1);
+    return true;
+}
+
+bool protos_read_bool(protos_reader *reader, bool *value) {
+    uint64_t raw;
+    if (!protos_read_varint(reader, &raw)) {
+        return false;
+    }
+    *value = raw != 0;
+    return true;
+}
+
+bool protos_read_fixed32(protos_reader *reader, uint32_t *value) {
+    const uint8_t *bytes;
+    if (!read_raw(reader, 4, &bytes)) {
+        return false;
+    }
+    *value = 0;
+    for (size_t i = 0; i < 4; i++) {
+        *value |= (uint32_t)bytes[i] << (8 * i);
+    }
+    return true;
+}
+
+bool protos_read_fixed64(protos_reader *reader, uint64_t *value) {
+    const uint8_t *bytes;
+    if (!read_raw(reader, 8, &bytes)) {
+        return false;
+    }
+    *value = 0;
+    for (size_t i = 0; i < 8; i++) {
+        *value |= (uint64_t)bytes[i] << (8 * i);
+    }
+    return true;
+}
+
+bool protos_read_sfixed32(protos_reader *reader, int32_t *value) {
+    return protos_read_fixed32(reader, (uint32_t *)value);
+}
+
+bool protos_read_sfixed64(protos_reader *reader, int64_t *value) {
+    return protos_read_fixed64(reader, (uint64_t *)value);
+}
+
+bool protos_read_float(protos_reader *reader, float *value) {
+    uint32_t bits;
+    if (!protos_read_fixed32(reader, &bits)) {
+        return false;
+    }
+    memcpy(value, &bits, 4);
+    return true;
+}
+
+bool protos_read_double(protos_reader *reader, double *value) {
+    uint64_t bits;
+    if (!protos_read_fixed64(reader, &bits)) {
+        return false;
+    }
+    memcpy(value, &bits, 8);
+    return true;
+}
+
+bool protos_read_string(protos_reader *reader, char *value, size_t size) {
+    protos_reader string;
+    if (!protos_read_delimited(reader, &string) || string.size >= size) {
+        return false;
+    }
+    memcpy(value, string.buf, string.size);
+    value[string.size] = '\0';
+    return true;
+}
+
+bool protos_read_bytes(protos_reader *reader, uint8_t *value, size_t capacity, size_t *size) {
+    protos_reader bytes;
+    if (!protos_read_delimited(reader, &bytes) || bytes.size > capacity) {
+        return false;
+    }
+    memcpy(value, bytes.buf, bytes.size);
+    *size = bytes.size;
+    return true;
+}
+
+bool protos_read_delimited(protos_reader *reader, protos_reader *value) {
+    uint64_t size;
+    const uint8_t *data;
+    if (!protos_read_varint(reader, &size) || size > reader->size - reader->pos ||
+        !read_raw(reader, (size_t)size, &data)) {
+        return false;
+    }
+    value->buf = data;
+    value->size = (size_t)size;
+    value->pos = 0;
+    return true;
+}
+
+bool protos_skip(protos_reader *reader, uint32_t wire_type) {
+    uint64_t ignored;
+    const uint8_t *data;
+    protos_reader value;
+    switch (wire_type) {
+    case 0:
+        return protos_read_varint(reader, &ignored);
+    case 1:
+        return read_raw(reader, 8, &data);
+    case 2:
+        return protos_read_delimited(reader, &value);
+    case 5:
+        return read_raw(reader, 4, &data);
+    default:
+        /* Groups are not supported. */
+        return false;
+    }
+}
@@ -0,0 +1,72 @@
+/* Runtime for code generated by `protos gen --lang c`. Nothing here
+ * allocates: writers fill a caller's buffer and readers read from one. */
+
+#ifndef PROTOS_H
+#define PROTOS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct {
+    /* NULL to only count the bytes that would be written. */
+    uint8_t *buf;
+    size_t size;
+    size_t pos;
+} protos_writer;
+
+typedef struct {
+    const uint8_t *buf;
+    size_t size;
+    size_t pos;
+} protos_reader;
+
+/* A writer that counts, used to size length-delimited values. */
+#define PROTOS_COUNTER {NULL, SIZE_MAX, 0}
+
+bool protos_write_varint(protos_writer *writer, uint64_t value);
+bool protos_write_tag(protos_writer *writer, uint32_t number, uint32_t wire_type);
+bool protos_write_int32(protos_writer *writer, int32_t value);
+bool protos_write_int64(protos_writer *writer, int64_t value);
+bool protos_write_uint32(protos_writer *writer, uint32_t value);
+bool protos_write_uint64(protos_writer *writer, uint64_t value);
+bool protos_write_sint32(protos_writer *writer, int32_t value);
+bool protos_write_sint64(protos_writer *writer, int64_t value);
+bool protos_write_bool(protos_writer *writer, bool value);
+bool protos_write_fixed32(protos_writer *writer, uint32_t value);
+bool protos_write_fixed64(protos_writer *writer, uint64_t value);
+bool protos_write_sfixed32(protos_writer *writer, int32_t value);
+bool protos_write_sfixed64(protos_writer *writer, int64_t value);
+bool protos_write_float(protos_writer *writer, float value);
+bool protos_write_double(protos_writer *writer, double value);
+/* Writes a NUL-terminated string with its length. */
+bool protos_write_string(protos_writer *writer, const char *value);
+/* Writes `size` bytes with their length. */
+bool protos_write_bytes(protos_writer *writer, const uint8_t *value, size_t size);
+
+bool protos_read_varint(protos_reader *reader, uint64_t *value);
+bool protos_read_tag(protos_reader *reader, uint32_t *number, uint32_t *wire_type);
+bool protos_read_int32(protos_reader *reader, int32_t *value);
+bool protos_read_int64(protos_reader *reader, int64_t *value);
+bool protos_read_uint32(protos_reader *reader, uint32_t *value);
+bool protos_read_uint64(protos_reader *reader, uint64_t *value);
+bool protos_read_sint32(protos_reader *reader, int32_t *value);
+bool protos_read_sint64(protos_reader *reader, int64_t *value);
+bool protos_read_bool(protos_reader *reader, bool *value);
+bool protos_read_fixed32(protos_reader *reader, uint32_t *value);
+bool protos_read_fixed64(protos_reader *reader, uint64_t *value);
+bool protos_read_sfixed32(protos_reader *reader, int32_t *value);
+bool protos_read_sfixed64(protos_reader *reader, int64_t *value);
+bool protos_read_float(protos_reader *reader, float *value);
+bool protos_read_double(protos_reader *reader, double *value);
+/* Reads a string into `size` bytes, failing if it doesn't fit with its
+ * NUL terminator. */
+bool protos_read_string(protos_reader *reader, char *value, size_t size);
+/* Reads at most `capacity` bytes, storing how many in `size`. */
+bool protos_read_bytes(protos_reader *reader, uint8_t *value, size_t capacity, size_t *size);
+/* Reads a length-delimited value as a reader of its own. */
+bool protos_read_delimited(protos_reader *reader, protos_reader *value);
+/* Skips the value of a field whose tag has just been read. */
+bool protos_skip(protos_reader *reader, uint32_t wire_type);
+
+#endif
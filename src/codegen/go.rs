@@ -0,0 +1,649 @@
+//! Generates Go types from linked schemas, laid out as `protoc-gen-go`
+//! lays them out.
+//!
+//! Each message becomes a struct whose fields carry `protobuf` and `json`
+//! struct tags, each enum an `int32` type with a `const` block of its
+//! values, and each oneof an interface implemented by one wrapper struct
+//! per member. Names follow `protoc-gen-go`: `snake_case` fields become
+//! exported `CamelCase`, nested types are named `Outer_Inner` and enum
+//! values are prefixed with the enum's parent, or the enum itself at the
+//! top level.
+//!
+//! The Go package comes from a file's `go_package` option, `import/path`
+//! or `import/path;name`, and otherwise from its protobuf package.
+
+use super::{declare_all, parameters, Code, Context, Declared};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Write files beside their `.proto` source rather than under their
+    /// Go import path.
+    pub source_relative: bool,
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters: `paths=import` or
+    /// `paths=source_relative`, as `protoc-gen-go` takes them.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            match item {
+                "paths=import" => options.source_relative = false,
+                "paths=source_relative" => options.source_relative = true,
+                _ => return Err(format!("unknown go generator parameter {}", item)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// The Go package of a file.
+#[derive(Debug, Clone, PartialEq)]
+struct GoPackage {
+    /// The import path, if the file has a `go_package` option.
+    path: Option<String>,
+    name: String,
+}
+
+impl GoPackage {
+    fn of(file: &ProtoFile) -> Self {
+        let option = file
+            .options
+            .iter()
+            .find(|option| option.name == "go_package")
+            .and_then(|option| match &option.value {
+                Constant::String(value) => Some(value.as_str()),
+                _ => None,
+            });
+        match option {
+            Some(value) => {
+                let (path, name) = match value.split_once(';') {
+                    Some((path, name)) => (path, name),
+                    None => (value, value.rsplit('/').next().unwrap_or(value)),
+                };
+                GoPackage {
+                    path: Some(path.to_string()),
+                    name: sanitize(name),
+                }
+            }
+            None => {
+                let name = match &file.package {
+                    Some(package) => package.rsplit('.').next().unwrap_or(package),
+                    None => base_name(&file.name),
+                };
+                GoPackage {
+                    path: None,
+                    name: sanitize(name),
+                }
+            }
+        }
+    }
+}
+
+/// Generates a `.pb.go` file for each named file. Files are placed under
+/// their Go import path, or beside the `.proto` file if it has none or
+/// `source_relative` is set.
+pub fn generate(set: &FileSet, files: &[&str], options: &Options) -> BTreeMap<String, String> {
+    let types = declare_all(set, camel, "_");
+    let packages: BTreeMap<&str, GoPackage> = set
+        .files
+        .iter()
+        .map(|file| (file.name.as_str(), GoPackage::of(file)))
+        .collect();
+
+    let mut ret = BTreeMap::new();
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(&file.name.as_str()))
+    {
+        let package = &packages[file.name.as_str()];
+        let stem = file.name.strip_suffix(".proto").unwrap_or(&file.name);
+        let name = match &package.path {
+            Some(path) if !options.source_relative => {
+                format!("{}/{}.pb.go", path, base_name(stem))
+            }
+            _ => format!("{}.pb.go", stem),
+        };
+        let generator = FileGenerator {
+            types: &types,
+            packages: &packages,
+            package,
+            imports: BTreeMap::new(),
+            strconv: false,
+        };
+        ret.insert(name, generator.generate(set, file));
+    }
+    ret
+}
+
+fn base_name(file: &str) -> &str {
+    file.rsplit('/').next().unwrap_or(file)
+}
+
+/// A Go identifier for a package name such as `shop-v1`.
+fn sanitize(name: &str) -> String {
+    let mut ret: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ret.starts_with(|c: char| c.is_ascii_digit()) {
+        ret.insert(0, '_');
+    }
+    ret
+}
+
+/// `CamelCase` as `protoc-gen-go` writes names: an underscore followed by
+/// a lowercase letter is dropped, and a lowercase letter is capitalized
+/// unless it follows another letter. `foo_bar_2` becomes `FooBar_2`.
+pub(crate) fn camel(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut ret = String::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next_lower = bytes.get(i + 1).is_some_and(u8::is_ascii_lowercase);
+        match c {
+            b'_' if i == 0 => ret.push('X'),
+            b'_' if next_lower => {}
+            c if c.is_ascii_digit() => ret.push(c as char),
+            c => {
+                ret.push(c.to_ascii_uppercase() as char);
+                while bytes.get(i + 1).is_some_and(u8::is_ascii_lowercase) {
+                    i += 1;
+                    ret.push(bytes[i] as char);
+                }
+            }
+        }
+        i += 1;
+    }
+    ret
+}
+
+/// A line of a block gofmt aligns: a comment, which ends the run of
+/// aligned rows before it, or a row of cells.
+enum Line {
+    Comment(String),
+    Row(Vec<String>),
+}
+
+/// Writes `lines`, padding the cells of each run of rows to line up as
+/// gofmt does.
+fn aligned(code: &mut Code, lines: &[Line]) {
+    let mut start = 0;
+    while start < lines.len() {
+        if let Line::Comment(comment) = &lines[start] {
+            code.line(comment);
+            start += 1;
+            continue;
+        }
+        let end = lines[start..]
+            .iter()
+            .position(|line| matches!(line, Line::Comment(_)))
+            .map_or(lines.len(), |offset| start + offset);
+        let rows: Vec<&Vec<String>> = lines[start..end]
+            .iter()
+            .filter_map(|line| match line {
+                Line::Row(cells) => Some(cells),
+                Line::Comment(_) => None,
+            })
+            .collect();
+        let columns = rows.iter().map(|cells| cells.len()).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                rows.iter()
+                    .filter(|cells| i + 1 < cells.len())
+                    .map(|cells| cells[i].len())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        for cells in rows {
+            let mut line = String::new();
+            for (i, cell) in cells.iter().enumerate() {
+                if i + 1 < cells.len() {
+                    line.push_str(&format!("{:width$} ", cell, width = widths[i]));
+                } else {
+                    line.push_str(cell);
+                }
+            }
+            code.line(line);
+        }
+        start = end;
+    }
+}
+
+struct FileGenerator<'a> {
+    types: &'a BTreeMap<String, Declared>,
+    packages: &'a BTreeMap<&'a str, GoPackage>,
+    package: &'a GoPackage,
+    /// The Go packages imported, by import path, with their names.
+    imports: BTreeMap<String, String>,
+    /// Whether `strconv` is imported, for enums' `String` methods.
+    strconv: bool,
+}
+
+impl FileGenerator<'_> {
+    fn generate(mut self, set: &FileSet, file: &ProtoFile) -> String {
+        let mut code = Code::new("\t");
+        let mut context = Context::new(set, file);
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            let full_name = format!("{}.{}", scope, e.name);
+            self.enumeration(&mut code, &context, e, &full_name, None);
+            context.path.pop();
+        }
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            let full_name = format!("{}.{}", scope, message.name);
+            self.message(&mut code, &mut context, message, &full_name);
+            context.path.pop();
+        }
+
+        let mut header = Code::new("\t");
+        header.line(format!(
+            "// Code generated by protos from {}. DO NOT EDIT.",
+            file.name
+        ));
+        header.line("");
+        header.line(format!("package {}", self.package.name));
+        let mut imports: Vec<String> = vec![];
+        if self.strconv {
+            imports.push("\"strconv\"".to_string());
+        }
+        for (path, name) in &self.imports {
+            if path.rsplit('/').next() == Some(name.as_str()) {
+                imports.push(format!("{:?}", path));
+            } else {
+                imports.push(format!("{} {:?}", name, path));
+            }
+        }
+        match imports.as_slice() {
+            [] => {}
+            [import] => {
+                header.line("");
+                header.line(format!("import {}", import));
+            }
+            _ => {
+                header.line("");
+                header.line("import (");
+                header.depth += 1;
+                if self.strconv {
+                    header.line(imports.remove(0));
+                    header.line("");
+                }
+                for import in imports {
+                    header.line(import);
+                }
+                header.depth -= 1;
+                header.line(")");
+            }
+        }
+        format!("{}{}", header.text, code.text)
+    }
+
+    /// The Go name of a message or enum, qualified by its package if
+    /// declared in another one.
+    fn name(&mut self, full_name: &str) -> String {
+        let declared = &self.types[full_name];
+        let package = &self.packages[declared.file.as_str()];
+        match &package.path {
+            Some(path) if package != self.package => {
+                self.imports.insert(path.clone(), package.name.clone());
+                format!("{}.{}", package.name, declared.name)
+            }
+            _ => declared.name.clone(),
+        }
+    }
+
+    /// The Go type of one value of `t`; messages are pointers.
+    fn go_type(&mut self, t: &Type) -> String {
+        match t {
+            Type::Double => "float64".to_string(),
+            Type::Float => "float32".to_string(),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => "int32".to_string(),
+            Type::Int64 | Type::Sint64 | Type::Sfixed64 => "int64".to_string(),
+            Type::Uint32 | Type::Fixed32 => "uint32".to_string(),
+            Type::Uint64 | Type::Fixed64 => "uint64".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bytes => "[]byte".to_string(),
+            Type::Enum(name) => self.name(name),
+            Type::Message(name) => format!("*{}", self.name(name)),
+            Type::Named(name) => panic!("type {} is not linked", name),
+            Type::Map(..) => unreachable!("map values are not maps"),
+        }
+    }
+
+    /// The encoding named first in a `protobuf` struct tag.
+    fn encoding(t: &Type) -> &'static str {
+        match t {
+            Type::Sint32 => "zigzag32",
+            Type::Sint64 => "zigzag64",
+            Type::Fixed32 | Type::Sfixed32 | Type::Float => "fixed32",
+            Type::Fixed64 | Type::Sfixed64 | Type::Double => "fixed64",
+            Type::String | Type::Bytes | Type::Message(_) | Type::Map(..) => "bytes",
+            _ => "varint",
+        }
+    }
+
+    /// The value of the `protobuf` struct tag of a field or map entry
+    /// field.
+    fn tag(&self, field: &MessageField, context: &Context, packed: bool, oneof: bool) -> String {
+        let t = field.field_type();
+        let cardinality = match field.frequency() {
+            Some(Frequency::Repeated) => "rep",
+            Some(Frequency::Required) => "req",
+            _ if matches!(t, Type::Map(..)) => "rep",
+            _ => "opt",
+        };
+        let mut parts = vec![
+            Self::encoding(&t).to_string(),
+            field.position().to_string(),
+            cardinality.to_string(),
+        ];
+        if packed {
+            parts.push("packed".to_string());
+        }
+        parts.push(format!("name={}", field.name()));
+        if field.json_name() != field.name() {
+            parts.push(format!("json={}", field.json_name()));
+        }
+        if context.proto3 {
+            parts.push("proto3".to_string());
+        }
+        if let Type::Enum(name) = &t {
+            let declared = &self.types[name];
+            if declared.package.is_empty() {
+                parts.push(format!("enum={}", declared.name));
+            } else {
+                parts.push(format!("enum={}.{}", declared.package, declared.name));
+            }
+        }
+        if oneof {
+            parts.push("oneof".to_string());
+        }
+        parts.join(",")
+    }
+
+    fn is_packed(field: &MessageField, options: Option<&Vec<ProtoOption>>, proto3: bool) -> bool {
+        let t = field.field_type();
+        let packable = !matches!(
+            t,
+            Type::String | Type::Bytes | Type::Message(_) | Type::Map(..)
+        );
+        let packed = options
+            .into_iter()
+            .flatten()
+            .find(|option| option.name == "packed")
+            .map_or(proto3, |option| option.value == Constant::Bool(true));
+        field.is_repeated() && packable && packed
+    }
+
+    fn comment(lines: &mut Vec<Line>, comment: Option<&String>) {
+        let mut code = Code::new("");
+        code.comment("//", comment);
+        lines.extend(
+            code.text
+                .lines()
+                .map(|line| Line::Comment(line.to_string())),
+        );
+    }
+
+    fn message(
+        &mut self,
+        code: &mut Code,
+        context: &mut Context,
+        message: &Message,
+        full_name: &str,
+    ) {
+        let name = self.types[full_name].name.clone();
+        let mut lines = vec![];
+        for (i, field) in message.fields.iter().enumerate() {
+            Self::comment(&mut lines, context.comment(&[PathSegment::Field(i)]));
+            let t = field.field_type();
+            let options = message.field_options.get(&field.position());
+            let packed = Self::is_packed(field, options, context.proto3);
+            // proto3 `optional` is a oneof of one field to protoc-gen-go.
+            let synthetic = context.proto3 && field.frequency() == Some(&Frequency::Optional);
+            let mut tags = vec![format!(
+                "protobuf:\"{}\"",
+                self.tag(field, context, packed, synthetic)
+            )];
+            tags.push(format!("json:\"{},omitempty\"", field.name()));
+            let go_type = match &t {
+                Type::Map(key, value) => {
+                    let entry = |t: &Type, name: &str, number| {
+                        MessageField::new(None, t.clone(), name.to_string(), number)
+                    };
+                    let key_field = entry(key, "key", 1);
+                    let value_field = entry(value, "value", 2);
+                    let key_tag = self.tag(&key_field, context, false, false);
+                    let value_tag = self.tag(&value_field, context, false, false);
+                    tags.push(format!("protobuf_key:\"{}\"", key_tag));
+                    tags.push(format!("protobuf_val:\"{}\"", value_tag));
+                    format!("map[{}]{}", self.go_type(key), self.go_type(value))
+                }
+                t if field.is_repeated() => format!("[]{}", self.go_type(t)),
+                Type::Message(_) => self.go_type(&t),
+                t if !context.proto3 || field.frequency().is_some() => {
+                    format!("*{}", self.go_type(t))
+                }
+                t => self.go_type(t),
+            };
+            lines.push(Line::Row(vec![
+                camel(field.name()),
+                go_type,
+                format!("`{}`", tags.join(" ")),
+            ]));
+        }
+        for (i, oneof) in message.oneofs.iter().enumerate() {
+            Self::comment(&mut lines, context.comment(&[PathSegment::Oneof(i)]));
+            lines.push(Line::Comment(format!(
+                "// Types that are assignable to {}:",
+                camel(&oneof.name)
+            )));
+            lines.push(Line::Comment("//".to_string()));
+            for field in &oneof.fields {
+                lines.push(Line::Comment(format!(
+                    "//\t*{}_{}",
+                    name,
+                    camel(field.name())
+                )));
+            }
+            lines.push(Line::Row(vec![
+                camel(&oneof.name),
+                format!("is{}_{}", name, camel(&oneof.name)),
+                format!("`protobuf_oneof:\"{}\"`", oneof.name),
+            ]));
+        }
+
+        code.line("");
+        code.comment("//", context.comment(&[]));
+        if lines.is_empty() {
+            code.line(format!("type {} struct{{}}", name));
+        } else {
+            code.open(format!("type {} struct", name));
+            aligned(code, &lines);
+            code.close();
+        }
+
+        for (i, oneof) in message.oneofs.iter().enumerate() {
+            let interface = format!("is{}_{}", name, camel(&oneof.name));
+            code.line("");
+            code.open(format!("type {} interface", interface));
+            code.line(format!("{}()", interface));
+            code.close();
+            for (j, field) in oneof.fields.iter().enumerate() {
+                code.line("");
+                context.path.push(PathSegment::Oneof(i));
+                code.comment("//", context.comment(&[PathSegment::Field(j)]));
+                context.path.pop();
+                code.open(format!("type {}_{} struct", name, camel(field.name())));
+                let tag = self.tag(field, context, false, true);
+                let go_type = self.go_type(&field.field_type());
+                aligned(
+                    code,
+                    &[Line::Row(vec![
+                        camel(field.name()),
+                        go_type,
+                        format!("`protobuf:\"{}\"`", tag),
+                    ])],
+                );
+                code.close();
+            }
+            for field in &oneof.fields {
+                code.line("");
+                code.line(format!(
+                    "func (*{}_{}) {}() {{}}",
+                    name,
+                    camel(field.name()),
+                    interface
+                ));
+            }
+        }
+
+        for (i, e) in message.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            let nested_name = format!("{}.{}", full_name, e.name);
+            self.enumeration(code, context, e, &nested_name, Some(&name));
+            context.path.pop();
+        }
+        for (i, nested) in message.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            let nested_name = format!("{}.{}", full_name, nested.name);
+            self.message(code, context, nested, &nested_name);
+            context.path.pop();
+        }
+    }
+
+    /// An enum declared in the message named `parent`, or at the top level.
+    fn enumeration(
+        &mut self,
+        code: &mut Code,
+        context: &Context,
+        e: &Enum,
+        full_name: &str,
+        parent: Option<&str>,
+    ) {
+        let name = self.types[full_name].name.clone();
+        let prefix = parent.unwrap_or(&name);
+        self.strconv = true;
+
+        code.line("");
+        code.comment("//", context.comment(&[]));
+        code.line(format!("type {} int32", name));
+        code.line("");
+        code.line("const (");
+        code.depth += 1;
+        let mut lines = vec![];
+        for (i, value) in e.fields.iter().enumerate() {
+            Self::comment(&mut lines, context.comment(&[PathSegment::EnumValue(i)]));
+            lines.push(Line::Row(vec![
+                format!("{}_{}", prefix, value.name),
+                name.clone(),
+                format!("= {}", value.position as i32),
+            ]));
+        }
+        aligned(code, &lines);
+        code.depth -= 1;
+        code.line(")");
+
+        code.line("");
+        code.line("// String returns the value's name in the schema, or its number if");
+        code.line("// the schema doesn't name it.");
+        code.open(format!("func (x {}) String() string", name));
+        code.line("switch x {");
+        // Aliases share a case with the first value of their number.
+        let mut numbers = BTreeSet::new();
+        for value in &e.fields {
+            if numbers.insert(value.position) {
+                code.line(format!("case {}_{}:", prefix, value.name));
+                code.line(format!("\treturn {:?}", value.name));
+            }
+        }
+        code.line("}");
+        code.line("return strconv.Itoa(int(x))");
+        code.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    #[test]
+    fn golden() {
+        let set = Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap();
+        let generated = generate(&set, &["demo.proto"], &Options::default());
+        let expected = std::fs::read_to_string("tests/go/demo.pb.go").unwrap();
+        assert_eq!(generated["demo.pb.go"], expected);
+    }
+
+    #[test]
+    fn go_packages_and_imports() {
+        let dir = std::env::temp_dir().join(format!("protos-go-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shop")).unwrap();
+        std::fs::write(
+            dir.join("shop/order.proto"),
+            "syntax = \"proto2\"; package shop.v1;
+             option go_package = \"example.com/gen/shop;shoppb\";
+             import \"common.proto\"; import \"google/protobuf/timestamp.proto\";
+             message Order {
+               required common.Money total = 1; optional common.Currency currency = 2;
+               optional google.protobuf.Timestamp created_at = 3;
+               repeated int32 line_ids = 4 [packed = true]; optional int64 count_2 = 5;
+             }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common.proto"),
+            "syntax = \"proto3\"; package common;
+             option go_package = \"example.com/gen/common\";
+             message Money { int64 units = 1; } enum Currency { USD = 0; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["shop/order.proto"])
+            .unwrap();
+        let generated = generate(&set, &["shop/order.proto"], &Options::default());
+        let order = &generated["example.com/gen/shop/order.pb.go"];
+        assert!(order.contains("\npackage shoppb\n\nimport (\n\t\"example.com/gen/common\"\n\t\"google.golang.org/protobuf/types/known/timestamppb\"\n)\n"));
+        assert!(order.contains("\tTotal     *common.Money          `protobuf:\"bytes,1,req,name=total\" json:\"total,omitempty\"`\n"));
+        assert!(order.contains("\tCurrency  *common.Currency       `protobuf:\"varint,2,opt,name=currency,enum=common.Currency\" json:\"currency,omitempty\"`\n"));
+        assert!(order.contains("\tCreatedAt *timestamppb.Timestamp `protobuf:\"bytes,3,opt,name=created_at,json=createdAt\" json:\"created_at,omitempty\"`\n"));
+        assert!(order.contains("\tLineIds   []int32                `protobuf:\"varint,4,rep,packed,name=line_ids,json=lineIds\" json:\"line_ids,omitempty\"`\n"));
+        assert!(order.contains("\tCount_2   *int64 "));
+
+        let options = Options::parse("paths=source_relative").unwrap();
+        let generated = generate(&set, &["shop/order.proto"], &options);
+        assert!(generated.contains_key("shop/order.pb.go"));
+        assert_eq!(
+            Options::parse("paths=module").unwrap_err(),
+            "unknown go generator parameter paths=module"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(camel("foo_bar"), "FooBar");
+        assert_eq!(camel("count_2"), "Count_2");
+        assert_eq!(camel("_private"), "XPrivate");
+        assert_eq!(camel("HTTPRule"), "HTTPRule");
+        assert_eq!(camel("a1b"), "A1B");
+        assert_eq!(sanitize("shop-v1"), "shop_v1");
+    }
+}
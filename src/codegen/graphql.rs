@@ -0,0 +1,600 @@
+//! Generates a GraphQL schema (SDL) from linked schemas, for serving
+//! protobuf-backed data over GraphQL.
+//!
+//! Every message becomes an object `type` for results and an `input` type
+//! for arguments, and every enum an `enum`. Names drop the package and
+//! join nested names with `_`, as in `Order_Line`. Fields take their JSON
+//! names and the types of the proto3 JSON mapping: 64-bit integers are
+//! `String`s, as are bytes in base64, and the well-known types map to
+//! scalars. `repeated` fields and maps, as lists of entries, are lists
+//! and proto2 `required` fields are non-null.
+//!
+//! A oneof whose members are all distinct messages is a `union` in the
+//! object type; other oneofs, and all oneofs of input types, become
+//! nullable fields of which at most one may be set.
+//!
+//! Unary methods are `Query` or `Mutation` fields taking their request as
+//! `input`; see [`Options`] for which is which. Server-streaming methods
+//! are `Subscription` fields, and client-streaming methods are left out.
+
+use super::json_schema::Kind;
+use super::{parameters, upper_camel, Code, Context};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The name of the single schema file generated.
+pub const SCHEMA: &str = "schema.graphql";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Name prefixes of the methods that are queries, such as `Get` for
+    /// `GetShelf`. Methods with an `idempotency_level` option are queries
+    /// if it is `NO_SIDE_EFFECTS`, whatever their name.
+    pub query_prefixes: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            query_prefixes: ["Get", "List", "Search", "BatchGet"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters:
+    /// `query_prefixes=Get:List`, empty for none.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            match item.split_once('=') {
+                Some(("query_prefixes", prefixes)) => {
+                    options.query_prefixes = prefixes
+                        .split(':')
+                        .filter(|prefix| !prefix.is_empty())
+                        .map(String::from)
+                        .collect();
+                }
+                _ => return Err(format!("unknown graphql generator parameter {}", item)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether a unary method is a query rather than a mutation.
+    fn is_query(&self, method: &Method) -> bool {
+        let level = method
+            .options
+            .iter()
+            .find(|option| option.name == "idempotency_level");
+        if let Some(level) = level {
+            return matches!(&level.value, Constant::Ident(level) if level == "NO_SIDE_EFFECTS");
+        }
+        self.query_prefixes.iter().any(|prefix| {
+            method
+                .name
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_lowercase()))
+        })
+    }
+}
+
+/// Generates one schema, [`SCHEMA`], with the messages, enums and
+/// services of the named files and the types they use from others.
+pub fn generate(set: &FileSet, files: &[&str], options: &Options) -> BTreeMap<String, String> {
+    let mut types = BTreeMap::new();
+    for file in &set.files {
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        let mut path = vec![];
+        for (i, message) in file.messages.iter().enumerate() {
+            path.push(PathSegment::Message(i));
+            declare_message(&mut types, &file.name, &mut path, &scope, "", message);
+            path.pop();
+        }
+        for (i, e) in file.enums.iter().enumerate() {
+            path.push(PathSegment::Enum(i));
+            declare(&mut types, &file.name, &path, &scope, "", Kind::Enum(e));
+            path.pop();
+        }
+    }
+    let contexts = set
+        .files
+        .iter()
+        .map(|file| (file.name.as_str(), Context::new(set, file)))
+        .collect();
+    let generator = Generator {
+        types: &types,
+        contexts,
+        used: BTreeSet::new(),
+        json: false,
+    };
+    let mut ret = BTreeMap::new();
+    ret.insert(SCHEMA.to_string(), generator.generate(set, files, options));
+    ret
+}
+
+/// A message or enum and where it is declared.
+struct Declared<'a> {
+    /// The GraphQL name of its object or enum type.
+    name: String,
+    file: &'a str,
+    path: Vec<PathSegment>,
+    kind: Kind<'a>,
+}
+
+fn declare<'a>(
+    types: &mut BTreeMap<String, Declared<'a>>,
+    file: &'a str,
+    path: &[PathSegment],
+    scope: &str,
+    prefix: &str,
+    kind: Kind<'a>,
+) {
+    let name = match &kind {
+        Kind::Message(message) => &message.name,
+        Kind::Enum(e) => &e.name,
+    };
+    types.insert(
+        format!("{}.{}", scope, name),
+        Declared {
+            name: format!("{}{}", prefix, name),
+            file,
+            path: path.to_vec(),
+            kind,
+        },
+    );
+}
+
+fn declare_message<'a>(
+    types: &mut BTreeMap<String, Declared<'a>>,
+    file: &'a str,
+    path: &mut Vec<PathSegment>,
+    scope: &str,
+    prefix: &str,
+    message: &'a Message,
+) {
+    declare(types, file, path, scope, prefix, Kind::Message(message));
+    let scope = format!("{}.{}", scope, message.name);
+    let prefix = format!("{}{}_", prefix, message.name);
+    for (i, nested) in message.messages.iter().enumerate() {
+        path.push(PathSegment::Message(i));
+        declare_message(types, file, path, &scope, &prefix, nested);
+        path.pop();
+    }
+    for (i, e) in message.enums.iter().enumerate() {
+        path.push(PathSegment::Enum(i));
+        declare(types, file, path, &scope, &prefix, Kind::Enum(e));
+        path.pop();
+    }
+}
+
+/// The scalar a well-known message type is in the JSON mapping, if any.
+fn well_known(name: &str) -> Option<&'static str> {
+    Some(match name.strip_prefix(".google.protobuf.")? {
+        "Timestamp" | "Duration" | "FieldMask" => "String",
+        "Struct" | "Value" | "ListValue" | "Any" => "JSON",
+        "DoubleValue" | "FloatValue" | "UInt32Value" => "Float",
+        "Int32Value" => "Int",
+        "BoolValue" => "Boolean",
+        "Int64Value" | "UInt64Value" | "StringValue" | "BytesValue" => "String",
+        _ => return None,
+    })
+}
+
+/// The union a oneof becomes, if all its members are distinct messages
+/// with object types.
+fn union(oneof: &Oneof) -> Option<Vec<&str>> {
+    let mut members = vec![];
+    for field in &oneof.fields {
+        match field {
+            MessageField::Message(None, name, ..)
+                if well_known(name).is_none() && !members.contains(&name.as_str()) =>
+            {
+                members.push(name.as_str())
+            }
+            _ => return None,
+        }
+    }
+    Some(members).filter(|members| !members.is_empty())
+}
+
+fn lower_camel(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => format!("{}{}", first.to_ascii_lowercase(), chars.as_str()),
+        None => String::new(),
+    }
+}
+
+/// Writes a comment as a description, in a block string.
+fn description(code: &mut Code, comment: Option<&String>) {
+    let Some(comment) = comment else { return };
+    let lines: Vec<String> = comment
+        .trim()
+        .lines()
+        .map(|line| line.trim().replace("\"\"\"", "\\\"\"\""))
+        .collect();
+    match &lines[..] {
+        [] => {}
+        [line] if !line.ends_with('"') => code.line(format!("\"\"\"{}\"\"\"", line)),
+        lines => {
+            code.line("\"\"\"");
+            for line in lines {
+                code.line(line);
+            }
+            code.line("\"\"\"");
+        }
+    }
+}
+
+/// Which root type a method is a field of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Root {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+struct Generator<'a> {
+    types: &'a BTreeMap<String, Declared<'a>>,
+    contexts: BTreeMap<&'a str, Context<'a>>,
+    /// The messages and enums the schema has types for.
+    used: BTreeSet<String>,
+    /// Whether the `JSON` scalar is used.
+    json: bool,
+}
+
+impl<'a> Generator<'a> {
+    fn generate(mut self, set: &'a FileSet, files: &[&str], options: &Options) -> String {
+        let mut operations: Vec<(Root, &Service, &Method, Option<&String>)> = vec![];
+        for file in set
+            .files
+            .iter()
+            .filter(|file| files.contains(&file.name.as_str()))
+        {
+            let types = self.types;
+            for (name, declared) in types {
+                if declared.file == file.name {
+                    self.collect(name);
+                }
+            }
+            let mut context = Context::new(set, file);
+            for (i, service) in file.services.iter().enumerate() {
+                context.path.push(PathSegment::Service(i));
+                for (j, method) in service.methods.iter().enumerate() {
+                    let root = match (method.client_streaming, method.server_streaming) {
+                        (true, _) => continue,
+                        (false, true) => Root::Subscription,
+                        _ if options.is_query(method) => Root::Query,
+                        _ => Root::Mutation,
+                    };
+                    self.collect(&method.input_type);
+                    self.collect(&method.output_type);
+                    let comment = context.comment(&[PathSegment::Method(j)]);
+                    operations.push((root, service, method, comment));
+                }
+                context.path.pop();
+            }
+        }
+
+        let mut code = Code::new("  ");
+        for root in [Root::Query, Root::Mutation, Root::Subscription] {
+            let fields: Vec<_> = operations.iter().filter(|(r, ..)| *r == root).collect();
+            if fields.is_empty() {
+                continue;
+            }
+            code.line("");
+            code.open(format!("type {:?}", root));
+            for (_, service, method, comment) in &fields {
+                // Methods of different services may share a name.
+                let shared = fields
+                    .iter()
+                    .filter(|(_, _, other, _)| other.name == method.name)
+                    .count()
+                    > 1;
+                let name = match shared {
+                    true => lower_camel(&format!("{}{}", service.name, method.name)),
+                    false => lower_camel(&method.name),
+                };
+                let argument = match self.input_argument(&method.input_type) {
+                    Some(t) => format!("(input: {}!)", t),
+                    None => String::new(),
+                };
+                let output = self.named(&method.output_type, false);
+                description(&mut code, *comment);
+                code.line(format!("{}{}: {}", name, argument, output));
+            }
+            code.close();
+        }
+        for name in self.used.clone() {
+            let declared = &self.types[&name];
+            match declared.kind {
+                Kind::Message(message) => self.message(&mut code, &name, message),
+                Kind::Enum(e) => self.enumeration(&mut code, &name, e),
+            }
+        }
+
+        let mut header = Code::new("  ");
+        header.line(format!(
+            "# @generated by protos from {}. Do not edit.",
+            files.join(", ")
+        ));
+        if self.json {
+            header.line("");
+            header.line("\"\"\"A value in the JSON form of a well-known type.\"\"\"");
+            header.line("scalar JSON");
+        }
+        format!("{}{}", header.text, code.text)
+    }
+
+    /// Adds a message or enum, and the types its fields use, to the schema.
+    fn collect(&mut self, name: &str) {
+        if well_known(name).is_some() || !self.used.insert(name.to_string()) {
+            return;
+        }
+        if let Kind::Message(message) = self.types[name].kind {
+            for field in all_fields(message) {
+                match field.field_type() {
+                    Type::Map(_, value) => {
+                        if let Type::Message(name) | Type::Enum(name) = *value {
+                            self.collect(&name)
+                        }
+                    }
+                    Type::Message(name) | Type::Enum(name) => self.collect(&name),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// The comment at `relative` to the declaration of `name`.
+    fn comment(&mut self, name: &str, relative: &[PathSegment]) -> Option<&'a String> {
+        let declared = &self.types[name];
+        let context = self.contexts.get_mut(declared.file)?;
+        context.path.clone_from(&declared.path);
+        context.comment(relative)
+    }
+
+    /// The type of a method's `input` argument, or `None` if its request
+    /// has no fields.
+    fn input_argument(&mut self, name: &str) -> Option<String> {
+        match self.types.get(name).map(|declared| &declared.kind) {
+            Some(Kind::Message(message)) if well_known(name).is_none() => {
+                all_fields(message).next()?;
+            }
+            _ => {}
+        }
+        Some(self.named(name, true))
+    }
+
+    /// The GraphQL type of a named message or enum.
+    fn named(&mut self, name: &str, input: bool) -> String {
+        if let Some(scalar) = well_known(name) {
+            self.json |= scalar == "JSON";
+            return scalar.to_string();
+        }
+        let declared = &self.types[name];
+        match (&declared.kind, input) {
+            (Kind::Message(_), true) => format!("{}Input", declared.name),
+            _ => declared.name.clone(),
+        }
+    }
+
+    fn value(&mut self, t: &Type, input: bool) -> String {
+        match t {
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => "Int".to_string(),
+            // GraphQL's `Int` is signed 32-bit.
+            Type::Uint32 | Type::Fixed32 | Type::Float | Type::Double => "Float".to_string(),
+            Type::Bool => "Boolean".to_string(),
+            Type::Message(name) | Type::Enum(name) => self.named(name, input),
+            _ => "String".to_string(),
+        }
+    }
+
+    /// The type of a field of the object or input type of `parent`.
+    fn field(&mut self, parent: &str, field: &MessageField, input: bool) -> String {
+        let t = match field.field_type() {
+            Type::Map(..) => {
+                let entry = entry_name(parent, field);
+                match input {
+                    true => format!("{}Input", entry),
+                    false => entry,
+                }
+            }
+            t => self.value(&t, input),
+        };
+        match (field.is_repeated(), field.frequency(), input) {
+            (true, _, true) => format!("[{}!]", t),
+            (true, _, false) => format!("[{}!]!", t),
+            (false, Some(Frequency::Required), _) => format!("{}!", t),
+            _ => t,
+        }
+    }
+
+    fn message(&mut self, code: &mut Code, name: &str, message: &Message) {
+        let graphql_name = self.types[name].name.clone();
+        let comment = self.comment(name, &[]);
+        let mut unions = vec![];
+        for input in [false, true] {
+            code.line("");
+            description(code, comment);
+            match input {
+                true => code.open(format!("input {}Input", graphql_name)),
+                false => code.open(format!("type {}", graphql_name)),
+            }
+            for (i, field) in message.fields.iter().enumerate() {
+                let comment = self.comment(name, &[PathSegment::Field(i)]);
+                let t = self.field(&graphql_name, field, input);
+                description(code, comment);
+                code.line(format!("{}: {}", field.json_name(), t));
+            }
+            for (i, oneof) in message.oneofs.iter().enumerate() {
+                let oneof_comment = self.comment(name, &[PathSegment::Oneof(i)]);
+                if let (Some(members), false) = (union(oneof), input) {
+                    let union_name = format!("{}{}", graphql_name, upper_camel(&oneof.name));
+                    description(code, oneof_comment);
+                    code.line(format!(
+                        "{}: {}",
+                        lower_camel(&upper_camel(&oneof.name)),
+                        union_name
+                    ));
+                    unions.push((union_name, oneof_comment, members));
+                    continue;
+                }
+                for (j, field) in oneof.fields.iter().enumerate() {
+                    let comment =
+                        self.comment(name, &[PathSegment::Oneof(i), PathSegment::Field(j)]);
+                    let t = self.field(&graphql_name, field, input);
+                    description(code, comment);
+                    code.line(format!("{}: {}", field.json_name(), t));
+                }
+            }
+            if all_fields(message).next().is_none() {
+                code.line("\"\"\"GraphQL types need a field; this one is always null.\"\"\"");
+                code.line("_: Boolean");
+            }
+            code.close();
+        }
+        for (union_name, comment, members) in unions {
+            let members: Vec<String> = members
+                .into_iter()
+                .map(|member| self.named(member, false))
+                .collect();
+            code.line("");
+            description(code, comment);
+            code.line(format!("union {} = {}", union_name, members.join(" | ")));
+        }
+        for field in all_fields(message) {
+            let MessageField::Map(key, value, ..) = field else {
+                continue;
+            };
+            let entry = entry_name(&graphql_name, field);
+            for input in [false, true] {
+                let key = self.value(key, input);
+                let value = self.value(value, input);
+                code.line("");
+                match input {
+                    true => code.open(format!("input {}Input", entry)),
+                    false => code.open(format!("type {}", entry)),
+                }
+                code.line(format!("key: {}!", key));
+                code.line(format!("value: {}!", value));
+                code.close();
+            }
+        }
+    }
+
+    fn enumeration(&mut self, code: &mut Code, name: &str, e: &Enum) {
+        code.line("");
+        description(code, self.comment(name, &[]));
+        code.open(format!("enum {}", self.types[name].name));
+        for (i, value) in e.fields.iter().enumerate() {
+            description(code, self.comment(name, &[PathSegment::EnumValue(i)]));
+            code.line(&value.name);
+        }
+        code.close();
+    }
+}
+
+/// The object type of a map field's entries, named as `protoc` names the
+/// entry message.
+fn entry_name(parent: &str, field: &MessageField) -> String {
+    format!("{}_{}Entry", parent, upper_camel(field.name()))
+}
+
+fn all_fields(message: &Message) -> impl Iterator<Item = &MessageField> {
+    message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/graphql")
+            .load(&["store.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["store.proto"], &Options::default());
+        let expected = std::fs::read_to_string("tests/graphql/schema.graphql").unwrap();
+        assert_eq!(generated[SCHEMA], expected);
+        assert_eq!(generated.len(), 1);
+    }
+
+    #[test]
+    fn required_fields_and_oneofs() {
+        let dir = std::env::temp_dir().join(format!("protos-graphql-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("shapes.proto"),
+            "syntax = \"proto2\"; package shapes;
+             message Circle { required double radius = 1; }
+             message Square { required double side = 1; }
+             message Shape {
+               required string id = 1; repeated string tags = 2;
+               oneof kind { Circle circle = 3; Square square = 4; }
+               oneof twice { Circle inner = 5; Circle outer = 6; }
+             }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["shapes.proto"])
+            .unwrap();
+        let schema = &generate(&set, &["shapes.proto"], &Options::default())[SCHEMA];
+        assert!(schema.contains(
+            "type Shape {\n  id: String!\n  tags: [String!]!\n  kind: ShapeKind\n  inner: Circle\n  outer: Circle\n}\n"
+        ));
+        assert!(schema.contains(
+            "input ShapeInput {\n  id: String!\n  tags: [String!]\n  circle: CircleInput\n  square: SquareInput\n"
+        ));
+        assert!(schema.contains("\nunion ShapeKind = Circle | Square\n"));
+        assert!(!schema.contains("type Query"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn classifies_methods() {
+        let options = Options::default();
+        let method = |name: &str, level: Option<&str>| Method {
+            name: name.to_string(),
+            options: level
+                .map(|level| ProtoOption {
+                    name: "idempotency_level".to_string(),
+                    value: Constant::Ident(level.to_string()),
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(options.is_query(&method("GetShelf", None)));
+        assert!(options.is_query(&method("List", None)));
+        assert!(!options.is_query(&method("Getaway", None)));
+        assert!(!options.is_query(&method("GetOrCreate", Some("IDEMPOTENT"))));
+        assert!(options.is_query(&method("Check", Some("NO_SIDE_EFFECTS"))));
+        let options = Options::parse("query_prefixes=Fetch:Check").unwrap();
+        assert_eq!(options.query_prefixes, ["Fetch", "Check"]);
+        assert!(Options::parse("query_prefixes=")
+            .unwrap()
+            .query_prefixes
+            .is_empty());
+        assert_eq!(
+            Options::parse("roots=all").unwrap_err(),
+            "unknown graphql generator parameter roots=all"
+        );
+    }
+}
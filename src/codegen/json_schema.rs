@@ -0,0 +1,622 @@
+//! Generates JSON Schema (draft 2020-12) documents describing messages as
+//! the proto3 JSON mapping writes them, for validating JSON payloads.
+//!
+//! Each top-level message becomes a document, `package.Message.schema.json`,
+//! with the other messages and enums it uses under `$defs`. Fields are
+//! keyed by their JSON names and, as parsers accept both, their own names,
+//! and may be `null` unless required. 64-bit integers are decimal strings,
+//! enums are the names of their values, and the well-known types take
+//! their special JSON forms.
+
+use super::Context;
+use crate::json::Json;
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Generates a schema document for each top-level message in the named
+/// files.
+pub fn generate(set: &FileSet, files: &[&str]) -> BTreeMap<String, String> {
+    let definitions = definitions(set);
+    let mut ret = BTreeMap::new();
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(&file.name.as_str()))
+    {
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for message in &file.messages {
+            let full_name = format!("{}.{}", scope, message.name);
+            let schemas = Schemas {
+                definitions: &definitions,
+                root: &full_name,
+                prefix: "#/$defs/",
+            };
+            ret.insert(
+                format!("{}.schema.json", &full_name[1..]),
+                schemas.document().pretty(),
+            );
+        }
+    }
+    ret
+}
+
+pub(crate) enum Kind<'a> {
+    Message(&'a Message),
+    Enum(&'a Enum),
+}
+
+/// A message or enum with its comments.
+pub(crate) struct Definition<'a> {
+    pub kind: Kind<'a>,
+    pub comment: Option<&'a String>,
+    /// The comments of a message's fields, by number.
+    pub fields: BTreeMap<u32, &'a String>,
+}
+
+/// Every message and enum in `set`, by full name.
+pub(crate) fn definitions(set: &FileSet) -> BTreeMap<String, Definition<'_>> {
+    let mut definitions = BTreeMap::new();
+    for file in &set.files {
+        let mut context = Context::new(set, file);
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            define_message(&mut definitions, &mut context, &scope, message);
+            context.path.pop();
+        }
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            define_enum(&mut definitions, &context, &scope, e);
+            context.path.pop();
+        }
+    }
+    definitions
+}
+
+fn define_message<'a>(
+    definitions: &mut BTreeMap<String, Definition<'a>>,
+    context: &mut Context<'a>,
+    scope: &str,
+    message: &'a Message,
+) {
+    let full_name = format!("{}.{}", scope, message.name);
+    let mut fields = BTreeMap::new();
+    for (i, field) in message.fields.iter().enumerate() {
+        if let Some(comment) = context.comment(&[PathSegment::Field(i)]) {
+            fields.insert(field.position(), comment);
+        }
+    }
+    for (i, oneof) in message.oneofs.iter().enumerate() {
+        for (j, field) in oneof.fields.iter().enumerate() {
+            if let Some(comment) = context.comment(&[PathSegment::Oneof(i), PathSegment::Field(j)])
+            {
+                fields.insert(field.position(), comment);
+            }
+        }
+    }
+    definitions.insert(
+        full_name.clone(),
+        Definition {
+            kind: Kind::Message(message),
+            comment: context.comment(&[]),
+            fields,
+        },
+    );
+    for (i, nested) in message.messages.iter().enumerate() {
+        context.path.push(PathSegment::Message(i));
+        define_message(definitions, context, &full_name, nested);
+        context.path.pop();
+    }
+    for (i, e) in message.enums.iter().enumerate() {
+        context.path.push(PathSegment::Enum(i));
+        define_enum(definitions, context, &full_name, e);
+        context.path.pop();
+    }
+}
+
+fn define_enum<'a>(
+    definitions: &mut BTreeMap<String, Definition<'a>>,
+    context: &Context<'a>,
+    scope: &str,
+    e: &'a Enum,
+) {
+    definitions.insert(
+        format!("{}.{}", scope, e.name),
+        Definition {
+            kind: Kind::Enum(e),
+            comment: context.comment(&[]),
+            fields: BTreeMap::new(),
+        },
+    );
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+/// Adds a schema comment to the front of a schema object as its
+/// description.
+pub(crate) fn described(schema: Json, comment: Option<&String>) -> Json {
+    match (schema, comment) {
+        (Json::Object(mut members), Some(comment)) => {
+            let lines: Vec<&str> = comment.trim().lines().map(str::trim).collect();
+            members.insert(0, ("description".to_string(), string(&lines.join("\n"))));
+            Json::Object(members)
+        }
+        (schema, _) => schema,
+    }
+}
+
+fn integer(minimum: &str, maximum: &str) -> Json {
+    object(vec![
+        ("type", string("integer")),
+        ("minimum", Json::Number(minimum.to_string())),
+        ("maximum", Json::Number(maximum.to_string())),
+    ])
+}
+
+/// A decimal string, as 64-bit integers and map keys are written.
+fn decimal(signed: bool) -> Json {
+    let pattern = if signed { "^-?[0-9]+$" } else { "^[0-9]+$" };
+    object(vec![
+        ("type", string("string")),
+        ("pattern", string(pattern)),
+    ])
+}
+
+/// A number, or one of the strings that stand for those JSON can't write.
+fn floating() -> Json {
+    object(vec![(
+        "anyOf",
+        Json::Array(vec![
+            object(vec![("type", string("number"))]),
+            object(vec![(
+                "enum",
+                Json::Array(vec![string("NaN"), string("Infinity"), string("-Infinity")]),
+            )]),
+        ]),
+    )])
+}
+
+/// Builds schemas that refer to definitions by `$ref`, as `prefix`
+/// followed by their full name, or as `#` for the document's `root`.
+pub(crate) struct Schemas<'a> {
+    pub definitions: &'a BTreeMap<String, Definition<'a>>,
+    /// The full name of the message the document describes, if any.
+    pub root: &'a str,
+    pub prefix: &'a str,
+}
+
+impl Schemas<'_> {
+    /// A document describing the root message.
+    fn document(&self) -> Json {
+        let mut used = BTreeSet::new();
+        self.collect(self.root, &mut used);
+        used.remove(self.root);
+        let mut members = vec![
+            ("$schema".to_string(), string(DRAFT)),
+            ("title".to_string(), string(&self.root[1..])),
+        ];
+        if let Json::Object(schema) = self.definition(self.root) {
+            members.extend(schema);
+        }
+        if !used.is_empty() {
+            let defs = used
+                .iter()
+                .map(|name| (name[1..].to_string(), self.definition(name)))
+                .collect();
+            members.push(("$defs".to_string(), Json::Object(defs)));
+        }
+        Json::Object(members)
+    }
+
+    /// Adds the messages and enums `name` refers to, other than the
+    /// well-known types written inline, to `used`.
+    pub fn collect(&self, name: &str, used: &mut BTreeSet<String>) {
+        if well_known(name).is_some() || !used.insert(name.to_string()) {
+            return;
+        }
+        let Some(Definition {
+            kind: Kind::Message(message),
+            ..
+        }) = self.definitions.get(name)
+        else {
+            return;
+        };
+        let fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields));
+        for field in fields {
+            let t = match field.field_type() {
+                Type::Map(_, value) => *value,
+                t => t,
+            };
+            if let Type::Message(name) | Type::Enum(name) = &t {
+                self.collect(name, used);
+            }
+        }
+    }
+
+    fn reference(&self, name: &str) -> Json {
+        let target = if name == self.root {
+            "#".to_string()
+        } else {
+            format!("{}{}", self.prefix, &name[1..])
+        };
+        object(vec![("$ref", Json::String(target))])
+    }
+
+    pub fn definition(&self, name: &str) -> Json {
+        let definition = &self.definitions[name];
+        let schema = match definition.kind {
+            Kind::Message(message) => self.message(message, definition),
+            Kind::Enum(e) => object(vec![
+                ("type", string("string")),
+                (
+                    "enum",
+                    Json::Array(e.fields.iter().map(|value| string(&value.name)).collect()),
+                ),
+            ]),
+        };
+        described(schema, definition.comment)
+    }
+
+    fn message(&self, message: &Message, definition: &Definition) -> Json {
+        let mut properties = vec![];
+        let mut required = vec![];
+        // What `required` can't say: keywords for `allOf`, or the message
+        // itself if there is just one.
+        let mut constraints = vec![];
+        let fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields));
+        for field in fields {
+            let is_required = field.frequency() == Some(&Frequency::Required);
+            let mut schema = self.field(field);
+            if !is_required {
+                // Parsers skip a `null` as if the field were absent.
+                schema = nullable(schema);
+            }
+            let schema = described(schema, definition.fields.get(&field.position()).copied());
+            let names = names(field);
+            for name in &names {
+                properties.push((name.clone(), schema.clone()));
+            }
+            if is_required {
+                match &names[..] {
+                    [name] => required.push(string(name)),
+                    _ => constraints.push((
+                        "anyOf",
+                        Json::Array(
+                            names
+                                .iter()
+                                .map(|name| {
+                                    object(vec![("required", Json::Array(vec![string(name)]))])
+                                })
+                                .collect(),
+                        ),
+                    )),
+                }
+            }
+        }
+        let mut members = vec![("type", string("object"))];
+        if !properties.is_empty() {
+            members.push(("properties", Json::Object(properties)));
+        }
+        if !required.is_empty() {
+            members.push(("required", Json::Array(required)));
+        }
+        members.push(("additionalProperties", Json::Bool(false)));
+
+        // Each oneof has at most one member set: exactly one of the
+        // alternatives, one per member and one with none of them, holds.
+        for oneof in &message.oneofs {
+            let present: Vec<Json> = oneof.fields.iter().map(present).collect();
+            let mut alternatives = present.clone();
+            alternatives.push(object(vec![(
+                "not",
+                object(vec![("anyOf", Json::Array(present))]),
+            )]));
+            constraints.push(("oneOf", Json::Array(alternatives)));
+        }
+        if constraints.len() == 1 {
+            members.push(constraints.remove(0));
+        } else if !constraints.is_empty() {
+            let all = constraints
+                .into_iter()
+                .map(|constraint| object(vec![constraint]))
+                .collect();
+            members.push(("allOf", Json::Array(all)));
+        }
+        object(members)
+    }
+
+    /// The schema of a field's value, or values if repeated or a map.
+    pub fn field(&self, field: &MessageField) -> Json {
+        match field.field_type() {
+            Type::Map(key, value) => {
+                let mut members = vec![("type", string("object"))];
+                match *key {
+                    Type::String => {}
+                    Type::Bool => members.push((
+                        "propertyNames",
+                        object(vec![(
+                            "enum",
+                            Json::Array(vec![string("true"), string("false")]),
+                        )]),
+                    )),
+                    Type::Uint32 | Type::Uint64 | Type::Fixed32 | Type::Fixed64 => {
+                        members.push(("propertyNames", decimal(false)))
+                    }
+                    _ => members.push(("propertyNames", decimal(true))),
+                }
+                members.push(("additionalProperties", self.value(&value)));
+                object(members)
+            }
+            t if field.is_repeated() => {
+                object(vec![("type", string("array")), ("items", self.value(&t))])
+            }
+            t => self.value(&t),
+        }
+    }
+
+    /// The schema of one value of type `t`.
+    pub fn value(&self, t: &Type) -> Json {
+        match t {
+            Type::Double | Type::Float => floating(),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => integer("-2147483648", "2147483647"),
+            Type::Uint32 | Type::Fixed32 => integer("0", "4294967295"),
+            Type::Int64 | Type::Sint64 | Type::Sfixed64 => decimal(true),
+            Type::Uint64 | Type::Fixed64 => decimal(false),
+            Type::Bool => object(vec![("type", string("boolean"))]),
+            Type::String => object(vec![("type", string("string"))]),
+            Type::Bytes => object(vec![
+                ("type", string("string")),
+                ("contentEncoding", string("base64")),
+            ]),
+            Type::Message(name) | Type::Enum(name) => match well_known(name) {
+                Some(schema) => schema,
+                None => self.reference(name),
+            },
+            Type::Named(name) => panic!("type {} is not linked", name),
+            Type::Map(..) => unreachable!("map values are not maps"),
+        }
+    }
+}
+
+/// `schema` or `null`.
+fn nullable(schema: Json) -> Json {
+    let null = object(vec![("type", string("null"))]);
+    match schema {
+        Json::Object(mut members) if members.len() == 1 && members[0].0 == "anyOf" => {
+            if let Json::Array(alternatives) = &mut members[0].1 {
+                alternatives.push(null);
+            }
+            Json::Object(members)
+        }
+        schema => object(vec![("anyOf", Json::Array(vec![schema, null]))]),
+    }
+}
+
+/// The keys a field is parsed from: its JSON name, and its own name if
+/// that differs.
+fn names(field: &MessageField) -> Vec<String> {
+    let mut names = vec![field.json_name()];
+    if field.name() != names[0] {
+        names.push(field.name().to_string());
+    }
+    names
+}
+
+/// Holds when a field is set, under either of its names and not `null`.
+fn present(field: &MessageField) -> Json {
+    let mut alternatives: Vec<Json> = names(field)
+        .iter()
+        .map(|name| {
+            object(vec![
+                ("required", Json::Array(vec![string(name)])),
+                (
+                    "properties",
+                    object(vec![(
+                        name,
+                        object(vec![("not", object(vec![("type", string("null"))]))]),
+                    )]),
+                ),
+            ])
+        })
+        .collect();
+    match alternatives.len() {
+        1 => alternatives.remove(0),
+        _ => object(vec![("anyOf", Json::Array(alternatives))]),
+    }
+}
+
+/// The schema of a well-known type with a JSON form of its own.
+fn well_known(name: &str) -> Option<Json> {
+    let typed = |t| object(vec![("type", string(t))]);
+    Some(match name.strip_prefix(".google.protobuf.")? {
+        "Timestamp" => object(vec![
+            ("type", string("string")),
+            ("format", string("date-time")),
+        ]),
+        "Duration" => object(vec![
+            ("type", string("string")),
+            ("pattern", string("^-?[0-9]+(\\.[0-9]{1,9})?s$")),
+        ]),
+        "FieldMask" => typed("string"),
+        "Struct" => typed("object"),
+        "ListValue" => typed("array"),
+        "Value" => object(vec![]),
+        "NullValue" => typed("null"),
+        "Empty" => object(vec![
+            ("type", string("object")),
+            ("additionalProperties", Json::Bool(false)),
+        ]),
+        "Any" => object(vec![
+            ("type", string("object")),
+            ("properties", object(vec![("@type", typed("string"))])),
+            ("required", Json::Array(vec![string("@type")])),
+        ]),
+        "DoubleValue" | "FloatValue" => floating(),
+        "Int64Value" => decimal(true),
+        "UInt64Value" => decimal(false),
+        "Int32Value" => integer("-2147483648", "2147483647"),
+        "UInt32Value" => integer("0", "4294967295"),
+        "BoolValue" => typed("boolean"),
+        "StringValue" => typed("string"),
+        "BytesValue" => object(vec![
+            ("type", string("string")),
+            ("contentEncoding", string("base64")),
+        ]),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic::DynamicMessage;
+    use crate::resolver::Resolver;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["demo.proto"]);
+        let expected =
+            std::fs::read_to_string("tests/json_schema/demo.Person.schema.json").unwrap();
+        assert_eq!(generated["demo.Person.schema.json"], expected);
+        assert_eq!(
+            generated["demo.Empty.schema.json"],
+            "{\n  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n  \"title\": \"demo.Empty\",\n  \"type\": \"object\",\n  \"additionalProperties\": false\n}\n"
+        );
+    }
+
+    #[test]
+    fn validates_printed_json() {
+        let dir = std::env::temp_dir().join(format!("protos-json-schema-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let set = load();
+        let schema = &generate(&set, &["demo.proto"])["demo.Person.schema.json"];
+        std::fs::write(dir.join("schema.json"), schema).unwrap();
+        let mut person = DynamicMessage::from_file_set(Arc::new(set), "demo.Person").unwrap();
+        text_format::merge(
+            r#"name: "Ada" id: -7 emails: "ada@example.com" scores { key: "chess" value: -3 }
+               address { city: "Paris" owner { name: "Bo" } } kind: KIND_FRIEND
+               office { city: "Oslo" } height: 0.5 lucky: 7 counts: -9000000000
+               photo: "\x00\xff" friends { active: true } homes { key: -1 value {} }"#,
+            &mut person,
+        )
+        .unwrap();
+        let printed = json::print(&person, &json::Options::default()).unwrap();
+        // Parsers also take `null` for absent fields.
+        std::fs::write(
+            dir.join("valid.json"),
+            format!(
+                r#"[{}, {{"name": null, "address": null, "emails": null, "phone": null}}]"#,
+                printed
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("invalid.json"),
+            r#"[{"phone": "555", "office": {}}, {"counts": [1]}, {"kind": 1},
+                {"id": 2147483648}, {"homes": {"x": {}}}, {"nickname": "A"}]"#,
+        )
+        .unwrap();
+        let script = "import json, jsonschema\n\
+                      schema = json.load(open('schema.json'))\n\
+                      validator = jsonschema.Draft202012Validator(schema)\n\
+                      for instance in json.load(open('valid.json')):\n\
+                      \x20   validator.validate(instance)\n\
+                      for instance in json.load(open('invalid.json')):\n\
+                      \x20   assert not validator.is_valid(instance), instance\n";
+        let output = match Command::new("python3")
+            .args(["-c", script])
+            .current_dir(&dir)
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping validates_printed_json: python3 is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No module named 'jsonschema'") {
+            eprintln!("skipping validates_printed_json: jsonschema is not installed");
+            return;
+        }
+        assert!(output.status.success(), "{}", stderr);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn required_fields_oneofs_and_well_known_types() {
+        let dir =
+            std::env::temp_dir().join(format!("protos-json-schema-wkt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("event.proto"),
+            "syntax = \"proto2\"; package events;
+             import \"google/protobuf/timestamp.proto\"; import \"google/protobuf/wrappers.proto\";
+             message Event {
+               required string id = 1; optional google.protobuf.Timestamp at = 2;
+               optional google.protobuf.UInt64Value size = 3; map<bool, uint64> flags = 4;
+               oneof a { int32 x = 5; int32 y = 6; }
+               oneof b { int32 z = 7; }
+               required string trace_id = 8;
+             }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["event.proto"])
+            .unwrap();
+        let generated = generate(&set, &["event.proto"]);
+        let schema = &generated["events.Event.schema.json"];
+        assert!(!schema.contains("$defs"));
+        assert!(schema.contains(
+            "{\n          \"type\": \"string\",\n          \"format\": \"date-time\"\n        },"
+        ));
+        assert!(schema.contains(
+            "{\n          \"type\": \"string\",\n          \"pattern\": \"^[0-9]+$\"\n        },"
+        ));
+        assert!(schema.contains("\"propertyNames\": {\n            \"enum\": [\n              \"true\",\n              \"false\"\n            ]\n          }"));
+        assert!(schema.contains("\"required\": [\n    \"id\"\n  ],"));
+        // Fields are also parsed by their own names.
+        assert!(schema
+            .contains("\"traceId\": {\n      \"type\": \"string\"\n    },\n    \"trace_id\": {"));
+        assert!(schema.contains("\"allOf\": [\n    {\n      \"anyOf\": [\n        {\n          \"required\": [\n            \"traceId\"\n          ]\n        },\n        {\n          \"required\": [\n            \"trace_id\"\n          ]\n        }\n      ]\n    },\n    {\n      \"oneOf\": ["));
+        assert_eq!(generated.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
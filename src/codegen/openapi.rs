@@ -0,0 +1,621 @@
+//! Generates OpenAPI 3.1 documents from services whose methods carry
+//! `google.api.http` rules, as served by gRPC transcoding.
+//!
+//! Each file with such methods becomes `x.openapi.json`. A rule's path
+//! template gives the operation's path, its variables the path
+//! parameters, and the request fields bound by neither the path nor the
+//! body the query parameters. Request and response bodies refer to
+//! schemas of the proto3 JSON mapping under `components`, and the
+//! schema's comments become descriptions.
+
+use super::json_schema::{self, described, Definition, Kind, Schemas};
+use super::{extension_fields, parameters, Context};
+use crate::json::Json;
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The API version written to `info.version`.
+    pub version: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            version: "0.0.0".to_string(),
+        }
+    }
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters: `version=V`.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            match item.split_once('=') {
+                Some(("version", version)) => options.version = version.to_string(),
+                _ => return Err(format!("unknown openapi generator parameter {}", item)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Generates a document for each named file with HTTP rules.
+pub fn generate(set: &FileSet, files: &[&str], options: &Options) -> BTreeMap<String, String> {
+    let definitions = json_schema::definitions(set);
+    let schemas = Schemas {
+        definitions: &definitions,
+        root: "",
+        prefix: "#/components/schemas/",
+    };
+    let mut ret = BTreeMap::new();
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(&file.name.as_str()))
+    {
+        let generator = Generator {
+            set,
+            schemas: &schemas,
+            used: BTreeSet::new(),
+        };
+        if let Some(document) = generator.generate(file, options) {
+            let stem = file.name.strip_suffix(".proto").unwrap_or(&file.name);
+            ret.insert(format!("{}.openapi.json", stem), document.pretty());
+        }
+    }
+    ret
+}
+
+/// The HTTP methods an OpenAPI path item can have operations for.
+const VERBS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// One binding of a method to HTTP.
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    /// The HTTP method, lowercase.
+    verb: String,
+    template: String,
+    body: Option<String>,
+    response_body: Option<String>,
+}
+
+/// The rules of a method's `google.api.http` option, written whole or
+/// field by field, followed by its additional bindings.
+fn rules(method: &Method) -> Vec<Rule> {
+    let entries = extension_fields(&method.options, "google.api.http");
+    let mut ret = vec![];
+    add_rules(&entries, &mut ret);
+    ret
+}
+
+fn add_rules(entries: &[(String, Constant)], ret: &mut Vec<Rule>) {
+    let text = |entries: &[(String, Constant)], name: &str| {
+        entries.iter().find_map(|(key, value)| match value {
+            Constant::String(value) if key == name => Some(value.clone()),
+            _ => None,
+        })
+    };
+    let standard = ["get", "put", "post", "delete", "patch"]
+        .iter()
+        .find_map(|verb| Some((verb.to_string(), text(entries, verb)?)));
+    let custom = || {
+        entries.iter().find_map(|(key, value)| match value {
+            Constant::Aggregate(custom) if key == "custom" => {
+                Some((text(custom, "kind")?.to_lowercase(), text(custom, "path")?))
+            }
+            _ => None,
+        })
+    };
+    if let Some((verb, template)) = standard.or_else(custom) {
+        ret.push(Rule {
+            verb,
+            template,
+            body: text(entries, "body"),
+            response_body: text(entries, "response_body"),
+        });
+    }
+    for (key, value) in entries {
+        if let (Constant::Aggregate(binding), "additional_bindings") = (value, key.as_str()) {
+            add_rules(binding, ret);
+        }
+    }
+}
+
+/// A variable of a path template.
+#[derive(Debug, PartialEq)]
+struct Variable {
+    /// The field path, such as `book.name`.
+    field: String,
+    /// The regular expression a value matches, if it spans more than one
+    /// segment or has literal parts.
+    pattern: Option<String>,
+}
+
+/// An OpenAPI path for a path template, with the template's variables:
+/// `/v1/{name=shelves/*}` becomes `/v1/{name}` with a variable whose
+/// value matches `^shelves/[^/]+$`.
+fn path(template: &str) -> (String, Vec<Variable>) {
+    let mut path = String::new();
+    let mut variables = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        path.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end);
+        let variable = &rest[start + 1..end];
+        let (field, segments) = match variable.split_once('=') {
+            Some((field, segments)) => (field.trim(), Some(segments.trim())),
+            None => (variable.trim(), None),
+        };
+        let pattern = segments
+            .filter(|segments| *segments != "*")
+            .map(|segments| {
+                let parts: Vec<String> = segments
+                    .split('/')
+                    .map(|segment| match segment {
+                        "*" => "[^/]+".to_string(),
+                        "**" => ".+".to_string(),
+                        literal => literal
+                            .chars()
+                            .map(|c| {
+                                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                                    c.to_string()
+                                } else {
+                                    format!("\\{}", c)
+                                }
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                format!("^{}$", parts.join("/"))
+            });
+        path.push_str(&format!("{{{}}}", field));
+        variables.push(Variable {
+            field: field.to_string(),
+            pattern,
+        });
+        rest = rest.get(end + 1..).unwrap_or("");
+    }
+    path.push_str(rest);
+    (path, variables)
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+/// A message's fields, including those of its oneofs.
+fn all_fields(message: &Message) -> impl Iterator<Item = &MessageField> {
+    message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields))
+}
+
+struct Generator<'a> {
+    set: &'a FileSet,
+    schemas: &'a Schemas<'a>,
+    /// The messages and enums the document's schemas refer to.
+    used: BTreeSet<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn generate(mut self, file: &ProtoFile, options: &Options) -> Option<Json> {
+        let mut context = Context::new(self.set, file);
+        let mut tags = vec![];
+        let mut paths: Vec<(String, Vec<(String, Json)>)> = vec![];
+        for (i, service) in file.services.iter().enumerate() {
+            context.path.push(PathSegment::Service(i));
+            let mut tagged = false;
+            for (j, method) in service.methods.iter().enumerate() {
+                let comment = context.comment(&[PathSegment::Method(j)]);
+                // OpenAPI has no place for other custom methods.
+                let rules = rules(method)
+                    .into_iter()
+                    .filter(|rule| VERBS.contains(&rule.verb.as_str()));
+                for (k, rule) in rules.enumerate() {
+                    tagged = true;
+                    let mut operation_id = format!("{}_{}", service.name, method.name);
+                    if k > 0 {
+                        operation_id.push_str(&format!("_{}", k));
+                    }
+                    let (path, variables) = path(&rule.template);
+                    let operation = self.operation(
+                        &service.name,
+                        &operation_id,
+                        method,
+                        &rule,
+                        &variables,
+                        comment,
+                    );
+                    match paths.iter_mut().find(|(existing, _)| *existing == path) {
+                        Some((_, operations)) => operations.push((rule.verb.clone(), operation)),
+                        None => paths.push((path, vec![(rule.verb.clone(), operation)])),
+                    }
+                }
+            }
+            if tagged {
+                let tag = object(vec![("name", string(&service.name))]);
+                tags.push(described(tag, context.comment(&[])));
+            }
+            context.path.pop();
+        }
+        if paths.is_empty() {
+            return None;
+        }
+
+        let title = file.package.as_deref().unwrap_or(&file.name);
+        let mut document = vec![
+            ("openapi", string("3.1.0")),
+            (
+                "info",
+                object(vec![
+                    ("title", string(title)),
+                    ("version", string(&options.version)),
+                ]),
+            ),
+            ("tags", Json::Array(tags)),
+            (
+                "paths",
+                Json::Object(
+                    paths
+                        .into_iter()
+                        .map(|(path, operations)| (path, Json::Object(operations)))
+                        .collect(),
+                ),
+            ),
+        ];
+        if !self.used.is_empty() {
+            let schemas = self
+                .used
+                .iter()
+                .map(|name| (name[1..].to_string(), self.schemas.definition(name)))
+                .collect();
+            document.push((
+                "components",
+                object(vec![("schemas", Json::Object(schemas))]),
+            ));
+        }
+        Some(object(document))
+    }
+
+    /// Marks the definitions a value of type `t` refers to as used.
+    fn uses(&mut self, t: &Type) {
+        match t {
+            Type::Map(_, value) => self.uses(value),
+            Type::Message(name) | Type::Enum(name) => self.schemas.collect(name, &mut self.used),
+            _ => {}
+        }
+    }
+
+    /// The field at a dotted `path` from the message named `message`, with
+    /// the name of the message declaring it.
+    fn find_field(&self, message: &str, path: &str) -> Option<(String, &'a MessageField)> {
+        let (first, rest) = match path.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (path, None),
+        };
+        let field =
+            all_fields(self.set.find_message(message)?).find(|field| field.name() == first)?;
+        match (rest, field.field_type()) {
+            (None, _) => Some((message.to_string(), field)),
+            (Some(rest), Type::Message(name)) => self.find_field(&name, rest),
+            _ => None,
+        }
+    }
+
+    fn comment(&self, message: &str, field: &MessageField) -> Option<&'a String> {
+        self.schemas
+            .definitions
+            .get(message)
+            .and_then(|definition: &Definition| definition.fields.get(&field.position()))
+            .copied()
+    }
+
+    fn operation(
+        &mut self,
+        service: &str,
+        operation_id: &str,
+        method: &Method,
+        rule: &Rule,
+        variables: &[Variable],
+        comment: Option<&String>,
+    ) -> Json {
+        let mut parameters = vec![];
+        for variable in variables {
+            let (schema, description) = match self.find_field(&method.input_type, &variable.field) {
+                Some((message, field)) => {
+                    self.uses(&field.field_type());
+                    (self.schemas.field(field), self.comment(&message, field))
+                }
+                None => (object(vec![("type", string("string"))]), None),
+            };
+            let schema = match &variable.pattern {
+                Some(pattern) => object(vec![
+                    ("type", string("string")),
+                    ("pattern", string(pattern)),
+                ]),
+                None => schema,
+            };
+            parameters.push(described(
+                object(vec![
+                    ("name", string(&variable.field)),
+                    ("in", string("path")),
+                    ("required", Json::Bool(true)),
+                    ("schema", schema),
+                ]),
+                description,
+            ));
+        }
+        if rule.body.as_deref() != Some("*") {
+            let mut bound: Vec<String> = variables.iter().map(|v| v.field.clone()).collect();
+            bound.extend(rule.body.clone());
+            let mut stack = vec![];
+            self.query(
+                &method.input_type,
+                "",
+                "",
+                &bound,
+                &mut stack,
+                &mut parameters,
+            );
+        }
+
+        let mut members = vec![
+            ("tags", Json::Array(vec![string(service)])),
+            ("operationId", string(operation_id)),
+        ];
+        if !parameters.is_empty() {
+            members.push(("parameters", Json::Array(parameters)));
+        }
+        let body = match rule.body.as_deref() {
+            None => None,
+            Some("*") => Some(self.message(&method.input_type)),
+            Some(path) => self.find_field(&method.input_type, path).map(|(_, field)| {
+                self.uses(&field.field_type());
+                self.schemas.field(field)
+            }),
+        };
+        if let Some(schema) = body {
+            members.push((
+                "requestBody",
+                object(vec![
+                    ("required", Json::Bool(true)),
+                    ("content", Self::json_content(schema)),
+                ]),
+            ));
+        }
+        let response = match &rule.response_body {
+            Some(path) => self
+                .find_field(&method.output_type, path)
+                .map(|(_, field)| {
+                    self.uses(&field.field_type());
+                    self.schemas.field(field)
+                }),
+            None => None,
+        };
+        let response = response.unwrap_or_else(|| self.message(&method.output_type));
+        members.push((
+            "responses",
+            object(vec![(
+                "200",
+                object(vec![
+                    ("description", string("A successful response.")),
+                    ("content", Self::json_content(response)),
+                ]),
+            )]),
+        ));
+        described(object(members), comment)
+    }
+
+    fn json_content(schema: Json) -> Json {
+        object(vec![("application/json", object(vec![("schema", schema)]))])
+    }
+
+    /// The schema of a whole message, referring to its definition.
+    fn message(&mut self, name: &str) -> Json {
+        let t = Type::Message(name.to_string());
+        self.uses(&t);
+        self.schemas.value(&t)
+    }
+
+    /// Adds a query parameter for each field of `message` not `bound` to
+    /// the path or body, naming the fields of singular message fields by
+    /// their dotted paths. `stack` holds the messages being expanded, so
+    /// recursive ones stop.
+    #[allow(clippy::too_many_arguments)]
+    fn query(
+        &mut self,
+        message: &str,
+        path: &str,
+        json_path: &str,
+        bound: &[String],
+        stack: &mut Vec<String>,
+        parameters: &mut Vec<Json>,
+    ) {
+        let Some(Definition {
+            kind: Kind::Message(definition),
+            ..
+        }) = self.schemas.definitions.get(message)
+        else {
+            return;
+        };
+        stack.push(message.to_string());
+        for field in all_fields(definition) {
+            let field_path = format!("{}{}", path, field.name());
+            let json_name = format!("{}{}", json_path, field.json_name());
+            if bound.contains(&field_path) {
+                continue;
+            }
+            let t = field.field_type();
+            match &t {
+                Type::Map(..) => continue,
+                Type::Message(name)
+                    if !name.starts_with(".google.protobuf.") || field.is_repeated() =>
+                {
+                    if !field.is_repeated() && !stack.contains(name) {
+                        let field_path = format!("{}.", field_path);
+                        let json_name = format!("{}.", json_name);
+                        self.query(name, &field_path, &json_name, bound, stack, parameters);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            self.uses(&t);
+            parameters.push(described(
+                object(vec![
+                    ("name", string(&json_name)),
+                    ("in", string("query")),
+                    ("schema", self.schemas.field(field)),
+                ]),
+                self.comment(message, field),
+            ));
+        }
+        stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::FileDescriptorSet;
+    use crate::resolver::Resolver;
+    use std::process::Command;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/openapi")
+            .load(&["library.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["library.proto"], &Options::default());
+        let expected = std::fs::read_to_string("tests/openapi/library.openapi.json").unwrap();
+        assert_eq!(generated["library.openapi.json"], expected);
+        assert_eq!(generated.len(), 1);
+    }
+
+    #[test]
+    fn from_descriptors() {
+        // As plugins get them: options decoded from descriptors and
+        // comments from their source info.
+        let expected = std::fs::read_to_string("tests/openapi/library.openapi.json").unwrap();
+        let encoded = FileDescriptorSet::from_file_set(&load(), true).encode_to_vec();
+        let set = FileDescriptorSet::decode(&encoded).unwrap().to_file_set();
+        let generated = generate(&set, &["library.proto"], &Options::default());
+        assert_eq!(generated["library.openapi.json"], expected);
+
+        let out = std::env::temp_dir().join(format!("protos-openapi-{}.pb", std::process::id()));
+        let output = match Command::new("protoc")
+            .args(["-Itests/openapi", "-Isrc/well_known", "--include_imports"])
+            .arg("--include_source_info")
+            .arg(format!("--descriptor_set_out={}", out.display()))
+            .arg("library.proto")
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping protoc descriptors: protoc is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let set = FileDescriptorSet::decode(&std::fs::read(&out).unwrap())
+            .unwrap()
+            .to_file_set();
+        let generated = generate(&set, &["library.proto"], &Options::default());
+        assert_eq!(generated["library.openapi.json"], expected);
+        std::fs::remove_file(&out).unwrap();
+    }
+
+    #[test]
+    fn path_templates() {
+        assert_eq!(
+            path("/v1/{name=shelves/*}"),
+            (
+                "/v1/{name}".to_string(),
+                vec![Variable {
+                    field: "name".to_string(),
+                    pattern: Some("^shelves/[^/]+$".to_string()),
+                }]
+            )
+        );
+        let (path, variables) = path("/v1/{book.name=shelves/*/books/**}:move/{id}");
+        assert_eq!(path, "/v1/{book.name}:move/{id}");
+        assert_eq!(
+            variables[0].pattern.as_deref(),
+            Some("^shelves/[^/]+/books/.+$")
+        );
+        assert_eq!(variables[1].pattern, None);
+    }
+
+    #[test]
+    fn rules_and_options() {
+        let method = Method {
+            name: "Run".to_string(),
+            options: vec![
+                ProtoOption {
+                    name: "(google.api.http)".to_string(),
+                    value: Constant::Aggregate(vec![
+                        (
+                            "custom".to_string(),
+                            Constant::Aggregate(vec![
+                                ("kind".to_string(), Constant::String("HEAD".to_string())),
+                                ("path".to_string(), Constant::String("/v1/jobs".to_string())),
+                            ]),
+                        ),
+                        (
+                            "additional_bindings".to_string(),
+                            Constant::Aggregate(vec![(
+                                "put".to_string(),
+                                Constant::String("/v1/jobs/{id}".to_string()),
+                            )]),
+                        ),
+                    ]),
+                },
+                ProtoOption {
+                    name: "(google.api.http).response_body".to_string(),
+                    value: Constant::String("job".to_string()),
+                },
+            ],
+            ..Default::default()
+        };
+        let rules = rules(&method);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(
+            (rules[0].verb.as_str(), rules[0].template.as_str()),
+            ("head", "/v1/jobs")
+        );
+        assert_eq!(rules[0].response_body.as_deref(), Some("job"));
+        assert_eq!(rules[1].verb, "put");
+        assert_eq!(Options::parse("version=2.1").unwrap().version, "2.1");
+        assert_eq!(
+            Options::parse("title=x").unwrap_err(),
+            "unknown openapi generator parameter title=x"
+        );
+    }
+}
@@ -0,0 +1,811 @@
+//! Generates Python from linked schemas.
+//!
+//! Each message becomes a `@dataclass` with `encode` and `decode`, each
+//! enum an `enum.IntEnum`, and types nested in a message are nested
+//! classes. A oneof's members are separate `Optional` fields of which at
+//! most one is set, with a `which_` method naming it. Every `.proto` file
+//! becomes a module importing the modules of its dependencies, and all of
+//! them import the codec from `protos_runtime.py`. The well-known types
+//! used are generated too, under `protos_well_known` rather than the
+//! official `google.protobuf` package, whose classes are not dataclasses.
+//!
+//! Enum-typed fields hold plain `int`s for values the schema doesn't
+//! name, so that they survive a round trip.
+
+use super::{declare_all, is_packable, method, wire_type, Code, Context, Declared, Label};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The reader and writer generated modules import.
+pub const RUNTIME: &str = include_str!("python/protos_runtime.py");
+
+/// The package the well-known types are generated in.
+const WELL_KNOWN: &str = "protos_well_known";
+
+/// Generates a module for each named file, keyed by its name with `.py`
+/// in place of `.proto`, and `protos_runtime.py` at the root of the
+/// output, which must be on `sys.path`.
+pub fn generate(set: &FileSet, files: &[&str]) -> BTreeMap<String, String> {
+    let types = declare_all(set, ident, ".");
+    let mut ret = BTreeMap::new();
+    let mut pending: Vec<&str> = files.to_vec();
+    let mut done = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        let Some(file) = set.files.iter().find(|file| file.name == name) else {
+            continue;
+        };
+        if !done.insert(name) {
+            continue;
+        }
+        let generator = FileGenerator {
+            set,
+            types: &types,
+            file: &file.name,
+            defined: BTreeSet::new(),
+            imports: BTreeSet::new(),
+            typing: BTreeSet::new(),
+        };
+        let (code, imports) = generator.generate(set, file);
+        ret.insert(format!("{}.py", module_path(name).replace('.', "/")), code);
+        // The well-known types come with the modules that use them.
+        pending.extend(
+            imports
+                .iter()
+                .filter(|import| well_known::source(import).is_some())
+                .filter_map(|import| set.files.iter().find(|file| file.name == **import))
+                .map(|file| file.name.as_str()),
+        );
+    }
+    ret.insert("protos_runtime.py".to_string(), RUNTIME.to_string());
+    ret
+}
+
+/// The module path of the module generated for `file`: `a/b.proto` is
+/// `a.b`. The well-known types' modules are under `protos_well_known`, so
+/// as not to clash with the official `google.protobuf` package.
+fn module_path(file: &str) -> String {
+    let path = file
+        .strip_suffix(".proto")
+        .unwrap_or(file)
+        .replace('/', ".");
+    match well_known::source(file) {
+        Some(_) => format!("{}.{}", WELL_KNOWN, path),
+        None => path,
+    }
+}
+
+struct FileGenerator<'a> {
+    set: &'a FileSet,
+    types: &'a BTreeMap<String, Declared>,
+    file: &'a str,
+    /// The top-level classes already declared, which class bodies can
+    /// refer to while the module is being executed.
+    defined: BTreeSet<String>,
+    /// The files whose modules are imported.
+    imports: BTreeSet<String>,
+    /// The names imported from `typing`.
+    typing: BTreeSet<&'static str>,
+}
+
+impl FileGenerator<'_> {
+    /// The module's code and the files whose modules it imports.
+    fn generate(mut self, set: &FileSet, file: &ProtoFile) -> (String, BTreeSet<String>) {
+        let mut code = Code::new("    ");
+        let mut context = Context::new(set, file);
+        // Enums come first so that field defaults can name their values.
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            code.line("");
+            code.line("");
+            self.enumeration(&mut code, &context, e);
+            self.defined.insert(ident(&e.name));
+            context.path.pop();
+        }
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            code.line("");
+            code.line("");
+            let full_name = format!("{}.{}", scope, message.name);
+            self.message(&mut code, &mut context, message, &full_name);
+            self.defined.insert(ident(&message.name));
+            context.path.pop();
+        }
+
+        let mut header = Code::new("    ");
+        header.line(format!(
+            "# @generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        header.line("");
+        header.line("from __future__ import annotations");
+        header.line("");
+        if !file.messages.is_empty() {
+            header.line("import dataclasses");
+        }
+        if !file.enums.is_empty() || file.messages.iter().any(has_enums) {
+            header.line("import enum");
+        }
+        if !self.typing.is_empty() {
+            let names: Vec<&str> = self.typing.iter().copied().collect();
+            header.line(format!("from typing import {}", names.join(", ")));
+        }
+        if !file.messages.is_empty() {
+            header.line("");
+            header.line("import protos_runtime as _protos_runtime");
+        }
+        for import in &self.imports {
+            header.line(format!(
+                "import {} as {}",
+                module_path(import),
+                module_alias(import)
+            ));
+        }
+        (format!("{}{}", header.text, code.text), self.imports)
+    }
+
+    /// The path of a message or enum from module scope, qualified by its
+    /// module's alias if declared in another file.
+    fn name(&mut self, full_name: &str) -> String {
+        let declared = &self.types[full_name];
+        if declared.file == self.file {
+            declared.name.clone()
+        } else {
+            self.imports.insert(declared.file.clone());
+            format!("{}.{}", module_alias(&declared.file), declared.name)
+        }
+    }
+
+    fn py_type(&mut self, t: &Type) -> String {
+        match t {
+            Type::String => "str".to_string(),
+            Type::Bytes => "bytes".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Float | Type::Double => "float".to_string(),
+            Type::Enum(name) | Type::Message(name) => self.name(name),
+            _ => "int".to_string(),
+        }
+    }
+
+    /// The value of an unset `t`, from code run after the module loaded.
+    fn default(&mut self, t: &Type) -> String {
+        match t {
+            Type::String => "\"\"".to_string(),
+            Type::Bytes => "b\"\"".to_string(),
+            Type::Bool => "False".to_string(),
+            Type::Float | Type::Double => "0.0".to_string(),
+            Type::Enum(name) => format!("{}.{}", self.name(name), self.first_value(name)),
+            Type::Message(name) => format!("{}()", self.name(name)),
+            _ => "0".to_string(),
+        }
+    }
+
+    /// A statement writing field `number` holding `expr`.
+    fn write(&mut self, t: &Type, number: u32, expr: &str, writer: &str) -> String {
+        let tagged = format!("{}.tag({}, {})", writer, number, wire_type(t));
+        Self::write_value(t, expr, &tagged)
+    }
+
+    /// A call writing `expr` to `writer` without a tag.
+    fn write_value(t: &Type, expr: &str, writer: &str) -> String {
+        match t {
+            Type::Message(_) => format!("{}.bytes({}.encode())", writer, expr),
+            t => format!("{}.{}({})", writer, method(t), expr),
+        }
+    }
+
+    /// An expression reading one value of `t` from `reader`.
+    fn read(&mut self, t: &Type, reader: &str) -> String {
+        let read = format!("{}.{}()", reader, method(t));
+        match t {
+            Type::Message(name) => format!("{}.decode({})", self.name(name), read),
+            Type::Enum(name) => {
+                format!("_protos_runtime.enum_value({}, {})", self.name(name), read)
+            }
+            _ => read,
+        }
+    }
+
+    fn message(
+        &mut self,
+        code: &mut Code,
+        context: &mut Context,
+        message: &Message,
+        full_name: &str,
+    ) {
+        let name = ident(&message.name);
+        let fields: Vec<(usize, &MessageField, Label)> = message
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let options = message.field_options.get(&field.position());
+                (i, field, Label::of(field, options, context.proto3))
+            })
+            .collect();
+
+        code.line("@dataclasses.dataclass");
+        code.line(format!("class {}:", name));
+        code.depth += 1;
+        let mut empty = true;
+        if let Some(comment) = context.comment(&[]) {
+            docstring(code, comment);
+            empty = false;
+        }
+        for (i, e) in message.enums.iter().enumerate() {
+            if !std::mem::take(&mut empty) {
+                code.line("");
+            }
+            context.path.push(PathSegment::Enum(i));
+            self.enumeration(code, context, e);
+            context.path.pop();
+        }
+        for (i, nested) in message.messages.iter().enumerate() {
+            if !std::mem::take(&mut empty) {
+                code.line("");
+            }
+            context.path.push(PathSegment::Message(i));
+            let nested_name = format!("{}.{}", full_name, nested.name);
+            self.message(code, context, nested, &nested_name);
+            context.path.pop();
+        }
+
+        if !fields.is_empty() || !message.oneofs.is_empty() {
+            if !std::mem::take(&mut empty) {
+                code.line("");
+            }
+            for (i, field, label) in &fields {
+                code.comment("#", context.comment(&[PathSegment::Field(*i)]));
+                let declaration = self.declaration(message, full_name, field, *label);
+                code.line(declaration);
+            }
+            for (i, oneof) in message.oneofs.iter().enumerate() {
+                code.comment("#", context.comment(&[PathSegment::Oneof(i)]));
+                for field in &oneof.fields {
+                    let declaration = self.declaration(message, full_name, field, Label::Optional);
+                    code.line(declaration);
+                }
+            }
+        }
+
+        for oneof in &message.oneofs {
+            self.typing.insert("Optional");
+            if !std::mem::take(&mut empty) {
+                code.line("");
+            }
+            code.line(format!("def which_{}(self) -> Optional[str]:", oneof.name));
+            code.depth += 1;
+            code.line(format!(
+                "\"\"\"The name of the member of `{}` that is set, if any.\"\"\"",
+                oneof.name
+            ));
+            for field in &oneof.fields {
+                code.line(format!("if self.{} is not None:", ident(field.name())));
+                code.line(format!("    return \"{}\"", field.name()));
+            }
+            code.line("return None");
+            code.depth -= 1;
+        }
+
+        if !empty {
+            code.line("");
+        }
+        self.encode(code, message, &fields);
+        code.line("");
+        self.decode(code, message, &self.types[full_name].name.clone(), &fields);
+        code.depth -= 1;
+    }
+
+    /// The dataclass field for `field` of `message`.
+    fn declaration(
+        &mut self,
+        message: &Message,
+        full_name: &str,
+        field: &MessageField,
+        label: Label,
+    ) -> String {
+        let name = ident(field.name());
+        let t = field.field_type();
+        match (label, &t) {
+            (Label::Map, Type::Map(key, value)) => {
+                let key = self.py_type(key);
+                let value = self.py_type(value);
+                format!(
+                    "{}: dict[{}, {}] = dataclasses.field(default_factory=dict)",
+                    name, key, value
+                )
+            }
+            (Label::Repeated | Label::Packed, t) => {
+                let t = self.py_type(t);
+                format!(
+                    "{}: list[{}] = dataclasses.field(default_factory=list)",
+                    name, t
+                )
+            }
+            (Label::Optional, t) => {
+                self.typing.insert("Optional");
+                format!("{}: Optional[{}] = None", name, self.py_type(t))
+            }
+            (_, Type::Enum(enum_name)) => {
+                let t = self.py_type(&t);
+                let first = self.first_value(enum_name);
+                // Class bodies run as the module loads, so only enums
+                // declared by then can be named directly.
+                match self.visible(message, full_name, enum_name) {
+                    Some(path) => format!("{}: {} = {}.{}", name, t, path, first),
+                    None => format!(
+                        "{}: {} = dataclasses.field(default_factory=lambda: {}.{})",
+                        name, t, t, first
+                    ),
+                }
+            }
+            (_, t) => {
+                let py_type = self.py_type(t);
+                format!("{}: {} = {}", name, py_type, self.default(t))
+            }
+        }
+    }
+
+    /// The name of the enum `full_name` as the body of `message` can refer
+    /// to it, if it can.
+    fn visible(&mut self, message: &Message, container: &str, full_name: &str) -> Option<String> {
+        let declared = &self.types[full_name];
+        if declared.file != self.file {
+            return Some(self.name(full_name));
+        }
+        if let Some(nested) = full_name.strip_prefix(&format!("{}.", container)) {
+            if message.enums.iter().any(|e| e.name == nested) {
+                return Some(ident(nested));
+            }
+        }
+        let top = declared.name.split('.').next().unwrap_or_default();
+        self.defined.contains(top).then(|| declared.name.clone())
+    }
+
+    /// The first value of an enum, its default.
+    fn first_value(&self, full_name: &str) -> String {
+        self.set
+            .find_enum(full_name)
+            .and_then(|e| e.fields.first())
+            .map(|value| ident(&value.name))
+            .unwrap_or_default()
+    }
+
+    fn encode(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        let members = members(message, fields);
+        code.line("def encode(self) -> bytes:");
+        code.depth += 1;
+        code.line("writer = _protos_runtime.Writer()");
+        for (number, _, field, label) in members {
+            let t = field.field_type();
+            let expr = format!("self.{}", ident(field.name()));
+            match label {
+                Label::Implicit => {
+                    code.line(format!("if {}:", expr));
+                    let statement = self.write(&t, number, &expr, "writer");
+                    code.line(format!("    {}", statement));
+                }
+                Label::Optional => {
+                    code.line(format!("if {} is not None:", expr));
+                    let statement = self.write(&t, number, &expr, "writer");
+                    code.line(format!("    {}", statement));
+                }
+                Label::Repeated => {
+                    code.line(format!("for value in {}:", expr));
+                    let statement = self.write(&t, number, "value", "writer");
+                    code.line(format!("    {}", statement));
+                }
+                Label::Packed => {
+                    code.line(format!("if {}:", expr));
+                    code.depth += 1;
+                    code.line("packed = _protos_runtime.Writer()");
+                    code.line(format!("for value in {}:", expr));
+                    code.line(format!("    {}", Self::write_value(&t, "value", "packed")));
+                    code.line(format!("writer.tag({}, 2).bytes(packed.finish())", number));
+                    code.depth -= 1;
+                }
+                Label::Map => {
+                    let Type::Map(key, value) = &t else {
+                        unreachable!()
+                    };
+                    code.line(format!("for key, value in {}.items():", expr));
+                    code.depth += 1;
+                    code.line("entry = _protos_runtime.Writer()");
+                    let statement = self.write(key, 1, "key", "entry");
+                    code.line(statement);
+                    let statement = self.write(value, 2, "value", "entry");
+                    code.line(statement);
+                    code.line(format!("writer.tag({}, 2).bytes(entry.finish())", number));
+                    code.depth -= 1;
+                }
+            }
+        }
+        code.line("return writer.finish()");
+        code.depth -= 1;
+    }
+
+    fn decode(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        name: &str,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        let members = members(message, fields);
+        code.line("@classmethod");
+        code.line(format!("def decode(cls, data: bytes) -> {}:", name));
+        code.depth += 1;
+        code.line("message = cls()");
+        code.line("reader = _protos_runtime.Reader(data)");
+        code.line("while not reader.done:");
+        code.depth += 1;
+        code.line("tag = reader.uint32()");
+        if members.is_empty() {
+            code.line("reader.skip(tag & 7)");
+        } else {
+            code.line("number = tag >> 3");
+        }
+        for (i, (number, oneof, field, label)) in members.iter().enumerate() {
+            let t = field.field_type();
+            let property = format!("message.{}", ident(field.name()));
+            code.line(format!(
+                "{} number == {}:",
+                if i == 0 { "if" } else { "elif" },
+                number
+            ));
+            code.depth += 1;
+            match label {
+                Label::Implicit | Label::Optional => {
+                    let read = self.read(&t, "reader");
+                    code.line(format!("{} = {}", property, read));
+                    // Setting a oneof member clears the others.
+                    for other in oneof.iter().flat_map(|oneof| &oneof.fields) {
+                        if other.name() != field.name() {
+                            code.line(format!("message.{} = None", ident(other.name())));
+                        }
+                    }
+                }
+                Label::Repeated | Label::Packed => {
+                    let read = self.read(&t, "reader");
+                    if is_packable(&t) {
+                        // Parsers accept either encoding of packable fields.
+                        code.line("if tag & 7 == 2:");
+                        code.line("    end = reader.uint32() + reader.pos");
+                        code.line("    while reader.pos < end:");
+                        code.line(format!("        {}.append({})", property, read));
+                        code.line("else:");
+                        code.line(format!("    {}.append({})", property, read));
+                    } else {
+                        code.line(format!("{}.append({})", property, read));
+                    }
+                }
+                Label::Map => {
+                    let Type::Map(key, value) = &t else {
+                        unreachable!()
+                    };
+                    code.line("entry = _protos_runtime.Reader(reader.bytes())");
+                    let key_default = self.default(key);
+                    let value_default = self.default(value);
+                    code.line(format!("key, value = {}, {}", key_default, value_default));
+                    code.line("while not entry.done:");
+                    code.depth += 1;
+                    code.line("entry_tag = entry.uint32()");
+                    code.line("if entry_tag >> 3 == 1:");
+                    code.line(format!("    key = {}", self.read(key, "entry")));
+                    code.line("elif entry_tag >> 3 == 2:");
+                    code.line(format!("    value = {}", self.read(value, "entry")));
+                    code.line("else:");
+                    code.line("    entry.skip(entry_tag & 7)");
+                    code.depth -= 1;
+                    code.line(format!("{}[key] = value", property));
+                }
+            }
+            code.depth -= 1;
+        }
+        if !members.is_empty() {
+            code.line("else:");
+            code.line("    reader.skip(tag & 7)");
+        }
+        code.depth -= 1;
+        code.line("return message");
+        code.depth -= 1;
+    }
+
+    fn enumeration(&mut self, code: &mut Code, context: &Context, e: &Enum) {
+        code.line(format!("class {}(enum.IntEnum):", ident(&e.name)));
+        code.depth += 1;
+        if let Some(comment) = context.comment(&[]) {
+            docstring(code, comment);
+            code.line("");
+        }
+        // Later values with the same number become aliases of the first.
+        for (i, value) in e.fields.iter().enumerate() {
+            code.comment("#", context.comment(&[PathSegment::EnumValue(i)]));
+            code.line(format!(
+                "{} = {}",
+                ident(&value.name),
+                value.position as i32
+            ));
+        }
+        code.depth -= 1;
+    }
+}
+
+/// The fields and oneof members of `message` in number order, the order
+/// protoc writes them in.
+fn members<'a>(
+    message: &'a Message,
+    fields: &[(usize, &'a MessageField, Label)],
+) -> Vec<(u32, Option<&'a Oneof>, &'a MessageField, Label)> {
+    let mut members: Vec<_> = fields
+        .iter()
+        .map(|(_, field, label)| (field.position(), None, *field, *label))
+        .chain(message.oneofs.iter().flat_map(|oneof| {
+            oneof
+                .fields
+                .iter()
+                .map(move |field| (field.position(), Some(oneof), field, Label::Optional))
+        }))
+        .collect();
+    members.sort_by_key(|(number, ..)| *number);
+    members
+}
+
+fn has_enums(message: &Message) -> bool {
+    !message.enums.is_empty() || message.messages.iter().any(has_enums)
+}
+
+fn docstring(code: &mut Code, comment: &str) {
+    let lines: Vec<&str> = comment.trim_end().lines().map(str::trim).collect();
+    match lines.as_slice() {
+        [line] => code.line(format!("\"\"\"{}\"\"\"", line)),
+        _ => {
+            code.line(format!(
+                "\"\"\"{}",
+                lines.first().copied().unwrap_or_default()
+            ));
+            for line in &lines[1..] {
+                code.line(*line);
+            }
+            code.line("\"\"\"");
+        }
+    }
+}
+
+/// The name a module imports the module generated for `file` as.
+fn module_alias(file: &str) -> String {
+    format!("_{}", module_path(file).replace('.', "_"))
+}
+
+const KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// A Python identifier for a schema name, escaping keywords.
+fn ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+    use crate::wire::WireMessage;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    // The Rust generator's output for the same schema, whose encoder the
+    // Python output is checked against.
+    #[allow(dead_code)]
+    mod demo {
+        include!("../../tests/rust/demo.rs");
+    }
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("protos-py-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["demo.proto"]);
+        let expected = std::fs::read_to_string("tests/python/demo.py").unwrap();
+        assert_eq!(generated["demo.py"], expected);
+        assert_eq!(generated["protos_runtime.py"], RUNTIME);
+    }
+
+    #[test]
+    fn round_trips_against_the_rust_encoder() {
+        let dir = temp_dir("round-trip");
+        for (name, code) in generate(&load(), &["demo.proto"]) {
+            std::fs::write(dir.join(name), code).unwrap();
+        }
+        let address = demo::person::Address {
+            city: "Paris".to_string(),
+            owner: Some(Box::new(demo::Person {
+                name: "Owner".to_string(),
+                ..Default::default()
+            })),
+        };
+        let person = demo::Person {
+            name: "Ada".to_string(),
+            id: -7,
+            emails: vec!["ada@example.com".to_string(), String::new()],
+            scores: [("chess".to_string(), -3)].into(),
+            kind: 9,
+            contact: Some(demo::person::Contact::Office(Box::new(address.clone()))),
+            height: Some(0.5),
+            lucky: vec![7, u32::MAX],
+            counts: vec![1, i64::MIN],
+            photo: vec![0, 255],
+            friends: vec![demo::Person::default()],
+            homes: [(-1, address)].into(),
+            active: true,
+            ..Default::default()
+        };
+        std::fs::write(dir.join("in.bin"), person.encode_to_vec()).unwrap();
+        let script = "import demo\n\
+                      data = open('in.bin', 'rb').read()\n\
+                      person = demo.Person.decode(data)\n\
+                      assert person.which_contact() == 'office', person\n\
+                      assert person.kind == 9 and person.counts[1] == -2**63\n\
+                      open('out.bin', 'wb').write(person.encode())\n\
+                      made = demo.Person(name='Bo', kind=demo.Person.Kind.KIND_FRIEND, phone='555')\n\
+                      made.homes[4] = demo.Person.Address(city='Oslo')\n\
+                      open('made.bin', 'wb').write(made.encode())\n";
+        let output = match Command::new("python3")
+            .args(["-c", script])
+            .current_dir(&dir)
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!(
+                    "skipping round_trips_against_the_rust_encoder: python3 is not installed"
+                );
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let out = std::fs::read(dir.join("out.bin")).unwrap();
+        assert_eq!(demo::Person::decode(&out).unwrap(), person);
+        let made = demo::Person::decode(&std::fs::read(dir.join("made.bin")).unwrap()).unwrap();
+        assert_eq!(made.kind(), demo::person::Kind::Friend);
+        assert_eq!(
+            made.contact,
+            Some(demo::person::Contact::Phone("555".to_string()))
+        );
+        assert_eq!(made.homes[&4].city, "Oslo");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn imports_and_enum_defaults() {
+        let dir = temp_dir("imports");
+        std::fs::create_dir_all(dir.join("shop")).unwrap();
+        std::fs::write(
+            dir.join("shop/order.proto"),
+            "syntax = \"proto3\"; package shop; import \"common.proto\";
+             message Order {
+               common.Money total = 1; common.Currency currency = 2;
+               Line.State state = 3; repeated Line lines = 4; string from = 5;
+             }
+             message Line { enum State { NEW = 0; } State state = 1; }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common.proto"),
+            "syntax = \"proto3\"; package common;
+             message Money { int64 units = 1; } enum Currency { USD = 0; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["shop/order.proto"])
+            .unwrap();
+        let generated = generate(&set, &["shop/order.proto"]);
+        let order = &generated["shop/order.py"];
+        assert!(order.contains("\nimport common as _common\n"));
+        assert!(order.contains("    total: Optional[_common.Money] = None\n"));
+        assert!(order.contains("    currency: _common.Currency = _common.Currency.USD\n"));
+        assert!(order.contains(
+            "    state: Line.State = dataclasses.field(default_factory=lambda: Line.State.NEW)\n"
+        ));
+        assert!(order.contains("    state: Line.State = State.NEW\n"));
+        assert!(order.contains("    from_: str = \"\"\n"));
+        assert!(!generated.contains_key("common.py"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn well_known_types() {
+        let dir = temp_dir("well-known");
+        std::fs::write(
+            dir.join("event.proto"),
+            "syntax = \"proto3\"; package events;
+             import \"google/protobuf/any.proto\"; import \"google/protobuf/struct.proto\";
+             message Event { google.protobuf.Any body = 1; google.protobuf.Struct tags = 2; }
+             message Reader { Event event = 1; }
+             message Writer { Reader reader = 1; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["event.proto"])
+            .unwrap();
+        let generated = generate(&set, &["event.proto"]);
+        let names: Vec<&str> = generated.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            [
+                "event.py",
+                "protos_runtime.py",
+                "protos_well_known/google/protobuf/any.py",
+                "protos_well_known/google/protobuf/struct.py",
+            ]
+        );
+        assert!(generated["event.py"].contains(
+            "\nimport protos_well_known.google.protobuf.any as _protos_well_known_google_protobuf_any\n"
+        ));
+        for (name, content) in &generated {
+            std::fs::create_dir_all(dir.join(name).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+        let script = "import event\n\
+                      from protos_well_known.google.protobuf import struct\n\
+                      tags = struct.Struct()\n\
+                      tags.fields['a'] = struct.Value(string_value='b')\n\
+                      made = event.Event(tags=tags)\n\
+                      made.body = event._protos_well_known_google_protobuf_any.Any(type_url='x', value=b'1')\n\
+                      assert event.Event.decode(made.encode()) == made\n\
+                      wrapped = event.Writer(reader=event.Reader(event=made))\n\
+                      assert event.Writer.decode(wrapped.encode()) == wrapped\n";
+        let output = match Command::new("python3")
+            .args(["-c", script])
+            .current_dir(&dir)
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping well_known_types: python3 is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+"""Runtime for code generated by `protos gen --lang python`."""
+
+from __future__ import annotations
+
+import enum
+import struct
+from typing import TypeVar
+
+E = TypeVar("E", bound=enum.IntEnum)
+
+
+class DecodeError(ValueError):
+    pass
+
+
+class Writer:
+    def __init__(self) -> None:
+        self.buf = bytearray()
+
+    def tag(self, field_number: int, wire_type: int) -> Writer:
+        return self.uint64((field_number << 3) | wire_type)
+
+    def uint64(self, value: int) -> Writer:
+        value &= 0xFFFFFFFFFFFFFFFF
+        while value > 0x7F:
+            self.buf.append((value & 0x7F) | 0x80)
+            value >>= 7
+        self.buf.append(value)
+        return self
+
+    def uint32(self, value: int) -> Writer:
+        return self.uint64(value & 0xFFFFFFFF)
+
+    def int32(self, value: int) -> Writer:
+        return self.uint64(value)
+
+    def int64(self, value: int) -> Writer:
+        return self.uint64(value)
+
+    def sint32(self, value: int) -> Writer:
+        return self.uint64((value << 1) ^ (value >> 31))
+
+    def sint64(self, value: int) -> Writer:
+        return self.uint64((value << 1) ^ (value >> 63))
+
+    def bool(self, value: bool) -> Writer:
+        return self.uint64(1 if value else 0)
+
+    def fixed32(self, value: int) -> Writer:
+        return self._pack("<I", value)
+
+    def sfixed32(self, value: int) -> Writer:
+        return self._pack("<i", value)
+
+    def float(self, value: float) -> Writer:
+        return self._pack("<f", value)
+
+    def fixed64(self, value: int) -> Writer:
+        return self._pack("<Q", value)
+
+    def sfixed64(self, value: int) -> Writer:
+        return self._pack("<q", value)
+
+    def double(self, value: float) -> Writer:
+        return self._pack("<d", value)
+
+    def string(self, value: str) -> Writer:
+        return self.bytes(value.encode("utf-8"))
+
+    def bytes(self, value: bytes) -> Writer:
+        """Writes a length-prefixed byte string."""
+        self.uint64(len(value))
+        self.buf += value
+        return self
+
+    def finish(self) -> bytes:
+        return bytes(self.buf)
+
+    def _pack(self, fmt: str, value: int | float) -> Writer:
+        self.buf += struct.pack(fmt, value)
+        return self
+
+
+class Reader:
+    def __init__(self, data: bytes) -> None:
+        self.data = data
+        self.pos = 0
+
+    @property
+    def done(self) -> bool:
+        return self.pos >= len(self.data)
+
+    def uint64(self) -> int:
+        value = 0
+        for shift in range(0, 70, 7):
+            byte = self.data[self._advance(1)]
+            value |= (byte & 0x7F) << shift
+            if byte < 0x80:
+                return value & 0xFFFFFFFFFFFFFFFF
+        raise DecodeError("varint is longer than ten bytes")
+
+    def uint32(self) -> int:
+        return self.uint64() & 0xFFFFFFFF
+
+    def int32(self) -> int:
+        value = self.uint32()
+        return value - (1 << 32) if value >= 1 << 31 else value
+
+    def int64(self) -> int:
+        value = self.uint64()
+        return value - (1 << 64) if value >= 1 << 63 else value
+
+    def sint32(self) -> int:
+        value = self.uint32()
+        return (value >> 1) ^ -(value & 1)
+
+    def sint64(self) -> int:
+        value = self.uint64()
+        return (value >> 1) ^ -(value & 1)
+
+    def bool(self) -> bool:
+        return self.uint64() != 0
+
+    def fixed32(self) -> int:
+        return self._unpack("<I", 4)
+
+    def sfixed32(self) -> int:
+        return self._unpack("<i", 4)
+
+    def float(self) -> float:
+        return self._unpack("<f", 4)
+
+    def fixed64(self) -> int:
+        return self._unpack("<Q", 8)
+
+    def sfixed64(self) -> int:
+        return self._unpack("<q", 8)
+
+    def double(self) -> float:
+        return self._unpack("<d", 8)
+
+    def bytes(self) -> bytes:
+        length = self.uint64()
+        start = self._advance(length)
+        return bytes(self.data[start : start + length])
+
+    def string(self) -> str:
+        try:
+            return self.bytes().decode("utf-8")
+        except UnicodeDecodeError as err:
+            raise DecodeError("string is not UTF-8") from err
+
+    def skip(self, wire_type: int) -> None:
+        """Skips the value of a field whose tag has just been read."""
+        if wire_type == 0:
+            self.uint64()
+        elif wire_type == 1:
+            self._advance(8)
+        elif wire_type == 2:
+            self.bytes()
+        elif wire_type == 3:
+            while (tag := self.uint32()) & 7 != 4:
+                self.skip(tag & 7)
+        elif wire_type == 5:
+            self._advance(4)
+        else:
+            raise DecodeError(f"invalid wire type {wire_type}")
+
+    def _unpack(self, fmt: str, size: int):
+        return struct.unpack_from(fmt, self.data, self._advance(size))[0]
+
+    def _advance(self, count: int) -> int:
+        start = self.pos
+        if start + count > len(self.data):
+            raise DecodeError("unexpected end of input")
+        self.pos += count
+        return start
+
+
+def enum_value(cls: type[E], value: int) -> E | int:
+    """The member of `cls` numbered `value`, or `value` itself if the
+    schema doesn't name it, so that unknown values survive a round trip."""
+    try:
+        return cls(value)
+    except ValueError:
+        return value
@@ -0,0 +1,1169 @@
+//! Generates Rust types from linked schemas.
+//!
+//! Each message becomes a struct implementing
+//! [`WireMessage`](crate::wire::WireMessage), each enum an `i32`-backed
+//! enum, and each oneof an enum held in an `Option`. Types nested in a
+//! message live in a module named after it, and packages are expected to
+//! be mounted as nested modules, so `a.b.Msg` is `a::b::Msg`.
+//!
+//! Enum-typed fields are stored as `i32` so that values unknown to the
+//! schema survive a round trip; generated accessors convert them.
+
+use super::{declare_all, parameters, snake, upper_camel, Code, Context};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Generator settings. Settings that take paths apply to the types and
+/// fields those paths select: `.` selects everything, a fully qualified
+/// name such as `.pkg.Msg` selects that type and everything in it, and a
+/// name without the leading dot, such as `Msg.field`, matches by suffix.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The path generated code uses for this crate.
+    pub runtime: String,
+    /// Map fields stored as `BTreeMap` rather than `HashMap`.
+    pub btree_map: Vec<String>,
+    /// Bytes fields stored as `bytes::Bytes` rather than `Vec<u8>`.
+    pub bytes: Vec<String>,
+    /// Attributes added to the structs and enums of the selected types,
+    /// e.g. `#[derive(serde::Serialize)]`.
+    pub type_attributes: Vec<(String, String)>,
+    /// Attributes added to the selected fields and oneof variants.
+    pub field_attributes: Vec<(String, String)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            runtime: "::protos".to_string(),
+            btree_map: vec![],
+            bytes: vec![],
+            type_attributes: vec![],
+            field_attributes: vec![],
+        }
+    }
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters: `runtime=PATH`, and
+    /// `btree_map` and `bytes` either alone, for every field, or as
+    /// `btree_map=PATH`.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            let (key, value) = item.split_once('=').unwrap_or((item, "."));
+            match key {
+                "btree_map" => options.btree_map.push(value.to_string()),
+                "bytes" => options.bytes.push(value.to_string()),
+                "runtime" if value != "." => options.runtime = value.to_string(),
+                _ => return Err(format!("unknown rust generator parameter {}", item)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Whether `path` selects the type or field with fully qualified `name`.
+fn selects(path: &str, name: &str) -> bool {
+    match path.strip_prefix('.') {
+        Some("") => true,
+        Some(_) => {
+            name == path
+                || name
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        }
+        None => name
+            .strip_suffix(path)
+            .is_some_and(|rest| rest.ends_with('.')),
+    }
+}
+
+fn selected(paths: &[String], name: &str) -> bool {
+    paths.iter().any(|path| selects(path, name))
+}
+
+fn attributes<'a>(list: &'a [(String, String)], name: &'a str) -> impl Iterator<Item = &'a str> {
+    list.iter()
+        .filter(move |(path, _)| selects(path, name))
+        .map(|(_, attribute)| attribute.as_str())
+}
+
+/// Generates the named files, and the well-known types they use, grouped
+/// by package. Keys are the package name with a `.rs` suffix, or `_.rs`
+/// for files without a package; the well-known types are in
+/// `google.protobuf.rs`.
+pub fn generate(set: &FileSet, files: &[&str], options: &Options) -> BTreeMap<String, String> {
+    let generator = Generator::new(set, options);
+    let files = with_well_known(set, files);
+    let mut ret: BTreeMap<String, String> = BTreeMap::new();
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(file.name.as_str()))
+    {
+        let name = format!("{}.rs", file.package.as_deref().unwrap_or("_"));
+        let code = ret.entry(name).or_default();
+        if !code.is_empty() {
+            code.push('\n');
+        }
+        code.push_str(&generator.file(file));
+    }
+    ret
+}
+
+/// `files` and the bundled well-known files whose types they use, directly
+/// or through one another.
+fn with_well_known<'a>(set: &'a FileSet, files: &[&'a str]) -> BTreeSet<&'a str> {
+    fn used(message: &Message, types: &mut Vec<String>) {
+        let fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields));
+        for field in fields {
+            let t = match field.field_type() {
+                Type::Map(_, value) => *value,
+                t => t,
+            };
+            if let Type::Message(name) | Type::Enum(name) = t {
+                types.push(name);
+            }
+        }
+        for nested in &message.messages {
+            used(nested, types);
+        }
+    }
+
+    let declared = declare_all(set, str::to_string, ".");
+    let mut ret = BTreeSet::new();
+    let mut pending = files.to_vec();
+    while let Some(name) = pending.pop() {
+        let Some(file) = set.files.iter().find(|file| file.name == name) else {
+            continue;
+        };
+        if !ret.insert(file.name.as_str()) {
+            continue;
+        }
+        let mut types = vec![];
+        for message in &file.messages {
+            used(message, &mut types);
+        }
+        pending.extend(
+            set.files
+                .iter()
+                .filter(|file| well_known::FILES.iter().any(|(path, _)| *path == file.name))
+                .filter(|file| {
+                    types
+                        .iter()
+                        .any(|t| declared.get(t).is_some_and(|d| d.file == file.name))
+                })
+                .map(|file| file.name.as_str()),
+        );
+    }
+    ret
+}
+
+/// Where a message or enum is declared, relative to the package root.
+#[derive(Debug)]
+struct Declared {
+    modules: Vec<String>,
+    name: String,
+}
+
+struct Generator<'a> {
+    set: &'a FileSet,
+    options: &'a Options,
+    types: BTreeMap<String, Declared>,
+    /// The message types of each message's singular fields, which make a
+    /// struct recursive if they lead back to it.
+    embeds: BTreeMap<String, Vec<String>>,
+}
+
+/// How a field is stored and which `wire` helpers read and write it.
+#[derive(Debug)]
+enum Label {
+    /// A proto3 scalar without presence, omitted when it is the default.
+    Implicit,
+    Optional,
+    /// A singular message field.
+    Message {
+        boxed: bool,
+    },
+    Repeated,
+    Packed,
+    Map {
+        key: String,
+        value: String,
+    },
+}
+
+#[derive(Debug)]
+struct Field {
+    ident: String,
+    number: u32,
+    /// The Rust type of one value.
+    value_type: String,
+    /// The `wire::types` marker for one value.
+    codec: String,
+    label: Label,
+    /// The Rust path of the enum type, for enum fields.
+    enum_path: Option<String>,
+    attributes: Vec<String>,
+}
+
+/// A oneof member, stored as a variant of the oneof's enum.
+#[derive(Debug)]
+struct Variant {
+    name: String,
+    number: u32,
+    value_type: String,
+    codec: String,
+    boxed: bool,
+    attributes: Vec<String>,
+}
+
+#[derive(Debug)]
+struct OneofField {
+    ident: String,
+    /// The path of the oneof's enum from the message's scope.
+    path: String,
+    variants: Vec<Variant>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(set: &'a FileSet, options: &'a Options) -> Self {
+        let mut generator = Generator {
+            set,
+            options,
+            types: BTreeMap::new(),
+            embeds: BTreeMap::new(),
+        };
+        for file in &set.files {
+            let package = file.package.as_deref().unwrap_or("");
+            let modules: Vec<String> = package
+                .split('.')
+                .filter(|s| !s.is_empty())
+                .map(ident)
+                .collect();
+            let scope = if package.is_empty() {
+                String::new()
+            } else {
+                format!(".{}", package)
+            };
+            for message in &file.messages {
+                generator.declare_message(&scope, &modules, message);
+            }
+            for e in &file.enums {
+                generator.declare(&scope, &modules, &e.name);
+            }
+        }
+        generator
+    }
+
+    fn declare(&mut self, scope: &str, modules: &[String], name: &str) -> String {
+        let full_name = format!("{}.{}", scope, name);
+        let declared = Declared {
+            modules: modules.to_vec(),
+            name: type_ident(name),
+        };
+        self.types.insert(full_name.clone(), declared);
+        full_name
+    }
+
+    fn declare_message(&mut self, scope: &str, modules: &[String], message: &Message) {
+        let full_name = self.declare(scope, modules, &message.name);
+        let singular = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields))
+            .filter(|field| !field.is_repeated());
+        let embeds = singular
+            .filter_map(|field| match field.field_type() {
+                Type::Message(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        self.embeds.insert(full_name.clone(), embeds);
+
+        let mut inner = modules.to_vec();
+        inner.push(ident(&snake(&message.name)));
+        for nested in &message.messages {
+            self.declare_message(&full_name, &inner, nested);
+        }
+        for e in &message.enums {
+            self.declare(&full_name, &inner, &e.name);
+        }
+    }
+
+    /// Whether a field of type `target` in `container` must be boxed to
+    /// give the struct a finite size.
+    fn is_recursive(&self, container: &str, target: &str) -> bool {
+        let mut stack = vec![target];
+        let mut seen = BTreeSet::new();
+        while let Some(name) = stack.pop() {
+            if name == container {
+                return true;
+            }
+            if seen.insert(name) {
+                stack.extend(
+                    self.embeds
+                        .get(name)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                );
+            }
+        }
+        false
+    }
+
+    /// The path of a type from code in module `scope`.
+    fn path(&self, scope: &[String], full_name: &str) -> String {
+        let declared = &self.types[full_name];
+        let common = scope
+            .iter()
+            .zip(&declared.modules)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut parts = vec!["super".to_string(); scope.len() - common];
+        parts.extend(declared.modules[common..].iter().cloned());
+        parts.push(declared.name.clone());
+        parts.join("::")
+    }
+
+    fn wire(&self) -> String {
+        format!("{}::wire", self.options.runtime)
+    }
+
+    fn map_type(&self, field: &str) -> &'static str {
+        if selected(&self.options.btree_map, field) {
+            "::std::collections::BTreeMap"
+        } else {
+            "::std::collections::HashMap"
+        }
+    }
+
+    /// The Rust type and `wire::types` marker for one value of `t` in
+    /// `field`.
+    fn value_type(&self, scope: &[String], t: &Type, field: &str) -> (String, String) {
+        let marker = |name: &str| format!("{}::types::{}", self.wire(), name);
+        let (rust, codec) = match t {
+            Type::String => ("::std::string::String", "String"),
+            Type::Bytes if selected(&self.options.bytes, field) => {
+                return (
+                    "::bytes::Bytes".to_string(),
+                    marker("Bytes<::bytes::Bytes>"),
+                );
+            }
+            Type::Bytes => ("::std::vec::Vec<u8>", "Bytes"),
+            Type::Int32 => ("i32", "Int32"),
+            Type::Int64 => ("i64", "Int64"),
+            Type::Uint32 => ("u32", "Uint32"),
+            Type::Uint64 => ("u64", "Uint64"),
+            Type::Sint32 => ("i32", "Sint32"),
+            Type::Sint64 => ("i64", "Sint64"),
+            Type::Fixed32 => ("u32", "Fixed32"),
+            Type::Fixed64 => ("u64", "Fixed64"),
+            Type::Sfixed32 => ("i32", "Sfixed32"),
+            Type::Sfixed64 => ("i64", "Sfixed64"),
+            Type::Bool => ("bool", "Bool"),
+            Type::Float => ("f32", "Float"),
+            Type::Double => ("f64", "Double"),
+            Type::Enum(_) => ("i32", "Enum"),
+            Type::Message(name) => {
+                let path = self.path(scope, name);
+                return (path.clone(), format!("{}<{}>", marker("Msg"), path));
+            }
+            Type::Named(name) => panic!("type {} is not linked", name),
+            Type::Map(..) => unreachable!("map values are not maps"),
+        };
+        (rust.to_string(), marker(codec))
+    }
+
+    fn field(
+        &self,
+        scope: &[String],
+        container: &str,
+        field: &MessageField,
+        options: Option<&Vec<ProtoOption>>,
+        proto3: bool,
+    ) -> Field {
+        let t = field.field_type();
+        let full_name = format!("{}.{}", container, field.name());
+        let enum_path = match &t {
+            Type::Enum(name) => Some(self.path(scope, name)),
+            _ => None,
+        };
+        let label = match (&t, field.frequency()) {
+            (Type::Map(key, value), _) => Label::Map {
+                key: self.value_type(scope, key, &full_name).1,
+                value: self.value_type(scope, value, &full_name).1,
+            },
+            (_, Some(Frequency::Repeated)) => {
+                let packed = options
+                    .into_iter()
+                    .flatten()
+                    .find(|option| option.name == "packed")
+                    .map_or(proto3, |option| option.value == Constant::Bool(true));
+                let packable = !matches!(t, Type::String | Type::Bytes | Type::Message(_));
+                if packed && packable {
+                    Label::Packed
+                } else {
+                    Label::Repeated
+                }
+            }
+            (Type::Message(name), _) => Label::Message {
+                boxed: self.is_recursive(container, name),
+            },
+            (_, None) if proto3 => Label::Implicit,
+            _ => Label::Optional,
+        };
+        let (value_type, codec) = match &t {
+            Type::Map(key, value) => {
+                let key = self.value_type(scope, key, &full_name).0;
+                let value = self.value_type(scope, value, &full_name).0;
+                let map = self.map_type(&full_name);
+                (format!("{}<{}, {}>", map, key, value), String::new())
+            }
+            t => self.value_type(scope, t, &full_name),
+        };
+        Field {
+            ident: ident(field.name()),
+            number: field.position(),
+            value_type,
+            codec,
+            label,
+            enum_path,
+            attributes: attributes(&self.options.field_attributes, &full_name)
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    fn file(&self, file: &ProtoFile) -> String {
+        let mut code = Code::new("    ");
+        code.line(format!(
+            "// @generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        let package = file.package.as_deref().unwrap_or("");
+        let scope: Vec<String> = package
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .map(ident)
+            .collect();
+        let full_scope = if package.is_empty() {
+            String::new()
+        } else {
+            format!(".{}", package)
+        };
+        let mut context = Context::new(self.set, file);
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            self.message(&mut code, &mut context, &scope, &full_scope, message);
+            context.path.pop();
+        }
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            let full_name = format!("{}.{}", full_scope, e.name);
+            self.enumeration(&mut code, &context, e, &full_name);
+            context.path.pop();
+        }
+        code.text
+    }
+
+    fn message(
+        &self,
+        code: &mut Code,
+        context: &mut Context,
+        scope: &[String],
+        parent: &str,
+        message: &Message,
+    ) {
+        let full_name = format!("{}.{}", parent, message.name);
+        let name = type_ident(&message.name);
+        let module = ident(&snake(&message.name));
+        let mut inner = scope.to_vec();
+        inner.push(module.clone());
+
+        let fields: Vec<(usize, Field)> = message
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let options = message.field_options.get(&field.position());
+                (
+                    i,
+                    self.field(scope, &full_name, field, options, context.proto3),
+                )
+            })
+            .collect();
+        let oneofs: Vec<OneofField> = message
+            .oneofs
+            .iter()
+            .map(|oneof| OneofField {
+                ident: ident(&oneof.name),
+                path: format!("{}::{}", module, type_ident(&oneof.name)),
+                variants: oneof
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let t = field.field_type();
+                        let field_name = format!("{}.{}", full_name, field.name());
+                        // The enum is declared in the message's module but
+                        // encoded by the message's impl, outside it.
+                        let value_type = self.value_type(&inner, &t, &field_name).0;
+                        let codec = self.value_type(scope, &t, &field_name).1;
+                        Variant {
+                            attributes: attributes(&self.options.field_attributes, &field_name)
+                                .map(str::to_string)
+                                .collect(),
+                            name: type_ident(field.name()),
+                            number: field.position(),
+                            value_type,
+                            codec,
+                            boxed: matches!(&t, Type::Message(name) if self.is_recursive(&full_name, name)),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        code.line("");
+        code.comment("///", context.comment(&[]));
+        code.line("#[derive(Debug, Clone, PartialEq, Default)]");
+        self.type_attributes(code, &full_name);
+        if fields.is_empty() && oneofs.is_empty() {
+            code.line(format!("pub struct {} {{}}", name));
+        } else {
+            self.struct_fields(code, context, &name, &fields, &oneofs);
+        }
+
+        self.accessors(code, &name, &fields);
+        self.implementation(code, &name, &fields, &oneofs);
+
+        if message.messages.is_empty() && message.enums.is_empty() && oneofs.is_empty() {
+            return;
+        }
+        code.line("");
+        code.line(format!("/// Types nested in `{}`.", name));
+        code.open(format!("pub mod {}", module));
+        let mut first = true;
+        for (i, oneof) in oneofs.iter().enumerate() {
+            if !std::mem::take(&mut first) {
+                code.line("");
+            }
+            context.path.push(PathSegment::Oneof(i));
+            let oneof_name = format!("{}.{}", full_name, message.oneofs[i].name);
+            self.oneof(code, context, &message.oneofs[i], oneof, &oneof_name);
+            context.path.pop();
+        }
+        for (i, nested) in message.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            self.message(code, context, &inner, &full_name, nested);
+            context.path.pop();
+        }
+        for (i, e) in message.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            self.enumeration(code, context, e, &format!("{}.{}", full_name, e.name));
+            context.path.pop();
+        }
+        code.close();
+    }
+
+    fn type_attributes(&self, code: &mut Code, full_name: &str) {
+        for attribute in attributes(&self.options.type_attributes, full_name) {
+            code.line(attribute);
+        }
+    }
+
+    fn struct_fields(
+        &self,
+        code: &mut Code,
+        context: &Context,
+        name: &str,
+        fields: &[(usize, Field)],
+        oneofs: &[OneofField],
+    ) {
+        code.open(format!("pub struct {}", name));
+        for (i, field) in fields {
+            code.comment("///", context.comment(&[PathSegment::Field(*i)]));
+            let t = &field.value_type;
+            let declared = match field.label {
+                Label::Implicit | Label::Map { .. } => t.clone(),
+                Label::Optional | Label::Message { boxed: false } => {
+                    format!("::core::option::Option<{}>", t)
+                }
+                Label::Message { boxed: true } => {
+                    format!("::core::option::Option<::std::boxed::Box<{}>>", t)
+                }
+                Label::Repeated | Label::Packed => format!("::std::vec::Vec<{}>", t),
+            };
+            for attribute in &field.attributes {
+                code.line(attribute);
+            }
+            code.line(format!("pub {}: {},", field.ident, declared));
+        }
+        for (i, oneof) in oneofs.iter().enumerate() {
+            code.comment("///", context.comment(&[PathSegment::Oneof(i)]));
+            code.line(format!(
+                "pub {}: ::core::option::Option<{}>,",
+                oneof.ident, oneof.path
+            ));
+        }
+        code.close();
+    }
+
+    /// Typed getters and setters for enum fields.
+    fn accessors(&self, code: &mut Code, name: &str, fields: &[(usize, Field)]) {
+        let enums: Vec<(&Field, &String)> = fields
+            .iter()
+            .filter_map(|(_, field)| Some((field, field.enum_path.as_ref()?)))
+            .filter(|(field, _)| matches!(field.label, Label::Implicit | Label::Optional))
+            .collect();
+        if enums.is_empty() {
+            return;
+        }
+        code.line("");
+        code.open(format!("impl {}", name));
+        for (i, (field, path)) in enums.into_iter().enumerate() {
+            if i > 0 {
+                code.line("");
+            }
+            let bare = field.ident.trim_start_matches("r#");
+            code.line(format!(
+                "/// `{}` as a `{}`, or the enum's default if the value is unknown.",
+                bare, path
+            ));
+            code.open(format!("pub fn {}(&self) -> {}", field.ident, path));
+            match field.label {
+                Label::Implicit => code.line(format!(
+                    "<{} as ::core::convert::TryFrom<i32>>::try_from(self.{}).unwrap_or_default()",
+                    path, field.ident
+                )),
+                _ => code.line(format!(
+                    "self.{}.and_then(|value| <{} as ::core::convert::TryFrom<i32>>::try_from(value).ok()).unwrap_or_default()",
+                    field.ident, path
+                )),
+            }
+            code.close();
+            code.line("");
+            code.open(format!("pub fn set_{}(&mut self, value: {})", bare, path));
+            match field.label {
+                Label::Implicit => code.line(format!("self.{} = value as i32;", field.ident)),
+                _ => code.line(format!(
+                    "self.{} = ::core::option::Option::Some(value as i32);",
+                    field.ident
+                )),
+            }
+            code.close();
+        }
+        code.close();
+    }
+
+    fn implementation(
+        &self,
+        code: &mut Code,
+        name: &str,
+        fields: &[(usize, Field)],
+        oneofs: &[OneofField],
+    ) {
+        let wire = self.wire();
+        // Fields are written in number order, as protoc does.
+        let mut members: Vec<(u32, Member)> = fields
+            .iter()
+            .map(|(_, field)| (field.number, Member::Field(field)))
+            .chain(oneofs.iter().flat_map(|oneof| {
+                oneof
+                    .variants
+                    .iter()
+                    .map(move |variant| (variant.number, Member::Variant(oneof, variant)))
+            }))
+            .collect();
+        members.sort_by_key(|(number, _)| *number);
+
+        code.line("");
+        code.open(format!("impl {}::WireMessage for {}", wire, name));
+        if members.is_empty() {
+            code.line("fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}");
+        } else {
+            code.open("fn encode(&self, buf: &mut ::std::vec::Vec<u8>)");
+            for (_, member) in &members {
+                member.write(code, &wire, "encode", "buf");
+            }
+            code.close();
+        }
+        code.line("");
+        code.open(format!(
+            "fn merge_field(&mut self, field_number: u32, wire_type: {0}::WireType, buf: &mut &[u8], {1}: usize) -> ::core::result::Result<(), {0}::Error>",
+            wire,
+            if members.is_empty() { "_depth" } else { "depth" }
+        ));
+        if members.is_empty() {
+            code.line(format!(
+                "{}::skip_field(field_number, wire_type, buf)",
+                wire
+            ));
+        } else {
+            code.open("match field_number");
+            for (_, member) in &members {
+                member.merge(code, &wire);
+            }
+            code.line(format!(
+                "_ => {}::skip_field(field_number, wire_type, buf)?,",
+                wire
+            ));
+            code.close();
+            code.line("::core::result::Result::Ok(())");
+        }
+        code.close();
+        code.line("");
+        code.open("fn encoded_len(&self) -> usize");
+        if members.is_empty() {
+            code.line("0");
+        } else {
+            code.line("let mut len = 0;");
+            for (_, member) in &members {
+                member.write(code, &wire, "len", "");
+            }
+            code.line("len");
+        }
+        code.close();
+        code.close();
+    }
+
+    fn oneof(
+        &self,
+        code: &mut Code,
+        context: &Context,
+        oneof: &Oneof,
+        generated: &OneofField,
+        full_name: &str,
+    ) {
+        code.comment("///", context.comment(&[]));
+        code.line("#[derive(Debug, Clone, PartialEq)]");
+        self.type_attributes(code, full_name);
+        code.open(format!("pub enum {}", type_ident(&oneof.name)));
+        for (i, variant) in generated.variants.iter().enumerate() {
+            code.comment("///", context.comment(&[PathSegment::Field(i)]));
+            for attribute in &variant.attributes {
+                code.line(attribute);
+            }
+            if variant.boxed {
+                code.line(format!(
+                    "{}(::std::boxed::Box<{}>),",
+                    variant.name, variant.value_type
+                ));
+            } else {
+                code.line(format!("{}({}),", variant.name, variant.value_type));
+            }
+        }
+        code.close();
+    }
+
+    fn enumeration(&self, code: &mut Code, context: &Context, e: &Enum, full_name: &str) {
+        let name = type_ident(&e.name);
+        let prefix = format!("{}_", snake(&e.name).to_uppercase());
+        let variant_name = |value: &str| {
+            let stripped = value
+                .strip_prefix(&prefix)
+                .filter(|rest| rest.starts_with(|c: char| c.is_ascii_alphabetic()));
+            type_ident(stripped.unwrap_or(value))
+        };
+        // The first value with each number is the variant; later ones are
+        // aliases, which Rust enums cannot express as variants.
+        let mut variants: BTreeMap<u32, String> = BTreeMap::new();
+        let mut canonical = vec![];
+        for value in &e.fields {
+            let variant = variants
+                .entry(value.position)
+                .or_insert_with(|| variant_name(&value.name))
+                .clone();
+            canonical.push(variant);
+        }
+
+        code.line("");
+        code.comment("///", context.comment(&[]));
+        code.line("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]");
+        self.type_attributes(code, full_name);
+        code.line("#[repr(i32)]");
+        code.open(format!("pub enum {}", name));
+        let mut declared = BTreeSet::new();
+        for (i, (value, variant)) in e.fields.iter().zip(&canonical).enumerate() {
+            if !declared.insert(value.position) {
+                continue;
+            }
+            code.comment("///", context.comment(&[PathSegment::EnumValue(i)]));
+            if i == 0 {
+                code.line("#[default]");
+            }
+            code.line(format!("{} = {},", variant, value.position as i32));
+        }
+        code.close();
+
+        code.line("");
+        code.open(format!("impl {}", name));
+        for (i, (value, variant)) in e.fields.iter().zip(&canonical).enumerate() {
+            if variant_name(&value.name) != *variant {
+                code.comment("///", context.comment(&[PathSegment::EnumValue(i)]));
+                code.line(format!(
+                    "pub const {}: {} = {}::{};",
+                    value.name, name, name, variant
+                ));
+                code.line("");
+            }
+        }
+        code.line("/// The value's name in the schema.");
+        code.open("pub fn as_str_name(&self) -> &'static str");
+        code.open("match self");
+        let mut declared = BTreeSet::new();
+        for (value, variant) in e.fields.iter().zip(&canonical) {
+            if declared.insert(value.position) {
+                code.line(format!("{}::{} => \"{}\",", name, variant, value.name));
+            }
+        }
+        code.close();
+        code.close();
+        code.line("");
+        code.open("pub fn from_str_name(name: &str) -> ::core::option::Option<Self>");
+        code.open("match name");
+        for (value, variant) in e.fields.iter().zip(&canonical) {
+            code.line(format!(
+                "\"{}\" => ::core::option::Option::Some({}::{}),",
+                value.name, name, variant
+            ));
+        }
+        code.line("_ => ::core::option::Option::None,");
+        code.close();
+        code.close();
+        code.close();
+
+        code.line("");
+        code.open(format!("impl ::core::convert::From<{}> for i32", name));
+        code.open(format!("fn from(value: {}) -> i32", name));
+        code.line("value as i32");
+        code.close();
+        code.close();
+
+        code.line("");
+        code.line("/// Values the schema doesn't name are returned as the error.");
+        code.open(format!("impl ::core::convert::TryFrom<i32> for {}", name));
+        code.line("type Error = i32;");
+        code.line("");
+        code.open("fn try_from(value: i32) -> ::core::result::Result<Self, i32>");
+        code.open("match value");
+        for (number, variant) in &variants {
+            code.line(format!(
+                "{} => ::core::result::Result::Ok({}::{}),",
+                *number as i32, name, variant
+            ));
+        }
+        code.line("_ => ::core::result::Result::Err(value),");
+        code.close();
+        code.close();
+        code.close();
+    }
+}
+
+enum Member<'a> {
+    Field(&'a Field),
+    Variant(&'a OneofField, &'a Variant),
+}
+
+impl Member<'_> {
+    /// Writes a statement of `encode` (`action` "encode") or of
+    /// `encoded_len` (`action` "len").
+    fn write(&self, code: &mut Code, wire: &str, action: &str, buf: &str) {
+        let call = |function: &str, args: String| match action {
+            "encode" => format!("{}::encode_{}({}, {});", wire, function, args, buf),
+            _ => format!("len += {}::{}_len({});", wire, function, args),
+        };
+        match self {
+            Member::Field(field) => {
+                let f = &field.ident;
+                let n = field.number;
+                let c = &field.codec;
+                match &field.label {
+                    Label::Implicit => {
+                        let condition = match field.value_type.as_str() {
+                            "::std::string::String" | "::std::vec::Vec<u8>" | "::bytes::Bytes" => {
+                                format!("!self.{}.is_empty()", f)
+                            }
+                            "bool" => format!("self.{}", f),
+                            "f32" | "f64" => format!("self.{} != 0.0", f),
+                            _ => format!("self.{} != 0", f),
+                        };
+                        code.open(format!("if {}", condition));
+                        code.line(with_codec(call("field", format!("{}, &self.{}", n, f)), c));
+                        code.close();
+                    }
+                    Label::Optional | Label::Message { .. } => {
+                        code.open(format!(
+                            "if let ::core::option::Option::Some(value) = &self.{}",
+                            f
+                        ));
+                        code.line(with_codec(call("field", format!("{}, value", n)), c));
+                        code.close();
+                    }
+                    Label::Repeated => code.line(with_codec(
+                        call("repeated", format!("{}, &self.{}", n, f)),
+                        c,
+                    )),
+                    Label::Packed => {
+                        code.line(with_codec(call("packed", format!("{}, &self.{}", n, f)), c))
+                    }
+                    Label::Map { key, value } => code.line(with_codec(
+                        call("map", format!("{}, &self.{}", n, f)),
+                        &format!("{}, {}", key, value),
+                    )),
+                }
+            }
+            Member::Variant(oneof, variant) => {
+                code.open(format!(
+                    "if let ::core::option::Option::Some({}::{}(value)) = &self.{}",
+                    oneof.path, variant.name, oneof.ident
+                ));
+                code.line(with_codec(
+                    call("field", format!("{}, value", variant.number)),
+                    &variant.codec,
+                ));
+                code.close();
+            }
+        }
+    }
+
+    /// Writes the arm of `merge_field` for this member.
+    fn merge(&self, code: &mut Code, wire: &str) {
+        match self {
+            Member::Field(field) => {
+                let (f, n, c) = (&field.ident, field.number, &field.codec);
+                let decode = format!("{}::decode_field::<{}>(wire_type, buf, depth)?", wire, c);
+                match &field.label {
+                    Label::Implicit => code.line(format!("{} => self.{} = {},", n, f, decode)),
+                    Label::Optional => code.line(format!(
+                        "{} => self.{} = ::core::option::Option::Some({}),",
+                        n, f, decode
+                    )),
+                    Label::Message { boxed } => code.line(format!(
+                        "{} => {}::merge_message(wire_type, {}self.{}.get_or_insert_with(::core::default::Default::default), buf, depth)?,",
+                        n,
+                        wire,
+                        if *boxed { "&mut **" } else { "" },
+                        f
+                    )),
+                    Label::Repeated | Label::Packed => code.line(format!(
+                        "{} => {}::merge_repeated::<{}>(wire_type, &mut self.{}, buf, depth)?,",
+                        n, wire, c, f
+                    )),
+                    Label::Map { key, value } => {
+                        code.open(format!("{} =>", n));
+                        code.line(format!(
+                            "let (key, value) = {}::decode_map_entry::<{}, {}>(wire_type, buf, depth)?;",
+                            wire, key, value
+                        ));
+                        code.line(format!("self.{}.insert(key, value);", f));
+                        code.close();
+                    }
+                }
+            }
+            Member::Variant(oneof, variant) => {
+                let mut decode = format!(
+                    "{}::decode_field::<{}>(wire_type, buf, depth)?",
+                    wire, variant.codec
+                );
+                if variant.boxed {
+                    decode = format!("::std::boxed::Box::new({})", decode);
+                }
+                code.line(format!(
+                    "{} => self.{} = ::core::option::Option::Some({}::{}({})),",
+                    variant.number, oneof.ident, oneof.path, variant.name, decode
+                ));
+            }
+        }
+    }
+}
+
+/// Adds the turbofish of `wire` helper calls in `statement`.
+fn with_codec(statement: String, codec: &str) -> String {
+    statement.replacen('(', &format!("::<{}>(", codec), 1)
+}
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// A Rust type or variant name for a schema name, in `UpperCamelCase`.
+fn type_ident(name: &str) -> String {
+    match upper_camel(name) {
+        name if name == "Self" => "Self_".to_string(),
+        name => name,
+    }
+}
+
+/// A Rust identifier for a schema name, escaping keywords.
+fn ident(name: &str) -> String {
+    match name {
+        "self" | "Self" | "super" | "crate" => format!("{}_", name),
+        _ if KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic::DynamicMessage;
+    use crate::resolver::Resolver;
+    use crate::wire::WireMessage;
+    use std::sync::Arc;
+
+    // Generated from tests/rust/demo.proto by `golden` below.
+    mod demo {
+        include!("../../tests/rust/demo.rs");
+    }
+
+    // Generated from tests/rust/names.proto by `golden` below.
+    mod names {
+        include!("../../tests/rust/names.rs");
+    }
+
+    use demo::{person, Color, Person};
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap()
+    }
+
+    fn person() -> Person {
+        let address = person::Address {
+            city: "Paris".to_string(),
+            owner: Some(Box::new(Person {
+                name: "Owner".to_string(),
+                ..Default::default()
+            })),
+        };
+        let mut person = Person {
+            name: "Ada".to_string(),
+            id: -7,
+            emails: vec!["ada@example.com".to_string(), String::new()],
+            scores: [("chess".to_string(), -3)].into(),
+            address: Some(Box::new(address.clone())),
+            contact: Some(person::Contact::Office(Box::new(address.clone()))),
+            height: Some(0.0),
+            lucky: vec![7, 13],
+            counts: vec![1, -1],
+            photo: vec![0, 255],
+            friends: vec![
+                Person::default(),
+                Person {
+                    id: 2,
+                    ..Default::default()
+                },
+            ],
+            homes: [(1, address)].into(),
+            active: true,
+            r#type: "keyword".to_string(),
+            ..Default::default()
+        };
+        person.set_kind(person::Kind::Family);
+        person
+    }
+
+    #[test]
+    fn golden() {
+        let options = Options {
+            runtime: "crate".to_string(),
+            ..Options::default()
+        };
+        let generated = generate(&load(), &["demo.proto"], &options);
+        let expected = std::fs::read_to_string("tests/rust/demo.rs").unwrap();
+        assert_eq!(generated["demo.rs"], expected);
+
+        let set = Resolver::new()
+            .include("tests/rust")
+            .load(&["names.proto"])
+            .unwrap();
+        let generated = generate(&set, &["names.proto"], &options);
+        let expected = std::fs::read_to_string("tests/rust/names.rs").unwrap();
+        assert_eq!(generated["names.rs"], expected);
+    }
+
+    /// Types named like the prelude don't shadow it in generated code.
+    #[test]
+    fn shadowed_names() {
+        let message = names::Result {
+            value: "v".to_string(),
+            items: vec![names::Vec {}],
+            self_: Some(names::Self_ {
+                children: [("a".to_string(), names::Self_::default())].into(),
+                ..Default::default()
+            }),
+            crate_: Some(names::result::Crate::Super(vec![0xff])),
+            ..Default::default()
+        };
+        let bytes = message.encode_to_vec();
+        assert_eq!(names::Result::decode(&bytes).unwrap(), message);
+        assert_eq!(names::Ok::try_from(0), Ok(names::Ok::Self_));
+    }
+
+    #[test]
+    fn round_trips_through_dynamic_messages() {
+        let person = person();
+        let bytes = person.encode_to_vec();
+        assert_eq!(bytes.len(), person.encoded_len());
+        assert_eq!(Person::decode(&bytes).unwrap(), person);
+
+        let mut dynamic = DynamicMessage::from_file_set(Arc::new(load()), ".demo.Person").unwrap();
+        dynamic.merge(&bytes).unwrap();
+        assert_eq!(dynamic.encode_to_vec(), bytes);
+
+        let text = "name: \"Bo\" id: 3 lucky: [1, 2] counts: [5] phone: \"555\" kind: KIND_FRIEND \
+                    homes { key: 4 value { city: \"Oslo\" } }";
+        let mut dynamic = DynamicMessage::from_file_set(Arc::new(load()), ".demo.Person").unwrap();
+        crate::text_format::merge(text, &mut dynamic).unwrap();
+        let bytes = dynamic.encode_to_vec();
+        let decoded = Person::decode(&bytes).unwrap();
+        assert_eq!(
+            decoded.contact,
+            Some(person::Contact::Phone("555".to_string()))
+        );
+        assert_eq!(decoded.kind(), person::Kind::Friend);
+        assert_eq!(decoded.homes[&4].city, "Oslo");
+        assert_eq!(decoded.encode_to_vec(), bytes);
+    }
+
+    #[test]
+    fn unknown_enum_values_survive() {
+        let person = Person {
+            kind: 9,
+            ..Default::default()
+        };
+        let bytes = person.encode_to_vec();
+        let decoded = Person::decode(&bytes).unwrap();
+        assert_eq!(decoded.kind, 9);
+        assert_eq!(demo::Empty::decode(&bytes).unwrap().encoded_len(), 0);
+        assert_eq!(decoded.kind(), person::Kind::Unknown);
+        assert_eq!(person::Kind::try_from(9), Err(9));
+        assert_eq!(Color::CRIMSON, Color::Red);
+        assert_eq!(Color::from_str_name("CRIMSON"), Some(Color::Red));
+        assert_eq!(Color::Green.as_str_name(), "GREEN");
+        assert_eq!(i32::from(Color::Green), 1);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(upper_camel("PHONE_TYPE"), "PhoneType");
+        assert_eq!(upper_camel("HTTPRule"), "HTTPRule");
+        assert_eq!(snake("HTTPRule"), "http_rule");
+        assert_eq!(snake("FooBar2"), "foo_bar2");
+        assert_eq!(ident("type"), "r#type");
+        assert_eq!(ident("self"), "self_");
+    }
+}
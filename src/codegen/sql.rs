@@ -0,0 +1,776 @@
+//! Generates SQL `CREATE TABLE` statements for storing messages as
+//! flattened records, in SQLite or PostgreSQL.
+//!
+//! Each top-level message becomes a table named after it in `snake_case`.
+//! Scalars are columns, enums are the names of their values checked
+//! against the enum, and the well-known types take their natural column
+//! types. A nested message is flattened into columns prefixed with the
+//! field's name, or with `nested=table` is a child table. Repeated fields
+//! and maps are child tables, keyed by the parent's key and a `position`
+//! or the map's `key`; with `repeated=array`, PostgreSQL stores repeated
+//! scalars and enums as arrays instead.
+//!
+//! A table's key is the fields marked `[(sql).primary_key = true]`, an
+//! option declared by the bundled `protos/sql.proto`, or else a generated
+//! `id`. Child tables reference their parent's key and are deleted with
+//! it.
+
+use super::json_schema::{self, Definition, Kind};
+use super::{extension_fields, parameters, snake, Code};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Sqlite,
+    Postgres,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub dialect: Dialect,
+    /// Give singular nested messages child tables rather than flattening
+    /// them.
+    pub nested_tables: bool,
+    /// Store repeated scalars and enums in PostgreSQL arrays rather than
+    /// child tables.
+    pub arrays: bool,
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters: `dialect=sqlite` or
+    /// `dialect=postgres`, `nested=flatten` or `nested=table`, and
+    /// `repeated=table` or `repeated=array`.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            match item {
+                "dialect=sqlite" => options.dialect = Dialect::Sqlite,
+                "dialect=postgres" => options.dialect = Dialect::Postgres,
+                "nested=flatten" => options.nested_tables = false,
+                "nested=table" => options.nested_tables = true,
+                "repeated=table" => options.arrays = false,
+                "repeated=array" => options.arrays = true,
+                _ => return Err(format!("unknown sql generator parameter {}", item)),
+            }
+        }
+        if options.arrays && options.dialect == Dialect::Sqlite {
+            return Err(
+                "sql generator parameter repeated=array needs dialect=postgres".to_string(),
+            );
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Two columns of a table have the same name: the table and column.
+    DuplicateColumn(String, String),
+    /// A message that contains itself, whose columns or child tables
+    /// would never end.
+    Recursive(String),
+    /// A `primary_key` field that is not a singular scalar or enum.
+    PrimaryKey(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DuplicateColumn(table, column) => {
+                write!(f, "table {} has two columns named {}", table, column)
+            }
+            Error::Recursive(message) => {
+                write!(f, "message {} contains itself and has no table", message)
+            }
+            Error::PrimaryKey(field) => {
+                write!(
+                    f,
+                    "field {} is a (sql).primary_key but not a singular scalar or enum",
+                    field
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Generates `x.sql` for each named file with messages.
+pub fn generate(
+    set: &FileSet,
+    files: &[&str],
+    options: &Options,
+) -> Result<BTreeMap<String, String>, Error> {
+    let definitions = json_schema::definitions(set);
+    let mut ret = BTreeMap::new();
+    for file in set
+        .files
+        .iter()
+        .filter(|file| files.contains(&file.name.as_str()))
+    {
+        if file.messages.is_empty() {
+            continue;
+        }
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        let mut generator = Generator {
+            set,
+            definitions: &definitions,
+            options,
+            tables: vec![],
+            stack: vec![],
+        };
+        for message in &file.messages {
+            generator.top_level(&format!("{}.{}", scope, message.name), message)?;
+        }
+        let mut code = Code::new("  ");
+        code.line(format!(
+            "-- @generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        for table in &generator.tables {
+            code.line("");
+            table.write(&mut code);
+        }
+        let stem = file.name.strip_suffix(".proto").unwrap_or(&file.name);
+        ret.insert(format!("{}.sql", stem), code.text);
+    }
+    Ok(ret)
+}
+
+/// Words quoted where they name a table or column.
+const RESERVED: &[&str] = &[
+    "all",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "between",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "cross",
+    "default",
+    "deferrable",
+    "delete",
+    "desc",
+    "distinct",
+    "do",
+    "drop",
+    "else",
+    "end",
+    "except",
+    "exists",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "full",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "index",
+    "inner",
+    "insert",
+    "intersect",
+    "into",
+    "is",
+    "join",
+    "key",
+    "leading",
+    "left",
+    "like",
+    "limit",
+    "natural",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "outer",
+    "primary",
+    "references",
+    "returning",
+    "right",
+    "select",
+    "set",
+    "some",
+    "table",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "using",
+    "values",
+    "view",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+fn quote(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain && !RESERVED.contains(&name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+fn quote_all(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct Column<'a> {
+    name: String,
+    sql_type: String,
+    /// `NOT NULL`, `CHECK (...)` or nothing.
+    constraint: String,
+    comment: Option<&'a String>,
+}
+
+/// A column of a table's key.
+#[derive(Clone)]
+struct Key {
+    name: String,
+    sql_type: String,
+    /// Whether the column is the table's own rather than its parent's.
+    own: bool,
+}
+
+struct Table<'a> {
+    name: String,
+    comment: Option<&'a String>,
+    columns: Vec<Column<'a>>,
+    key: Vec<Key>,
+    /// The parent table and the columns referring to its key.
+    parent: Option<(String, Vec<String>)>,
+}
+
+impl Table<'_> {
+    fn write(&self, code: &mut Code) {
+        let mut lines: Vec<(Option<&String>, String)> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut line = format!("{} {}", quote(&column.name), column.sql_type);
+                if !column.constraint.is_empty() {
+                    line.push(' ');
+                    line.push_str(&column.constraint);
+                }
+                (column.comment, line)
+            })
+            .collect();
+        let key: Vec<String> = self.key.iter().map(|key| key.name.clone()).collect();
+        lines.push((None, format!("PRIMARY KEY ({})", quote_all(&key))));
+        if let Some((parent, references)) = &self.parent {
+            let columns: Vec<String> = self.key[..references.len()]
+                .iter()
+                .map(|key| key.name.clone())
+                .collect();
+            lines.push((
+                None,
+                format!(
+                    "FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE CASCADE",
+                    quote_all(&columns),
+                    quote(parent),
+                    quote_all(references)
+                ),
+            ));
+        }
+        code.comment("--", self.comment);
+        code.line(format!("CREATE TABLE {} (", quote(&self.name)));
+        code.depth += 1;
+        let last = lines.len() - 1;
+        for (i, (comment, line)) in lines.into_iter().enumerate() {
+            code.comment("--", comment);
+            code.line(if i < last { format!("{},", line) } else { line });
+        }
+        code.close_with(");");
+    }
+}
+
+/// A field stored in a child table, found while building its parent.
+struct Child<'a> {
+    /// The child table's name after its parent's.
+    suffix: String,
+    field: MessageField,
+    comment: Option<&'a String>,
+    /// The messages being flattened where the field was found.
+    stack: Vec<String>,
+}
+
+struct Generator<'a> {
+    set: &'a FileSet,
+    definitions: &'a BTreeMap<String, Definition<'a>>,
+    options: &'a Options,
+    tables: Vec<Table<'a>>,
+    /// The messages whose columns are being built, outermost first.
+    stack: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn top_level(&mut self, full_name: &str, message: &'a Message) -> Result<(), Error> {
+        let name = snake(&message.name);
+        let mut columns = vec![];
+        let mut key = vec![];
+        for field in &message.fields {
+            let options = message.field_options.get(&field.position());
+            let marked = extension_fields(options.map_or(&[], |options| &options[..]), "sql")
+                .iter()
+                .any(|(key, value)| key == "primary_key" && *value == Constant::Bool(true));
+            if !marked {
+                continue;
+            }
+            let sql_type = match (field.is_repeated(), field.field_type()) {
+                (false, Type::Message(_)) | (true, _) => {
+                    return Err(Error::PrimaryKey(format!(
+                        "{}.{}",
+                        &full_name[1..],
+                        field.name()
+                    )))
+                }
+                (false, t) => self.value_type(&t),
+            };
+            key.push(Key {
+                name: field.name().to_string(),
+                sql_type,
+                own: true,
+            });
+        }
+        let marked: Vec<String> = key.iter().map(|key| key.name.clone()).collect();
+        if key.is_empty() {
+            let (sql_type, constraint) = match self.options.dialect {
+                // The key is then an alias of the `rowid`, assigned on insert.
+                Dialect::Sqlite => ("INTEGER", ""),
+                Dialect::Postgres => ("BIGINT", "GENERATED ALWAYS AS IDENTITY"),
+            };
+            columns.push(Column {
+                name: "id".to_string(),
+                sql_type: sql_type.to_string(),
+                constraint: constraint.to_string(),
+                comment: None,
+            });
+            key.push(Key {
+                name: "id".to_string(),
+                sql_type: sql_type.to_string(),
+                own: true,
+            });
+        }
+        self.stack = vec![];
+        let mut children = vec![];
+        self.message_columns(&mut columns, &mut children, "", full_name)?;
+        // SQLite allows nulls in keys other than an `INTEGER` one.
+        for column in &mut columns {
+            if marked.contains(&column.name) && !column.constraint.starts_with("NOT NULL") {
+                column.constraint = format!("NOT NULL {}", column.constraint)
+                    .trim_end()
+                    .to_string();
+            }
+        }
+        let comment = self.definitions[full_name].comment;
+        self.table(name, comment, columns, key, None, children)
+    }
+
+    /// Adds a table after checking its columns, then its children.
+    fn table(
+        &mut self,
+        name: String,
+        comment: Option<&'a String>,
+        columns: Vec<Column<'a>>,
+        key: Vec<Key>,
+        parent: Option<(String, Vec<String>)>,
+        children: Vec<Child<'a>>,
+    ) -> Result<(), Error> {
+        let mut names = BTreeSet::new();
+        for column in &columns {
+            if !names.insert(&column.name) {
+                return Err(Error::DuplicateColumn(name, column.name.clone()));
+            }
+        }
+        self.tables.push(Table {
+            name: name.clone(),
+            comment,
+            columns,
+            key: key.clone(),
+            parent,
+        });
+        for child in children {
+            self.child(&name, &key, child)?;
+        }
+        Ok(())
+    }
+
+    fn child(&mut self, parent: &str, parent_key: &[Key], child: Child<'a>) -> Result<(), Error> {
+        let name = format!("{}_{}", parent, child.suffix);
+        let mut key: Vec<Key> = parent_key
+            .iter()
+            .map(|key| Key {
+                name: match key.own {
+                    true => format!("{}_{}", parent, key.name),
+                    false => key.name.clone(),
+                },
+                sql_type: key.sql_type.clone(),
+                own: false,
+            })
+            .collect();
+        let references = parent_key.iter().map(|key| key.name.clone()).collect();
+        let mut columns: Vec<Column> = key
+            .iter()
+            .map(|key| Column {
+                name: key.name.clone(),
+                sql_type: key.sql_type.clone(),
+                constraint: "NOT NULL".to_string(),
+                comment: None,
+            })
+            .collect();
+        self.stack = child.stack;
+        let mut children = vec![];
+        let field = &child.field;
+        match field.field_type() {
+            Type::Map(key_type, value_type) => {
+                key.push(Key {
+                    name: "key".to_string(),
+                    sql_type: self.value_type(&key_type),
+                    own: true,
+                });
+                let key_field = MessageField::new(None, *key_type, "key".to_string(), 1);
+                let value_field = MessageField::new(None, *value_type, "value".to_string(), 2);
+                self.field(&mut columns, &mut children, "", &key_field, None)?;
+                self.field(&mut columns, &mut children, "", &value_field, None)?;
+                columns[key.len() - 1].constraint = "NOT NULL".to_string();
+            }
+            t if field.is_repeated() => {
+                key.push(Key {
+                    name: "position".to_string(),
+                    sql_type: "INTEGER".to_string(),
+                    own: true,
+                });
+                columns.push(Column {
+                    name: "position".to_string(),
+                    sql_type: "INTEGER".to_string(),
+                    constraint: "NOT NULL".to_string(),
+                    comment: None,
+                });
+                match &t {
+                    Type::Message(message) if self.well_known(message).is_none() => {
+                        self.message_columns(&mut columns, &mut children, "", message)?
+                    }
+                    _ => {
+                        let value = MessageField::new(None, t, "value".to_string(), 1);
+                        self.field(&mut columns, &mut children, "", &value, None)?
+                    }
+                }
+            }
+            Type::Message(message) => {
+                self.message_columns(&mut columns, &mut children, "", &message)?
+            }
+            _ => unreachable!("only messages, repeated fields and maps have child tables"),
+        }
+        let parent = Some((parent.to_string(), references));
+        self.table(name, child.comment, columns, key, parent, children)
+    }
+
+    /// Adds the columns of a message, flattened behind `prefix`.
+    fn message_columns(
+        &mut self,
+        columns: &mut Vec<Column<'a>>,
+        children: &mut Vec<Child<'a>>,
+        prefix: &str,
+        full_name: &str,
+    ) -> Result<(), Error> {
+        if self.stack.iter().any(|name| name == full_name) {
+            return Err(Error::Recursive(full_name[1..].to_string()));
+        }
+        self.stack.push(full_name.to_string());
+        let definition = &self.definitions[full_name];
+        let Kind::Message(message) = definition.kind else {
+            unreachable!("{} is a message", full_name);
+        };
+        let fields = message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields));
+        for field in fields {
+            let comment = definition.fields.get(&field.position()).copied();
+            self.field(columns, children, prefix, field, comment)?;
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn field(
+        &mut self,
+        columns: &mut Vec<Column<'a>>,
+        children: &mut Vec<Child<'a>>,
+        prefix: &str,
+        field: &MessageField,
+        comment: Option<&'a String>,
+    ) -> Result<(), Error> {
+        let name = format!("{}{}", prefix, field.name());
+        let t = field.field_type();
+        let array = self.options.arrays && !matches!(t, Type::Message(_));
+        let nested = match &t {
+            Type::Message(message) if self.well_known(message).is_none() => Some(message),
+            _ => None,
+        };
+        if matches!(t, Type::Map(..))
+            || (field.is_repeated() && !array)
+            || (nested.is_some() && self.options.nested_tables)
+        {
+            children.push(Child {
+                suffix: name,
+                field: field.clone(),
+                comment,
+                stack: self.stack.clone(),
+            });
+            return Ok(());
+        }
+        if let Some(message) = nested {
+            return self.message_columns(columns, children, &format!("{}_", name), message);
+        }
+        let mut sql_type = self.value_type(&t);
+        let mut constraint = String::new();
+        if let Type::Enum(e) = &t {
+            let values = self.enum_values(e);
+            constraint = match field.is_repeated() {
+                true => format!("CHECK ({} <@ ARRAY[{}])", quote(&name), values),
+                false => format!("CHECK ({} IN ({}))", quote(&name), values),
+            };
+        }
+        if field.is_repeated() {
+            sql_type.push_str("[]");
+        } else if field.frequency() == Some(&Frequency::Required) {
+            constraint = format!("NOT NULL {}", constraint).trim_end().to_string();
+        }
+        columns.push(Column {
+            name,
+            sql_type,
+            constraint,
+            comment,
+        });
+        Ok(())
+    }
+
+    /// The names of an enum's values as a list of SQL strings.
+    fn enum_values(&self, name: &str) -> String {
+        let e = self.set.find_enum(name).expect("enum types are linked");
+        e.fields
+            .iter()
+            .map(|value| format!("'{}'", value.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The column type of a well-known message type, if it has one.
+    fn well_known(&self, name: &str) -> Option<&'static str> {
+        let postgres = self.options.dialect == Dialect::Postgres;
+        Some(match name.strip_prefix(".google.protobuf.")? {
+            "Timestamp" if postgres => "TIMESTAMPTZ",
+            "Duration" if postgres => "INTERVAL",
+            "Struct" | "Value" | "ListValue" | "Any" if postgres => "JSONB",
+            "Timestamp" | "Duration" | "FieldMask" | "Struct" | "Value" | "ListValue" | "Any" => {
+                "TEXT"
+            }
+            "BoolValue" => return Some(self.scalar(&Type::Bool)),
+            "Int32Value" => return Some(self.scalar(&Type::Int32)),
+            "UInt32Value" => return Some(self.scalar(&Type::Uint32)),
+            "Int64Value" => return Some(self.scalar(&Type::Int64)),
+            "UInt64Value" => return Some(self.scalar(&Type::Uint64)),
+            "FloatValue" => return Some(self.scalar(&Type::Float)),
+            "DoubleValue" => return Some(self.scalar(&Type::Double)),
+            "StringValue" => "TEXT",
+            "BytesValue" => return Some(self.scalar(&Type::Bytes)),
+            _ => return None,
+        })
+    }
+
+    fn scalar(&self, t: &Type) -> &'static str {
+        match (self.options.dialect, t) {
+            (Dialect::Sqlite, Type::Float | Type::Double) => "REAL",
+            (Dialect::Sqlite, Type::Bytes) => "BLOB",
+            (Dialect::Sqlite, Type::String | Type::Enum(_)) => "TEXT",
+            (Dialect::Sqlite, _) => "INTEGER",
+            (Dialect::Postgres, Type::Int32 | Type::Sint32 | Type::Sfixed32) => "INTEGER",
+            (Dialect::Postgres, Type::Uint64 | Type::Fixed64) => "NUMERIC(20)",
+            (Dialect::Postgres, Type::Float) => "REAL",
+            (Dialect::Postgres, Type::Double) => "DOUBLE PRECISION",
+            (Dialect::Postgres, Type::Bool) => "BOOLEAN",
+            (Dialect::Postgres, Type::Bytes) => "BYTEA",
+            (Dialect::Postgres, Type::String | Type::Enum(_)) => "TEXT",
+            (Dialect::Postgres, _) => "BIGINT",
+        }
+    }
+
+    /// The column type of a scalar, enum or well-known message.
+    fn value_type(&self, t: &Type) -> String {
+        match t {
+            Type::Message(name) => self.well_known(name).unwrap_or("TEXT").to_string(),
+            t => self.scalar(t).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+    use std::process::Command;
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/sql")
+            .load(&["crm.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["crm.proto"], &Options::default()).unwrap();
+        let expected = std::fs::read_to_string("tests/sql/crm.sql").unwrap();
+        assert_eq!(generated["crm.sql"], expected);
+        assert_eq!(generated.len(), 1);
+    }
+
+    #[test]
+    fn runs_in_sqlite() {
+        let generated = generate(&load(), &["crm.proto"], &Options::default()).unwrap();
+        let script = format!(
+            "PRAGMA foreign_keys = ON;\n{}\n\
+             INSERT INTO customer (email, tier, billing_city) VALUES ('a@example.com', 'GOLD', 'Oslo');\n\
+             INSERT INTO customer_tags (customer_email, position, value) VALUES ('a@example.com', 0, 'vip');\n\
+             INSERT INTO customer_billing_lines (customer_email, position, value) VALUES ('a@example.com', 0, 'Main St');\n\
+             INSERT INTO customer_phones (customer_email, position, number, kind) VALUES ('a@example.com', 0, '555', 'MOBILE');\n\
+             INSERT INTO customer_credits (customer_email, \"key\", value) VALUES ('a@example.com', 'gift', 5);\n\
+             INSERT INTO visit (at) VALUES (1);\n\
+             SELECT id FROM visit;\n\
+             SELECT count(*) FROM customer_tags;\n\
+             DELETE FROM customer;\n\
+             SELECT count(*) FROM customer_phones;\n",
+            generated["crm.sql"]
+        );
+        let run = |script: &str| {
+            let mut child = Command::new("sqlite3")
+                .arg(":memory:")
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()?;
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(script.as_bytes())?;
+            child.wait_with_output()
+        };
+        let output = match run(&script) {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("skipping runs_in_sqlite: sqlite3 is not installed");
+                return;
+            }
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "1\n1\n0\n",
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.status.success());
+
+        for invalid in [
+            "INSERT INTO customer (email, tier) VALUES ('b@example.com', 'SILVER');",
+            "INSERT INTO customer_tags (customer_email, position) VALUES ('nobody', 0);",
+            "INSERT INTO customer (name) VALUES ('no key');",
+        ] {
+            let script = format!(
+                "PRAGMA foreign_keys = ON;\n{}\n{}\n",
+                generated["crm.sql"], invalid
+            );
+            let output = run(&script).unwrap();
+            assert!(
+                String::from_utf8_lossy(&output.stderr).contains("constraint failed"),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn postgres_options_and_errors() {
+        let options = Options::parse("dialect=postgres, nested=table, repeated=array").unwrap();
+        let sql = &generate(&load(), &["crm.proto"], &options).unwrap()["crm.sql"];
+        assert!(sql.contains("  tags TEXT[],\n"));
+        assert!(
+            sql.contains("  kind TEXT CHECK (kind IN ('KIND_UNSPECIFIED', 'MOBILE', 'HOME')),\n")
+        );
+        assert!(sql.contains("  created TIMESTAMPTZ,\n"));
+        assert!(sql.contains(
+            "CREATE TABLE customer_billing (\n  customer_email TEXT NOT NULL,\n  street TEXT,\n"
+        ));
+        assert!(sql.contains("  id BIGINT GENERATED ALWAYS AS IDENTITY,\n"));
+        assert!(sql.contains(
+            "  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE\n"
+        ));
+        assert_eq!(
+            Options::parse("repeated=array").unwrap_err(),
+            "sql generator parameter repeated=array needs dialect=postgres"
+        );
+        assert_eq!(
+            Options::parse("nested=json").unwrap_err(),
+            "unknown sql generator parameter nested=json"
+        );
+
+        let parse = |source: &str| {
+            let file = Parser::new(source).consume_file().unwrap();
+            let file = ProtoFile {
+                name: "x.proto".to_string(),
+                ..file
+            };
+            FileSet::new(vec![file]).unwrap()
+        };
+        let set = parse("syntax = \"proto3\"; message Node { Node next = 1; }");
+        let err = generate(&set, &["x.proto"], &Options::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "message Node contains itself and has no table"
+        );
+        let set = parse("syntax = \"proto3\"; message A { B b = 1; string b_c = 2; } message B { string c = 1; }");
+        assert_eq!(
+            generate(&set, &["x.proto"], &Options::default()).unwrap_err(),
+            Error::DuplicateColumn("a".to_string(), "b_c".to_string())
+        );
+        let set = parse("syntax = \"proto3\"; message A { repeated string ids = 1 [(sql).primary_key = true]; }");
+        assert_eq!(
+            generate(&set, &["x.proto"], &Options::default()).unwrap_err(),
+            Error::PrimaryKey("A.ids".to_string())
+        );
+    }
+}
@@ -0,0 +1,1162 @@
+//! Generates TypeScript from linked schemas.
+//!
+//! Each message becomes an interface and a same-named object with
+//! `encode`, `decode`, `fromJSON` and `toJSON`; each enum a numeric `enum`
+//! or a union of its value names; and each oneof a union discriminated by
+//! `$case`. Types nested in a message are named `Outer_Inner`. Every
+//! `.proto` file becomes a `.ts` module importing the modules of its
+//! dependencies, and all of them import the codec from `runtime.ts`. The
+//! well-known types used are generated too, at their import paths.
+
+use super::{declare_all, method, parameters, wire_type, Code, Context, Declared, Label};
+use crate::resolver::FileSet;
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The reader, writer and JSON helpers generated modules import.
+pub const RUNTIME: &str = include_str!("typescript/runtime.ts");
+
+/// How 64-bit integers are represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Long {
+    /// Decimal strings, as proto3 JSON writes them.
+    #[default]
+    String,
+    BigInt,
+    /// Plain numbers, which lose precision beyond 2^53.
+    Number,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub long: Long,
+    /// Enums as unions of their value names rather than numeric `enum`s.
+    /// Values the schema doesn't name then decode as its first value.
+    pub string_enums: bool,
+}
+
+impl Options {
+    /// Reads comma-separated generator parameters:
+    /// `long=string|bigint|number` and `enums=numeric|string`.
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Options::default();
+        for item in parameters(parameter) {
+            match item.split_once('=') {
+                Some(("long", "string")) => options.long = Long::String,
+                Some(("long", "bigint")) => options.long = Long::BigInt,
+                Some(("long", "number")) => options.long = Long::Number,
+                Some(("enums", "numeric")) => options.string_enums = false,
+                Some(("enums", "string")) => options.string_enums = true,
+                _ => return Err(format!("unknown typescript generator parameter {}", item)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Generates a module for each named file, keyed by its name with `.ts`
+/// in place of `.proto`, and `runtime.ts` at the root of the output.
+pub fn generate(set: &FileSet, files: &[&str], options: &Options) -> BTreeMap<String, String> {
+    let types = declare_all(set, str::to_string, "_");
+    let mut ret = BTreeMap::new();
+    let mut pending: Vec<&str> = files.to_vec();
+    let mut done = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        let Some(file) = set.files.iter().find(|file| file.name == name) else {
+            continue;
+        };
+        if !done.insert(name) {
+            continue;
+        }
+        let generator = FileGenerator {
+            options,
+            types: &types,
+            file: &file.name,
+            runtime: false,
+            imports: BTreeMap::new(),
+        };
+        let (code, imports) = generator.generate(set, file);
+        ret.insert(module_name(&file.name), code);
+        // The well-known types come with the modules that use them.
+        pending.extend(
+            imports
+                .iter()
+                .filter(|import| well_known::source(import).is_some())
+                .filter_map(|import| set.files.iter().find(|file| file.name == **import))
+                .map(|file| file.name.as_str()),
+        );
+    }
+    ret.insert("runtime.ts".to_string(), RUNTIME.to_string());
+    ret
+}
+
+fn full_name(file: &ProtoFile, name: &str) -> String {
+    match &file.package {
+        Some(package) => format!(".{}.{}", package, name),
+        None => format!(".{}", name),
+    }
+}
+
+fn module_name(file: &str) -> String {
+    format!("{}.ts", file.strip_suffix(".proto").unwrap_or(file))
+}
+
+fn directory(file: &str) -> &str {
+    file.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// The import specifier of module `to` from module `from`.
+fn relative(from: &str, to: &str) -> String {
+    let from: Vec<&str> = directory(from)
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let to: Vec<&str> = to.split('/').collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut path = match from.len() - common {
+        0 => "./".to_string(),
+        up => "../".repeat(up),
+    };
+    path.push_str(&to[common..].join("/"));
+    path.trim_end_matches(".ts").to_string()
+}
+
+struct FileGenerator<'a> {
+    options: &'a Options,
+    types: &'a BTreeMap<String, Declared>,
+    file: &'a str,
+    /// Whether the module uses the runtime, imported as `_runtime` so that
+    /// its names cannot clash with generated ones.
+    runtime: bool,
+    /// The names the module imports, by the module declaring them.
+    imports: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl<'a> FileGenerator<'a> {
+    /// The module's code and the files whose modules it imports.
+    fn generate(mut self, set: &FileSet, file: &ProtoFile) -> (String, BTreeSet<String>) {
+        let mut code = Code::new("  ");
+        let mut context = Context::new(set, file);
+        for (i, message) in file.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            let full_name = full_name(file, &message.name);
+            self.message(&mut code, &mut context, message, &full_name);
+            context.path.pop();
+        }
+        for (i, e) in file.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            let full_name = full_name(file, &e.name);
+            self.enumeration(&mut code, &context, e, &full_name);
+            context.path.pop();
+        }
+
+        let mut header = Code::new("  ");
+        header.line(format!(
+            "// @generated by protos from {}. Do not edit.",
+            file.name
+        ));
+        header.line("");
+        if self.runtime {
+            header.line(format!(
+                "import * as _runtime from \"{}\";",
+                relative(&file.name, "runtime.ts")
+            ));
+        }
+        for (module, names) in &self.imports {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            header.line(format!(
+                "import {{ {} }} from \"{}\";",
+                names.join(", "),
+                relative(&file.name, &module_name(module))
+            ));
+        }
+        let imports = self.imports.into_keys().collect();
+        (format!("{}{}", header.text, code.text), imports)
+    }
+
+    /// `name` from the runtime.
+    fn rt(&mut self, name: &str) -> String {
+        self.runtime = true;
+        format!("_runtime.{}", name)
+    }
+
+    /// The TypeScript name of a message or enum, plus `suffix` for the
+    /// functions declared with enums, imported if declared elsewhere.
+    fn name(&mut self, full_name: &str, suffix: &str) -> String {
+        let declared = &self.types[full_name];
+        let name = format!("{}{}", declared.name, suffix);
+        if declared.file != self.file {
+            self.imports
+                .entry(declared.file.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+        name
+    }
+
+    fn is_long(t: &Type) -> bool {
+        matches!(
+            t,
+            Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Fixed64 | Type::Sfixed64
+        )
+    }
+
+    /// Scalars whose map keys are numbers rather than strings.
+    fn is_number_key(t: &Type) -> bool {
+        matches!(
+            t,
+            Type::Int32 | Type::Uint32 | Type::Sint32 | Type::Fixed32 | Type::Sfixed32
+        )
+    }
+
+    fn ts_type(&mut self, t: &Type) -> String {
+        match t {
+            Type::String => "string".to_string(),
+            Type::Bytes => "Uint8Array".to_string(),
+            Type::Bool => "boolean".to_string(),
+            t if Self::is_long(t) => match self.options.long {
+                Long::String => "string".to_string(),
+                Long::BigInt => "bigint".to_string(),
+                Long::Number => "number".to_string(),
+            },
+            Type::Enum(name) | Type::Message(name) => self.name(name, ""),
+            _ => "number".to_string(),
+        }
+    }
+
+    fn default(&mut self, t: &Type) -> String {
+        match t {
+            Type::String => "\"\"".to_string(),
+            Type::Bytes => "new Uint8Array(0)".to_string(),
+            Type::Bool => "false".to_string(),
+            t if Self::is_long(t) => match self.options.long {
+                Long::String => "\"0\"".to_string(),
+                Long::BigInt => "0n".to_string(),
+                Long::Number => "0".to_string(),
+            },
+            Type::Enum(name) if self.options.string_enums => {
+                format!("{}(0)", self.name(name, "FromJSON"))
+            }
+            Type::Message(name) => format!("{}.decode(new Uint8Array(0))", self.name(name, "")),
+            _ => "0".to_string(),
+        }
+    }
+
+    /// The condition under which an implicit-presence `expr` is written.
+    fn is_not_default(&mut self, t: &Type, expr: &str) -> String {
+        match t {
+            Type::String => format!("{} !== \"\"", expr),
+            Type::Bytes => format!("{}.length !== 0", expr),
+            Type::Bool => expr.to_string(),
+            t if Self::is_long(t) => format!("{} !== {}", expr, self.default(t)),
+            Type::Enum(name) if self.options.string_enums => {
+                format!("{}({}) !== 0", self.name(name, "ToNumber"), expr)
+            }
+            _ => format!("{} !== 0", expr),
+        }
+    }
+
+    /// A call writing `expr` to `writer` without a tag.
+    fn write_value(&mut self, t: &Type, expr: &str, writer: &str) -> String {
+        match t {
+            Type::Message(name) => format!(
+                "{}.bytes({}.encode({}).finish())",
+                writer,
+                self.name(name, ""),
+                expr
+            ),
+            Type::Enum(name) if self.options.string_enums => format!(
+                "{}.int32({}({}))",
+                writer,
+                self.name(name, "ToNumber"),
+                expr
+            ),
+            t => format!("{}.{}({})", writer, method(t), expr),
+        }
+    }
+
+    /// A statement writing field `number` holding `expr`.
+    fn write(&mut self, t: &Type, number: u32, expr: &str, writer: &str) -> String {
+        let tagged = format!("{}.tag({}, {})", writer, number, wire_type(t));
+        format!("{};", self.write_value(t, expr, &tagged))
+    }
+
+    /// An expression reading one value of `t` from `reader`.
+    fn read(&mut self, t: &Type, reader: &str) -> String {
+        let read = format!("{}.{}()", reader, method(t));
+        match t {
+            Type::Message(name) => format!("{}.decode({})", self.name(name, ""), read),
+            Type::Enum(name) if self.options.string_enums => {
+                format!("{}({})", self.name(name, "FromJSON"), read)
+            }
+            t if Self::is_long(t) => match self.options.long {
+                Long::String => format!("{}.toString()", read),
+                Long::BigInt => read,
+                Long::Number => format!("Number({})", read),
+            },
+            _ => read,
+        }
+    }
+
+    fn json_value(&mut self, t: &Type, expr: &str) -> String {
+        match t {
+            Type::Float | Type::Double => format!("{}({})", self.rt("jsonNumber"), expr),
+            Type::Bytes => format!("{}({})", self.rt("base64Encode"), expr),
+            t if Self::is_long(t) => format!("String({})", expr),
+            Type::Enum(name) => format!("{}({})", self.name(name, "ToJSON"), expr),
+            Type::Message(name) => format!("{}.toJSON({})", self.name(name, ""), expr),
+            _ => expr.to_string(),
+        }
+    }
+
+    fn parse_json(&mut self, t: &Type, expr: &str) -> String {
+        match t {
+            Type::String => format!("String({})", expr),
+            Type::Bool => format!("Boolean({})", expr),
+            Type::Bytes => format!("{}({})", self.rt("base64Decode"), expr),
+            t if Self::is_long(t) => match self.options.long {
+                Long::String => format!("String({})", expr),
+                Long::BigInt => format!("BigInt({})", expr),
+                Long::Number => format!("Number({})", expr),
+            },
+            Type::Enum(name) => format!("{}({})", self.name(name, "FromJSON"), expr),
+            Type::Message(name) => format!("{}.fromJSON({})", self.name(name, ""), expr),
+            _ => format!("Number({})", expr),
+        }
+    }
+
+    fn jsdoc(code: &mut Code, comment: Option<&String>) {
+        if comment.is_some() {
+            code.line("/**");
+            code.comment(" *", comment);
+            code.line(" */");
+        }
+    }
+
+    /// The value of JSON property `json`, also accepting the field's
+    /// original name as proto3 JSON parsers must.
+    fn json_property(field: &MessageField) -> String {
+        let json = field.json_name();
+        if json == field.name() {
+            format!("object.{}", json)
+        } else {
+            format!("(object.{} ?? object.{})", json, field.name())
+        }
+    }
+
+    fn message(
+        &mut self,
+        code: &mut Code,
+        context: &mut Context,
+        message: &Message,
+        full_name: &str,
+    ) {
+        let name = self.name(full_name, "");
+        let fields: Vec<(usize, &MessageField, Label)> = message
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let options = message.field_options.get(&field.position());
+                (i, field, Label::of(field, options, context.proto3))
+            })
+            .collect();
+
+        code.line("");
+        Self::jsdoc(code, context.comment(&[]));
+        if fields.is_empty() && message.oneofs.is_empty() {
+            code.line(format!("export interface {} {{}}", name));
+        } else {
+            code.open(format!("export interface {}", name));
+            for (i, field, label) in &fields {
+                Self::jsdoc(code, context.comment(&[PathSegment::Field(*i)]));
+                let declaration = self.declaration(field, *label);
+                code.line(declaration);
+            }
+            for (i, oneof) in message.oneofs.iter().enumerate() {
+                Self::jsdoc(code, context.comment(&[PathSegment::Oneof(i)]));
+                let cases: Vec<String> = oneof
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let property = field.json_name();
+                        let t = self.ts_type(&field.field_type());
+                        format!("{{ $case: \"{0}\"; {0}: {1} }}", property, t)
+                    })
+                    .collect();
+                code.line(format!(
+                    "{}?: {} | undefined;",
+                    oneof_property(oneof),
+                    cases.join(" | ")
+                ));
+            }
+            code.close();
+        }
+
+        code.line("");
+        code.open(format!("export const {} =", name));
+        self.encode(code, message, &name, &fields);
+        self.decode(code, message, &name, &fields);
+        self.method_from_json(code, message, &name, &fields);
+        self.method_to_json(code, message, &name, &fields);
+        code.close_with("};");
+
+        for (i, nested) in message.messages.iter().enumerate() {
+            context.path.push(PathSegment::Message(i));
+            self.message(
+                code,
+                context,
+                nested,
+                &format!("{}.{}", full_name, nested.name),
+            );
+            context.path.pop();
+        }
+        for (i, e) in message.enums.iter().enumerate() {
+            context.path.push(PathSegment::Enum(i));
+            self.enumeration(code, context, e, &format!("{}.{}", full_name, e.name));
+            context.path.pop();
+        }
+    }
+
+    fn declaration(&mut self, field: &MessageField, label: Label) -> String {
+        let property = field.json_name();
+        let t = field.field_type();
+        match (label, &t) {
+            (Label::Map, Type::Map(key, value)) => {
+                let key = if Self::is_number_key(key) {
+                    "number"
+                } else {
+                    "string"
+                };
+                format!(
+                    "{}: {{ [key: {}]: {} }};",
+                    property,
+                    key,
+                    self.ts_type(value)
+                )
+            }
+            (Label::Repeated | Label::Packed, t) => {
+                format!("{}: {}[];", property, self.ts_type(t))
+            }
+            (Label::Optional, t) => format!("{}?: {} | undefined;", property, self.ts_type(t)),
+            (_, t) => format!("{}: {};", property, self.ts_type(t)),
+        }
+    }
+
+    fn encode(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        name: &str,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        let writer = self.rt("Writer");
+        let mut members: Vec<(u32, Option<&Oneof>, &MessageField, Label)> = fields
+            .iter()
+            .map(|(_, field, label)| (field.position(), None, *field, *label))
+            .chain(message.oneofs.iter().flat_map(|oneof| {
+                oneof
+                    .fields
+                    .iter()
+                    .map(move |field| (field.position(), Some(oneof), field, Label::Optional))
+            }))
+            .collect();
+        // Fields are written in number order, as protoc does.
+        members.sort_by_key(|(number, ..)| *number);
+
+        let parameter = if members.is_empty() {
+            "_message"
+        } else {
+            "message"
+        };
+        code.open(format!(
+            "encode({}: {}, writer: {} = new {}()): {}",
+            parameter, name, writer, writer, writer
+        ));
+        for (number, oneof, field, label) in members {
+            let t = field.field_type();
+            let expr = format!("message.{}", field.json_name());
+            match (oneof, label) {
+                (Some(oneof), _) => {
+                    let property = format!("message.{}", oneof_property(oneof));
+                    code.open(format!(
+                        "if ({}?.$case === \"{}\")",
+                        property,
+                        field.json_name()
+                    ));
+                    let value = format!("{}.{}", property, field.json_name());
+                    let statement = self.write(&t, number, &value, "writer");
+                    code.line(statement);
+                    code.close();
+                }
+                (None, Label::Implicit) => {
+                    let condition = self.is_not_default(&t, &expr);
+                    code.open(format!("if ({})", condition));
+                    let statement = self.write(&t, number, &expr, "writer");
+                    code.line(statement);
+                    code.close();
+                }
+                (None, Label::Optional) => {
+                    code.open(format!("if ({} !== undefined)", expr));
+                    let statement = self.write(&t, number, &expr, "writer");
+                    code.line(statement);
+                    code.close();
+                }
+                (None, Label::Repeated) => {
+                    code.open(format!("for (const value of {})", expr));
+                    let statement = self.write(&t, number, "value", "writer");
+                    code.line(statement);
+                    code.close();
+                }
+                (None, Label::Packed) => {
+                    code.open(format!("if ({}.length !== 0)", expr));
+                    code.line(format!("const packed = new {}();", writer));
+                    code.open(format!("for (const value of {})", expr));
+                    let statement = self.write_value(&t, "value", "packed");
+                    code.line(format!("{};", statement));
+                    code.close();
+                    code.line(format!("writer.tag({}, 2).bytes(packed.finish());", number));
+                    code.close();
+                }
+                (None, Label::Map) => {
+                    let Type::Map(key, value) = &t else {
+                        unreachable!()
+                    };
+                    code.open(format!(
+                        "for (const [key, value] of Object.entries({}))",
+                        expr
+                    ));
+                    code.line(format!("const entry = new {}();", writer));
+                    let key_expr = if Self::is_number_key(key) {
+                        "Number(key)"
+                    } else if **key == Type::Bool {
+                        "key === \"true\""
+                    } else {
+                        "key"
+                    };
+                    let statement = self.write(key, 1, key_expr, "entry");
+                    code.line(statement);
+                    let statement = self.write(value, 2, "value", "entry");
+                    code.line(statement);
+                    code.line(format!("writer.tag({}, 2).bytes(entry.finish());", number));
+                    code.close();
+                }
+            }
+        }
+        code.line("return writer;");
+        code.close_with("},");
+    }
+
+    fn decode(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        name: &str,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        let reader = self.rt("Reader");
+        code.line("");
+        code.open(format!("decode(input: Uint8Array): {}", name));
+        code.line(format!("const reader = new {}(input);", reader));
+        let defaults = self.defaults(fields);
+        code.line(format!("const message: {} = {};", name, defaults));
+        code.open("while (!reader.done)");
+        code.line("const tag = reader.uint32();");
+        code.open("switch (tag >>> 3)");
+        let mut members: Vec<(u32, Option<&Oneof>, &MessageField, Label)> = fields
+            .iter()
+            .map(|(_, field, label)| (field.position(), None, *field, *label))
+            .chain(message.oneofs.iter().flat_map(|oneof| {
+                oneof
+                    .fields
+                    .iter()
+                    .map(move |field| (field.position(), Some(oneof), field, Label::Optional))
+            }))
+            .collect();
+        members.sort_by_key(|(number, ..)| *number);
+        for (number, oneof, field, label) in members {
+            let t = field.field_type();
+            let property = format!("message.{}", field.json_name());
+            match (oneof, label) {
+                (Some(oneof), _) => {
+                    code.open_case(number);
+                    let read = self.read(&t, "reader");
+                    code.line(format!(
+                        "message.{} = {{ $case: \"{1}\", {1}: {2} }};",
+                        oneof_property(oneof),
+                        field.json_name(),
+                        read
+                    ));
+                }
+                (None, Label::Implicit | Label::Optional) => {
+                    code.open_case(number);
+                    let read = self.read(&t, "reader");
+                    code.line(format!("{} = {};", property, read));
+                }
+                (None, Label::Repeated | Label::Packed) => {
+                    code.open_case(number);
+                    let read = self.read(&t, "reader");
+                    let packable = !matches!(t, Type::String | Type::Bytes | Type::Message(_));
+                    if packable {
+                        // Parsers accept either encoding of packable fields.
+                        code.open("if ((tag & 7) === 2)");
+                        code.line("const end = reader.uint32() + reader.pos;");
+                        code.open("while (reader.pos < end)");
+                        code.line(format!("{}.push({});", property, read));
+                        code.close();
+                        code.close_open("else");
+                        code.line(format!("{}.push({});", property, read));
+                        code.close();
+                    } else {
+                        code.line(format!("{}.push({});", property, read));
+                    }
+                }
+                (None, Label::Map) => {
+                    let Type::Map(key, value) = &t else {
+                        unreachable!()
+                    };
+                    code.line(format!("case {}: {{", number));
+                    code.depth += 1;
+                    code.line(format!("const entry = new {}(reader.bytes());", reader));
+                    let key_default = if Self::is_number_key(key) {
+                        "0".to_string()
+                    } else if **key == Type::Bool {
+                        "\"false\"".to_string()
+                    } else if Self::is_long(key) {
+                        "\"0\"".to_string()
+                    } else {
+                        "\"\"".to_string()
+                    };
+                    code.line(format!("let key = {};", key_default));
+                    let value_default = self.default(value);
+                    code.line(format!("let value = {};", value_default));
+                    code.open("while (!entry.done)");
+                    code.line("const entryTag = entry.uint32();");
+                    code.open("switch (entryTag >>> 3)");
+                    let read_key = if **key == Type::Bool {
+                        "String(entry.bool())".to_string()
+                    } else if Self::is_long(key) {
+                        format!("entry.{}().toString()", method(key))
+                    } else {
+                        self.read(key, "entry")
+                    };
+                    code.line("case 1:");
+                    code.line(format!("  key = {};", read_key));
+                    code.line("  break;");
+                    code.line("case 2:");
+                    let read_value = self.read(value, "entry");
+                    code.line(format!("  value = {};", read_value));
+                    code.line("  break;");
+                    code.line("default:");
+                    code.line("  entry.skip(entryTag & 7);");
+                    code.close();
+                    code.close();
+                    code.line(format!("{}[key] = value;", property));
+                    code.line("break;");
+                    code.depth -= 1;
+                    code.line("}");
+                    continue;
+                }
+            }
+            code.line("break;");
+            code.depth -= 1;
+        }
+        code.line("default:");
+        code.line("  reader.skip(tag & 7);");
+        code.close();
+        code.close();
+        code.line("return message;");
+        code.close_with("},");
+    }
+
+    /// The object literal of a message with every field unset.
+    fn defaults(&mut self, fields: &[(usize, &MessageField, Label)]) -> String {
+        let mut properties = vec![];
+        for (_, field, label) in fields {
+            let value = match label {
+                Label::Implicit => self.default(&field.field_type()),
+                Label::Repeated | Label::Packed => "[]".to_string(),
+                Label::Map => "{}".to_string(),
+                Label::Optional => continue,
+            };
+            properties.push(format!("{}: {}", field.json_name(), value));
+        }
+        if properties.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{ {} }}", properties.join(", "))
+        }
+    }
+
+    fn method_from_json(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        name: &str,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        code.line("");
+        if fields.is_empty() && message.oneofs.is_empty() {
+            code.open(format!("fromJSON(_object: any): {}", name));
+            code.line("return {};");
+            code.close_with("},");
+            return;
+        }
+        code.open(format!("fromJSON(object: any): {}", name));
+        code.open("return");
+        for (_, field, label) in fields {
+            let property = Self::json_property(field);
+            let t = field.field_type();
+            let value = match label {
+                Label::Implicit => {
+                    let is_set = self.rt("isSet");
+                    let converted = self.parse_json(&t, &property);
+                    let default = self.default(&t);
+                    format!("{}({}) ? {} : {}", is_set, property, converted, default)
+                }
+                Label::Optional => {
+                    let is_set = self.rt("isSet");
+                    let converted = self.parse_json(&t, &property);
+                    format!("{}({}) ? {} : undefined", is_set, property, converted)
+                }
+                Label::Repeated | Label::Packed => {
+                    let converted = self.parse_json(&t, "value");
+                    format!(
+                        "Array.isArray({0}) ? {0}.map((value: any) => {1}) : []",
+                        property, converted
+                    )
+                }
+                Label::Map => {
+                    let Type::Map(_, value) = &t else {
+                        unreachable!()
+                    };
+                    let is_object = self.rt("isObject");
+                    let converted = self.parse_json(value, "value");
+                    format!(
+                        "{}({1}) ? Object.fromEntries(Object.entries({1}).map(([key, value]) => [key, {2}])) : {{}}",
+                        is_object, property, converted
+                    )
+                }
+            };
+            code.line(format!("{}: {},", field.json_name(), value));
+        }
+        for oneof in &message.oneofs {
+            let is_set = self.rt("isSet");
+            let mut value = String::new();
+            for field in &oneof.fields {
+                let property = Self::json_property(field);
+                let converted = self.parse_json(&field.field_type(), &property);
+                value.push_str(&format!(
+                    "{}({}) ? {{ $case: \"{2}\", {2}: {3} }} : ",
+                    is_set,
+                    property,
+                    field.json_name(),
+                    converted
+                ));
+            }
+            code.line(format!("{}: {}undefined,", oneof_property(oneof), value));
+        }
+        code.close_with("};");
+        code.close_with("},");
+    }
+
+    fn method_to_json(
+        &mut self,
+        code: &mut Code,
+        message: &Message,
+        name: &str,
+        fields: &[(usize, &MessageField, Label)],
+    ) {
+        code.line("");
+        let parameter = if fields.is_empty() && message.oneofs.is_empty() {
+            "_message"
+        } else {
+            "message"
+        };
+        code.open(format!("toJSON({}: {}): unknown", parameter, name));
+        code.line("const object: any = {};");
+        for (_, field, label) in fields {
+            let json = field.json_name();
+            let expr = format!("message.{}", json);
+            let t = field.field_type();
+            match label {
+                Label::Implicit => {
+                    let condition = self.is_not_default(&t, &expr);
+                    code.open(format!("if ({})", condition));
+                    let converted = self.json_value(&t, &expr);
+                    code.line(format!("object.{} = {};", json, converted));
+                }
+                Label::Optional => {
+                    code.open(format!("if ({} !== undefined)", expr));
+                    let converted = self.json_value(&t, &expr);
+                    code.line(format!("object.{} = {};", json, converted));
+                }
+                Label::Repeated | Label::Packed => {
+                    code.open(format!("if ({}.length !== 0)", expr));
+                    let converted = self.json_value(&t, "value");
+                    if converted == "value" {
+                        code.line(format!("object.{} = {};", json, expr));
+                    } else {
+                        code.line(format!(
+                            "object.{} = {}.map((value) => {});",
+                            json, expr, converted
+                        ));
+                    }
+                }
+                Label::Map => {
+                    let Type::Map(_, value) = &t else {
+                        unreachable!()
+                    };
+                    code.open(format!("if (Object.keys({}).length !== 0)", expr));
+                    let converted = self.json_value(value, "value");
+                    code.line(format!(
+                        "object.{} = Object.fromEntries(Object.entries({}).map(([key, value]) => [key, {}]));",
+                        json, expr, converted
+                    ));
+                }
+            }
+            code.close();
+        }
+        for oneof in &message.oneofs {
+            let property = format!("message.{}", oneof_property(oneof));
+            for field in &oneof.fields {
+                let json = field.json_name();
+                code.open(format!("if ({}?.$case === \"{}\")", property, json));
+                let converted =
+                    self.json_value(&field.field_type(), &format!("{}.{}", property, json));
+                code.line(format!("object.{} = {};", json, converted));
+                code.close();
+            }
+        }
+        code.line("return object;");
+        code.close_with("},");
+    }
+
+    fn enumeration(&mut self, code: &mut Code, context: &Context, e: &Enum, full_name: &str) {
+        let name = self.name(full_name, "");
+        // The first value with each number is the one decoded and written
+        // to JSON; later ones are aliases.
+        let mut canonical: BTreeMap<u32, &str> = BTreeMap::new();
+        for value in &e.fields {
+            canonical.entry(value.position).or_insert(&value.name);
+        }
+        let first = e.fields.first().map_or("", |value| value.name.as_str());
+
+        code.line("");
+        Self::jsdoc(code, context.comment(&[]));
+        if self.options.string_enums {
+            let names: Vec<String> = e
+                .fields
+                .iter()
+                .map(|value| format!("\"{}\"", value.name))
+                .collect();
+            code.line(format!("export type {} = {};", name, names.join(" | ")));
+        } else {
+            code.open(format!("export enum {}", name));
+            for (i, value) in e.fields.iter().enumerate() {
+                Self::jsdoc(code, context.comment(&[PathSegment::EnumValue(i)]));
+                code.line(format!("{} = {},", value.name, value.position as i32));
+            }
+            code.close();
+        }
+
+        let member = |value: &str| {
+            if self.options.string_enums {
+                format!("\"{}\"", value)
+            } else {
+                format!("{}.{}", name, value)
+            }
+        };
+        code.line("");
+        code.open(format!(
+            "export function {}FromJSON(object: any): {}",
+            name, name
+        ));
+        code.open("switch (object)");
+        let mut numbered = BTreeSet::new();
+        for value in &e.fields {
+            if numbered.insert(value.position) {
+                code.line(format!("case {}:", value.position as i32));
+            }
+            code.line(format!("case \"{}\":", value.name));
+            code.line(format!("  return {};", member(canonical[&value.position])));
+        }
+        code.line("default:");
+        if self.options.string_enums {
+            code.line(format!("  return {};", member(first)));
+        } else {
+            // Numeric enums keep values the schema doesn't name.
+            code.line(format!(
+                "  return typeof object === \"number\" ? object : {};",
+                member(first)
+            ));
+        }
+        code.close();
+        code.close();
+
+        code.line("");
+        if self.options.string_enums {
+            code.open(format!(
+                "export function {}ToJSON(object: {}): string",
+                name, name
+            ));
+            code.line("return object;");
+            code.close();
+            code.line("");
+            code.open(format!(
+                "export function {}ToNumber(object: {}): number",
+                name, name
+            ));
+            code.open("switch (object)");
+            for value in &e.fields {
+                code.line(format!("case \"{}\":", value.name));
+                code.line(format!("  return {};", value.position as i32));
+            }
+            code.close();
+            code.close();
+        } else {
+            code.open(format!(
+                "export function {}ToJSON(object: {}): string | number",
+                name, name
+            ));
+            code.open("switch (object)");
+            for value in canonical.values() {
+                code.line(format!("case {}:", member(value)));
+                code.line(format!("  return \"{}\";", value));
+            }
+            code.line("default:");
+            code.line("  return object;");
+            code.close();
+            code.close();
+        }
+    }
+}
+
+fn oneof_property(oneof: &Oneof) -> String {
+    MessageField::new(None, Type::Bool, oneof.name.clone(), 0).json_name()
+}
+
+impl Code {
+    /// Starts a `case` of a `switch`, ended by lowering the depth.
+    fn open_case(&mut self, number: u32) {
+        self.line(format!("case {}:", number));
+        self.depth += 1;
+    }
+
+    /// Ends a block and starts another, as in `} else {`.
+    fn close_open(&mut self, header: &str) {
+        self.depth -= 1;
+        self.line(format!("}} {} {{", header));
+        self.depth += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+    use crate::wire::WireMessage;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    // The Rust generator's output for the same schema, whose encoder the
+    // TypeScript output is checked against.
+    #[allow(dead_code)]
+    mod demo {
+        include!("../../tests/rust/demo.rs");
+    }
+
+    fn load() -> FileSet {
+        Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        let generated = generate(&load(), &["demo.proto"], &Options::default());
+        let expected = std::fs::read_to_string("tests/typescript/demo.ts").unwrap();
+        assert_eq!(generated["demo.ts"], expected);
+        assert_eq!(generated["runtime.ts"], RUNTIME);
+    }
+
+    /// Writes `generated` and `main.ts` to a new directory, with hooks
+    /// that let node run them by stripping their types.
+    fn node_dir(name: &str, generated: BTreeMap<String, String>, main: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("protos-ts-{}-{}", name, std::process::id()));
+        for (name, code) in generated {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, code).unwrap();
+        }
+        std::fs::write(dir.join("package.json"), "{\"type\": \"module\"}\n").unwrap();
+        std::fs::copy("tests/typescript/strip_types.mjs", dir.join("hooks.mjs")).unwrap();
+        std::fs::write(
+            dir.join("register.mjs"),
+            "import { register } from 'node:module';\n\
+             register('./hooks.mjs', import.meta.url);\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("main.ts"), main).unwrap();
+        dir
+    }
+
+    fn run_node(dir: &Path) {
+        let output = Command::new("node")
+            .args(["--import", "./register.mjs", "main.ts"])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn round_trips_under_node() {
+        if Command::new("node").arg("--version").output().is_err() {
+            eprintln!("skipping round_trips_under_node: node is not installed");
+            return;
+        }
+        let dir = node_dir(
+            "demo",
+            generate(&load(), &["demo.proto"], &Options::default()),
+            "import { readFileSync, writeFileSync } from 'node:fs';\n\
+             import { Person } from './demo';\n\
+             const person = Person.decode(new Uint8Array(readFileSync('in.bin')));\n\
+             if (person.contact?.$case !== 'office' || person.counts[1] !== '-9223372036854775808') {\n\
+             \x20 throw new Error(JSON.stringify(Person.toJSON(person)));\n\
+             }\n\
+             writeFileSync('out.bin', Person.encode(person).finish());\n\
+             const made = Person.fromJSON({ name: 'Bo', kind: 'KIND_FRIEND', phone: '555', homes: { 4: { city: 'Oslo' } } });\n\
+             writeFileSync('made.bin', Person.encode(made).finish());\n",
+        );
+        let address = demo::person::Address {
+            city: "Paris".to_string(),
+            owner: Some(Box::new(demo::Person {
+                name: "Owner".to_string(),
+                ..Default::default()
+            })),
+        };
+        let person = demo::Person {
+            name: "Ada".to_string(),
+            id: -7,
+            emails: vec!["ada@example.com".to_string(), String::new()],
+            scores: [("chess".to_string(), -3)].into(),
+            kind: 9,
+            contact: Some(demo::person::Contact::Office(Box::new(address.clone()))),
+            height: Some(0.5),
+            lucky: vec![7, u32::MAX],
+            counts: vec![1, i64::MIN],
+            photo: vec![0, 255],
+            friends: vec![demo::Person::default()],
+            homes: [(-1, address)].into(),
+            active: true,
+            ..Default::default()
+        };
+        std::fs::write(dir.join("in.bin"), person.encode_to_vec()).unwrap();
+        run_node(&dir);
+        let out = std::fs::read(dir.join("out.bin")).unwrap();
+        assert_eq!(demo::Person::decode(&out).unwrap(), person);
+        let made = demo::Person::decode(&std::fs::read(dir.join("made.bin")).unwrap()).unwrap();
+        assert_eq!(made.kind(), demo::person::Kind::Friend);
+        assert_eq!(
+            made.contact,
+            Some(demo::person::Contact::Phone("555".to_string()))
+        );
+        assert_eq!(made.homes[&4].city, "Oslo");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The well-known types a schema imports are generated with it.
+    #[test]
+    fn well_known_types_under_node() {
+        if Command::new("node").arg("--version").output().is_err() {
+            eprintln!("skipping well_known_types_under_node: node is not installed");
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("protos-ts-wkt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("event.proto"),
+            "syntax = \"proto3\"; import \"google/protobuf/timestamp.proto\";
+             message Event { google.protobuf.Timestamp at = 1; }
+             message Reader { Event event = 1; }
+             message Writer { Reader reader = 1; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["event.proto"])
+            .unwrap();
+        let generated = generate(&set, &["event.proto"], &Options::default());
+        assert!(generated.contains_key("google/protobuf/timestamp.ts"));
+        // Messages named like the runtime's classes don't shadow them.
+        let dir = node_dir(
+            "wkt",
+            generated,
+            "import { writeFileSync } from 'node:fs';\n\
+             import { Writer } from './event';\n\
+             const event = { at: { seconds: '5', nanos: 7 } };\n\
+             writeFileSync('out.bin', Writer.encode({ reader: { event } }).finish());\n",
+        );
+        run_node(&dir);
+        let out = std::fs::read(dir.join("out.bin")).unwrap();
+        assert_eq!(out, [0x0a, 8, 0x0a, 6, 0x0a, 4, 0x08, 5, 0x10, 7]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn string_enums_and_bigints() {
+        let options = Options::parse("long=bigint,enums=string").unwrap();
+        let generated = generate(&load(), &["demo.proto"], &options);
+        let code = &generated["demo.ts"];
+        assert!(code.contains("export type Color = \"RED\" | \"CRIMSON\" | \"GREEN\";\n"));
+        assert!(code.contains("  counts: bigint[];\n"));
+        assert!(code.contains("writer.tag(6, 0).int32(Person_KindToNumber(message.kind));"));
+        assert!(code.contains("message.kind = Person_KindFromJSON(reader.int32());"));
+        assert!(code.contains("counts: Array.isArray(object.counts) ? object.counts.map((value: any) => BigInt(value)) : [],"));
+        assert_eq!(
+            Options::parse("long=int").unwrap_err(),
+            "unknown typescript generator parameter long=int"
+        );
+    }
+
+    #[test]
+    fn imports_across_directories() {
+        let dir = std::env::temp_dir().join(format!("protos-ts-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shop")).unwrap();
+        std::fs::write(
+            dir.join("shop/order.proto"),
+            "syntax = \"proto3\"; package shop; import \"common.proto\";
+             message Order { common.Money total = 1; common.Currency currency = 2; }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common.proto"),
+            "syntax = \"proto3\"; package common;
+             message Money { int64 units = 1; } enum Currency { USD = 0; }",
+        )
+        .unwrap();
+        let set = Resolver::new()
+            .include(&dir)
+            .load(&["shop/order.proto", "common.proto"])
+            .unwrap();
+        let generated = generate(
+            &set,
+            &["shop/order.proto", "common.proto"],
+            &Options::default(),
+        );
+        let order = &generated["shop/order.ts"];
+        assert!(order.contains("import * as _runtime from \"../runtime\";\n"));
+        assert!(order.contains(
+            "import { Currency, CurrencyFromJSON, CurrencyToJSON, Money } from \"../common\";\n"
+        ));
+        assert!(generated["common.ts"].contains("from \"./runtime\";\n"));
+        assert_eq!(relative("a/b/c.ts", "a/d.ts"), "../d");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+// Runtime for code generated by `protos gen --lang typescript`. It needs
+// BigInt, so compile with a target of ES2020 or later.
+
+export type Long = bigint | number | string;
+
+export class Writer {
+  private readonly buf: number[] = [];
+
+  tag(fieldNumber: number, wireType: number): this {
+    return this.uint32(((fieldNumber << 3) | wireType) >>> 0);
+  }
+
+  uint32(value: number): this {
+    value >>>= 0;
+    while (value > 0x7f) {
+      this.buf.push((value & 0x7f) | 0x80);
+      value >>>= 7;
+    }
+    this.buf.push(value);
+    return this;
+  }
+
+  int32(value: number): this {
+    return value < 0 ? this.varint(BigInt.asUintN(64, BigInt(value))) : this.uint32(value);
+  }
+
+  sint32(value: number): this {
+    return this.uint32(((value << 1) ^ (value >> 31)) >>> 0);
+  }
+
+  uint64(value: Long): this {
+    return this.varint(BigInt.asUintN(64, BigInt(value)));
+  }
+
+  int64(value: Long): this {
+    return this.varint(BigInt.asUintN(64, BigInt(value)));
+  }
+
+  sint64(value: Long): this {
+    const signed = BigInt.asIntN(64, BigInt(value));
+    return this.varint(BigInt.asUintN(64, (signed << 1n) ^ (signed >> 63n)));
+  }
+
+  bool(value: boolean): this {
+    return this.uint32(value ? 1 : 0);
+  }
+
+  fixed32(value: number): this {
+    return this.fixed(4, (view) => view.setUint32(0, value, true));
+  }
+
+  sfixed32(value: number): this {
+    return this.fixed(4, (view) => view.setInt32(0, value, true));
+  }
+
+  float(value: number): this {
+    return this.fixed(4, (view) => view.setFloat32(0, value, true));
+  }
+
+  fixed64(value: Long): this {
+    return this.fixed(8, (view) => view.setBigUint64(0, BigInt.asUintN(64, BigInt(value)), true));
+  }
+
+  sfixed64(value: Long): this {
+    return this.fixed(8, (view) => view.setBigInt64(0, BigInt.asIntN(64, BigInt(value)), true));
+  }
+
+  double(value: number): this {
+    return this.fixed(8, (view) => view.setFloat64(0, value, true));
+  }
+
+  string(value: string): this {
+    return this.bytes(new TextEncoder().encode(value));
+  }
+
+  /** Writes a length-prefixed byte string. */
+  bytes(value: Uint8Array): this {
+    this.uint32(value.length);
+    for (const byte of value) {
+      this.buf.push(byte);
+    }
+    return this;
+  }
+
+  finish(): Uint8Array {
+    return Uint8Array.from(this.buf);
+  }
+
+  private varint(value: bigint): this {
+    while (value > 0x7fn) {
+      this.buf.push(Number(value & 0x7fn) | 0x80);
+      value >>= 7n;
+    }
+    this.buf.push(Number(value));
+    return this;
+  }
+
+  private fixed(size: number, write: (view: DataView) => void): this {
+    const bytes = new Uint8Array(size);
+    write(new DataView(bytes.buffer));
+    for (const byte of bytes) {
+      this.buf.push(byte);
+    }
+    return this;
+  }
+}
+
+export class Reader {
+  pos = 0;
+  private readonly view: DataView;
+
+  constructor(private readonly buf: Uint8Array) {
+    this.view = new DataView(buf.buffer, buf.byteOffset, buf.byteLength);
+  }
+
+  get done(): boolean {
+    return this.pos >= this.buf.length;
+  }
+
+  varint(): bigint {
+    let value = 0n;
+    for (let shift = 0n; shift < 70n; shift += 7n) {
+      const byte = this.buf[this.advance(1)];
+      value |= BigInt(byte & 0x7f) << shift;
+      if (byte < 0x80) {
+        return BigInt.asUintN(64, value);
+      }
+    }
+    throw new Error("varint is longer than ten bytes");
+  }
+
+  uint32(): number {
+    return Number(BigInt.asUintN(32, this.varint()));
+  }
+
+  int32(): number {
+    return Number(BigInt.asIntN(32, this.varint()));
+  }
+
+  sint32(): number {
+    const value = this.uint32();
+    return (value >>> 1) ^ -(value & 1);
+  }
+
+  uint64(): bigint {
+    return this.varint();
+  }
+
+  int64(): bigint {
+    return BigInt.asIntN(64, this.varint());
+  }
+
+  sint64(): bigint {
+    const value = this.varint();
+    return (value >> 1n) ^ -(value & 1n);
+  }
+
+  bool(): boolean {
+    return this.varint() !== 0n;
+  }
+
+  fixed32(): number {
+    return this.view.getUint32(this.advance(4), true);
+  }
+
+  sfixed32(): number {
+    return this.view.getInt32(this.advance(4), true);
+  }
+
+  float(): number {
+    return this.view.getFloat32(this.advance(4), true);
+  }
+
+  fixed64(): bigint {
+    return this.view.getBigUint64(this.advance(8), true);
+  }
+
+  sfixed64(): bigint {
+    return this.view.getBigInt64(this.advance(8), true);
+  }
+
+  double(): number {
+    return this.view.getFloat64(this.advance(8), true);
+  }
+
+  bytes(): Uint8Array {
+    const length = this.uint32();
+    const start = this.advance(length);
+    return this.buf.slice(start, start + length);
+  }
+
+  string(): string {
+    return new TextDecoder().decode(this.bytes());
+  }
+
+  /** Skips the value of a field whose tag has just been read. */
+  skip(wireType: number): void {
+    switch (wireType) {
+      case 0:
+        this.varint();
+        break;
+      case 1:
+        this.advance(8);
+        break;
+      case 2:
+        this.bytes();
+        break;
+      case 3: {
+        let tag: number;
+        while (((tag = this.uint32()) & 7) !== 4) {
+          this.skip(tag & 7);
+        }
+        break;
+      }
+      case 5:
+        this.advance(4);
+        break;
+      default:
+        throw new Error(`invalid wire type ${wireType}`);
+    }
+  }
+
+  private advance(count: number): number {
+    const start = this.pos;
+    if (start + count > this.buf.length) {
+      throw new Error("unexpected end of input");
+    }
+    this.pos += count;
+    return start;
+  }
+}
+
+export function isSet(value: unknown): boolean {
+  return value !== null && value !== undefined;
+}
+
+export function isObject(value: unknown): boolean {
+  return typeof value === "object" && value !== null && !Array.isArray(value);
+}
+
+export function base64Encode(bytes: Uint8Array): string {
+  let binary = "";
+  for (const byte of bytes) {
+    binary += String.fromCharCode(byte);
+  }
+  return btoa(binary);
+}
+
+/** Decodes standard or URL-safe base64, as proto3 JSON allows both. */
+export function base64Decode(text: string): Uint8Array {
+  const binary = atob(text.replace(/-/g, "+").replace(/_/g, "/"));
+  return Uint8Array.from(binary, (char) => char.charCodeAt(0));
+}
+
+/** A float for proto3 JSON, which spells non-finite values as strings. */
+export function jsonNumber(value: number): number | string {
+  return Number.isFinite(value) ? value : String(value);
+}
@@ -0,0 +1,3411 @@
+//! The `google/protobuf/descriptor.proto` model, built from linked files and
+//! serialised in the binary format `protoc --descriptor_set_out` writes.
+
+use crate::dynamic::{DynamicMessage, MapKey, Value};
+use crate::resolver::FileSet;
+use crate::wire::*;
+use crate::*;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileDescriptorSet {
+    pub file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileDescriptorProto {
+    pub name: Option<String>,
+    pub package: Option<String>,
+    pub dependency: Vec<String>,
+    pub public_dependency: Vec<i32>,
+    pub weak_dependency: Vec<i32>,
+    pub message_type: Vec<DescriptorProto>,
+    pub enum_type: Vec<EnumDescriptorProto>,
+    pub service: Vec<ServiceDescriptorProto>,
+    pub extension: Vec<FieldDescriptorProto>,
+    pub options: Vec<ProtoOption>,
+    /// Option fields `options` does not hold, such as custom options, as
+    /// encoded.
+    pub unknown_options: Vec<u8>,
+    pub source_code_info: Option<SourceCodeInfo>,
+    pub syntax: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DescriptorProto {
+    pub name: Option<String>,
+    pub field: Vec<FieldDescriptorProto>,
+    pub extension: Vec<FieldDescriptorProto>,
+    pub nested_type: Vec<DescriptorProto>,
+    pub enum_type: Vec<EnumDescriptorProto>,
+    pub extension_range: Vec<ExtensionRange>,
+    pub oneof_decl: Vec<OneofDescriptorProto>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+    pub reserved_range: Vec<ReservedRange>,
+    pub reserved_name: Vec<String>,
+}
+
+/// `DescriptorProto.ExtensionRange`. `end` is exclusive.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExtensionRange {
+    pub start: Option<i32>,
+    pub end: Option<i32>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+}
+
+/// `DescriptorProto.ReservedRange`, whose `end` is exclusive, or
+/// `EnumDescriptorProto.EnumReservedRange`, whose `end` is inclusive.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReservedRange {
+    pub start: Option<i32>,
+    pub end: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Optional = 1,
+    Required = 2,
+    Repeated = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Double = 1,
+    Float = 2,
+    Int64 = 3,
+    Uint64 = 4,
+    Int32 = 5,
+    Fixed64 = 6,
+    Fixed32 = 7,
+    Bool = 8,
+    String = 9,
+    Group = 10,
+    Message = 11,
+    Bytes = 12,
+    Uint32 = 13,
+    Enum = 14,
+    Sfixed32 = 15,
+    Sfixed64 = 16,
+    Sint32 = 17,
+    Sint64 = 18,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FieldDescriptorProto {
+    pub name: Option<String>,
+    pub number: Option<i32>,
+    pub label: Option<Label>,
+    pub r#type: Option<FieldType>,
+    pub type_name: Option<String>,
+    pub extendee: Option<String>,
+    pub default_value: Option<String>,
+    pub oneof_index: Option<i32>,
+    pub json_name: Option<String>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+    pub proto3_optional: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OneofDescriptorProto {
+    pub name: Option<String>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnumDescriptorProto {
+    pub name: Option<String>,
+    pub value: Vec<EnumValueDescriptorProto>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+    pub reserved_range: Vec<ReservedRange>,
+    pub reserved_name: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnumValueDescriptorProto {
+    pub name: Option<String>,
+    pub number: Option<i32>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ServiceDescriptorProto {
+    pub name: Option<String>,
+    pub method: Vec<MethodDescriptorProto>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MethodDescriptorProto {
+    pub name: Option<String>,
+    pub input_type: Option<String>,
+    pub output_type: Option<String>,
+    pub options: Vec<ProtoOption>,
+    pub unknown_options: Vec<u8>,
+    pub client_streaming: Option<bool>,
+    pub server_streaming: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceCodeInfo {
+    pub location: Vec<SourceCodeLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceCodeLocation {
+    pub path: Vec<i32>,
+    pub span: Vec<i32>,
+    pub leading_comments: Option<String>,
+    pub trailing_comments: Option<String>,
+    pub leading_detached_comments: Vec<String>,
+}
+
+/// How the value of a standard option is encoded in its options message.
+#[derive(Debug, Clone, Copy)]
+enum OptionKind {
+    Bool,
+    String,
+    Enum(&'static [(&'static str, i32)]),
+}
+
+type OptionTable = &'static [(&'static str, u32, OptionKind)];
+
+const FILE_OPTIONS: OptionTable = &[
+    ("java_package", 1, OptionKind::String),
+    ("java_outer_classname", 8, OptionKind::String),
+    (
+        "optimize_for",
+        9,
+        OptionKind::Enum(&[("SPEED", 1), ("CODE_SIZE", 2), ("LITE_RUNTIME", 3)]),
+    ),
+    ("java_multiple_files", 10, OptionKind::Bool),
+    ("go_package", 11, OptionKind::String),
+    ("cc_generic_services", 16, OptionKind::Bool),
+    ("java_generic_services", 17, OptionKind::Bool),
+    ("py_generic_services", 18, OptionKind::Bool),
+    ("java_generate_equals_and_hash", 20, OptionKind::Bool),
+    ("deprecated", 23, OptionKind::Bool),
+    ("java_string_check_utf8", 27, OptionKind::Bool),
+    ("cc_enable_arenas", 31, OptionKind::Bool),
+    ("objc_class_prefix", 36, OptionKind::String),
+    ("csharp_namespace", 37, OptionKind::String),
+    ("swift_prefix", 39, OptionKind::String),
+    ("php_class_prefix", 40, OptionKind::String),
+    ("php_namespace", 41, OptionKind::String),
+    ("php_metadata_namespace", 44, OptionKind::String),
+    ("ruby_package", 45, OptionKind::String),
+];
+
+const MESSAGE_OPTIONS: OptionTable = &[
+    ("message_set_wire_format", 1, OptionKind::Bool),
+    ("no_standard_descriptor_accessor", 2, OptionKind::Bool),
+    ("deprecated", 3, OptionKind::Bool),
+    ("map_entry", 7, OptionKind::Bool),
+];
+
+const FIELD_OPTIONS: OptionTable = &[
+    (
+        "ctype",
+        1,
+        OptionKind::Enum(&[("STRING", 0), ("CORD", 1), ("STRING_PIECE", 2)]),
+    ),
+    ("packed", 2, OptionKind::Bool),
+    ("deprecated", 3, OptionKind::Bool),
+    ("lazy", 5, OptionKind::Bool),
+    (
+        "jstype",
+        6,
+        OptionKind::Enum(&[("JS_NORMAL", 0), ("JS_STRING", 1), ("JS_NUMBER", 2)]),
+    ),
+    ("weak", 10, OptionKind::Bool),
+    ("unverified_lazy", 15, OptionKind::Bool),
+    ("debug_redact", 16, OptionKind::Bool),
+];
+
+const ONEOF_OPTIONS: OptionTable = &[];
+
+const ENUM_OPTIONS: OptionTable = &[
+    ("allow_alias", 2, OptionKind::Bool),
+    ("deprecated", 3, OptionKind::Bool),
+];
+
+/// `verification` and `declaration` are source-retention options, which
+/// `protoc` leaves out of descriptors.
+const EXTENSION_RANGE_OPTIONS: OptionTable = &[];
+
+const ENUM_VALUE_OPTIONS: OptionTable = &[("deprecated", 1, OptionKind::Bool)];
+
+const SERVICE_OPTIONS: OptionTable = &[("deprecated", 33, OptionKind::Bool)];
+
+const METHOD_OPTIONS: OptionTable = &[
+    ("deprecated", 33, OptionKind::Bool),
+    (
+        "idempotency_level",
+        34,
+        OptionKind::Enum(&[
+            ("IDEMPOTENCY_UNKNOWN", 0),
+            ("NO_SIDE_EFFECTS", 1),
+            ("IDEMPOTENT", 2),
+        ]),
+    ),
+];
+
+fn option_number(table: OptionTable, name: &str) -> Option<u32> {
+    table
+        .iter()
+        .find(|(option, _, _)| *option == name)
+        .map(|(_, number, _)| *number)
+}
+
+/// Encodes the options `table` knows about in field number order, the way
+/// `protoc` serialises them, followed by the fields in `unknown`.
+fn encode_options(
+    field_number: u32,
+    options: &[ProtoOption],
+    unknown: &[u8],
+    table: OptionTable,
+    buf: &mut Vec<u8>,
+) {
+    let mut known: Vec<(u32, OptionKind, &Constant)> = options
+        .iter()
+        .filter_map(|option| {
+            table
+                .iter()
+                .find(|(name, _, _)| *name == option.name)
+                .map(|(_, number, kind)| (*number, *kind, &option.value))
+        })
+        .collect();
+    if known.is_empty() && unknown.is_empty() {
+        return;
+    }
+    known.sort_by_key(|(number, _, _)| *number);
+
+    let mut message = vec![];
+    for (number, kind, value) in known {
+        match (kind, value) {
+            (OptionKind::Bool, Constant::Bool(b)) => put_varint(number, *b as u64, &mut message),
+            (OptionKind::String, Constant::String(s)) => put_string(number, s, &mut message),
+            (OptionKind::Enum(values), Constant::Ident(ident)) => {
+                if let Some((_, value)) = values.iter().find(|(name, _)| name == ident) {
+                    put_varint(number, *value as u64, &mut message);
+                }
+            }
+            (OptionKind::Enum(_), Constant::Int(value)) => {
+                put_varint(number, *value as u64, &mut message)
+            }
+            _ => {}
+        }
+    }
+    message.extend_from_slice(unknown);
+    encode_length_delimited(field_number, &message, buf);
+}
+
+pub(crate) fn put_varint(field_number: u32, value: u64, buf: &mut Vec<u8>) {
+    encode_key(field_number, WireType::Varint, buf);
+    encode_varint(value, buf);
+}
+
+pub(crate) fn put_int32(field_number: u32, value: i32, buf: &mut Vec<u8>) {
+    put_varint(field_number, value as i64 as u64, buf);
+}
+
+pub(crate) fn put_string(field_number: u32, value: &str, buf: &mut Vec<u8>) {
+    encode_length_delimited(field_number, value.as_bytes(), buf);
+}
+
+pub(crate) fn put_message(field_number: u32, buf: &mut Vec<u8>, encode: impl FnOnce(&mut Vec<u8>)) {
+    let mut message = vec![];
+    encode(&mut message);
+    encode_length_delimited(field_number, &message, buf);
+}
+
+fn put_packed(field_number: u32, values: &[i32], buf: &mut Vec<u8>) {
+    if values.is_empty() {
+        return;
+    }
+    let mut packed = vec![];
+    for value in values {
+        encode_varint(*value as i64 as u64, &mut packed);
+    }
+    encode_length_delimited(field_number, &packed, buf);
+}
+
+/// Calls back with the fully qualified name of an options message, the
+/// options this module knows for it, and an options list with the raw
+/// fields beside it.
+type OptionsVisitor<'a> =
+    dyn FnMut(&'static str, OptionTable, &mut Vec<ProtoOption>, &mut Vec<u8>) + 'a;
+
+impl FileDescriptorProto {
+    fn visit_options(&mut self, f: &mut OptionsVisitor) {
+        f(
+            "google.protobuf.FileOptions",
+            FILE_OPTIONS,
+            &mut self.options,
+            &mut self.unknown_options,
+        );
+        for message in &mut self.message_type {
+            message.visit_options(f);
+        }
+        for e in &mut self.enum_type {
+            e.visit_options(f);
+        }
+        for service in &mut self.service {
+            f(
+                "google.protobuf.ServiceOptions",
+                SERVICE_OPTIONS,
+                &mut service.options,
+                &mut service.unknown_options,
+            );
+            for method in &mut service.method {
+                f(
+                    "google.protobuf.MethodOptions",
+                    METHOD_OPTIONS,
+                    &mut method.options,
+                    &mut method.unknown_options,
+                );
+            }
+        }
+        for field in &mut self.extension {
+            f(
+                "google.protobuf.FieldOptions",
+                FIELD_OPTIONS,
+                &mut field.options,
+                &mut field.unknown_options,
+            );
+        }
+    }
+}
+
+impl DescriptorProto {
+    fn visit_options(&mut self, f: &mut OptionsVisitor) {
+        f(
+            "google.protobuf.MessageOptions",
+            MESSAGE_OPTIONS,
+            &mut self.options,
+            &mut self.unknown_options,
+        );
+        for field in self.field.iter_mut().chain(&mut self.extension) {
+            f(
+                "google.protobuf.FieldOptions",
+                FIELD_OPTIONS,
+                &mut field.options,
+                &mut field.unknown_options,
+            );
+        }
+        for nested in &mut self.nested_type {
+            nested.visit_options(f);
+        }
+        for e in &mut self.enum_type {
+            e.visit_options(f);
+        }
+        for range in &mut self.extension_range {
+            f(
+                "google.protobuf.ExtensionRangeOptions",
+                EXTENSION_RANGE_OPTIONS,
+                &mut range.options,
+                &mut range.unknown_options,
+            );
+        }
+        for oneof in &mut self.oneof_decl {
+            f(
+                "google.protobuf.OneofOptions",
+                ONEOF_OPTIONS,
+                &mut oneof.options,
+                &mut oneof.unknown_options,
+            );
+        }
+    }
+}
+
+impl EnumDescriptorProto {
+    fn visit_options(&mut self, f: &mut OptionsVisitor) {
+        f(
+            "google.protobuf.EnumOptions",
+            ENUM_OPTIONS,
+            &mut self.options,
+            &mut self.unknown_options,
+        );
+        for value in &mut self.value {
+            f(
+                "google.protobuf.EnumValueOptions",
+                ENUM_VALUE_OPTIONS,
+                &mut value.options,
+                &mut value.unknown_options,
+            );
+        }
+    }
+}
+
+/// Encodes an option as the field of `options_type` that `protoc` sets for
+/// it, or returns `None` if `set` declares no such field or extension or
+/// the value does not fit its type.
+fn interpret_option(
+    set: &Arc<FileSet>,
+    scope: &str,
+    options_type: &str,
+    option: &ProtoOption,
+) -> Option<Vec<u8>> {
+    let mut message = DynamicMessage::from_file_set(set.clone(), options_type).ok()?;
+    let (field, field_options, path) = match option.extension() {
+        Some((name, path)) => {
+            let extension = set.resolve_extension(scope, name)?;
+            if extension.extendee.strip_prefix('.') != Some(options_type) {
+                return None;
+            }
+            (extension.field.clone(), extension.options, path)
+        }
+        None => {
+            let (name, path) = option.name.split_once('.').unwrap_or((&option.name, ""));
+            let field = message.field_by_name(name)?.clone();
+            let field_options = message.descriptor().field_options.get(&field.position());
+            (field, field_options, path)
+        }
+    };
+    // Like `protoc`, leave source-retention options out of descriptors.
+    let retention = ProtoOption {
+        name: "retention".to_string(),
+        value: Constant::Ident("RETENTION_SOURCE".to_string()),
+    };
+    if field_options.is_some_and(|options| options.contains(&retention)) {
+        return Some(vec![]);
+    }
+    let mut value = option.value.clone();
+    for field in path.rsplit('.').filter(|field| !field.is_empty()) {
+        value = Constant::Aggregate(vec![(field.to_string(), value)]);
+    }
+    set_constant(&mut message, &field, &value)?;
+    Some(message.encode_to_vec())
+}
+
+/// Sets, or for repeated fields adds to, a field from an option value.
+fn set_constant(
+    message: &mut DynamicMessage,
+    field: &MessageField,
+    value: &Constant,
+) -> Option<()> {
+    let number = field.position();
+    match field.field_type() {
+        Type::Map(key_type, value_type) => {
+            let Constant::Aggregate(entry) = value else {
+                return None;
+            };
+            let part = |name: &str, t: &Type| match entry.iter().find(|(n, _)| n == name) {
+                Some((_, value)) => to_value(message, t, value),
+                None => Some(message.default_value(t)),
+            };
+            let key = MapKey::from_value(part("key", &key_type)?)?;
+            let value = part("value", &value_type)?;
+            message.insert_by_number(number, key, value).ok()
+        }
+        t if field.is_repeated() => {
+            let value = to_value(message, &t, value)?;
+            message.add_by_number(number, value).ok()
+        }
+        t => {
+            let value = to_value(message, &t, value)?;
+            message.set_by_number(number, value).ok()
+        }
+    }
+}
+
+fn to_value(message: &DynamicMessage, t: &Type, value: &Constant) -> Option<Value> {
+    Some(match (t, value) {
+        (Type::Bool, Constant::Bool(b)) => Value::Bool(*b),
+        (Type::Int32 | Type::Sint32 | Type::Sfixed32, Constant::Int(i)) => {
+            Value::I32((*i).try_into().ok()?)
+        }
+        (Type::Int64 | Type::Sint64 | Type::Sfixed64, Constant::Int(i)) => {
+            Value::I64((*i).try_into().ok()?)
+        }
+        (Type::Uint32 | Type::Fixed32, Constant::Int(i)) => Value::U32((*i).try_into().ok()?),
+        (Type::Uint64 | Type::Fixed64, Constant::Int(i)) => Value::U64((*i).try_into().ok()?),
+        (Type::Double, Constant::Int(i)) => Value::F64(*i as f64),
+        (Type::Float, Constant::Int(i)) => Value::F32(*i as f32),
+        (Type::Double, Constant::Float(f) | Constant::Ident(f)) => Value::F64(f.parse().ok()?),
+        (Type::Float, Constant::Float(f) | Constant::Ident(f)) => Value::F32(f.parse().ok()?),
+        (Type::String, Constant::String(s)) => Value::String(s.clone()),
+        (Type::Bytes, Constant::String(s)) => Value::Bytes(s.clone().into_bytes()),
+        (Type::Bytes, Constant::Bytes(b)) => Value::Bytes(b.clone()),
+        (Type::Enum(name), Constant::Ident(ident)) => {
+            let e = message.file_set().find_enum(name)?;
+            let value = e.fields.iter().find(|value| value.name == *ident)?;
+            Value::Enum(value.position as i32)
+        }
+        (Type::Enum(_), Constant::Int(i)) => Value::Enum((*i).try_into().ok()?),
+        (Type::Message(name), Constant::Aggregate(fields)) => {
+            let mut nested = message.new_message(name).ok()?;
+            for (name, value) in fields {
+                let name = name.trim_start_matches('[').trim_end_matches(']');
+                let field = nested.field_by_name(name)?.clone();
+                set_constant(&mut nested, &field, value)?;
+            }
+            Value::Message(nested)
+        }
+        _ => return None,
+    })
+}
+
+/// Reads options out of the raw fields of an options message, naming each
+/// after its field or, in parentheses, its extension. Fields `set` does not
+/// declare are left out.
+fn read_options(
+    set: &Arc<FileSet>,
+    options_type: &str,
+    mut unknown: &[u8],
+) -> Vec<(u32, ProtoOption)> {
+    let mut ret = vec![];
+    while !unknown.is_empty() {
+        let start = unknown;
+        let Ok((number, wire_type)) = decode_key(&mut unknown) else {
+            break;
+        };
+        if skip_field(number, wire_type, &mut unknown).is_err() {
+            break;
+        }
+        let Ok(mut message) = DynamicMessage::from_file_set(set.clone(), options_type) else {
+            break;
+        };
+        if message
+            .merge(&start[..start.len() - unknown.len()])
+            .is_err()
+        {
+            continue;
+        }
+        for (field, value) in message.fields() {
+            let name = match message.extension_name(number) {
+                Some(name) => format!("({})", name.trim_start_matches('.')),
+                None => field.name().to_string(),
+            };
+            let values = match value {
+                Value::List(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                let value = to_constant(set, &field.field_type(), value);
+                let name = name.clone();
+                ret.push((number, ProtoOption { name, value }));
+            }
+        }
+    }
+    ret
+}
+
+/// Writes a decoded value the way the parser reads it from an option.
+fn to_constant(set: &FileSet, t: &Type, value: &Value) -> Constant {
+    let float = |finite: bool, integral: bool, text: String| match (finite, integral) {
+        (true, true) => text.parse().map_or(Constant::Float(text), Constant::Int),
+        (true, false) => Constant::Float(text),
+        (false, _) => Constant::Ident(text.to_ascii_lowercase()),
+    };
+    match value {
+        Value::Bool(b) => Constant::Bool(*b),
+        Value::I32(i) => Constant::Int(*i as i128),
+        Value::I64(i) => Constant::Int(*i as i128),
+        Value::U32(u) => Constant::Int(*u as i128),
+        Value::U64(u) => Constant::Int(*u as i128),
+        Value::F32(f) => float(f.is_finite(), f.fract() == 0.0, f.to_string()),
+        Value::F64(f) => float(f.is_finite(), f.fract() == 0.0, f.to_string()),
+        Value::String(s) => Constant::String(s.clone()),
+        Value::Bytes(bytes) => match String::from_utf8(bytes.clone()) {
+            Ok(s) => Constant::String(s),
+            Err(err) => Constant::Bytes(err.into_bytes()),
+        },
+        Value::Enum(number) => match t {
+            Type::Enum(name) => set
+                .find_enum(name)
+                .and_then(|e| e.fields.iter().find(|v| v.position as i32 == *number))
+                .map_or(Constant::Int(*number as i128), |v| {
+                    Constant::Ident(v.name.clone())
+                }),
+            _ => Constant::Int(*number as i128),
+        },
+        Value::Message(message) => {
+            let mut fields = vec![];
+            for (field, value) in message.fields() {
+                let name = match message.extension_name(field.position()) {
+                    Some(name) => format!("[{}]", name.trim_start_matches('.')),
+                    None => field.name().to_string(),
+                };
+                match (field.field_type(), value) {
+                    (Type::Map(key_type, value_type), Value::Map(entries)) => {
+                        for (key, value) in entries {
+                            let entry = vec![
+                                (
+                                    "key".to_string(),
+                                    to_constant(set, &key_type, &key.clone().into()),
+                                ),
+                                ("value".to_string(), to_constant(set, &value_type, value)),
+                            ];
+                            fields.push((name.clone(), Constant::Aggregate(entry)));
+                        }
+                    }
+                    (t, Value::List(values)) => {
+                        for value in values {
+                            fields.push((name.clone(), to_constant(set, &t, value)));
+                        }
+                    }
+                    (t, value) => fields.push((name, to_constant(set, &t, value))),
+                }
+            }
+            Constant::Aggregate(fields)
+        }
+        Value::List(_) | Value::Map(_) => Constant::Aggregate(vec![]),
+    }
+}
+
+impl FileDescriptorSet {
+    /// Builds the descriptor set for every file in `set`, imports first,
+    /// like `protoc --include_imports`. Options this module has no table
+    /// for, such as custom options, are encoded by looking their fields up
+    /// in `set`; those it does not declare are left out.
+    pub fn from_file_set(set: &FileSet, include_source_info: bool) -> Self {
+        let mut shared = None;
+        let file = set
+            .files
+            .iter()
+            .map(|file| {
+                let mut descriptor = FileDescriptorProto::from_file(file, None);
+                let scope = file.package.clone().unwrap_or_default();
+                descriptor.visit_options(&mut |options_type, table, options, unknown| {
+                    let mut interpreted = vec![];
+                    options.retain(|option| {
+                        if option_number(table, &option.name).is_some() {
+                            return true;
+                        }
+                        let shared = shared.get_or_insert_with(|| Arc::new(set.clone()));
+                        match interpret_option(shared, &scope, options_type, option) {
+                            Some(bytes) => {
+                                interpreted.push(bytes);
+                                false
+                            }
+                            None => true,
+                        }
+                    });
+                    // In field number order, as `protoc` writes them.
+                    interpreted
+                        .sort_by_key(|bytes| decode_key(&mut &bytes[..]).ok().map(|key| key.0));
+                    unknown.extend(interpreted.into_iter().flatten());
+                });
+                if include_source_info {
+                    descriptor.source_code_info = set.locations.get(&file.name).map(|locations| {
+                        SourceCodeInfo::from_locations(file, locations, Some(set))
+                    });
+                }
+                descriptor
+            })
+            .collect();
+        FileDescriptorSet { file }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for file in &self.file {
+            put_message(1, buf, |buf| file.encode(buf));
+        }
+    }
+
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+impl FileDescriptorProto {
+    /// Converts a linked file. Types that were never linked are emitted
+    /// with a `type_name` but no `type`, as `protoc`'s parser does.
+    pub fn from_file(file: &ProtoFile, locations: Option<&[Location]>) -> Self {
+        let proto3 = file.syntax.as_deref() == Some("proto3");
+        let scope = match &file.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+
+        let mut public_dependency = vec![];
+        let mut weak_dependency = vec![];
+        for (i, import) in file.imports.iter().enumerate() {
+            if import.public {
+                public_dependency.push(i as i32);
+            }
+            if import.weak {
+                weak_dependency.push(i as i32);
+            }
+        }
+
+        FileDescriptorProto {
+            name: Some(file.name.clone()),
+            package: file.package.clone(),
+            dependency: file.imports.iter().map(|i| i.path.clone()).collect(),
+            public_dependency,
+            weak_dependency,
+            message_type: file
+                .messages
+                .iter()
+                .map(|m| DescriptorProto::from_message(m, &scope, proto3))
+                .collect(),
+            enum_type: file
+                .enums
+                .iter()
+                .map(EnumDescriptorProto::from_enum)
+                .collect(),
+            service: file
+                .services
+                .iter()
+                .map(ServiceDescriptorProto::from_service)
+                .collect(),
+            extension: extension_fields(&file.extends, &scope, proto3),
+            options: file.options.clone(),
+            unknown_options: vec![],
+            source_code_info: locations
+                .map(|locations| SourceCodeInfo::from_locations(file, locations, None)),
+            syntax: file.syntax.clone().filter(|syntax| syntax != "proto2"),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        if let Some(package) = &self.package {
+            put_string(2, package, buf);
+        }
+        for dependency in &self.dependency {
+            put_string(3, dependency, buf);
+        }
+        for message in &self.message_type {
+            put_message(4, buf, |buf| message.encode(buf));
+        }
+        for e in &self.enum_type {
+            put_message(5, buf, |buf| e.encode(buf));
+        }
+        for service in &self.service {
+            put_message(6, buf, |buf| service.encode(buf));
+        }
+        for extension in &self.extension {
+            put_message(7, buf, |buf| extension.encode(buf));
+        }
+        encode_options(8, &self.options, &self.unknown_options, FILE_OPTIONS, buf);
+        if let Some(info) = &self.source_code_info {
+            put_message(9, buf, |buf| info.encode(buf));
+        }
+        for dependency in &self.public_dependency {
+            put_int32(10, *dependency, buf);
+        }
+        for dependency in &self.weak_dependency {
+            put_int32(11, *dependency, buf);
+        }
+        if let Some(syntax) = &self.syntax {
+            put_string(12, syntax, buf);
+        }
+    }
+}
+
+/// Flattens `extend` blocks into the fields they declare, each naming its
+/// extendee.
+fn extension_fields(extends: &[Extend], scope: &str, proto3: bool) -> Vec<FieldDescriptorProto> {
+    let mut ret = vec![];
+    for extend in extends {
+        for field in &extend.fields {
+            let mut descriptor = FieldDescriptorProto::from_field(
+                field,
+                extend.field_options.get(&field.position()),
+                scope,
+            );
+            descriptor.extendee = Some(extend.extendee.clone());
+            if !proto3 {
+                descriptor.proto3_optional = None;
+            }
+            ret.push(descriptor);
+        }
+    }
+    ret
+}
+
+/// Regroups extension fields into `extend` blocks. Consecutive fields with
+/// the same extendee share a block.
+fn to_extends(
+    fields: &[FieldDescriptorProto],
+    proto3: bool,
+    cx: &Decoder,
+    path: &[i32],
+) -> Vec<Extend> {
+    let mut extends: Vec<Extend> = vec![];
+    for (i, f) in fields.iter().enumerate() {
+        let extendee = f.extendee.clone().unwrap_or_default();
+        if extends.last().is_none_or(|e| e.extendee != extendee) {
+            extends.push(Extend {
+                extendee,
+                ..Default::default()
+            });
+        }
+        let extend = extends.last_mut().unwrap();
+        let field = f.to_field(proto3, false);
+        let options = f.to_field_options(&field, cx, &[path, &[i as i32]].concat());
+        if !options.is_empty() {
+            extend.field_options.insert(field.position(), options);
+        }
+        extend.fields.push(field);
+    }
+    extends
+}
+
+/// The name `protoc` gives the synthetic message backing a map field:
+/// `phone_numbers` becomes `PhoneNumbersEntry`.
+pub fn map_entry_name(field_name: &str) -> String {
+    let mut ret = String::new();
+    let mut capitalize_next = true;
+    for c in field_name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            ret.push(c.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            ret.push(c);
+        }
+    }
+    ret.push_str("Entry");
+    ret
+}
+
+/// A field as a descriptor lists it: one of a message's `fields`, or field
+/// `.1` of oneof `.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldIndex {
+    Field(usize),
+    Member(usize, usize),
+}
+
+/// The fields of `message` in declaration order, as `protoc` lists them.
+fn field_order(message: &Message) -> Vec<FieldIndex> {
+    let mut ret = vec![];
+    for declaration in message.declarations() {
+        match declaration {
+            Declaration::Field(i) => ret.push(FieldIndex::Field(i)),
+            Declaration::Oneof(o) => {
+                ret.extend((0..message.oneofs[o].fields.len()).map(|i| FieldIndex::Member(o, i)))
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+/// A nested type as a descriptor lists it: a nested message, or the entry
+/// of the map field at that index of `fields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Nested {
+    Message(usize),
+    MapEntry(usize),
+}
+
+/// The nested types of `message` in declaration order, each map entry
+/// where its field was declared.
+fn nested_order(message: &Message) -> Vec<Nested> {
+    let mut ret = vec![];
+    for declaration in message.declarations() {
+        match declaration {
+            Declaration::Message(i) => ret.push(Nested::Message(i)),
+            Declaration::Field(i) if matches!(message.fields[i], MessageField::Map(..)) => {
+                ret.push(Nested::MapEntry(i))
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+impl DescriptorProto {
+    fn from_message(message: &Message, scope: &str, proto3: bool) -> Self {
+        let scope = format!("{}.{}", scope, message.name);
+
+        let mut field = vec![];
+        for index in field_order(message) {
+            field.push(match index {
+                FieldIndex::Field(i) => {
+                    let f = &message.fields[i];
+                    let options = message.field_options.get(&f.position());
+                    FieldDescriptorProto::from_field(f, options, &scope)
+                }
+                FieldIndex::Member(o, i) => {
+                    let oneof = &message.oneofs[o];
+                    let f = &oneof.fields[i];
+                    let options = oneof.field_options.get(&f.position());
+                    let mut descriptor = FieldDescriptorProto::from_field(f, options, &scope);
+                    descriptor.oneof_index = Some(o as i32);
+                    descriptor
+                }
+            });
+        }
+
+        let mut oneof_decl: Vec<_> = message
+            .oneofs
+            .iter()
+            .map(|oneof| OneofDescriptorProto {
+                name: Some(oneof.name.clone()),
+                options: oneof.options.clone(),
+                unknown_options: vec![],
+            })
+            .collect();
+
+        if proto3 {
+            for f in field.iter_mut() {
+                if f.proto3_optional == Some(true) {
+                    f.oneof_index = Some(oneof_decl.len() as i32);
+                    oneof_decl.push(OneofDescriptorProto {
+                        name: Some(format!("_{}", f.name.clone().unwrap_or_default())),
+                        options: vec![],
+                        unknown_options: vec![],
+                    });
+                }
+            }
+        } else {
+            for f in field.iter_mut() {
+                f.proto3_optional = None;
+            }
+        }
+
+        let nested_type = nested_order(message)
+            .into_iter()
+            .map(|nested| match nested {
+                Nested::Message(i) => {
+                    DescriptorProto::from_message(&message.messages[i], &scope, proto3)
+                }
+                Nested::MapEntry(i) => match &message.fields[i] {
+                    MessageField::Map(key, value, name, _) => {
+                        DescriptorProto::map_entry(key, value, name)
+                    }
+                    _ => unreachable!("only map fields have entries"),
+                },
+            })
+            .collect();
+
+        DescriptorProto {
+            name: Some(message.name.clone()),
+            field,
+            extension: extension_fields(&message.extends, &scope, proto3),
+            nested_type,
+            enum_type: message
+                .enums
+                .iter()
+                .map(EnumDescriptorProto::from_enum)
+                .collect(),
+            extension_range: message
+                .extension_ranges
+                .iter()
+                .map(|(start, end)| ExtensionRange {
+                    start: Some(*start as i32),
+                    end: Some(*end as i32 + 1),
+                    options: message
+                        .extension_range_options
+                        .get(start)
+                        .cloned()
+                        .unwrap_or_default(),
+                    unknown_options: vec![],
+                })
+                .collect(),
+            oneof_decl,
+            options: message.options.clone(),
+            unknown_options: vec![],
+            reserved_range: message
+                .reserved_ranges
+                .iter()
+                .map(|(start, end)| ReservedRange {
+                    start: Some(*start as i32),
+                    end: Some(*end as i32 + 1),
+                })
+                .collect(),
+            reserved_name: message.reserved_names.clone(),
+        }
+    }
+
+    fn map_entry(key: &Type, value: &Type, field_name: &str) -> Self {
+        let entry_field = |name: &str, number: i32, t: &Type| {
+            let mut field = FieldDescriptorProto {
+                name: Some(name.to_string()),
+                number: Some(number),
+                label: Some(Label::Optional),
+                json_name: Some(name.to_string()),
+                ..Default::default()
+            };
+            field.set_type(t);
+            field
+        };
+        DescriptorProto {
+            name: Some(map_entry_name(field_name)),
+            field: vec![entry_field("key", 1, key), entry_field("value", 2, value)],
+            options: vec![ProtoOption {
+                name: "map_entry".to_string(),
+                value: Constant::Bool(true),
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        for field in &self.field {
+            put_message(2, buf, |buf| field.encode(buf));
+        }
+        for nested in &self.nested_type {
+            put_message(3, buf, |buf| nested.encode(buf));
+        }
+        for e in &self.enum_type {
+            put_message(4, buf, |buf| e.encode(buf));
+        }
+        for range in &self.extension_range {
+            put_message(5, buf, |buf| range.encode(buf));
+        }
+        for extension in &self.extension {
+            put_message(6, buf, |buf| extension.encode(buf));
+        }
+        encode_options(
+            7,
+            &self.options,
+            &self.unknown_options,
+            MESSAGE_OPTIONS,
+            buf,
+        );
+        for oneof in &self.oneof_decl {
+            put_message(8, buf, |buf| oneof.encode(buf));
+        }
+        for range in &self.reserved_range {
+            put_message(9, buf, |buf| range.encode(buf));
+        }
+        for name in &self.reserved_name {
+            put_string(10, name, buf);
+        }
+    }
+}
+
+impl ExtensionRange {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(start) = self.start {
+            put_int32(1, start, buf);
+        }
+        if let Some(end) = self.end {
+            put_int32(2, end, buf);
+        }
+        encode_options(
+            3,
+            &self.options,
+            &self.unknown_options,
+            EXTENSION_RANGE_OPTIONS,
+            buf,
+        );
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut range = ExtensionRange::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::Varint) => range.start = Some(decode_int32(&mut buf)?),
+                (2, WireType::Varint) => range.end = Some(decode_int32(&mut buf)?),
+                (3, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    EXTENSION_RANGE_OPTIONS,
+                    &mut range.options,
+                    &mut range.unknown_options,
+                )?,
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(range)
+    }
+}
+
+impl ReservedRange {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(start) = self.start {
+            put_int32(1, start, buf);
+        }
+        if let Some(end) = self.end {
+            put_int32(2, end, buf);
+        }
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut range = ReservedRange::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::Varint) => range.start = Some(decode_int32(&mut buf)?),
+                (2, WireType::Varint) => range.end = Some(decode_int32(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(range)
+    }
+}
+
+impl FieldDescriptorProto {
+    fn from_field(field: &MessageField, options: Option<&Vec<ProtoOption>>, scope: &str) -> Self {
+        let label = match (field, field.frequency()) {
+            (MessageField::Map(..), _) => Label::Repeated,
+            (_, Some(Frequency::Repeated)) => Label::Repeated,
+            (_, Some(Frequency::Required)) => Label::Required,
+            _ => Label::Optional,
+        };
+
+        let mut descriptor = FieldDescriptorProto {
+            name: Some(field.name().to_string()),
+            number: Some(field.position() as i32),
+            label: Some(label),
+            json_name: Some(field.json_name()),
+            proto3_optional: (field.frequency() == Some(&Frequency::Optional)).then_some(true),
+            ..Default::default()
+        };
+
+        match field {
+            MessageField::Map(_, _, name, _) => {
+                descriptor.r#type = Some(FieldType::Message);
+                descriptor.type_name = Some(format!("{}.{}", scope, map_entry_name(name)));
+            }
+            _ => descriptor.set_type(&field.field_type()),
+        }
+
+        for option in options.into_iter().flatten() {
+            match (option.name.as_str(), &option.value) {
+                ("json_name", Constant::String(name)) => descriptor.json_name = Some(name.clone()),
+                ("default", value) => {
+                    let bytes = descriptor.r#type == Some(FieldType::Bytes);
+                    descriptor.default_value = Some(default_value(value, bytes));
+                }
+                _ => descriptor.options.push(option.clone()),
+            }
+        }
+
+        descriptor
+    }
+
+    fn set_type(&mut self, t: &Type) {
+        let (field_type, type_name) = match t {
+            Type::Double => (Some(FieldType::Double), None),
+            Type::Float => (Some(FieldType::Float), None),
+            Type::Int64 => (Some(FieldType::Int64), None),
+            Type::Uint64 => (Some(FieldType::Uint64), None),
+            Type::Int32 => (Some(FieldType::Int32), None),
+            Type::Fixed64 => (Some(FieldType::Fixed64), None),
+            Type::Fixed32 => (Some(FieldType::Fixed32), None),
+            Type::Bool => (Some(FieldType::Bool), None),
+            Type::String => (Some(FieldType::String), None),
+            Type::Bytes => (Some(FieldType::Bytes), None),
+            Type::Uint32 => (Some(FieldType::Uint32), None),
+            Type::Sfixed32 => (Some(FieldType::Sfixed32), None),
+            Type::Sfixed64 => (Some(FieldType::Sfixed64), None),
+            Type::Sint32 => (Some(FieldType::Sint32), None),
+            Type::Sint64 => (Some(FieldType::Sint64), None),
+            Type::Message(name) => (Some(FieldType::Message), Some(name.clone())),
+            Type::Enum(name) => (Some(FieldType::Enum), Some(name.clone())),
+            Type::Named(name) => (None, Some(name.clone())),
+            Type::Map(..) => (Some(FieldType::Message), None),
+        };
+        self.r#type = field_type;
+        self.type_name = type_name;
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        if let Some(extendee) = &self.extendee {
+            put_string(2, extendee, buf);
+        }
+        if let Some(number) = self.number {
+            put_int32(3, number, buf);
+        }
+        if let Some(label) = self.label {
+            put_varint(4, label as u64, buf);
+        }
+        if let Some(field_type) = self.r#type {
+            put_varint(5, field_type as u64, buf);
+        }
+        if let Some(type_name) = &self.type_name {
+            put_string(6, type_name, buf);
+        }
+        if let Some(default_value) = &self.default_value {
+            put_string(7, default_value, buf);
+        }
+        encode_options(8, &self.options, &self.unknown_options, FIELD_OPTIONS, buf);
+        if let Some(oneof_index) = self.oneof_index {
+            put_int32(9, oneof_index, buf);
+        }
+        if let Some(json_name) = &self.json_name {
+            put_string(10, json_name, buf);
+        }
+        if let Some(proto3_optional) = self.proto3_optional {
+            put_varint(17, proto3_optional as u64, buf);
+        }
+    }
+}
+
+/// A default as `FieldDescriptorProto` spells it: bytes C-escaped, other
+/// values as written.
+fn default_value(value: &Constant, bytes: bool) -> String {
+    match value {
+        Constant::Ident(ident) => ident.clone(),
+        Constant::Int(int) => int.to_string(),
+        Constant::Float(float) => float.clone(),
+        Constant::String(s) if bytes => c_escape(s.as_bytes()),
+        Constant::String(s) => s.clone(),
+        Constant::Bytes(b) if bytes => c_escape(b),
+        Constant::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+        Constant::Bool(b) => b.to_string(),
+        Constant::Aggregate(_) => String::new(),
+    }
+}
+
+/// Escapes bytes the way protoc's `CEscape` does.
+fn c_escape(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for &byte in bytes {
+        match byte {
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            b'"' => s.push_str("\\\""),
+            b'\'' => s.push_str("\\'"),
+            b'\\' => s.push_str("\\\\"),
+            b' '..=b'~' => s.push(byte as char),
+            _ => s.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    s
+}
+
+/// Reverses [`c_escape`].
+fn c_unescape(text: &str) -> Vec<u8> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut bytes = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            if let Some(used) = text_format::unescape(&chars[i + 1..], &mut bytes) {
+                i += 1 + used;
+                continue;
+            }
+        }
+        bytes.extend_from_slice(chars[i].encode_utf8(&mut [0; 4]).as_bytes());
+        i += 1;
+    }
+    bytes
+}
+
+impl OneofDescriptorProto {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        encode_options(2, &self.options, &self.unknown_options, ONEOF_OPTIONS, buf);
+    }
+}
+
+impl EnumDescriptorProto {
+    fn from_enum(e: &Enum) -> Self {
+        EnumDescriptorProto {
+            name: Some(e.name.clone()),
+            value: e
+                .fields
+                .iter()
+                .map(|field| EnumValueDescriptorProto {
+                    name: Some(field.name.clone()),
+                    number: Some(field.position as i32),
+                    options: e
+                        .value_options
+                        .get(&field.name)
+                        .cloned()
+                        .unwrap_or_default(),
+                    unknown_options: vec![],
+                })
+                .collect(),
+            options: e.options.clone(),
+            unknown_options: vec![],
+            reserved_range: e
+                .reserved_ranges
+                .iter()
+                .map(|(start, end)| ReservedRange {
+                    start: Some(*start as i32),
+                    end: Some(*end as i32),
+                })
+                .collect(),
+            reserved_name: e.reserved_names.clone(),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        for value in &self.value {
+            put_message(2, buf, |buf| value.encode(buf));
+        }
+        encode_options(3, &self.options, &self.unknown_options, ENUM_OPTIONS, buf);
+        for range in &self.reserved_range {
+            put_message(4, buf, |buf| range.encode(buf));
+        }
+        for name in &self.reserved_name {
+            put_string(5, name, buf);
+        }
+    }
+}
+
+impl EnumValueDescriptorProto {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        if let Some(number) = self.number {
+            put_int32(2, number, buf);
+        }
+        encode_options(
+            3,
+            &self.options,
+            &self.unknown_options,
+            ENUM_VALUE_OPTIONS,
+            buf,
+        );
+    }
+}
+
+impl ServiceDescriptorProto {
+    fn from_service(service: &Service) -> Self {
+        ServiceDescriptorProto {
+            name: Some(service.name.clone()),
+            method: service
+                .methods
+                .iter()
+                .map(|method| MethodDescriptorProto {
+                    name: Some(method.name.clone()),
+                    input_type: Some(method.input_type.clone()),
+                    output_type: Some(method.output_type.clone()),
+                    options: method.options.clone(),
+                    unknown_options: vec![],
+                    client_streaming: method.client_streaming.then_some(true),
+                    server_streaming: method.server_streaming.then_some(true),
+                })
+                .collect(),
+            options: service.options.clone(),
+            unknown_options: vec![],
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        for method in &self.method {
+            put_message(2, buf, |buf| method.encode(buf));
+        }
+        encode_options(
+            3,
+            &self.options,
+            &self.unknown_options,
+            SERVICE_OPTIONS,
+            buf,
+        );
+    }
+}
+
+impl MethodDescriptorProto {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        if let Some(input_type) = &self.input_type {
+            put_string(2, input_type, buf);
+        }
+        if let Some(output_type) = &self.output_type {
+            put_string(3, output_type, buf);
+        }
+        encode_options(4, &self.options, &self.unknown_options, METHOD_OPTIONS, buf);
+        if let Some(client_streaming) = self.client_streaming {
+            put_varint(5, client_streaming as u64, buf);
+        }
+        if let Some(server_streaming) = self.server_streaming {
+            put_varint(6, server_streaming as u64, buf);
+        }
+    }
+}
+
+impl SourceCodeInfo {
+    /// Translates the parser's locations, whose paths index into the AST,
+    /// into descriptor paths. Custom options are located at the extensions
+    /// `set` declares for them.
+    pub fn from_locations(file: &ProtoFile, locations: &[Location], set: Option<&FileSet>) -> Self {
+        let mut location = vec![];
+        for l in locations {
+            let Location { span, .. } = l;
+            let span = if span.start_line == span.end_line {
+                vec![span.start_line, span.start_column, span.end_column]
+            } else {
+                vec![
+                    span.start_line,
+                    span.start_column,
+                    span.end_line,
+                    span.end_column,
+                ]
+            };
+            // An option statement's comments belong to the option, not to
+            // the options message recorded before it.
+            let paths = descriptor_paths(file, &l.path, set);
+            let last = paths.len().saturating_sub(1);
+            for (i, path) in paths.into_iter().enumerate() {
+                let commented = i == last;
+                location.push(SourceCodeLocation {
+                    path,
+                    span: span.clone(),
+                    leading_comments: l.leading_comments.clone().filter(|_| commented),
+                    trailing_comments: l.trailing_comments.clone().filter(|_| commented),
+                    leading_detached_comments: match commented {
+                        true => l.leading_detached_comments.clone(),
+                        false => vec![],
+                    },
+                });
+            }
+        }
+        SourceCodeInfo { location }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for location in &self.location {
+            put_message(1, buf, |buf| location.encode(buf));
+        }
+    }
+}
+
+impl SourceCodeLocation {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        put_packed(1, &self.path, buf);
+        put_packed(2, &self.span, buf);
+        if let Some(comments) = &self.leading_comments {
+            put_string(3, comments, buf);
+        }
+        if let Some(comments) = &self.trailing_comments {
+            put_string(4, comments, buf);
+        }
+        for comments in &self.leading_detached_comments {
+            put_string(6, comments, buf);
+        }
+    }
+}
+
+/// What an AST path points at while it is being translated.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    File(&'a ProtoFile),
+    Message(&'a Message),
+    Oneof(&'a Message, &'a Oneof, usize),
+    Field(&'a MessageField, Option<&'a Vec<ProtoOption>>),
+    FieldOptions(Option<&'a Vec<ProtoOption>>),
+    /// The value of a field option; `true` if `protoc` records its span.
+    OptionValue(bool),
+    Enum(&'a Enum),
+    EnumValue(Option<&'a Vec<ProtoOption>>),
+    EnumValueOptions(Option<&'a Vec<ProtoOption>>),
+    Service(&'a Service),
+    Method(&'a Method),
+    /// The `extend` blocks of a scope and the index of the current one.
+    Extend(&'a [Extend], usize),
+    /// The extension or reserved ranges of a message or enum.
+    Ranges,
+    Range,
+    ReservedNames,
+    Leaf,
+}
+
+/// Returns the descriptor paths an AST path corresponds to. Most map to one
+/// path; option statements map to both the options message and the option
+/// field within it, and paths into things the descriptor cannot represent
+/// map to none.
+fn descriptor_paths(
+    file: &ProtoFile,
+    path: &[PathSegment],
+    set: Option<&FileSet>,
+) -> Vec<Vec<i32>> {
+    let package = file.package.as_deref().unwrap_or_default();
+    // The option's field number, and whether the field is repeated.
+    let field = |table: OptionTable, options_type: &str, option: &ProtoOption| {
+        if let Some(number) = option_number(table, &option.name) {
+            return Some((number, false));
+        }
+        let field = match option.extension() {
+            Some((name, _)) => {
+                let extension = set?.resolve_extension(package, name)?;
+                if extension.extendee.strip_prefix('.') != Some(options_type) {
+                    return None;
+                }
+                extension.field
+            }
+            None => {
+                let name = option.name.split('.').next()?;
+                let options = set?.find_message(options_type)?;
+                options.fields.iter().find(|f| f.name() == name)?
+            }
+        };
+        Some((
+            field.position(),
+            field.frequency() == Some(&Frequency::Repeated),
+        ))
+    };
+    // The path of the `i`th option within its options message. A repeated
+    // field's values are told apart by their index, as `protoc` does.
+    let option_path = |table, options_type, options: &[ProtoOption], i: usize| {
+        let option = options.get(i)?;
+        let (number, repeated) = field(table, options_type, option)?;
+        let mut path = vec![number as i32];
+        if repeated {
+            let index = options[..i]
+                .iter()
+                .filter(|o| o.name == option.name)
+                .count();
+            path.push(index as i32);
+        }
+        Some(path)
+    };
+    let options_at = |prefix: Vec<i32>,
+                      options_field: i32,
+                      options: &[ProtoOption],
+                      i: usize,
+                      table,
+                      options_type| {
+        let mut options_path = prefix;
+        options_path.push(options_field);
+        match option_path(table, options_type, options, i) {
+            Some(path) => vec![options_path.clone(), [options_path, path].concat()],
+            None => vec![options_path],
+        }
+    };
+    let mut ret = vec![];
+    let mut scope = Scope::File(file);
+    for (depth, segment) in path.iter().enumerate() {
+        let last = depth + 1 == path.len();
+        let (numbers, next): (Vec<i32>, Scope) = match (scope, segment) {
+            (Scope::File(_), PathSegment::Syntax) => (vec![12], Scope::Leaf),
+            (Scope::File(_), PathSegment::Package) => (vec![2], Scope::Leaf),
+            (Scope::File(_), PathSegment::Import(i)) => (vec![3, *i as i32], Scope::Leaf),
+            (Scope::File(f), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    8,
+                    &f.options,
+                    *i,
+                    FILE_OPTIONS,
+                    "google.protobuf.FileOptions",
+                )
+            }
+            (Scope::File(f), PathSegment::Message(i)) => {
+                (vec![4, *i as i32], Scope::Message(&f.messages[*i]))
+            }
+            (Scope::File(f), PathSegment::Enum(i)) => {
+                (vec![5, *i as i32], Scope::Enum(&f.enums[*i]))
+            }
+            (Scope::File(f), PathSegment::Service(i)) => {
+                (vec![6, *i as i32], Scope::Service(&f.services[*i]))
+            }
+            (Scope::File(f), PathSegment::Extend(i)) => (vec![7], Scope::Extend(&f.extends, *i)),
+            (Scope::Message(_), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Message(m), PathSegment::Extend(i)) => (vec![6], Scope::Extend(&m.extends, *i)),
+            (Scope::Extend(extends, index), PathSegment::Field(i)) => {
+                let preceding: usize = extends[..index].iter().map(|e| e.fields.len()).sum();
+                let extend = &extends[index];
+                let field = &extend.fields[*i];
+                (
+                    vec![(preceding + *i) as i32],
+                    Scope::Field(field, extend.field_options.get(&field.position())),
+                )
+            }
+            (Scope::Field(..), PathSegment::Extendee) => (vec![2], Scope::Leaf),
+            (Scope::Message(_), PathSegment::Extensions) => (vec![5], Scope::Ranges),
+            (Scope::Message(_), PathSegment::Reserved) => (vec![9], Scope::Ranges),
+            (Scope::Message(_), PathSegment::ReservedNames) => (vec![10], Scope::ReservedNames),
+            (Scope::Enum(_), PathSegment::Reserved) => (vec![4], Scope::Ranges),
+            (Scope::Enum(_), PathSegment::ReservedNames) => (vec![5], Scope::ReservedNames),
+            (Scope::Ranges, PathSegment::ExtensionRange(i) | PathSegment::ReservedRange(i)) => {
+                (vec![*i as i32], Scope::Range)
+            }
+            (Scope::Range, PathSegment::Start) => (vec![1], Scope::Leaf),
+            (Scope::Range, PathSegment::End) => (vec![2], Scope::Leaf),
+            (Scope::ReservedNames, PathSegment::ReservedName(i)) => (vec![*i as i32], Scope::Leaf),
+            (Scope::Message(m), PathSegment::Field(i)) => {
+                let field = &m.fields[*i];
+                let index = field_order(m)
+                    .iter()
+                    .position(|f| *f == FieldIndex::Field(*i));
+                (
+                    vec![2, index.unwrap_or_default() as i32],
+                    Scope::Field(field, m.field_options.get(&field.position())),
+                )
+            }
+            (Scope::Message(m), PathSegment::Message(i)) => {
+                let index = nested_order(m)
+                    .iter()
+                    .position(|n| *n == Nested::Message(*i));
+                (
+                    vec![3, index.unwrap_or_default() as i32],
+                    Scope::Message(&m.messages[*i]),
+                )
+            }
+            (Scope::Message(m), PathSegment::Enum(i)) => {
+                (vec![4, *i as i32], Scope::Enum(&m.enums[*i]))
+            }
+            (Scope::Message(m), PathSegment::Oneof(i)) => {
+                (vec![8, *i as i32], Scope::Oneof(m, &m.oneofs[*i], *i))
+            }
+            (Scope::Message(m), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    7,
+                    &m.options,
+                    *i,
+                    MESSAGE_OPTIONS,
+                    "google.protobuf.MessageOptions",
+                )
+            }
+            (Scope::Oneof(..), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Oneof(m, o, index), PathSegment::Field(i)) => {
+                ret.truncate(ret.len() - 2);
+                let member = FieldIndex::Member(index, *i);
+                let field = &o.fields[*i];
+                (
+                    vec![
+                        2,
+                        field_order(m)
+                            .iter()
+                            .position(|f| *f == member)
+                            .unwrap_or_default() as i32,
+                    ],
+                    Scope::Field(field, o.field_options.get(&field.position())),
+                )
+            }
+            (Scope::Oneof(_, o, _), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    2,
+                    &o.options,
+                    *i,
+                    ONEOF_OPTIONS,
+                    "google.protobuf.OneofOptions",
+                )
+            }
+            (Scope::Field(..), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Field(..), PathSegment::Number) => (vec![3], Scope::Leaf),
+            (Scope::Field(..), PathSegment::Label) => (vec![4], Scope::Leaf),
+            (Scope::Field(f, _), PathSegment::Type) => match f.field_type() {
+                Type::Named(_) | Type::Message(_) | Type::Enum(_) | Type::Map(..) => {
+                    (vec![6], Scope::Leaf)
+                }
+                _ => (vec![5], Scope::Leaf),
+            },
+            (Scope::Field(_, options), PathSegment::Options) => {
+                (vec![8], Scope::FieldOptions(options))
+            }
+            (Scope::FieldOptions(options), PathSegment::Option(i)) => {
+                let Some(option) = options.and_then(|options| options.get(*i)) else {
+                    return vec![];
+                };
+                match option.name.as_str() {
+                    "json_name" => {
+                        ret.pop();
+                        (vec![10], Scope::OptionValue(true))
+                    }
+                    "default" if last => return vec![],
+                    "default" => {
+                        ret.pop();
+                        (vec![7], Scope::OptionValue(true))
+                    }
+                    _ => match option_path(
+                        FIELD_OPTIONS,
+                        "google.protobuf.FieldOptions",
+                        options.unwrap(),
+                        *i,
+                    ) {
+                        Some(path) => (path, Scope::OptionValue(false)),
+                        None => return vec![],
+                    },
+                }
+            }
+            (Scope::OptionValue(true), PathSegment::Value) => (vec![], Scope::Leaf),
+            (Scope::Enum(_), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Enum(e), PathSegment::EnumValue(i)) => (
+                vec![2, *i as i32],
+                Scope::EnumValue(e.value_options.get(&e.fields[*i].name)),
+            ),
+            (Scope::Enum(e), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    3,
+                    &e.options,
+                    *i,
+                    ENUM_OPTIONS,
+                    "google.protobuf.EnumOptions",
+                )
+            }
+            (Scope::EnumValue(_), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::EnumValue(_), PathSegment::Number) => (vec![2], Scope::Leaf),
+            (Scope::EnumValue(options), PathSegment::Options) => {
+                (vec![3], Scope::EnumValueOptions(options))
+            }
+            (Scope::EnumValueOptions(options), PathSegment::Option(i)) => {
+                let path = options.and_then(|options| {
+                    option_path(
+                        ENUM_VALUE_OPTIONS,
+                        "google.protobuf.EnumValueOptions",
+                        options,
+                        *i,
+                    )
+                });
+                match path {
+                    Some(path) => (path, Scope::OptionValue(false)),
+                    None => return vec![],
+                }
+            }
+            (Scope::Service(_), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Service(s), PathSegment::Method(i)) => {
+                (vec![2, *i as i32], Scope::Method(&s.methods[*i]))
+            }
+            (Scope::Service(s), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    3,
+                    &s.options,
+                    *i,
+                    SERVICE_OPTIONS,
+                    "google.protobuf.ServiceOptions",
+                )
+            }
+            (Scope::Method(_), PathSegment::Name) => (vec![1], Scope::Leaf),
+            (Scope::Method(_), PathSegment::InputType) => (vec![2], Scope::Leaf),
+            (Scope::Method(_), PathSegment::OutputType) => (vec![3], Scope::Leaf),
+            (Scope::Method(_), PathSegment::ClientStreaming) => (vec![5], Scope::Leaf),
+            (Scope::Method(_), PathSegment::ServerStreaming) => (vec![6], Scope::Leaf),
+            (Scope::Method(m), PathSegment::Option(i)) => {
+                return options_at(
+                    ret,
+                    4,
+                    &m.options,
+                    *i,
+                    METHOD_OPTIONS,
+                    "google.protobuf.MethodOptions",
+                )
+            }
+            _ => return vec![],
+        };
+        ret.extend(numbers);
+        scope = next;
+        if last {
+            return vec![ret];
+        }
+    }
+    vec![ret]
+}
+
+/// Every path into `file` the parser may record a location for.
+fn ast_paths(file: &ProtoFile) -> Vec<Vec<PathSegment>> {
+    let mut ret = vec![
+        vec![],
+        vec![PathSegment::Syntax],
+        vec![PathSegment::Package],
+    ];
+    ret.extend((0..file.imports.len()).map(|i| vec![PathSegment::Import(i)]));
+    ret.extend((0..file.options.len()).map(|i| vec![PathSegment::Option(i)]));
+    for (i, message) in file.messages.iter().enumerate() {
+        message_paths(vec![PathSegment::Message(i)], message, &mut ret);
+    }
+    for (i, e) in file.enums.iter().enumerate() {
+        enum_paths(vec![PathSegment::Enum(i)], e, &mut ret);
+    }
+    for (i, service) in file.services.iter().enumerate() {
+        let path = vec![PathSegment::Service(i)];
+        ret.push(path.clone());
+        ret.push(with(&path, &[PathSegment::Name]));
+        for j in 0..service.options.len() {
+            ret.push(with(&path, &[PathSegment::Option(j)]));
+        }
+        for (j, method) in service.methods.iter().enumerate() {
+            let path = with(&path, &[PathSegment::Method(j)]);
+            ret.push(path.clone());
+            for segment in [
+                PathSegment::Name,
+                PathSegment::InputType,
+                PathSegment::OutputType,
+                PathSegment::ClientStreaming,
+                PathSegment::ServerStreaming,
+            ] {
+                ret.push(with(&path, &[segment]));
+            }
+            for k in 0..method.options.len() {
+                ret.push(with(&path, &[PathSegment::Option(k)]));
+            }
+        }
+    }
+    extend_paths(&[], &file.extends, &mut ret);
+    ret
+}
+
+fn with(path: &[PathSegment], segments: &[PathSegment]) -> Vec<PathSegment> {
+    [path, segments].concat()
+}
+
+fn message_paths(path: Vec<PathSegment>, message: &Message, ret: &mut Vec<Vec<PathSegment>>) {
+    ret.push(with(&path, &[PathSegment::Name]));
+    for (i, field) in message.fields.iter().enumerate() {
+        let options = message.field_options.get(&field.position());
+        field_paths(with(&path, &[PathSegment::Field(i)]), options, ret);
+    }
+    for (i, oneof) in message.oneofs.iter().enumerate() {
+        let path = with(&path, &[PathSegment::Oneof(i)]);
+        ret.push(with(&path, &[PathSegment::Name]));
+        for j in 0..oneof.options.len() {
+            ret.push(with(&path, &[PathSegment::Option(j)]));
+        }
+        for (j, field) in oneof.fields.iter().enumerate() {
+            let options = oneof.field_options.get(&field.position());
+            field_paths(with(&path, &[PathSegment::Field(j)]), options, ret);
+        }
+        ret.push(path);
+    }
+    for (i, nested) in message.messages.iter().enumerate() {
+        message_paths(with(&path, &[PathSegment::Message(i)]), nested, ret);
+    }
+    for (i, e) in message.enums.iter().enumerate() {
+        enum_paths(with(&path, &[PathSegment::Enum(i)]), e, ret);
+    }
+    for i in 0..message.options.len() {
+        ret.push(with(&path, &[PathSegment::Option(i)]));
+    }
+    extend_paths(&path, &message.extends, ret);
+    range_paths(
+        &with(&path, &[PathSegment::Extensions]),
+        message.extension_ranges.len(),
+        PathSegment::ExtensionRange,
+        ret,
+    );
+    range_paths(
+        &with(&path, &[PathSegment::Reserved]),
+        message.reserved_ranges.len(),
+        PathSegment::ReservedRange,
+        ret,
+    );
+    reserved_name_paths(&path, message.reserved_names.len(), ret);
+    ret.push(path);
+}
+
+fn enum_paths(path: Vec<PathSegment>, e: &Enum, ret: &mut Vec<Vec<PathSegment>>) {
+    ret.push(with(&path, &[PathSegment::Name]));
+    for i in 0..e.options.len() {
+        ret.push(with(&path, &[PathSegment::Option(i)]));
+    }
+    for (i, value) in e.fields.iter().enumerate() {
+        let path = with(&path, &[PathSegment::EnumValue(i)]);
+        ret.push(with(&path, &[PathSegment::Name]));
+        ret.push(with(&path, &[PathSegment::Number]));
+        if let Some(options) = e.value_options.get(&value.name) {
+            ret.push(with(&path, &[PathSegment::Options]));
+            for j in 0..options.len() {
+                ret.push(with(&path, &[PathSegment::Options, PathSegment::Option(j)]));
+            }
+        }
+        ret.push(path);
+    }
+    range_paths(
+        &with(&path, &[PathSegment::Reserved]),
+        e.reserved_ranges.len(),
+        PathSegment::ReservedRange,
+        ret,
+    );
+    reserved_name_paths(&path, e.reserved_names.len(), ret);
+    ret.push(path);
+}
+
+fn field_paths(
+    path: Vec<PathSegment>,
+    options: Option<&Vec<ProtoOption>>,
+    ret: &mut Vec<Vec<PathSegment>>,
+) {
+    for segment in [
+        PathSegment::Name,
+        PathSegment::Number,
+        PathSegment::Label,
+        PathSegment::Type,
+        PathSegment::Extendee,
+    ] {
+        ret.push(with(&path, &[segment]));
+    }
+    if let Some(options) = options {
+        ret.push(with(&path, &[PathSegment::Options]));
+        for i in 0..options.len() {
+            let option = with(&path, &[PathSegment::Options, PathSegment::Option(i)]);
+            ret.push(with(&option, &[PathSegment::Value]));
+            ret.push(option);
+        }
+    }
+    ret.push(path);
+}
+
+fn extend_paths(path: &[PathSegment], extends: &[Extend], ret: &mut Vec<Vec<PathSegment>>) {
+    for (i, extend) in extends.iter().enumerate() {
+        let path = with(path, &[PathSegment::Extend(i)]);
+        for (j, field) in extend.fields.iter().enumerate() {
+            let options = extend.field_options.get(&field.position());
+            field_paths(with(&path, &[PathSegment::Field(j)]), options, ret);
+        }
+        ret.push(path);
+    }
+}
+
+fn range_paths(
+    path: &[PathSegment],
+    count: usize,
+    segment: fn(usize) -> PathSegment,
+    ret: &mut Vec<Vec<PathSegment>>,
+) {
+    if count == 0 {
+        return;
+    }
+    ret.push(path.to_vec());
+    for i in 0..count {
+        let range = with(path, &[segment(i)]);
+        ret.push(with(&range, &[PathSegment::Start]));
+        ret.push(with(&range, &[PathSegment::End]));
+        ret.push(range);
+    }
+}
+
+fn reserved_name_paths(path: &[PathSegment], count: usize, ret: &mut Vec<Vec<PathSegment>>) {
+    if count == 0 {
+        return;
+    }
+    let path = with(path, &[PathSegment::ReservedNames]);
+    for i in 0..count {
+        ret.push(with(&path, &[PathSegment::ReservedName(i)]));
+    }
+    ret.push(path);
+}
+
+/// Decodes an options message into the options `table` knows about and the
+/// raw bytes of every other field.
+fn decode_options(
+    mut buf: &[u8],
+    table: OptionTable,
+    options: &mut Vec<ProtoOption>,
+    unknown: &mut Vec<u8>,
+) -> Result<(), Error> {
+    while !buf.is_empty() {
+        let start = buf;
+        let (number, wire_type) = decode_key(&mut buf)?;
+        let known = table.iter().find(|(_, n, _)| *n == number);
+        let value = match (known, wire_type) {
+            (Some((_, _, OptionKind::Bool)), WireType::Varint) => {
+                Constant::Bool(decode_varint(&mut buf)? != 0)
+            }
+            (Some((_, _, OptionKind::String)), WireType::LengthDelimited) => {
+                Constant::String(decode_string(&mut buf)?)
+            }
+            (Some((_, _, OptionKind::Enum(values))), WireType::Varint) => {
+                let value = decode_varint(&mut buf)? as i32;
+                match values.iter().find(|(_, v)| *v == value) {
+                    Some((name, _)) => Constant::Ident(name.to_string()),
+                    None => Constant::Int(value as i128),
+                }
+            }
+            _ => {
+                skip_field(number, wire_type, &mut buf)?;
+                unknown.extend_from_slice(&start[..start.len() - buf.len()]);
+                continue;
+            }
+        };
+        let (name, _, _) = known.unwrap();
+        options.push(ProtoOption {
+            name: name.to_string(),
+            value,
+        });
+    }
+    Ok(())
+}
+
+fn decode_int32(buf: &mut &[u8]) -> Result<i32, Error> {
+    Ok(decode_varint(buf)? as i32)
+}
+
+/// Reads a repeated `int32`, which may arrive packed or one value at a
+/// time.
+fn decode_repeated_int32(
+    wire_type: WireType,
+    buf: &mut &[u8],
+    values: &mut Vec<i32>,
+) -> Result<(), Error> {
+    if wire_type == WireType::LengthDelimited {
+        let mut packed = decode_length_delimited(buf)?;
+        while !packed.is_empty() {
+            values.push(decode_int32(&mut packed)?);
+        }
+    } else {
+        values.push(decode_int32(buf)?);
+    }
+    Ok(())
+}
+
+impl Label {
+    fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            1 => Some(Label::Optional),
+            2 => Some(Label::Required),
+            3 => Some(Label::Repeated),
+            _ => None,
+        }
+    }
+}
+
+impl FieldType {
+    fn from_i32(value: i32) -> Option<Self> {
+        Some(match value {
+            1 => FieldType::Double,
+            2 => FieldType::Float,
+            3 => FieldType::Int64,
+            4 => FieldType::Uint64,
+            5 => FieldType::Int32,
+            6 => FieldType::Fixed64,
+            7 => FieldType::Fixed32,
+            8 => FieldType::Bool,
+            9 => FieldType::String,
+            10 => FieldType::Group,
+            11 => FieldType::Message,
+            12 => FieldType::Bytes,
+            13 => FieldType::Uint32,
+            14 => FieldType::Enum,
+            15 => FieldType::Sfixed32,
+            16 => FieldType::Sfixed64,
+            17 => FieldType::Sint32,
+            18 => FieldType::Sint64,
+            _ => return None,
+        })
+    }
+}
+
+/// What converting a descriptor back into the AST needs besides the
+/// descriptor itself: the set, to name custom options after their
+/// extensions, and the source spans, to put options back in the order they
+/// were written.
+struct Decoder<'a> {
+    set: Option<&'a Arc<FileSet>>,
+    /// The start of every span `protoc` recorded, by descriptor path.
+    spans: BTreeMap<&'a [i32], Vec<(i32, i32)>>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(set: Option<&'a Arc<FileSet>>, info: Option<&'a SourceCodeInfo>) -> Self {
+        let mut spans: BTreeMap<&[i32], Vec<(i32, i32)>> = BTreeMap::new();
+        for location in info.into_iter().flat_map(|info| &info.location) {
+            if let [line, column, ..] = location.span[..] {
+                spans
+                    .entry(&location.path)
+                    .or_default()
+                    .push((line, column));
+            }
+        }
+        Decoder { set, spans }
+    }
+
+    /// The options of the options message at `path`, each with the path of
+    /// its own field: the standard options and the custom ones in
+    /// `unknown`.
+    fn options(
+        &self,
+        path: &[i32],
+        options: &[ProtoOption],
+        unknown: &[u8],
+        options_type: &str,
+        table: OptionTable,
+    ) -> Vec<(Option<Vec<i32>>, ProtoOption)> {
+        let at = |number: u32| [path, &[number as i32]].concat();
+        let mut ret: Vec<_> = options
+            .iter()
+            .map(|option| (option_number(table, &option.name).map(at), option.clone()))
+            .collect();
+        if let Some(set) = self.set {
+            ret.extend(
+                read_options(set, options_type, unknown)
+                    .into_iter()
+                    .map(|(number, option)| (Some(at(number)), option)),
+            );
+        }
+        ret
+    }
+
+    /// Orders options by where they were written. The n-th option with a
+    /// given path takes the n-th span recorded there; options without one
+    /// keep their order after the rest.
+    fn sort(&self, options: Vec<(Option<Vec<i32>>, ProtoOption)>) -> Vec<ProtoOption> {
+        let keyed = self.sort_keyed(options);
+        keyed.into_iter().map(|(_, option)| option).collect()
+    }
+
+    /// [`Decoder::sort`], keeping where each option starts.
+    fn sort_keyed(
+        &self,
+        options: Vec<(Option<Vec<i32>>, ProtoOption)>,
+    ) -> Vec<(Option<(i32, i32)>, ProtoOption)> {
+        let mut seen: BTreeMap<Vec<i32>, usize> = BTreeMap::new();
+        let mut keyed: Vec<_> = options
+            .into_iter()
+            .map(|(path, option)| {
+                let start = path.and_then(|path| {
+                    let n = seen.entry(path.clone()).or_default();
+                    *n += 1;
+                    match self.spans.get(&path[..]) {
+                        Some(spans) => spans.get(*n - 1).copied(),
+                        // Each value of a repeated option has a path of its own.
+                        None => self.start(&[&path[..], &[*n as i32 - 1]].concat()),
+                    }
+                });
+                (start, option)
+            })
+            .collect();
+        keyed.sort_by_key(|(start, _)| (start.is_none(), *start));
+        keyed
+    }
+
+    /// Where the first span recorded at `path` starts.
+    fn start(&self, path: &[i32]) -> Option<(i32, i32)> {
+        self.spans.get(path)?.first().copied()
+    }
+
+    /// Groups the `count` items at `path` by the statement that listed
+    /// them, as where that statement starts, the first item and how many
+    /// items it has. Items without a span are left out.
+    fn statements(&self, path: &[i32], count: usize) -> Vec<((i32, i32), usize, usize)> {
+        let starts = self.spans.get(path).map(Vec::as_slice).unwrap_or_default();
+        let mut ret: Vec<((i32, i32), usize, usize)> = vec![];
+        for i in 0..count {
+            let Some(item) = self.start(&[path, &[i as i32]].concat()) else {
+                continue;
+            };
+            let statement = starts.iter().rev().find(|s| **s <= item).copied();
+            let statement = statement.unwrap_or(item);
+            match ret.last_mut() {
+                Some((start, first, n)) if *start == statement && *first + *n == i => *n += 1,
+                _ => ret.push((statement, i, 1)),
+            }
+        }
+        ret
+    }
+}
+
+impl FileDescriptorSet {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut set = FileDescriptorSet::default();
+        while !buf.is_empty() {
+            match decode_key(&mut buf)? {
+                (1, WireType::LengthDelimited) => {
+                    set.file
+                        .push(FileDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (number, wire_type) => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(set)
+    }
+
+    /// Converts every file back into the AST. Type references are already
+    /// fully qualified in a descriptor, so the files come out linked.
+    /// Custom options are named after the extensions the set declares, and
+    /// source info becomes the set's locations.
+    pub fn to_file_set(&self) -> FileSet {
+        let plain = Arc::new(FileSet {
+            files: self.file.iter().map(FileDescriptorProto::to_file).collect(),
+            locations: BTreeMap::new(),
+        });
+        let files: Vec<ProtoFile> = self
+            .file
+            .iter()
+            .map(|file| {
+                file.to_file_with(&Decoder::new(Some(&plain), file.source_code_info.as_ref()))
+            })
+            .collect();
+        let locations = self
+            .file
+            .iter()
+            .zip(&files)
+            .filter_map(|(descriptor, file)| {
+                let info = descriptor.source_code_info.as_ref()?;
+                Some((file.name.clone(), info.to_locations(file, Some(&plain))))
+            })
+            .collect();
+        FileSet { files, locations }
+    }
+}
+
+impl FileDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut file = FileDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => file.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => file.package = Some(decode_string(&mut buf)?),
+                (3, WireType::LengthDelimited) => file.dependency.push(decode_string(&mut buf)?),
+                (4, WireType::LengthDelimited) => file
+                    .message_type
+                    .push(DescriptorProto::decode(decode_length_delimited(&mut buf)?)?),
+                (5, WireType::LengthDelimited) => {
+                    file.enum_type
+                        .push(EnumDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (6, WireType::LengthDelimited) => {
+                    file.service
+                        .push(ServiceDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (7, WireType::LengthDelimited) => {
+                    file.extension
+                        .push(FieldDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (8, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    FILE_OPTIONS,
+                    &mut file.options,
+                    &mut file.unknown_options,
+                )?,
+                (9, WireType::LengthDelimited) => {
+                    file.source_code_info =
+                        Some(SourceCodeInfo::decode(decode_length_delimited(&mut buf)?)?)
+                }
+                (10, _) => decode_repeated_int32(wire_type, &mut buf, &mut file.public_dependency)?,
+                (11, _) => decode_repeated_int32(wire_type, &mut buf, &mut file.weak_dependency)?,
+                (12, WireType::LengthDelimited) => file.syntax = Some(decode_string(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(file)
+    }
+
+    /// Converts the descriptor back into the AST the parser would have
+    /// produced for the same source. Map entry messages and the synthetic
+    /// oneofs behind proto3 `optional` fields are folded back into the
+    /// fields that introduced them. Options come back in source order when
+    /// there is source info and in field number order otherwise; custom
+    /// options need the rest of the set to be named, so only `to_file_set`
+    /// converts them.
+    pub fn to_file(&self) -> ProtoFile {
+        self.to_file_with(&Decoder::new(None, self.source_code_info.as_ref()))
+    }
+
+    fn to_file_with(&self, cx: &Decoder) -> ProtoFile {
+        let syntax = self.syntax.clone().unwrap_or_else(|| "proto2".to_string());
+        let proto3 = syntax == "proto3";
+        let scope = match &self.package {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+
+        let options = cx.sort_keyed(cx.options(
+            &[8],
+            &self.options,
+            &self.unknown_options,
+            "google.protobuf.FileOptions",
+            FILE_OPTIONS,
+        ));
+        let mut file = ProtoFile {
+            name: self.name.clone().unwrap_or_default(),
+            syntax: Some(syntax),
+            package: self.package.clone(),
+            imports: self
+                .dependency
+                .iter()
+                .enumerate()
+                .map(|(i, path)| Import {
+                    path: path.clone(),
+                    public: self.public_dependency.contains(&(i as i32)),
+                    weak: self.weak_dependency.contains(&(i as i32)),
+                })
+                .collect(),
+            options: options.iter().map(|(_, option)| option.clone()).collect(),
+            messages: self
+                .message_type
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| !m.is_map_entry())
+                .map(|(i, m)| m.to_message(&scope, proto3, cx, &[4, i as i32]))
+                .collect(),
+            enums: self
+                .enum_type
+                .iter()
+                .enumerate()
+                .map(|(i, e)| e.to_enum(cx, &[5, i as i32]))
+                .collect(),
+            services: self
+                .service
+                .iter()
+                .enumerate()
+                .map(|(i, service)| service.to_service(cx, &[6, i as i32]))
+                .collect(),
+            extends: to_extends(&self.extension, proto3, cx, &[7]),
+            order: vec![],
+        };
+        file.order = file.complete(self.written_order(&options, cx));
+        file.normalize_order();
+        file
+    }
+
+    /// The order of the file as written, from where each statement's span
+    /// starts.
+    fn written_order(
+        &self,
+        options: &[(Option<(i32, i32)>, ProtoOption)],
+        cx: &Decoder,
+    ) -> Vec<Declaration> {
+        let mut keyed: Vec<(Option<(i32, i32)>, Declaration)> = vec![
+            (cx.start(&[12]), Declaration::Syntax),
+            (cx.start(&[2]), Declaration::Package),
+        ];
+        for i in 0..self.dependency.len() {
+            keyed.push((cx.start(&[3, i as i32]), Declaration::Import(i)));
+        }
+        for (i, (start, _)) in options.iter().enumerate() {
+            keyed.push((*start, Declaration::Option(i)));
+        }
+        let messages =
+            (0..self.message_type.len()).filter(|&j| !self.message_type[j].is_map_entry());
+        for (m, j) in messages.enumerate() {
+            keyed.push((cx.start(&[4, j as i32]), Declaration::Message(m)));
+        }
+        for i in 0..self.enum_type.len() {
+            keyed.push((cx.start(&[5, i as i32]), Declaration::Enum(i)));
+        }
+        for i in 0..self.service.len() {
+            keyed.push((cx.start(&[6, i as i32]), Declaration::Service(i)));
+        }
+        let extends = cx.spans.get(&[7][..]).map(Vec::as_slice);
+        for (i, start) in extends.unwrap_or_default().iter().enumerate() {
+            keyed.push((Some(*start), Declaration::Extend(i)));
+        }
+        keyed.retain(|(start, _)| start.is_some());
+        keyed.sort_by_key(|(start, _)| *start);
+        keyed
+            .into_iter()
+            .map(|(_, declaration)| declaration)
+            .collect()
+    }
+}
+
+impl DescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut message = DescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => message.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => {
+                    message
+                        .field
+                        .push(FieldDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (3, WireType::LengthDelimited) => message
+                    .nested_type
+                    .push(DescriptorProto::decode(decode_length_delimited(&mut buf)?)?),
+                (4, WireType::LengthDelimited) => {
+                    message
+                        .enum_type
+                        .push(EnumDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (5, WireType::LengthDelimited) => message
+                    .extension_range
+                    .push(ExtensionRange::decode(decode_length_delimited(&mut buf)?)?),
+                (6, WireType::LengthDelimited) => {
+                    message
+                        .extension
+                        .push(FieldDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (7, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    MESSAGE_OPTIONS,
+                    &mut message.options,
+                    &mut message.unknown_options,
+                )?,
+                (8, WireType::LengthDelimited) => {
+                    message
+                        .oneof_decl
+                        .push(OneofDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (9, WireType::LengthDelimited) => message
+                    .reserved_range
+                    .push(ReservedRange::decode(decode_length_delimited(&mut buf)?)?),
+                (10, WireType::LengthDelimited) => {
+                    message.reserved_name.push(decode_string(&mut buf)?)
+                }
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(message)
+    }
+
+    fn is_map_entry(&self) -> bool {
+        self.options
+            .iter()
+            .any(|option| option.name == "map_entry" && option.value == Constant::Bool(true))
+    }
+
+    fn to_message(&self, scope: &str, proto3: bool, cx: &Decoder, path: &[i32]) -> Message {
+        let at = |numbers: &[i32]| [path, numbers].concat();
+        let name = self.name.clone().unwrap_or_default();
+        let scope = format!("{}.{}", scope, name);
+
+        let mut map_entries = BTreeMap::new();
+        for nested in self.nested_type.iter().filter(|m| m.is_map_entry()) {
+            let entry_type = |number| {
+                nested
+                    .field
+                    .iter()
+                    .find(|f| f.number == Some(number))
+                    .map(FieldDescriptorProto::to_type)
+                    .unwrap_or(Type::Bytes)
+            };
+            map_entries.insert(
+                format!("{}.{}", scope, nested.name.clone().unwrap_or_default()),
+                (entry_type(1), entry_type(2)),
+            );
+        }
+
+        let synthetic: Vec<i32> = self
+            .field
+            .iter()
+            .filter(|f| f.proto3_optional == Some(true))
+            .filter_map(|f| f.oneof_index)
+            .collect();
+        let mut oneofs: Vec<(i32, Oneof)> = self
+            .oneof_decl
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !synthetic.contains(&(*i as i32)))
+            .map(|(i, oneof)| {
+                let options = cx.options(
+                    &at(&[8, i as i32, 2]),
+                    &oneof.options,
+                    &oneof.unknown_options,
+                    "google.protobuf.OneofOptions",
+                    ONEOF_OPTIONS,
+                );
+                let oneof = Oneof {
+                    name: oneof.name.clone().unwrap_or_default(),
+                    options: cx.sort(options),
+                    ..Default::default()
+                };
+                (i as i32, oneof)
+            })
+            .collect();
+
+        let options = cx.sort_keyed(cx.options(
+            &at(&[7]),
+            &self.options,
+            &self.unknown_options,
+            "google.protobuf.MessageOptions",
+            MESSAGE_OPTIONS,
+        ));
+        let mut message = Message {
+            name,
+            messages: self
+                .nested_type
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| !m.is_map_entry())
+                .map(|(i, m)| m.to_message(&scope, proto3, cx, &at(&[3, i as i32])))
+                .collect(),
+            enums: self
+                .enum_type
+                .iter()
+                .enumerate()
+                .map(|(i, e)| e.to_enum(cx, &at(&[4, i as i32])))
+                .collect(),
+            options: options.iter().map(|(_, option)| option.clone()).collect(),
+            extends: to_extends(&self.extension, proto3, cx, &at(&[6])),
+            extension_ranges: self
+                .extension_range
+                .iter()
+                .map(|range| {
+                    let start = range.start.unwrap_or_default() as u32;
+                    (
+                        start,
+                        (range.end.unwrap_or_default() as u32).saturating_sub(1),
+                    )
+                })
+                .collect(),
+            extension_range_options: self
+                .extension_range
+                .iter()
+                .enumerate()
+                .map(|(i, range)| {
+                    let options = cx.options(
+                        &at(&[5, i as i32, 3]),
+                        &range.options,
+                        &range.unknown_options,
+                        "google.protobuf.ExtensionRangeOptions",
+                        EXTENSION_RANGE_OPTIONS,
+                    );
+                    (range.start.unwrap_or_default() as u32, cx.sort(options))
+                })
+                .filter(|(_, options)| !options.is_empty())
+                .collect(),
+            reserved_ranges: self
+                .reserved_range
+                .iter()
+                .map(|range| {
+                    (
+                        range.start.unwrap_or_default() as u32,
+                        (range.end.unwrap_or_default() as u32).saturating_sub(1),
+                    )
+                })
+                .collect(),
+            reserved_names: self.reserved_name.clone(),
+            ..Default::default()
+        };
+
+        // Fields and oneofs by where the descriptor lists them, which is
+        // the order they were declared in.
+        let mut declared = vec![];
+        for (i, f) in self.field.iter().enumerate() {
+            let in_oneof = f.proto3_optional != Some(true) && f.oneof_index.is_some();
+            if !in_oneof {
+                declared.push((i, Declaration::Field(message.fields.len())));
+            } else if let Some(o) = oneofs
+                .iter()
+                .position(|(o, oneof)| Some(*o) == f.oneof_index && oneof.fields.is_empty())
+            {
+                declared.push((i, Declaration::Oneof(o)));
+            }
+            let field = match f.type_name.as_ref().and_then(|name| map_entries.get(name)) {
+                Some((key, value)) if f.label == Some(Label::Repeated) => MessageField::Map(
+                    key.clone(),
+                    value.clone(),
+                    f.name.clone().unwrap_or_default(),
+                    f.number.unwrap_or_default() as u32,
+                ),
+                _ => f.to_field(proto3, in_oneof),
+            };
+            let options = f.to_field_options(&field, cx, &at(&[2, i as i32]));
+
+            let target = match oneofs
+                .iter_mut()
+                .find(|(i, _)| in_oneof && Some(*i) == f.oneof_index)
+            {
+                Some((_, oneof)) => (&mut oneof.fields, &mut oneof.field_options),
+                None => (&mut message.fields, &mut message.field_options),
+            };
+            if !options.is_empty() {
+                target.1.insert(field.position(), options);
+            }
+            target.0.push(field);
+        }
+
+        let oneof_indices: Vec<i32> = oneofs.iter().map(|(i, _)| *i).collect();
+        message.oneofs = oneofs.into_iter().map(|(_, oneof)| oneof).collect();
+        let order = if cx.spans.is_empty() {
+            self.declared_order(&declared)
+        } else {
+            self.written_order(&declared, &oneof_indices, &options, cx, path)
+        };
+        message.order = message.complete(order);
+        message.normalize_order();
+        message
+    }
+
+    /// The nested messages, by index into the AST's `messages`, with their
+    /// index in `nested_type`.
+    fn nested_messages(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.nested_type
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !m.is_map_entry())
+            .enumerate()
+            .map(|(m, (j, _))| (m, j))
+    }
+
+    /// The order of the body without source info: fields and oneofs as
+    /// listed, with each nested message ahead of the first map field whose
+    /// entry comes after it.
+    fn declared_order(&self, declared: &[(usize, Declaration)]) -> Vec<Declaration> {
+        let mut nested = self.nested_messages().peekable();
+        let mut order = vec![];
+        for (i, declaration) in declared {
+            let field = &self.field[*i];
+            let entry = self.nested_type.iter().position(|m| {
+                m.is_map_entry()
+                    && field
+                        .type_name
+                        .as_ref()
+                        .and_then(|name| name.rsplit('.').next())
+                        == m.name.as_deref()
+            });
+            if let Some(entry) = entry {
+                while let Some((m, _)) = nested.next_if(|(_, j)| *j < entry) {
+                    order.push(Declaration::Message(m));
+                }
+            }
+            order.push(*declaration);
+        }
+        order
+    }
+
+    /// The order of the body as written, from where each declaration's
+    /// span starts.
+    fn written_order(
+        &self,
+        declared: &[(usize, Declaration)],
+        oneofs: &[i32],
+        options: &[(Option<(i32, i32)>, ProtoOption)],
+        cx: &Decoder,
+        path: &[i32],
+    ) -> Vec<Declaration> {
+        let at = |numbers: &[i32]| [path, numbers].concat();
+        let mut keyed: Vec<(Option<(i32, i32)>, Declaration)> = vec![];
+        for (i, (start, _)) in options.iter().enumerate() {
+            keyed.push((*start, Declaration::Option(i)));
+        }
+        for (i, declaration) in declared {
+            let start = match declaration {
+                Declaration::Oneof(o) => cx.start(&at(&[8, oneofs[*o]])),
+                _ => cx.start(&at(&[2, *i as i32])),
+            };
+            keyed.push((start, *declaration));
+        }
+        let statements = [
+            (9, self.reserved_range.len()),
+            (10, self.reserved_name.len()),
+            (5, self.extension_range.len()),
+        ];
+        for (number, count) in statements {
+            for (start, first, count) in cx.statements(&at(&[number]), count) {
+                let declaration = match number {
+                    9 => Declaration::Reserved { first, count },
+                    10 => Declaration::ReservedNames { first, count },
+                    _ => Declaration::Extensions { first, count },
+                };
+                keyed.push((Some(start), declaration));
+            }
+        }
+        let extends = cx.spans.get(&at(&[6])[..]).map(Vec::as_slice);
+        for (i, start) in extends.unwrap_or_default().iter().enumerate() {
+            keyed.push((Some(*start), Declaration::Extend(i)));
+        }
+        for i in 0..self.enum_type.len() {
+            keyed.push((cx.start(&at(&[4, i as i32])), Declaration::Enum(i)));
+        }
+        for (m, j) in self.nested_messages() {
+            keyed.push((cx.start(&at(&[3, j as i32])), Declaration::Message(m)));
+        }
+        keyed.retain(|(start, _)| start.is_some());
+        keyed.sort_by_key(|(start, _)| *start);
+        keyed
+            .into_iter()
+            .map(|(_, declaration)| declaration)
+            .collect()
+    }
+}
+
+impl FieldDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut field = FieldDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => field.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => field.extendee = Some(decode_string(&mut buf)?),
+                (3, WireType::Varint) => field.number = Some(decode_int32(&mut buf)?),
+                (4, WireType::Varint) => field.label = Label::from_i32(decode_int32(&mut buf)?),
+                (5, WireType::Varint) => {
+                    field.r#type = FieldType::from_i32(decode_int32(&mut buf)?)
+                }
+                (6, WireType::LengthDelimited) => field.type_name = Some(decode_string(&mut buf)?),
+                (7, WireType::LengthDelimited) => {
+                    field.default_value = Some(decode_string(&mut buf)?)
+                }
+                (8, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    FIELD_OPTIONS,
+                    &mut field.options,
+                    &mut field.unknown_options,
+                )?,
+                (9, WireType::Varint) => field.oneof_index = Some(decode_int32(&mut buf)?),
+                (10, WireType::LengthDelimited) => field.json_name = Some(decode_string(&mut buf)?),
+                (17, WireType::Varint) => {
+                    field.proto3_optional = Some(decode_varint(&mut buf)? != 0)
+                }
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(field)
+    }
+
+    fn to_field(&self, proto3: bool, in_oneof: bool) -> MessageField {
+        let frequency = match self.label {
+            Some(Label::Repeated) => Some(Frequency::Repeated),
+            Some(Label::Required) => Some(Frequency::Required),
+            _ if self.proto3_optional == Some(true) => Some(Frequency::Optional),
+            _ if proto3 || in_oneof => None,
+            _ => Some(Frequency::Optional),
+        };
+        MessageField::new(
+            frequency,
+            self.to_type(),
+            self.name.clone().unwrap_or_default(),
+            self.number.unwrap_or_default() as u32,
+        )
+    }
+
+    fn to_type(&self) -> Type {
+        let type_name = self.type_name.clone().unwrap_or_default();
+        match self.r#type {
+            Some(FieldType::Double) => Type::Double,
+            Some(FieldType::Float) => Type::Float,
+            Some(FieldType::Int64) => Type::Int64,
+            Some(FieldType::Uint64) => Type::Uint64,
+            Some(FieldType::Int32) => Type::Int32,
+            Some(FieldType::Fixed64) => Type::Fixed64,
+            Some(FieldType::Fixed32) => Type::Fixed32,
+            Some(FieldType::Bool) => Type::Bool,
+            Some(FieldType::String) => Type::String,
+            Some(FieldType::Bytes) => Type::Bytes,
+            Some(FieldType::Uint32) => Type::Uint32,
+            Some(FieldType::Sfixed32) => Type::Sfixed32,
+            Some(FieldType::Sfixed64) => Type::Sfixed64,
+            Some(FieldType::Sint32) => Type::Sint32,
+            Some(FieldType::Sint64) => Type::Sint64,
+            Some(FieldType::Message) | Some(FieldType::Group) => Type::Message(type_name),
+            Some(FieldType::Enum) => Type::Enum(type_name),
+            None => Type::Named(type_name),
+        }
+    }
+
+    /// The bracketed options a field would have been declared with:
+    /// `json_name` when it differs from the default, `default`, and the
+    /// options proper.
+    fn to_field_options(
+        &self,
+        field: &MessageField,
+        cx: &Decoder,
+        path: &[i32],
+    ) -> Vec<ProtoOption> {
+        let at = |number: i32| Some([path, &[number]].concat());
+        let mut options = vec![];
+        if let Some(json_name) = &self.json_name {
+            if *json_name != field.json_name() {
+                let option = ProtoOption {
+                    name: "json_name".to_string(),
+                    value: Constant::String(json_name.clone()),
+                };
+                options.push((at(10), option));
+            }
+        }
+        if let Some(default) = &self.default_value {
+            let value = match self.r#type {
+                Some(FieldType::String) => Constant::String(default.clone()),
+                Some(FieldType::Bytes) => match String::from_utf8(c_unescape(default)) {
+                    Ok(s) => Constant::String(s),
+                    Err(err) => Constant::Bytes(err.into_bytes()),
+                },
+                Some(FieldType::Bool) => Constant::Bool(default == "true"),
+                Some(FieldType::Enum) => Constant::Ident(default.clone()),
+                Some(FieldType::Double) | Some(FieldType::Float) => match default.parse() {
+                    Ok(int) if !default.contains(['.', 'e', 'E']) => Constant::Int(int),
+                    _ => match default.as_str() {
+                        "inf" | "-inf" | "nan" => Constant::Ident(default.clone()),
+                        _ => Constant::Float(default.clone()),
+                    },
+                },
+                _ => match default.parse() {
+                    Ok(int) => Constant::Int(int),
+                    Err(_) => Constant::Ident(default.clone()),
+                },
+            };
+            let option = ProtoOption {
+                name: "default".to_string(),
+                value,
+            };
+            options.push((at(7), option));
+        }
+        options.extend(cx.options(
+            &[path, &[8]].concat(),
+            &self.options,
+            &self.unknown_options,
+            "google.protobuf.FieldOptions",
+            FIELD_OPTIONS,
+        ));
+        cx.sort(options)
+    }
+}
+
+impl OneofDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut oneof = OneofDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => oneof.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    ONEOF_OPTIONS,
+                    &mut oneof.options,
+                    &mut oneof.unknown_options,
+                )?,
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(oneof)
+    }
+}
+
+impl EnumDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut e = EnumDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => e.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => {
+                    e.value
+                        .push(EnumValueDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (3, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    ENUM_OPTIONS,
+                    &mut e.options,
+                    &mut e.unknown_options,
+                )?,
+                (4, WireType::LengthDelimited) => e
+                    .reserved_range
+                    .push(ReservedRange::decode(decode_length_delimited(&mut buf)?)?),
+                (5, WireType::LengthDelimited) => e.reserved_name.push(decode_string(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(e)
+    }
+
+    fn to_enum(&self, cx: &Decoder, path: &[i32]) -> Enum {
+        let value_options = |i: usize, value: &EnumValueDescriptorProto| {
+            cx.sort(cx.options(
+                &[path, &[2, i as i32, 3]].concat(),
+                &value.options,
+                &value.unknown_options,
+                "google.protobuf.EnumValueOptions",
+                ENUM_VALUE_OPTIONS,
+            ))
+        };
+        let options = cx.sort_keyed(cx.options(
+            &[path, &[3]].concat(),
+            &self.options,
+            &self.unknown_options,
+            "google.protobuf.EnumOptions",
+            ENUM_OPTIONS,
+        ));
+        let mut e = Enum {
+            name: self.name.clone().unwrap_or_default(),
+            fields: self
+                .value
+                .iter()
+                .map(|value| EnumField {
+                    name: value.name.clone().unwrap_or_default(),
+                    position: value.number.unwrap_or_default() as u32,
+                })
+                .collect(),
+            options: options.iter().map(|(_, option)| option.clone()).collect(),
+            value_options: self
+                .value
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    (
+                        value.name.clone().unwrap_or_default(),
+                        value_options(i, value),
+                    )
+                })
+                .filter(|(_, options)| !options.is_empty())
+                .collect(),
+            reserved_ranges: self
+                .reserved_range
+                .iter()
+                .map(|range| {
+                    (
+                        range.start.unwrap_or_default() as u32,
+                        range.end.unwrap_or_default() as u32,
+                    )
+                })
+                .collect(),
+            reserved_names: self.reserved_name.clone(),
+            order: vec![],
+        };
+        e.order = e.complete(self.written_order(&options, cx, path));
+        e.normalize_order();
+        e
+    }
+
+    /// The order of the body as written, from where each statement's span
+    /// starts.
+    fn written_order(
+        &self,
+        options: &[(Option<(i32, i32)>, ProtoOption)],
+        cx: &Decoder,
+        path: &[i32],
+    ) -> Vec<Declaration> {
+        let at = |numbers: &[i32]| [path, numbers].concat();
+        let mut keyed: Vec<(Option<(i32, i32)>, Declaration)> = vec![];
+        for (i, (start, _)) in options.iter().enumerate() {
+            keyed.push((*start, Declaration::Option(i)));
+        }
+        for i in 0..self.value.len() {
+            keyed.push((cx.start(&at(&[2, i as i32])), Declaration::Field(i)));
+        }
+        let statements = cx.statements(&at(&[4]), self.reserved_range.len());
+        for (start, first, count) in statements {
+            keyed.push((Some(start), Declaration::Reserved { first, count }));
+        }
+        let statements = cx.statements(&at(&[5]), self.reserved_name.len());
+        for (start, first, count) in statements {
+            keyed.push((Some(start), Declaration::ReservedNames { first, count }));
+        }
+        keyed.retain(|(start, _)| start.is_some());
+        keyed.sort_by_key(|(start, _)| *start);
+        keyed
+            .into_iter()
+            .map(|(_, declaration)| declaration)
+            .collect()
+    }
+}
+
+impl EnumValueDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut value = EnumValueDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => value.name = Some(decode_string(&mut buf)?),
+                (2, WireType::Varint) => value.number = Some(decode_int32(&mut buf)?),
+                (3, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    ENUM_VALUE_OPTIONS,
+                    &mut value.options,
+                    &mut value.unknown_options,
+                )?,
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl ServiceDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut service = ServiceDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => service.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => {
+                    service
+                        .method
+                        .push(MethodDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (3, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    SERVICE_OPTIONS,
+                    &mut service.options,
+                    &mut service.unknown_options,
+                )?,
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(service)
+    }
+
+    fn to_service(&self, cx: &Decoder, path: &[i32]) -> Service {
+        Service {
+            name: self.name.clone().unwrap_or_default(),
+            methods: self
+                .method
+                .iter()
+                .enumerate()
+                .map(|(i, method)| Method {
+                    name: method.name.clone().unwrap_or_default(),
+                    input_type: method.input_type.clone().unwrap_or_default(),
+                    output_type: method.output_type.clone().unwrap_or_default(),
+                    client_streaming: method.client_streaming == Some(true),
+                    server_streaming: method.server_streaming == Some(true),
+                    options: cx.sort(cx.options(
+                        &[path, &[2, i as i32, 4]].concat(),
+                        &method.options,
+                        &method.unknown_options,
+                        "google.protobuf.MethodOptions",
+                        METHOD_OPTIONS,
+                    )),
+                })
+                .collect(),
+            options: cx.sort(cx.options(
+                &[path, &[3]].concat(),
+                &self.options,
+                &self.unknown_options,
+                "google.protobuf.ServiceOptions",
+                SERVICE_OPTIONS,
+            )),
+        }
+    }
+}
+
+impl MethodDescriptorProto {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut method = MethodDescriptorProto::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => method.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => {
+                    method.input_type = Some(decode_string(&mut buf)?)
+                }
+                (3, WireType::LengthDelimited) => {
+                    method.output_type = Some(decode_string(&mut buf)?)
+                }
+                (4, WireType::LengthDelimited) => decode_options(
+                    decode_length_delimited(&mut buf)?,
+                    METHOD_OPTIONS,
+                    &mut method.options,
+                    &mut method.unknown_options,
+                )?,
+                (5, WireType::Varint) => {
+                    method.client_streaming = Some(decode_varint(&mut buf)? != 0)
+                }
+                (6, WireType::Varint) => {
+                    method.server_streaming = Some(decode_varint(&mut buf)? != 0)
+                }
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(method)
+    }
+}
+
+impl SourceCodeInfo {
+    /// Translates descriptor paths back into paths into `file`, the inverse
+    /// of `from_locations`. Locations of things the AST does not keep are
+    /// dropped.
+    pub fn to_locations(&self, file: &ProtoFile, set: Option<&FileSet>) -> Vec<Location> {
+        let mut candidates: BTreeMap<Vec<i32>, Vec<Vec<PathSegment>>> = BTreeMap::new();
+        for path in ast_paths(file) {
+            for descriptor_path in descriptor_paths(file, &path, set) {
+                candidates
+                    .entry(descriptor_path)
+                    .or_default()
+                    .push(path.clone());
+            }
+        }
+
+        // Some descriptor paths, like that of the file's options message,
+        // are shared by several statements; they are recorded in source
+        // order, as are the AST's vectors.
+        let mut seen: BTreeMap<&[i32], usize> = BTreeMap::new();
+        let mut ret: Vec<Location> = vec![];
+        for location in &self.location {
+            let Some(paths) = candidates.get(&location.path) else {
+                continue;
+            };
+            let n = seen.entry(&location.path).or_default();
+            let path = paths[(*n).min(paths.len() - 1)].clone();
+            *n += 1;
+            let span = match location.span[..] {
+                [line, start_column, end_column] => Span {
+                    start_line: line,
+                    start_column,
+                    end_line: line,
+                    end_column,
+                },
+                [start_line, start_column, end_line, end_column] => Span {
+                    start_line,
+                    start_column,
+                    end_line,
+                    end_column,
+                },
+                _ => continue,
+            };
+            // An option statement is recorded at two descriptor paths.
+            if ret.last().is_some_and(|l| l.path == path && l.span == span) {
+                continue;
+            }
+            ret.push(Location {
+                path,
+                span,
+                leading_comments: location.leading_comments.clone(),
+                trailing_comments: location.trailing_comments.clone(),
+                leading_detached_comments: location.leading_detached_comments.clone(),
+            });
+        }
+        ret
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut info = SourceCodeInfo::default();
+        while !buf.is_empty() {
+            match decode_key(&mut buf)? {
+                (1, WireType::LengthDelimited) => {
+                    info.location
+                        .push(SourceCodeLocation::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                (number, wire_type) => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(info)
+    }
+}
+
+impl SourceCodeLocation {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, Error> {
+        let mut location = SourceCodeLocation::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, _) => decode_repeated_int32(wire_type, &mut buf, &mut location.path)?,
+                (2, _) => decode_repeated_int32(wire_type, &mut buf, &mut location.span)?,
+                (3, WireType::LengthDelimited) => {
+                    location.leading_comments = Some(decode_string(&mut buf)?)
+                }
+                (4, WireType::LengthDelimited) => {
+                    location.trailing_comments = Some(decode_string(&mut buf)?)
+                }
+                (6, WireType::LengthDelimited) => location
+                    .leading_detached_comments
+                    .push(decode_string(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn golden(name: &str) {
+        let mut resolver = Resolver::new();
+        resolver.include("tests/descriptor");
+        let set = resolver.load(&[&format!("{}.proto", name)]).unwrap();
+
+        let expected = std::fs::read(format!("tests/descriptor/{}.pb", name)).unwrap();
+        let actual = FileDescriptorSet::from_file_set(&set, true).encode_to_vec();
+        assert_eq!(actual, expected);
+    }
+
+    /// Decoding `protoc`'s output gives back the same bytes on re-encoding
+    /// and the same AST as parsing the source.
+    fn decode_golden(name: &str) {
+        let mut resolver = Resolver::new();
+        resolver.include("tests/descriptor");
+        let parsed = resolver.load(&[&format!("{}.proto", name)]).unwrap();
+
+        let bytes = std::fs::read(format!("tests/descriptor/{}.pb", name)).unwrap();
+        let set = FileDescriptorSet::decode(&bytes).unwrap();
+        assert_eq!(set.encode_to_vec(), bytes);
+        let decoded = set.to_file_set();
+        assert_eq!(decoded.files, parsed.files);
+        // protoc records an option and its value under one path, so only the
+        // commented locations are expected to match exactly.
+        let commented = |locations: &Vec<Location>| {
+            locations
+                .iter()
+                .filter(|l| {
+                    l.leading_comments.is_some()
+                        || l.trailing_comments.is_some()
+                        || !l.leading_detached_comments.is_empty()
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        for (name, locations) in &parsed.locations {
+            assert_eq!(commented(&decoded.locations[name]), commented(locations));
+        }
+    }
+
+    #[test]
+    fn golden_person() {
+        golden("person");
+    }
+
+    #[test]
+    fn golden_options() {
+        golden("options");
+    }
+
+    #[test]
+    fn golden_extend() {
+        golden("extend");
+    }
+
+    #[test]
+    fn golden_reserved() {
+        golden("reserved");
+    }
+
+    #[test]
+    fn golden_order() {
+        golden("order");
+    }
+
+    #[test]
+    fn decode_order() {
+        decode_golden("order");
+
+        // Without source info, the order comes from the descriptor's lists.
+        let bytes = std::fs::read("tests/descriptor/order.pb").unwrap();
+        let mut set = FileDescriptorSet::decode(&bytes).unwrap();
+        set.file
+            .iter_mut()
+            .for_each(|file| file.source_code_info = None);
+        let files = set.to_file_set().files;
+        let file = files.iter().find(|f| f.name == "order.proto").unwrap();
+        let mixed = &file.messages[0];
+        assert_eq!(
+            mixed.declarations()[..6],
+            [
+                Declaration::Field(0),
+                Declaration::Oneof(0),
+                Declaration::Field(1),
+                Declaration::Message(0),
+                Declaration::Field(2),
+                Declaration::Field(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_reserved() {
+        let bytes = std::fs::read("tests/descriptor/reserved.pb").unwrap();
+        let set = FileDescriptorSet::decode(&bytes).unwrap();
+        assert_eq!(set.encode_to_vec(), bytes);
+
+        let files = set.to_file_set().files;
+        let file = files.iter().find(|f| f.name == "reserved.proto").unwrap();
+        let event = &file.messages[0];
+        assert_eq!(event.reserved_ranges, vec![(2, 2), (15, 15), (9, 11)]);
+        assert_eq!(event.reserved_names, vec!["old_name", "legacy"]);
+        let level = &file.enums[0];
+        assert_eq!(level.reserved_ranges, vec![(5, 8), (32, 32)]);
+        assert_eq!(level.value_options["VERBOSE"].len(), 1);
+    }
+
+    #[test]
+    fn decode_extend() {
+        decode_golden("extend");
+    }
+
+    #[test]
+    fn decode_person() {
+        decode_golden("person");
+    }
+
+    #[test]
+    fn decode_options() {
+        decode_golden("options");
+    }
+
+    /// Custom options are encoded as `protoc` interprets them, and named
+    /// after their extensions when decoded.
+    #[test]
+    fn custom_options() {
+        let mut resolver = Resolver::new();
+        resolver.include("tests/descriptor");
+        let parsed = resolver.load(&["custom.proto"]).unwrap();
+
+        let bytes = std::fs::read("tests/descriptor/custom.pb").unwrap();
+        let set = FileDescriptorSet::decode(&bytes).unwrap();
+        assert_eq!(set.encode_to_vec(), bytes);
+        let actual = FileDescriptorSet::from_file_set(&parsed, true);
+        assert_eq!(actual.encode_to_vec(), bytes);
+
+        let decoded = set.to_file_set();
+        assert_eq!(decoded.files.last(), parsed.files.last());
+        // Each value of a repeated option keeps its place.
+        let messages = &decoded.files[0].messages;
+        let features = messages.iter().find(|m| m.name == "FeatureSet").unwrap();
+        let names: Vec<_> = features.field_options[&1]
+            .iter()
+            .map(|o| &o.name[..])
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "retention",
+                "targets",
+                "targets",
+                "feature_support",
+                "edition_defaults",
+                "edition_defaults",
+                "edition_defaults"
+            ]
+        );
+        let account = &decoded.files.last().unwrap().messages[1];
+        assert_eq!(account.options[0].name, "(custom.rule)");
+        let comment = decoded.locations["custom.proto"]
+            .iter()
+            .find(|l| l.path == [PathSegment::Message(1)])
+            .and_then(|l| l.leading_comments.as_deref());
+        assert_eq!(comment, Some(" An account, keyed by id.\n"));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let bytes = std::fs::read("tests/descriptor/person.pb").unwrap();
+        assert!(matches!(
+            FileDescriptorSet::decode(&bytes[..bytes.len() - 1]),
+            Err(Error::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn field_descriptor_bytes() {
+        let field = FieldDescriptorProto::from_field(
+            &MessageField::Int32(None, "a".to_string(), 1),
+            None,
+            ".tutorial.Test1",
+        );
+        let mut buf = vec![];
+        field.encode(&mut buf);
+
+        assert_eq!(
+            buf,
+            vec![0x0a, 0x01, b'a', 0x18, 0x01, 0x20, 0x01, 0x28, 0x05, 0x52, 0x01, b'a']
+        );
+    }
+
+    #[test]
+    fn map_entry_names() {
+        assert_eq!(map_entry_name("phones"), "PhonesEntry");
+        assert_eq!(map_entry_name("phone_numbers"), "PhoneNumbersEntry");
+    }
+}
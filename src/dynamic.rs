@@ -0,0 +1,888 @@
+//! Messages whose layout comes from a parsed schema at runtime rather than
+//! from generated code.
+
+use crate::resolver::{self, FileSet};
+use crate::wire::{self, *};
+use crate::*;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The schema could not be linked.
+    Resolve(resolver::Error),
+    /// No message with this fully qualified name is in the schema.
+    UnknownType(String),
+    NoSuchField(String),
+    /// A value does not match the declared type of the field it was set on.
+    TypeMismatch {
+        field: String,
+        expected: String,
+    },
+    Decode(wire::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Resolve(err) => write!(f, "{}", err),
+            Error::UnknownType(name) => write!(f, "unknown message type \"{}\"", name),
+            Error::NoSuchField(name) => write!(f, "no field \"{}\"", name),
+            Error::TypeMismatch { field, expected } => {
+                write!(f, "field \"{}\" expects {}", field, expected)
+            }
+            Error::Decode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<wire::Error> for Error {
+    fn from(err: wire::Error) -> Self {
+        Error::Decode(err)
+    }
+}
+
+/// A field value. Repeated fields hold a `List`, map fields a `Map`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    /// An enum value's number; numbers the enum does not declare are kept.
+    Enum(i32),
+    Message(DynamicMessage),
+    List(Vec<Value>),
+    Map(BTreeMap<MapKey, Value>),
+}
+
+/// The types protobuf allows as map keys.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    String(String),
+}
+
+impl From<MapKey> for Value {
+    fn from(key: MapKey) -> Self {
+        match key {
+            MapKey::Bool(b) => Value::Bool(b),
+            MapKey::I32(i) => Value::I32(i),
+            MapKey::I64(i) => Value::I64(i),
+            MapKey::U32(u) => Value::U32(u),
+            MapKey::U64(u) => Value::U64(u),
+            MapKey::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl MapKey {
+    pub(crate) fn from_value(value: Value) -> Option<Self> {
+        Some(match value {
+            Value::Bool(b) => MapKey::Bool(b),
+            Value::I32(i) => MapKey::I32(i),
+            Value::I64(i) => MapKey::I64(i),
+            Value::U32(u) => MapKey::U32(u),
+            Value::U64(u) => MapKey::U64(u),
+            Value::String(s) => MapKey::String(s),
+            _ => return None,
+        })
+    }
+}
+
+/// A message of any type in a linked schema, with fields set and read by
+/// name or number.
+///
+/// Fields are kept in field number order and encoded in that order,
+/// followed by any unknown fields exactly as they were decoded.
+#[derive(Debug, Clone)]
+pub struct DynamicMessage {
+    set: Arc<FileSet>,
+    type_name: String,
+    fields: BTreeMap<u32, Value>,
+    unknown_fields: Vec<u8>,
+}
+
+impl PartialEq for DynamicMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name == other.type_name
+            && self.fields == other.fields
+            && self.unknown_fields == other.unknown_fields
+    }
+}
+
+impl DynamicMessage {
+    /// Creates an empty message for a standalone definition, such as one
+    /// from `Parser::consume_message`. Field types must resolve within the
+    /// message itself. With no file to say otherwise, repeated scalars are
+    /// packed as in proto3.
+    pub fn new(message: &Message) -> Result<Self, Error> {
+        let file = ProtoFile {
+            syntax: Some("proto3".to_string()),
+            messages: vec![message.clone()],
+            ..Default::default()
+        };
+        let set = FileSet::new(vec![file]).map_err(Error::Resolve)?;
+        DynamicMessage::from_file_set(Arc::new(set), &message.name)
+    }
+
+    /// Creates an empty message of the type with fully qualified name
+    /// `type_name` in `set`.
+    pub fn from_file_set(set: Arc<FileSet>, type_name: &str) -> Result<Self, Error> {
+        let type_name = format!(".{}", type_name.strip_prefix('.').unwrap_or(type_name));
+        if set.find_message(&type_name).is_none() {
+            return Err(Error::UnknownType(type_name));
+        }
+        Ok(DynamicMessage {
+            set,
+            type_name,
+            fields: BTreeMap::new(),
+            unknown_fields: vec![],
+        })
+    }
+
+    /// Creates an empty message of another type from the same schema, for
+    /// example to fill in a message-typed field.
+    pub fn new_message(&self, type_name: &str) -> Result<Self, Error> {
+        DynamicMessage::from_file_set(self.set.clone(), type_name)
+    }
+
+    pub fn file_set(&self) -> &Arc<FileSet> {
+        &self.set
+    }
+
+    /// The fully qualified name of this message's type, with a leading dot.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn descriptor(&self) -> &Message {
+        self.set
+            .find_message(&self.type_name)
+            .expect("type was checked on construction")
+    }
+
+    /// Every field of the message, including the members of its oneofs.
+    pub fn field_descriptors(&self) -> impl Iterator<Item = &MessageField> {
+        let message = self.descriptor();
+        message
+            .fields
+            .iter()
+            .chain(message.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+    }
+
+    /// Finds a field by name, or an extension of this message by its fully
+    /// qualified name.
+    pub fn field_by_name(&self, name: &str) -> Option<&MessageField> {
+        self.field_descriptors()
+            .find(|f| f.name() == name)
+            .or_else(|| {
+                self.extension(|e| e.name.strip_prefix('.') == Some(name.trim_start_matches('.')))
+            })
+    }
+
+    pub fn field_by_number(&self, number: u32) -> Option<&MessageField> {
+        self.field_descriptors()
+            .find(|f| f.position() == number)
+            .or_else(|| self.extension(|e| e.field.position() == number))
+    }
+
+    fn extension(
+        &self,
+        predicate: impl Fn(&resolver::ExtensionField) -> bool,
+    ) -> Option<&MessageField> {
+        self.set
+            .extensions_of(&self.type_name)
+            .into_iter()
+            .find(predicate)
+            .map(|e| e.field)
+    }
+
+    /// The fully qualified name of the extension with this field number, if
+    /// the number belongs to an extension rather than a regular field.
+    pub fn extension_name(&self, number: u32) -> Option<String> {
+        if self.field_descriptors().any(|f| f.position() == number) {
+            return None;
+        }
+        self.set
+            .extensions_of(&self.type_name)
+            .into_iter()
+            .find(|e| e.field.position() == number)
+            .map(|e| e.name)
+    }
+
+    /// The field's name in JSON: its `json_name` option if it has one,
+    /// otherwise its name in lowerCamelCase.
+    pub fn json_name(&self, field: &MessageField) -> String {
+        self.field_options(field.position())
+            .into_iter()
+            .flatten()
+            .find_map(|option| match (option.name.as_str(), &option.value) {
+                ("json_name", Constant::String(name)) => Some(name.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| field.json_name())
+    }
+
+    /// The bracketed options of a field or extension.
+    fn field_options(&self, number: u32) -> Option<&Vec<ProtoOption>> {
+        let message = self.descriptor();
+        message
+            .field_options
+            .get(&number)
+            .or_else(|| {
+                message
+                    .oneofs
+                    .iter()
+                    .find_map(|oneof| oneof.field_options.get(&number))
+            })
+            .or_else(|| {
+                self.set
+                    .extensions_of(&self.type_name)
+                    .into_iter()
+                    .find(|e| e.field.position() == number)
+                    .and_then(|e| e.options)
+            })
+    }
+
+    fn field_number(&self, name: &str) -> Result<u32, Error> {
+        self.field_by_name(name)
+            .map(MessageField::position)
+            .ok_or_else(|| Error::NoSuchField(name.to_string()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.get_by_number(self.field_number(name).ok()?)
+    }
+
+    pub fn get_by_number(&self, number: u32) -> Option<&Value> {
+        self.fields.get(&number)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets a field after checking `value` against its declared type.
+    /// Setting a member of a oneof clears the other members.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        let number = self.field_number(name)?;
+        self.set_by_number(number, value)
+    }
+
+    pub fn set_by_number(&mut self, number: u32, value: Value) -> Result<(), Error> {
+        let field = self
+            .field_by_number(number)
+            .ok_or_else(|| Error::NoSuchField(number.to_string()))?;
+        if !field_accepts(field, &value) {
+            return Err(Error::TypeMismatch {
+                field: field.name().to_string(),
+                expected: expected_description(field),
+            });
+        }
+
+        let siblings: Vec<u32> = self
+            .descriptor()
+            .oneofs
+            .iter()
+            .find(|oneof| oneof.fields.iter().any(|f| f.position() == number))
+            .map(|oneof| oneof.fields.iter().map(MessageField::position).collect())
+            .unwrap_or_default();
+        for sibling in siblings {
+            self.fields.remove(&sibling);
+        }
+        self.fields.insert(number, value);
+        Ok(())
+    }
+
+    /// Appends an element to a repeated field.
+    pub fn add(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        let number = self.field_number(name)?;
+        self.add_by_number(number, value)
+    }
+
+    pub fn add_by_number(&mut self, number: u32, value: Value) -> Result<(), Error> {
+        let field = self
+            .field_by_number(number)
+            .ok_or_else(|| Error::NoSuchField(number.to_string()))?;
+        if matches!(field, MessageField::Map(..))
+            || !field.is_repeated()
+            || !value_accepts(&field.field_type(), &value)
+        {
+            return Err(Error::TypeMismatch {
+                field: field.name().to_string(),
+                expected: expected_description(field),
+            });
+        }
+        match self.fields.entry(number).or_insert(Value::List(vec![])) {
+            Value::List(list) => list.push(value),
+            _ => unreachable!("repeated fields only hold lists"),
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry into a map field.
+    pub fn insert(&mut self, name: &str, key: MapKey, value: Value) -> Result<(), Error> {
+        let number = self.field_number(name)?;
+        self.insert_by_number(number, key, value)
+    }
+
+    pub fn insert_by_number(
+        &mut self,
+        number: u32,
+        key: MapKey,
+        value: Value,
+    ) -> Result<(), Error> {
+        let field = self
+            .field_by_number(number)
+            .ok_or_else(|| Error::NoSuchField(number.to_string()))?;
+        let accepts = match field.field_type() {
+            Type::Map(key_type, value_type) => {
+                value_accepts(&key_type, &key.clone().into()) && value_accepts(&value_type, &value)
+            }
+            _ => false,
+        };
+        if !accepts {
+            return Err(Error::TypeMismatch {
+                field: field.name().to_string(),
+                expected: expected_description(field),
+            });
+        }
+        match self
+            .fields
+            .entry(number)
+            .or_insert(Value::Map(BTreeMap::new()))
+        {
+            Value::Map(entries) => {
+                entries.insert(key, value);
+            }
+            _ => unreachable!("map fields only hold maps"),
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self, name: &str) {
+        if let Ok(number) = self.field_number(name) {
+            self.fields.remove(&number);
+        }
+    }
+
+    /// The fields that are set, in field number order.
+    pub fn fields(&self) -> impl Iterator<Item = (&MessageField, &Value)> {
+        self.fields
+            .iter()
+            .filter_map(|(number, value)| self.field_by_number(*number).map(|field| (field, value)))
+    }
+
+    /// Fields that were decoded but are not in the schema, still encoded.
+    pub fn unknown_fields(&self) -> &[u8] {
+        &self.unknown_fields
+    }
+
+    fn is_packed(&self, field: &MessageField) -> bool {
+        if !field.is_repeated() || !is_packable(&field.field_type()) {
+            return false;
+        }
+        let packed_option = self
+            .field_options(field.position())
+            .into_iter()
+            .flatten()
+            .find(|option| option.name == "packed")
+            .map(|option| option.value == Constant::Bool(true));
+        packed_option.unwrap_or_else(|| {
+            self.set
+                .find_message_file(&self.type_name)
+                .and_then(|file| file.syntax.as_deref())
+                == Some("proto3")
+        })
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for (field, value) in self.fields() {
+            let number = field.position();
+            match (field.field_type(), value) {
+                (Type::Map(key_type, value_type), Value::Map(entries)) => {
+                    for (key, value) in entries {
+                        let mut entry = vec![];
+                        encode_field(1, &key_type, &key.clone().into(), &mut entry);
+                        encode_field(2, &value_type, value, &mut entry);
+                        encode_length_delimited(number, &entry, buf);
+                    }
+                }
+                (t, Value::List(values)) if self.is_packed(field) => {
+                    if values.is_empty() {
+                        continue;
+                    }
+                    let mut packed = vec![];
+                    for value in values {
+                        encode_value(&t, value, &mut packed);
+                    }
+                    encode_length_delimited(number, &packed, buf);
+                }
+                (t, Value::List(values)) => {
+                    for value in values {
+                        encode_field(number, &t, value, buf);
+                    }
+                }
+                (t, value) => encode_field(number, &t, value, buf),
+            }
+        }
+        buf.extend_from_slice(&self.unknown_fields);
+    }
+
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Decodes a message of this type, merging its fields into this one
+    /// the way protobuf parsers do: scalars are replaced, repeated fields
+    /// are appended to, and embedded messages are merged.
+    pub fn merge(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.merge_nested(buf, RECURSION_LIMIT)
+    }
+
+    /// [`merge`](Self::merge) with `depth` more levels of messages allowed.
+    fn merge_nested(&mut self, mut buf: &[u8], depth: usize) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let start = buf;
+            let (number, wire_type) = decode_key(&mut buf)?;
+            let field = self.field_by_number(number).cloned();
+            let decoded = match &field {
+                Some(field) => self.merge_field(field, wire_type, &mut buf, depth)?,
+                None => false,
+            };
+            if !decoded {
+                skip_field(number, wire_type, &mut buf)?;
+                let len = start.len() - buf.len();
+                self.unknown_fields.extend_from_slice(&start[..len]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes one field's value into the message. Returns `false`, leaving
+    /// `buf` untouched, if the wire type does not fit the field's type.
+    fn merge_field(
+        &mut self,
+        field: &MessageField,
+        wire_type: WireType,
+        buf: &mut &[u8],
+        depth: usize,
+    ) -> Result<bool, Error> {
+        let number = field.position();
+        let t = field.field_type();
+
+        if let Type::Map(key_type, value_type) = &t {
+            if wire_type != WireType::LengthDelimited {
+                return Ok(false);
+            }
+            let mut entry = decode_length_delimited(buf)?;
+            let mut key = None;
+            let mut value = None;
+            while !entry.is_empty() {
+                let (entry_number, entry_wire_type) = decode_key(&mut entry)?;
+                let entry_type = match entry_number {
+                    1 => key_type,
+                    2 => value_type,
+                    _ => {
+                        skip_field(entry_number, entry_wire_type, &mut entry)?;
+                        continue;
+                    }
+                };
+                if entry_wire_type != wire_type_of(entry_type) {
+                    skip_field(entry_number, entry_wire_type, &mut entry)?;
+                    continue;
+                }
+                let decoded = self.decode_value(entry_type, &mut entry, depth)?;
+                match entry_number {
+                    1 => key = Some(decoded),
+                    _ => value = Some(decoded),
+                }
+            }
+            let key = key.unwrap_or_else(|| self.default_value(key_type));
+            let value = value.unwrap_or_else(|| self.default_value(value_type));
+            let key = MapKey::from_value(key).expect("map keys are scalar");
+            match self
+                .fields
+                .entry(number)
+                .or_insert(Value::Map(BTreeMap::new()))
+            {
+                Value::Map(entries) => {
+                    entries.insert(key, value);
+                }
+                _ => unreachable!("map fields only hold maps"),
+            }
+            return Ok(true);
+        }
+
+        let expected = wire_type_of(&t);
+        if field.is_repeated() {
+            let mut values = vec![];
+            if wire_type == WireType::LengthDelimited && expected != WireType::LengthDelimited {
+                let mut packed = decode_length_delimited(buf)?;
+                while !packed.is_empty() {
+                    values.push(self.decode_value(&t, &mut packed, depth)?);
+                }
+            } else if wire_type == expected {
+                values.push(self.decode_value(&t, buf, depth)?);
+            } else {
+                return Ok(false);
+            }
+            match self.fields.entry(number).or_insert(Value::List(vec![])) {
+                Value::List(list) => list.extend(values),
+                _ => unreachable!("repeated fields only hold lists"),
+            }
+            return Ok(true);
+        }
+
+        if wire_type != expected {
+            return Ok(false);
+        }
+        if let Some(Value::Message(existing)) = self.fields.get_mut(&number) {
+            let depth = depth
+                .checked_sub(1)
+                .ok_or(wire::Error::RecursionLimitExceeded)?;
+            existing.merge_nested(decode_length_delimited(buf)?, depth)?;
+            return Ok(true);
+        }
+        let value = self.decode_value(&t, buf, depth)?;
+        self.set_by_number(number, value)?;
+        Ok(true)
+    }
+
+    fn decode_value(&self, t: &Type, buf: &mut &[u8], depth: usize) -> Result<Value, Error> {
+        Ok(match t {
+            Type::Double => Value::F64(f64::from_bits(decode_fixed64(buf)?)),
+            Type::Float => Value::F32(f32::from_bits(decode_fixed32(buf)?)),
+            Type::Int32 => Value::I32(decode_varint(buf)? as i32),
+            Type::Int64 => Value::I64(decode_varint(buf)? as i64),
+            Type::Uint32 => Value::U32(decode_varint(buf)? as u32),
+            Type::Uint64 => Value::U64(decode_varint(buf)?),
+            Type::Sint32 => Value::I32(decode_zigzag32(decode_varint(buf)? as u32)),
+            Type::Sint64 => Value::I64(decode_zigzag64(decode_varint(buf)?)),
+            Type::Fixed32 => Value::U32(decode_fixed32(buf)?),
+            Type::Fixed64 => Value::U64(decode_fixed64(buf)?),
+            Type::Sfixed32 => Value::I32(decode_fixed32(buf)? as i32),
+            Type::Sfixed64 => Value::I64(decode_fixed64(buf)? as i64),
+            Type::Bool => Value::Bool(decode_varint(buf)? != 0),
+            Type::String => Value::String(decode_string(buf)?),
+            Type::Bytes => Value::Bytes(decode_length_delimited(buf)?.to_vec()),
+            Type::Enum(_) => Value::Enum(decode_varint(buf)? as i32),
+            Type::Message(name) => {
+                let depth = depth
+                    .checked_sub(1)
+                    .ok_or(wire::Error::RecursionLimitExceeded)?;
+                let mut message = self.new_message(name)?;
+                message.merge_nested(decode_length_delimited(buf)?, depth)?;
+                Value::Message(message)
+            }
+            Type::Named(name) => return Err(Error::UnknownType(name.clone())),
+            Type::Map(..) => unreachable!("maps are decoded entry by entry"),
+        })
+    }
+
+    /// The value an unset field of type `t` reads as.
+    pub fn default_value(&self, t: &Type) -> Value {
+        match t {
+            Type::Double => Value::F64(0.0),
+            Type::Float => Value::F32(0.0),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => Value::I32(0),
+            Type::Int64 | Type::Sint64 | Type::Sfixed64 => Value::I64(0),
+            Type::Uint32 | Type::Fixed32 => Value::U32(0),
+            Type::Uint64 | Type::Fixed64 => Value::U64(0),
+            Type::Bool => Value::Bool(false),
+            Type::String => Value::String(String::new()),
+            Type::Bytes => Value::Bytes(vec![]),
+            Type::Enum(name) => Value::Enum(
+                self.set
+                    .find_enum(name)
+                    .and_then(|e| e.fields.first())
+                    .map_or(0, |value| value.position as i32),
+            ),
+            Type::Message(name) | Type::Named(name) => match self.new_message(name) {
+                Ok(message) => Value::Message(message),
+                Err(_) => Value::Bytes(vec![]),
+            },
+            Type::Map(..) => Value::Map(BTreeMap::new()),
+        }
+    }
+}
+
+fn is_packable(t: &Type) -> bool {
+    !matches!(
+        t,
+        Type::String | Type::Bytes | Type::Message(_) | Type::Named(_) | Type::Map(..)
+    )
+}
+
+fn wire_type_of(t: &Type) -> WireType {
+    match t {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => WireType::Fixed64,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => WireType::Fixed32,
+        Type::String | Type::Bytes | Type::Message(_) | Type::Named(_) | Type::Map(..) => {
+            WireType::LengthDelimited
+        }
+        _ => WireType::Varint,
+    }
+}
+
+fn value_accepts(t: &Type, value: &Value) -> bool {
+    match (t, value) {
+        (Type::Double, Value::F64(_)) | (Type::Float, Value::F32(_)) => true,
+        (Type::Int32 | Type::Sint32 | Type::Sfixed32, Value::I32(_)) => true,
+        (Type::Int64 | Type::Sint64 | Type::Sfixed64, Value::I64(_)) => true,
+        (Type::Uint32 | Type::Fixed32, Value::U32(_)) => true,
+        (Type::Uint64 | Type::Fixed64, Value::U64(_)) => true,
+        (Type::Bool, Value::Bool(_)) => true,
+        (Type::String, Value::String(_)) => true,
+        (Type::Bytes, Value::Bytes(_)) => true,
+        (Type::Enum(_), Value::Enum(_)) => true,
+        (Type::Message(name), Value::Message(message)) => message.type_name == *name,
+        _ => false,
+    }
+}
+
+fn field_accepts(field: &MessageField, value: &Value) -> bool {
+    match (field.field_type(), value) {
+        (Type::Map(key_type, value_type), Value::Map(entries)) => entries.iter().all(|(k, v)| {
+            value_accepts(&key_type, &k.clone().into()) && value_accepts(&value_type, v)
+        }),
+        (t, Value::List(values)) if field.is_repeated() => {
+            values.iter().all(|v| value_accepts(&t, v))
+        }
+        (t, value) => !field.is_repeated() && value_accepts(&t, value),
+    }
+}
+
+fn expected_description(field: &MessageField) -> String {
+    let t: String = field.field_type().into();
+    match field {
+        MessageField::Map(..) => format!("a map of {}", t),
+        _ if field.is_repeated() => format!("a list of {}", t),
+        _ => t,
+    }
+}
+
+fn encode_value(t: &Type, value: &Value, buf: &mut Vec<u8>) {
+    match (t, value) {
+        (Type::Sint32, Value::I32(v)) => encode_varint(encode_zigzag32(*v) as u64, buf),
+        (Type::Sint64, Value::I64(v)) => encode_varint(encode_zigzag64(*v), buf),
+        (Type::Sfixed32, Value::I32(v)) => encode_fixed32(*v as u32, buf),
+        (Type::Sfixed64, Value::I64(v)) => encode_fixed64(*v as u64, buf),
+        (Type::Fixed32, Value::U32(v)) => encode_fixed32(*v, buf),
+        (Type::Fixed64, Value::U64(v)) => encode_fixed64(*v, buf),
+        (_, Value::F64(v)) => encode_fixed64(v.to_bits(), buf),
+        (_, Value::F32(v)) => encode_fixed32(v.to_bits(), buf),
+        (_, Value::I32(v) | Value::Enum(v)) => encode_varint(*v as i64 as u64, buf),
+        (_, Value::I64(v)) => encode_varint(*v as u64, buf),
+        (_, Value::U32(v)) => encode_varint(*v as u64, buf),
+        (_, Value::U64(v)) => encode_varint(*v, buf),
+        (_, Value::Bool(v)) => encode_varint(*v as u64, buf),
+        (_, Value::String(s)) => {
+            encode_varint(s.len() as u64, buf);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        (_, Value::Bytes(bytes)) => {
+            encode_varint(bytes.len() as u64, buf);
+            buf.extend_from_slice(bytes);
+        }
+        (_, Value::Message(message)) => {
+            let bytes = message.encode_to_vec();
+            encode_varint(bytes.len() as u64, buf);
+            buf.extend_from_slice(&bytes);
+        }
+        (_, Value::List(_) | Value::Map(_)) => {
+            unreachable!("nested collections are rejected by set")
+        }
+    }
+}
+
+fn encode_field(number: u32, t: &Type, value: &Value, buf: &mut Vec<u8>) {
+    encode_key(number, wire_type_of(t), buf);
+    encode_value(t, value, buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person() -> DynamicMessage {
+        let message = Parser::new(
+            "message Person {
+                string name = 1;
+                int32 id = 2;
+                repeated sint32 scores = 3;
+                Phone phone = 4;
+                map<string, int64> counts = 5;
+                oneof contact {
+                    string email = 6;
+                    fixed64 pager = 7;
+                }
+                message Phone { string number = 1; }
+            }",
+        )
+        .consume_message()
+        .unwrap();
+        DynamicMessage::new(&message).unwrap()
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut person = person();
+        person
+            .set("name", Value::String("Ada".to_string()))
+            .unwrap();
+        person.set_by_number(2, Value::I32(7)).unwrap();
+
+        assert_eq!(person.get("name"), Some(&Value::String("Ada".to_string())));
+        assert_eq!(person.get_by_number(2), Some(&Value::I32(7)));
+        assert!(!person.has("phone"));
+
+        assert!(matches!(
+            person.set("id", Value::String("7".to_string())),
+            Err(Error::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            person.set("scores", Value::I32(1)),
+            Err(Error::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            person.set("missing", Value::I32(1)),
+            Err(Error::NoSuchField(_))
+        ));
+
+        person
+            .set("email", Value::String("a@b".to_string()))
+            .unwrap();
+        person.set("pager", Value::U64(5)).unwrap();
+        assert!(!person.has("email"));
+        assert!(person.has("pager"));
+    }
+
+    #[test]
+    fn encode() {
+        let mut person = person();
+        person.set("id", Value::I32(150)).unwrap();
+        person
+            .set("scores", Value::List(vec![Value::I32(-1), Value::I32(1)]))
+            .unwrap();
+        let mut phone = person.new_message(".Person.Phone").unwrap();
+        phone.set("number", Value::String("1".to_string())).unwrap();
+        person.set("phone", Value::Message(phone)).unwrap();
+        person
+            .set(
+                "counts",
+                Value::Map(BTreeMap::from([(
+                    MapKey::String("a".to_string()),
+                    Value::I64(1),
+                )])),
+            )
+            .unwrap();
+
+        assert_eq!(
+            person.encode_to_vec(),
+            vec![
+                0x10, 0x96, 0x01, // id
+                0x1a, 0x02, 0x01, 0x02, // scores, packed and zigzagged
+                0x22, 0x03, 0x0a, 0x01, b'1', // phone
+                0x2a, 0x05, 0x0a, 0x01, b'a', 0x10, 0x01, // counts
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_round_trip_with_unknown_fields() {
+        let bytes = vec![
+            0x0a, 0x03, b'A', b'd', b'a', // name
+            0x18, 0x01, 0x18, 0x02, // scores, unpacked
+            0x39, 0x05, 0, 0, 0, 0, 0, 0, 0, // pager
+            0x50, 0x2a, // field 10, unknown
+            0x2a, 0x05, 0x0a, 0x01, b'a', 0x10, 0x01, // counts
+        ];
+        let mut person = person();
+        person.merge(&bytes).unwrap();
+
+        assert_eq!(person.get("name"), Some(&Value::String("Ada".to_string())));
+        assert_eq!(
+            person.get("scores"),
+            Some(&Value::List(vec![Value::I32(-1), Value::I32(1)]))
+        );
+        assert_eq!(person.get("pager"), Some(&Value::U64(5)));
+        assert_eq!(person.unknown_fields(), &[0x50, 0x2a]);
+
+        let mut decoded = self::person();
+        decoded.merge(&person.encode_to_vec()).unwrap();
+        assert_eq!(decoded, person);
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let message = Parser::new("message Node { Node child = 1; }")
+            .consume_message()
+            .unwrap();
+        let node = DynamicMessage::new(&message).unwrap();
+        // Each level wraps the one inside it as field 1.
+        let nested = |levels: usize| {
+            let mut buf = vec![];
+            for _ in 0..levels {
+                let mut outer = vec![0x0a];
+                encode_varint(buf.len() as u64, &mut outer);
+                outer.extend_from_slice(&buf);
+                buf = outer;
+            }
+            buf
+        };
+        node.clone().merge(&nested(RECURSION_LIMIT)).unwrap();
+        assert!(matches!(
+            node.clone().merge(&nested(RECURSION_LIMIT + 1)),
+            Err(Error::Decode(wire::Error::RecursionLimitExceeded))
+        ));
+        assert!(matches!(
+            node.clone().merge(&nested(100_000)),
+            Err(Error::Decode(wire::Error::RecursionLimitExceeded))
+        ));
+    }
+
+    #[test]
+    fn from_file_set() {
+        let mut resolver = resolver::Resolver::new();
+        resolver.include("tests/descriptor");
+        let set = Arc::new(resolver.load(&["person.proto"]).unwrap());
+        let mut person = DynamicMessage::from_file_set(set.clone(), "tutorial.Person").unwrap();
+
+        let mut thing = person.new_message("things.Thing").unwrap();
+        thing
+            .set("tags", Value::List(vec![Value::String("x".to_string())]))
+            .unwrap();
+        person.set("favourite", Value::Message(thing)).unwrap();
+        person
+            .set(
+                "lucky_numbers",
+                Value::List(vec![Value::I64(1), Value::I64(2)]),
+            )
+            .unwrap();
+
+        // `lucky_numbers` is declared `[packed = false]`.
+        assert_eq!(
+            person.encode_to_vec(),
+            vec![0x18, 0x01, 0x18, 0x02, 0x22, 0x03, 0x12, 0x01, b'x']
+        );
+        assert!(matches!(
+            DynamicMessage::from_file_set(set, "tutorial.Missing"),
+            Err(Error::UnknownType(_))
+        ));
+    }
+}
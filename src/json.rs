@@ -0,0 +1,1480 @@
+//! The canonical proto3 JSON mapping for dynamic messages, including the
+//! special representations of the well-known types.
+
+use crate::dynamic::{DynamicMessage, MapKey, Value};
+use crate::*;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input is not valid JSON.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An object member names no field of the message.
+    UnknownField { message: String, field: String },
+    /// A JSON value cannot be converted to or from the field it belongs to.
+    InvalidValue { field: String, message: String },
+    /// An `Any` names a type that is not in the schema.
+    UnknownType(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+            Error::UnknownField { message, field } => {
+                write!(f, "message \"{}\" has no field \"{}\"", message, field)
+            }
+            Error::InvalidValue { field, message } => write!(f, "{}: {}", field, message),
+            Error::UnknownType(url) => write!(f, "unknown type \"{}\"", url),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Print unset fields with their default values. Fields of message
+    /// type and members of oneofs are still omitted.
+    pub emit_defaults: bool,
+    /// Print the field names from the `.proto` file instead of their
+    /// lowerCamelCase JSON names. Parsing always accepts both.
+    pub preserve_field_names: bool,
+    /// Skip object members that name no field instead of failing.
+    pub ignore_unknown_fields: bool,
+}
+
+/// Prints a message as compact proto3 JSON.
+pub fn print(message: &DynamicMessage, options: &Options) -> Result<String, Error> {
+    let mut s = String::new();
+    write_json(&message_to_json(message, options)?, &mut s);
+    Ok(s)
+}
+
+/// Parses proto3 JSON into an existing message. Repeated fields and maps are
+/// appended to, other fields overwritten.
+pub fn merge(input: &str, message: &mut DynamicMessage, options: &Options) -> Result<(), Error> {
+    merge_message(message, &Json::parse(input)?, options)
+}
+
+/// A parsed JSON value. Numbers are kept as written so 64-bit integers
+/// survive without going through a float.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+
+    /// Parses a JSON document.
+    pub(crate) fn parse(input: &str) -> Result<Json, Error> {
+        Reader { input, pos: 0 }.read_document()
+    }
+
+    /// The value as JSON with two-space indentation, ending in a newline.
+    pub(crate) fn pretty(&self) -> String {
+        let mut s = String::new();
+        write_pretty(self, 0, &mut s);
+        s.push('\n');
+        s
+    }
+}
+
+fn write_pretty(json: &Json, depth: usize, s: &mut String) {
+    let indent = |s: &mut String, depth| s.push_str(&"  ".repeat(depth));
+    match json {
+        Json::Array(values) if !values.is_empty() => {
+            s.push_str("[\n");
+            for (i, value) in values.iter().enumerate() {
+                indent(s, depth + 1);
+                write_pretty(value, depth + 1, s);
+                s.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+            }
+            indent(s, depth);
+            s.push(']');
+        }
+        Json::Object(members) if !members.is_empty() => {
+            s.push_str("{\n");
+            for (i, (key, value)) in members.iter().enumerate() {
+                indent(s, depth + 1);
+                write_string(key, s);
+                s.push_str(": ");
+                write_pretty(value, depth + 1, s);
+                s.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+            }
+            indent(s, depth);
+            s.push('}');
+        }
+        json => write_json(json, s),
+    }
+}
+
+fn write_json(json: &Json, s: &mut String) {
+    match json {
+        Json::Null => s.push_str("null"),
+        Json::Bool(b) => s.push_str(if *b { "true" } else { "false" }),
+        Json::Number(n) => s.push_str(n),
+        Json::String(v) => write_string(v, s),
+        Json::Array(values) => {
+            s.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_json(value, s);
+            }
+            s.push(']');
+        }
+        Json::Object(members) => {
+            s.push('{');
+            for (i, (key, value)) in members.iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_string(key, s);
+                s.push(':');
+                write_json(value, s);
+            }
+            s.push('}');
+        }
+    }
+}
+
+fn write_string(v: &str, s: &mut String) {
+    s.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            '\u{8}' => s.push_str("\\b"),
+            '\u{c}' => s.push_str("\\f"),
+            c if (c as u32) < 0x20 => s.push_str(&format!("\\u{:04x}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: impl Into<String>) -> Error {
+        let before = &self.input[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        Error::Syntax {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn read_document(mut self) -> Result<Json, Error> {
+        let json = self.read_value(wire::RECURSION_LIMIT)?;
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(self.error("unexpected trailing characters"));
+        }
+        Ok(json)
+    }
+
+    /// Reads a value with objects and arrays allowed to nest `depth` deep.
+    fn read_value(&mut self, depth: usize) -> Result<Json, Error> {
+        self.skip_whitespace();
+        if matches!(self.peek(), Some(b'{' | b'[')) && depth == 0 {
+            return Err(self.error(format!(
+                "values are nested more than {} deep",
+                wire::RECURSION_LIMIT
+            )));
+        }
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected string"));
+                    }
+                    let key = self.read_string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.read_value(depth - 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.read_value(depth - 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'"') => self.read_string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.read_number(),
+            Some(_) => {
+                for (word, json) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.input[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(json);
+                    }
+                }
+                Err(self.error("expected value"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn read_number(&mut self) -> Result<Json, Error> {
+        let start = self.pos;
+        let digits = |reader: &mut Self| {
+            let from = reader.pos;
+            while matches!(reader.peek(), Some(b'0'..=b'9')) {
+                reader.pos += 1;
+            }
+            reader.pos > from
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("invalid number"));
+            }
+        } else if !digits(self) {
+            return Err(self.error("invalid number"));
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        Ok(Json::Number(self.input[start..self.pos].to_string()))
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let rest = &self.input[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let high = self.read_hex4()?;
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                if !self.input[self.pos..].starts_with("\\u") {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                self.pos += 2;
+                                let low = self.read_hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                high
+                            };
+                            s.push(
+                                char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?,
+                            );
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 1;
+                    s.push(escaped);
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => {
+                    self.pos += c.len_utf8();
+                    s.push(c);
+                }
+            }
+        }
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, Error> {
+        let hex = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid escape"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).expect("checked to be hex"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WellKnown {
+    Any,
+    Timestamp,
+    Duration,
+    FieldMask,
+    Struct,
+    Value,
+    ListValue,
+    Empty,
+    /// `DoubleValue`, `StringValue` and the other single-field wrappers.
+    Wrapper,
+}
+
+const NULL_VALUE: &str = ".google.protobuf.NullValue";
+
+fn well_known(type_name: &str) -> Option<WellKnown> {
+    Some(match type_name.strip_prefix(".google.protobuf.")? {
+        "Any" => WellKnown::Any,
+        "Timestamp" => WellKnown::Timestamp,
+        "Duration" => WellKnown::Duration,
+        "FieldMask" => WellKnown::FieldMask,
+        "Struct" => WellKnown::Struct,
+        "Value" => WellKnown::Value,
+        "ListValue" => WellKnown::ListValue,
+        "Empty" => WellKnown::Empty,
+        "DoubleValue" | "FloatValue" | "Int64Value" | "UInt64Value" | "Int32Value"
+        | "UInt32Value" | "BoolValue" | "StringValue" | "BytesValue" => WellKnown::Wrapper,
+        _ => return None,
+    })
+}
+
+fn invalid(field: &str, message: impl Into<String>) -> Error {
+    Error::InvalidValue {
+        field: field.to_string(),
+        message: message.into(),
+    }
+}
+
+fn short_name(message: &DynamicMessage) -> &str {
+    message.type_name().trim_start_matches('.')
+}
+
+fn message_to_json(message: &DynamicMessage, options: &Options) -> Result<Json, Error> {
+    let name = short_name(message);
+    match well_known(message.type_name()) {
+        Some(WellKnown::Any) => any_to_json(message, options),
+        Some(WellKnown::Timestamp) => {
+            let (seconds, nanos) = seconds_and_nanos(message);
+            format_timestamp(seconds, nanos)
+                .map(Json::String)
+                .ok_or_else(|| invalid(name, "timestamp out of range"))
+        }
+        Some(WellKnown::Duration) => {
+            let (seconds, nanos) = seconds_and_nanos(message);
+            format_duration(seconds, nanos)
+                .map(Json::String)
+                .ok_or_else(|| invalid(name, "duration out of range"))
+        }
+        Some(WellKnown::FieldMask) => {
+            let paths = match message.get_by_number(1) {
+                Some(Value::List(paths)) => paths
+                    .iter()
+                    .map(|path| match path {
+                        Value::String(path) => camel_path(path),
+                        _ => String::new(),
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            Ok(Json::String(paths.join(",")))
+        }
+        Some(WellKnown::Struct) => {
+            let mut members = vec![];
+            if let Some(Value::Map(entries)) = message.get_by_number(1) {
+                for (key, value) in entries {
+                    if let (MapKey::String(key), Value::Message(value)) = (key, value) {
+                        members.push((key.clone(), message_to_json(value, options)?));
+                    }
+                }
+            }
+            Ok(Json::Object(members))
+        }
+        Some(WellKnown::Value) => match message.fields().next() {
+            Some((_, Value::Enum(_))) => Ok(Json::Null),
+            Some((_, Value::F64(v))) => {
+                if v.is_finite() {
+                    Ok(float_json(*v, v.to_string()))
+                } else {
+                    Err(invalid(name, "number_value must be finite"))
+                }
+            }
+            Some((_, Value::String(s))) => Ok(Json::String(s.clone())),
+            Some((_, Value::Bool(b))) => Ok(Json::Bool(*b)),
+            Some((_, Value::Message(nested))) => message_to_json(nested, options),
+            _ => Err(invalid(name, "no value is set")),
+        },
+        Some(WellKnown::ListValue) => {
+            let mut values = vec![];
+            if let Some(Value::List(list)) = message.get_by_number(1) {
+                for value in list {
+                    if let Value::Message(value) = value {
+                        values.push(message_to_json(value, options)?);
+                    }
+                }
+            }
+            Ok(Json::Array(values))
+        }
+        Some(WellKnown::Wrapper) => {
+            let t = message
+                .field_by_number(1)
+                .map(|field| field.field_type())
+                .ok_or_else(|| invalid(name, "wrapper has no value field"))?;
+            let value = message
+                .get_by_number(1)
+                .cloned()
+                .unwrap_or_else(|| message.default_value(&t));
+            value_to_json(message, &t, &value, options)
+        }
+        Some(WellKnown::Empty) | None => object_to_json(message, options),
+    }
+}
+
+fn object_to_json(message: &DynamicMessage, options: &Options) -> Result<Json, Error> {
+    let mut members = vec![];
+    for (field, value) in message.fields() {
+        let key = match message.extension_name(field.position()) {
+            Some(name) => format!("[{}]", name.trim_start_matches('.')),
+            None if options.preserve_field_names => field.name().to_string(),
+            None => message.json_name(field),
+        };
+        members.push((
+            field.position(),
+            key,
+            field_to_json(message, field, value, options)?,
+        ));
+    }
+
+    if options.emit_defaults {
+        let descriptor = message.descriptor();
+        for field in &descriptor.fields {
+            let t = field.field_type();
+            if message.get_by_number(field.position()).is_some()
+                || (matches!(t, Type::Message(_)) && !field.is_repeated())
+            {
+                continue;
+            }
+            let value = match t {
+                Type::Map(..) => Value::Map(BTreeMap::new()),
+                _ if field.is_repeated() => Value::List(vec![]),
+                t => message.default_value(&t),
+            };
+            let key = if options.preserve_field_names {
+                field.name().to_string()
+            } else {
+                message.json_name(field)
+            };
+            members.push((
+                field.position(),
+                key,
+                field_to_json(message, field, &value, options)?,
+            ));
+        }
+        members.sort_by_key(|(number, _, _)| *number);
+    }
+
+    Ok(Json::Object(
+        members
+            .into_iter()
+            .map(|(_, key, json)| (key, json))
+            .collect(),
+    ))
+}
+
+fn field_to_json(
+    message: &DynamicMessage,
+    field: &MessageField,
+    value: &Value,
+    options: &Options,
+) -> Result<Json, Error> {
+    match (field.field_type(), value) {
+        (Type::Map(_, value_type), Value::Map(entries)) => {
+            let mut members = vec![];
+            for (key, value) in entries {
+                let key = match key {
+                    MapKey::Bool(b) => b.to_string(),
+                    MapKey::I32(i) => i.to_string(),
+                    MapKey::I64(i) => i.to_string(),
+                    MapKey::U32(u) => u.to_string(),
+                    MapKey::U64(u) => u.to_string(),
+                    MapKey::String(s) => s.clone(),
+                };
+                members.push((key, value_to_json(message, &value_type, value, options)?));
+            }
+            Ok(Json::Object(members))
+        }
+        (t, Value::List(values)) => values
+            .iter()
+            .map(|value| value_to_json(message, &t, value, options))
+            .collect::<Result<_, _>>()
+            .map(Json::Array),
+        (t, value) => value_to_json(message, &t, value, options),
+    }
+}
+
+fn value_to_json(
+    message: &DynamicMessage,
+    t: &Type,
+    value: &Value,
+    options: &Options,
+) -> Result<Json, Error> {
+    Ok(match value {
+        Value::Bool(b) => Json::Bool(*b),
+        Value::I32(v) => Json::Number(v.to_string()),
+        Value::U32(v) => Json::Number(v.to_string()),
+        Value::I64(v) => Json::String(v.to_string()),
+        Value::U64(v) => Json::String(v.to_string()),
+        Value::F32(v) => float_json(*v as f64, v.to_string()),
+        Value::F64(v) => float_json(*v, v.to_string()),
+        Value::String(s) => Json::String(s.clone()),
+        Value::Bytes(bytes) => Json::String(base64_encode(bytes)),
+        Value::Enum(number) => match t {
+            Type::Enum(name) if name == NULL_VALUE => Json::Null,
+            Type::Enum(name) => message
+                .file_set()
+                .find_enum(name)
+                .and_then(|e| e.fields.iter().find(|f| f.position as i32 == *number))
+                .map_or_else(
+                    || Json::Number(number.to_string()),
+                    |f| Json::String(f.name.clone()),
+                ),
+            _ => Json::Number(number.to_string()),
+        },
+        Value::Message(nested) => message_to_json(nested, options)?,
+        Value::List(_) | Value::Map(_) => unreachable!("written by field_to_json"),
+    })
+}
+
+/// Non-finite floats are strings in JSON. Very large and very small
+/// magnitudes use exponent notation rather than a long run of zeros.
+fn float_json(v: f64, text: String) -> Json {
+    if v.is_nan() {
+        Json::String("NaN".to_string())
+    } else if v.is_infinite() {
+        Json::String(if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string())
+    } else if v != 0.0 && (v.abs() >= 1e21 || v.abs() < 1e-6) {
+        Json::Number(format!("{:e}", text.parse::<f64>().unwrap_or(v)))
+    } else {
+        Json::Number(text)
+    }
+}
+
+fn any_to_json(message: &DynamicMessage, options: &Options) -> Result<Json, Error> {
+    let url = match message.get_by_number(1) {
+        Some(Value::String(url)) => url.clone(),
+        _ => return Ok(Json::Object(vec![])),
+    };
+    let bytes = match message.get_by_number(2) {
+        Some(Value::Bytes(bytes)) => bytes.as_slice(),
+        _ => &[],
+    };
+    let mut inner = message
+        .new_message(&url[url.rfind('/').map_or(0, |i| i + 1)..])
+        .map_err(|_| Error::UnknownType(url.clone()))?;
+    inner
+        .merge(bytes)
+        .map_err(|err| invalid(&url, err.to_string()))?;
+
+    let mut members = vec![("@type".to_string(), Json::String(url))];
+    match message_to_json(&inner, options)? {
+        Json::Object(fields) if well_known(inner.type_name()).is_none() => members.extend(fields),
+        json => members.push(("value".to_string(), json)),
+    }
+    Ok(Json::Object(members))
+}
+
+fn seconds_and_nanos(message: &DynamicMessage) -> (i64, i32) {
+    let seconds = match message.get_by_number(1) {
+        Some(Value::I64(seconds)) => *seconds,
+        _ => 0,
+    };
+    let nanos = match message.get_by_number(2) {
+        Some(Value::I32(nanos)) => *nanos,
+        _ => 0,
+    };
+    (seconds, nanos)
+}
+
+/// 0001-01-01T00:00:00Z and 9999-12-31T23:59:59Z, the range RFC 3339 allows.
+const MIN_TIMESTAMP: i64 = -62_135_596_800;
+const MAX_TIMESTAMP: i64 = 253_402_300_799;
+const MAX_DURATION: i64 = 315_576_000_000;
+
+/// Nanoseconds as 3, 6 or 9 fractional digits, or nothing when zero.
+fn format_nanos(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos.is_multiple_of(1_000_000) {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos.is_multiple_of(1_000) {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+fn format_timestamp(seconds: i64, nanos: i32) -> Option<String> {
+    if !(MIN_TIMESTAMP..=MAX_TIMESTAMP).contains(&seconds) || !(0..1_000_000_000).contains(&nanos) {
+        return None;
+    }
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        format_nanos(nanos as u32)
+    ))
+}
+
+fn format_duration(seconds: i64, nanos: i32) -> Option<String> {
+    if seconds.abs() > MAX_DURATION
+        || nanos.abs() >= 1_000_000_000
+        || (seconds < 0 && nanos > 0)
+        || (seconds > 0 && nanos < 0)
+    {
+        return None;
+    }
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    Some(format!(
+        "{}{}{}s",
+        sign,
+        seconds.unsigned_abs(),
+        format_nanos(nanos.unsigned_abs())
+    ))
+}
+
+/// Reads up to nine fractional digits after a `.` as nanoseconds.
+fn parse_nanos(fraction: &str) -> Option<i32> {
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    format!("{:0<9}", fraction).parse().ok()
+}
+
+fn parse_digits(s: &str) -> Option<i64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parses an RFC 3339 timestamp such as `1972-01-01T10:00:20.021Z` or
+/// `1972-01-01T12:00:20+02:00`.
+fn parse_timestamp(s: &str) -> Option<(i64, i32)> {
+    let field = |range: std::ops::Range<usize>| parse_digits(s.get(range)?);
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    if s.len() < 20
+        || separators
+            .iter()
+            .any(|(i, c)| !s.as_bytes()[*i].eq_ignore_ascii_case(c))
+    {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+
+    let mut rest = &s[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        nanos = parse_nanos(&fraction[..len])?;
+        rest = &fraction[len..];
+    }
+    let offset = match rest.as_bytes().first()? {
+        b'Z' | b'z' if rest.len() == 1 => 0,
+        sign @ (b'+' | b'-') if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let offset = parse_digits(&rest[1..3])? * 3600 + parse_digits(&rest[4..6])? * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days_in_month = match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day < 1 || day > days_in_month || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    (MIN_TIMESTAMP..=MAX_TIMESTAMP)
+        .contains(&seconds)
+        .then_some((seconds, nanos))
+}
+
+/// Parses a duration such as `1.5s` or `-0.000001s`.
+fn parse_duration(s: &str) -> Option<(i64, i32)> {
+    let s = s.strip_suffix('s')?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (seconds, nanos) = match s.split_once('.') {
+        Some((seconds, fraction)) => (parse_digits(seconds)?, parse_nanos(fraction)?),
+        None => (parse_digits(s)?, 0),
+    };
+    if seconds > MAX_DURATION {
+        return None;
+    }
+    Some(if negative {
+        (-seconds, -nanos)
+    } else {
+        (seconds, nanos)
+    })
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// `foo_bar.baz_qux` becomes `fooBar.bazQux`.
+fn camel_path(path: &str) -> String {
+    let mut ret = String::new();
+    let mut capitalize_next = false;
+    for c in path.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            ret.push(c.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+fn snake_path(path: &str) -> String {
+    let mut ret = String::new();
+    for c in path.chars() {
+        if c.is_ascii_uppercase() {
+            ret.push('_');
+            ret.push(c.to_ascii_lowercase());
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Accepts the standard and URL-safe alphabets, with or without padding.
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.trim_end_matches('=').bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    (bits < 6).then_some(bytes)
+}
+
+fn merge_message(
+    message: &mut DynamicMessage,
+    json: &Json,
+    options: &Options,
+) -> Result<(), Error> {
+    let name = short_name(message).to_string();
+    let expect_string = |json: &Json| match json {
+        Json::String(s) => Ok(s.clone()),
+        json => Err(invalid(
+            &name,
+            format!("expected a string, found {}", json.kind()),
+        )),
+    };
+    let set = |message: &mut DynamicMessage, number: u32, value: Value| {
+        message
+            .set_by_number(number, value)
+            .map_err(|err| invalid(&name, err.to_string()))
+    };
+
+    match well_known(message.type_name()) {
+        Some(WellKnown::Any) => merge_any(message, json, options),
+        Some(kind @ (WellKnown::Timestamp | WellKnown::Duration)) => {
+            let s = expect_string(json)?;
+            let parsed = if kind == WellKnown::Timestamp {
+                parse_timestamp(&s)
+            } else {
+                parse_duration(&s)
+            };
+            let (seconds, nanos) =
+                parsed.ok_or_else(|| invalid(&name, format!("invalid value \"{}\"", s)))?;
+            if seconds != 0 {
+                set(message, 1, Value::I64(seconds))?;
+            }
+            if nanos != 0 {
+                set(message, 2, Value::I32(nanos))?;
+            }
+            Ok(())
+        }
+        Some(WellKnown::FieldMask) => {
+            let s = expect_string(json)?;
+            for path in s.split(',').filter(|path| !path.is_empty()) {
+                message
+                    .add_by_number(1, Value::String(snake_path(path)))
+                    .map_err(|err| invalid(&name, err.to_string()))?;
+            }
+            Ok(())
+        }
+        Some(WellKnown::Struct) => {
+            let Json::Object(members) = json else {
+                return Err(invalid(
+                    &name,
+                    format!("expected an object, found {}", json.kind()),
+                ));
+            };
+            for (key, json) in members {
+                let value = new_value(message, json, options)?;
+                message
+                    .insert_by_number(1, MapKey::String(key.clone()), value)
+                    .map_err(|err| invalid(&name, err.to_string()))?;
+            }
+            Ok(())
+        }
+        Some(WellKnown::Value) => match json {
+            Json::Null => set(message, 1, Value::Enum(0)),
+            Json::Number(n) => set(
+                message,
+                2,
+                Value::F64(n.parse().map_err(|_| invalid(&name, "invalid number"))?),
+            ),
+            Json::String(s) => set(message, 3, Value::String(s.clone())),
+            Json::Bool(b) => set(message, 4, Value::Bool(*b)),
+            Json::Object(_) | Json::Array(_) => {
+                let (number, type_name) = if matches!(json, Json::Object(_)) {
+                    (5, ".google.protobuf.Struct")
+                } else {
+                    (6, ".google.protobuf.ListValue")
+                };
+                let mut nested = message
+                    .new_message(type_name)
+                    .map_err(|_| Error::UnknownType(type_name[1..].to_string()))?;
+                merge_message(&mut nested, json, options)?;
+                set(message, number, Value::Message(nested))
+            }
+        },
+        Some(WellKnown::ListValue) => {
+            let Json::Array(values) = json else {
+                return Err(invalid(
+                    &name,
+                    format!("expected an array, found {}", json.kind()),
+                ));
+            };
+            for json in values {
+                let value = new_value(message, json, options)?;
+                message
+                    .add_by_number(1, value)
+                    .map_err(|err| invalid(&name, err.to_string()))?;
+            }
+            Ok(())
+        }
+        Some(WellKnown::Wrapper) => {
+            let t = message
+                .field_by_number(1)
+                .map(|field| field.field_type())
+                .ok_or_else(|| invalid(&name, "wrapper has no value field"))?;
+            let value = json_to_value(message, &t, json, options, &name)?;
+            set(message, 1, value)
+        }
+        Some(WellKnown::Empty) | None => merge_object(message, json, options),
+    }
+}
+
+/// A `google.protobuf.Value` holding `json`.
+fn new_value(message: &DynamicMessage, json: &Json, options: &Options) -> Result<Value, Error> {
+    let mut value = message
+        .new_message(".google.protobuf.Value")
+        .map_err(|_| Error::UnknownType("google.protobuf.Value".to_string()))?;
+    merge_message(&mut value, json, options)?;
+    Ok(Value::Message(value))
+}
+
+fn merge_any(message: &mut DynamicMessage, json: &Json, options: &Options) -> Result<(), Error> {
+    let Json::Object(members) = json else {
+        return Err(invalid(
+            "google.protobuf.Any",
+            format!("expected an object, found {}", json.kind()),
+        ));
+    };
+    if members.is_empty() {
+        return Ok(());
+    }
+    let url = match members.iter().find(|(key, _)| key == "@type") {
+        Some((_, Json::String(url))) => url.clone(),
+        _ => return Err(invalid("google.protobuf.Any", "missing \"@type\"")),
+    };
+    let mut inner = message
+        .new_message(&url[url.rfind('/').map_or(0, |i| i + 1)..])
+        .map_err(|_| Error::UnknownType(url.clone()))?;
+    let rest: Vec<_> = members
+        .iter()
+        .filter(|(key, _)| key != "@type")
+        .cloned()
+        .collect();
+    if well_known(inner.type_name()).is_some() {
+        match rest.iter().find(|(key, _)| key == "value") {
+            Some((_, value)) => merge_message(&mut inner, value, options)?,
+            None => return Err(invalid(&url, "missing \"value\"")),
+        }
+    } else {
+        merge_message(&mut inner, &Json::Object(rest), options)?;
+    }
+    message
+        .set_by_number(1, Value::String(url.clone()))
+        .and_then(|_| message.set_by_number(2, Value::Bytes(inner.encode_to_vec())))
+        .map_err(|err| invalid(&url, err.to_string()))
+}
+
+fn merge_object(message: &mut DynamicMessage, json: &Json, options: &Options) -> Result<(), Error> {
+    let Json::Object(members) = json else {
+        return Err(invalid(
+            short_name(message),
+            format!("expected an object, found {}", json.kind()),
+        ));
+    };
+    for (key, json) in members {
+        let field = match key.strip_prefix('[').and_then(|key| key.strip_suffix(']')) {
+            Some(name) => message
+                .field_by_name(name)
+                .filter(|f| message.extension_name(f.position()).is_some()),
+            None => message
+                .field_descriptors()
+                .find(|f| f.name() == key || message.json_name(f) == *key),
+        };
+        let Some(field) = field.cloned() else {
+            if options.ignore_unknown_fields {
+                continue;
+            }
+            return Err(Error::UnknownField {
+                message: short_name(message).to_string(),
+                field: key.clone(),
+            });
+        };
+        let t = field.field_type();
+        let accepts_null = matches!(&t, Type::Enum(name) if name == NULL_VALUE)
+            || matches!(&t, Type::Message(name) if name == ".google.protobuf.Value");
+        if *json == Json::Null && (!accepts_null || field.is_repeated()) {
+            continue;
+        }
+        let name = field.name();
+        let number = field.position();
+        let error = |err: dynamic::Error| invalid(name, err.to_string());
+        match (&t, json) {
+            (Type::Map(key_type, value_type), Json::Object(entries)) => {
+                for (key, json) in entries {
+                    let key = map_key(key_type, key)
+                        .ok_or_else(|| invalid(name, format!("invalid map key \"{}\"", key)))?;
+                    let value = json_to_value(message, value_type, json, options, name)?;
+                    message
+                        .insert_by_number(number, key, value)
+                        .map_err(error)?;
+                }
+            }
+            (_, Json::Array(values)) if field.is_repeated() && !matches!(t, Type::Map(..)) => {
+                for json in values {
+                    let value = json_to_value(message, &t, json, options, name)?;
+                    message.add_by_number(number, value).map_err(error)?;
+                }
+            }
+            _ if field.is_repeated() => {
+                let expected = if matches!(t, Type::Map(..)) {
+                    "an object"
+                } else {
+                    "an array"
+                };
+                return Err(invalid(
+                    name,
+                    format!("expected {}, found {}", expected, json.kind()),
+                ));
+            }
+            _ => {
+                let value = json_to_value(message, &t, json, options, name)?;
+                message.set_by_number(number, value).map_err(error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn map_key(t: &Type, key: &str) -> Option<MapKey> {
+    Some(match t {
+        Type::Bool => match key {
+            "true" => MapKey::Bool(true),
+            "false" => MapKey::Bool(false),
+            _ => return None,
+        },
+        Type::String => MapKey::String(key.to_string()),
+        Type::Int32 | Type::Sint32 | Type::Sfixed32 => MapKey::I32(key.parse().ok()?),
+        Type::Int64 | Type::Sint64 | Type::Sfixed64 => MapKey::I64(key.parse().ok()?),
+        Type::Uint32 | Type::Fixed32 => MapKey::U32(key.parse().ok()?),
+        Type::Uint64 | Type::Fixed64 => MapKey::U64(key.parse().ok()?),
+        _ => return None,
+    })
+}
+
+/// Reads an integer written as a JSON number or string. Numbers in
+/// exponent or decimal form are accepted when they are whole.
+fn parse_integer(text: &str) -> Option<i128> {
+    if let Ok(v) = text.parse::<i128>() {
+        return Some(v);
+    }
+    let v: f64 = text.parse().ok()?;
+    (v.fract() == 0.0 && v.abs() < 2f64.powi(64)).then_some(v as i128)
+}
+
+fn json_to_value(
+    message: &DynamicMessage,
+    t: &Type,
+    json: &Json,
+    options: &Options,
+    field: &str,
+) -> Result<Value, Error> {
+    let mismatch = || invalid(field, format!("unexpected {}", json.kind()));
+    match (t, json) {
+        (Type::Message(name), json) => {
+            let mut nested = message
+                .new_message(name)
+                .map_err(|err| invalid(field, err.to_string()))?;
+            merge_message(&mut nested, json, options)?;
+            Ok(Value::Message(nested))
+        }
+        (Type::Enum(name), Json::Null) if name == NULL_VALUE => Ok(Value::Enum(0)),
+        (Type::Enum(name), Json::String(value)) => message
+            .file_set()
+            .find_enum(name)
+            .and_then(|e| e.fields.iter().find(|f| f.name == *value))
+            .map(|f| Value::Enum(f.position as i32))
+            .ok_or_else(|| invalid(field, format!("unknown enum value \"{}\"", value))),
+        (Type::Enum(_), Json::Number(n)) => parse_integer(n)
+            .and_then(|v| i32::try_from(v).ok())
+            .map(Value::Enum)
+            .ok_or_else(|| invalid(field, format!("invalid enum value {}", n))),
+        (Type::Bool, Json::Bool(b)) => Ok(Value::Bool(*b)),
+        (Type::String, Json::String(s)) => Ok(Value::String(s.clone())),
+        (Type::Bytes, Json::String(s)) => base64_decode(s)
+            .map(Value::Bytes)
+            .ok_or_else(|| invalid(field, "invalid base64")),
+        (Type::Float | Type::Double, Json::Number(text) | Json::String(text)) => {
+            let v = match text.as_str() {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                text => text
+                    .parse()
+                    .map_err(|_| invalid(field, format!("invalid number \"{}\"", text)))?,
+            };
+            if *t == Type::Double {
+                Ok(Value::F64(v))
+            } else if v.is_finite() && v.abs() > f32::MAX as f64 {
+                Err(invalid(field, "float out of range"))
+            } else {
+                Ok(Value::F32(v as f32))
+            }
+        }
+        (t, Json::Number(text) | Json::String(text)) => {
+            let v = parse_integer(text)
+                .ok_or_else(|| invalid(field, format!("invalid integer \"{}\"", text)))?;
+            let value = match t {
+                Type::Int32 | Type::Sint32 | Type::Sfixed32 => {
+                    i32::try_from(v).ok().map(Value::I32)
+                }
+                Type::Int64 | Type::Sint64 | Type::Sfixed64 => {
+                    i64::try_from(v).ok().map(Value::I64)
+                }
+                Type::Uint32 | Type::Fixed32 => u32::try_from(v).ok().map(Value::U32),
+                Type::Uint64 | Type::Fixed64 => u64::try_from(v).ok().map(Value::U64),
+                _ => return Err(mismatch()),
+            };
+            value.ok_or_else(|| invalid(field, format!("integer {} out of range", text)))
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::FileSet;
+    use std::sync::Arc;
+
+    const WELL_KNOWN: &str = "
+        syntax = \"proto3\";
+        package google.protobuf;
+        message Any { string type_url = 1; bytes value = 2; }
+        message Timestamp { int64 seconds = 1; int32 nanos = 2; }
+        message Duration { int64 seconds = 1; int32 nanos = 2; }
+        message FieldMask { repeated string paths = 1; }
+        message Struct { map<string, Value> fields = 1; }
+        message Value {
+            oneof kind {
+                NullValue null_value = 1;
+                double number_value = 2;
+                string string_value = 3;
+                bool bool_value = 4;
+                Struct struct_value = 5;
+                ListValue list_value = 6;
+            }
+        }
+        enum NullValue { NULL_VALUE = 0; }
+        message ListValue { repeated Value values = 1; }
+        message Int64Value { int64 value = 1; }
+        message StringValue { string value = 1; }";
+
+    fn message(type_name: &str) -> DynamicMessage {
+        let mut well_known = Parser::new(WELL_KNOWN).consume_file().unwrap();
+        well_known.name = "google/protobuf/well_known.proto".to_string();
+        let mut file = Parser::new(
+            "syntax = \"proto3\";
+            package test;
+            import \"google/protobuf/well_known.proto\";
+            message Person {
+                string user_name = 1;
+                int64 id = 2;
+                bytes avatar = 3;
+                Kind kind = 4;
+                repeated double scores = 5;
+                map<int32, string> labels = 6;
+                oneof contact { string email = 7; Person friend = 8; }
+                uint32 custom = 9 [json_name = \"renamed\"];
+                enum Kind { HUMAN = 0; ROBOT = 1; }
+            }
+            message Known {
+                google.protobuf.Timestamp created = 1;
+                google.protobuf.Duration ttl = 2;
+                google.protobuf.FieldMask mask = 3;
+                google.protobuf.Struct extra = 4;
+                google.protobuf.Int64Value count = 5;
+                google.protobuf.Any detail = 6;
+                google.protobuf.Any wrapped = 7;
+                google.protobuf.Value nothing = 8;
+            }",
+        )
+        .consume_file()
+        .unwrap();
+        file.name = "test.proto".to_string();
+        let set = Arc::new(FileSet::new(vec![well_known, file]).unwrap());
+        DynamicMessage::from_file_set(set, type_name).unwrap()
+    }
+
+    fn round_trip(type_name: &str, json: &str, options: &Options) -> DynamicMessage {
+        let mut parsed = message(type_name);
+        merge(json, &mut parsed, options).unwrap();
+        assert_eq!(print(&parsed, options).unwrap(), json);
+        parsed
+    }
+
+    #[test]
+    fn scalars_and_names() {
+        let json = "{\"userName\":\"Ada \\\"L\\\"\",\"id\":\"9007199254740993\",\
+                    \"avatar\":\"AAH/\",\"kind\":\"ROBOT\",\"scores\":[1.5,\"NaN\",1e300],\
+                    \"labels\":{\"-1\":\"neg\",\"2\":\"two\"},\"friend\":{\"id\":\"1\"},\
+                    \"renamed\":7}";
+        let person = round_trip("test.Person", json, &Options::default());
+        assert_eq!(person.get("id"), Some(&Value::I64(9_007_199_254_740_993)));
+        assert_eq!(person.get("avatar"), Some(&Value::Bytes(vec![0, 1, 255])));
+        assert_eq!(person.get("custom"), Some(&Value::U32(7)));
+
+        let mut wire = message("test.Person");
+        wire.merge(&person.encode_to_vec()).unwrap();
+        // Compared as bytes, since NaN is not equal to itself.
+        assert_eq!(wire.encode_to_vec(), person.encode_to_vec());
+
+        let options = Options {
+            preserve_field_names: true,
+            emit_defaults: true,
+            ..Options::default()
+        };
+        round_trip(
+            "test.Person",
+            "{\"user_name\":\"\",\"id\":\"0\",\"avatar\":\"\",\"kind\":\"HUMAN\",\
+             \"scores\":[],\"labels\":{},\"custom\":0}",
+            &options,
+        );
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let mut person = message("test.Person");
+        merge(
+            "{ \"user_name\": \"x\", \"id\": 12, \"avatar\": \"AAH_\", \"kind\": 1,
+               \"scores\": [\"-Infinity\", 2], \"custom\": \"3\", \"email\": null }",
+            &mut person,
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            print(&person, &Options::default()).unwrap(),
+            "{\"userName\":\"x\",\"id\":\"12\",\"avatar\":\"AAH/\",\"kind\":\"ROBOT\",\
+             \"scores\":[\"-Infinity\",2],\"renamed\":3}"
+        );
+    }
+
+    #[test]
+    fn well_known_types() {
+        let json = "{\"created\":\"1972-01-01T10:00:20.021Z\",\"ttl\":\"-1.000340012s\",\
+                    \"mask\":\"userName,friend.id\",\
+                    \"extra\":{\"a\":[1,\"two\",true,null,{}]},\"count\":\"5\",\
+                    \"detail\":{\"@type\":\"type.googleapis.com/test.Person\",\"userName\":\"x\"},\
+                    \"wrapped\":{\"@type\":\"type.googleapis.com/google.protobuf.Duration\",\"value\":\"3s\"},\
+                    \"nothing\":null}";
+        let known = round_trip("test.Known", json, &Options::default());
+        let Some(Value::Message(created)) = known.get("created") else {
+            panic!("created is not set");
+        };
+        assert_eq!(created.get("seconds"), Some(&Value::I64(63_108_020)));
+        assert_eq!(created.get("nanos"), Some(&Value::I32(21_000_000)));
+        let Some(Value::Message(mask)) = known.get("mask") else {
+            panic!("mask is not set");
+        };
+        assert_eq!(
+            mask.get("paths"),
+            Some(&Value::List(vec![
+                Value::String("user_name".to_string()),
+                Value::String("friend.id".to_string())
+            ]))
+        );
+
+        let mut offset = message("test.Known");
+        merge(
+            "{\"created\":\"1972-01-01T12:00:20.021+02:00\"}",
+            &mut offset,
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(offset.get("created"), known.get("created"));
+    }
+
+    #[test]
+    fn errors() {
+        let options = Options::default();
+        let cases = [
+            (
+                "{\"nope\": 1}",
+                Error::UnknownField {
+                    message: "test.Person".to_string(),
+                    field: "nope".to_string(),
+                },
+            ),
+            ("{\"id\": 1.5}", invalid("id", "invalid integer \"1.5\"")),
+            (
+                "{\"kind\": \"ALIEN\"}",
+                invalid("kind", "unknown enum value \"ALIEN\""),
+            ),
+            (
+                "{\"id\": 1,}",
+                Error::Syntax {
+                    line: 1,
+                    column: 10,
+                    message: "expected string".to_string(),
+                },
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                merge(input, &mut message("test.Person"), &options),
+                Err(expected)
+            );
+        }
+
+        let lenient = Options {
+            ignore_unknown_fields: true,
+            ..Options::default()
+        };
+        assert!(merge(
+            "{\"nope\": {\"deep\": [1]}}",
+            &mut message("test.Person"),
+            &lenient
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let options = Options::default();
+        let friends = |levels: usize| "{\"friend\":".repeat(levels) + "{}" + &"}".repeat(levels);
+        merge(
+            &friends(wire::RECURSION_LIMIT - 1),
+            &mut message("test.Person"),
+            &options,
+        )
+        .unwrap();
+        for input in [friends(100_000), "[".repeat(100_000)] {
+            let Err(Error::Syntax { message, .. }) =
+                merge(&input, &mut message("test.Person"), &options)
+            else {
+                panic!("deep nesting was accepted");
+            };
+            assert_eq!(message, "values are nested more than 100 deep");
+        }
+    }
+}
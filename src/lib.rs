@@ -1,19 +1,63 @@
-mod codegen;
+use std::collections::BTreeMap;
+
+// Lets code generated by `#[derive(ProtoSchema)]`, which names the crate
+// `::protos`, compile inside it too.
+extern crate self as protos;
+
+pub mod avro;
+pub mod build;
+pub mod codegen;
+pub mod descriptor;
+pub mod dynamic;
+pub mod json;
+pub mod plugin;
+pub mod protofmt;
+pub mod raw;
+pub mod resolver;
+pub mod schema;
+pub mod text_format;
+pub mod thrift;
+pub mod well_known;
+pub mod wire;
 
 #[derive(Debug)]
 pub struct Parser {
     body: Vec<char>,
     curr_index: usize,
+    last_end: usize,
+    line_starts: Vec<usize>,
+    path: Vec<PathSegment>,
+    open: Vec<usize>,
+    locations: Vec<Location>,
+    comments: Vec<Comment>,
+    dangling: Vec<(Vec<PathSegment>, Vec<String>)>,
 }
 
 fn str_to_vec(s: &str) -> Vec<char> {
     s.chars().collect()
 }
 
-const TYPES: &[&str] = &["string", "int32"];
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+const TYPES: &[&str] = &[
+    "string", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32", "fixed64",
+    "sfixed32", "sfixed64", "bool", "float", "double", "bytes",
+];
 
 const FREQUENCIES: &[&str] = &["optional", "repeated", "required"];
 
+const STATEMENTS: &[&str] = &[
+    "message",
+    "enum",
+    "oneof",
+    "option",
+    "reserved",
+    "extensions",
+    "extend",
+];
+
 pub enum CompoundTypeMarker {
     Message,
     Oneof,
@@ -24,13 +68,71 @@ pub enum CompoundType {
     Oneof(Oneof),
 }
 
+/// A comment skipped by the parser, kept until it can be attached to a
+/// declaration as a leading or trailing comment.
+#[derive(Debug)]
+struct Comment {
+    start: usize,
+    end: usize,
+    block: bool,
+}
+
+/// Why source could not be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// The 1-based line and column the parser stopped at.
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl Parser {
     pub fn new(input: &str) -> Self {
+        let body = str_to_vec(input);
+        let mut line_starts = vec![0];
+        for (i, c) in body.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
         Parser {
-            body: str_to_vec(input),
+            body,
             curr_index: 0,
+            last_end: 0,
+            line_starts,
+            path: vec![],
+            open: vec![],
+            locations: vec![],
+            comments: vec![],
+            dangling: vec![],
         }
     }
+
+    /// The source locations recorded while parsing, in source order.
+    pub fn locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    /// Comment blocks that belong to no declaration because they come right
+    /// before a closing brace or the end of the file, keyed by the path of
+    /// the enclosing declaration. `protoc` drops these; formatters keep
+    /// them.
+    pub fn dangling_comments(&self) -> &[(Vec<PathSegment>, Vec<String>)] {
+        &self.dangling
+    }
+
     fn peek_curr(&self) -> Option<char> {
         if self.is_in_bounds() {
             Some(self.body[self.curr_index])
@@ -51,29 +153,65 @@ impl Parser {
         self.curr_index + 1 < self.body.len()
     }
 
-    fn consume(&mut self, s: &str) -> String {
-        let veced_str = str_to_vec(s);
-        let veced_str_len = veced_str.len();
-        if self.body[self.curr_index..self.curr_index + veced_str_len] == veced_str {
-            self.curr_index += veced_str_len;
-            return s.to_string();
+    fn token_consumed(&mut self) {
+        self.last_end = self.curr_index;
+        self.comments.clear();
+    }
+
+    fn consume(&mut self, s: &str) -> Result<String, ParseError> {
+        if self.matches(s) {
+            self.curr_index += s.chars().count();
+            self.token_consumed();
+            return Ok(s.to_string());
+        }
+        Err(self.error(format!("Could not consume {:?}", s)))
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let (line, column) = self.line_col(self.curr_index.min(self.body.len()));
+        ParseError {
+            message: message.into(),
+            line: line as usize + 1,
+            column: column as usize + 1,
         }
-        panic!("Could not consume");
     }
 
     fn consume_name(&mut self) -> String {
         self.skip_whitespace_or_comment();
         let mut ret = String::new();
-        while self.curr_char().is_ascii_alphabetic() || self.curr_char() == '_' {
+        while self.is_in_bounds()
+            && (self.curr_char().is_ascii_alphabetic()
+                || self.curr_char() == '_'
+                || (!ret.is_empty() && self.curr_char().is_ascii_digit()))
+        {
             ret.push(self.body[self.curr_index]);
             self.curr_index += 1;
         }
+        self.token_consumed();
+        ret
+    }
+
+    /// Consumes a possibly dotted and possibly fully qualified name such as
+    /// `.google.protobuf.Timestamp`.
+    fn consume_full_name(&mut self) -> String {
+        self.skip_whitespace_or_comment();
+        let mut ret = String::new();
+        if self.skip('.') {
+            ret.push('.');
+        }
+        ret.push_str(&self.consume_name());
+        while self.peek_curr() == Some('.') {
+            self.skip('.');
+            ret.push('.');
+            ret.push_str(&self.consume_name());
+        }
         ret
     }
 
     fn skip(&mut self, c: char) -> bool {
-        if self.body[self.curr_index] == c {
+        if self.peek_curr() == Some(c) {
             self.curr_index += 1;
+            self.token_consumed();
             return true;
         }
         false
@@ -98,7 +236,22 @@ impl Parser {
 
     fn skip_comment(&mut self) -> bool {
         if self.is_comment() {
-            self.skip_until('\n');
+            let start = self.curr_index;
+            let block = self.matches("/*");
+            if block {
+                self.curr_index += 2;
+                while self.is_in_bounds() && !self.matches("*/") {
+                    self.curr_index += 1;
+                }
+                self.curr_index = (self.curr_index + 2).min(self.body.len());
+            } else {
+                self.skip_until('\n');
+            }
+            self.comments.push(Comment {
+                start,
+                end: self.curr_index,
+                block,
+            });
             return true;
         }
         false
@@ -106,7 +259,7 @@ impl Parser {
 
     fn is_comment(&self) -> bool {
         if let (Some(curr), Some(next)) = (self.peek_curr(), self.peek_next()) {
-            curr == '/' && next == '/'
+            curr == '/' && (next == '/' || next == '*')
         } else {
             false
         }
@@ -133,39 +286,247 @@ impl Parser {
         self.body[self.curr_index..self.curr_index + veced_str_len] == veced_str
     }
 
-    fn consume_number(&mut self) -> u32 {
-        let mut res = 0;
-        while self.curr_char().is_ascii_digit() {
-            res *= 10;
-            res += self.curr_char().to_digit(10).unwrap();
-            self.curr_index += 1;
+    /// Like `matches`, but only if `s` is not immediately followed by more of
+    /// an identifier, so `int32` does not match the start of `int32_value`.
+    fn matches_word(&self, s: &str) -> bool {
+        if !self.matches(s) {
+            return false;
+        }
+        match self.body.get(self.curr_index + s.chars().count()) {
+            Some(c) => !is_ident_char(*c),
+            None => true,
+        }
+    }
+
+    /// Consumes a decimal, `0x` hexadecimal or `0` octal integer.
+    fn consume_number(&mut self) -> Result<u32, ParseError> {
+        let start = self.curr_index;
+        if self.is_in_bounds() && self.curr_char().is_ascii_digit() {
+            while self.is_in_bounds() && self.curr_char().is_ascii_alphanumeric() {
+                self.curr_index += 1;
+            }
+        }
+        self.token_consumed();
+        let text: String = self.body[start..self.curr_index].iter().collect();
+        let parsed = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+            u32::from_str_radix(hex, 16)
+        } else if text.len() > 1 && text.starts_with('0') {
+            u32::from_str_radix(&text[1..], 8)
+        } else {
+            text.parse()
+        };
+        match parsed {
+            Ok(number) => Ok(number),
+            Err(_) => Err(self.error("Could not consume number")),
         }
-        res
     }
 
     fn curr_char(&self) -> char {
         self.body[self.curr_index]
     }
 
-    fn consume_message_field(&mut self) -> MessageField {
+    fn line_col(&self, index: usize) -> (i32, i32) {
+        let line = match self.line_starts.binary_search(&index) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let mut column = 0;
+        for c in &self.body[self.line_starts[line]..index] {
+            if *c == '\t' {
+                column += 8 - column % 8;
+            } else {
+                column += 1;
+            }
+        }
+        (line as i32, column)
+    }
+
+    fn comment_text(&self, comment: &Comment) -> String {
+        if comment.block {
+            let end = comment.end.max(comment.start + 4) - 2;
+            self.body[comment.start + 2..end].iter().collect()
+        } else {
+            let mut text: String = self.body[comment.start + 2..comment.end].iter().collect();
+            text.push('\n');
+            text
+        }
+    }
+
+    /// Groups the pending comments into blocks of consecutive line comments,
+    /// returning each block's text and its first and last line.
+    fn comment_blocks(&self) -> Vec<(String, i32, i32)> {
+        let mut blocks: Vec<(String, i32, i32)> = vec![];
+        let mut previous_was_line = false;
+        for comment in &self.comments {
+            let (start_line, _) = self.line_col(comment.start);
+            let (end_line, _) = self.line_col(comment.end);
+            let text = self.comment_text(comment);
+            match blocks.last_mut() {
+                Some(block) if previous_was_line && !comment.block && block.2 + 1 == start_line => {
+                    block.0.push_str(&text);
+                    block.2 = end_line;
+                }
+                _ => blocks.push((text, start_line, end_line)),
+            }
+            previous_was_line = !comment.block;
+        }
+        blocks
+    }
+
+    fn start_location(&mut self, segment: PathSegment) {
         self.skip_whitespace_or_comment();
-        let frequency = self.consume_frequency();
+        let (line, column) = self.line_col(self.curr_index);
+
+        let mut blocks = self.comment_blocks();
+        self.comments.clear();
+        let leading_comments = match blocks.last() {
+            Some((_, _, end_line)) if line - end_line <= 1 => blocks.pop().map(|block| block.0),
+            _ => None,
+        };
+
+        self.path.push(segment);
+        self.open.push(self.locations.len());
+        self.locations.push(Location {
+            path: self.path.clone(),
+            span: Span {
+                start_line: line,
+                start_column: column,
+                end_line: line,
+                end_column: column,
+            },
+            leading_comments,
+            trailing_comments: None,
+            leading_detached_comments: blocks.into_iter().map(|block| block.0).collect(),
+        });
+    }
+
+    fn end_location(&mut self) {
+        let index = self.open.pop().expect("no open location");
+        self.path.pop();
+        let (end_line, end_column) = self.line_col(self.last_end);
+        self.locations[index].span.end_line = end_line;
+        self.locations[index].span.end_column = end_column;
+        self.attach_trailing_comment(index);
+    }
+
+    fn take_dangling_comments(&mut self) {
+        let blocks = self.comment_blocks();
+        self.comments.clear();
+        if !blocks.is_empty() {
+            let texts = blocks.into_iter().map(|block| block.0).collect();
+            self.dangling.push((self.path.clone(), texts));
+        }
+    }
+
+    /// Attaches the comment starting on the line the declaration ends on.
+    /// As in `protoc`, comments on the lines after it are left to lead the
+    /// next declaration.
+    fn attach_trailing_comment(&mut self, index: usize) {
         self.skip_whitespace_or_comment();
-        let t = self.consume_type();
+        let (line, _) = self.line_col(self.last_end);
+        let Some(first) = self.comments.first() else {
+            return;
+        };
+        if self.line_col(first.start).0 == line && self.locations[index].trailing_comments.is_none()
+        {
+            self.locations[index].trailing_comments = Some(self.comment_text(first));
+            self.comments.remove(0);
+        }
+    }
+
+    /// Records the location of a single token or token run, from `start` up
+    /// to the end of the last consumed token.
+    fn record(&mut self, segment: PathSegment, start: usize) {
+        self.record_span(segment, start, self.last_end);
+    }
+
+    fn record_span(&mut self, segment: PathSegment, start: usize, end: usize) {
+        let mut path = self.path.clone();
+        path.push(segment);
+        let (start_line, start_column) = self.line_col(start);
+        let (end_line, end_column) = self.line_col(end);
+        self.locations.push(Location {
+            path,
+            span: Span {
+                start_line,
+                start_column,
+                end_line,
+                end_column,
+            },
+            leading_comments: None,
+            trailing_comments: None,
+            leading_detached_comments: vec![],
+        });
+    }
+
+    fn consume_message_field(&mut self) -> Result<(MessageField, Vec<ProtoOption>), ParseError> {
         self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let frequency = self.consume_frequency()?;
+        if frequency.is_some() {
+            self.record(PathSegment::Label, start);
+        }
+        self.skip_whitespace_or_comment();
+        if self.matches_word("group") {
+            return Err(self.error("Groups are not supported; use a nested message"));
+        }
+        let start = self.curr_index;
+        let t = if self.is_map_type() {
+            self.consume_map_type()?
+        } else {
+            self.consume_type()?
+        };
+        self.record(PathSegment::Type, start);
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
         let name = self.consume_name();
+        self.record(PathSegment::Name, start);
         self.skip_whitespace_or_comment();
-        self.skip('=');
+        self.consume("=")?;
         self.skip_whitespace_or_comment();
-        let position = self.consume_number();
+        let start = self.curr_index;
+        let position = self.consume_number()?;
+        self.record(PathSegment::Number, start);
+        self.skip_whitespace_or_comment();
+        let options = if self.peek_curr() == Some('[') {
+            self.consume_field_options()?
+        } else {
+            vec![]
+        };
         self.skip_whitespace_or_comment();
         self.skip(';');
         self.skip_whitespace_or_comment();
 
-        match t {
-            Type::String => MessageField::String(frequency, name, position),
-            Type::Int32 => MessageField::Int32(frequency, name, position),
+        Ok((MessageField::new(frequency, t, name, position), options))
+    }
+
+    fn consume_field_options(&mut self) -> Result<Vec<ProtoOption>, ParseError> {
+        self.start_location(PathSegment::Options);
+        self.skip('[');
+        let mut options = vec![];
+        loop {
+            self.skip_whitespace_or_comment();
+            self.start_location(PathSegment::Option(options.len()));
+            let name = self.consume_option_name();
+            self.skip_whitespace_or_comment();
+            self.skip('=');
+            self.skip_whitespace_or_comment();
+            let start = self.curr_index;
+            let value = self.consume_constant()?;
+            self.record(PathSegment::Value, start);
+            options.push(ProtoOption { name, value });
+            self.end_location();
+            self.skip_whitespace_or_comment();
+            if !self.skip(',') {
+                break;
+            }
+        }
+        self.skip_whitespace_or_comment();
+        if !self.skip(']') {
+            return Err(self.error("Could not consume field options"));
         }
+        self.end_location();
+        Ok(options)
     }
 
     fn skip_whitespace_or_comment(&mut self) {
@@ -175,55 +536,115 @@ impl Parser {
         }
     }
 
-    fn consume_any(&mut self, choices: &[&str]) -> Option<String> {
+    fn consume_any(&mut self, choices: &[&str]) -> Result<Option<String>, ParseError> {
         for choice in choices {
-            if self.matches(choice) {
-                return Some(self.consume(choice));
+            if self.matches_word(choice) {
+                return Ok(Some(self.consume(choice)?));
             }
         }
 
-        None
+        Ok(None)
     }
 
-    fn consume_type(&mut self) -> Type {
-        if let Some(t) = self.consume_any(TYPES) {
-            t.into()
+    fn consume_type(&mut self) -> Result<Type, ParseError> {
+        if let Some(t) = self.consume_any(TYPES)? {
+            Ok(t.into())
         } else {
-            panic!("Could not consume type")
+            let name = self.consume_full_name();
+            if name.is_empty() || name.ends_with('.') {
+                return Err(self.error("Could not consume type"));
+            }
+            Ok(Type::Named(name))
         }
     }
 
-    fn consume_frequency(&mut self) -> Option<Frequency> {
-        self.consume_any(FREQUENCIES).map(|freq| freq.into())
+    fn is_map_type(&self) -> bool {
+        if !self.matches_word("map") {
+            return false;
+        }
+        self.body[self.curr_index + 3..]
+            .iter()
+            .find(|c| !c.is_ascii_whitespace())
+            == Some(&'<')
+    }
+
+    fn consume_map_type(&mut self) -> Result<Type, ParseError> {
+        self.consume("map")?;
+        self.skip_whitespace_or_comment();
+        self.skip('<');
+        let key = self.consume_type()?;
+        self.skip_whitespace_or_comment();
+        self.skip(',');
+        self.skip_whitespace_or_comment();
+        let value = self.consume_type()?;
+        self.skip_whitespace_or_comment();
+        self.skip('>');
+        Ok(Type::Map(Box::new(key), Box::new(value)))
+    }
+
+    fn consume_frequency(&mut self) -> Result<Option<Frequency>, ParseError> {
+        Ok(self.consume_any(FREQUENCIES)?.map(|freq| freq.into()))
     }
 
     pub fn is_finished(&self) -> bool {
         self.curr_index == self.body.len()
     }
 
-    fn consume_enum(&mut self) -> Enum {
+    fn consume_enum(&mut self) -> Result<Enum, ParseError> {
         self.skip_whitespace_or_comment();
-        self.consume("enum");
+        self.consume("enum")?;
         self.skip_whitespace_or_comment();
+        let start = self.curr_index;
         let name = self.consume_name();
+        self.record(PathSegment::Name, start);
         self.skip_whitespace_or_comment();
         self.skip('{');
-        self.skip_whitespace_or_comment();
-        let fields = self.consume_enum_fields();
-        self.skip_whitespace_or_comment();
+        if !self.open.is_empty() {
+            self.attach_trailing_comment(*self.open.last().unwrap());
+        }
+        let mut ret = Enum {
+            name,
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace_or_comment();
+            if !self.is_in_bounds() || self.curr_char() == '}' {
+                break;
+            }
+            if self.is_option() {
+                ret.order.push(Declaration::Option(ret.options.len()));
+                self.start_location(PathSegment::Option(ret.options.len()));
+                ret.options.push(self.consume_option_statement()?);
+                self.end_location();
+            } else if self.matches_word("reserved") {
+                let declaration =
+                    self.consume_reserved(&mut ret.reserved_ranges, &mut ret.reserved_names)?;
+                ret.order.push(declaration);
+            } else if !self.skip(';') {
+                ret.order.push(Declaration::Field(ret.fields.len()));
+                self.start_location(PathSegment::EnumValue(ret.fields.len()));
+                let (field, options) = self.consume_enum_field()?;
+                if !options.is_empty() {
+                    ret.value_options.insert(field.name.clone(), options);
+                }
+                ret.fields.push(field);
+                self.end_location();
+            }
+        }
+        self.take_dangling_comments();
         self.skip('}');
         self.skip_whitespace_or_comment();
-
-        Enum { name, fields }
+        ret.normalize_order();
+        Ok(ret)
     }
 
     fn is_enum(&self) -> bool {
-        self.matches("enum")
+        self.matches_word("enum")
     }
 
     fn matches_any(&self, choices: &[&str]) -> bool {
         for choice in choices {
-            if self.matches(choice) {
+            if self.matches_word(choice) {
                 return true;
             }
         }
@@ -232,114 +653,793 @@ impl Parser {
     }
 
     fn is_message_field(&self) -> bool {
-        self.matches_any(&[TYPES, FREQUENCIES].concat())
-    }
-
-    fn consume_enum_fields(&mut self) -> Vec<EnumField> {
-        self.skip_whitespace_or_comment();
-        let mut fields = vec![];
-        while self.curr_char() != '}' {
-            self.skip_whitespace_or_comment();
-            fields.push(self.consume_enum_field());
-            self.skip_whitespace_or_comment();
+        match self.peek_curr() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+                !self.matches_any(STATEMENTS)
+            }
+            _ => false,
         }
-        self.skip_whitespace_or_comment();
-        fields
     }
 
-    fn consume_enum_field(&mut self) -> EnumField {
+    fn consume_enum_field(&mut self) -> Result<(EnumField, Vec<ProtoOption>), ParseError> {
         self.skip_whitespace_or_comment();
+        let start = self.curr_index;
         let name = self.consume_name();
+        if name.is_empty() {
+            return Err(self.error("Could not consume enum field"));
+        }
+        self.record(PathSegment::Name, start);
         self.skip_whitespace_or_comment();
-        self.skip('=');
+        self.consume("=")?;
         self.skip_whitespace_or_comment();
-        let position = self.consume_number();
+        let start = self.curr_index;
+        let position = self.consume_number()?;
+        self.record(PathSegment::Number, start);
         self.skip_whitespace_or_comment();
-        self.skip(';');
+        let options = if self.peek_curr() == Some('[') {
+            self.consume_field_options()?
+        } else {
+            vec![]
+        };
         self.skip_whitespace_or_comment();
+        self.skip(';');
 
-        EnumField { name, position }
+        Ok((EnumField { name, position }, options))
     }
 
     fn is_message(&self) -> bool {
-        self.matches("message")
+        self.matches_word("message")
     }
 
     fn is_oneof(&self) -> bool {
-        self.matches("oneof")
+        self.matches_word("oneof")
+    }
+
+    fn is_option(&self) -> bool {
+        self.matches_word("option")
     }
 
-    fn consume_oneof(&mut self) -> Oneof {
-        let oneof = self.consume_compound_type(CompoundTypeMarker::Oneof);
+    fn consume_oneof(&mut self) -> Result<Oneof, ParseError> {
+        let oneof = self.consume_compound_type(CompoundTypeMarker::Oneof)?;
         match oneof {
-            CompoundType::Oneof(oneof) => oneof,
+            CompoundType::Oneof(oneof) => Ok(oneof),
             _ => unreachable!(),
         }
     }
 
-    pub fn consume_message(&mut self) -> Message {
-        let message = self.consume_compound_type(CompoundTypeMarker::Message);
+    pub fn consume_message(&mut self) -> Result<Message, ParseError> {
+        let message = self.consume_compound_type(CompoundTypeMarker::Message)?;
 
         match message {
-            CompoundType::Message(message) => message,
+            CompoundType::Message(message) => Ok(message),
             _ => unreachable!(),
         }
     }
 
-    fn consume_compound_type(&mut self, marker: CompoundTypeMarker) -> CompoundType {
+    fn consume_compound_type(
+        &mut self,
+        marker: CompoundTypeMarker,
+    ) -> Result<CompoundType, ParseError> {
         self.skip_whitespace_or_comment();
         match marker {
-            CompoundTypeMarker::Oneof => self.consume("oneof"),
-            CompoundTypeMarker::Message => self.consume("message"),
+            CompoundTypeMarker::Oneof => self.consume("oneof")?,
+            CompoundTypeMarker::Message => self.consume("message")?,
         };
         self.skip_whitespace_or_comment();
+        let start = self.curr_index;
         let name = self.consume_name();
+        self.record(PathSegment::Name, start);
         self.skip_whitespace_or_comment();
         self.skip('{');
+        if !self.open.is_empty() {
+            self.attach_trailing_comment(*self.open.last().unwrap());
+        }
         self.skip_whitespace_or_comment();
 
         let mut enums = vec![];
         let mut fields = vec![];
         let mut messages = vec![];
         let mut oneofs = vec![];
-
-        while self.is_message_field() || self.is_enum() || self.is_message() || self.is_oneof() {
+        let mut options = vec![];
+        let mut field_options = BTreeMap::new();
+        let mut extends = vec![];
+        let mut extension_ranges = vec![];
+        let mut extension_range_options = BTreeMap::new();
+        let mut reserved_ranges = vec![];
+        let mut reserved_names = vec![];
+        let mut order = vec![];
+
+        loop {
             self.skip_whitespace_or_comment();
 
-            if self.is_message_field() {
-                fields.push(self.consume_message_field());
-            } else if self.is_enum() {
-                enums.push(self.consume_enum());
+            if self.is_enum() {
+                order.push(Declaration::Enum(enums.len()));
+                self.start_location(PathSegment::Enum(enums.len()));
+                enums.push(self.consume_enum()?);
+                self.end_location();
             } else if self.is_message() {
-                messages.push(self.consume_message());
+                order.push(Declaration::Message(messages.len()));
+                self.start_location(PathSegment::Message(messages.len()));
+                messages.push(self.consume_message()?);
+                self.end_location();
             } else if self.is_oneof() {
-                oneofs.push(self.consume_oneof());
-            } else {
-                unreachable!();
+                order.push(Declaration::Oneof(oneofs.len()));
+                self.start_location(PathSegment::Oneof(oneofs.len()));
+                oneofs.push(self.consume_oneof()?);
+                self.end_location();
+            } else if self.is_option() {
+                order.push(Declaration::Option(options.len()));
+                self.start_location(PathSegment::Option(options.len()));
+                options.push(self.consume_option_statement()?);
+                self.end_location();
+            } else if self.matches_word("extend") {
+                order.push(Declaration::Extend(extends.len()));
+                self.start_location(PathSegment::Extend(extends.len()));
+                extends.push(self.consume_extend()?);
+                self.end_location();
+            } else if self.matches_word("extensions") {
+                let first = extension_ranges.len();
+                self.start_location(PathSegment::Extensions);
+                self.consume_extensions(&mut extension_ranges, &mut extension_range_options)?;
+                self.end_location();
+                order.push(Declaration::Extensions {
+                    first,
+                    count: extension_ranges.len() - first,
+                });
+            } else if self.matches_word("reserved") {
+                order.push(self.consume_reserved(&mut reserved_ranges, &mut reserved_names)?);
+            } else if self.is_message_field() {
+                order.push(Declaration::Field(fields.len()));
+                self.start_location(PathSegment::Field(fields.len()));
+                let (field, opts) = self.consume_message_field()?;
+                if !opts.is_empty() {
+                    field_options.insert(field.position(), opts);
+                }
+                fields.push(field);
+                self.end_location();
+            } else if !self.skip(';') {
+                break;
             }
             self.skip_whitespace_or_comment();
         }
 
         self.skip_whitespace_or_comment();
-        self.skip('}');
+        self.take_dangling_comments();
+        if !self.skip('}') {
+            return Err(self.error("Could not consume }"));
+        }
         self.skip_whitespace_or_comment();
 
-        match marker {
-            CompoundTypeMarker::Message => CompoundType::Message(Message {
-                name,
-                messages,
-                enums,
-                fields,
-                oneofs,
-            }),
+        Ok(match marker {
+            CompoundTypeMarker::Message => {
+                let mut message = Message {
+                    name,
+                    messages,
+                    enums,
+                    fields,
+                    oneofs,
+                    options,
+                    field_options,
+                    extends,
+                    extension_ranges,
+                    extension_range_options,
+                    reserved_ranges,
+                    reserved_names,
+                    order,
+                };
+                message.normalize_order();
+                CompoundType::Message(message)
+            }
             CompoundTypeMarker::Oneof => CompoundType::Oneof(Oneof {
                 name,
                 messages,
                 enums,
                 fields,
                 oneofs,
+                options,
+                field_options,
             }),
+        })
+    }
+
+    /// Consumes a whole `.proto` file: syntax, package, imports, options and
+    /// top level messages, enums and services.
+    pub fn consume_file(&mut self) -> Result<ProtoFile, ParseError> {
+        let mut file = ProtoFile::default();
+
+        self.skip_whitespace_or_comment();
+        let (line, column) = self.line_col(self.curr_index);
+        self.locations.push(Location {
+            path: vec![],
+            span: Span {
+                start_line: line,
+                start_column: column,
+                end_line: line,
+                end_column: column,
+            },
+            leading_comments: None,
+            trailing_comments: None,
+            leading_detached_comments: vec![],
+        });
+
+        loop {
+            self.skip_whitespace_or_comment();
+            if !self.is_in_bounds() {
+                self.take_dangling_comments();
+                break;
+            }
+
+            if self.matches_word("syntax") {
+                file.order.push(Declaration::Syntax);
+                self.start_location(PathSegment::Syntax);
+                self.consume("syntax")?;
+                self.skip_whitespace_or_comment();
+                self.skip('=');
+                self.skip_whitespace_or_comment();
+                file.syntax = Some(self.consume_string()?);
+                self.skip_whitespace_or_comment();
+                self.skip(';');
+                self.end_location();
+            } else if self.matches_word("package") {
+                file.order.push(Declaration::Package);
+                self.start_location(PathSegment::Package);
+                self.consume("package")?;
+                file.package = Some(self.consume_full_name());
+                self.skip_whitespace_or_comment();
+                self.skip(';');
+                self.end_location();
+            } else if self.matches_word("import") {
+                file.order.push(Declaration::Import(file.imports.len()));
+                self.start_location(PathSegment::Import(file.imports.len()));
+                file.imports.push(self.consume_import()?);
+                self.end_location();
+            } else if self.is_option() {
+                file.order.push(Declaration::Option(file.options.len()));
+                self.start_location(PathSegment::Option(file.options.len()));
+                file.options.push(self.consume_option_statement()?);
+                self.end_location();
+            } else if self.is_message() {
+                file.order.push(Declaration::Message(file.messages.len()));
+                self.start_location(PathSegment::Message(file.messages.len()));
+                file.messages.push(self.consume_message()?);
+                self.end_location();
+            } else if self.is_enum() {
+                file.order.push(Declaration::Enum(file.enums.len()));
+                self.start_location(PathSegment::Enum(file.enums.len()));
+                file.enums.push(self.consume_enum()?);
+                self.end_location();
+            } else if self.matches_word("service") {
+                file.order.push(Declaration::Service(file.services.len()));
+                self.start_location(PathSegment::Service(file.services.len()));
+                file.services.push(self.consume_service()?);
+                self.end_location();
+            } else if self.matches_word("extend") {
+                file.order.push(Declaration::Extend(file.extends.len()));
+                self.start_location(PathSegment::Extend(file.extends.len()));
+                file.extends.push(self.consume_extend()?);
+                self.end_location();
+            } else if !self.skip(';') {
+                return Err(self.error("Unexpected input"));
+            }
+        }
+
+        let (end_line, end_column) = self.line_col(self.last_end);
+        self.locations[0].span.end_line = end_line;
+        self.locations[0].span.end_column = end_column;
+
+        file.normalize_order();
+        Ok(file)
+    }
+
+    /// The largest field number, which `max` stands for in ranges.
+    const MAX_FIELD_NUMBER: u32 = 536_870_911;
+
+    fn consume_extensions(
+        &mut self,
+        ranges: &mut Vec<(u32, u32)>,
+        range_options: &mut BTreeMap<u32, Vec<ProtoOption>>,
+    ) -> Result<(), ParseError> {
+        self.consume("extensions")?;
+        let first = ranges.len();
+        self.consume_ranges(ranges, PathSegment::ExtensionRange)?;
+        if self.peek_curr() == Some('[') {
+            let options = self.consume_field_options()?;
+            for (start, _) in &ranges[first..] {
+                range_options.insert(*start, options.clone());
+            }
+        }
+        self.skip_whitespace_or_comment();
+        self.skip(';');
+        Ok(())
+    }
+
+    /// Consumes a `reserved` statement of either field numbers or names.
+    /// Consumes a `reserved` statement, returning it as a declaration.
+    fn consume_reserved(
+        &mut self,
+        ranges: &mut Vec<(u32, u32)>,
+        names: &mut Vec<String>,
+    ) -> Result<Declaration, ParseError> {
+        self.start_location(PathSegment::Reserved);
+        self.consume("reserved")?;
+        self.skip_whitespace_or_comment();
+        let declaration = if matches!(self.peek_curr(), Some('"') | Some('\'')) {
+            self.path.pop();
+            self.path.push(PathSegment::ReservedNames);
+            let index = *self.open.last().unwrap();
+            self.locations[index].path = self.path.clone();
+            let first = names.len();
+            loop {
+                self.skip_whitespace_or_comment();
+                let start = self.curr_index;
+                names.push(self.consume_string()?);
+                self.record(PathSegment::ReservedName(names.len() - 1), start);
+                self.skip_whitespace_or_comment();
+                if !self.skip(',') {
+                    break;
+                }
+            }
+            Declaration::ReservedNames {
+                first,
+                count: names.len() - first,
+            }
+        } else {
+            let first = ranges.len();
+            self.consume_ranges(ranges, PathSegment::ReservedRange)?;
+            Declaration::Reserved {
+                first,
+                count: ranges.len() - first,
+            }
+        };
+        self.skip_whitespace_or_comment();
+        self.skip(';');
+        self.end_location();
+        Ok(declaration)
+    }
+
+    /// Consumes a comma separated list of `N`, `N to M` and `N to max`,
+    /// recording each as an inclusive range.
+    fn consume_ranges(
+        &mut self,
+        ranges: &mut Vec<(u32, u32)>,
+        segment: impl Fn(usize) -> PathSegment,
+    ) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace_or_comment();
+            self.start_location(segment(ranges.len()));
+            let start_index = self.curr_index;
+            let start = self.consume_number()?;
+            let start_end = self.last_end;
+            self.record(PathSegment::Start, start_index);
+            self.skip_whitespace_or_comment();
+            let end = if self.matches_word("to") {
+                self.consume("to")?;
+                self.skip_whitespace_or_comment();
+                let end_index = self.curr_index;
+                let end = if self.matches_word("max") {
+                    self.consume("max")?;
+                    Parser::MAX_FIELD_NUMBER
+                } else {
+                    self.consume_number()?
+                };
+                self.record(PathSegment::End, end_index);
+                end
+            } else {
+                self.record_span(PathSegment::End, start_index, start_end);
+                start
+            };
+            ranges.push((start, end));
+            self.end_location();
+            self.skip_whitespace_or_comment();
+            if !self.skip(',') {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn consume_extend(&mut self) -> Result<Extend, ParseError> {
+        self.consume("extend")?;
+        self.skip_whitespace_or_comment();
+        let extendee_start = self.curr_index;
+        let extendee = self.consume_full_name();
+        let extendee_end = self.last_end;
+        self.skip_whitespace_or_comment();
+        self.skip('{');
+        self.attach_trailing_comment(*self.open.last().unwrap());
+
+        let mut fields = vec![];
+        let mut field_options = BTreeMap::new();
+        loop {
+            self.skip_whitespace_or_comment();
+            if self.is_message_field() {
+                self.start_location(PathSegment::Field(fields.len()));
+                self.record_span(PathSegment::Extendee, extendee_start, extendee_end);
+                let (field, opts) = self.consume_message_field()?;
+                if !opts.is_empty() {
+                    field_options.insert(field.position(), opts);
+                }
+                fields.push(field);
+                self.end_location();
+            } else if !self.skip(';') {
+                break;
+            }
+        }
+
+        self.take_dangling_comments();
+        if !self.skip('}') {
+            return Err(self.error("Could not consume }"));
+        }
+        Ok(Extend {
+            extendee,
+            fields,
+            field_options,
+        })
+    }
+
+    fn consume_import(&mut self) -> Result<Import, ParseError> {
+        self.consume("import")?;
+        self.skip_whitespace_or_comment();
+        let public = self.matches_word("public");
+        let weak = self.matches_word("weak");
+        if public || weak {
+            self.consume_name();
+            self.skip_whitespace_or_comment();
+        }
+        let path = self.consume_string()?;
+        self.skip_whitespace_or_comment();
+        self.skip(';');
+        Ok(Import { path, public, weak })
+    }
+
+    fn consume_service(&mut self) -> Result<Service, ParseError> {
+        self.consume("service")?;
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let name = self.consume_name();
+        self.record(PathSegment::Name, start);
+        self.skip_whitespace_or_comment();
+        self.skip('{');
+        self.attach_trailing_comment(*self.open.last().unwrap());
+
+        let mut methods = vec![];
+        let mut options = vec![];
+        loop {
+            self.skip_whitespace_or_comment();
+            if self.is_option() {
+                self.start_location(PathSegment::Option(options.len()));
+                options.push(self.consume_option_statement()?);
+                self.end_location();
+            } else if self.matches_word("rpc") {
+                self.start_location(PathSegment::Method(methods.len()));
+                methods.push(self.consume_method()?);
+                self.end_location();
+            } else if !self.skip(';') {
+                break;
+            }
+        }
+
+        self.skip_whitespace_or_comment();
+        self.take_dangling_comments();
+        if !self.skip('}') {
+            return Err(self.error("Could not consume }"));
+        }
+
+        Ok(Service {
+            name,
+            methods,
+            options,
+        })
+    }
+
+    fn consume_method(&mut self) -> Result<Method, ParseError> {
+        self.consume("rpc")?;
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let name = self.consume_name();
+        self.record(PathSegment::Name, start);
+        let (client_streaming, input_type) = self.consume_method_type(true)?;
+        self.skip_whitespace_or_comment();
+        self.consume("returns")?;
+        let (server_streaming, output_type) = self.consume_method_type(false)?;
+        self.skip_whitespace_or_comment();
+
+        let mut options = vec![];
+        if self.skip('{') {
+            loop {
+                self.skip_whitespace_or_comment();
+                if self.is_option() {
+                    self.start_location(PathSegment::Option(options.len()));
+                    options.push(self.consume_option_statement()?);
+                    self.end_location();
+                } else if !self.skip(';') {
+                    break;
+                }
+            }
+            self.take_dangling_comments();
+            if !self.skip('}') {
+                return Err(self.error("Could not consume }"));
+            }
+        } else {
+            self.skip(';');
+        }
+
+        Ok(Method {
+            name,
+            input_type,
+            output_type,
+            client_streaming,
+            server_streaming,
+            options,
+        })
+    }
+
+    fn consume_method_type(&mut self, input: bool) -> Result<(bool, String), ParseError> {
+        self.skip_whitespace_or_comment();
+        self.skip('(');
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let streaming = self.matches_word("stream");
+        if streaming {
+            self.consume("stream")?;
+            let segment = if input {
+                PathSegment::ClientStreaming
+            } else {
+                PathSegment::ServerStreaming
+            };
+            self.record(segment, start);
+        }
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let name = self.consume_full_name();
+        let segment = if input {
+            PathSegment::InputType
+        } else {
+            PathSegment::OutputType
+        };
+        self.record(segment, start);
+        self.skip_whitespace_or_comment();
+        self.skip(')');
+        Ok((streaming, name))
+    }
+
+    fn consume_option_statement(&mut self) -> Result<ProtoOption, ParseError> {
+        self.consume("option")?;
+        let name = self.consume_option_name();
+        self.skip_whitespace_or_comment();
+        self.skip('=');
+        let value = self.consume_constant()?;
+        self.skip_whitespace_or_comment();
+        self.skip(';');
+        Ok(ProtoOption { name, value })
+    }
+
+    /// Consumes an option name such as `java_package`, `(my.option)` or
+    /// `(google.api.http).get`.
+    fn consume_option_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            self.skip_whitespace_or_comment();
+            if self.skip('(') {
+                name.push('(');
+                name.push_str(&self.consume_full_name());
+                self.skip_whitespace_or_comment();
+                self.skip(')');
+                name.push(')');
+            } else {
+                name.push_str(&self.consume_name());
+            }
+            if self.peek_curr() == Some('.') {
+                self.skip('.');
+                name.push('.');
+            } else {
+                break;
+            }
+        }
+        name
+    }
+
+    fn consume_constant(&mut self) -> Result<Constant, ParseError> {
+        self.skip_whitespace_or_comment();
+        match self.peek_curr() {
+            Some('"') | Some('\'') => {
+                let mut bytes = self.consume_string_bytes()?;
+                self.skip_whitespace_or_comment();
+                while matches!(self.peek_curr(), Some('"') | Some('\'')) {
+                    bytes.extend(self.consume_string_bytes()?);
+                    self.skip_whitespace_or_comment();
+                }
+                Ok(match String::from_utf8(bytes) {
+                    Ok(s) => Constant::String(s),
+                    Err(err) => Constant::Bytes(err.into_bytes()),
+                })
+            }
+            Some('{') | Some('<') => Ok(Constant::Aggregate(self.consume_aggregate()?)),
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() || c == '.' => {
+                Ok(self.consume_numeric_constant())
+            }
+            _ => {
+                let ident = self.consume_full_name();
+                if ident.is_empty() {
+                    return Err(self.error("Could not consume constant"));
+                }
+                Ok(match ident.as_str() {
+                    "true" => Constant::Bool(true),
+                    "false" => Constant::Bool(false),
+                    _ => Constant::Ident(ident),
+                })
+            }
+        }
+    }
+
+    fn consume_numeric_constant(&mut self) -> Constant {
+        let mut sign = String::new();
+        if self.peek_curr() == Some('-') || self.peek_curr() == Some('+') {
+            if self.curr_char() == '-' {
+                sign.push('-');
+            }
+            self.curr_index += 1;
+            self.skip_whitespace_or_comment();
+        }
+        if self.peek_curr().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return Constant::Ident(format!("{}{}", sign, self.consume_name()));
+        }
+
+        let mut text = String::new();
+        while let Some(c) = self.peek_curr() {
+            let exponent_sign = (c == '-' || c == '+')
+                && !text.starts_with("0x")
+                && (text.ends_with('e') || text.ends_with('E'));
+            if is_ident_char(c) || c == '.' || exponent_sign {
+                text.push(c);
+                self.curr_index += 1;
+            } else {
+                break;
+            }
+        }
+        self.token_consumed();
+
+        let lower = text.to_ascii_lowercase();
+        let int = if let Some(hex) = lower.strip_prefix("0x") {
+            i128::from_str_radix(hex, 16).ok()
+        } else if lower.contains(['.', 'e', 'f']) {
+            None
+        } else if lower.len() > 1 && lower.starts_with('0') {
+            i128::from_str_radix(&lower[1..], 8).ok()
+        } else {
+            lower.parse().ok()
+        };
+
+        match int {
+            Some(int) if sign.is_empty() => Constant::Int(int),
+            Some(int) => Constant::Int(-int),
+            None => Constant::Float(format!("{}{}", sign, text)),
+        }
+    }
+
+    /// Consumes a text format message literal, as used by aggregate option
+    /// values like `option (google.api.http) = { get: "/v1/{name}" };`.
+    fn consume_aggregate(&mut self) -> Result<Vec<(String, Constant)>, ParseError> {
+        let close = if self.skip('<') { '>' } else { '}' };
+        if close == '}' {
+            self.skip('{');
+        }
+        let mut fields = vec![];
+        loop {
+            self.skip_whitespace_or_comment();
+            if self.skip(close) {
+                break;
+            }
+            if !self.is_in_bounds() {
+                return Err(self.error("Unterminated aggregate value"));
+            }
+            let name = if self.skip('[') {
+                let name = format!("[{}]", self.consume_full_name());
+                self.skip_whitespace_or_comment();
+                self.skip(']');
+                name
+            } else {
+                self.consume_name()
+            };
+            if name.is_empty() {
+                return Err(self.error("Could not consume aggregate field"));
+            }
+            self.skip_whitespace_or_comment();
+            self.skip(':');
+            self.skip_whitespace_or_comment();
+            if self.skip('[') {
+                loop {
+                    self.skip_whitespace_or_comment();
+                    if self.skip(']') {
+                        break;
+                    }
+                    fields.push((name.clone(), self.consume_constant()?));
+                    self.skip_whitespace_or_comment();
+                    self.skip(',');
+                }
+            } else {
+                fields.push((name, self.consume_constant()?));
+            }
+            self.skip_whitespace_or_comment();
+            if !self.skip(',') {
+                self.skip(';');
+            }
+        }
+        Ok(fields)
+    }
+
+    fn consume_string(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace_or_comment();
+        let start = self.curr_index;
+        let bytes = self.consume_string_bytes()?;
+        String::from_utf8(bytes).map_err(|_| {
+            self.curr_index = start;
+            self.error("String literal is not valid UTF-8")
+        })
+    }
+
+    /// Reads a quoted literal. Escapes spell bytes, so the result is only
+    /// UTF-8 when the literal is.
+    fn consume_string_bytes(&mut self) -> Result<Vec<u8>, ParseError> {
+        self.skip_whitespace_or_comment();
+        let quote = match self.peek_curr() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return Err(self.error("Could not consume string")),
+        };
+        self.curr_index += 1;
+        let mut ret = vec![];
+        let push = |ret: &mut Vec<u8>, c: char| {
+            ret.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+        };
+        while let Some(c) = self.peek_curr() {
+            self.curr_index += 1;
+            if c == quote {
+                self.token_consumed();
+                return Ok(ret);
+            }
+            if c != '\\' {
+                push(&mut ret, c);
+                continue;
+            }
+            let escaped = self.peek_curr().unwrap_or('\\');
+            self.curr_index += 1;
+            match escaped {
+                'n' => ret.push(b'\n'),
+                'r' => ret.push(b'\r'),
+                't' => ret.push(b'\t'),
+                'a' => ret.push(0x07),
+                'b' => ret.push(0x08),
+                'f' => ret.push(0x0c),
+                'v' => ret.push(0x0b),
+                'x' | 'X' => ret.push(self.consume_radix_digits(16, 2) as u8),
+                'u' | 'U' => {
+                    let len = if escaped == 'u' { 4 } else { 8 };
+                    if let Some(c) = char::from_u32(self.consume_radix_digits(16, len)) {
+                        push(&mut ret, c);
+                    }
+                }
+                '0'..='7' => {
+                    self.curr_index -= 1;
+                    ret.push(self.consume_radix_digits(8, 3) as u8);
+                }
+                other => push(&mut ret, other),
+            }
         }
+        Err(self.error("Unterminated string literal"))
+    }
+
+    fn consume_radix_digits(&mut self, radix: u32, max: usize) -> u32 {
+        let mut value = 0;
+        for _ in 0..max {
+            match self.peek_curr().and_then(|c| c.to_digit(radix)) {
+                Some(digit) => {
+                    value = value * radix + digit;
+                    self.curr_index += 1;
+                }
+                None => break,
+            }
+        }
+        value
     }
 }
 
@@ -350,34 +1450,417 @@ pub enum Frequency {
     Required,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub struct ProtoFile {
+    /// The path the file was loaded from, relative to its include directory.
+    pub name: String,
+    pub syntax: Option<String>,
+    pub package: Option<String>,
+    pub imports: Vec<Import>,
+    pub options: Vec<ProtoOption>,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub services: Vec<Service>,
+    pub extends: Vec<Extend>,
+    /// The order the file was written in, when it is not the canonical
+    /// one. See [`ProtoFile::declarations`].
+    pub order: Vec<Declaration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub struct Import {
+    pub path: String,
+    pub public: bool,
+    pub weak: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
 pub struct Message {
-    name: String,
-    messages: Vec<Message>,
-    enums: Vec<Enum>,
-    fields: Vec<MessageField>,
-    oneofs: Vec<Oneof>,
+    pub name: String,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub fields: Vec<MessageField>,
+    pub oneofs: Vec<Oneof>,
+    pub options: Vec<ProtoOption>,
+    /// Options given in brackets after a field, keyed by field position.
+    pub field_options: BTreeMap<u32, Vec<ProtoOption>>,
+    pub extends: Vec<Extend>,
+    /// Field numbers reserved for extensions, as inclusive ranges.
+    pub extension_ranges: Vec<(u32, u32)>,
+    /// Options given in brackets after an `extensions` statement, keyed by
+    /// the first number of each range they apply to.
+    pub extension_range_options: BTreeMap<u32, Vec<ProtoOption>>,
+    /// Field numbers that may not be used, as inclusive ranges.
+    pub reserved_ranges: Vec<(u32, u32)>,
+    pub reserved_names: Vec<String>,
+    /// The order the body was written in, when it is not the canonical
+    /// one. See [`Message::declarations`].
+    pub order: Vec<Declaration>,
+}
+
+impl Message {
+    /// The declarations of the body in the order they were written. Those
+    /// not in `order` follow in the canonical order: options, fields,
+    /// oneofs, `reserved`, `extensions`, `extend` blocks, enums, messages.
+    pub fn declarations(&self) -> Vec<Declaration> {
+        self.complete(self.order.clone())
+    }
+
+    /// `order` without declarations that no longer exist, followed by
+    /// those it leaves out.
+    fn complete(&self, order: Vec<Declaration>) -> Vec<Declaration> {
+        Declaration::complete(
+            order,
+            &[
+                (Declaration::OPTION, self.options.len()),
+                (Declaration::FIELD, self.fields.len()),
+                (Declaration::ONEOF, self.oneofs.len()),
+                (Declaration::RESERVED, self.reserved_ranges.len()),
+                (Declaration::RESERVED_NAMES, self.reserved_names.len()),
+                (Declaration::EXTENSIONS, self.extension_ranges.len()),
+                (Declaration::EXTEND, self.extends.len()),
+                (Declaration::ENUM, self.enums.len()),
+                (Declaration::MESSAGE, self.messages.len()),
+            ],
+        )
+    }
+
+    /// Clears `order` when it is the canonical one, so that a message
+    /// written that way equals one built in code.
+    pub(crate) fn normalize_order(&mut self) {
+        if self.order == self.complete(vec![]) {
+            self.order.clear();
+        }
+    }
+}
+
+impl ProtoFile {
+    /// The statements of the file in the order they were written, as
+    /// [`Message::declarations`]. The canonical order is `syntax`,
+    /// `package`, imports, options, messages, enums, services, `extend`
+    /// blocks.
+    pub fn declarations(&self) -> Vec<Declaration> {
+        self.complete(self.order.clone())
+    }
+
+    fn complete(&self, order: Vec<Declaration>) -> Vec<Declaration> {
+        Declaration::complete(
+            order,
+            &[
+                (Declaration::SYNTAX, usize::from(self.syntax.is_some())),
+                (Declaration::PACKAGE, usize::from(self.package.is_some())),
+                (Declaration::IMPORT, self.imports.len()),
+                (Declaration::OPTION, self.options.len()),
+                (Declaration::MESSAGE, self.messages.len()),
+                (Declaration::ENUM, self.enums.len()),
+                (Declaration::SERVICE, self.services.len()),
+                (Declaration::EXTEND, self.extends.len()),
+            ],
+        )
+    }
+
+    pub(crate) fn normalize_order(&mut self) {
+        if self.order == self.complete(vec![]) {
+            self.order.clear();
+        }
+    }
+}
+
+impl Enum {
+    /// The statements of the body in the order they were written, as
+    /// [`Message::declarations`], with values as [`Declaration::Field`].
+    /// The canonical order is options, values, `reserved`.
+    pub fn declarations(&self) -> Vec<Declaration> {
+        self.complete(self.order.clone())
+    }
+
+    fn complete(&self, order: Vec<Declaration>) -> Vec<Declaration> {
+        Declaration::complete(
+            order,
+            &[
+                (Declaration::OPTION, self.options.len()),
+                (Declaration::FIELD, self.fields.len()),
+                (Declaration::RESERVED, self.reserved_ranges.len()),
+                (Declaration::RESERVED_NAMES, self.reserved_names.len()),
+            ],
+        )
+    }
+
+    pub(crate) fn normalize_order(&mut self) {
+        if self.order == self.complete(vec![]) {
+            self.order.clear();
+        }
+    }
+}
+
+/// One statement in a file, message or enum body, by its index into the
+/// AST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum Declaration {
+    Syntax,
+    Package,
+    Import(usize),
+    Option(usize),
+    /// A field, or an enum's value.
+    Field(usize),
+    Oneof(usize),
+    /// A `reserved` statement listing `count` ranges from `first`.
+    Reserved {
+        first: usize,
+        count: usize,
+    },
+    /// A `reserved` statement listing `count` names from `first`.
+    ReservedNames {
+        first: usize,
+        count: usize,
+    },
+    /// An `extensions` statement listing `count` ranges from `first`.
+    Extensions {
+        first: usize,
+        count: usize,
+    },
+    Extend(usize),
+    Enum(usize),
+    Message(usize),
+    Service(usize),
+}
+
+impl Declaration {
+    // The kinds of declaration.
+    const SYNTAX: usize = 0;
+    const PACKAGE: usize = 1;
+    const IMPORT: usize = 2;
+    const OPTION: usize = 3;
+    const FIELD: usize = 4;
+    const ONEOF: usize = 5;
+    const RESERVED: usize = 6;
+    const RESERVED_NAMES: usize = 7;
+    const EXTENSIONS: usize = 8;
+    const EXTEND: usize = 9;
+    const ENUM: usize = 10;
+    const MESSAGE: usize = 11;
+    const SERVICE: usize = 12;
+
+    /// The kind of declaration and the indices of the items it declares.
+    fn indices(&self) -> (usize, std::ops::Range<usize>) {
+        match *self {
+            Declaration::Syntax => (Self::SYNTAX, 0..1),
+            Declaration::Package => (Self::PACKAGE, 0..1),
+            Declaration::Import(i) => (Self::IMPORT, i..i + 1),
+            Declaration::Option(i) => (Self::OPTION, i..i + 1),
+            Declaration::Field(i) => (Self::FIELD, i..i + 1),
+            Declaration::Oneof(i) => (Self::ONEOF, i..i + 1),
+            Declaration::Reserved { first, count } => (Self::RESERVED, first..first + count),
+            Declaration::ReservedNames { first, count } => {
+                (Self::RESERVED_NAMES, first..first + count)
+            }
+            Declaration::Extensions { first, count } => (Self::EXTENSIONS, first..first + count),
+            Declaration::Extend(i) => (Self::EXTEND, i..i + 1),
+            Declaration::Enum(i) => (Self::ENUM, i..i + 1),
+            Declaration::Message(i) => (Self::MESSAGE, i..i + 1),
+            Declaration::Service(i) => (Self::SERVICE, i..i + 1),
+        }
+    }
+
+    /// `order` without declarations beyond the number of items of each
+    /// kind in `lens`, followed by those it leaves out, in the order of
+    /// `lens`: a `reserved` statement for the rest of each list, an
+    /// `extensions` statement per range, and a declaration per item of the
+    /// other kinds.
+    fn complete(mut order: Vec<Declaration>, lens: &[(usize, usize)]) -> Vec<Declaration> {
+        let len = |kind| {
+            lens.iter()
+                .find(|(k, _)| *k == kind)
+                .map_or(0, |(_, len)| *len)
+        };
+        order.retain(|d| {
+            let (kind, indices) = d.indices();
+            indices.end <= len(kind)
+        });
+        for &(kind, len) in lens {
+            let first = order
+                .iter()
+                .map(Declaration::indices)
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, indices)| indices.end)
+                .max()
+                .unwrap_or(0);
+            let count = len.saturating_sub(first);
+            match kind {
+                _ if count == 0 => {}
+                Self::SYNTAX => order.push(Declaration::Syntax),
+                Self::PACKAGE => order.push(Declaration::Package),
+                Self::RESERVED => order.push(Declaration::Reserved { first, count }),
+                Self::RESERVED_NAMES => order.push(Declaration::ReservedNames { first, count }),
+                _ => order.extend((first..len).map(|i| match kind {
+                    Self::IMPORT => Declaration::Import(i),
+                    Self::OPTION => Declaration::Option(i),
+                    Self::FIELD => Declaration::Field(i),
+                    Self::ONEOF => Declaration::Oneof(i),
+                    Self::EXTENSIONS => Declaration::Extensions { first: i, count: 1 },
+                    Self::EXTEND => Declaration::Extend(i),
+                    Self::ENUM => Declaration::Enum(i),
+                    Self::MESSAGE => Declaration::Message(i),
+                    _ => Declaration::Service(i),
+                })),
+            }
+        }
+        order
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
 pub struct Oneof {
-    name: String,
-    messages: Vec<Message>,
-    enums: Vec<Enum>,
-    fields: Vec<MessageField>,
-    oneofs: Vec<Oneof>,
+    pub name: String,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub fields: Vec<MessageField>,
+    pub oneofs: Vec<Oneof>,
+    pub options: Vec<ProtoOption>,
+    /// Options given in brackets after a field, keyed by field position.
+    pub field_options: BTreeMap<u32, Vec<ProtoOption>>,
+}
+
+/// An `extend` block, declaring fields of another message in the scope
+/// it appears in.
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub struct Extend {
+    /// The extended message, fully qualified with a leading dot once
+    /// linked.
+    pub extendee: String,
+    pub fields: Vec<MessageField>,
+    pub field_options: BTreeMap<u32, Vec<ProtoOption>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct EnumField {
-    name: String,
-    position: u32,
+    pub name: String,
+    pub position: u32,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
 pub struct Enum {
-    name: String,
-    fields: Vec<EnumField>,
+    pub name: String,
+    pub fields: Vec<EnumField>,
+    pub options: Vec<ProtoOption>,
+    /// Options given in brackets after a value, keyed by value name.
+    pub value_options: BTreeMap<String, Vec<ProtoOption>>,
+    /// Values that may not be used, as inclusive ranges.
+    pub reserved_ranges: Vec<(u32, u32)>,
+    pub reserved_names: Vec<String>,
+    /// The order the body was written in, when it is not the canonical
+    /// one. See [`Enum::declarations`].
+    pub order: Vec<Declaration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub struct Service {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Default)]
+pub struct Method {
+    pub name: String,
+    pub input_type: String,
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct ProtoOption {
+    /// The option name as written, e.g. `java_package` or `(my.ext).field`.
+    pub name: String,
+    pub value: Constant,
+}
+
+impl ProtoOption {
+    /// For a custom option, the extension it sets and the field path within
+    /// that extension: `(my.ext).a.b` gives `("my.ext", "a.b")`.
+    pub fn extension(&self) -> Option<(&str, &str)> {
+        let rest = self.name.strip_prefix('(')?;
+        let (extension, path) = rest.split_once(')')?;
+        Some((extension, path.strip_prefix('.').unwrap_or(path)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum Constant {
+    Ident(String),
+    Int(i128),
+    /// Floating point literals are kept as written so the AST stays `Eq`.
+    Float(String),
+    String(String),
+    /// A string literal whose escapes do not spell valid UTF-8, which only
+    /// `bytes` fields and options accept.
+    Bytes(Vec<u8>),
+    Bool(bool),
+    Aggregate(Vec<(String, Constant)>),
+}
+
+/// One step of a path from the root of a file to a declaration or to one of
+/// its tokens. Indices refer to the vectors of the AST, not to descriptor
+/// field numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum PathSegment {
+    Syntax,
+    Package,
+    Import(usize),
+    Option(usize),
+    Message(usize),
+    Enum(usize),
+    Service(usize),
+    Field(usize),
+    Oneof(usize),
+    EnumValue(usize),
+    Method(usize),
+    Extend(usize),
+    /// An `extensions` statement; its ranges are numbered across the
+    /// message.
+    Extensions,
+    ExtensionRange(usize),
+    /// A `reserved` statement of numbers, whose ranges are numbered across
+    /// the message or enum.
+    Reserved,
+    ReservedRange(usize),
+    /// A `reserved` statement of names.
+    ReservedNames,
+    ReservedName(usize),
+    Start,
+    End,
+    Name,
+    Number,
+    Label,
+    Type,
+    Options,
+    Value,
+    InputType,
+    OutputType,
+    ClientStreaming,
+    ServerStreaming,
+    Extendee,
+}
+
+/// Zero based line and column span, with tabs advancing the column to the
+/// next multiple of eight like `protoc` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct Span {
+    pub start_line: i32,
+    pub start_column: i32,
+    pub end_line: i32,
+    pub end_column: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct Location {
+    pub path: Vec<PathSegment>,
+    pub span: Span,
+    pub leading_comments: Option<String>,
+    pub trailing_comments: Option<String>,
+    pub leading_detached_comments: Vec<String>,
 }
 
 impl From<String> for Frequency {
@@ -403,9 +1886,29 @@ impl From<Frequency> for String {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
-enum Type {
+pub enum Type {
     String,
     Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    Bool,
+    Float,
+    Double,
+    Bytes,
+    /// A message or enum type name as written, before linking.
+    Named(String),
+    /// A fully qualified message type name, e.g. `.tutorial.Person`.
+    Message(String),
+    /// A fully qualified enum type name.
+    Enum(String),
+    Map(Box<Type>, Box<Type>),
 }
 
 impl From<String> for Type {
@@ -413,15 +1916,232 @@ impl From<String> for Type {
         match value.as_str() {
             "string" => Self::String,
             "int32" => Self::Int32,
-            _ => unimplemented!(),
+            "int64" => Self::Int64,
+            "uint32" => Self::Uint32,
+            "uint64" => Self::Uint64,
+            "sint32" => Self::Sint32,
+            "sint64" => Self::Sint64,
+            "fixed32" => Self::Fixed32,
+            "fixed64" => Self::Fixed64,
+            "sfixed32" => Self::Sfixed32,
+            "sfixed64" => Self::Sfixed64,
+            "bool" => Self::Bool,
+            "float" => Self::Float,
+            "double" => Self::Double,
+            "bytes" => Self::Bytes,
+            _ => Self::Named(value),
         }
     }
 }
 
+impl From<Type> for String {
+    fn from(val: Type) -> Self {
+        match val {
+            Type::String => "string".into(),
+            Type::Int32 => "int32".into(),
+            Type::Int64 => "int64".into(),
+            Type::Uint32 => "uint32".into(),
+            Type::Uint64 => "uint64".into(),
+            Type::Sint32 => "sint32".into(),
+            Type::Sint64 => "sint64".into(),
+            Type::Fixed32 => "fixed32".into(),
+            Type::Fixed64 => "fixed64".into(),
+            Type::Sfixed32 => "sfixed32".into(),
+            Type::Sfixed64 => "sfixed64".into(),
+            Type::Bool => "bool".into(),
+            Type::Float => "float".into(),
+            Type::Double => "double".into(),
+            Type::Bytes => "bytes".into(),
+            Type::Named(name) | Type::Message(name) | Type::Enum(name) => name,
+            Type::Map(key, value) => format!(
+                "map<{}, {}>",
+                std::convert::Into::<String>::into(*key),
+                std::convert::Into::<String>::into(*value)
+            ),
+        }
+    }
+}
+
+/// A message field. Scalar variants hold the frequency, name and position;
+/// `Message`, `Enum` and `Named` also hold the type name before the field
+/// name, and `Map` holds the key and value types instead of a frequency.
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
-enum MessageField {
+pub enum MessageField {
     Int32(Option<Frequency>, String, u32),
     String(Option<Frequency>, String, u32),
+    Int64(Option<Frequency>, String, u32),
+    Uint32(Option<Frequency>, String, u32),
+    Uint64(Option<Frequency>, String, u32),
+    Sint32(Option<Frequency>, String, u32),
+    Sint64(Option<Frequency>, String, u32),
+    Fixed32(Option<Frequency>, String, u32),
+    Fixed64(Option<Frequency>, String, u32),
+    Sfixed32(Option<Frequency>, String, u32),
+    Sfixed64(Option<Frequency>, String, u32),
+    Bool(Option<Frequency>, String, u32),
+    Float(Option<Frequency>, String, u32),
+    Double(Option<Frequency>, String, u32),
+    Bytes(Option<Frequency>, String, u32),
+    Named(Option<Frequency>, String, String, u32),
+    Message(Option<Frequency>, String, String, u32),
+    Enum(Option<Frequency>, String, String, u32),
+    Map(Type, Type, String, u32),
+}
+
+impl MessageField {
+    pub fn new(frequency: Option<Frequency>, t: Type, name: String, position: u32) -> Self {
+        match t {
+            Type::String => MessageField::String(frequency, name, position),
+            Type::Int32 => MessageField::Int32(frequency, name, position),
+            Type::Int64 => MessageField::Int64(frequency, name, position),
+            Type::Uint32 => MessageField::Uint32(frequency, name, position),
+            Type::Uint64 => MessageField::Uint64(frequency, name, position),
+            Type::Sint32 => MessageField::Sint32(frequency, name, position),
+            Type::Sint64 => MessageField::Sint64(frequency, name, position),
+            Type::Fixed32 => MessageField::Fixed32(frequency, name, position),
+            Type::Fixed64 => MessageField::Fixed64(frequency, name, position),
+            Type::Sfixed32 => MessageField::Sfixed32(frequency, name, position),
+            Type::Sfixed64 => MessageField::Sfixed64(frequency, name, position),
+            Type::Bool => MessageField::Bool(frequency, name, position),
+            Type::Float => MessageField::Float(frequency, name, position),
+            Type::Double => MessageField::Double(frequency, name, position),
+            Type::Bytes => MessageField::Bytes(frequency, name, position),
+            Type::Named(t) => MessageField::Named(frequency, t, name, position),
+            Type::Message(t) => MessageField::Message(frequency, t, name, position),
+            Type::Enum(t) => MessageField::Enum(frequency, t, name, position),
+            Type::Map(key, value) => MessageField::Map(*key, *value, name, position),
+        }
+    }
+
+    pub fn frequency(&self) -> Option<&Frequency> {
+        match self {
+            MessageField::Int32(frequency, ..)
+            | MessageField::String(frequency, ..)
+            | MessageField::Int64(frequency, ..)
+            | MessageField::Uint32(frequency, ..)
+            | MessageField::Uint64(frequency, ..)
+            | MessageField::Sint32(frequency, ..)
+            | MessageField::Sint64(frequency, ..)
+            | MessageField::Fixed32(frequency, ..)
+            | MessageField::Fixed64(frequency, ..)
+            | MessageField::Sfixed32(frequency, ..)
+            | MessageField::Sfixed64(frequency, ..)
+            | MessageField::Bool(frequency, ..)
+            | MessageField::Float(frequency, ..)
+            | MessageField::Double(frequency, ..)
+            | MessageField::Bytes(frequency, ..)
+            | MessageField::Named(frequency, ..)
+            | MessageField::Message(frequency, ..)
+            | MessageField::Enum(frequency, ..) => frequency.as_ref(),
+            MessageField::Map(..) => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            MessageField::Int32(_, name, _)
+            | MessageField::String(_, name, _)
+            | MessageField::Int64(_, name, _)
+            | MessageField::Uint32(_, name, _)
+            | MessageField::Uint64(_, name, _)
+            | MessageField::Sint32(_, name, _)
+            | MessageField::Sint64(_, name, _)
+            | MessageField::Fixed32(_, name, _)
+            | MessageField::Fixed64(_, name, _)
+            | MessageField::Sfixed32(_, name, _)
+            | MessageField::Sfixed64(_, name, _)
+            | MessageField::Bool(_, name, _)
+            | MessageField::Float(_, name, _)
+            | MessageField::Double(_, name, _)
+            | MessageField::Bytes(_, name, _)
+            | MessageField::Named(_, _, name, _)
+            | MessageField::Message(_, _, name, _)
+            | MessageField::Enum(_, _, name, _)
+            | MessageField::Map(_, _, name, _) => name,
+        }
+    }
+
+    pub fn position(&self) -> u32 {
+        match self {
+            MessageField::Int32(_, _, position)
+            | MessageField::String(_, _, position)
+            | MessageField::Int64(_, _, position)
+            | MessageField::Uint32(_, _, position)
+            | MessageField::Uint64(_, _, position)
+            | MessageField::Sint32(_, _, position)
+            | MessageField::Sint64(_, _, position)
+            | MessageField::Fixed32(_, _, position)
+            | MessageField::Fixed64(_, _, position)
+            | MessageField::Sfixed32(_, _, position)
+            | MessageField::Sfixed64(_, _, position)
+            | MessageField::Bool(_, _, position)
+            | MessageField::Float(_, _, position)
+            | MessageField::Double(_, _, position)
+            | MessageField::Bytes(_, _, position)
+            | MessageField::Named(_, _, _, position)
+            | MessageField::Message(_, _, _, position)
+            | MessageField::Enum(_, _, _, position)
+            | MessageField::Map(_, _, _, position) => *position,
+        }
+    }
+
+    pub fn field_type(&self) -> Type {
+        match self {
+            MessageField::Int32(..) => Type::Int32,
+            MessageField::String(..) => Type::String,
+            MessageField::Int64(..) => Type::Int64,
+            MessageField::Uint32(..) => Type::Uint32,
+            MessageField::Uint64(..) => Type::Uint64,
+            MessageField::Sint32(..) => Type::Sint32,
+            MessageField::Sint64(..) => Type::Sint64,
+            MessageField::Fixed32(..) => Type::Fixed32,
+            MessageField::Fixed64(..) => Type::Fixed64,
+            MessageField::Sfixed32(..) => Type::Sfixed32,
+            MessageField::Sfixed64(..) => Type::Sfixed64,
+            MessageField::Bool(..) => Type::Bool,
+            MessageField::Float(..) => Type::Float,
+            MessageField::Double(..) => Type::Double,
+            MessageField::Bytes(..) => Type::Bytes,
+            MessageField::Named(_, t, ..) => Type::Named(t.clone()),
+            MessageField::Message(_, t, ..) => Type::Message(t.clone()),
+            MessageField::Enum(_, t, ..) => Type::Enum(t.clone()),
+            MessageField::Map(key, value, ..) => {
+                Type::Map(Box::new(key.clone()), Box::new(value.clone()))
+            }
+        }
+    }
+
+    /// Returns a copy of this field with its type replaced by `t`.
+    pub fn with_type(&self, t: Type) -> Self {
+        MessageField::new(
+            self.frequency().cloned(),
+            t,
+            self.name().to_string(),
+            self.position(),
+        )
+    }
+
+    pub fn is_repeated(&self) -> bool {
+        matches!(self, MessageField::Map(..)) || self.frequency() == Some(&Frequency::Repeated)
+    }
+
+    /// The lowerCamelCase name `protoc` derives for the JSON mapping:
+    /// underscores are dropped and the letter after each one is uppercased.
+    pub fn json_name(&self) -> String {
+        let mut ret = String::new();
+        let mut capitalize_next = false;
+        for c in self.name().chars() {
+            if c == '_' {
+                capitalize_next = true;
+            } else if capitalize_next {
+                ret.push(c.to_ascii_uppercase());
+                capitalize_next = false;
+            } else {
+                ret.push(c);
+            }
+        }
+        ret
+    }
 }
 
 #[cfg(test)]
@@ -486,23 +2206,49 @@ mod tests {
 //  xd";
         let mut parser = Parser::new(input);
 
-        let output = parser.consume_message();
+        let output = parser.consume_message().unwrap();
         let expected = Message {
             name: "blah".to_string(),
+            options: vec![],
+            field_options: BTreeMap::new(),
+            extends: vec![],
+            extension_ranges: vec![],
+            extension_range_options: BTreeMap::new(),
+            reserved_ranges: vec![],
+            reserved_names: vec![],
             oneofs: vec![],
             messages: vec![Message {
                 name: "inner".to_string(),
+                options: vec![],
+                field_options: BTreeMap::new(),
+                extends: vec![],
+                extension_ranges: vec![],
+                extension_range_options: BTreeMap::new(),
+                reserved_ranges: vec![],
+                reserved_names: vec![],
                 oneofs: vec![],
                 messages: vec![
                     Message {
                         name: "inner_inner".to_string(),
+                        options: vec![],
+                        field_options: BTreeMap::new(),
+                        extends: vec![],
+                        extension_ranges: vec![],
+                        extension_range_options: BTreeMap::new(),
+                        reserved_ranges: vec![],
+                        reserved_names: vec![],
                         messages: vec![],
                         enums: vec![Enum {
                             name: "inner_inner_enum".to_string(),
+                            options: vec![],
+                            value_options: BTreeMap::new(),
+                            reserved_ranges: vec![],
+                            reserved_names: vec![],
                             fields: vec![EnumField {
                                 name: "one".to_string(),
                                 position: 1,
                             }],
+                            order: vec![],
                         }],
                         fields: vec![
                             MessageField::String(
@@ -517,12 +2263,28 @@ mod tests {
                             ),
                         ],
                         oneofs: vec![],
+                        order: vec![
+                            Declaration::Field(0),
+                            Declaration::Enum(0),
+                            Declaration::Field(1),
+                        ],
                     },
                     Message {
                         name: "second_inner_inner".to_string(),
+                        options: vec![],
+                        field_options: BTreeMap::new(),
+                        extends: vec![],
+                        extension_ranges: vec![],
+                        extension_range_options: BTreeMap::new(),
+                        reserved_ranges: vec![],
+                        reserved_names: vec![],
                         messages: vec![],
                         enums: vec![Enum {
                             name: "inner_inner_inner_enum".to_string(),
+                            options: vec![],
+                            value_options: BTreeMap::new(),
+                            reserved_ranges: vec![],
+                            reserved_names: vec![],
                             fields: vec![
                                 EnumField {
                                     name: "one".to_string(),
@@ -533,6 +2295,7 @@ mod tests {
                                     position: 2,
                                 },
                             ],
+                            order: vec![],
                         }],
                         fields: vec![MessageField::Int32(
                             Some(Frequency::Repeated),
@@ -540,14 +2303,20 @@ mod tests {
                             1,
                         )],
                         oneofs: vec![],
+                        order: vec![],
                     },
                 ],
                 enums: vec![Enum {
                     name: "inner_enum".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![EnumField {
                         name: "one".to_string(),
                         position: 1,
                     }],
+                    order: vec![],
                 }],
                 fields: vec![
                     MessageField::String(Some(Frequency::Optional), "inner_field".to_string(), 1),
@@ -557,10 +2326,21 @@ mod tests {
                         2,
                     ),
                 ],
+                order: vec![
+                    Declaration::Field(0),
+                    Declaration::Enum(0),
+                    Declaration::Field(1),
+                    Declaration::Message(0),
+                    Declaration::Message(1),
+                ],
             }],
             enums: vec![
                 Enum {
                     name: "Person".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![
                         EnumField {
                             name: "name".to_string(),
@@ -575,9 +2355,14 @@ mod tests {
                             position: 3,
                         },
                     ],
+                    order: vec![],
                 },
                 Enum {
                     name: "Other".to_string(),
+                    options: vec![],
+                    value_options: BTreeMap::new(),
+                    reserved_ranges: vec![],
+                    reserved_names: vec![],
                     fields: vec![
                         EnumField {
                             name: "one".to_string(),
@@ -592,6 +2377,7 @@ mod tests {
                             position: 3,
                         },
                     ],
+                    order: vec![],
                 },
             ],
             fields: vec![
@@ -599,6 +2385,14 @@ mod tests {
                 MessageField::String(Some(Frequency::Repeated), "second".to_string(), 2),
                 MessageField::String(Some(Frequency::Optional), "third".to_string(), 3),
             ],
+            order: vec![
+                Declaration::Field(0),
+                Declaration::Enum(0),
+                Declaration::Enum(1),
+                Declaration::Field(1),
+                Declaration::Field(2),
+                Declaration::Message(0),
+            ],
         };
 
         assert_eq!(output, expected);
@@ -619,7 +2413,7 @@ mod tests {
 //  xd";
         let mut parser = Parser::new(input);
 
-        let output = parser.consume_enum();
+        let output = parser.consume_enum().unwrap();
 
         assert!(parser.is_finished());
 
@@ -627,6 +2421,10 @@ mod tests {
             output,
             Enum {
                 name: "Person".to_string(),
+                options: vec![],
+                value_options: BTreeMap::new(),
+                reserved_ranges: vec![],
+                reserved_names: vec![],
                 fields: vec![
                     EnumField {
                         name: "name".to_string(),
@@ -640,7 +2438,8 @@ mod tests {
                         name: "age".to_string(),
                         position: 3
                     }
-                ]
+                ],
+                order: vec![],
             }
         );
     }
@@ -659,7 +2458,7 @@ mod tests {
 //  xd";
         let mut parser = Parser::new(input);
 
-        let res = parser.consume_message();
+        let res = parser.consume_message().unwrap();
 
         assert!(parser.is_finished());
 
@@ -667,6 +2466,13 @@ mod tests {
             res,
             Message {
                 name: "Person".to_string(),
+                options: vec![],
+                field_options: BTreeMap::new(),
+                extends: vec![],
+                extension_ranges: vec![],
+                extension_range_options: BTreeMap::new(),
+                reserved_ranges: vec![],
+                reserved_names: vec![],
                 messages: vec![],
                 enums: vec![],
                 fields: vec![
@@ -675,6 +2481,7 @@ mod tests {
                     MessageField::Int32(Some(Frequency::Required), "age".to_string(), 3)
                 ],
                 oneofs: vec![],
+                order: vec![],
             },
         );
     }
@@ -690,7 +2497,7 @@ mod tests {
 
         let mut parser = Parser::new(input);
 
-        let res = parser.consume_message();
+        let res = parser.consume_message().unwrap();
 
         assert!(parser.is_finished());
 
@@ -698,11 +2505,20 @@ mod tests {
             res,
             Message {
                 name: "SampleMessage".to_string(),
+                options: vec![],
+                field_options: BTreeMap::new(),
+                extends: vec![],
+                extension_ranges: vec![],
+                extension_range_options: BTreeMap::new(),
+                reserved_ranges: vec![],
+                reserved_names: vec![],
                 messages: vec![],
                 enums: vec![],
                 fields: vec![],
                 oneofs: vec![Oneof {
                     name: "test_oneof".to_string(),
+                    options: vec![],
+                    field_options: BTreeMap::new(),
                     fields: vec![
                         MessageField::String(None, "name".to_string(), 4),
                         MessageField::Int32(None, "sub_message".to_string(), 9)
@@ -710,8 +2526,105 @@ mod tests {
                     messages: vec![],
                     enums: vec![],
                     oneofs: vec![]
-                }]
+                }],
+                order: vec![],
             }
         );
     }
+
+    #[test]
+    fn parse_file() {
+        let input = "syntax = \"proto3\";
+package tutorial;
+
+import public \"other.proto\";
+option java_package = \"com.example\";
+
+message Test1 {
+  optional int32 a = 1;
+  map<string, Test1> children = 2 [deprecated = true];
+}
+
+service Tests {
+  rpc Run (Test1) returns (stream .tutorial.Test1);
+}
+";
+        let mut parser = Parser::new(input);
+
+        let file = parser.consume_file().unwrap();
+
+        assert!(parser.is_finished());
+        assert_eq!(file.syntax, Some("proto3".to_string()));
+        assert_eq!(file.package, Some("tutorial".to_string()));
+        assert_eq!(
+            file.imports,
+            vec![Import {
+                path: "other.proto".to_string(),
+                public: true,
+                weak: false,
+            }]
+        );
+        assert_eq!(
+            file.options,
+            vec![ProtoOption {
+                name: "java_package".to_string(),
+                value: Constant::String("com.example".to_string()),
+            }]
+        );
+        assert_eq!(
+            file.messages[0].fields,
+            vec![
+                MessageField::Int32(Some(Frequency::Optional), "a".to_string(), 1),
+                MessageField::Map(
+                    Type::String,
+                    Type::Named("Test1".to_string()),
+                    "children".to_string(),
+                    2
+                ),
+            ]
+        );
+        assert_eq!(
+            file.messages[0].field_options[&2],
+            vec![ProtoOption {
+                name: "deprecated".to_string(),
+                value: Constant::Bool(true),
+            }]
+        );
+        assert_eq!(
+            file.services[0].methods[0],
+            Method {
+                name: "Run".to_string(),
+                input_type: "Test1".to_string(),
+                output_type: ".tutorial.Test1".to_string(),
+                client_streaming: false,
+                server_streaming: true,
+                options: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input: &str| Parser::new(input).consume_file().unwrap_err();
+
+        let err = error("message A {\n  int32 x 1;\n}");
+        assert_eq!(err.message, "Could not consume \"=\"");
+        assert_eq!((err.line, err.column), (2, 11));
+        assert_eq!(
+            error("message A { int32 x = ; }").message,
+            "Could not consume number"
+        );
+        assert_eq!(
+            error("enum E { A = 0; B; }").to_string(),
+            "Could not consume \"=\" at line 1 column 18"
+        );
+        assert_eq!(
+            error("message A { string s = 1 [default = \"x]; }").message,
+            "Unterminated string literal"
+        );
+        assert_eq!(
+            error("message A { optional group G = 1 { optional int32 x = 2; } }").message,
+            "Groups are not supported; use a nested message"
+        );
+    }
 }
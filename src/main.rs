@@ -0,0 +1,1213 @@
+//! The `protos` command-line tool.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use protos::codegen;
+use protos::descriptor::{FileDescriptorProto, FileDescriptorSet};
+use protos::dynamic::DynamicMessage;
+use protos::plugin::{self, CodeGeneratorRequest, GeneratedFile};
+use protos::protofmt;
+use protos::resolver::{FileSet, Resolver};
+use protos::{avro, json, raw, text_format, thrift, Parser};
+
+const USAGE: &str = "\
+usage: protos <command> [options] <files>
+
+commands:
+  parse       print the syntax tree of each file, or with --json its
+              FileDescriptorProto as JSON
+  fmt         format files in place; --check only reports and diffs,
+              --config FILE reads the style
+  check       parse, link and validate files and their imports
+  descriptor  write a FileDescriptorSet to -o FILE or stdout, with
+              --include-imports and --include-source-info
+  decode      read a binary --type NAME message on stdin and print it as
+              text format, or --json; --raw needs no schema
+  encode      read a text format, or --json, --type NAME message on stdin
+              and write it in binary
+  gen         run --lang NAME (proto, rust, typescript, python, go, c,
+              jsonschema, openapi, graphql, sql or avro) or --plugin
+              PROGRAM into --out DIR, passing --param PARAMETER and
+              warning of what the output does not carry over
+  plugin      act as a protoc plugin running --lang NAME: read a
+              CodeGeneratorRequest on stdin and write the response to
+              stdout
+  import      convert an Avro schema (.avsc) or Thrift IDL (.thrift) to a
+              .proto file, written to -o FILE or stdout, warning of what
+              does not carry over
+
+options:
+  -I DIR      search DIR for files and imports; the current directory if
+              none are given
+
+exit status: 0 on success, 1 when input is invalid or a check fails, 2 on
+bad usage
+";
+
+const FAILURE: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+
+/// Options that take a value.
+const VALUE_OPTIONS: &[&str] = &[
+    "--type", "-o", "--out", "--config", "--lang", "--plugin", "--param",
+];
+
+const FLAGS: &[&str] = &[
+    "--json",
+    "--check",
+    "--raw",
+    "--include-imports",
+    "--include-source-info",
+];
+
+/// Generates code for `files` from a linked set, given `--param`, adding
+/// to the warnings what the output can't express.
+type Generator =
+    fn(&FileSet, &[&str], &str, &mut Vec<String>) -> Result<Vec<GeneratedFile>, String>;
+
+/// The generators built into `protos gen --lang`.
+const GENERATORS: &[(&str, Generator)] = &[
+    ("proto", generate_proto),
+    ("rust", generate_rust),
+    ("typescript", generate_typescript),
+    ("python", generate_python),
+    ("go", generate_go),
+    ("c", generate_c),
+    ("jsonschema", generate_json_schema),
+    ("openapi", generate_openapi),
+    ("graphql", generate_graphql),
+    ("sql", generate_sql),
+    ("avro", generate_avro),
+];
+
+enum Failure {
+    Usage(String),
+    Failed(String),
+}
+
+fn failed(err: impl Display) -> Failure {
+    Failure::Failed(err.to_string())
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    command: String,
+    includes: Vec<PathBuf>,
+    files: Vec<String>,
+    flags: BTreeSet<String>,
+    values: BTreeMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, Failure> {
+        let mut iter = args.iter();
+        let mut ret = Args {
+            command: iter
+                .next()
+                .ok_or_else(|| Failure::Usage("missing command".to_string()))?
+                .clone(),
+            ..Default::default()
+        };
+        while let Some(arg) = iter.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| iter.next().cloned())
+                    .ok_or_else(|| Failure::Usage(format!("{} needs a value", name)))
+            };
+            if name == "-I" || name == "--proto_path" {
+                ret.includes.push(value()?.into());
+            } else if let Some(dir) = name.strip_prefix("-I") {
+                ret.includes.push(dir.into());
+            } else if VALUE_OPTIONS.contains(&name) {
+                ret.values.insert(name.to_string(), value()?);
+            } else if FLAGS.contains(&name) {
+                ret.flags.insert(name.to_string());
+            } else if name.starts_with('-') && name != "-" {
+                return Err(Failure::Usage(format!("unknown option {}", name)));
+            } else {
+                ret.files.push(arg.clone());
+            }
+        }
+        Ok(ret)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, Failure> {
+        self.value(name)
+            .ok_or_else(|| Failure::Usage(format!("protos {} needs {}", self.command, name)))
+    }
+
+    fn needs_files(&self) -> Result<(), Failure> {
+        if self.files.is_empty() {
+            return Err(Failure::Usage(format!(
+                "protos {} needs at least one file",
+                self.command
+            )));
+        }
+        Ok(())
+    }
+
+    /// Loads and links the files named on the command line.
+    fn load(&self) -> Result<FileSet, Failure> {
+        let mut resolver = Resolver::new();
+        if self.includes.is_empty() {
+            resolver.include(".");
+        }
+        for dir in &self.includes {
+            resolver.include(dir);
+        }
+        let files: Vec<&str> = self.files.iter().map(String::as_str).collect();
+        resolver.load(&files).map_err(failed)
+    }
+
+    /// An empty message of `--type` from the loaded files.
+    fn message(&self) -> Result<DynamicMessage, Failure> {
+        let type_name = self.required("--type")?;
+        DynamicMessage::from_file_set(Arc::new(self.load()?), type_name).map_err(failed)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = run(
+        &args,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        &mut std::io::stderr().lock(),
+    );
+    std::process::exit(code);
+}
+
+/// Runs a command line, returning the exit status.
+fn run(
+    args: &[String],
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let result = Args::parse(args).and_then(|args| dispatch(&args, stdin, stdout, stderr));
+    match result {
+        Ok(code) => code,
+        Err(Failure::Usage(message)) => {
+            let _ = write!(stderr, "error: {}\n\n{}", message, USAGE);
+            USAGE_ERROR
+        }
+        Err(Failure::Failed(message)) => {
+            let _ = writeln!(stderr, "error: {}", message);
+            FAILURE
+        }
+    }
+}
+
+fn dispatch(
+    args: &Args,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32, Failure> {
+    match args.command.as_str() {
+        "parse" => parse(args, stdout),
+        "fmt" => fmt(args, stdout, stderr),
+        "check" => check(args, stderr),
+        "descriptor" => descriptor(args, stdout),
+        "decode" => decode(args, stdin, stdout),
+        "encode" => encode(args, stdin, stdout),
+        "gen" => gen(args, stderr),
+        "plugin" => serve_plugin(args, stdin, stdout, stderr),
+        "import" => import(args, stdout, stderr),
+        "help" | "-h" | "--help" => {
+            write!(stdout, "{}", USAGE).map_err(failed)?;
+            Ok(0)
+        }
+        command => Err(Failure::Usage(format!("unknown command {}", command))),
+    }
+}
+
+fn read_file(path: impl AsRef<Path>) -> Result<String, Failure> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path).map_err(|err| failed(format!("{}: {}", path.display(), err)))
+}
+
+fn read_input(stdin: &mut dyn Read) -> Result<Vec<u8>, Failure> {
+    let mut buf = vec![];
+    stdin.read_to_end(&mut buf).map_err(failed)?;
+    Ok(buf)
+}
+
+fn parse(args: &Args, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let schema = match args.flag("--json") {
+        true => Some(Arc::new(
+            Resolver::new()
+                .load(&["google/protobuf/descriptor.proto"])
+                .map_err(failed)?,
+        )),
+        false => None,
+    };
+    for path in &args.files {
+        let mut file = Parser::new(&read_file(path)?)
+            .consume_file()
+            .map_err(|err| failed(format!("{}: {}", path, err)))?;
+        file.name = path.clone();
+        match &schema {
+            Some(schema) => {
+                let mut bytes = vec![];
+                FileDescriptorProto::from_file(&file, None).encode(&mut bytes);
+                let mut message = DynamicMessage::from_file_set(
+                    schema.clone(),
+                    "google.protobuf.FileDescriptorProto",
+                )
+                .map_err(failed)?;
+                message.merge(&bytes).map_err(failed)?;
+                let json = json::print(&message, &json::Options::default()).map_err(failed)?;
+                writeln!(stdout, "{}", json).map_err(failed)?;
+            }
+            None => writeln!(stdout, "{:#?}", file).map_err(failed)?,
+        }
+    }
+    Ok(0)
+}
+
+fn fmt(args: &Args, stdout: &mut dyn Write, stderr: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let config = match args.value("--config") {
+        Some(path) => protofmt::Config::parse(&read_file(path)?).map_err(failed)?,
+        None => protofmt::Config::default(),
+    };
+    if args.flag("--check") {
+        let changes = protofmt::check(&args.files, &config).map_err(failed)?;
+        for change in &changes {
+            writeln!(stderr, "{}: not formatted", change.path.display()).map_err(failed)?;
+            write!(stdout, "{}", change.diff).map_err(failed)?;
+        }
+        return Ok(if changes.is_empty() { 0 } else { FAILURE });
+    }
+    for path in protofmt::rewrite(&args.files, &config).map_err(failed)? {
+        writeln!(stderr, "formatted {}", path.display()).map_err(failed)?;
+    }
+    Ok(0)
+}
+
+fn check(args: &Args, stderr: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let problems = args.load()?.validate();
+    for problem in &problems {
+        writeln!(stderr, "{}", problem).map_err(failed)?;
+    }
+    Ok(if problems.is_empty() { 0 } else { FAILURE })
+}
+
+fn descriptor(args: &Args, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let set = args.load()?;
+    let mut descriptors =
+        FileDescriptorSet::from_file_set(&set, args.flag("--include-source-info"));
+    if !args.flag("--include-imports") {
+        descriptors.file.retain(|file| {
+            file.name
+                .as_ref()
+                .is_some_and(|name| args.files.contains(name))
+        });
+    }
+    let bytes = descriptors.encode_to_vec();
+    match args.value("-o") {
+        Some(path) => {
+            std::fs::write(path, bytes).map_err(|err| failed(format!("{}: {}", path, err)))?
+        }
+        None => stdout.write_all(&bytes).map_err(failed)?,
+    }
+    Ok(0)
+}
+
+fn decode(args: &Args, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    let bytes = read_input(stdin)?;
+    if args.flag("--raw") {
+        let fields = raw::decode_raw(&bytes).map_err(failed)?;
+        write!(stdout, "{}", raw::to_text(&fields)).map_err(failed)?;
+        return Ok(0);
+    }
+    args.needs_files()?;
+    let mut message = args.message()?;
+    message.merge(&bytes).map_err(failed)?;
+    if args.flag("--json") {
+        let json = json::print(&message, &json::Options::default()).map_err(failed)?;
+        writeln!(stdout, "{}", json).map_err(failed)?;
+    } else {
+        write!(stdout, "{}", text_format::print(&message)).map_err(failed)?;
+    }
+    Ok(0)
+}
+
+fn encode(args: &Args, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let mut message = args.message()?;
+    let input = String::from_utf8(read_input(stdin)?).map_err(failed)?;
+    if args.flag("--json") {
+        json::merge(&input, &mut message, &json::Options::default()).map_err(failed)?;
+    } else {
+        text_format::merge(&input, &mut message).map_err(failed)?;
+    }
+    stdout.write_all(&message.encode_to_vec()).map_err(failed)?;
+    Ok(0)
+}
+
+fn gen(args: &Args, stderr: &mut dyn Write) -> Result<i32, Failure> {
+    args.needs_files()?;
+    let out = PathBuf::from(args.required("--out")?);
+    let set = args.load()?;
+    let files: Vec<&str> = args.files.iter().map(String::as_str).collect();
+    let parameter = args.value("--param");
+
+    // Built-in generators get the loaded files as they are, comments and
+    // all; plugins get them as descriptors.
+    let mut warnings = vec![];
+    let generated = match (args.value("--lang"), args.value("--plugin")) {
+        (Some(lang), None) => {
+            generator(lang)?(&set, &files, parameter.unwrap_or(""), &mut warnings)
+                .map_err(failed)?
+        }
+        (None, Some(program)) => {
+            let request = CodeGeneratorRequest::new(&set, &files, parameter);
+            plugin::run_plugin(program, &request).map_err(failed)?
+        }
+        _ => {
+            return Err(Failure::Usage(
+                "protos gen needs one of --lang or --plugin".to_string(),
+            ))
+        }
+    };
+    warn(stderr, &warnings)?;
+
+    for (name, content) in plugin::assemble(&generated).map_err(failed)? {
+        let path = out.join(&name);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(failed)?;
+        }
+        std::fs::write(&path, content)
+            .map_err(|err| failed(format!("{}: {}", path.display(), err)))?;
+    }
+    Ok(0)
+}
+
+/// Serves one `protoc` plugin request with a built-in generator, so that
+/// `protoc --plugin=protoc-gen-x=WRAPPER --x_out=DIR` works with a wrapper
+/// script that runs `protos plugin --lang NAME`.
+/// Warnings go to stderr, which `protoc` passes on.
+fn serve_plugin(
+    args: &Args,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let generate = generator(args.required("--lang")?)?;
+    let mut warnings = vec![];
+    plugin::serve(stdin, stdout, |request| {
+        // Generators expect linked, valid files, as `protos gen` gives them.
+        let set = request.file_set().map_err(|err| err.to_string())?;
+        let problems = set.validate();
+        if !problems.is_empty() {
+            return Err(problems.join("\n"));
+        }
+        let files: Vec<&str> = request
+            .file_to_generate
+            .iter()
+            .map(String::as_str)
+            .collect();
+        generate(
+            &set,
+            &files,
+            request.parameter.as_deref().unwrap_or(""),
+            &mut warnings,
+        )
+    })
+    .map_err(failed)?;
+    warn(stderr, &warnings)?;
+    Ok(0)
+}
+
+fn warn(stderr: &mut dyn Write, warnings: &[String]) -> Result<(), Failure> {
+    for warning in warnings {
+        writeln!(stderr, "warning: {}", warning).map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// The built-in generator for `--lang`.
+fn generator(lang: &str) -> Result<Generator, Failure> {
+    GENERATORS
+        .iter()
+        .find(|(name, _)| *name == lang)
+        .map(|(_, generate)| *generate)
+        .ok_or_else(|| {
+            let names: Vec<&str> = GENERATORS.iter().map(|(name, _)| *name).collect();
+            Failure::Usage(format!(
+                "unknown language {}; expected one of {}",
+                lang,
+                names.join(", ")
+            ))
+        })
+}
+
+fn import(args: &Args, stdout: &mut dyn Write, stderr: &mut dyn Write) -> Result<i32, Failure> {
+    let [path] = &args.files[..] else {
+        return Err(Failure::Usage("protos import needs one file".to_string()));
+    };
+    let stem = Path::new(path).file_stem().unwrap_or_default();
+    let name = format!("{}.proto", stem.to_string_lossy());
+    let (file, warnings): (_, Vec<String>) =
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("avsc") => {
+                let (file, losses) = avro::import(&read_file(path)?, &name)
+                    .map_err(|err| failed(format!("{}: {}", path, err)))?;
+                (file, losses.iter().map(ToString::to_string).collect())
+            }
+            Some("thrift") => {
+                let (file, warnings) = thrift::import(&read_file(path)?, &name)
+                    .map_err(|err| failed(format!("{}:{}", path, err)))?;
+                let warnings = warnings
+                    .iter()
+                    .map(|warning| format!("{}:{}", path, warning))
+                    .collect();
+                (file, warnings)
+            }
+            _ => {
+                return Err(Failure::Usage(format!(
+                    "cannot import {}; expected an .avsc or .thrift file",
+                    path
+                )))
+            }
+        };
+    warn(stderr, &warnings)?;
+    let proto = codegen::format(&file);
+    match args.value("-o") {
+        Some(path) => {
+            std::fs::write(path, proto).map_err(|err| failed(format!("{}: {}", path, err)))?
+        }
+        None => stdout.write_all(proto.as_bytes()).map_err(failed)?,
+    }
+    Ok(0)
+}
+
+/// Writes each file back out as canonical `.proto` source.
+fn generate_proto(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    if !parameter.is_empty() {
+        return Err(format!("unknown proto generator parameter {}", parameter));
+    }
+    files
+        .iter()
+        .map(|name| {
+            let file = set
+                .file(name)
+                .ok_or_else(|| format!("{} is not loaded", name))?;
+            Ok(GeneratedFile::new(name, codegen::format(file)))
+        })
+        .collect()
+}
+
+/// Writes one Rust module per package; `--param` takes the options of
+/// [`codegen::rust::Options::parse`].
+fn generate_rust(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::rust::Options::parse(parameter)?;
+    let generated = codegen::rust::generate(set, files, &options);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes one TypeScript module per file and their runtime; `--param`
+/// takes the options of [`codegen::typescript::Options::parse`].
+fn generate_typescript(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::typescript::Options::parse(parameter)?;
+    let generated = codegen::typescript::generate(set, files, &options);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes one Python module per file and their runtime.
+fn generate_python(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    if !parameter.is_empty() {
+        return Err(format!("unknown python generator parameter {}", parameter));
+    }
+    let generated = codegen::python::generate(set, files);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes one Go file per file; `--param` takes the options of
+/// [`codegen::go::Options::parse`].
+fn generate_go(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::go::Options::parse(parameter)?;
+    let generated = codegen::go::generate(set, files, &options);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes a C header and source per file and their runtime; `--param`
+/// takes the options of [`codegen::c::Options::parse`].
+fn generate_c(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::c::Options::parse(parameter)?;
+    let generated = codegen::c::generate(set, files, &options).map_err(|err| err.to_string())?;
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes a JSON Schema document per top-level message.
+fn generate_json_schema(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    if !parameter.is_empty() {
+        return Err(format!(
+            "unknown jsonschema generator parameter {}",
+            parameter
+        ));
+    }
+    let generated = codegen::json_schema::generate(set, files);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes an OpenAPI document per file with HTTP rules; `--param` takes
+/// the options of [`codegen::openapi::Options::parse`].
+fn generate_openapi(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::openapi::Options::parse(parameter)?;
+    let generated = codegen::openapi::generate(set, files, &options);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes one GraphQL schema for all files; `--param` takes the options
+/// of [`codegen::graphql::Options::parse`].
+fn generate_graphql(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::graphql::Options::parse(parameter)?;
+    let generated = codegen::graphql::generate(set, files, &options);
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes an Avro schema per top-level message.
+fn generate_avro(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    if !parameter.is_empty() {
+        return Err(format!("unknown avro generator parameter {}", parameter));
+    }
+    let (generated, losses) = avro::generate(set, files);
+    warnings.extend(losses.iter().map(ToString::to_string));
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+/// Writes SQL `CREATE TABLE` statements per file; `--param` takes the
+/// options of [`codegen::sql::Options::parse`].
+fn generate_sql(
+    set: &FileSet,
+    files: &[&str],
+    parameter: &str,
+    _warnings: &mut Vec<String>,
+) -> Result<Vec<GeneratedFile>, String> {
+    let options = codegen::sql::Options::parse(parameter)?;
+    let generated = codegen::sql::generate(set, files, &options).map_err(|err| err.to_string())?;
+    Ok(generated
+        .into_iter()
+        .map(|(name, content)| GeneratedFile::new(&name, content))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a command line, returning the status, stdout and stderr.
+    fn protos(args: &[&str], stdin: &[u8]) -> (i32, Vec<u8>, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = run(&args, &mut &stdin[..], &mut stdout, &mut stderr);
+        (code, stdout, String::from_utf8(stderr).unwrap())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("protos-cli-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let args = [
+            "-I",
+            "tests/descriptor",
+            "person.proto",
+            "--type",
+            "tutorial.Person",
+        ];
+        let (code, binary, _) = protos(&[&["encode"][..], &args].concat(), b"name: \"Ada\" id: 7");
+        assert_eq!(code, 0);
+
+        let (code, text, _) = protos(&[&["decode"][..], &args].concat(), &binary);
+        assert_eq!(code, 0);
+        assert_eq!(String::from_utf8(text).unwrap(), "name: \"Ada\"\nid: 7\n");
+
+        let (code, json, _) = protos(&[&["decode", "--json"][..], &args].concat(), &binary);
+        assert_eq!(code, 0);
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"name\":\"Ada\",\"id\":7}\n"
+        );
+
+        let (code, raw, _) = protos(&["decode", "--raw"], &binary);
+        assert_eq!(code, 0);
+        assert_eq!(String::from_utf8(raw).unwrap(), "1: \"Ada\"\n2: 7\n");
+    }
+
+    #[test]
+    fn descriptor_matches_protoc() {
+        let (code, bytes, _) = protos(
+            &[
+                "descriptor",
+                "-Itests/descriptor",
+                "--include-imports",
+                "--include-source-info",
+                "person.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        assert_eq!(bytes, std::fs::read("tests/descriptor/person.pb").unwrap());
+    }
+
+    #[test]
+    fn fmt_check_and_exit_codes() {
+        let dir = temp_dir("fmt");
+        let path = dir.join("a.proto");
+        std::fs::write(&path, "message A { int32 x=1; }").unwrap();
+        let path = path.to_str().unwrap();
+
+        let (code, diff, stderr) = protos(&["fmt", "--check", path], b"");
+        assert_eq!(code, FAILURE);
+        assert!(String::from_utf8(diff).unwrap().contains("+  int32 x = 1;"));
+        assert!(stderr.ends_with("a.proto: not formatted\n"));
+
+        assert_eq!(protos(&["fmt", path], b"").0, 0);
+        assert_eq!(protos(&["fmt", "--check", path], b"").0, 0);
+
+        std::fs::write(path, "message A { int32 x = 1 }}").unwrap();
+        let (code, _, stderr) = protos(&["check", "-I", dir.to_str().unwrap(), "a.proto"], b"");
+        assert_eq!(code, FAILURE);
+        assert!(stderr.starts_with("error: a.proto: Unexpected input at line 1"));
+
+        assert_eq!(protos(&["fmt", "--bogus"], b"").0, USAGE_ERROR);
+        assert_eq!(protos(&["frobnicate"], b"").0, USAGE_ERROR);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_and_gen() {
+        let dir = temp_dir("gen");
+        std::fs::write(
+            dir.join("a.proto"),
+            "syntax = \"proto3\"; message A { int32 x = 1; int32 y = 1; }",
+        )
+        .unwrap();
+        let include = dir.to_str().unwrap();
+
+        let (code, _, stderr) = protos(&["check", "-I", include, "a.proto"], b"");
+        assert_eq!(code, FAILURE);
+        assert_eq!(stderr, "a.proto: A.y: number 1 is already used by \"x\"\n");
+
+        let out = dir.join("out");
+        let out = out.to_str().unwrap();
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "proto", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out/a.proto")).unwrap(),
+            "syntax = \"proto3\";\n\nmessage A {\n  int32 x = 1;\n  int32 y = 1;\n}\n"
+        );
+
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "rust", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let rust = std::fs::read_to_string(dir.join("out/_.rs")).unwrap();
+        assert!(rust.contains("impl ::protos::wire::WireMessage for A {"));
+        let (code, _, stderr) = protos(
+            &[
+                "gen", "--lang", "rust", "--param", "boxed", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, FAILURE);
+        assert_eq!(stderr, "error: unknown rust generator parameter boxed\n");
+
+        let (code, _, _) = protos(
+            &[
+                "gen",
+                "--lang",
+                "typescript",
+                "--param",
+                "long=bigint",
+                "--out",
+                out,
+                "-I",
+                include,
+                "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let typescript = std::fs::read_to_string(dir.join("out/a.ts")).unwrap();
+        assert!(typescript.contains("export const A = {"));
+        assert!(dir.join("out/runtime.ts").exists());
+
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "python", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let python = std::fs::read_to_string(dir.join("out/a.py")).unwrap();
+        assert!(python.contains("@dataclasses.dataclass\nclass A:\n"));
+
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "go", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let go = std::fs::read_to_string(dir.join("out/a.pb.go")).unwrap();
+        assert!(go.contains("type A struct {\n"));
+
+        let (code, _, _) = protos(
+            &["gen", "--lang", "c", "--out", out, "-I", include, "a.proto"],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let header = std::fs::read_to_string(dir.join("out/a.pb.h")).unwrap();
+        assert!(header.contains("typedef struct _A {\n"));
+        assert!(dir.join("out/protos.c").exists());
+
+        let (code, _, _) = protos(
+            &[
+                "gen",
+                "--lang",
+                "jsonschema",
+                "--out",
+                out,
+                "-I",
+                include,
+                "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let schema = std::fs::read_to_string(dir.join("out/A.schema.json")).unwrap();
+        assert!(schema.contains("\"title\": \"A\""));
+
+        let (code, _, stderr) = protos(
+            &[
+                "gen", "--lang", "openapi", "--param", "title=A", "--out", out, "-I", include,
+                "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, FAILURE);
+        assert_eq!(
+            stderr,
+            "error: unknown openapi generator parameter title=A\n"
+        );
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "openapi", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        assert!(!dir.join("out/a.openapi.json").exists());
+
+        let (code, _, _) = protos(
+            &[
+                "gen", "--lang", "graphql", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let schema = std::fs::read_to_string(dir.join("out/schema.graphql")).unwrap();
+        assert!(schema.contains("\ntype A {\n"));
+
+        let (code, _, _) = protos(
+            &[
+                "gen",
+                "--lang",
+                "sql",
+                "--param",
+                "dialect=postgres",
+                "--out",
+                out,
+                "-I",
+                include,
+                "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        let sql = std::fs::read_to_string(dir.join("out/a.sql")).unwrap();
+        assert!(sql.contains("CREATE TABLE a (\n"));
+
+        let (code, _, stderr) = protos(
+            &[
+                "gen", "--lang", "avro", "--out", out, "-I", include, "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, 0);
+        assert_eq!(stderr, "warning: A: field numbers are not kept\n");
+        let avro = std::fs::read_to_string(dir.join("out/A.avsc")).unwrap();
+        assert!(avro.contains("\"type\": \"record\""));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_avro_and_thrift() {
+        let dir = temp_dir("import");
+        let schema = dir.join("click.avsc");
+        std::fs::copy("tests/avro/events.Click.avsc", &schema).unwrap();
+        let (code, stdout, stderr) = protos(&["import", schema.to_str().unwrap()], b"");
+        assert_eq!(code, 0);
+        let expected = std::fs::read_to_string("tests/avro/click.proto").unwrap();
+        assert_eq!(String::from_utf8(stdout).unwrap(), expected);
+        assert!(stderr.contains("warning: events.Click: field numbers are assigned in order\n"));
+
+        let out = dir.join("click.proto");
+        let (code, stdout, _) = protos(
+            &[
+                "import",
+                "-o",
+                out.to_str().unwrap(),
+                schema.to_str().unwrap(),
+            ],
+            b"",
+        );
+        assert_eq!((code, stdout.len()), (0, 0));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+
+        let (code, stdout, stderr) = protos(&["import", "tests/thrift/tutorial.thrift"], b"");
+        assert_eq!(code, 0);
+        let expected = std::fs::read_to_string("tests/thrift/tutorial.proto").unwrap();
+        assert_eq!(String::from_utf8(stdout).unwrap(), expected);
+        assert!(stderr.contains(
+            "warning: tests/thrift/tutorial.thrift:56: exception InvalidOperation becomes a message\n"
+        ));
+
+        let thrift = dir.join("bad.thrift");
+        std::fs::write(&thrift, "struct A {\n  1: i32\n}\n").unwrap();
+        let (code, _, stderr) = protos(&["import", thrift.to_str().unwrap()], b"");
+        assert_eq!(code, FAILURE);
+        assert_eq!(
+            stderr,
+            format!("error: {}:3:1: expected identifier\n", thrift.display())
+        );
+
+        let (code, _, stderr) = protos(&["import", "a.idl"], b"");
+        assert_eq!(code, USAGE_ERROR);
+        assert!(
+            stderr.starts_with("error: cannot import a.idl; expected an .avsc or .thrift file\n")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gen_matches_goldens() {
+        let dir = temp_dir("goldens");
+        let out = dir.to_str().unwrap();
+        let cases = [
+            (
+                "go",
+                "tests/rust",
+                "demo.proto",
+                "demo.pb.go",
+                "tests/go/demo.pb.go",
+            ),
+            (
+                "sql",
+                "tests/sql",
+                "crm.proto",
+                "crm.sql",
+                "tests/sql/crm.sql",
+            ),
+            (
+                "c",
+                "tests/c",
+                "sensor.proto",
+                "sensor.pb.h",
+                "tests/c/sensor.pb.h",
+            ),
+            (
+                "c",
+                "tests/c",
+                "sensor.proto",
+                "sensor.pb.c",
+                "tests/c/sensor.pb.c",
+            ),
+            (
+                "openapi",
+                "tests/openapi",
+                "library.proto",
+                "library.openapi.json",
+                "tests/openapi/library.openapi.json",
+            ),
+        ];
+        for (lang, include, file, generated, golden) in cases {
+            let args = ["gen", "--lang", lang, "--out", out, "-I", include, file];
+            let (code, _, stderr) = protos(&args, b"");
+            assert_eq!((code, stderr.as_str()), (0, ""), "{}", lang);
+            assert_eq!(
+                std::fs::read_to_string(dir.join(generated)).unwrap(),
+                std::fs::read_to_string(golden).unwrap(),
+                "{}",
+                generated
+            );
+        }
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert!(read("crm.sql").contains("-- A customer account.\n"));
+        assert!(read("sensor.pb.h").contains("// One measurement from a device.\n"));
+        assert!(read("demo.pb.go").contains("// A person in the address book.\n"));
+        assert!(
+            read("library.openapi.json").contains("\"description\": \"Manages shelves of books.\"")
+        );
+        assert!(read("library.openapi.json").contains("\"/v1/{parent}/books\": {"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plugin_serves_requests() {
+        let set = Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap();
+        let request = CodeGeneratorRequest::new(&set, &["demo.proto"], None);
+        let (code, stdout, _) = protos(&["plugin", "--lang", "go"], &request.encode_to_vec());
+        assert_eq!(code, 0);
+        let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+        assert_eq!(response.error, None);
+        assert_eq!(response.file[0].name.as_deref(), Some("demo.pb.go"));
+        assert_eq!(
+            response.file[0].content.as_deref(),
+            Some(
+                std::fs::read_to_string("tests/go/demo.pb.go")
+                    .unwrap()
+                    .as_str()
+            )
+        );
+
+        // Descriptions and paths come from source info and decoded options.
+        let set = Resolver::new()
+            .include("tests/openapi")
+            .load(&["library.proto"])
+            .unwrap();
+        let request = CodeGeneratorRequest::new(&set, &["library.proto"], None);
+        let (code, stdout, _) = protos(&["plugin", "--lang", "openapi"], &request.encode_to_vec());
+        assert_eq!(code, 0);
+        let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+        assert_eq!(response.error, None);
+        let content = response.file[0].content.as_deref().unwrap();
+        assert!(content.contains("\"description\": \"Manages shelves of books.\""));
+        assert!(content.contains("\"/v1/{name}\": {"));
+        assert_eq!(
+            content,
+            std::fs::read_to_string("tests/openapi/library.openapi.json").unwrap()
+        );
+
+        let set = Resolver::new()
+            .include("tests/rust")
+            .load(&["demo.proto"])
+            .unwrap();
+        let request = CodeGeneratorRequest::new(&set, &["demo.proto"], Some("bogus"));
+        let (code, stdout, _) = protos(&["plugin", "--lang", "python"], &request.encode_to_vec());
+        assert_eq!(code, 0);
+        let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+        assert_eq!(
+            response.error.as_deref(),
+            Some("unknown python generator parameter bogus")
+        );
+
+        // What a schema can't carry over is reported, not dropped.
+        let set = Resolver::new()
+            .include("tests/avro")
+            .load(&["events.proto"])
+            .unwrap();
+        let request = CodeGeneratorRequest::new(&set, &["events.proto"], None);
+        let (code, stdout, stderr) =
+            protos(&["plugin", "--lang", "avro"], &request.encode_to_vec());
+        assert_eq!(code, 0);
+        let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+        assert_eq!(response.error, None);
+        assert_eq!(response.file.len(), 2);
+        for warning in [
+            "warning: events.Click.user_id: uint64 values above 2^63 - 1 do not fit a long\n",
+            "warning: events.Click.labels: int32 map keys become strings\n",
+            "warning: events.Page.Kind: enum numbers are not kept, only the order of values\n",
+            "warning: events.Click.target: oneof members of the same type become separate fields\n",
+            "warning: events.Page: field numbers are not kept\n",
+        ] {
+            assert!(stderr.contains(warning), "{}", stderr);
+        }
+        assert_eq!(protos(&["plugin"], b"").0, USAGE_ERROR);
+    }
+
+    #[test]
+    fn plugin_reports_invalid_requests() {
+        let dir = temp_dir("plugin-invalid");
+        std::fs::write(
+            dir.join("a.proto"),
+            "syntax = \"proto3\"; package a;
+             message A { B b = 1; repeated B bs = 2; map<string, B> m = 3; }
+             message B { int32 x = 1; }",
+        )
+        .unwrap();
+        let set = Resolver::new().include(&dir).load(&["a.proto"]).unwrap();
+        let mut request = CodeGeneratorRequest::new(&set, &["a.proto"], None);
+        request.proto_file[0].message_type.pop();
+        for lang in [
+            "rust",
+            "typescript",
+            "python",
+            "go",
+            "graphql",
+            "sql",
+            "avro",
+        ] {
+            let (code, stdout, _) = protos(&["plugin", "--lang", lang], &request.encode_to_vec());
+            assert_eq!(code, 0);
+            let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+            assert_eq!(
+                response.error.as_deref(),
+                Some("a.proto: \".a.B\" is not defined"),
+                "{}",
+                lang
+            );
+        }
+
+        let mut request = CodeGeneratorRequest::new(&set, &["a.proto"], None);
+        let b = request.proto_file[0].message_type.last_mut().unwrap();
+        b.field.push(b.field[0].clone());
+        let (_, stdout, _) = protos(&["plugin", "--lang", "go"], &request.encode_to_vec());
+        let response = plugin::CodeGeneratorResponse::decode(&stdout).unwrap();
+        assert_eq!(
+            response.error.as_deref(),
+            Some(
+                "a.proto: a.B.x: number 1 is already used by \"x\"\n\
+                  a.proto: a.B.x: field name is already used"
+            )
+        );
+
+        let missing = dir.join("protoc-gen-missing");
+        let (code, _, stderr) = protos(
+            &[
+                "gen",
+                "--plugin",
+                missing.to_str().unwrap(),
+                "--out",
+                dir.join("out").to_str().unwrap(),
+                "-I",
+                dir.to_str().unwrap(),
+                "a.proto",
+            ],
+            b"",
+        );
+        assert_eq!(code, FAILURE);
+        assert!(
+            stderr.starts_with(&format!("error: {}: ", missing.display())),
+            "{}",
+            stderr
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
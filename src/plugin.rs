@@ -0,0 +1,492 @@
+//! The `protoc` plugin protocol: `google/protobuf/compiler/plugin.proto`.
+//!
+//! A plugin reads a `CodeGeneratorRequest` from stdin and writes a
+//! `CodeGeneratorResponse` to stdout. [`serve`] runs one of this crate's
+//! generators that way, and [`run_plugin`] drives an external
+//! `protoc-gen-*` executable from files parsed here.
+
+use crate::descriptor::*;
+use crate::resolver::{self, FileSet};
+use crate::wire::{self, *};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// `CodeGeneratorResponse.Feature.FEATURE_PROTO3_OPTIONAL`.
+pub const FEATURE_PROTO3_OPTIONAL: u64 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The plugin executable could not be started.
+    Start(PathBuf, std::io::Error),
+    Decode(wire::Error),
+    /// The plugin reported an error in its response.
+    Plugin(String),
+    /// The plugin exited unsuccessfully; holds what it wrote to stderr.
+    Exit(std::process::ExitStatus, String),
+    /// An insertion point names a file or marker that does not exist.
+    InsertionPoint {
+        file: String,
+        name: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Start(program, err) => write!(f, "{}: {}", program.display(), err),
+            Error::Decode(err) => write!(f, "malformed plugin message: {}", err),
+            Error::Plugin(message) => write!(f, "{}", message),
+            Error::Exit(status, stderr) => write!(f, "plugin failed with {}: {}", status, stderr),
+            Error::InsertionPoint { file, name } => {
+                write!(f, "{}: insertion point \"{}\" not found", file, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<wire::Error> for Error {
+    fn from(err: wire::Error) -> Self {
+        Error::Decode(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Version {
+    pub major: Option<i32>,
+    pub minor: Option<i32>,
+    pub patch: Option<i32>,
+    pub suffix: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CodeGeneratorRequest {
+    pub file_to_generate: Vec<String>,
+    pub parameter: Option<String>,
+    /// Every file in `file_to_generate` and everything they import, imports
+    /// first.
+    pub proto_file: Vec<FileDescriptorProto>,
+    pub compiler_version: Option<Version>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CodeGeneratorResponse {
+    pub error: Option<String>,
+    pub supported_features: Option<u64>,
+    pub file: Vec<GeneratedFile>,
+}
+
+/// `CodeGeneratorResponse.File`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GeneratedFile {
+    pub name: Option<String>,
+    pub insertion_point: Option<String>,
+    pub content: Option<String>,
+}
+
+impl Version {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, wire::Error> {
+        let mut version = Version::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::Varint) => version.major = Some(decode_varint(&mut buf)? as i32),
+                (2, WireType::Varint) => version.minor = Some(decode_varint(&mut buf)? as i32),
+                (3, WireType::Varint) => version.patch = Some(decode_varint(&mut buf)? as i32),
+                (4, WireType::LengthDelimited) => version.suffix = Some(decode_string(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(version)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(major) = self.major {
+            put_int32(1, major, buf);
+        }
+        if let Some(minor) = self.minor {
+            put_int32(2, minor, buf);
+        }
+        if let Some(patch) = self.patch {
+            put_int32(3, patch, buf);
+        }
+        if let Some(suffix) = &self.suffix {
+            put_string(4, suffix, buf);
+        }
+    }
+}
+
+impl CodeGeneratorRequest {
+    /// Builds the request `protoc` would send for `files_to_generate`, which
+    /// must all be in `set`.
+    pub fn new(set: &FileSet, files_to_generate: &[&str], parameter: Option<&str>) -> Self {
+        CodeGeneratorRequest {
+            file_to_generate: files_to_generate.iter().map(|f| f.to_string()).collect(),
+            parameter: parameter.map(|p| p.to_string()),
+            proto_file: FileDescriptorSet::from_file_set(set, true).file,
+            compiler_version: None,
+        }
+    }
+
+    /// The request's files converted back into the AST and linked, which
+    /// fails if they refer to a type none of them define.
+    pub fn file_set(&self) -> Result<FileSet, resolver::Error> {
+        let decoded = FileDescriptorSet {
+            file: self.proto_file.clone(),
+        }
+        .to_file_set();
+        let mut set = FileSet::new(decoded.files)?;
+        set.locations = decoded.locations;
+        Ok(set)
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, wire::Error> {
+        let mut request = CodeGeneratorRequest::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => {
+                    request.file_to_generate.push(decode_string(&mut buf)?)
+                }
+                (2, WireType::LengthDelimited) => {
+                    request.parameter = Some(decode_string(&mut buf)?)
+                }
+                (3, WireType::LengthDelimited) => {
+                    request.compiler_version =
+                        Some(Version::decode(decode_length_delimited(&mut buf)?)?)
+                }
+                (15, WireType::LengthDelimited) => {
+                    request
+                        .proto_file
+                        .push(FileDescriptorProto::decode(decode_length_delimited(
+                            &mut buf,
+                        )?)?)
+                }
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(request)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for file in &self.file_to_generate {
+            put_string(1, file, buf);
+        }
+        if let Some(parameter) = &self.parameter {
+            put_string(2, parameter, buf);
+        }
+        if let Some(version) = &self.compiler_version {
+            put_message(3, buf, |buf| version.encode(buf));
+        }
+        for file in &self.proto_file {
+            put_message(15, buf, |buf| file.encode(buf));
+        }
+    }
+
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+impl CodeGeneratorResponse {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, wire::Error> {
+        let mut response = CodeGeneratorResponse::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => response.error = Some(decode_string(&mut buf)?),
+                (2, WireType::Varint) => {
+                    response.supported_features = Some(decode_varint(&mut buf)?)
+                }
+                (15, WireType::LengthDelimited) => response
+                    .file
+                    .push(GeneratedFile::decode(decode_length_delimited(&mut buf)?)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(response)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(error) = &self.error {
+            put_string(1, error, buf);
+        }
+        if let Some(features) = self.supported_features {
+            put_varint(2, features, buf);
+        }
+        for file in &self.file {
+            put_message(15, buf, |buf| file.encode(buf));
+        }
+    }
+
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+impl GeneratedFile {
+    pub fn new(name: &str, content: String) -> Self {
+        GeneratedFile {
+            name: Some(name.to_string()),
+            insertion_point: None,
+            content: Some(content),
+        }
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, wire::Error> {
+        let mut file = GeneratedFile::default();
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            match (number, wire_type) {
+                (1, WireType::LengthDelimited) => file.name = Some(decode_string(&mut buf)?),
+                (2, WireType::LengthDelimited) => {
+                    file.insertion_point = Some(decode_string(&mut buf)?)
+                }
+                (15, WireType::LengthDelimited) => file.content = Some(decode_string(&mut buf)?),
+                _ => skip_field(number, wire_type, &mut buf)?,
+            }
+        }
+        Ok(file)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(name) = &self.name {
+            put_string(1, name, buf);
+        }
+        if let Some(insertion_point) = &self.insertion_point {
+            put_string(2, insertion_point, buf);
+        }
+        if let Some(content) = &self.content {
+            put_string(15, content, buf);
+        }
+    }
+}
+
+/// Runs `generate` as a `protoc` plugin: reads a request from `input` and
+/// writes the response to `output`. An `Err` from `generate` is reported
+/// to `protoc` in the response rather than returned.
+///
+/// A `protoc-gen-*` binary's `main` is typically just
+/// `serve(std::io::stdin(), std::io::stdout(), generate)`.
+pub fn serve(
+    mut input: impl Read,
+    mut output: impl Write,
+    generate: impl FnOnce(&CodeGeneratorRequest) -> Result<Vec<GeneratedFile>, String>,
+) -> Result<(), Error> {
+    let mut buf = vec![];
+    input.read_to_end(&mut buf)?;
+    let request = CodeGeneratorRequest::decode(&buf)?;
+
+    let mut response = CodeGeneratorResponse {
+        supported_features: Some(FEATURE_PROTO3_OPTIONAL),
+        ..Default::default()
+    };
+    match generate(&request) {
+        Ok(files) => response.file = files,
+        Err(error) => response.error = Some(error),
+    }
+    output.write_all(&response.encode_to_vec())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Sends `request` to the plugin executable at `program` and returns the
+/// files it generated, as `protoc --plugin` would.
+pub fn run_plugin(
+    program: impl AsRef<Path>,
+    request: &CodeGeneratorRequest,
+) -> Result<Vec<GeneratedFile>, Error> {
+    let mut child = Command::new(program.as_ref())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::Start(program.as_ref().to_path_buf(), err))?;
+
+    // Write from another thread so a plugin that streams its output before
+    // reading all of its input cannot deadlock against us.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let bytes = request.encode_to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&bytes));
+    let output = child.wait_with_output()?;
+    writer.join().expect("writer thread panicked")?;
+
+    if !output.status.success() {
+        return Err(Error::Exit(
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    let response = CodeGeneratorResponse::decode(&output.stdout)?;
+    match response.error {
+        Some(error) => Err(Error::Plugin(error)),
+        None => Ok(response.file),
+    }
+}
+
+/// Assembles generated files into their final contents, keyed by name.
+///
+/// A file without a name continues the previous one, and a file with an
+/// insertion point is spliced into an earlier file just above the line
+/// containing `@@protoc_insertion_point(NAME)`, with that line's
+/// indentation.
+pub fn assemble(files: &[GeneratedFile]) -> Result<BTreeMap<String, String>, Error> {
+    let mut ret: BTreeMap<String, String> = BTreeMap::new();
+    let mut last: Option<(String, Option<String>)> = None;
+
+    for file in files {
+        let content = file.content.as_deref().unwrap_or_default();
+        let target = match &file.name {
+            Some(name) if !name.is_empty() => (name.clone(), file.insertion_point.clone()),
+            _ => match &last {
+                Some(target) => target.clone(),
+                None => continue,
+            },
+        };
+
+        match &target.1 {
+            None => ret.entry(target.0.clone()).or_default().push_str(content),
+            Some(point) => {
+                let insertion_error = || Error::InsertionPoint {
+                    file: target.0.clone(),
+                    name: point.clone(),
+                };
+                let existing = ret.get_mut(&target.0).ok_or_else(insertion_error)?;
+                let marker = format!("@@protoc_insertion_point({})", point);
+                let at = existing.find(&marker).ok_or_else(insertion_error)?;
+                let line_start = existing[..at].rfind('\n').map_or(0, |i| i + 1);
+                let indent: String = existing[line_start..]
+                    .chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect();
+
+                let mut inserted = String::new();
+                for line in content.split_inclusive('\n') {
+                    if line != "\n" {
+                        inserted.push_str(&indent);
+                    }
+                    inserted.push_str(line);
+                }
+                existing.insert_str(line_start, &inserted);
+            }
+        }
+        last = Some(target);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn request() -> CodeGeneratorRequest {
+        let mut resolver = Resolver::new();
+        resolver.include("tests/descriptor");
+        let set = resolver.load(&["person.proto"]).unwrap();
+        CodeGeneratorRequest::new(&set, &["person.proto"], Some("opt=1"))
+    }
+
+    #[test]
+    fn request_round_trip() {
+        let request = request();
+        let decoded = CodeGeneratorRequest::decode(&request.encode_to_vec()).unwrap();
+
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.proto_file.len(), 2);
+        assert!(decoded
+            .file_set()
+            .unwrap()
+            .find_message("tutorial.Person")
+            .is_some());
+    }
+
+    #[test]
+    fn serve_writes_response() {
+        let input = request().encode_to_vec();
+        let mut output = vec![];
+        serve(input.as_slice(), &mut output, |request| {
+            Ok(request
+                .file_to_generate
+                .iter()
+                .map(|name| GeneratedFile::new(&format!("{}.txt", name), "hello\n".to_string()))
+                .collect())
+        })
+        .unwrap();
+
+        let response = CodeGeneratorResponse::decode(&output).unwrap();
+        assert_eq!(response.supported_features, Some(FEATURE_PROTO3_OPTIONAL));
+        assert_eq!(
+            response.file,
+            vec![GeneratedFile::new(
+                "person.proto.txt",
+                "hello\n".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn insertion_points() {
+        let files = vec![
+            GeneratedFile::new(
+                "a.rs",
+                "fn a() {\n    // @@protoc_insertion_point(body)\n}\n".to_string(),
+            ),
+            GeneratedFile {
+                name: Some("a.rs".to_string()),
+                insertion_point: Some("body".to_string()),
+                content: Some("one();\n".to_string()),
+            },
+            GeneratedFile {
+                name: None,
+                insertion_point: None,
+                content: Some("two();\n".to_string()),
+            },
+        ];
+
+        let assembled = assemble(&files).unwrap();
+        assert_eq!(
+            assembled["a.rs"],
+            "fn a() {\n    one();\n    two();\n    // @@protoc_insertion_point(body)\n}\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_external_plugin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Answers every request with one file, `a.txt`, containing "hi".
+        let path = std::env::temp_dir().join(format!("protoc-gen-test-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "#!/bin/sh\ncat > /dev/null\nprintf '\\172\\013\\012\\005a.txt\\172\\002hi'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let files = run_plugin(&path, &request());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            files.unwrap(),
+            vec![GeneratedFile::new("a.txt", "hi".to_string())]
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! `protofmt`: rewrites `.proto` files in a configurable canonical style,
+//! keeping their comments, or reports which files are not formatted.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::codegen::{PrettyPrint, Printer};
+use crate::{ParseError, Parser};
+
+/// Style options. The defaults match [`PrettyPrint::pretty_print`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Indent with one tab per level instead of spaces.
+    pub use_tabs: bool,
+    /// Spaces per level, and the width of a tab when measuring lines.
+    pub indent_width: usize,
+    /// Lines are kept within this many columns where the style allows:
+    /// field options move onto their own lines and reflowed comments wrap.
+    pub max_line_width: usize,
+    /// Pads field and enum value names so their `=` line up within a block.
+    pub align_fields: bool,
+    pub sort_imports: bool,
+    /// Blank lines between groups of statements and between blocks.
+    pub blank_lines: usize,
+    /// Rewraps line comments to `max_line_width` instead of keeping their
+    /// lines as written.
+    pub reflow_comments: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            use_tabs: false,
+            indent_width: 2,
+            max_line_width: 100,
+            align_fields: false,
+            sort_imports: false,
+            blank_lines: 1,
+            reflow_comments: false,
+        }
+    }
+}
+
+impl Config {
+    /// Reads a config of `key = value` lines, with `#` comments. Keys are
+    /// the field names; missing keys keep their defaults.
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| Error::Config {
+                line: i + 1,
+                message,
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected `key = value`, got {:?}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            let flag = || {
+                value
+                    .parse::<bool>()
+                    .map_err(|_| error(format!("{} must be true or false", key)))
+            };
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| error(format!("{} must be a number", key)))
+            };
+            match key {
+                "use_tabs" => config.use_tabs = flag()?,
+                "indent_width" => config.indent_width = number()?,
+                "max_line_width" => config.max_line_width = number()?,
+                "align_fields" => config.align_fields = flag()?,
+                "sort_imports" => config.sort_imports = flag()?,
+                "blank_lines" => config.blank_lines = number()?,
+                "reflow_comments" => config.reflow_comments = flag()?,
+                _ => return Err(error(format!("unknown option {}", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ParseError),
+    Config { line: usize, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Config { line, message } => write!(f, "config line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A file whose formatting would change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: PathBuf,
+    /// A unified diff from the file as it is to the formatted file.
+    pub diff: String,
+}
+
+/// Formats the source of one file, keeping its comments.
+pub fn format_source(source: &str, config: &Config) -> Result<String, ParseError> {
+    let mut parser = Parser::new(source);
+    let file = parser.consume_file()?;
+    let mut printer = Printer::new(config.clone()).with_comments(&parser);
+    Ok(file.print(&mut printer, 0))
+}
+
+fn format_file(path: &Path, source: &str, config: &Config) -> Result<String, Error> {
+    format_source(source, config).map_err(|err| Error::Parse(path.to_path_buf(), err))
+}
+
+/// Lists the files that are not formatted, without touching them, like
+/// `protofmt --check`.
+pub fn check<P: AsRef<Path>>(paths: &[P], config: &Config) -> Result<Vec<Change>, Error> {
+    let mut changes = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        let source = read(path)?;
+        let formatted = format_file(path, &source, config)?;
+        if formatted != source {
+            changes.push(Change {
+                path: path.to_path_buf(),
+                diff: unified_diff(&path.display().to_string(), &source, &formatted),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Formats files in place, returning the ones that changed.
+pub fn rewrite<P: AsRef<Path>>(paths: &[P], config: &Config) -> Result<Vec<PathBuf>, Error> {
+    let changes = check(paths, config)?;
+    for change in &changes {
+        let formatted = format_file(&change.path, &read(&change.path)?, config)?;
+        std::fs::write(&change.path, formatted)
+            .map_err(|err| Error::Io(change.path.clone(), err))?;
+    }
+    Ok(changes.into_iter().map(|change| change.path).collect())
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))
+}
+
+/// Lines of context around each hunk, as `diff -u` uses.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// A `diff -u` style diff between two texts, labelled `a/<name>` and
+/// `b/<name>`. Empty when they are equal.
+pub fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = diff_lines(&old, &new);
+    if edits.iter().all(|edit| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    // The line each edit starts at in both texts, for hunk headers.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        positions.push((i, j));
+        match edit {
+            Edit::Keep => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", name, name);
+    let mut start = 0;
+    while let Some(first) = (start..edits.len()).find(|&k| edits[k] != Edit::Keep) {
+        // Extend the hunk while changes are within two contexts of each other.
+        let mut last = first;
+        while let Some(next) = (last + 1..edits.len()).find(|&k| edits[k] != Edit::Keep) {
+            if next - last > 2 * CONTEXT {
+                break;
+            }
+            last = next;
+        }
+        let from = first.saturating_sub(CONTEXT);
+        let to = (last + 1 + CONTEXT).min(edits.len());
+
+        let (old_start, new_start) = positions[from];
+        let (old_end, new_end) = positions[to];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for (k, edit) in edits.iter().enumerate().take(to).skip(from) {
+            let (i, j) = positions[k];
+            match edit {
+                Edit::Keep => out.push_str(&format!(" {}\n", old[i])),
+                Edit::Delete => out.push_str(&format!("-{}\n", old[i])),
+                Edit::Insert => out.push_str(&format!("+{}\n", new[j])),
+            }
+        }
+        start = to;
+    }
+    out
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// A shortest edit script by longest common subsequence, deleting before
+/// inserting within each change.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            edits.push(Edit::Keep);
+            (i, j) = (i + 1, j + 1);
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Delete);
+            i += 1;
+        } else {
+            edits.push(Edit::Insert);
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMENTED: &str = r#"// File header, detached.
+
+syntax = "proto3";
+package demo;  // the package
+
+import "b.proto";
+import "a.proto";
+
+// A person.
+message Person {  // after the brace
+  // Their name.
+  string name = 1; // trailing
+  int32 id = 2 [/* was 3 */ deprecated = true];
+
+  /* A block
+     comment. */
+  enum Kind { A = 0; B = 1; }
+  // Left before the closing brace.
+}
+
+service People {
+  rpc Get(Person) returns (Person);  // gets one
+}
+// End of file.
+"#;
+
+    #[test]
+    fn keeps_comments() {
+        let formatted = format_source(COMMENTED, &Config::default()).unwrap();
+        assert_eq!(
+            formatted,
+            r#"// File header, detached.
+
+syntax = "proto3";
+
+package demo; // the package
+
+import "b.proto";
+import "a.proto";
+
+// A person.
+message Person { // after the brace
+  // Their name.
+  string name = 1; // trailing
+  int32 id = 2 [/* was 3 */ deprecated = true];
+
+  /* A block
+     comment. */
+  enum Kind {
+    A = 0;
+    B = 1;
+  }
+
+  // Left before the closing brace.
+}
+
+service People {
+  rpc Get(Person) returns (Person); // gets one
+}
+
+// End of file.
+"#
+        );
+        assert_eq!(
+            format_source(&formatted, &Config::default()).unwrap(),
+            formatted
+        );
+    }
+
+    #[test]
+    fn style_options() {
+        let config = Config {
+            use_tabs: true,
+            max_line_width: 40,
+            align_fields: true,
+            sort_imports: true,
+            blank_lines: 0,
+            reflow_comments: true,
+            ..Config::default()
+        };
+        let source = r#"
+            import "b.proto"; import "a.proto";
+            message M {
+              // A long comment that needs wrapping at forty columns.
+              string name = 1;
+              int32 identifier = 2 [deprecated = true, (my.option) = "value"];
+            }
+        "#;
+        let formatted = format_source(source, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "import \"a.proto\";\n\
+             import \"b.proto\";\n\
+             message M {\n\
+             \t// A long comment that needs wrapping\n\
+             \t// at forty columns.\n\
+             \tstring name      = 1;\n\
+             \tint32 identifier = 2 [\n\
+             \t\tdeprecated = true,\n\
+             \t\t(my.option) = \"value\"\n\
+             \t];\n\
+             }\n"
+        );
+        assert_eq!(format_source(&formatted, &config).unwrap(), formatted);
+    }
+
+    #[test]
+    fn check_and_rewrite() {
+        let dir = std::env::temp_dir().join(format!("protofmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let messy = dir.join("messy.proto");
+        let tidy = dir.join("tidy.proto");
+        std::fs::write(&messy, "message A { int32 x=1; }\n").unwrap();
+        std::fs::write(&tidy, "message A {\n  int32 x = 1;\n}\n").unwrap();
+
+        let config = Config::default();
+        let changes = check(&[&messy, &tidy], &config).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, messy);
+        assert!(changes[0].diff.contains("-message A { int32 x=1; }\n"));
+
+        assert_eq!(
+            rewrite(&[&messy, &tidy], &config).unwrap(),
+            vec![messy.clone()]
+        );
+        assert!(check(&[&messy, &tidy], &config).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn same_descriptors() {
+        // Formatting must not change what a file means, so protoc describes
+        // the formatted file exactly as it does the original.
+        let dir = std::env::temp_dir().join(format!("protofmt-protoc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let descriptor = |include: &Path, name: &str| {
+            let out = dir.join(format!("{}.pb", name));
+            let output = std::process::Command::new("protoc")
+                .arg(format!("-I{}", include.display()))
+                .arg("-Isrc/well_known")
+                .arg(format!("--descriptor_set_out={}", out.display()))
+                .arg(name)
+                .output()?;
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            std::fs::read(out)
+        };
+        for name in [
+            "order.proto",
+            "reserved.proto",
+            "extend.proto",
+            "custom.proto",
+        ] {
+            let source = std::fs::read_to_string(Path::new("tests/descriptor").join(name)).unwrap();
+            let formatted = format_source(&source, &Config::default()).unwrap();
+            let formatted_dir = dir.join("formatted");
+            std::fs::create_dir_all(&formatted_dir).unwrap();
+            std::fs::write(formatted_dir.join(name), formatted).unwrap();
+            let expected = match descriptor(Path::new("tests/descriptor"), name) {
+                Ok(expected) => expected,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    eprintln!("skipping same_descriptors: protoc is not installed");
+                    return;
+                }
+                Err(err) => panic!("{}", err),
+            };
+            assert_eq!(
+                descriptor(&formatted_dir, name).unwrap(),
+                expected,
+                "{}",
+                name
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(
+            unified_diff("x.proto", old, new),
+            "--- a/x.proto\n+++ b/x.proto\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+        assert_eq!(unified_diff("x.proto", old, old), "");
+    }
+
+    #[test]
+    fn config_file() {
+        let config =
+            Config::parse("# style\nuse_tabs = true\nindent_width = 4 # columns\n").unwrap();
+        assert!(config.use_tabs);
+        assert_eq!(config.indent_width, 4);
+        assert!(matches!(
+            Config::parse("align = yes"),
+            Err(Error::Config { line: 1, .. })
+        ));
+    }
+}
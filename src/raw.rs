@@ -0,0 +1,419 @@
+//! Decoding protobuf bytes without a schema, like `protoc --decode_raw`,
+//! with optional field names and types from a parsed `Message`.
+
+use crate::resolver::FileSet;
+use crate::wire::{self, *};
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawField {
+    pub number: u32,
+    /// The field's name, when decoded against a schema that declares it.
+    pub name: Option<String>,
+    /// The field's declared type, used to print its value.
+    pub field_type: Option<Type>,
+    pub value: RawValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawValue {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    Message(Vec<RawField>),
+    Group(Vec<RawField>),
+    /// A packed repeated field, only recognised with a schema.
+    Packed(Vec<RawValue>),
+}
+
+/// Decodes `buf` into its fields without a schema.
+///
+/// A length-delimited field becomes a `String` if it is printable UTF-8,
+/// otherwise a `Message` if it parses as one completely, otherwise `Bytes`.
+pub fn decode_raw(buf: &[u8]) -> Result<Vec<RawField>, wire::Error> {
+    Decoder {
+        set: None,
+        root: None,
+    }
+    .decode(buf, None, RECURSION_LIMIT)
+}
+
+/// Decodes `buf` as a `message`, naming and typing the fields it declares.
+/// `set` resolves linked message types; without it, types are looked up
+/// among `message` and its nested messages. Fields missing from the schema
+/// are decoded as by [`decode_raw`].
+pub fn decode_annotated(
+    buf: &[u8],
+    message: &Message,
+    set: Option<&FileSet>,
+) -> Result<Vec<RawField>, wire::Error> {
+    Decoder {
+        set,
+        root: Some(message),
+    }
+    .decode(buf, Some(message), RECURSION_LIMIT)
+}
+
+struct Decoder<'a> {
+    set: Option<&'a FileSet>,
+    /// The message being decoded, whose nested types stand in for `set`.
+    root: Option<&'a Message>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Decodes the fields of a message or group nested `depth` levels
+    /// short of the recursion limit.
+    fn decode(
+        &self,
+        mut buf: &[u8],
+        message: Option<&Message>,
+        depth: usize,
+    ) -> Result<Vec<RawField>, wire::Error> {
+        let depth = depth
+            .checked_sub(1)
+            .ok_or(wire::Error::RecursionLimitExceeded)?;
+        let mut fields = vec![];
+        while !buf.is_empty() {
+            let (number, wire_type) = decode_key(&mut buf)?;
+            if wire_type == WireType::EndGroup {
+                return Err(wire::Error::InvalidWireType(WireType::EndGroup as u64));
+            }
+            let field = message.and_then(|m| find_field(m, number));
+            let field_type = field.map(MessageField::field_type);
+            let value = self.decode_value(number, wire_type, field, &mut buf, depth)?;
+            fields.push(RawField {
+                number,
+                name: field.map(|f| f.name().to_string()),
+                field_type,
+                value,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn decode_value(
+        &self,
+        number: u32,
+        wire_type: WireType,
+        field: Option<&MessageField>,
+        buf: &mut &[u8],
+        depth: usize,
+    ) -> Result<RawValue, wire::Error> {
+        Ok(match wire_type {
+            WireType::Varint => RawValue::Varint(decode_varint(buf)?),
+            WireType::Fixed32 => RawValue::Fixed32(decode_fixed32(buf)?),
+            WireType::Fixed64 => RawValue::Fixed64(decode_fixed64(buf)?),
+            WireType::StartGroup => RawValue::Group(self.decode_group(number, buf, depth)?),
+            WireType::EndGroup => unreachable!("rejected by the caller"),
+            WireType::LengthDelimited => {
+                let bytes = decode_length_delimited(buf)?;
+                match field.map(MessageField::field_type) {
+                    Some(t) => self.decode_typed(bytes, &t, depth),
+                    None => self.guess(bytes, depth),
+                }
+            }
+        })
+    }
+
+    fn decode_group(
+        &self,
+        number: u32,
+        buf: &mut &[u8],
+        depth: usize,
+    ) -> Result<Vec<RawField>, wire::Error> {
+        let depth = depth
+            .checked_sub(1)
+            .ok_or(wire::Error::RecursionLimitExceeded)?;
+        let mut fields = vec![];
+        loop {
+            let (field_number, wire_type) = decode_key(buf)?;
+            if wire_type == WireType::EndGroup {
+                if field_number != number {
+                    return Err(wire::Error::InvalidWireType(WireType::EndGroup as u64));
+                }
+                return Ok(fields);
+            }
+            fields.push(RawField {
+                number: field_number,
+                name: None,
+                field_type: None,
+                value: self.decode_value(field_number, wire_type, None, buf, depth)?,
+            });
+        }
+    }
+
+    /// Decodes a length-delimited value the way the schema says it is
+    /// encoded, falling back to guessing when it does not fit.
+    fn decode_typed(&self, bytes: &[u8], t: &Type, depth: usize) -> RawValue {
+        let value = match t {
+            Type::String => std::str::from_utf8(bytes)
+                .ok()
+                .map(|s| RawValue::String(s.to_string())),
+            Type::Bytes => Some(RawValue::Bytes(bytes.to_vec())),
+            Type::Message(name) | Type::Named(name) => self
+                .find_message(name)
+                .and_then(|m| self.decode(bytes, Some(m), depth).ok())
+                .map(RawValue::Message),
+            Type::Map(key, value) => self
+                .decode(bytes, Some(&map_entry(key, value)), depth)
+                .ok()
+                .map(RawValue::Message),
+            Type::Double | Type::Fixed64 | Type::Sfixed64 => {
+                decode_packed_fixed64(bytes).ok().map(|values| {
+                    RawValue::Packed(values.into_iter().map(RawValue::Fixed64).collect())
+                })
+            }
+            Type::Float | Type::Fixed32 | Type::Sfixed32 => {
+                decode_packed_fixed32(bytes).ok().map(|values| {
+                    RawValue::Packed(values.into_iter().map(RawValue::Fixed32).collect())
+                })
+            }
+            _ => decode_packed_varints(bytes)
+                .ok()
+                .map(|values| RawValue::Packed(values.into_iter().map(RawValue::Varint).collect())),
+        };
+        value.unwrap_or_else(|| self.guess(bytes, depth))
+    }
+
+    /// Guesses what a length-delimited value holds; past the recursion
+    /// limit, nested messages are left as bytes.
+    fn guess(&self, bytes: &[u8], depth: usize) -> RawValue {
+        if let Ok(s) = std::str::from_utf8(bytes) {
+            if s.chars()
+                .all(|c| !c.is_control() || c == '\n' || c == '\t' || c == '\r')
+            {
+                return RawValue::String(s.to_string());
+            }
+        }
+        match self.decode(bytes, None, depth) {
+            Ok(fields) => RawValue::Message(fields),
+            Err(_) => RawValue::Bytes(bytes.to_vec()),
+        }
+    }
+
+    fn find_message(&self, name: &str) -> Option<&'a Message> {
+        if let Some(set) = self.set {
+            if let Some(message) = set.find_message(name) {
+                return Some(message);
+            }
+        }
+        let simple = name.rsplit('.').next().unwrap_or(name);
+        self.root.and_then(|m| find_nested(m, simple))
+    }
+}
+
+fn find_nested<'a>(message: &'a Message, name: &str) -> Option<&'a Message> {
+    if message.name == name {
+        return Some(message);
+    }
+    message.messages.iter().find_map(|m| find_nested(m, name))
+}
+
+fn find_field(message: &Message, number: u32) -> Option<&MessageField> {
+    message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|o| o.fields.iter()))
+        .find(|f| f.position() == number)
+}
+
+fn map_entry(key: &Type, value: &Type) -> Message {
+    Message {
+        name: "Entry".to_string(),
+        fields: vec![
+            MessageField::new(None, key.clone(), "key".to_string(), 1),
+            MessageField::new(None, value.clone(), "value".to_string(), 2),
+        ],
+        ..Default::default()
+    }
+}
+
+/// Prints decoded fields in the style of `protoc --decode_raw`. Fields
+/// with a name are written `name (number): value`, and scalar values are
+/// shown as their declared type.
+pub fn to_text(fields: &[RawField]) -> String {
+    let mut s = String::new();
+    write_fields(fields, 0, &mut s);
+    s
+}
+
+fn write_fields(fields: &[RawField], depth: usize, s: &mut String) {
+    for field in fields {
+        let label = match &field.name {
+            Some(name) => format!("{} ({})", name, field.number),
+            None => field.number.to_string(),
+        };
+        let t = field.field_type.as_ref();
+        match &field.value {
+            RawValue::Message(nested) | RawValue::Group(nested) => {
+                s.push_str(&format!("{}{} {{\n", "  ".repeat(depth), label));
+                write_fields(nested, depth + 1, s);
+                s.push_str(&format!("{}}}\n", "  ".repeat(depth)));
+            }
+            RawValue::Packed(values) => {
+                for value in values {
+                    s.push_str(&format!(
+                        "{}{}: {}\n",
+                        "  ".repeat(depth),
+                        label,
+                        scalar_text(value, t)
+                    ));
+                }
+            }
+            value => s.push_str(&format!(
+                "{}{}: {}\n",
+                "  ".repeat(depth),
+                label,
+                scalar_text(value, t)
+            )),
+        }
+    }
+}
+
+fn scalar_text(value: &RawValue, t: Option<&Type>) -> String {
+    match (value, t) {
+        (RawValue::Varint(v), Some(Type::Int32)) => (*v as i32).to_string(),
+        (RawValue::Varint(v), Some(Type::Int64)) => (*v as i64).to_string(),
+        (RawValue::Varint(v), Some(Type::Uint32)) => (*v as u32).to_string(),
+        (RawValue::Varint(v), Some(Type::Sint32)) => decode_zigzag32(*v as u32).to_string(),
+        (RawValue::Varint(v), Some(Type::Sint64)) => decode_zigzag64(*v).to_string(),
+        (RawValue::Varint(v), Some(Type::Bool)) => (*v != 0).to_string(),
+        (RawValue::Varint(v), Some(Type::Enum(_))) => (*v as i32).to_string(),
+        (RawValue::Varint(v), _) => v.to_string(),
+        (RawValue::Fixed32(v), Some(Type::Float)) => f32::from_bits(*v).to_string(),
+        (RawValue::Fixed32(v), Some(Type::Fixed32)) => v.to_string(),
+        (RawValue::Fixed32(v), Some(Type::Sfixed32)) => (*v as i32).to_string(),
+        (RawValue::Fixed32(v), _) => format!("0x{:08x}", v),
+        (RawValue::Fixed64(v), Some(Type::Double)) => f64::from_bits(*v).to_string(),
+        (RawValue::Fixed64(v), Some(Type::Fixed64)) => v.to_string(),
+        (RawValue::Fixed64(v), Some(Type::Sfixed64)) => (*v as i64).to_string(),
+        (RawValue::Fixed64(v), _) => format!("0x{:016x}", v),
+        (RawValue::String(s), _) => format!("\"{}\"", escape(s.as_bytes())),
+        (RawValue::Bytes(b), _) => format!("\"{}\"", escape(b)),
+        _ => String::new(),
+    }
+}
+
+/// C-style escaping, as `protoc` uses for strings and bytes.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    let text = std::str::from_utf8(bytes).ok();
+    match text {
+        Some(text) => {
+            for c in text.chars() {
+                match c {
+                    '\n' => s.push_str("\\n"),
+                    '\r' => s.push_str("\\r"),
+                    '\t' => s.push_str("\\t"),
+                    '"' => s.push_str("\\\""),
+                    '\'' => s.push_str("\\'"),
+                    '\\' => s.push_str("\\\\"),
+                    c if c.is_control() => {
+                        let mut buf = [0; 4];
+                        for byte in c.encode_utf8(&mut buf).bytes() {
+                            s.push_str(&format!("\\{:03o}", byte));
+                        }
+                    }
+                    c => s.push(c),
+                }
+            }
+        }
+        None => {
+            for byte in bytes {
+                match byte {
+                    b'\n' => s.push_str("\\n"),
+                    b'\r' => s.push_str("\\r"),
+                    b'\t' => s.push_str("\\t"),
+                    b'"' => s.push_str("\\\""),
+                    b'\'' => s.push_str("\\'"),
+                    b'\\' => s.push_str("\\\\"),
+                    0x20..=0x7e => s.push(*byte as char),
+                    _ => s.push_str(&format!("\\{:03o}", byte)),
+                }
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Field 1 = 150, field 2 = "testing", field 3 = { 1: 1 }, field 4 = the
+    // packed varints 3, 270, 86942, and field 5 = the fixed32 1.
+    const BYTES: &[u8] = &[
+        0x08, 0x96, 0x01, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g', 0x1a, 0x02, 0x08,
+        0x01, 0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05, 0x2d, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn decodes_without_schema() {
+        let fields = decode_raw(BYTES).unwrap();
+
+        assert_eq!(fields[0].value, RawValue::Varint(150));
+        assert_eq!(fields[1].value, RawValue::String("testing".to_string()));
+        assert_eq!(
+            fields[2].value,
+            RawValue::Message(vec![RawField {
+                number: 1,
+                name: None,
+                field_type: None,
+                value: RawValue::Varint(1),
+            }])
+        );
+        assert_eq!(
+            fields[3].value,
+            RawValue::Bytes(vec![0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05])
+        );
+        assert_eq!(
+            to_text(&fields),
+            "1: 150\n2: \"testing\"\n3 {\n  1: 1\n}\n4: \"\\003\\216\\002\\236\\247\\005\"\n5: 0x00000001\n"
+        );
+    }
+
+    #[test]
+    fn annotates_with_schema() {
+        let message = Parser::new(
+            "message Test {
+                int32 a = 1;
+                string b = 2;
+                Inner c = 3;
+                repeated sint32 d = 4;
+                message Inner { bool flag = 1; }
+            }",
+        )
+        .consume_message()
+        .unwrap();
+
+        let fields = decode_annotated(BYTES, &message, None).unwrap();
+        assert_eq!(
+            to_text(&fields),
+            "a (1): 150\nb (2): \"testing\"\nc (3) {\n  flag (1): true\n}\nd (4): -2\nd (4): 135\nd (4): 43471\n5: 0x00000001\n"
+        );
+    }
+
+    #[test]
+    fn groups_and_errors() {
+        let fields = decode_raw(&[0x0b, 0x10, 0x01, 0x0c]).unwrap();
+        assert_eq!(to_text(&fields), "1 {\n  2: 1\n}\n");
+
+        assert!(decode_raw(&[0x0b, 0x10, 0x01, 0x14]).is_err());
+        assert!(decode_raw(&[0x12, 0x05, b'a']).is_err());
+
+        assert!(matches!(
+            decode_raw(&vec![0x0b; 200_000]),
+            Err(wire::Error::RecursionLimitExceeded)
+        ));
+        let mut nested = vec![0x08, 0x01];
+        for _ in 0..1000 {
+            let mut outer = vec![0x0a];
+            encode_varint(nested.len() as u64, &mut outer);
+            outer.extend(nested);
+            nested = outer;
+        }
+        assert!(decode_raw(&nested).is_ok());
+    }
+}
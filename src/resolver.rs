@@ -0,0 +1,988 @@
+//! Loading `.proto` files and their imports from include paths, and linking
+//! type references to the fully qualified messages and enums they name.
+
+use crate::*;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// No include path contains the file.
+    NotFound(String),
+    Io(String, std::io::Error),
+    Parse(String, ParseError),
+    ImportCycle(String),
+    /// A field or method refers to a type that no loaded file defines.
+    UnresolvedType {
+        file: String,
+        name: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(name) => write!(f, "{}: file not found in include paths", name),
+            Error::Io(name, err) => write!(f, "{}: {}", name, err),
+            Error::Parse(name, err) => write!(f, "{}: {}", name, err),
+            Error::ImportCycle(name) => write!(f, "{}: import cycle", name),
+            Error::UnresolvedType { file, name } => {
+                write!(f, "{}: \"{}\" is not defined", file, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symbol {
+    Package,
+    Message,
+    Enum,
+}
+
+/// A set of linked files, with every file appearing after its imports.
+#[derive(Debug, Clone, Default)]
+pub struct FileSet {
+    pub files: Vec<ProtoFile>,
+    /// The parser's source locations for each file, keyed by file name.
+    pub locations: BTreeMap<String, Vec<Location>>,
+}
+
+impl FileSet {
+    /// Links already parsed files. Imports must come before the files that
+    /// import them.
+    pub fn new(mut files: Vec<ProtoFile>) -> Result<Self, Error> {
+        link(&mut files)?;
+        Ok(FileSet {
+            files,
+            locations: BTreeMap::new(),
+        })
+    }
+
+    pub fn file(&self, name: &str) -> Option<&ProtoFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Finds a message by fully qualified name, with or without the leading
+    /// dot.
+    pub fn find_message(&self, name: &str) -> Option<&Message> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        self.files.iter().find_map(|file| {
+            let rest = strip_package(name, file.package.as_deref())?;
+            find_nested_message(&file.messages, rest)
+        })
+    }
+
+    /// Finds the file that defines a message, given its fully qualified
+    /// name.
+    pub fn find_message_file(&self, name: &str) -> Option<&ProtoFile> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        self.files.iter().find(|file| {
+            strip_package(name, file.package.as_deref())
+                .and_then(|rest| find_nested_message(&file.messages, rest))
+                .is_some()
+        })
+    }
+
+    pub fn find_enum(&self, name: &str) -> Option<&Enum> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        self.files.iter().find_map(|file| {
+            let rest = strip_package(name, file.package.as_deref())?;
+            match rest.rsplit_once('.') {
+                Some((parent, enum_name)) => find_nested_message(&file.messages, parent)?
+                    .enums
+                    .iter()
+                    .find(|e| e.name == enum_name),
+                None => file.enums.iter().find(|e| e.name == rest),
+            }
+        })
+    }
+}
+
+/// A field declared in an `extend` block.
+#[derive(Debug, Clone)]
+pub struct ExtensionField<'a> {
+    /// The extension's fully qualified name, with a leading dot.
+    pub name: String,
+    /// The extended message, fully qualified with a leading dot.
+    pub extendee: &'a str,
+    pub field: &'a MessageField,
+    pub options: Option<&'a Vec<ProtoOption>>,
+}
+
+impl FileSet {
+    /// Every extension declared in the set, in declaration order.
+    pub fn extensions(&self) -> Vec<ExtensionField<'_>> {
+        let mut ret = vec![];
+        for file in &self.files {
+            let scope = file.package.clone().unwrap_or_default();
+            collect_extensions(&scope, &file.extends, &file.messages, &mut ret);
+        }
+        ret
+    }
+
+    /// Finds an extension by fully qualified name, with or without the
+    /// leading dot.
+    pub fn find_extension(&self, name: &str) -> Option<ExtensionField<'_>> {
+        let name = format!(".{}", name.strip_prefix('.').unwrap_or(name));
+        self.extensions().into_iter().find(|e| e.name == name)
+    }
+
+    /// Finds the extension a custom option names, searching from `scope`
+    /// outwards the way `protoc` resolves relative names.
+    pub fn resolve_extension(&self, scope: &str, name: &str) -> Option<ExtensionField<'_>> {
+        if let Some(name) = name.strip_prefix('.') {
+            return self.find_extension(name);
+        }
+        let mut scope = scope;
+        loop {
+            if let Some(extension) = self.find_extension(&join(scope, name)) {
+                return Some(extension);
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope.rsplit_once('.').map_or("", |(parent, _)| parent);
+        }
+    }
+
+    /// The extensions of the message with fully qualified name `extendee`.
+    pub fn extensions_of(&self, extendee: &str) -> Vec<ExtensionField<'_>> {
+        let extendee = format!(".{}", extendee.strip_prefix('.').unwrap_or(extendee));
+        self.extensions()
+            .into_iter()
+            .filter(|e| e.extendee == extendee)
+            .collect()
+    }
+}
+
+impl FileSet {
+    /// Checks the rules `protoc` enforces beyond resolving names: a known
+    /// syntax, unique symbols, field numbers in range, unique and clear of
+    /// reserved and extension ranges, unique names, labels and defaults the
+    /// syntax allows, ranges in order, non-empty oneofs and enums, enum
+    /// values, and custom options that name a known extension of the right
+    /// options message. Returns one message per problem, prefixed with the
+    /// file name.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut symbols = BTreeMap::new();
+        for file in &self.files {
+            let proto3 = file.syntax.as_deref() == Some("proto3");
+            let mut report = |message: String| problems.push(format!("{}: {}", file.name, message));
+            if let Some(syntax) = file.syntax.as_deref().filter(|s| !SYNTAXES.contains(s)) {
+                report(format!(
+                    "syntax \"{}\" is not supported; use \"proto2\" or \"proto3\"",
+                    syntax
+                ));
+            }
+            let scope = file.package.clone().unwrap_or_default();
+            let mut define = |name: String| {
+                if let Some(other) = symbols.insert(name.clone(), &file.name) {
+                    let place = match *other == file.name {
+                        true => String::new(),
+                        false => format!(" in {}", other),
+                    };
+                    report(format!("\"{}\" is already defined{}", name, place));
+                }
+            };
+            define_symbols(
+                &scope,
+                &file.messages,
+                &file.enums,
+                &file.extends,
+                &mut define,
+            );
+            for service in &file.services {
+                define(join(&scope, &service.name));
+            }
+
+            for message in &file.messages {
+                validate_message(&scope, message, proto3, &mut report);
+            }
+            for e in &file.enums {
+                validate_enum(&scope, e, proto3, &mut report);
+            }
+            validate_extends(&scope, &file.extends, proto3, &mut report);
+
+            let mut check = |scope: &str, options_type: &str, options: &[ProtoOption]| {
+                self.validate_options(scope, options_type, options, &mut report)
+            };
+            check(&scope, "FileOptions", &file.options);
+            for message in &file.messages {
+                message_options(&scope, message, &mut check);
+            }
+            for e in &file.enums {
+                enum_options(&scope, e, &mut check);
+            }
+            extend_options(&scope, &file.extends, &mut check);
+            for service in &file.services {
+                let name = join(&scope, &service.name);
+                check(&name, "ServiceOptions", &service.options);
+                for method in &service.methods {
+                    check(&name, "MethodOptions", &method.options);
+                }
+            }
+        }
+        problems
+    }
+
+    /// Reports custom options in `options` that name no extension, or an
+    /// extension of a message other than `google.protobuf.<options_type>`.
+    fn validate_options(
+        &self,
+        scope: &str,
+        options_type: &str,
+        options: &[ProtoOption],
+        report: &mut dyn FnMut(String),
+    ) {
+        let place = match scope.is_empty() {
+            true => String::new(),
+            false => format!("{}: ", scope),
+        };
+        for option in options {
+            let Some((name, _)) = option.extension() else {
+                continue;
+            };
+            match self.resolve_extension(scope, name) {
+                None => report(format!("{}option ({}) is not defined", place, name)),
+                Some(extension)
+                    if extension.extendee != format!(".google.protobuf.{}", options_type) =>
+                {
+                    report(format!(
+                        "{}option ({}) extends {}, not google.protobuf.{}",
+                        place,
+                        name,
+                        extension.extendee.trim_start_matches('.'),
+                        options_type
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Calls `define` with the fully qualified name of every message, enum,
+/// enum value and extension in a scope. Enum values are siblings of their
+/// enum, as in C++.
+fn define_symbols(
+    scope: &str,
+    messages: &[Message],
+    enums: &[Enum],
+    extends: &[Extend],
+    define: &mut dyn FnMut(String),
+) {
+    for extend in extends {
+        for field in &extend.fields {
+            define(join(scope, field.name()));
+        }
+    }
+    for e in enums {
+        define(join(scope, &e.name));
+        for value in &e.fields {
+            define(join(scope, &value.name));
+        }
+    }
+    for message in messages {
+        let name = join(scope, &message.name);
+        define(name.clone());
+        define_symbols(
+            &name,
+            &message.messages,
+            &message.enums,
+            &message.extends,
+            define,
+        );
+    }
+}
+
+type OptionsCheck<'a> = dyn FnMut(&str, &str, &[ProtoOption]) + 'a;
+
+fn message_options(scope: &str, message: &Message, check: &mut OptionsCheck) {
+    let name = join(scope, &message.name);
+    check(&name, "MessageOptions", &message.options);
+    for options in message.field_options.values() {
+        check(&name, "FieldOptions", options);
+    }
+    for oneof in &message.oneofs {
+        check(&name, "OneofOptions", &oneof.options);
+        for options in oneof.field_options.values() {
+            check(&name, "FieldOptions", options);
+        }
+    }
+    for options in message.extension_range_options.values() {
+        check(&name, "ExtensionRangeOptions", options);
+    }
+    extend_options(&name, &message.extends, check);
+    for nested in &message.messages {
+        message_options(&name, nested, check);
+    }
+    for e in &message.enums {
+        enum_options(&name, e, check);
+    }
+}
+
+fn enum_options(scope: &str, e: &Enum, check: &mut OptionsCheck) {
+    check(scope, "EnumOptions", &e.options);
+    for options in e.value_options.values() {
+        check(scope, "EnumValueOptions", options);
+    }
+}
+
+fn extend_options(scope: &str, extends: &[Extend], check: &mut OptionsCheck) {
+    for extend in extends {
+        for options in extend.field_options.values() {
+            check(scope, "FieldOptions", options);
+        }
+    }
+}
+
+/// The `syntax` values the parser understands.
+const SYNTAXES: [&str; 2] = ["proto2", "proto3"];
+
+/// Field numbers `protoc` keeps for its own use.
+const IMPLEMENTATION_RESERVED: std::ops::RangeInclusive<u32> = 19_000..=19_999;
+
+fn in_ranges(ranges: &[(u32, u32)], number: u32) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&number))
+}
+
+/// Reports ranges written backwards, such as `reserved 5 to 1`.
+fn validate_ranges(name: &str, what: &str, ranges: &[(u32, u32)], report: &mut dyn FnMut(String)) {
+    for (start, end) in ranges.iter().filter(|(start, end)| start > end) {
+        report(format!(
+            "{}: {} range {} to {} ends before it starts",
+            name, what, start, end
+        ));
+    }
+}
+
+/// Reports labels and defaults the file's syntax does not allow: proto2
+/// fields outside a oneof need a label, and proto3 has neither `required`
+/// nor `default`.
+fn validate_label(
+    name: &str,
+    field: &MessageField,
+    options: Option<&Vec<ProtoOption>>,
+    in_oneof: bool,
+    proto3: bool,
+    report: &mut dyn FnMut(String),
+) {
+    let field_name = field.name();
+    let unlabeled = field.frequency().is_none() && !matches!(field, MessageField::Map(..));
+    if !proto3 && unlabeled && !in_oneof {
+        report(format!(
+            "{}.{}: fields need a label (optional, required or repeated) in proto2",
+            name, field_name
+        ));
+    }
+    if proto3 && field.frequency() == Some(&Frequency::Required) {
+        report(format!(
+            "{}.{}: required fields are not allowed in proto3",
+            name, field_name
+        ));
+    }
+    let has_default = options.is_some_and(|options| options.iter().any(|o| o.name == "default"));
+    if proto3 && has_default {
+        report(format!(
+            "{}.{}: default values are not allowed in proto3",
+            name, field_name
+        ));
+    }
+}
+
+fn validate_extends(scope: &str, extends: &[Extend], proto3: bool, report: &mut dyn FnMut(String)) {
+    for extend in extends {
+        for field in &extend.fields {
+            let options = extend.field_options.get(&field.position());
+            validate_label(scope, field, options, false, proto3, report);
+        }
+    }
+}
+
+fn validate_message(scope: &str, message: &Message, proto3: bool, report: &mut dyn FnMut(String)) {
+    let name = join(scope, &message.name);
+    validate_ranges(&name, "reserved", &message.reserved_ranges, report);
+    validate_ranges(&name, "extension", &message.extension_ranges, report);
+    for field in &message.fields {
+        let options = message.field_options.get(&field.position());
+        validate_label(&name, field, options, false, proto3, report);
+    }
+    for oneof in &message.oneofs {
+        if oneof.fields.is_empty() {
+            report(format!("{}.{}: oneof has no fields", name, oneof.name));
+        }
+        for field in &oneof.fields {
+            let options = oneof.field_options.get(&field.position());
+            validate_label(&name, field, options, true, proto3, report);
+        }
+    }
+    let fields = message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| &oneof.fields));
+
+    let mut numbers = BTreeMap::new();
+    let mut names = BTreeSet::new();
+    for field in fields {
+        let (field_name, number) = (field.name(), field.position());
+        if number == 0 || number > Parser::MAX_FIELD_NUMBER {
+            report(format!(
+                "{}.{}: number {} is out of range",
+                name, field_name, number
+            ));
+        } else if IMPLEMENTATION_RESERVED.contains(&number) {
+            report(format!(
+                "{}.{}: numbers 19000 to 19999 are reserved for the protobuf implementation",
+                name, field_name
+            ));
+        }
+        if let Some(other) = numbers.insert(number, field_name) {
+            report(format!(
+                "{}.{}: number {} is already used by \"{}\"",
+                name, field_name, number, other
+            ));
+        }
+        if !names.insert(field_name) {
+            report(format!(
+                "{}.{}: field name is already used",
+                name, field_name
+            ));
+        }
+        if in_ranges(&message.reserved_ranges, number) {
+            report(format!(
+                "{}.{}: number {} is reserved",
+                name, field_name, number
+            ));
+        }
+        if message.reserved_names.iter().any(|n| n == field_name) {
+            report(format!("{}.{}: name is reserved", name, field_name));
+        }
+        if in_ranges(&message.extension_ranges, number) {
+            report(format!(
+                "{}.{}: number {} is in an extension range",
+                name, field_name, number
+            ));
+        }
+    }
+
+    for nested in &message.messages {
+        validate_message(&name, nested, proto3, report);
+    }
+    for e in &message.enums {
+        validate_enum(&name, e, proto3, report);
+    }
+}
+
+fn validate_enum(scope: &str, e: &Enum, proto3: bool, report: &mut dyn FnMut(String)) {
+    let name = join(scope, &e.name);
+    let allow_alias = e
+        .options
+        .iter()
+        .any(|option| option.name == "allow_alias" && option.value == Constant::Bool(true));
+    validate_ranges(&name, "reserved", &e.reserved_ranges, report);
+    if e.fields.is_empty() {
+        report(format!("{}: enum has no values", name));
+    }
+    if proto3 && e.fields.first().is_some_and(|first| first.position != 0) {
+        report(format!("{}: the first value must be zero in proto3", name));
+    }
+
+    let mut numbers = BTreeMap::new();
+    let mut names = BTreeSet::new();
+    for value in &e.fields {
+        if let Some(other) = numbers.insert(value.position, &value.name) {
+            if !allow_alias {
+                report(format!(
+                    "{}.{}: number {} is already used by \"{}\"; set allow_alias to alias it",
+                    name, value.name, value.position, other
+                ));
+            }
+        }
+        if !names.insert(&value.name) {
+            report(format!(
+                "{}.{}: value name is already used",
+                name, value.name
+            ));
+        }
+        if in_ranges(&e.reserved_ranges, value.position) {
+            report(format!(
+                "{}.{}: number {} is reserved",
+                name, value.name, value.position
+            ));
+        }
+        if e.reserved_names.contains(&value.name) {
+            report(format!("{}.{}: name is reserved", name, value.name));
+        }
+    }
+}
+
+fn collect_extensions<'a>(
+    scope: &str,
+    extends: &'a [Extend],
+    messages: &'a [Message],
+    ret: &mut Vec<ExtensionField<'a>>,
+) {
+    for extend in extends {
+        for field in &extend.fields {
+            ret.push(ExtensionField {
+                name: format!(".{}", join(scope, field.name())),
+                extendee: &extend.extendee,
+                field,
+                options: extend.field_options.get(&field.position()),
+            });
+        }
+    }
+    for message in messages {
+        let scope = join(scope, &message.name);
+        collect_extensions(&scope, &message.extends, &message.messages, ret);
+    }
+}
+
+fn strip_package<'a>(name: &'a str, package: Option<&str>) -> Option<&'a str> {
+    match package {
+        Some(package) => name.strip_prefix(package)?.strip_prefix('.'),
+        None => Some(name),
+    }
+}
+
+fn find_nested_message<'a>(messages: &'a [Message], name: &str) -> Option<&'a Message> {
+    let (first, rest) = match name.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (name, None),
+    };
+    let message = messages.iter().find(|m| m.name == first)?;
+    match rest {
+        Some(rest) => find_nested_message(&message.messages, rest),
+        None => Some(message),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    include_paths: Vec<PathBuf>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    /// Adds a directory to search for files and their imports, like
+    /// `protoc -I`. Files not found in any directory fall back to the
+    /// bundled well-known types.
+    pub fn include(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    /// Loads and links the named files along with everything they import.
+    pub fn load(&self, names: &[&str]) -> Result<FileSet, Error> {
+        let mut set = FileSet::default();
+        let mut in_progress = BTreeSet::new();
+        for name in names {
+            self.load_file(name, &mut set, &mut in_progress)?;
+        }
+        link(&mut set.files)?;
+        Ok(set)
+    }
+
+    fn load_file(
+        &self,
+        name: &str,
+        set: &mut FileSet,
+        in_progress: &mut BTreeSet<String>,
+    ) -> Result<(), Error> {
+        if set.file(name).is_some() {
+            return Ok(());
+        }
+        if !in_progress.insert(name.to_string()) {
+            return Err(Error::ImportCycle(name.to_string()));
+        }
+
+        let source = self.read(name)?;
+        let mut parser = Parser::new(&source);
+        let mut file = parser
+            .consume_file()
+            .map_err(|err| Error::Parse(name.to_string(), err))?;
+        file.name = name.to_string();
+
+        for import in &file.imports {
+            self.load_file(&import.path, set, in_progress)?;
+        }
+
+        in_progress.remove(name);
+        set.locations
+            .insert(name.to_string(), parser.locations().to_vec());
+        set.files.push(file);
+        Ok(())
+    }
+
+    /// The directories searched, in order.
+    pub fn include_paths(&self) -> &[PathBuf] {
+        &self.include_paths
+    }
+
+    /// The path `name` is read from, or `None` if it is not in any
+    /// include directory.
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        self.include_paths
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    fn read(&self, name: &str) -> Result<String, Error> {
+        if let Some(path) = self.find(name) {
+            return std::fs::read_to_string(&path).map_err(|err| Error::Io(name.to_string(), err));
+        }
+        match well_known::source(name) {
+            Some(source) => Ok(source.to_string()),
+            None => Err(Error::NotFound(name.to_string())),
+        }
+    }
+}
+
+/// Replaces every `Type::Named` reference in `files` with the fully
+/// qualified message or enum it resolves to, following protobuf scoping
+/// rules: the innermost enclosing scope is searched first.
+pub fn link(files: &mut [ProtoFile]) -> Result<(), Error> {
+    let mut symbols = BTreeMap::new();
+    for file in files.iter() {
+        let package = file.package.clone().unwrap_or_default();
+        let mut prefix = String::new();
+        for part in package.split('.').filter(|part| !part.is_empty()) {
+            prefix = join(&prefix, part);
+            symbols.insert(prefix.clone(), Symbol::Package);
+        }
+        collect_symbols(&package, &file.messages, &file.enums, &mut symbols);
+    }
+
+    for file in files.iter_mut() {
+        let scope = file.package.clone().unwrap_or_default();
+        let linker = Linker {
+            symbols: &symbols,
+            file: file.name.clone(),
+        };
+        for message in file.messages.iter_mut() {
+            linker.link_message(message, &scope)?;
+        }
+        for extend in file.extends.iter_mut() {
+            linker.link_extend(extend, &scope)?;
+        }
+        for service in file.services.iter_mut() {
+            for method in service.methods.iter_mut() {
+                method.input_type = linker.resolve_name(&method.input_type, &scope)?.0;
+                method.output_type = linker.resolve_name(&method.output_type, &scope)?.0;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn join(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn collect_symbols(
+    scope: &str,
+    messages: &[Message],
+    enums: &[Enum],
+    symbols: &mut BTreeMap<String, Symbol>,
+) {
+    for e in enums {
+        symbols.insert(join(scope, &e.name), Symbol::Enum);
+    }
+    for message in messages {
+        let name = join(scope, &message.name);
+        symbols.insert(name.clone(), Symbol::Message);
+        collect_symbols(&name, &message.messages, &message.enums, symbols);
+        for oneof in &message.oneofs {
+            collect_symbols(&name, &oneof.messages, &oneof.enums, symbols);
+        }
+    }
+}
+
+struct Linker<'a> {
+    symbols: &'a BTreeMap<String, Symbol>,
+    file: String,
+}
+
+impl Linker<'_> {
+    fn link_message(&self, message: &mut Message, scope: &str) -> Result<(), Error> {
+        let scope = join(scope, &message.name);
+        self.link_fields(&mut message.fields, &scope)?;
+        for nested in message.messages.iter_mut() {
+            self.link_message(nested, &scope)?;
+        }
+        for oneof in message.oneofs.iter_mut() {
+            self.link_oneof(oneof, &scope)?;
+        }
+        for extend in message.extends.iter_mut() {
+            self.link_extend(extend, &scope)?;
+        }
+        Ok(())
+    }
+
+    fn link_extend(&self, extend: &mut Extend, scope: &str) -> Result<(), Error> {
+        extend.extendee = self.resolve_name(&extend.extendee, scope)?.0;
+        self.link_fields(&mut extend.fields, scope)
+    }
+
+    fn link_oneof(&self, oneof: &mut Oneof, scope: &str) -> Result<(), Error> {
+        self.link_fields(&mut oneof.fields, scope)?;
+        for nested in oneof.messages.iter_mut() {
+            self.link_message(nested, scope)?;
+        }
+        for nested in oneof.oneofs.iter_mut() {
+            self.link_oneof(nested, scope)?;
+        }
+        Ok(())
+    }
+
+    fn link_fields(&self, fields: &mut [MessageField], scope: &str) -> Result<(), Error> {
+        for field in fields.iter_mut() {
+            let t = self.link_type(field.field_type(), scope)?;
+            *field = field.with_type(t);
+        }
+        Ok(())
+    }
+
+    fn link_type(&self, t: Type, scope: &str) -> Result<Type, Error> {
+        Ok(match t {
+            Type::Named(name) => match self.resolve_name(&name, scope)? {
+                (name, Symbol::Enum) => Type::Enum(name),
+                (name, _) => Type::Message(name),
+            },
+            // Already qualified, as in decoded descriptors, but it must still
+            // name a type in the set.
+            Type::Message(name) | Type::Enum(name) => match self.resolve_name(&name, scope)? {
+                (name, Symbol::Enum) => Type::Enum(name),
+                (name, _) => Type::Message(name),
+            },
+            Type::Map(key, value) => Type::Map(key, Box::new(self.link_type(*value, scope)?)),
+            t => t,
+        })
+    }
+
+    fn resolve_name(&self, name: &str, scope: &str) -> Result<(String, Symbol), Error> {
+        self.resolve(name, scope)
+            .ok_or_else(|| Error::UnresolvedType {
+                file: self.file.clone(),
+                name: name.to_string(),
+            })
+    }
+
+    fn resolve(&self, name: &str, scope: &str) -> Option<(String, Symbol)> {
+        if let Some(full) = name.strip_prefix('.') {
+            return match self.symbols.get(full) {
+                Some(Symbol::Package) | None => None,
+                Some(symbol) => Some((name.to_string(), *symbol)),
+            };
+        }
+
+        let first = name.split('.').next().unwrap_or(name);
+        let mut scope = scope.to_string();
+        loop {
+            if self.symbols.contains_key(&join(&scope, first)) {
+                let candidate = join(&scope, name);
+                match self.symbols.get(&candidate) {
+                    Some(Symbol::Package) | None => {}
+                    Some(symbol) => return Some((format!(".{}", candidate), *symbol)),
+                }
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = match scope.rsplit_once('.') {
+                Some((parent, _)) => parent.to_string(),
+                None => String::new(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str, input: &str) -> ProtoFile {
+        let mut file = Parser::new(input).consume_file().unwrap();
+        file.name = name.to_string();
+        file
+    }
+
+    #[test]
+    fn links_nested_and_imported_types() {
+        let dependency = parse("b.proto", "package b; message Thing { bytes data = 1; }");
+        let file = parse(
+            "a.proto",
+            "
+            package a;
+            import \"b.proto\";
+
+            message Person {
+                message Phone { int64 n = 1; }
+                enum Kind { ONE = 1; }
+                Phone phone = 1;
+                b.Thing thing = 2;
+                map<string, Kind> kinds = 3;
+            }
+
+            service S {
+                rpc Get (Person) returns (stream .b.Thing);
+            }",
+        );
+
+        let set = FileSet::new(vec![dependency, file]).unwrap();
+        let person = set.find_message("a.Person").unwrap();
+
+        assert_eq!(
+            person.fields,
+            vec![
+                MessageField::Message(None, ".a.Person.Phone".to_string(), "phone".to_string(), 1),
+                MessageField::Message(None, ".b.Thing".to_string(), "thing".to_string(), 2),
+                MessageField::Map(
+                    Type::String,
+                    Type::Enum(".a.Person.Kind".to_string()),
+                    "kinds".to_string(),
+                    3
+                ),
+            ]
+        );
+        let method = &set.file("a.proto").unwrap().services[0].methods[0];
+        assert_eq!(method.input_type, ".a.Person");
+        assert_eq!(method.output_type, ".b.Thing");
+        assert!(set.find_enum(".a.Person.Kind").is_some());
+    }
+
+    #[test]
+    fn unresolved_type() {
+        let file = parse("a.proto", "message A { Missing m = 1; }");
+
+        match FileSet::new(vec![file]) {
+            Err(Error::UnresolvedType { file, name }) => {
+                assert_eq!(file, "a.proto");
+                assert_eq!(name, "Missing");
+            }
+            other => panic!("expected unresolved type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validation_problems() {
+        let file = parse(
+            "a.proto",
+            "syntax = \"proto3\"; package p;
+             message A {
+               reserved 5; reserved \"old\";
+               extensions 100 to 200;
+               int32 a = 1; int32 b = 1; string old = 5; int32 a = 150; int32 c = 19001;
+             }
+             enum E { X = 1; Y = 1; }
+             enum F { option allow_alias = true; Z = 0; W = 0; }",
+        );
+        let set = FileSet::new(vec![file]).unwrap();
+        assert_eq!(
+            set.validate(),
+            vec![
+                "a.proto: p.A.b: number 1 is already used by \"a\"",
+                "a.proto: p.A.old: number 5 is reserved",
+                "a.proto: p.A.old: name is reserved",
+                "a.proto: p.A.a: field name is already used",
+                "a.proto: p.A.a: number 150 is in an extension range",
+                "a.proto: p.A.c: numbers 19000 to 19999 are reserved for the protobuf implementation",
+                "a.proto: p.E: the first value must be zero in proto3",
+                "a.proto: p.E.Y: number 1 is already used by \"X\"; set allow_alias to alias it",
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_rules() {
+        let proto2 = parse(
+            "a.proto",
+            "syntax = \"proto2\"; package p;
+             message A {
+               int32 bare = 1; map<string, int32> m = 2; optional int32 x = 3;
+               oneof o {}
+               oneof k { int32 y = 4; }
+               reserved 5 to 1;
+               extensions 200 to 100;
+             }
+             enum E {}
+             enum F { reserved 9 to 8; Z = 0; }
+             extend A { int32 ext = 150; }",
+        );
+        let proto3 = parse(
+            "b.proto",
+            "syntax = \"proto3\"; package q;
+             message B {
+               required int32 a = 1; int32 b = 2 [default = 3];
+               oneof o { int32 c = 3 [default = 4]; }
+             }",
+        );
+        let proto4 = parse("c.proto", "syntax = \"proto4\"; package r;");
+        let set = FileSet::new(vec![proto2, proto3, proto4]).unwrap();
+        assert_eq!(
+            set.validate(),
+            vec![
+                "a.proto: p.A: reserved range 5 to 1 ends before it starts",
+                "a.proto: p.A: extension range 200 to 100 ends before it starts",
+                "a.proto: p.A.bare: fields need a label (optional, required or repeated) in proto2",
+                "a.proto: p.A.o: oneof has no fields",
+                "a.proto: p.E: enum has no values",
+                "a.proto: p.F: reserved range 9 to 8 ends before it starts",
+                "a.proto: p.ext: fields need a label (optional, required or repeated) in proto2",
+                "b.proto: q.B.a: required fields are not allowed in proto3",
+                "b.proto: q.B.b: default values are not allowed in proto3",
+                "b.proto: q.B.c: default values are not allowed in proto3",
+                "c.proto: syntax \"proto4\" is not supported; use \"proto2\" or \"proto3\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_symbols_and_unknown_options() {
+        let descriptor = parse(
+            "google/protobuf/descriptor.proto",
+            well_known::source("google/protobuf/descriptor.proto").unwrap(),
+        );
+        let other = parse("b.proto", "package p; message A {} enum Level { LOW = 0; }");
+        let file = parse(
+            "a.proto",
+            "syntax = \"proto3\"; package p;
+             import \"google/protobuf/descriptor.proto\";
+             extend google.protobuf.FieldOptions { int32 max_size = 50000; }
+             message A {}
+             message B {
+               option (max_size) = 1;
+               int32 x = 1 [(p.max_size) = 2, (sql).primary_key = true];
+               message B {}
+               enum E { HIGH = 0; }
+               enum F { HIGH = 0; }
+             }",
+        );
+        let set = FileSet::new(vec![descriptor, other, file]).unwrap();
+        assert_eq!(
+            set.validate(),
+            vec![
+                "a.proto: \"p.A\" is already defined in b.proto",
+                "a.proto: \"p.B.HIGH\" is already defined",
+                "a.proto: p.B: option (max_size) extends google.protobuf.FieldOptions, \
+                 not google.protobuf.MessageOptions",
+                "a.proto: p.B: option (sql) is not defined",
+            ]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Describes Rust types as protobuf schema, the reverse of
+//! [`codegen::rust`](crate::codegen::rust).
+//!
+//! `#[derive(ProtoSchema)]` implements the traits here for structs and
+//! enums; [`file`] collects the results into a [`ProtoFile`] that
+//! [`codegen::format`](crate::codegen::format) prints as `.proto` source.
+//!
+//! ```ignore
+//! use protos::schema::{self, ProtoSchema};
+//!
+//! #[derive(ProtoSchema)]
+//! struct Order {
+//!     #[proto(tag = 1)]
+//!     id: String,
+//!     #[proto(tag = 2)]
+//!     status: Status,
+//! }
+//!
+//! #[derive(ProtoSchema)]
+//! enum Status {
+//!     Unknown,
+//!     Paid,
+//! }
+//!
+//! let file = schema::file("shop.proto", Some("shop"), vec![Order::schema(), Status::schema()]);
+//! print!("{}", protos::codegen::format(&file));
+//! ```
+
+use crate::*;
+use std::collections::{BTreeMap, HashMap};
+
+pub use protos_derive::ProtoSchema;
+
+/// A top-level message or enum.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Message(Message),
+    Enum(Enum),
+}
+
+pub trait ProtoSchema {
+    fn schema() -> Schema;
+}
+
+/// Rust types that stand for one protobuf value.
+pub trait ProtoType {
+    /// Whether the type is a message, which has presence without being
+    /// declared `optional`.
+    const MESSAGE: bool = false;
+
+    fn proto_type() -> Type;
+}
+
+/// Rust types of message fields, which decide the field's label: `Option`
+/// is `optional`, `Vec` is `repeated` and maps are maps.
+pub trait ProtoField {
+    /// The field called `name` numbered `tag`. `scalar` replaces the
+    /// protobuf type of its values, e.g. to make an `i32` a `sint32`.
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField;
+}
+
+/// Enums whose variants each hold a value, used as a oneof.
+pub trait ProtoOneof {
+    fn oneof(name: &str) -> Oneof;
+}
+
+/// A field holding one `T` without a label, as proto3 writes it.
+pub fn singular<T: ProtoType>(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+    MessageField::new(None, scalar.unwrap_or_else(T::proto_type), name, tag)
+}
+
+/// A proto3 file of the given top-level types.
+pub fn file(name: &str, package: Option<&str>, items: Vec<Schema>) -> ProtoFile {
+    let mut file = ProtoFile {
+        name: name.to_string(),
+        syntax: Some("proto3".to_string()),
+        package: package.map(str::to_string),
+        ..Default::default()
+    };
+    for item in items {
+        match item {
+            Schema::Message(message) => file.messages.push(message),
+            Schema::Enum(e) => file.enums.push(e),
+        }
+    }
+    file
+}
+
+macro_rules! scalar {
+    ($($rust:ty => $proto:ident),* $(,)?) => {$(
+        impl ProtoType for $rust {
+            fn proto_type() -> Type {
+                Type::$proto
+            }
+        }
+
+        impl ProtoField for $rust {
+            fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+                singular::<Self>(name, tag, scalar)
+            }
+        }
+    )*};
+}
+
+scalar! {
+    f64 => Double,
+    f32 => Float,
+    i32 => Int32,
+    i64 => Int64,
+    u32 => Uint32,
+    u64 => Uint64,
+    bool => Bool,
+    String => String,
+}
+
+impl ProtoType for Vec<u8> {
+    fn proto_type() -> Type {
+        Type::Bytes
+    }
+}
+
+impl ProtoField for Vec<u8> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        singular::<Self>(name, tag, scalar)
+    }
+}
+
+impl<T: ProtoType> ProtoType for Box<T> {
+    const MESSAGE: bool = T::MESSAGE;
+
+    fn proto_type() -> Type {
+        T::proto_type()
+    }
+}
+
+impl<T: ProtoType> ProtoField for Box<T> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        singular::<T>(name, tag, scalar)
+    }
+}
+
+impl<T: ProtoType> ProtoField for Option<T> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        let frequency = (!T::MESSAGE).then_some(Frequency::Optional);
+        MessageField::new(frequency, scalar.unwrap_or_else(T::proto_type), name, tag)
+    }
+}
+
+impl<T: ProtoType> ProtoField for Vec<T> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        let t = scalar.unwrap_or_else(T::proto_type);
+        MessageField::new(Some(Frequency::Repeated), t, name, tag)
+    }
+}
+
+impl<K: ProtoType, V: ProtoType> ProtoField for HashMap<K, V> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        let value = scalar.unwrap_or_else(V::proto_type);
+        MessageField::Map(K::proto_type(), value, name, tag)
+    }
+}
+
+impl<K: ProtoType, V: ProtoType> ProtoField for BTreeMap<K, V> {
+    fn field(name: String, tag: u32, scalar: Option<Type>) -> MessageField {
+        let value = scalar.unwrap_or_else(V::proto_type);
+        MessageField::Map(K::proto_type(), value, name, tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen;
+
+    #[derive(ProtoSchema)]
+    #[allow(dead_code)]
+    struct Order {
+        #[proto(tag = 1)]
+        id: String,
+        #[proto(tag = 2, type = "sint64")]
+        total: i64,
+        #[proto(tag = 3)]
+        lines: Vec<Line>,
+        #[proto(tag = 4)]
+        status: OrderStatus,
+        #[proto(tag = 5)]
+        note: Option<String>,
+        #[proto(tag = 6)]
+        tags: BTreeMap<String, u32>,
+        #[proto(tag = 7)]
+        parent: Option<Box<Order>>,
+        #[proto(oneof)]
+        payment: Option<Payment>,
+        #[proto(tag = 10, name = "type")]
+        r#kind: Vec<u8>,
+        #[proto(skip)]
+        cache: Vec<String>,
+    }
+
+    #[derive(ProtoSchema)]
+    #[allow(dead_code)]
+    struct Line {
+        #[proto(tag = 1)]
+        pub sku: String,
+        #[proto(tag = 2, type = "fixed32")]
+        pub quantity: u32,
+    }
+
+    #[derive(ProtoSchema)]
+    #[allow(dead_code)]
+    enum OrderStatus {
+        Unknown,
+        Paid = 2,
+        Shipped,
+        #[proto(tag = 10, name = "REFUNDED")]
+        Refunded,
+    }
+
+    #[derive(ProtoSchema)]
+    #[allow(dead_code)]
+    enum Payment {
+        #[proto(tag = 8)]
+        Card(String),
+        #[proto(tag = 9, name = "voucher")]
+        GiftVoucher(Line),
+    }
+
+    #[test]
+    fn derives_messages_and_enums() {
+        let file = file(
+            "shop.proto",
+            Some("shop"),
+            vec![Order::schema(), Line::schema(), OrderStatus::schema()],
+        );
+        let expected = r#"syntax = "proto3";
+
+package shop;
+
+message Order {
+  string id = 1;
+  sint64 total = 2;
+  repeated Line lines = 3;
+  OrderStatus status = 4;
+  optional string note = 5;
+  map<string, uint32> tags = 6;
+  Order parent = 7;
+  bytes type = 10;
+
+  oneof payment {
+    string card = 8;
+    Line voucher = 9;
+  }
+}
+
+message Line {
+  string sku = 1;
+  fixed32 quantity = 2;
+}
+
+enum OrderStatus {
+  ORDER_STATUS_UNKNOWN = 0;
+  ORDER_STATUS_PAID = 2;
+  ORDER_STATUS_SHIPPED = 3;
+  REFUNDED = 10;
+}
+"#;
+        assert_eq!(codegen::format(&file), expected);
+    }
+
+    #[test]
+    fn schemas_link_and_validate() {
+        let mut file = file(
+            "shop.proto",
+            Some("shop"),
+            vec![Order::schema(), Line::schema(), OrderStatus::schema()],
+        );
+        file = Parser::new(&codegen::format(&file)).consume_file().unwrap();
+        file.name = "shop.proto".to_string();
+        let set = resolver::FileSet::new(vec![file]).unwrap();
+        assert_eq!(set.validate(), Vec::<String>::new());
+        assert!(set.find_message(".shop.Order").is_some());
+    }
+}
@@ -0,0 +1,998 @@
+//! The protobuf text format, as read by `protoc --encode` and written by
+//! `protoc --decode`.
+
+use crate::dynamic::{DynamicMessage, MapKey, Value};
+use crate::raw;
+use crate::*;
+use std::fmt;
+
+/// A text format parse error, positioned at the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+const ANY: &str = ".google.protobuf.Any";
+
+/// Parses text format into a new message of a standalone definition, such
+/// as one from `Parser::consume_message`.
+pub fn parse(input: &str, message: &Message) -> Result<DynamicMessage, Error> {
+    let mut ret = DynamicMessage::new(message).map_err(|err| Error {
+        line: 1,
+        column: 1,
+        message: err.to_string(),
+    })?;
+    merge(input, &mut ret)?;
+    Ok(ret)
+}
+
+/// Parses text format into an existing message. Repeated fields and map
+/// entries are appended to, other fields overwritten.
+pub fn merge(input: &str, message: &mut DynamicMessage) -> Result<(), Error> {
+    let (tokens, end) = tokenize(input)?;
+    let mut parser = TextParser {
+        tokens,
+        pos: 0,
+        end,
+        depth: wire::RECURSION_LIMIT,
+    };
+    parser.parse_fields(message, None)
+}
+
+/// Prints a message in canonical text format: one field per line in field
+/// number order, nested messages indented by two spaces, enums by name,
+/// extensions as `[full.name]` and `Any` expanded when its type is known.
+pub fn print(message: &DynamicMessage) -> String {
+    let mut s = String::new();
+    write_message(message, 0, &mut s);
+    s
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A number literal, still in source form so it can be read as the
+    /// type of the field it is assigned to.
+    Number(String),
+    String(Vec<u8>),
+    Symbol(char),
+}
+
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+/// Splits the input into tokens, also returning the line and column just
+/// past its end.
+fn tokenize(input: &str) -> Result<(Vec<Spanned>, (usize, usize)), Error> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let error = |line, column, message: &str| Error {
+        line,
+        column,
+        message: message.to_string(),
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let start = (line, column);
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            column += 1;
+            continue;
+        }
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let begin = i;
+        let token = if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Ident(chars[begin..i].iter().collect())
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            let hex = c == '0' && matches!(chars.get(i + 1), Some('x' | 'X'));
+            while i < chars.len() {
+                let c = chars[i];
+                let exponent_sign =
+                    (c == '+' || c == '-') && !hex && matches!(chars[i - 1], 'e' | 'E');
+                if c.is_ascii_alphanumeric() || c == '.' || c == '_' || exponent_sign {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Token::Number(chars[begin..i].iter().collect())
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            let mut bytes = vec![];
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') => {
+                        return Err(error(start.0, start.1, "unterminated string"));
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some('\\') => {
+                        i += 1;
+                        let consumed = unescape(&chars[i..], &mut bytes)
+                            .ok_or_else(|| error(line, column + i - begin, "invalid escape"))?;
+                        i += consumed;
+                    }
+                    Some(&other) => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+                        i += 1;
+                    }
+                }
+            }
+            Token::String(bytes)
+        } else if "{}<>[]:;,./-".contains(c) {
+            i += 1;
+            Token::Symbol(c)
+        } else {
+            return Err(error(
+                line,
+                column,
+                &format!("unexpected character '{}'", c),
+            ));
+        };
+        column += i - begin;
+        tokens.push(Spanned {
+            token,
+            line: start.0,
+            column: start.1,
+        });
+    }
+    Ok((tokens, (line, column)))
+}
+
+/// Decodes the escape sequence after a backslash, returning how many
+/// characters it used.
+pub(crate) fn unescape(chars: &[char], bytes: &mut Vec<u8>) -> Option<usize> {
+    let simple = match chars.first()? {
+        'n' => Some(b'\n'),
+        'r' => Some(b'\r'),
+        't' => Some(b'\t'),
+        'a' => Some(0x07),
+        'b' => Some(0x08),
+        'f' => Some(0x0c),
+        'v' => Some(0x0b),
+        '\\' => Some(b'\\'),
+        '\'' => Some(b'\''),
+        '"' => Some(b'"'),
+        '?' => Some(b'?'),
+        _ => None,
+    };
+    if let Some(byte) = simple {
+        bytes.push(byte);
+        return Some(1);
+    }
+    let digits = |radix: u32, skip: usize, max: usize| {
+        let len = chars[skip..]
+            .iter()
+            .take(max)
+            .take_while(|c| c.is_digit(radix))
+            .count();
+        let text: String = chars[skip..skip + len].iter().collect();
+        u32::from_str_radix(&text, radix)
+            .ok()
+            .map(|v| (v, skip + len))
+    };
+    match chars[0] {
+        '0'..='7' => {
+            let (v, used) = digits(8, 0, 3)?;
+            bytes.push(u8::try_from(v).ok()?);
+            Some(used)
+        }
+        'x' | 'X' => {
+            let (v, used) = digits(16, 1, 2)?;
+            bytes.push(v as u8);
+            Some(used)
+        }
+        'u' | 'U' => {
+            let len = if chars[0] == 'u' { 4 } else { 8 };
+            let (v, used) = digits(16, 1, len)?;
+            if used != len + 1 {
+                return None;
+            }
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(char::from_u32(v)?.encode_utf8(&mut buf).as_bytes());
+            Some(used)
+        }
+        _ => None,
+    }
+}
+
+struct TextParser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    end: (usize, usize),
+    /// How many more levels of messages may open.
+    depth: usize,
+}
+
+impl TextParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: impl Into<String>) -> Error {
+        let (line, column) = match self.tokens.get(pos) {
+            Some(t) => (t.line, t.column),
+            None => self.end,
+        };
+        Error {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn try_symbol(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, c: char) -> Result<(), Error> {
+        if self.try_symbol(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", c)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected identifier")),
+        }
+    }
+
+    /// Opens a message value with `{` or `<`, returning the closing symbol.
+    fn open_message(&mut self) -> Result<char, Error> {
+        if self.try_symbol('{') {
+            Ok('}')
+        } else if self.try_symbol('<') {
+            Ok('>')
+        } else {
+            Err(self.error("expected '{' or '<'"))
+        }
+    }
+
+    /// Parses the fields of a nested message up to `close`.
+    fn parse_nested(&mut self, message: &mut DynamicMessage, close: char) -> Result<(), Error> {
+        if self.depth == 0 {
+            return Err(self.error_at(
+                self.pos - 1,
+                format!(
+                    "messages are nested more than {} deep",
+                    wire::RECURSION_LIMIT
+                ),
+            ));
+        }
+        self.depth -= 1;
+        let result = self.parse_fields(message, Some(close));
+        self.depth += 1;
+        result
+    }
+
+    fn parse_fields(
+        &mut self,
+        message: &mut DynamicMessage,
+        close: Option<char>,
+    ) -> Result<(), Error> {
+        let mut seen: Vec<u32> = vec![];
+        loop {
+            match (self.peek(), close) {
+                (None, None) => return Ok(()),
+                (None, Some(c)) => return Err(self.error(format!("expected '{}'", c))),
+                (Some(Token::Symbol(c)), Some(close)) if *c == close => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+
+            let start = self.pos;
+            let field = if self.try_symbol('[') {
+                let name = self.parse_bracketed_name()?;
+                if name.contains('/') {
+                    self.parse_any(message, &name)?;
+                    let _ = self.try_symbol(',') || self.try_symbol(';');
+                    continue;
+                }
+                message
+                    .field_by_name(&name)
+                    .filter(|f| message.extension_name(f.position()).is_some())
+                    .cloned()
+                    .ok_or_else(|| {
+                        self.error_at(
+                            start,
+                            format!(
+                                "extension \"{}\" is not defined for \"{}\"",
+                                name,
+                                message.type_name().trim_start_matches('.')
+                            ),
+                        )
+                    })?
+            } else {
+                let name = self.expect_ident()?;
+                message
+                    .field_descriptors()
+                    .find(|f| f.name() == name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        self.error_at(
+                            start,
+                            format!(
+                                "message type \"{}\" has no field named \"{}\"",
+                                message.type_name().trim_start_matches('.'),
+                                name
+                            ),
+                        )
+                    })?
+            };
+            self.check_seen(message, &field, &seen, start)?;
+            seen.push(field.position());
+            self.parse_field_value(message, &field)?;
+            let _ = self.try_symbol(',') || self.try_symbol(';');
+        }
+    }
+
+    /// Reads `pkg.ext` or `type.googleapis.com/pkg.T` up to the closing `]`.
+    fn parse_bracketed_name(&mut self) -> Result<String, Error> {
+        let mut name = self.expect_ident()?;
+        loop {
+            if self.try_symbol('.') {
+                name.push('.');
+            } else if self.try_symbol('/') {
+                name.push('/');
+            } else {
+                break;
+            }
+            name.push_str(&self.expect_ident()?);
+        }
+        self.expect_symbol(']')?;
+        Ok(name)
+    }
+
+    fn check_seen(
+        &self,
+        message: &DynamicMessage,
+        field: &MessageField,
+        seen: &[u32],
+        start: usize,
+    ) -> Result<(), Error> {
+        let number = field.position();
+        if !field.is_repeated() && seen.contains(&number) {
+            return Err(self.error_at(
+                start,
+                format!(
+                    "non-repeated field \"{}\" is specified multiple times",
+                    field.name()
+                ),
+            ));
+        }
+        let oneof = message
+            .descriptor()
+            .oneofs
+            .iter()
+            .find(|oneof| oneof.fields.iter().any(|f| f.position() == number));
+        if let Some(other) = oneof.and_then(|oneof| {
+            oneof
+                .fields
+                .iter()
+                .find(|f| f.position() != number && seen.contains(&f.position()))
+        }) {
+            return Err(self.error_at(
+                start,
+                format!(
+                "field \"{}\" is specified along with field \"{}\", another member of oneof \"{}\"",
+                field.name(),
+                other.name(),
+                oneof.map_or("", |oneof| oneof.name.as_str())
+            ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_any(&mut self, message: &mut DynamicMessage, url: &str) -> Result<(), Error> {
+        if message.type_name() != ANY {
+            return Err(self.error(format!(
+                "\"{}\" is not an Any message",
+                message.type_name().trim_start_matches('.')
+            )));
+        }
+        let type_name = &url[url.rfind('/').unwrap_or(0) + 1..];
+        let mut inner = message
+            .new_message(type_name)
+            .map_err(|err| self.error(err.to_string()))?;
+        self.try_symbol(':');
+        let close = self.open_message()?;
+        self.parse_nested(&mut inner, close)?;
+        let result = message
+            .set_by_number(1, Value::String(url.to_string()))
+            .and_then(|_| message.set_by_number(2, Value::Bytes(inner.encode_to_vec())));
+        result.map_err(|err| self.error(err.to_string()))
+    }
+
+    fn parse_field_value(
+        &mut self,
+        message: &mut DynamicMessage,
+        field: &MessageField,
+    ) -> Result<(), Error> {
+        let number = field.position();
+        let t = field.field_type();
+        let is_message = matches!(t, Type::Message(_) | Type::Map(..));
+        if is_message {
+            self.try_symbol(':');
+        } else {
+            self.expect_symbol(':')?;
+        }
+
+        let mut values = vec![];
+        if field.is_repeated() && self.try_symbol('[') {
+            if !self.try_symbol(']') {
+                loop {
+                    values.push(self.parse_element(message, &t)?);
+                    if self.try_symbol(']') {
+                        break;
+                    }
+                    self.expect_symbol(',')?;
+                }
+            }
+        } else {
+            values.push(self.parse_element(message, &t)?);
+        }
+
+        for value in values {
+            let result = match value {
+                Element::Entry(key, value) => message.insert_by_number(number, key, value),
+                Element::Value(value) if field.is_repeated() => {
+                    message.add_by_number(number, value)
+                }
+                Element::Value(value) => message.set_by_number(number, value),
+            };
+            result.map_err(|err| self.error(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn parse_element(&mut self, message: &DynamicMessage, t: &Type) -> Result<Element, Error> {
+        match t {
+            Type::Message(name) => {
+                let mut nested = message
+                    .new_message(name)
+                    .map_err(|err| self.error(err.to_string()))?;
+                let close = self.open_message()?;
+                self.parse_nested(&mut nested, close)?;
+                Ok(Element::Value(Value::Message(nested)))
+            }
+            Type::Map(key_type, value_type) => {
+                let close = self.open_message()?;
+                let mut key = None;
+                let mut value = None;
+                while !self.try_symbol(close) {
+                    let name = self.expect_ident()?;
+                    let (t, slot) = match name.as_str() {
+                        "key" => (key_type.as_ref(), &mut key),
+                        "value" => (value_type.as_ref(), &mut value),
+                        _ => {
+                            return Err(
+                                self.error(format!("map entry has no field named \"{}\"", name))
+                            )
+                        }
+                    };
+                    if matches!(t, Type::Message(_)) {
+                        self.try_symbol(':');
+                    } else {
+                        self.expect_symbol(':')?;
+                    }
+                    let Element::Value(v) = self.parse_element(message, t)? else {
+                        unreachable!("map keys and values are never maps");
+                    };
+                    *slot = Some(v);
+                    let _ = self.try_symbol(',') || self.try_symbol(';');
+                }
+                let key = key.unwrap_or_else(|| message.default_value(key_type));
+                let value = value.unwrap_or_else(|| message.default_value(value_type));
+                let key = match key {
+                    Value::Bool(b) => MapKey::Bool(b),
+                    Value::I32(i) => MapKey::I32(i),
+                    Value::I64(i) => MapKey::I64(i),
+                    Value::U32(u) => MapKey::U32(u),
+                    Value::U64(u) => MapKey::U64(u),
+                    Value::String(s) => MapKey::String(s),
+                    _ => return Err(self.error("invalid map key type")),
+                };
+                Ok(Element::Entry(key, value))
+            }
+            t => self.parse_scalar(message, t).map(Element::Value),
+        }
+    }
+
+    fn parse_scalar(&mut self, message: &DynamicMessage, t: &Type) -> Result<Value, Error> {
+        match t {
+            Type::String | Type::Bytes => {
+                let start = self.pos;
+                let mut bytes = vec![];
+                while let Some(Token::String(part)) = self.peek() {
+                    bytes.extend_from_slice(part);
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.error("expected string"));
+                }
+                if *t == Type::Bytes {
+                    return Ok(Value::Bytes(bytes));
+                }
+                String::from_utf8(bytes)
+                    .map(Value::String)
+                    .map_err(|_| self.error("string is not valid UTF-8"))
+            }
+            Type::Bool => {
+                let value = match self.peek() {
+                    Some(Token::Ident(s)) if matches!(s.as_str(), "true" | "True" | "t") => true,
+                    Some(Token::Ident(s)) if matches!(s.as_str(), "false" | "False" | "f") => false,
+                    Some(Token::Number(s)) if s == "1" => true,
+                    Some(Token::Number(s)) if s == "0" => false,
+                    _ => return Err(self.error("expected boolean")),
+                };
+                self.pos += 1;
+                Ok(Value::Bool(value))
+            }
+            Type::Enum(name) => {
+                if let Some(Token::Ident(value)) = self.peek() {
+                    let number = message
+                        .file_set()
+                        .find_enum(name)
+                        .and_then(|e| e.fields.iter().find(|f| f.name == *value))
+                        .map(|f| f.position as i32)
+                        .ok_or_else(|| {
+                            self.error(format!(
+                                "unknown enumeration value \"{}\" for \"{}\"",
+                                value,
+                                name.trim_start_matches('.')
+                            ))
+                        })?;
+                    self.pos += 1;
+                    return Ok(Value::Enum(number));
+                }
+                let v = self.parse_integer()?;
+                i32::try_from(v)
+                    .map(Value::Enum)
+                    .map_err(|_| self.error("enum value out of range"))
+            }
+            Type::Float | Type::Double => {
+                let negative = self.try_symbol('-');
+                let v = match self.next() {
+                    Some(Token::Number(text)) => parse_float(&text),
+                    Some(Token::Ident(text)) => match text.to_ascii_lowercase().as_str() {
+                        "inf" | "infinity" => Some(f64::INFINITY),
+                        "nan" => Some(f64::NAN),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let v = v.ok_or_else(|| {
+                    self.pos -= 1;
+                    self.error("expected number")
+                })?;
+                let v = if negative { -v } else { v };
+                Ok(if *t == Type::Float {
+                    Value::F32(v as f32)
+                } else {
+                    Value::F64(v)
+                })
+            }
+            t => {
+                let v = self.parse_integer()?;
+                let value = match t {
+                    Type::Int32 | Type::Sint32 | Type::Sfixed32 => {
+                        i32::try_from(v).ok().map(Value::I32)
+                    }
+                    Type::Int64 | Type::Sint64 | Type::Sfixed64 => {
+                        i64::try_from(v).ok().map(Value::I64)
+                    }
+                    Type::Uint32 | Type::Fixed32 => u32::try_from(v).ok().map(Value::U32),
+                    Type::Uint64 | Type::Fixed64 => u64::try_from(v).ok().map(Value::U64),
+                    _ => {
+                        return Err(
+                            self.error(format!("unresolved type {}", String::from(t.clone())))
+                        )
+                    }
+                };
+                value.ok_or_else(|| {
+                    self.pos -= 1;
+                    self.error("integer out of range")
+                })
+            }
+        }
+    }
+
+    fn parse_integer(&mut self) -> Result<i128, Error> {
+        let negative = self.try_symbol('-');
+        let magnitude = match self.peek() {
+            Some(Token::Number(text)) => parse_int(text),
+            _ => None,
+        }
+        .ok_or_else(|| self.error("expected integer"))?;
+        self.pos += 1;
+        Ok(if negative {
+            -(magnitude as i128)
+        } else {
+            magnitude as i128
+        })
+    }
+}
+
+enum Element {
+    Value(Value),
+    Entry(MapKey, Value),
+}
+
+/// Reads a decimal, `0x` hexadecimal or `0` octal integer.
+fn parse_int(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_float(text: &str) -> Option<f64> {
+    if let Some(v) = parse_int(text) {
+        return Some(v as f64);
+    }
+    text.strip_suffix(['f', 'F']).unwrap_or(text).parse().ok()
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+fn write_message(message: &DynamicMessage, depth: usize, s: &mut String) {
+    if let Some((url, inner)) = expand_any(message) {
+        s.push_str(&format!("{}[{}] {{\n", indent(depth), url));
+        write_message(&inner, depth + 1, s);
+        s.push_str(&format!("{}}}\n", indent(depth)));
+        return;
+    }
+
+    for (field, value) in message.fields() {
+        let label = match message.extension_name(field.position()) {
+            Some(name) => format!("[{}]", name.trim_start_matches('.')),
+            None => field.name().to_string(),
+        };
+        match (field.field_type(), value) {
+            (Type::Map(key_type, value_type), Value::Map(entries)) => {
+                for (key, value) in entries {
+                    s.push_str(&format!("{}{} {{\n", indent(depth), label));
+                    write_value(message, "key", &key_type, &key.clone().into(), depth + 1, s);
+                    write_value(message, "value", &value_type, value, depth + 1, s);
+                    s.push_str(&format!("{}}}\n", indent(depth)));
+                }
+            }
+            (t, Value::List(values)) => {
+                for value in values {
+                    write_value(message, &label, &t, value, depth, s);
+                }
+            }
+            (t, value) => write_value(message, &label, &t, value, depth, s),
+        }
+    }
+
+    if let Ok(unknown) = raw::decode_raw(message.unknown_fields()) {
+        for line in raw::to_text(&unknown).lines() {
+            s.push_str(&format!("{}{}\n", indent(depth), line));
+        }
+    }
+}
+
+fn write_value(
+    message: &DynamicMessage,
+    label: &str,
+    t: &Type,
+    value: &Value,
+    depth: usize,
+    s: &mut String,
+) {
+    match value {
+        Value::Message(nested) => {
+            s.push_str(&format!("{}{} {{\n", indent(depth), label));
+            write_message(nested, depth + 1, s);
+            s.push_str(&format!("{}}}\n", indent(depth)));
+        }
+        value => s.push_str(&format!(
+            "{}{}: {}\n",
+            indent(depth),
+            label,
+            scalar_text(message, t, value)
+        )),
+    }
+}
+
+fn scalar_text(message: &DynamicMessage, t: &Type, value: &Value) -> String {
+    match value {
+        Value::Bool(b) => b.to_string(),
+        Value::I32(v) => v.to_string(),
+        Value::I64(v) => v.to_string(),
+        Value::U32(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::F32(v) => float_text(*v as f64, v.to_string()),
+        Value::F64(v) => float_text(*v, v.to_string()),
+        Value::String(v) => format!("\"{}\"", raw::escape(v.as_bytes())),
+        Value::Bytes(v) => format!("\"{}\"", raw::escape(v)),
+        Value::Enum(number) => match t {
+            Type::Enum(name) => message
+                .file_set()
+                .find_enum(name)
+                .and_then(|e| e.fields.iter().find(|f| f.position as i32 == *number))
+                .map_or_else(|| number.to_string(), |f| f.name.clone()),
+            _ => number.to_string(),
+        },
+        Value::Message(_) | Value::List(_) | Value::Map(_) => {
+            unreachable!("written by write_message")
+        }
+    }
+}
+
+fn float_text(v: f64, text: String) -> String {
+    if v.is_nan() {
+        "nan".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        text
+    }
+}
+
+/// The type URL and contents of an `Any` whose type is in the schema.
+fn expand_any(message: &DynamicMessage) -> Option<(String, DynamicMessage)> {
+    if message.type_name() != ANY {
+        return None;
+    }
+    let (Some(Value::String(url)), Some(Value::Bytes(value))) =
+        (message.get_by_number(1), message.get_by_number(2))
+    else {
+        return None;
+    };
+    let type_name = &url[url.rfind('/')? + 1..];
+    let mut inner = message.new_message(type_name).ok()?;
+    inner.merge(value).ok()?;
+    Some((url.clone(), inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::FileSet;
+    use std::sync::Arc;
+
+    fn schema() -> Arc<FileSet> {
+        let mut any = Parser::new(
+            "syntax = \"proto3\";
+            package google.protobuf;
+            message Any { string type_url = 1; bytes value = 2; }",
+        )
+        .consume_file()
+        .unwrap();
+        any.name = "google/protobuf/any.proto".to_string();
+        let mut file = Parser::new(
+            "syntax = \"proto2\";
+            package test;
+            import \"google/protobuf/any.proto\";
+            message Person {
+                optional string name = 1;
+                optional int32 id = 2;
+                repeated Phone phones = 3;
+                optional Kind kind = 4;
+                repeated sint64 scores = 5;
+                map<string, double> weights = 6;
+                optional google.protobuf.Any detail = 7;
+                optional bytes blob = 8;
+                extensions 100 to 199;
+                message Phone { optional string number = 1; }
+                enum Kind { HUMAN = 0; ROBOT = 1; }
+            }
+            extend Person { optional bool vip = 100; }",
+        )
+        .consume_file()
+        .unwrap();
+        file.name = "test.proto".to_string();
+        Arc::new(FileSet::new(vec![any, file]).unwrap())
+    }
+
+    fn person() -> DynamicMessage {
+        DynamicMessage::from_file_set(schema(), "test.Person").unwrap()
+    }
+
+    #[test]
+    fn parse_and_print() {
+        let mut person = person();
+        merge(
+            "name: \"x\" id: 3 phones { number: \"555\" }
+            # Comments, alternate delimiters and separators are accepted.
+            phones < number: '556' >, kind: ROBOT;
+            scores: [-1, 0x10, 010] scores: 2
+            weights { key: \"a\" value: inf } weights: { key: \"b\" value: -1.5e1 }
+            [test.vip]: t blob: \"\\001\\x02\" \"z\"",
+            &mut person,
+        )
+        .unwrap();
+
+        assert_eq!(person.get("id"), Some(&Value::I32(3)));
+        assert_eq!(person.get("kind"), Some(&Value::Enum(1)));
+        assert_eq!(
+            person.get("scores"),
+            Some(&Value::List(vec![
+                Value::I64(-1),
+                Value::I64(16),
+                Value::I64(8),
+                Value::I64(2)
+            ]))
+        );
+        assert_eq!(person.get("test.vip"), Some(&Value::Bool(true)));
+        assert_eq!(person.get("blob"), Some(&Value::Bytes(vec![1, 2, b'z'])));
+
+        let expected = "name: \"x\"
+id: 3
+phones {
+  number: \"555\"
+}
+phones {
+  number: \"556\"
+}
+kind: ROBOT
+scores: -1
+scores: 16
+scores: 8
+scores: 2
+weights {
+  key: \"a\"
+  value: inf
+}
+weights {
+  key: \"b\"
+  value: -15
+}
+blob: \"\\001\\002z\"
+[test.vip]: true
+";
+        assert_eq!(print(&person), expected);
+
+        let mut reparsed = self::person();
+        merge(expected, &mut reparsed).unwrap();
+        assert_eq!(reparsed, person);
+    }
+
+    #[test]
+    fn any_expansion() {
+        let mut person = person();
+        merge(
+            "detail { [type.googleapis.com/test.Person.Phone] { number: \"1\" } }",
+            &mut person,
+        )
+        .unwrap();
+
+        let Some(Value::Message(detail)) = person.get("detail") else {
+            panic!("detail is not set");
+        };
+        assert_eq!(
+            detail.get("type_url"),
+            Some(&Value::String(
+                "type.googleapis.com/test.Person.Phone".to_string()
+            ))
+        );
+        assert_eq!(
+            print(&person),
+            "detail {\n  [type.googleapis.com/test.Person.Phone] {\n    number: \"1\"\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn standalone_message() {
+        let message = Parser::new("message Point { int32 x = 1; int32 y = 2; }")
+            .consume_message()
+            .unwrap();
+        let point = parse("x: 1 y: -2", &message).unwrap();
+        assert_eq!(print(&point), "x: 1\ny: -2\n");
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let message = Parser::new("message Node { Node child = 1; }")
+            .consume_message()
+            .unwrap();
+        let nested = |levels: usize| "child { ".repeat(levels) + &"}".repeat(levels);
+        parse(&nested(wire::RECURSION_LIMIT), &message).unwrap();
+        let err = parse(&nested(50_000), &message).unwrap_err();
+        assert_eq!(err.message, "messages are nested more than 100 deep");
+        assert_eq!(err.column, 8 * 100 + 7);
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (
+                "id: 1\nnope: 2",
+                2,
+                1,
+                "message type \"test.Person\" has no field named \"nope\"",
+            ),
+            (
+                "id: 1 id: 2",
+                1,
+                7,
+                "non-repeated field \"id\" is specified multiple times",
+            ),
+            (
+                "kind: ALIEN",
+                1,
+                7,
+                "unknown enumeration value \"ALIEN\" for \"test.Person.Kind\"",
+            ),
+            ("id: 3000000000", 1, 5, "integer out of range"),
+            ("phones { number: \"1\"", 1, 21, "expected '}'"),
+        ];
+        for (input, line, column, message) in cases {
+            assert_eq!(
+                merge(input, &mut person()),
+                Err(Error {
+                    line,
+                    column,
+                    message: message.to_string()
+                }),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,1253 @@
+//! Conversion of Thrift IDL into a proto3 [`ProtoFile`], for migrating
+//! services from Thrift.
+//!
+//! Structs become messages, unions messages holding one oneof, enums
+//! enums and services services. Field IDs are kept as field numbers. Each
+//! method takes a `MethodRequest` holding its arguments and returns its
+//! result type if that is a struct, a `MethodResponse` holding it if not,
+//! or `google.protobuf.Empty` if it returns `void`. Typedefs are replaced
+//! by what they name, and the `*` namespace, or else the first one,
+//! becomes the package.
+//!
+//! What protobuf cannot express, such as exceptions, sets or constants, is
+//! converted as closely as it can be or dropped, with a [`Warning`].
+
+use crate::codegen::{snake, upper_camel};
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A Thrift parse error, positioned at the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Something in the Thrift source that did not carry over as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+const EMPTY: &str = "google.protobuf.Empty";
+
+/// Converts a Thrift IDL file into a proto3 file named `name`, returning
+/// the warnings in source order.
+pub fn import(source: &str, name: &str) -> Result<(ProtoFile, Vec<Warning>), Error> {
+    let (tokens, end) = tokenize(source)?;
+    let mut parser = ThriftParser {
+        tokens,
+        pos: 0,
+        end,
+        document: Document::default(),
+    };
+    parser.parse_document()?;
+    let mut converter = Converter::new(parser.document, name);
+    converter.convert();
+    let mut warnings = converter.warnings;
+    warnings.sort_by_key(|warning| warning.line);
+    Ok((converter.file, warnings))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An identifier, which in Thrift may contain dots.
+    Ident(String),
+    Number(String),
+    String(String),
+    Symbol(char),
+}
+
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+/// Splits the input into tokens, also returning the line and column just
+/// past its end.
+fn tokenize(input: &str) -> Result<(Vec<Spanned>, (usize, usize)), Error> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let error = |line, column, message: &str| Error {
+        line,
+        column,
+        message: message.to_string(),
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let start = (line, column);
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            column += 1;
+            continue;
+        }
+        if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            column += 2;
+            loop {
+                match chars.get(i) {
+                    None => return Err(error(start.0, start.1, "unterminated comment")),
+                    Some('*') if chars.get(i + 1) == Some(&'/') => {
+                        i += 2;
+                        column += 2;
+                        break;
+                    }
+                    Some('\n') => {
+                        i += 1;
+                        line += 1;
+                        column = 1;
+                    }
+                    Some(_) => {
+                        i += 1;
+                        column += 1;
+                    }
+                }
+            }
+            continue;
+        }
+        let begin = i;
+        let token = if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "_.".contains(chars[i])) {
+                i += 1;
+            }
+            Token::Ident(chars[begin..i].iter().collect())
+        } else if c.is_ascii_digit()
+            || ((c == '-' || c == '+') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            i += 1;
+            while i < chars.len() {
+                let c = chars[i];
+                let exponent_sign = (c == '+' || c == '-') && matches!(chars[i - 1], 'e' | 'E');
+                if c.is_ascii_alphanumeric() || c == '.' || exponent_sign {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Token::Number(chars[begin..i].iter().collect())
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            let mut value = String::new();
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') => {
+                        return Err(error(start.0, start.1, "unterminated string"));
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some('\\') if i + 1 < chars.len() => {
+                        value.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&other) => {
+                        value.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            Token::String(value)
+        } else if "{}()<>[]=:,;*".contains(c) {
+            i += 1;
+            Token::Symbol(c)
+        } else {
+            return Err(error(
+                line,
+                column,
+                &format!("unexpected character '{}'", c),
+            ));
+        };
+        column += i - begin;
+        tokens.push(Spanned {
+            token,
+            line: start.0,
+            column: start.1,
+        });
+    }
+    Ok((tokens, (line, column)))
+}
+
+/// A type as written in Thrift.
+#[derive(Debug, Clone, PartialEq)]
+enum ThriftType {
+    /// A base type such as `i32` or `binary`.
+    Base(String),
+    Named(String),
+    List(Box<ThriftType>),
+    Set(Box<ThriftType>),
+    Map(Box<ThriftType>, Box<ThriftType>),
+}
+
+impl fmt::Display for ThriftType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThriftType::Base(name) | ThriftType::Named(name) => write!(f, "{}", name),
+            ThriftType::List(t) => write!(f, "list<{}>", t),
+            ThriftType::Set(t) => write!(f, "set<{}>", t),
+            ThriftType::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+        }
+    }
+}
+
+const BASE_TYPES: &[&str] = &[
+    "bool", "byte", "i8", "i16", "i32", "i64", "double", "string", "binary", "uuid",
+];
+
+#[derive(Debug, Clone)]
+struct Field {
+    line: usize,
+    id: Option<i64>,
+    /// `required` or `optional`, if given.
+    requiredness: Option<String>,
+    t: ThriftType,
+    name: String,
+    has_default: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StructKind {
+    Struct,
+    Union,
+    Exception,
+}
+
+#[derive(Debug)]
+struct Struct {
+    line: usize,
+    kind: StructKind,
+    name: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+struct ThriftEnum {
+    line: usize,
+    name: String,
+    /// Each value's line, name and number, if given.
+    values: Vec<(usize, String, Option<i64>)>,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    line: usize,
+    oneway: bool,
+    /// `None` for `void`.
+    returns: Option<ThriftType>,
+    name: String,
+    args: Vec<Field>,
+    throws: bool,
+}
+
+#[derive(Debug)]
+struct ThriftService {
+    line: usize,
+    name: String,
+    extends: Option<String>,
+    functions: Vec<Function>,
+}
+
+#[derive(Debug, Default)]
+struct Document {
+    /// Each namespace's line, scope and name.
+    namespaces: Vec<(usize, String, String)>,
+    includes: Vec<(usize, String)>,
+    typedefs: BTreeMap<String, ThriftType>,
+    structs: Vec<Struct>,
+    enums: Vec<ThriftEnum>,
+    services: Vec<ThriftService>,
+    /// What the parser skipped.
+    warnings: Vec<Warning>,
+}
+
+struct ThriftParser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    end: (usize, usize),
+    document: Document,
+}
+
+impl ThriftParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end.0, |t| t.line)
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        let (line, column) = match self.tokens.get(self.pos) {
+            Some(t) => (t.line, t.column),
+            None => self.end,
+        };
+        Error {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn warn(&mut self, line: usize, message: impl Into<String>) {
+        self.document.warnings.push(Warning {
+            line,
+            message: message.into(),
+        });
+    }
+
+    fn try_symbol(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, c: char) -> Result<(), Error> {
+        if self.try_symbol(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", c)))
+        }
+    }
+
+    fn try_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(name)) if name == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected identifier")),
+        }
+    }
+
+    fn expect_string(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::String(value)) => {
+                let value = value.clone();
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.error("expected string")),
+        }
+    }
+
+    /// Skips the optional `,` or `;` after a list item.
+    fn separator(&mut self) {
+        let _ = self.try_symbol(',') || self.try_symbol(';');
+    }
+
+    fn parse_document(&mut self) -> Result<(), Error> {
+        while self.peek().is_some() {
+            let line = self.line();
+            let keyword = self.expect_ident()?;
+            match keyword.as_str() {
+                "include" => {
+                    let path = self.expect_string()?;
+                    self.document.includes.push((line, path));
+                }
+                "cpp_include" => {
+                    self.expect_string()?;
+                    self.warn(line, "cpp_include is not kept");
+                }
+                "namespace" => {
+                    let scope = match self.try_symbol('*') {
+                        true => "*".to_string(),
+                        false => self.expect_ident()?,
+                    };
+                    let name = self.expect_ident()?;
+                    self.annotations()?;
+                    self.document.namespaces.push((line, scope, name));
+                }
+                "const" => {
+                    self.parse_type()?;
+                    let name = self.expect_ident()?;
+                    self.expect_symbol('=')?;
+                    self.skip_value()?;
+                    self.warn(line, format!("const {} is not kept", name));
+                }
+                "typedef" => {
+                    let t = self.parse_type()?;
+                    let name = self.expect_ident()?;
+                    self.annotations()?;
+                    self.document.typedefs.insert(name, t);
+                }
+                "enum" => self.parse_enum(line)?,
+                "senum" => {
+                    let name = self.expect_ident()?;
+                    self.expect_symbol('{')?;
+                    while !self.try_symbol('}') {
+                        self.expect_string()?;
+                        self.separator();
+                    }
+                    self.warn(line, format!("senum {} is not kept", name));
+                }
+                "struct" | "union" | "exception" => {
+                    let kind = match keyword.as_str() {
+                        "struct" => StructKind::Struct,
+                        "union" => StructKind::Union,
+                        _ => StructKind::Exception,
+                    };
+                    let name = self.expect_ident()?;
+                    self.expect_symbol('{')?;
+                    let fields = self.parse_fields('}')?;
+                    self.annotations()?;
+                    self.document.structs.push(Struct {
+                        line,
+                        kind,
+                        name,
+                        fields,
+                    });
+                }
+                "service" => self.parse_service(line)?,
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error(format!("unexpected {}", keyword)));
+                }
+            }
+            self.separator();
+        }
+        Ok(())
+    }
+
+    fn parse_enum(&mut self, line: usize) -> Result<(), Error> {
+        let name = self.expect_ident()?;
+        self.expect_symbol('{')?;
+        let mut values = vec![];
+        while !self.try_symbol('}') {
+            let line = self.line();
+            let value = self.expect_ident()?;
+            let number = match self.try_symbol('=') {
+                true => Some(self.integer()?),
+                false => None,
+            };
+            self.annotations()?;
+            self.separator();
+            values.push((line, value, number));
+        }
+        self.annotations()?;
+        self.document.enums.push(ThriftEnum { line, name, values });
+        Ok(())
+    }
+
+    fn parse_service(&mut self, line: usize) -> Result<(), Error> {
+        let name = self.expect_ident()?;
+        let extends = match self.try_keyword("extends") {
+            true => Some(self.expect_ident()?),
+            false => None,
+        };
+        self.expect_symbol('{')?;
+        let mut functions = vec![];
+        while !self.try_symbol('}') {
+            let line = self.line();
+            let oneway = self.try_keyword("oneway");
+            let returns = match self.try_keyword("void") {
+                true => None,
+                false => Some(self.parse_type()?),
+            };
+            let name = self.expect_ident()?;
+            self.expect_symbol('(')?;
+            let args = self.parse_fields(')')?;
+            let throws = self.try_keyword("throws");
+            if throws {
+                self.expect_symbol('(')?;
+                self.parse_fields(')')?;
+            }
+            self.annotations()?;
+            self.separator();
+            functions.push(Function {
+                line,
+                oneway,
+                returns,
+                name,
+                args,
+                throws,
+            });
+        }
+        self.annotations()?;
+        self.document.services.push(ThriftService {
+            line,
+            name,
+            extends,
+            functions,
+        });
+        Ok(())
+    }
+
+    /// Fields up to the `close` symbol, which is consumed.
+    fn parse_fields(&mut self, close: char) -> Result<Vec<Field>, Error> {
+        let mut fields = vec![];
+        while !self.try_symbol(close) {
+            let line = self.line();
+            let id = match self.peek() {
+                Some(Token::Number(_)) => {
+                    let id = self.integer()?;
+                    self.expect_symbol(':')?;
+                    Some(id)
+                }
+                _ => None,
+            };
+            let requiredness = ["required", "optional"]
+                .into_iter()
+                .find(|keyword| self.try_keyword(keyword))
+                .map(str::to_string);
+            let t = self.parse_type()?;
+            let name = self.expect_ident()?;
+            let has_default = self.try_symbol('=');
+            if has_default {
+                self.skip_value()?;
+            }
+            self.annotations()?;
+            self.separator();
+            fields.push(Field {
+                line,
+                id,
+                requiredness,
+                t,
+                name,
+                has_default,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn parse_type(&mut self) -> Result<ThriftType, Error> {
+        let name = self.expect_ident()?;
+        let t = match name.as_str() {
+            "list" | "set" => {
+                self.expect_symbol('<')?;
+                let element = Box::new(self.parse_type()?);
+                self.expect_symbol('>')?;
+                match name.as_str() {
+                    "list" => ThriftType::List(element),
+                    _ => ThriftType::Set(element),
+                }
+            }
+            "map" => {
+                self.expect_symbol('<')?;
+                let key = Box::new(self.parse_type()?);
+                self.expect_symbol(',')?;
+                let value = Box::new(self.parse_type()?);
+                self.expect_symbol('>')?;
+                ThriftType::Map(key, value)
+            }
+            name if BASE_TYPES.contains(&name) => ThriftType::Base(name.to_string()),
+            name => ThriftType::Named(name.to_string()),
+        };
+        if matches!(
+            t,
+            ThriftType::List(_) | ThriftType::Set(_) | ThriftType::Map(..)
+        ) && self.try_keyword("cpp_type")
+        {
+            self.expect_string()?;
+        }
+        self.annotations()?;
+        Ok(t)
+    }
+
+    fn integer(&mut self) -> Result<i64, Error> {
+        let number = match self.peek() {
+            Some(Token::Number(number)) => number.clone(),
+            _ => return Err(self.error("expected integer")),
+        };
+        let parsed = match number.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => number.strip_prefix('+').unwrap_or(&number).parse(),
+        };
+        let value = parsed.map_err(|_| self.error("expected integer"))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    /// Skips a constant value: a literal, a name, a list or a map.
+    fn skip_value(&mut self) -> Result<(), Error> {
+        match self.next() {
+            Some(Token::Number(_) | Token::String(_) | Token::Ident(_)) => Ok(()),
+            Some(Token::Symbol('[')) => {
+                while !self.try_symbol(']') {
+                    self.skip_value()?;
+                    self.separator();
+                }
+                Ok(())
+            }
+            Some(Token::Symbol('{')) => {
+                while !self.try_symbol('}') {
+                    self.skip_value()?;
+                    self.expect_symbol(':')?;
+                    self.skip_value()?;
+                    self.separator();
+                }
+                Ok(())
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected value"))
+            }
+        }
+    }
+
+    /// Skips annotations in parentheses, which protobuf has no place for.
+    fn annotations(&mut self) -> Result<(), Error> {
+        let line = self.line();
+        if !self.try_symbol('(') {
+            return Ok(());
+        }
+        while !self.try_symbol(')') {
+            self.expect_ident()?;
+            if self.try_symbol('=') {
+                self.expect_string()?;
+            }
+            self.separator();
+        }
+        self.warn(line, "annotations are not kept");
+        Ok(())
+    }
+}
+
+/// The largest field number protobuf allows.
+const MAX_FIELD_NUMBER: i64 = 536_870_911;
+
+struct Converter {
+    document: Document,
+    file: ProtoFile,
+    /// The names declared at the top level of the package, including enum
+    /// values, which protobuf scopes alongside their enum.
+    names: BTreeSet<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Converter {
+    fn new(mut document: Document, name: &str) -> Self {
+        let warnings = std::mem::take(&mut document.warnings);
+        let names = document
+            .structs
+            .iter()
+            .map(|s| s.name.clone())
+            .chain(document.enums.iter().map(|e| e.name.clone()))
+            .chain(document.services.iter().map(|s| s.name.clone()))
+            .collect();
+        Converter {
+            document,
+            file: ProtoFile {
+                name: name.to_string(),
+                syntax: Some("proto3".to_string()),
+                ..Default::default()
+            },
+            names,
+            warnings,
+        }
+    }
+
+    fn warn(&mut self, line: usize, message: impl Into<String>) {
+        self.warnings.push(Warning {
+            line,
+            message: message.into(),
+        });
+    }
+
+    fn convert(&mut self) {
+        self.namespaces();
+        for (line, path) in self.document.includes.clone() {
+            let stem = path.strip_suffix(".thrift").unwrap_or(&path);
+            let proto = format!("{}.proto", stem);
+            let message = format!(
+                "include \"{}\" becomes import \"{}\", whose types are named without their package",
+                path, proto
+            );
+            self.warn(line, message);
+            self.file.imports.push(Import {
+                path: proto,
+                public: false,
+                weak: false,
+            });
+        }
+        let enums = std::mem::take(&mut self.document.enums);
+        for e in &enums {
+            self.enumeration(e);
+        }
+        self.document.enums = enums;
+        let structs = std::mem::take(&mut self.document.structs);
+        for s in &structs {
+            self.structure(s);
+        }
+        self.document.structs = structs;
+        let services = std::mem::take(&mut self.document.services);
+        for service in &services {
+            self.service(service, &services);
+        }
+    }
+
+    /// Takes the package from the `*` namespace, or else the first, and
+    /// the options of languages protobuf knows from theirs.
+    fn namespaces(&mut self) {
+        let namespaces = std::mem::take(&mut self.document.namespaces);
+        let package = namespaces
+            .iter()
+            .find(|(_, scope, _)| scope == "*")
+            .or(namespaces.first());
+        self.file.package = package.map(|(_, _, name)| name.clone());
+        for (line, scope, name) in &namespaces {
+            let option = match scope.as_str() {
+                "java" => "java_package",
+                "go" => "go_package",
+                "csharp" | "netstd" => "csharp_namespace",
+                "php" => "php_namespace",
+                "rb" => "ruby_package",
+                _ if Some(name) == self.file.package.as_ref() => continue,
+                _ => {
+                    self.warn(*line, format!("namespace {} {} is not kept", scope, name));
+                    continue;
+                }
+            };
+            self.file.options.push(ProtoOption {
+                name: option.to_string(),
+                value: Constant::String(name.clone()),
+            });
+        }
+    }
+
+    fn enumeration(&mut self, e: &ThriftEnum) {
+        let prefix = format!("{}_", snake(&e.name).to_uppercase());
+        let mut ret = Enum {
+            name: e.name.clone(),
+            ..Default::default()
+        };
+        let mut next = 0;
+        for (line, name, number) in &e.values {
+            let number = number.unwrap_or(next);
+            next = number + 1;
+            let Ok(position) = i32::try_from(number) else {
+                self.warn(
+                    *line,
+                    format!("{} = {} does not fit an int32 and is dropped", name, number),
+                );
+                continue;
+            };
+            // Enum values share a scope with their enum's siblings.
+            let mut name = name.clone();
+            if !self.names.insert(name.clone()) {
+                let renamed = self.unique(&format!("{}{}", prefix, name));
+                self.warn(*line, format!("{} is taken and becomes {}", name, renamed));
+                name = renamed;
+            }
+            ret.fields.push(EnumField {
+                name,
+                position: position as u32,
+            });
+        }
+        match ret.fields.iter().position(|value| value.position == 0) {
+            Some(i) => {
+                let zero = ret.fields.remove(i);
+                ret.fields.insert(0, zero);
+            }
+            None => {
+                let name = self.unique(&format!("{}UNSPECIFIED", prefix));
+                let message = format!("enum {} has no value 0 and gains {}", e.name, name);
+                self.warn(e.line, message);
+                ret.fields.insert(0, EnumField { name, position: 0 });
+            }
+        }
+        let numbers: BTreeSet<u32> = ret.fields.iter().map(|value| value.position).collect();
+        if numbers.len() < ret.fields.len() {
+            ret.options.push(ProtoOption {
+                name: "allow_alias".to_string(),
+                value: Constant::Bool(true),
+            });
+        }
+        self.file.enums.push(ret);
+    }
+
+    fn structure(&mut self, s: &Struct) {
+        if s.kind == StructKind::Exception {
+            self.warn(s.line, format!("exception {} becomes a message", s.name));
+        }
+        self.define(&s.name, &s.fields, s.kind == StructKind::Union);
+    }
+
+    /// Adds a message of `fields`, those of a union in a oneof named after
+    /// it, before any messages its fields need.
+    fn define(&mut self, name: &str, fields: &[Field], union: bool) {
+        let index = self.file.messages.len();
+        let mut message = Message {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut oneof = Oneof {
+            name: snake(name),
+            ..Default::default()
+        };
+        let numbers = self.numbers(name, fields);
+        for (field, number) in fields.iter().zip(numbers) {
+            if field.has_default {
+                let warning = format!("the default of {}.{} is not kept", name, field.name);
+                self.warn(field.line, warning);
+            }
+            if field.requiredness.as_deref() == Some("required") {
+                let warning = format!(
+                    "{}.{} is required, which proto3 does not enforce",
+                    name, field.name
+                );
+                self.warn(field.line, warning);
+            }
+            if union {
+                // Oneof members can't be repeated, so containers are wrapped.
+                let t = self.value(&field.t, name, &field.name, field.line);
+                let member = MessageField::new(None, t, field.name.clone(), number);
+                oneof.fields.push(member);
+            } else {
+                let converted = self.field(name, field, number);
+                message.fields.push(converted);
+            }
+        }
+        if union {
+            message.oneofs.push(oneof);
+        }
+        self.file.messages.insert(index, message);
+    }
+
+    /// The numbers of `fields`: their IDs where protobuf allows them, and
+    /// otherwise numbers after the largest.
+    fn numbers(&mut self, owner: &str, fields: &[Field]) -> Vec<u32> {
+        let valid =
+            |id: i64| (1..=MAX_FIELD_NUMBER).contains(&id) && !(19000..=19999).contains(&id);
+        let mut used = BTreeSet::new();
+        let mut ret: Vec<Option<u32>> = fields
+            .iter()
+            .map(|field| {
+                field
+                    .id
+                    .filter(|id| valid(*id) && used.insert(*id))
+                    .map(|id| id as u32)
+            })
+            .collect();
+        let mut next = used.last().copied().unwrap_or(0) as u32;
+        for (field, number) in fields.iter().zip(&mut ret) {
+            if number.is_none() {
+                next += 1;
+                if !valid(next as i64) {
+                    next = 20000;
+                }
+                let message = match field.id {
+                    Some(id) => format!(
+                        "{}.{} has ID {}, which becomes {}",
+                        owner, field.name, id, next
+                    ),
+                    None => format!("{}.{} has no ID and becomes {}", owner, field.name, next),
+                };
+                self.warn(field.line, message);
+                *number = Some(next);
+            }
+        }
+        ret.into_iter().flatten().collect()
+    }
+
+    /// A struct field, which unlike a union member may be a container.
+    fn field(&mut self, owner: &str, field: &Field, number: u32) -> MessageField {
+        let name = field.name.clone();
+        match self.resolve(&field.t) {
+            ThriftType::List(element) => {
+                let t = self.value(&element, owner, &field.name, field.line);
+                MessageField::new(Some(Frequency::Repeated), t, name, number)
+            }
+            ThriftType::Set(element) => {
+                let message = format!(
+                    "{}.{} is a set and becomes repeated, without uniqueness",
+                    owner, field.name
+                );
+                self.warn(field.line, message);
+                let t = self.value(&element, owner, &field.name, field.line);
+                MessageField::new(Some(Frequency::Repeated), t, name, number)
+            }
+            ThriftType::Map(key, value) => match self.map_key(&key) {
+                Some(key) => {
+                    let value = self.value(&value, owner, &field.name, field.line);
+                    MessageField::new(
+                        None,
+                        Type::Map(Box::new(key), Box::new(value)),
+                        name,
+                        number,
+                    )
+                }
+                None => {
+                    let t = self.entry(owner, &field.name, &key, &value, field.line);
+                    MessageField::new(Some(Frequency::Repeated), t, name, number)
+                }
+            },
+            t => {
+                let optional = field.requiredness.as_deref() == Some("optional");
+                let t = self.value(&t, owner, &field.name, field.line);
+                MessageField::new(optional.then_some(Frequency::Optional), t, name, number)
+            }
+        }
+    }
+
+    /// Follows typedefs to the type they name.
+    fn resolve(&self, t: &ThriftType) -> ThriftType {
+        let mut t = t.clone();
+        // A bound, in case typedefs refer to each other in a cycle.
+        for _ in 0..self.document.typedefs.len() {
+            match &t {
+                ThriftType::Named(name) => match self.document.typedefs.get(name) {
+                    Some(target) => t = target.clone(),
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        t
+    }
+
+    /// The protobuf type of a single value: a scalar or named type, or a
+    /// message wrapping a container where protobuf can't have one.
+    fn value(&mut self, t: &ThriftType, owner: &str, field: &str, line: usize) -> Type {
+        match self.resolve(t) {
+            ThriftType::Base(name) => match name.as_str() {
+                "bool" => Type::Bool,
+                "byte" | "i8" | "i16" | "i32" => Type::Int32,
+                "i64" => Type::Int64,
+                "double" => Type::Double,
+                "binary" => Type::Bytes,
+                "uuid" => {
+                    self.warn(
+                        line,
+                        format!("the uuid {}.{} becomes a string", owner, field),
+                    );
+                    Type::String
+                }
+                _ => Type::String,
+            },
+            ThriftType::Named(name) => {
+                // Types from includes are named by their file's stem.
+                let simple = match self.document.includes_prefix(&name) {
+                    Some(simple) => simple,
+                    None => name.as_str(),
+                };
+                Type::Named(simple.to_string())
+            }
+            container => {
+                let wrapper = self.unique(&format!("{}{}Value", owner, upper_camel(field)));
+                let message = format!(
+                    "the {} of {}.{} is wrapped in {}",
+                    container, owner, field, wrapper
+                );
+                self.warn(line, message);
+                let value = Field {
+                    line,
+                    id: Some(1),
+                    requiredness: None,
+                    t: container,
+                    name: "value".to_string(),
+                    has_default: false,
+                };
+                self.define(&wrapper, &[value], false);
+                Type::Named(wrapper)
+            }
+        }
+    }
+
+    /// `key` as a protobuf map key, if it can be one.
+    fn map_key(&self, key: &ThriftType) -> Option<Type> {
+        match self.resolve(key) {
+            ThriftType::Base(name) => match name.as_str() {
+                "bool" => Some(Type::Bool),
+                "byte" | "i8" | "i16" | "i32" => Some(Type::Int32),
+                "i64" => Some(Type::Int64),
+                "string" => Some(Type::String),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// A message holding a key and value, for a map whose keys protobuf
+    /// doesn't allow.
+    fn entry(
+        &mut self,
+        owner: &str,
+        field: &str,
+        key: &ThriftType,
+        value: &ThriftType,
+        line: usize,
+    ) -> Type {
+        let entry = self.unique(&format!("{}{}Entry", owner, upper_camel(field)));
+        let message = format!(
+            "{}.{} has {} keys and becomes a list of {}",
+            owner, field, key, entry
+        );
+        self.warn(line, message);
+        let fields: Vec<Field> = [("key", key), ("value", value)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, t))| Field {
+                line,
+                id: Some(i as i64 + 1),
+                requiredness: None,
+                t: t.clone(),
+                name: name.to_string(),
+                has_default: false,
+            })
+            .collect();
+        self.define(&entry, &fields, false);
+        Type::Named(entry)
+    }
+
+    /// `name`, or if that is taken, `name` with the first free number.
+    fn unique(&mut self, name: &str) -> String {
+        let mut ret = name.to_string();
+        let mut i = 1;
+        while !self.names.insert(ret.clone()) {
+            i += 1;
+            ret = format!("{}{}", name, i);
+        }
+        ret
+    }
+
+    fn service(&mut self, service: &ThriftService, services: &[ThriftService]) {
+        let mut functions = vec![];
+        if let Some(base) = &service.extends {
+            match services.iter().find(|s| s.name == *base) {
+                Some(base) => functions.extend(base.functions.iter().cloned()),
+                None => {
+                    let message = format!(
+                        "service {} extends {}, whose methods are not copied",
+                        service.name, base
+                    );
+                    self.warn(service.line, message);
+                }
+            }
+        }
+        functions.extend(service.functions.iter().cloned());
+        let mut ret = Service {
+            name: service.name.clone(),
+            ..Default::default()
+        };
+        for function in &functions {
+            ret.methods.push(self.method(&service.name, function));
+        }
+        self.file.services.push(ret);
+    }
+
+    fn method(&mut self, service: &str, function: &Function) -> Method {
+        let name = upper_camel(&function.name);
+        if function.throws {
+            let message = format!(
+                "the exceptions {}.{} throws are not kept",
+                service, function.name
+            );
+            self.warn(function.line, message);
+        }
+        let request = self.unique_in(service, &format!("{}Request", name));
+        self.define(&request, &function.args, false);
+
+        let response = match &function.returns {
+            _ if function.oneway => {
+                let message = format!("the oneway {}.{} returns {}", service, function.name, EMPTY);
+                self.warn(function.line, message);
+                self.empty()
+            }
+            None => self.empty(),
+            Some(t) => match self.resolve(t) {
+                ThriftType::Named(name) if self.is_struct(&name) => name,
+                _ => {
+                    let response = self.unique_in(service, &format!("{}Response", name));
+                    let result = Field {
+                        line: function.line,
+                        id: Some(1),
+                        requiredness: None,
+                        t: t.clone(),
+                        name: "result".to_string(),
+                        has_default: false,
+                    };
+                    self.define(&response, &[result], false);
+                    response
+                }
+            },
+        };
+        Method {
+            name,
+            input_type: request,
+            output_type: response,
+            client_streaming: false,
+            server_streaming: false,
+            options: vec![],
+        }
+    }
+
+    /// `name`, or if that is taken, `name` prefixed with the service.
+    fn unique_in(&mut self, service: &str, name: &str) -> String {
+        match self.names.insert(name.to_string()) {
+            true => name.to_string(),
+            false => self.unique(&format!("{}{}", service, name)),
+        }
+    }
+
+    fn is_struct(&self, name: &str) -> bool {
+        self.document.structs.iter().any(|s| s.name == name)
+    }
+
+    fn empty(&mut self) -> String {
+        let path = "google/protobuf/empty.proto";
+        if !self.file.imports.iter().any(|import| import.path == path) {
+            self.file.imports.push(Import {
+                path: path.to_string(),
+                public: false,
+                weak: false,
+            });
+        }
+        EMPTY.to_string()
+    }
+}
+
+impl Document {
+    /// The name after an include's prefix, as in `shared.SharedStruct`.
+    fn includes_prefix<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let (prefix, rest) = name.split_once('.')?;
+        self.includes
+            .iter()
+            .any(|(_, path)| {
+                let file = path.rsplit('/').next().unwrap_or(path);
+                file.strip_suffix(".thrift") == Some(prefix)
+            })
+            .then_some(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    fn convert(source: &str) -> (String, Vec<String>) {
+        let (file, warnings) = import(source, "a.proto").unwrap();
+        let warnings = warnings.iter().map(Warning::to_string).collect();
+        (codegen::format(&file), warnings)
+    }
+
+    #[test]
+    fn golden() {
+        let source = std::fs::read_to_string("tests/thrift/tutorial.thrift").unwrap();
+        let (proto, warnings) = convert(&source);
+        let expected = std::fs::read_to_string("tests/thrift/tutorial.proto").unwrap();
+        assert_eq!(proto, expected);
+        assert_eq!(
+            warnings,
+            [
+                "5: include \"shared.thrift\" becomes import \"shared.proto\", whose types are named without their package",
+                "9: namespace py tutorial.gen is not kept",
+                "14: const INT32CONSTANT is not kept",
+                "15: const MAPCONSTANT is not kept",
+                "17: enum Operation has no value 0 and gains OPERATION_UNSPECIFIED",
+                "30: UNKNOWN is taken and becomes OUTCOME_UNKNOWN",
+                "35: the default of Work.num1 is not kept",
+                "39: Work.priority is required, which proto3 does not enforce",
+                "41: annotations are not kept",
+                "41: Work.seen is a set and becomes repeated, without uniqueness",
+                "43: the list<i32> of Work.grid is wrapped in WorkGridValue",
+                "44: Work.related has Work keys and becomes a list of WorkRelatedEntry",
+                "47: Work.legacy has no ID and becomes 13",
+                "53: the list<string> of Value.words is wrapped in ValueWordsValue",
+                "56: exception InvalidOperation becomes a message",
+                "61: service Calculator extends shared.SharedService, whose methods are not copied",
+                "64: the exceptions Calculator.calculate throws are not kept",
+                "66: the oneway Calculator.zip returns google.protobuf.Empty",
+            ]
+        );
+        // The result, with its include converted too, links and passes the
+        // checks.
+        let set = Resolver::new()
+            .include("tests/thrift")
+            .load(&["tutorial.proto"])
+            .unwrap();
+        assert_eq!(set.validate(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn field_ids_and_services() {
+        let (proto, warnings) = convert(
+            "struct Pair { -1: string a, 0: string b, 3: string c, 19500: string d }\n\
+             enum Level { LOW = 2, ALSO_LOW = 2, HIGH = 0x10 }\n\
+             struct PingRequest {}\n\
+             service Base { Pair get(1: i32 id) }\n\
+             service Derived extends Base { void ping() }\n",
+        );
+        assert_eq!(
+            proto,
+            "syntax = \"proto3\";\n\n\
+             import \"google/protobuf/empty.proto\";\n\n\
+             message Pair {\n  string a = 4;\n  string b = 5;\n  string c = 3;\n  string d = 6;\n}\n\n\
+             message PingRequest {}\n\n\
+             message GetRequest {\n  int32 id = 1;\n}\n\n\
+             message DerivedGetRequest {\n  int32 id = 1;\n}\n\n\
+             message DerivedPingRequest {}\n\n\
+             enum Level {\n  option allow_alias = true;\n\n  LEVEL_UNSPECIFIED = 0;\n  LOW = 2;\n  ALSO_LOW = 2;\n  HIGH = 16;\n}\n\n\
+             service Base {\n  rpc Get(GetRequest) returns (Pair);\n}\n\n\
+             service Derived {\n  rpc Get(DerivedGetRequest) returns (Pair);\n  rpc Ping(DerivedPingRequest) returns (google.protobuf.Empty);\n}\n"
+        );
+        assert_eq!(
+            warnings,
+            [
+                "1: Pair.a has ID -1, which becomes 4",
+                "1: Pair.b has ID 0, which becomes 5",
+                "1: Pair.d has ID 19500, which becomes 6",
+                "2: enum Level has no value 0 and gains LEVEL_UNSPECIFIED",
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let error = |source| import(source, "a.proto").unwrap_err().to_string();
+        assert_eq!(error("struct A {\n  1: i32\n}"), "3:1: expected identifier");
+        assert_eq!(error("struct A { 1: map<i32 x }"), "1:23: expected ','");
+        assert_eq!(error("/* open"), "1:1: unterminated comment");
+        assert_eq!(error("message A {}"), "1:1: unexpected message");
+    }
+}
@@ -0,0 +1,103 @@
+//! The Google well-known types and `descriptor.proto`, embedded in the crate
+//! so that importing them needs no files on disk, along with the schemas of
+//! the generators' custom options.
+
+macro_rules! bundle {
+    ($($name:literal),* $(,)?) => {
+        &[$((
+            concat!("google/protobuf/", $name),
+            include_str!(concat!("well_known/google/protobuf/", $name)),
+        )),*]
+    };
+}
+
+/// Every bundled file as `(import path, source)`.
+pub const FILES: &[(&str, &str)] = bundle![
+    "any.proto",
+    "api.proto",
+    "descriptor.proto",
+    "duration.proto",
+    "empty.proto",
+    "field_mask.proto",
+    "source_context.proto",
+    "struct.proto",
+    "timestamp.proto",
+    "type.proto",
+    "wrappers.proto",
+];
+
+/// The custom options the C and SQL generators read, as `(import path,
+/// source)`: `(nanopb).max_size` and `max_count`, and
+/// `(sql).primary_key`.
+pub const OPTIONS: &[(&str, &str)] = &[
+    ("nanopb.proto", include_str!("well_known/nanopb.proto")),
+    (
+        "protos/sql.proto",
+        include_str!("well_known/protos/sql.proto"),
+    ),
+];
+
+/// The source of a bundled file, by import path such as
+/// `google/protobuf/timestamp.proto` or `nanopb.proto`.
+pub fn source(name: &str) -> Option<&'static str> {
+    FILES
+        .iter()
+        .chain(OPTIONS)
+        .find(|(path, _)| *path == name)
+        .map(|(_, source)| *source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::FileDescriptorSet;
+    use crate::resolver::Resolver;
+
+    #[test]
+    fn every_file_links() {
+        for (name, _) in FILES.iter().chain(OPTIONS) {
+            let set = Resolver::new().load(&[name]).unwrap();
+            assert_eq!(set.files.last().unwrap().name, *name);
+        }
+    }
+
+    /// The bundled sources give the same descriptors as `protoc` does for
+    /// its own copies.
+    #[test]
+    fn matches_protoc() {
+        for name in ["struct", "type"] {
+            let path = format!("google/protobuf/{}.proto", name);
+            let set = Resolver::new().load(&[&path]).unwrap();
+            let expected = std::fs::read(format!("tests/well_known/{}.pb", name)).unwrap();
+            let actual = FileDescriptorSet::from_file_set(&set, true).encode_to_vec();
+            assert_eq!(actual, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn imports_need_no_include_dirs() {
+        let dir = std::env::temp_dir().join(format!("well-known-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("event.proto"),
+            "syntax = \"proto3\";\n\
+             import \"google/protobuf/timestamp.proto\";\n\
+             import \"google/protobuf/descriptor.proto\";\n\
+             extend google.protobuf.FieldOptions { string label = 50000; }\n\
+             message Event { google.protobuf.Timestamp at = 1 [(label) = \"when\"]; }\n",
+        )
+        .unwrap();
+
+        let set = Resolver::new().include(&dir).load(&["event.proto"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let set = set.unwrap();
+        assert!(set.find_message(".google.protobuf.Timestamp").is_some());
+        assert_eq!(set.extensions_of(".google.protobuf.FieldOptions").len(), 1);
+    }
+
+    #[test]
+    fn unknown_paths_are_not_bundled() {
+        assert!(source("google/protobuf/timestamp.proto").is_some());
+        assert!(source("google/protobuf/missing.proto").is_none());
+    }
+}
@@ -0,0 +1,1275 @@
+//! Protobuf binary wire format primitives.
+//!
+//! The free functions encode into a `Vec<u8>` and decode from the front of
+//! a byte slice. [`Writer`] and [`Reader`] wrap the same encoding around any
+//! `std::io::Write` or `std::io::Read`, including slices and vectors.
+
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum WireType {
+    Varint = 0,
+    Fixed64 = 1,
+    LengthDelimited = 2,
+    StartGroup = 3,
+    EndGroup = 4,
+    Fixed32 = 5,
+}
+
+pub fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn encode_key(field_number: u32, wire_type: WireType, buf: &mut Vec<u8>) {
+    encode_varint(((field_number as u64) << 3) | wire_type as u64, buf);
+}
+
+pub fn encode_length_delimited(field_number: u32, bytes: &[u8], buf: &mut Vec<u8>) {
+    encode_key(field_number, WireType::LengthDelimited, buf);
+    encode_varint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnexpectedEof,
+    VarintTooLong,
+    InvalidWireType(u64),
+    InvalidFieldNumber,
+    InvalidUtf8,
+    RecursionLimitExceeded,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::VarintTooLong => write!(f, "varint is longer than ten bytes"),
+            Error::InvalidWireType(wire_type) => write!(f, "invalid wire type {}", wire_type),
+            Error::InvalidFieldNumber => write!(f, "field number is out of range"),
+            Error::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            Error::RecursionLimitExceeded => {
+                write!(
+                    f,
+                    "messages or groups are nested more than {} deep",
+                    RECURSION_LIMIT
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl TryFrom<u64> for WireType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WireType::Varint),
+            1 => Ok(WireType::Fixed64),
+            2 => Ok(WireType::LengthDelimited),
+            3 => Ok(WireType::StartGroup),
+            4 => Ok(WireType::EndGroup),
+            5 => Ok(WireType::Fixed32),
+            _ => Err(Error::InvalidWireType(value)),
+        }
+    }
+}
+
+/// Decodes a varint from the front of `buf`, advancing it past the bytes
+/// read.
+pub fn decode_varint(buf: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0;
+    for i in 0..10 {
+        let (&byte, rest) = buf.split_first().ok_or(Error::UnexpectedEof)?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err(Error::VarintTooLong)
+}
+
+/// The largest field number a key may carry.
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// How deeply messages and groups may nest before decoding gives up.
+pub const RECURSION_LIMIT: usize = 100;
+
+fn split_key(key: u64) -> Result<(u32, WireType), Error> {
+    let wire_type = WireType::try_from(key & 0x7)?;
+    let field_number = key >> 3;
+    if field_number == 0 || field_number > MAX_FIELD_NUMBER as u64 {
+        return Err(Error::InvalidFieldNumber);
+    }
+    Ok((field_number as u32, wire_type))
+}
+
+pub fn decode_key(buf: &mut &[u8]) -> Result<(u32, WireType), Error> {
+    split_key(decode_varint(buf)?)
+}
+
+pub fn decode_length_delimited<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = decode_varint(buf)? as usize;
+    if len > buf.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+pub fn decode_string(buf: &mut &[u8]) -> Result<String, Error> {
+    let bytes = decode_length_delimited(buf)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidUtf8)
+}
+
+/// Skips over the value of a field whose key has already been read.
+pub fn skip_field(field_number: u32, wire_type: WireType, buf: &mut &[u8]) -> Result<(), Error> {
+    skip_nested(field_number, wire_type, buf, RECURSION_LIMIT)
+}
+
+fn skip_nested(
+    field_number: u32,
+    wire_type: WireType,
+    buf: &mut &[u8],
+    depth: usize,
+) -> Result<(), Error> {
+    let len = match wire_type {
+        WireType::Varint => {
+            decode_varint(buf)?;
+            0
+        }
+        WireType::Fixed64 => 8,
+        WireType::Fixed32 => 4,
+        WireType::LengthDelimited => {
+            decode_length_delimited(buf)?;
+            0
+        }
+        WireType::StartGroup => loop {
+            let depth = depth.checked_sub(1).ok_or(Error::RecursionLimitExceeded)?;
+            let (number, wire_type) = decode_key(buf)?;
+            if wire_type == WireType::EndGroup {
+                if number != field_number {
+                    return Err(Error::InvalidWireType(WireType::EndGroup as u64));
+                }
+                break 0;
+            }
+            skip_nested(number, wire_type, buf, depth)?;
+        },
+        WireType::EndGroup => return Err(Error::InvalidWireType(WireType::EndGroup as u64)),
+    };
+    if len > buf.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    *buf = &buf[len..];
+    Ok(())
+}
+
+/// Maps signed integers to unsigned so that small magnitudes encode
+/// small: 0, -1, 1, -2 become 0, 1, 2, 3.
+pub fn encode_zigzag32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+pub fn decode_zigzag32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+pub fn encode_zigzag64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn decode_zigzag64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+pub fn encode_fixed32(value: u32, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn encode_fixed64(value: u64, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn decode_fixed32(buf: &mut &[u8]) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    buf.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn decode_fixed64(buf: &mut &[u8]) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    buf.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// The number of bytes `encode_varint` writes for `value`.
+pub fn encoded_len_varint(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Decodes the body of a packed repeated varint field.
+pub fn decode_packed_varints(mut buf: &[u8]) -> Result<Vec<u64>, Error> {
+    let mut values = vec![];
+    while !buf.is_empty() {
+        values.push(decode_varint(&mut buf)?);
+    }
+    Ok(values)
+}
+
+pub fn decode_packed_fixed32(mut buf: &[u8]) -> Result<Vec<u32>, Error> {
+    let mut values = vec![];
+    while !buf.is_empty() {
+        values.push(decode_fixed32(&mut buf)?);
+    }
+    Ok(values)
+}
+
+pub fn decode_packed_fixed64(mut buf: &[u8]) -> Result<Vec<u64>, Error> {
+    let mut values = vec![];
+    while !buf.is_empty() {
+        values.push(decode_fixed64(&mut buf)?);
+    }
+    Ok(values)
+}
+
+/// Implemented by message types generated with [`crate::codegen::rust`].
+///
+/// Generated code supplies the field-by-field `encode`, `merge_field` and
+/// `encoded_len`; the rest is shared. Unknown fields are skipped.
+pub trait WireMessage: Default {
+    /// Writes the message's fields, without a length prefix.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Reads one field whose key has just been read, merging it into `self`.
+    /// `depth` is how many more levels of messages the field may nest.
+    fn merge_field(
+        &mut self,
+        field_number: u32,
+        wire_type: WireType,
+        buf: &mut &[u8],
+        depth: usize,
+    ) -> Result<(), Error>;
+
+    /// The number of bytes `encode` writes.
+    fn encoded_len(&self) -> usize;
+
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Merges an encoded message into `self`, as protobuf parsing does:
+    /// singular fields are overwritten and repeated fields appended.
+    fn merge(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.merge_nested(buf, RECURSION_LIMIT)
+    }
+
+    /// [`merge`](Self::merge) with `depth` more levels of messages allowed.
+    fn merge_nested(&mut self, mut buf: &[u8], depth: usize) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let (field_number, wire_type) = decode_key(&mut buf)?;
+            self.merge_field(field_number, wire_type, &mut buf, depth)?;
+        }
+        Ok(())
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut message = Self::default();
+        message.merge(buf)?;
+        Ok(message)
+    }
+}
+
+/// How one protobuf field type is represented in Rust and on the wire.
+///
+/// The implementors in [`types`] are markers named after the protobuf
+/// types, since several of them share a Rust type.
+pub trait FieldType {
+    type Value: Default;
+    const WIRE_TYPE: WireType;
+
+    fn encode_value(value: &Self::Value, buf: &mut Vec<u8>);
+    /// Reads one value; `depth` bounds how deeply messages in it may nest.
+    fn decode_value(buf: &mut &[u8], depth: usize) -> Result<Self::Value, Error>;
+    fn value_len(value: &Self::Value) -> usize;
+}
+
+pub mod types {
+    //! [`FieldType`](super::FieldType) markers for each protobuf type.
+
+    use super::*;
+    use std::marker::PhantomData;
+
+    macro_rules! varint_type {
+        ($name:ident, $value:ty, |$v:ident| $encode:expr, |$u:ident| $decode:expr) => {
+            #[derive(Debug)]
+            pub struct $name;
+
+            impl FieldType for $name {
+                type Value = $value;
+                const WIRE_TYPE: WireType = WireType::Varint;
+
+                fn encode_value(&$v: &$value, buf: &mut Vec<u8>) {
+                    encode_varint($encode, buf);
+                }
+
+                fn decode_value(buf: &mut &[u8], _: usize) -> Result<$value, Error> {
+                    let $u = decode_varint(buf)?;
+                    Ok($decode)
+                }
+
+                fn value_len(&$v: &$value) -> usize {
+                    encoded_len_varint($encode)
+                }
+            }
+        };
+    }
+
+    macro_rules! fixed_type {
+        ($name:ident, $value:ty, $wire_type:ident, $len:expr, $encode_fn:ident, $decode_fn:ident, |$v:ident| $encode:expr, |$u:ident| $decode:expr) => {
+            #[derive(Debug)]
+            pub struct $name;
+
+            impl FieldType for $name {
+                type Value = $value;
+                const WIRE_TYPE: WireType = WireType::$wire_type;
+
+                fn encode_value(&$v: &$value, buf: &mut Vec<u8>) {
+                    $encode_fn($encode, buf);
+                }
+
+                fn decode_value(buf: &mut &[u8], _: usize) -> Result<$value, Error> {
+                    let $u = $decode_fn(buf)?;
+                    Ok($decode)
+                }
+
+                fn value_len(_: &$value) -> usize {
+                    $len
+                }
+            }
+        };
+    }
+
+    varint_type!(Int32, i32, |v| v as i64 as u64, |u| u as i32);
+    varint_type!(Int64, i64, |v| v as u64, |u| u as i64);
+    varint_type!(Uint32, u32, |v| v as u64, |u| u as u32);
+    varint_type!(Uint64, u64, |v| v, |u| u);
+    varint_type!(Sint32, i32, |v| encode_zigzag32(v) as u64, |u| {
+        decode_zigzag32(u as u32)
+    });
+    varint_type!(Sint64, i64, |v| encode_zigzag64(v), |u| decode_zigzag64(u));
+    varint_type!(Bool, bool, |v| v as u64, |u| u != 0);
+    varint_type!(Enum, i32, |v| v as i64 as u64, |u| u as i32);
+    fixed_type!(
+        Fixed32,
+        u32,
+        Fixed32,
+        4,
+        encode_fixed32,
+        decode_fixed32,
+        |v| v,
+        |u| u
+    );
+    fixed_type!(
+        Fixed64,
+        u64,
+        Fixed64,
+        8,
+        encode_fixed64,
+        decode_fixed64,
+        |v| v,
+        |u| u
+    );
+    fixed_type!(
+        Sfixed32,
+        i32,
+        Fixed32,
+        4,
+        encode_fixed32,
+        decode_fixed32,
+        |v| v as u32,
+        |u| u as i32
+    );
+    fixed_type!(
+        Sfixed64,
+        i64,
+        Fixed64,
+        8,
+        encode_fixed64,
+        decode_fixed64,
+        |v| v as u64,
+        |u| u as i64
+    );
+    fixed_type!(
+        Float,
+        f32,
+        Fixed32,
+        4,
+        encode_fixed32,
+        decode_fixed32,
+        |v| v.to_bits(),
+        |u| f32::from_bits(u)
+    );
+    fixed_type!(
+        Double,
+        f64,
+        Fixed64,
+        8,
+        encode_fixed64,
+        decode_fixed64,
+        |v| v.to_bits(),
+        |u| f64::from_bits(u)
+    );
+
+    #[derive(Debug)]
+    pub struct String;
+
+    impl FieldType for String {
+        type Value = std::string::String;
+        const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+        fn encode_value(value: &Self::Value, buf: &mut Vec<u8>) {
+            encode_varint(value.len() as u64, buf);
+            buf.extend_from_slice(value.as_bytes());
+        }
+
+        fn decode_value(buf: &mut &[u8], _: usize) -> Result<Self::Value, Error> {
+            decode_string(buf)
+        }
+
+        fn value_len(value: &Self::Value) -> usize {
+            encoded_len_varint(value.len() as u64) + value.len()
+        }
+    }
+
+    /// Bytes held as `B`: `Vec<u8>` unless another type such as
+    /// `bytes::Bytes` is named.
+    #[derive(Debug)]
+    pub struct Bytes<B = Vec<u8>>(PhantomData<B>);
+
+    impl<B: From<Vec<u8>> + AsRef<[u8]> + Default> FieldType for Bytes<B> {
+        type Value = B;
+        const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+        fn encode_value(value: &B, buf: &mut Vec<u8>) {
+            let value = value.as_ref();
+            encode_varint(value.len() as u64, buf);
+            buf.extend_from_slice(value);
+        }
+
+        fn decode_value(buf: &mut &[u8], _: usize) -> Result<B, Error> {
+            Ok(decode_length_delimited(buf)?.to_vec().into())
+        }
+
+        fn value_len(value: &B) -> usize {
+            let len = value.as_ref().len();
+            encoded_len_varint(len as u64) + len
+        }
+    }
+
+    /// A nested message of type `M`, written with a length prefix.
+    #[derive(Debug)]
+    pub struct Msg<M>(PhantomData<M>);
+
+    impl<M: WireMessage> FieldType for Msg<M> {
+        type Value = M;
+        const WIRE_TYPE: WireType = WireType::LengthDelimited;
+
+        fn encode_value(value: &M, buf: &mut Vec<u8>) {
+            encode_varint(value.encoded_len() as u64, buf);
+            value.encode(buf);
+        }
+
+        fn decode_value(buf: &mut &[u8], depth: usize) -> Result<M, Error> {
+            let mut message = M::default();
+            merge_message(WireType::LengthDelimited, &mut message, buf, depth)?;
+            Ok(message)
+        }
+
+        fn value_len(value: &M) -> usize {
+            let len = value.encoded_len();
+            encoded_len_varint(len as u64) + len
+        }
+    }
+}
+
+/// The number of bytes taken by the key of field `field_number`.
+pub fn key_len(field_number: u32) -> usize {
+    encoded_len_varint((field_number as u64) << 3)
+}
+
+pub fn encode_field<T: FieldType>(field_number: u32, value: &T::Value, buf: &mut Vec<u8>) {
+    encode_key(field_number, T::WIRE_TYPE, buf);
+    T::encode_value(value, buf);
+}
+
+pub fn field_len<T: FieldType>(field_number: u32, value: &T::Value) -> usize {
+    key_len(field_number) + T::value_len(value)
+}
+
+/// Decodes a singular field's value, checking its wire type.
+pub fn decode_field<T: FieldType>(
+    wire_type: WireType,
+    buf: &mut &[u8],
+    depth: usize,
+) -> Result<T::Value, Error> {
+    if wire_type != T::WIRE_TYPE {
+        return Err(Error::InvalidWireType(wire_type as u64));
+    }
+    T::decode_value(buf, depth)
+}
+
+/// Merges a length-delimited message into an existing value, so that a
+/// message field seen twice combines both occurrences. The message takes
+/// up one level of `depth`.
+pub fn merge_message<M: WireMessage>(
+    wire_type: WireType,
+    message: &mut M,
+    buf: &mut &[u8],
+    depth: usize,
+) -> Result<(), Error> {
+    if wire_type != WireType::LengthDelimited {
+        return Err(Error::InvalidWireType(wire_type as u64));
+    }
+    let depth = depth.checked_sub(1).ok_or(Error::RecursionLimitExceeded)?;
+    message.merge_nested(decode_length_delimited(buf)?, depth)
+}
+
+/// Writes each value as its own field.
+pub fn encode_repeated<T: FieldType>(field_number: u32, values: &[T::Value], buf: &mut Vec<u8>) {
+    for value in values {
+        encode_field::<T>(field_number, value, buf);
+    }
+}
+
+pub fn repeated_len<T: FieldType>(field_number: u32, values: &[T::Value]) -> usize {
+    values
+        .iter()
+        .map(|value| field_len::<T>(field_number, value))
+        .sum()
+}
+
+/// Writes scalar values as a single packed field, or nothing when empty.
+pub fn encode_packed<T: FieldType>(field_number: u32, values: &[T::Value], buf: &mut Vec<u8>) {
+    if values.is_empty() {
+        return;
+    }
+    encode_key(field_number, WireType::LengthDelimited, buf);
+    encode_varint(values.iter().map(T::value_len).sum::<usize>() as u64, buf);
+    for value in values {
+        T::encode_value(value, buf);
+    }
+}
+
+pub fn packed_len<T: FieldType>(field_number: u32, values: &[T::Value]) -> usize {
+    if values.is_empty() {
+        return 0;
+    }
+    let len = values.iter().map(T::value_len).sum::<usize>();
+    key_len(field_number) + encoded_len_varint(len as u64) + len
+}
+
+/// Appends one occurrence of a repeated field, accepting both packed and
+/// unpacked encodings of scalars.
+pub fn merge_repeated<T: FieldType>(
+    wire_type: WireType,
+    values: &mut Vec<T::Value>,
+    buf: &mut &[u8],
+    depth: usize,
+) -> Result<(), Error> {
+    if wire_type == WireType::LengthDelimited && T::WIRE_TYPE != WireType::LengthDelimited {
+        let mut packed = decode_length_delimited(buf)?;
+        while !packed.is_empty() {
+            values.push(T::decode_value(&mut packed, depth)?);
+        }
+        return Ok(());
+    }
+    values.push(decode_field::<T>(wire_type, buf, depth)?);
+    Ok(())
+}
+
+fn entry_len<K: FieldType, V: FieldType>(key: &K::Value, value: &V::Value) -> usize {
+    field_len::<K>(1, key) + field_len::<V>(2, value)
+}
+
+/// Writes a map field as one entry message per pair.
+pub fn encode_map<'a, K, V>(
+    field_number: u32,
+    entries: impl IntoIterator<Item = (&'a K::Value, &'a V::Value)>,
+    buf: &mut Vec<u8>,
+) where
+    K: FieldType<Value: 'a>,
+    V: FieldType<Value: 'a>,
+{
+    for (key, value) in entries {
+        encode_key(field_number, WireType::LengthDelimited, buf);
+        encode_varint(entry_len::<K, V>(key, value) as u64, buf);
+        encode_field::<K>(1, key, buf);
+        encode_field::<V>(2, value, buf);
+    }
+}
+
+pub fn map_len<'a, K, V>(
+    field_number: u32,
+    entries: impl IntoIterator<Item = (&'a K::Value, &'a V::Value)>,
+) -> usize
+where
+    K: FieldType<Value: 'a>,
+    V: FieldType<Value: 'a>,
+{
+    entries
+        .into_iter()
+        .map(|(key, value)| {
+            let len = entry_len::<K, V>(key, value);
+            key_len(field_number) + encoded_len_varint(len as u64) + len
+        })
+        .sum()
+}
+
+/// Decodes one map entry; a missing key or value takes its default.
+pub fn decode_map_entry<K: FieldType, V: FieldType>(
+    wire_type: WireType,
+    buf: &mut &[u8],
+    depth: usize,
+) -> Result<(K::Value, V::Value), Error> {
+    if wire_type != WireType::LengthDelimited {
+        return Err(Error::InvalidWireType(wire_type as u64));
+    }
+    let mut entry = decode_length_delimited(buf)?;
+    let (mut key, mut value) = Default::default();
+    while !entry.is_empty() {
+        match decode_key(&mut entry)? {
+            (1, wire_type) => key = decode_field::<K>(wire_type, &mut entry, depth)?,
+            (2, wire_type) => value = decode_field::<V>(wire_type, &mut entry, depth)?,
+            (number, wire_type) => skip_field(number, wire_type, &mut entry)?,
+        }
+    }
+    Ok((key, value))
+}
+
+/// Writes protobuf fields to any `std::io::Write`.
+///
+/// The `write_*` methods write bare values; the methods named after scalar
+/// types write a whole field, key included.
+#[derive(Debug)]
+pub struct Writer<W> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Writer { inner }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    pub fn write_varint(&mut self, value: u64) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(10);
+        encode_varint(value, &mut buf);
+        self.inner.write_all(&buf)
+    }
+
+    pub fn write_key(&mut self, field_number: u32, wire_type: WireType) -> io::Result<()> {
+        self.write_varint(((field_number as u64) << 3) | wire_type as u64)
+    }
+
+    pub fn write_fixed32(&mut self, value: u32) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    pub fn write_fixed64(&mut self, value: u64) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    /// Writes a length prefix followed by `bytes`.
+    pub fn write_length_delimited(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_varint(bytes.len() as u64)?;
+        self.inner.write_all(bytes)
+    }
+
+    pub fn int32(&mut self, field_number: u32, value: i32) -> io::Result<()> {
+        self.int64(field_number, value as i64)
+    }
+
+    pub fn int64(&mut self, field_number: u32, value: i64) -> io::Result<()> {
+        self.uint64(field_number, value as u64)
+    }
+
+    pub fn uint32(&mut self, field_number: u32, value: u32) -> io::Result<()> {
+        self.uint64(field_number, value as u64)
+    }
+
+    pub fn uint64(&mut self, field_number: u32, value: u64) -> io::Result<()> {
+        self.write_key(field_number, WireType::Varint)?;
+        self.write_varint(value)
+    }
+
+    pub fn sint32(&mut self, field_number: u32, value: i32) -> io::Result<()> {
+        self.uint64(field_number, encode_zigzag32(value) as u64)
+    }
+
+    pub fn sint64(&mut self, field_number: u32, value: i64) -> io::Result<()> {
+        self.uint64(field_number, encode_zigzag64(value))
+    }
+
+    pub fn bool(&mut self, field_number: u32, value: bool) -> io::Result<()> {
+        self.uint64(field_number, value as u64)
+    }
+
+    pub fn fixed32(&mut self, field_number: u32, value: u32) -> io::Result<()> {
+        self.write_key(field_number, WireType::Fixed32)?;
+        self.write_fixed32(value)
+    }
+
+    pub fn fixed64(&mut self, field_number: u32, value: u64) -> io::Result<()> {
+        self.write_key(field_number, WireType::Fixed64)?;
+        self.write_fixed64(value)
+    }
+
+    pub fn sfixed32(&mut self, field_number: u32, value: i32) -> io::Result<()> {
+        self.fixed32(field_number, value as u32)
+    }
+
+    pub fn sfixed64(&mut self, field_number: u32, value: i64) -> io::Result<()> {
+        self.fixed64(field_number, value as u64)
+    }
+
+    pub fn float(&mut self, field_number: u32, value: f32) -> io::Result<()> {
+        self.fixed32(field_number, value.to_bits())
+    }
+
+    pub fn double(&mut self, field_number: u32, value: f64) -> io::Result<()> {
+        self.fixed64(field_number, value.to_bits())
+    }
+
+    /// Writes a `bytes` field, or an embedded message already encoded.
+    pub fn bytes(&mut self, field_number: u32, value: &[u8]) -> io::Result<()> {
+        self.write_key(field_number, WireType::LengthDelimited)?;
+        self.write_length_delimited(value)
+    }
+
+    pub fn string(&mut self, field_number: u32, value: &str) -> io::Result<()> {
+        self.bytes(field_number, value.as_bytes())
+    }
+
+    /// Writes a packed repeated field of varints. Signed values should be
+    /// converted first: `as u64` for `int32`/`int64`, zigzag for `sint*`.
+    /// Nothing is written for an empty slice.
+    pub fn packed_varints(&mut self, field_number: u32, values: &[u64]) -> io::Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut buf = vec![];
+        for value in values {
+            encode_varint(*value, &mut buf);
+        }
+        self.bytes(field_number, &buf)
+    }
+
+    pub fn packed_fixed32(&mut self, field_number: u32, values: &[u32]) -> io::Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut buf = vec![];
+        for value in values {
+            encode_fixed32(*value, &mut buf);
+        }
+        self.bytes(field_number, &buf)
+    }
+
+    pub fn packed_fixed64(&mut self, field_number: u32, values: &[u64]) -> io::Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut buf = vec![];
+        for value in values {
+            encode_fixed64(*value, &mut buf);
+        }
+        self.bytes(field_number, &buf)
+    }
+}
+
+/// Reads protobuf fields from any `std::io::Read`.
+///
+/// Call [`Reader::read_key`] until it returns `None`, then read the value
+/// with the method matching the field's type, or [`Reader::skip`] it.
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Reader { inner }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, Error> {
+        let mut byte = [0];
+        loop {
+            match self.inner.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Reads a varint, or returns `None` if the input ends before it
+    /// starts.
+    fn read_varint_or_eof(&mut self) -> Result<Option<u64>, Error> {
+        let mut value = 0;
+        for i in 0..10 {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None if i == 0 => return Ok(None),
+                None => return Err(Error::UnexpectedEof),
+            };
+            value |= ((byte & 0x7f) as u64) << (i * 7);
+            if byte < 0x80 {
+                return Ok(Some(value));
+            }
+        }
+        Err(Error::VarintTooLong)
+    }
+
+    /// Reads the next field's key, or returns `None` at the end of the
+    /// input.
+    pub fn read_key(&mut self) -> Result<Option<(u32, WireType)>, Error> {
+        let key = match self.read_varint_or_eof()? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        split_key(key).map(Some)
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, Error> {
+        self.read_varint_or_eof()?.ok_or(Error::UnexpectedEof)
+    }
+
+    pub fn read_fixed32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0; 4];
+        self.inner.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_fixed64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0; 8];
+        self.inner.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads a length prefix and that many bytes.
+    pub fn read_length_delimited(&mut self) -> Result<Vec<u8>, Error> {
+        let len = self.read_varint()?;
+        let mut bytes = vec![];
+        // Read through `take` rather than allocating `len` up front, so a
+        // corrupt length fails at the end of the input instead of
+        // exhausting memory.
+        (&mut self.inner).take(len).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(bytes)
+    }
+
+    pub fn read_int32(&mut self) -> Result<i32, Error> {
+        Ok(self.read_varint()? as i32)
+    }
+
+    pub fn read_int64(&mut self) -> Result<i64, Error> {
+        Ok(self.read_varint()? as i64)
+    }
+
+    pub fn read_uint32(&mut self) -> Result<u32, Error> {
+        Ok(self.read_varint()? as u32)
+    }
+
+    pub fn read_uint64(&mut self) -> Result<u64, Error> {
+        self.read_varint()
+    }
+
+    pub fn read_sint32(&mut self) -> Result<i32, Error> {
+        Ok(decode_zigzag32(self.read_varint()? as u32))
+    }
+
+    pub fn read_sint64(&mut self) -> Result<i64, Error> {
+        Ok(decode_zigzag64(self.read_varint()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read_varint()? != 0)
+    }
+
+    pub fn read_sfixed32(&mut self) -> Result<i32, Error> {
+        Ok(self.read_fixed32()? as i32)
+    }
+
+    pub fn read_sfixed64(&mut self) -> Result<i64, Error> {
+        Ok(self.read_fixed64()? as i64)
+    }
+
+    pub fn read_float(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.read_fixed32()?))
+    }
+
+    pub fn read_double(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_bits(self.read_fixed64()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.read_length_delimited()
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        String::from_utf8(self.read_length_delimited()?).map_err(|_| Error::InvalidUtf8)
+    }
+
+    pub fn read_packed_varints(&mut self) -> Result<Vec<u64>, Error> {
+        decode_packed_varints(&self.read_length_delimited()?)
+    }
+
+    pub fn read_packed_fixed32(&mut self) -> Result<Vec<u32>, Error> {
+        decode_packed_fixed32(&self.read_length_delimited()?)
+    }
+
+    pub fn read_packed_fixed64(&mut self) -> Result<Vec<u64>, Error> {
+        decode_packed_fixed64(&self.read_length_delimited()?)
+    }
+
+    /// Skips the value of a field whose key has just been read.
+    pub fn skip(&mut self, field_number: u32, wire_type: WireType) -> Result<(), Error> {
+        self.skip_nested(field_number, wire_type, RECURSION_LIMIT)
+    }
+
+    fn skip_nested(
+        &mut self,
+        field_number: u32,
+        wire_type: WireType,
+        depth: usize,
+    ) -> Result<(), Error> {
+        match wire_type {
+            WireType::Varint => {
+                self.read_varint()?;
+            }
+            WireType::Fixed64 => {
+                self.read_fixed64()?;
+            }
+            WireType::Fixed32 => {
+                self.read_fixed32()?;
+            }
+            WireType::LengthDelimited => {
+                self.read_length_delimited()?;
+            }
+            WireType::StartGroup => loop {
+                let depth = depth.checked_sub(1).ok_or(Error::RecursionLimitExceeded)?;
+                let (number, wire_type) = self.read_key()?.ok_or(Error::UnexpectedEof)?;
+                if wire_type == WireType::EndGroup {
+                    if number != field_number {
+                        return Err(Error::InvalidWireType(WireType::EndGroup as u64));
+                    }
+                    break;
+                }
+                self.skip_nested(number, wire_type, depth)?;
+            },
+            WireType::EndGroup => return Err(Error::InvalidWireType(WireType::EndGroup as u64)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        s.split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf = vec![];
+        encode_varint(150, &mut buf);
+        assert_eq!(buf, hex("96 01"));
+        assert_eq!(decode_varint(&mut buf.as_slice()).unwrap(), 150);
+
+        let mut buf = vec![];
+        encode_varint(-1i64 as u64, &mut buf);
+        assert_eq!(buf, hex("ff ff ff ff ff ff ff ff ff 01"));
+        assert_eq!(encoded_len_varint(-1i64 as u64), 10);
+        assert_eq!(encoded_len_varint(0), 1);
+        assert_eq!(encoded_len_varint(127), 1);
+        assert_eq!(encoded_len_varint(128), 2);
+
+        assert!(matches!(
+            decode_varint(&mut hex("96").as_slice()),
+            Err(Error::UnexpectedEof)
+        ));
+        assert!(matches!(
+            decode_varint(&mut [0xff; 11].as_slice()),
+            Err(Error::VarintTooLong)
+        ));
+    }
+
+    #[test]
+    fn zigzag() {
+        for (signed, unsigned) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (i32::MAX, u32::MAX - 1)] {
+            assert_eq!(encode_zigzag32(signed), unsigned);
+            assert_eq!(decode_zigzag32(unsigned), signed);
+        }
+        assert_eq!(encode_zigzag32(i32::MIN), u32::MAX);
+        assert_eq!(encode_zigzag64(i64::MIN), u64::MAX);
+        assert_eq!(decode_zigzag64(u64::MAX - 1), i64::MAX);
+    }
+
+    #[test]
+    fn writer() {
+        let mut writer = Writer::new(vec![]);
+        writer.int32(1, 150).unwrap();
+        writer.string(2, "testing").unwrap();
+        writer.packed_varints(4, &[3, 270, 86942]).unwrap();
+        writer.sint32(5, -2).unwrap();
+        writer.fixed32(6, 1).unwrap();
+        writer.double(7, 1.0).unwrap();
+        writer.int32(8, -1).unwrap();
+
+        assert_eq!(
+            writer.into_inner(),
+            hex("08 96 01
+                 12 07 74 65 73 74 69 6e 67
+                 22 06 03 8e 02 9e a7 05
+                 28 03
+                 35 01 00 00 00
+                 39 00 00 00 00 00 00 f0 3f
+                 40 ff ff ff ff ff ff ff ff ff 01")
+        );
+    }
+
+    #[test]
+    fn reader() {
+        let bytes = hex("08 96 01
+             12 07 74 65 73 74 69 6e 67
+             22 06 03 8e 02 9e a7 05
+             28 03
+             35 01 00 00 00
+             39 00 00 00 00 00 00 f0 3f
+             4b 08 01 4c
+             50 ff ff ff ff ff ff ff ff ff 01");
+        let mut reader = Reader::new(std::io::Cursor::new(bytes));
+
+        assert_eq!(reader.read_key().unwrap(), Some((1, WireType::Varint)));
+        assert_eq!(reader.read_int32().unwrap(), 150);
+        assert_eq!(
+            reader.read_key().unwrap(),
+            Some((2, WireType::LengthDelimited))
+        );
+        assert_eq!(reader.read_string().unwrap(), "testing");
+        assert_eq!(
+            reader.read_key().unwrap(),
+            Some((4, WireType::LengthDelimited))
+        );
+        assert_eq!(reader.read_packed_varints().unwrap(), vec![3, 270, 86942]);
+        assert_eq!(reader.read_key().unwrap(), Some((5, WireType::Varint)));
+        assert_eq!(reader.read_sint32().unwrap(), -2);
+        assert_eq!(reader.read_key().unwrap(), Some((6, WireType::Fixed32)));
+        assert_eq!(reader.read_fixed32().unwrap(), 1);
+        assert_eq!(reader.read_key().unwrap(), Some((7, WireType::Fixed64)));
+        assert_eq!(reader.read_double().unwrap(), 1.0);
+        assert_eq!(reader.read_key().unwrap(), Some((9, WireType::StartGroup)));
+        reader.skip(9, WireType::StartGroup).unwrap();
+        assert_eq!(reader.read_key().unwrap(), Some((10, WireType::Varint)));
+        assert_eq!(reader.read_int64().unwrap(), -1);
+        assert_eq!(reader.read_key().unwrap(), None);
+    }
+
+    #[test]
+    fn reader_errors() {
+        let bytes = hex("12 07 74 65");
+        let mut reader = Reader::new(bytes.as_slice());
+        reader.read_key().unwrap();
+        assert!(matches!(reader.read_string(), Err(Error::UnexpectedEof)));
+
+        let mut reader = Reader::new([0x0f].as_slice());
+        assert!(matches!(reader.read_key(), Err(Error::InvalidWireType(7))));
+
+        let mut reader = Reader::new([0x00].as_slice());
+        assert!(matches!(reader.read_key(), Err(Error::InvalidFieldNumber)));
+    }
+
+    #[test]
+    fn limits() {
+        let mut buf = vec![];
+        encode_key(MAX_FIELD_NUMBER, WireType::Varint, &mut buf);
+        assert_eq!(decode_key(&mut buf.as_slice()).unwrap().0, MAX_FIELD_NUMBER);
+        let mut buf = vec![];
+        encode_varint(((MAX_FIELD_NUMBER as u64) + 1) << 3, &mut buf);
+        assert!(matches!(
+            decode_key(&mut buf.as_slice()),
+            Err(Error::InvalidFieldNumber)
+        ));
+
+        let deep = vec![0x0b; 200_000];
+        assert!(matches!(
+            skip_field(1, WireType::StartGroup, &mut deep.as_slice()),
+            Err(Error::RecursionLimitExceeded)
+        ));
+        let mut reader = Reader::new(deep.as_slice());
+        assert!(matches!(
+            reader.skip(1, WireType::StartGroup),
+            Err(Error::RecursionLimitExceeded)
+        ));
+        let nested = hex("0b 0b 0c 0c");
+        let mut rest = &nested[1..];
+        skip_field(1, WireType::StartGroup, &mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn field_helpers() {
+        let mut buf = vec![];
+        encode_packed::<types::Sint32>(4, &[3, -2], &mut buf);
+        encode_field::<types::Bytes<std::sync::Arc<[u8]>>>(2, &b"hi"[..].into(), &mut buf);
+        encode_map::<types::String, types::Int32>(5, [(&"a".to_string(), &1)], &mut buf);
+        assert_eq!(buf, hex("22 02 06 03 12 02 68 69 2a 05 0a 01 61 10 01"));
+        assert_eq!(
+            packed_len::<types::Sint32>(4, &[3, -2])
+                + field_len::<types::Bytes>(2, &b"hi".to_vec()),
+            8
+        );
+
+        let mut rest = &buf[..];
+        let mut values = vec![];
+        decode_key(&mut rest).unwrap();
+        merge_repeated::<types::Sint32>(
+            WireType::LengthDelimited,
+            &mut values,
+            &mut rest,
+            RECURSION_LIMIT,
+        )
+        .unwrap();
+        assert_eq!(values, vec![3, -2]);
+        decode_key(&mut rest).unwrap();
+        let bytes = decode_field::<types::Bytes<std::sync::Arc<[u8]>>>(
+            WireType::LengthDelimited,
+            &mut rest,
+            RECURSION_LIMIT,
+        );
+        assert_eq!(&*bytes.unwrap(), b"hi");
+        decode_key(&mut rest).unwrap();
+        let entry = decode_map_entry::<types::String, types::Int32>(
+            WireType::LengthDelimited,
+            &mut rest,
+            RECURSION_LIMIT,
+        );
+        assert_eq!(entry.unwrap(), ("a".to_string(), 1));
+        assert!(matches!(
+            decode_field::<types::Int32>(WireType::Fixed32, &mut rest, RECURSION_LIMIT),
+            Err(Error::InvalidWireType(5))
+        ));
+    }
+
+    #[derive(Debug, Default)]
+    struct Node {
+        child: Option<Box<Node>>,
+    }
+
+    impl WireMessage for Node {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            if let Some(child) = &self.child {
+                encode_field::<types::Msg<Node>>(1, child, buf);
+            }
+        }
+
+        fn merge_field(
+            &mut self,
+            field_number: u32,
+            wire_type: WireType,
+            buf: &mut &[u8],
+            depth: usize,
+        ) -> Result<(), Error> {
+            match field_number {
+                1 => merge_message(
+                    wire_type,
+                    &mut **self.child.get_or_insert_with(Default::default),
+                    buf,
+                    depth,
+                ),
+                _ => skip_field(field_number, wire_type, buf),
+            }
+        }
+
+        fn encoded_len(&self) -> usize {
+            self.child
+                .as_ref()
+                .map_or(0, |child| field_len::<types::Msg<Node>>(1, child))
+        }
+    }
+
+    #[test]
+    fn message_recursion_limit() {
+        let nested = |levels: usize| {
+            let mut buf = vec![];
+            for _ in 0..levels {
+                let mut outer = vec![0x0a];
+                encode_varint(buf.len() as u64, &mut outer);
+                outer.extend_from_slice(&buf);
+                buf = outer;
+            }
+            buf
+        };
+        let node = Node::decode(&nested(RECURSION_LIMIT)).unwrap();
+        assert_eq!(node.encode_to_vec(), nested(RECURSION_LIMIT));
+        for levels in [RECURSION_LIMIT + 1, 100_000] {
+            assert!(matches!(
+                Node::decode(&nested(levels)),
+                Err(Error::RecursionLimitExceeded)
+            ));
+        }
+        let mut repeated = vec![];
+        encode_key(1, WireType::LengthDelimited, &mut repeated);
+        encode_varint(0, &mut repeated);
+        let mut values: Vec<Node> = vec![];
+        let mut rest = &repeated[1..];
+        assert!(matches!(
+            merge_repeated::<types::Msg<Node>>(
+                WireType::LengthDelimited,
+                &mut values,
+                &mut rest,
+                0
+            ),
+            Err(Error::RecursionLimitExceeded)
+        ));
+    }
+}
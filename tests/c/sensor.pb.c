@@ -0,0 +1,394 @@
+// Generated by protos from sensor.proto. Do not edit.
+
+#include "sensor.pb.h"
+
+#include <string.h>
+
+bool sensor_Location_encode(const sensor_Location *message, uint8_t *buf, size_t size, size_t *written) {
+    protos_writer writer = {buf, size, 0};
+    if (!sensor_Location_write(message, &writer)) {
+        return false;
+    }
+    *written = writer.pos;
+    return true;
+}
+
+bool sensor_Location_decode(sensor_Location *message, const uint8_t *buf, size_t size) {
+    protos_reader reader = {buf, size, 0};
+    memset(message, 0, sizeof *message);
+    return sensor_Location_read(message, &reader);
+}
+
+bool sensor_Location_write_delimited(const sensor_Location *message, protos_writer *writer) {
+    protos_writer counter = PROTOS_COUNTER;
+    if (!sensor_Location_write(message, &counter) || !protos_write_varint(writer, counter.pos)) {
+        return false;
+    }
+    return sensor_Location_write(message, writer);
+}
+
+bool sensor_Location_read_delimited(sensor_Location *message, protos_reader *reader) {
+    protos_reader value;
+    return protos_read_delimited(reader, &value) && sensor_Location_read(message, &value);
+}
+
+bool sensor_Location_write(const sensor_Location *message, protos_writer *writer) {
+    if (message->lat != 0) {
+        if (!protos_write_tag(writer, 1, 1) || !protos_write_double(writer, message->lat)) {
+            return false;
+        }
+    }
+    if (message->lng != 0) {
+        if (!protos_write_tag(writer, 2, 1) || !protos_write_double(writer, message->lng)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+bool sensor_Location_read(sensor_Location *message, protos_reader *reader) {
+    while (reader->pos < reader->size) {
+        uint32_t number, wire_type;
+        if (!protos_read_tag(reader, &number, &wire_type)) {
+            return false;
+        }
+        switch (number) {
+        case 1:
+            if (wire_type != 1 || !protos_read_double(reader, &message->lat)) {
+                return false;
+            }
+            break;
+        case 2:
+            if (wire_type != 1 || !protos_read_double(reader, &message->lng)) {
+                return false;
+            }
+            break;
+        default:
+            if (!protos_skip(reader, wire_type)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+bool sensor_Reading_CountersEntry_encode(const sensor_Reading_CountersEntry *message, uint8_t *buf, size_t size, size_t *written) {
+    protos_writer writer = {buf, size, 0};
+    if (!sensor_Reading_CountersEntry_write(message, &writer)) {
+        return false;
+    }
+    *written = writer.pos;
+    return true;
+}
+
+bool sensor_Reading_CountersEntry_decode(sensor_Reading_CountersEntry *message, const uint8_t *buf, size_t size) {
+    protos_reader reader = {buf, size, 0};
+    memset(message, 0, sizeof *message);
+    return sensor_Reading_CountersEntry_read(message, &reader);
+}
+
+bool sensor_Reading_CountersEntry_write_delimited(const sensor_Reading_CountersEntry *message, protos_writer *writer) {
+    protos_writer counter = PROTOS_COUNTER;
+    if (!sensor_Reading_CountersEntry_write(message, &counter) || !protos_write_varint(writer, counter.pos)) {
+        return false;
+    }
+    return sensor_Reading_CountersEntry_write(message, writer);
+}
+
+bool sensor_Reading_CountersEntry_read_delimited(sensor_Reading_CountersEntry *message, protos_reader *reader) {
+    protos_reader value;
+    return protos_read_delimited(reader, &value) && sensor_Reading_CountersEntry_read(message, &value);
+}
+
+bool sensor_Reading_CountersEntry_write(const sensor_Reading_CountersEntry *message, protos_writer *writer) {
+    if (!protos_write_tag(writer, 1, 2) || !protos_write_string(writer, message->key)) {
+        return false;
+    }
+    if (!protos_write_tag(writer, 2, 0) || !protos_write_int32(writer, message->value)) {
+        return false;
+    }
+    return true;
+}
+
+bool sensor_Reading_CountersEntry_read(sensor_Reading_CountersEntry *message, protos_reader *reader) {
+    while (reader->pos < reader->size) {
+        uint32_t number, wire_type;
+        if (!protos_read_tag(reader, &number, &wire_type)) {
+            return false;
+        }
+        switch (number) {
+        case 1:
+            if (wire_type != 2 || !protos_read_string(reader, message->key, sizeof message->key)) {
+                return false;
+            }
+            break;
+        case 2:
+            if (wire_type != 0 || !protos_read_int32(reader, &message->value)) {
+                return false;
+            }
+            break;
+        default:
+            if (!protos_skip(reader, wire_type)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+bool sensor_Reading_encode(const sensor_Reading *message, uint8_t *buf, size_t size, size_t *written) {
+    protos_writer writer = {buf, size, 0};
+    if (!sensor_Reading_write(message, &writer)) {
+        return false;
+    }
+    *written = writer.pos;
+    return true;
+}
+
+bool sensor_Reading_decode(sensor_Reading *message, const uint8_t *buf, size_t size) {
+    protos_reader reader = {buf, size, 0};
+    memset(message, 0, sizeof *message);
+    return sensor_Reading_read(message, &reader);
+}
+
+bool sensor_Reading_write_delimited(const sensor_Reading *message, protos_writer *writer) {
+    protos_writer counter = PROTOS_COUNTER;
+    if (!sensor_Reading_write(message, &counter) || !protos_write_varint(writer, counter.pos)) {
+        return false;
+    }
+    return sensor_Reading_write(message, writer);
+}
+
+bool sensor_Reading_read_delimited(sensor_Reading *message, protos_reader *reader) {
+    protos_reader value;
+    return protos_read_delimited(reader, &value) && sensor_Reading_read(message, &value);
+}
+
+bool sensor_Reading_write(const sensor_Reading *message, protos_writer *writer) {
+    if (message->device[0] != '\0') {
+        if (!protos_write_tag(writer, 1, 2) || !protos_write_string(writer, message->device)) {
+            return false;
+        }
+    }
+    if (message->sequence != 0) {
+        if (!protos_write_tag(writer, 2, 0) || !protos_write_uint32(writer, message->sequence)) {
+            return false;
+        }
+    }
+    if (message->offset != 0) {
+        if (!protos_write_tag(writer, 3, 0) || !protos_write_sint64(writer, message->offset)) {
+            return false;
+        }
+    }
+    if (message->has_temperature) {
+        if (!protos_write_tag(writer, 4, 5) || !protos_write_float(writer, message->temperature)) {
+            return false;
+        }
+    }
+    if (message->samples_count > 0) {
+        protos_writer counter = PROTOS_COUNTER;
+        for (size_t i = 0; i < message->samples_count; i++) {
+            protos_write_int32(&counter, message->samples[i]);
+        }
+        if (!protos_write_tag(writer, 5, 2) || !protos_write_varint(writer, counter.pos)) {
+            return false;
+        }
+        for (size_t i = 0; i < message->samples_count; i++) {
+            if (!protos_write_int32(writer, message->samples[i])) {
+                return false;
+            }
+        }
+    }
+    if (message->signature.size != 0) {
+        if (!protos_write_tag(writer, 6, 2) || !protos_write_bytes(writer, message->signature.bytes, message->signature.size)) {
+            return false;
+        }
+    }
+    if (message->has_location) {
+        if (!protos_write_tag(writer, 7, 2) || !sensor_Location_write_delimited(&message->location, writer)) {
+            return false;
+        }
+    }
+    if (message->status != 0) {
+        if (!protos_write_tag(writer, 8, 0) || !protos_write_int32(writer, (int32_t)message->status)) {
+            return false;
+        }
+    }
+    if (message->which_payload == 9) {
+        if (!protos_write_tag(writer, 9, 2) || !protos_write_string(writer, message->payload.note)) {
+            return false;
+        }
+    }
+    if (message->which_payload == 10) {
+        if (!protos_write_tag(writer, 10, 2) || !sensor_Location_write_delimited(&message->payload.origin, writer)) {
+            return false;
+        }
+    }
+    if (message->which_payload == 11) {
+        if (!protos_write_tag(writer, 11, 5) || !protos_write_fixed32(writer, message->payload.code)) {
+            return false;
+        }
+    }
+    for (size_t i = 0; i < message->counters_count; i++) {
+        if (!protos_write_tag(writer, 12, 2) || !sensor_Reading_CountersEntry_write_delimited(&message->counters[i], writer)) {
+            return false;
+        }
+    }
+    for (size_t i = 0; i < message->tags_count; i++) {
+        if (!protos_write_tag(writer, 13, 2) || !protos_write_string(writer, message->tags[i])) {
+            return false;
+        }
+    }
+    if (message->urgent != 0) {
+        if (!protos_write_tag(writer, 14, 0) || !protos_write_bool(writer, message->urgent)) {
+            return false;
+        }
+    }
+    for (size_t i = 0; i < message->trail_count; i++) {
+        if (!protos_write_tag(writer, 15, 2) || !sensor_Location_write_delimited(&message->trail[i], writer)) {
+            return false;
+        }
+    }
+    if (message->check != 0) {
+        if (!protos_write_tag(writer, 16, 0) || !protos_write_int32(writer, (int32_t)message->check)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+bool sensor_Reading_read(sensor_Reading *message, protos_reader *reader) {
+    while (reader->pos < reader->size) {
+        uint32_t number, wire_type;
+        if (!protos_read_tag(reader, &number, &wire_type)) {
+            return false;
+        }
+        switch (number) {
+        case 1:
+            if (wire_type != 2 || !protos_read_string(reader, message->device, sizeof message->device)) {
+                return false;
+            }
+            break;
+        case 2:
+            if (wire_type != 0 || !protos_read_uint32(reader, &message->sequence)) {
+                return false;
+            }
+            break;
+        case 3:
+            if (wire_type != 0 || !protos_read_sint64(reader, &message->offset)) {
+                return false;
+            }
+            break;
+        case 4:
+            if (wire_type != 5 || !protos_read_float(reader, &message->temperature)) {
+                return false;
+            }
+            message->has_temperature = true;
+            break;
+        case 5:
+            if (wire_type == 2) {
+                protos_reader packed;
+                if (!protos_read_delimited(reader, &packed)) {
+                    return false;
+                }
+                while (packed.pos < packed.size) {
+                    if (message->samples_count == 8) {
+                        return false;
+                    }
+                    if (!protos_read_int32(&packed, &message->samples[message->samples_count])) {
+                        return false;
+                    }
+                    message->samples_count++;
+                }
+            } else {
+                if (wire_type != 0 || message->samples_count == 8) {
+                    return false;
+                }
+                if (!protos_read_int32(reader, &message->samples[message->samples_count])) {
+                    return false;
+                }
+                message->samples_count++;
+            }
+            break;
+        case 6:
+            if (wire_type != 2 || !protos_read_bytes(reader, message->signature.bytes, sizeof message->signature.bytes, &message->signature.size)) {
+                return false;
+            }
+            break;
+        case 7:
+            if (wire_type != 2 || !sensor_Location_read_delimited(&message->location, reader)) {
+                return false;
+            }
+            message->has_location = true;
+            break;
+        case 8:
+            if (wire_type != 0 || !sensor_Status_read(&message->status, reader)) {
+                return false;
+            }
+            break;
+        case 9:
+            if (wire_type != 2 || !protos_read_string(reader, message->payload.note, sizeof message->payload.note)) {
+                return false;
+            }
+            message->which_payload = 9;
+            break;
+        case 10:
+            if (message->which_payload != 10) {
+                memset(&message->payload.origin, 0, sizeof message->payload.origin);
+            }
+            if (wire_type != 2 || !sensor_Location_read_delimited(&message->payload.origin, reader)) {
+                return false;
+            }
+            message->which_payload = 10;
+            break;
+        case 11:
+            if (wire_type != 5 || !protos_read_fixed32(reader, &message->payload.code)) {
+                return false;
+            }
+            message->which_payload = 11;
+            break;
+        case 12:
+            if (wire_type != 2 || message->counters_count == 4) {
+                return false;
+            }
+            if (!sensor_Reading_CountersEntry_read_delimited(&message->counters[message->counters_count], reader)) {
+                return false;
+            }
+            message->counters_count++;
+            break;
+        case 13:
+            if (wire_type != 2 || message->tags_count == 3) {
+                return false;
+            }
+            if (!protos_read_string(reader, message->tags[message->tags_count], sizeof message->tags[message->tags_count])) {
+                return false;
+            }
+            message->tags_count++;
+            break;
+        case 14:
+            if (wire_type != 0 || !protos_read_bool(reader, &message->urgent)) {
+                return false;
+            }
+            break;
+        case 15:
+            if (wire_type != 2 || message->trail_count == 2) {
+                return false;
+            }
+            if (!sensor_Location_read_delimited(&message->trail[message->trail_count], reader)) {
+                return false;
+            }
+            message->trail_count++;
+            break;
+        case 16:
+            if (wire_type != 0 || !sensor_Reading_Check_read(&message->check, reader)) {
+                return false;
+            }
+            break;
+        default:
+            if (!protos_skip(reader, wire_type)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
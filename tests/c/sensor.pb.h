@@ -0,0 +1,115 @@
+// Generated by protos from sensor.proto. Do not edit.
+
+#ifndef PROTOS_SENSOR_PB_H
+#define PROTOS_SENSOR_PB_H
+
+#include "protos.h"
+
+typedef enum _sensor_Status {
+    sensor_Status_STATUS_UNKNOWN = 0,
+    sensor_Status_STATUS_OK = 1,
+    sensor_Status_STATUS_FAULT = 2,
+} sensor_Status;
+
+static inline bool sensor_Status_read(sensor_Status *value, protos_reader *reader) {
+    int32_t number;
+    if (!protos_read_int32(reader, &number)) {
+        return false;
+    }
+    *value = (sensor_Status)number;
+    return true;
+}
+
+// Whether the reading passed calibration.
+typedef enum _sensor_Reading_Check {
+    sensor_Reading_Check_CHECK_NONE = 0,
+    sensor_Reading_Check_CHECK_PASSED = 1,
+} sensor_Reading_Check;
+
+static inline bool sensor_Reading_Check_read(sensor_Reading_Check *value, protos_reader *reader) {
+    int32_t number;
+    if (!protos_read_int32(reader, &number)) {
+        return false;
+    }
+    *value = (sensor_Reading_Check)number;
+    return true;
+}
+
+// Where a reading was taken.
+typedef struct _sensor_Location {
+    double lat;
+    double lng;
+} sensor_Location;
+
+typedef struct _sensor_Reading_CountersEntry {
+    char key[8];
+    int32_t value;
+} sensor_Reading_CountersEntry;
+
+typedef struct {
+    size_t size;
+    uint8_t bytes[32];
+} sensor_Reading_signature_t;
+
+// One measurement from a device.
+typedef struct _sensor_Reading {
+    char device[16];
+    uint32_t sequence;
+    int64_t offset;
+    // Degrees Celsius, if measured.
+    bool has_temperature;
+    float temperature;
+    size_t samples_count;
+    int32_t samples[8];
+    sensor_Reading_signature_t signature;
+    bool has_location;
+    sensor_Location location;
+    sensor_Status status;
+    size_t counters_count;
+    sensor_Reading_CountersEntry counters[4];
+    size_t tags_count;
+    char tags[3][10];
+    bool urgent;
+    size_t trail_count;
+    sensor_Location trail[2];
+    sensor_Reading_Check check;
+    uint32_t which_payload;
+    union {
+        char note[24];
+        sensor_Location origin;
+        uint32_t code;
+    } payload;
+} sensor_Reading;
+
+// The most bytes an encoded message of each type can take.
+#define sensor_Location_size 18
+#define sensor_Reading_size 386
+#define sensor_Reading_CountersEntry_size 20
+
+// encode and decode work on whole buffers, write and read on the
+// runtime's writers and readers. decode clears the message first,
+// while read merges into it. All fail rather than overrun a buffer
+// or a field's bounds.
+
+bool sensor_Location_encode(const sensor_Location *message, uint8_t *buf, size_t size, size_t *written);
+bool sensor_Location_decode(sensor_Location *message, const uint8_t *buf, size_t size);
+bool sensor_Location_write(const sensor_Location *message, protos_writer *writer);
+bool sensor_Location_read(sensor_Location *message, protos_reader *reader);
+bool sensor_Location_write_delimited(const sensor_Location *message, protos_writer *writer);
+bool sensor_Location_read_delimited(sensor_Location *message, protos_reader *reader);
+
+bool sensor_Reading_CountersEntry_encode(const sensor_Reading_CountersEntry *message, uint8_t *buf, size_t size, size_t *written);
+bool sensor_Reading_CountersEntry_decode(sensor_Reading_CountersEntry *message, const uint8_t *buf, size_t size);
+bool sensor_Reading_CountersEntry_write(const sensor_Reading_CountersEntry *message, protos_writer *writer);
+bool sensor_Reading_CountersEntry_read(sensor_Reading_CountersEntry *message, protos_reader *reader);
+bool sensor_Reading_CountersEntry_write_delimited(const sensor_Reading_CountersEntry *message, protos_writer *writer);
+bool sensor_Reading_CountersEntry_read_delimited(sensor_Reading_CountersEntry *message, protos_reader *reader);
+
+bool sensor_Reading_encode(const sensor_Reading *message, uint8_t *buf, size_t size, size_t *written);
+bool sensor_Reading_decode(sensor_Reading *message, const uint8_t *buf, size_t size);
+bool sensor_Reading_write(const sensor_Reading *message, protos_writer *writer);
+bool sensor_Reading_read(sensor_Reading *message, protos_reader *reader);
+bool sensor_Reading_write_delimited(const sensor_Reading *message, protos_writer *writer);
+bool sensor_Reading_read_delimited(sensor_Reading *message, protos_reader *reader);
+
+#endif
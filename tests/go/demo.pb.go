@@ -0,0 +1,94 @@
+// Code generated by protos from demo.proto. DO NOT EDIT.
+
+package demo
+
+import "strconv"
+
+type Color int32
+
+const (
+	Color_RED     Color = 0
+	Color_CRIMSON Color = 0
+	Color_GREEN   Color = 1
+)
+
+// String returns the value's name in the schema, or its number if
+// the schema doesn't name it.
+func (x Color) String() string {
+	switch x {
+	case Color_RED:
+		return "RED"
+	case Color_GREEN:
+		return "GREEN"
+	}
+	return strconv.Itoa(int(x))
+}
+
+// A person in the address book.
+type Person struct {
+	Name    string           `protobuf:"bytes,1,opt,name=name,proto3" json:"name,omitempty"`
+	Id      int32            `protobuf:"varint,2,opt,name=id,proto3" json:"id,omitempty"`
+	Emails  []string         `protobuf:"bytes,3,rep,name=emails,proto3" json:"emails,omitempty"`
+	Scores  map[string]int64 `protobuf:"bytes,4,rep,name=scores,proto3" json:"scores,omitempty" protobuf_key:"bytes,1,opt,name=key,proto3" protobuf_val:"zigzag64,2,opt,name=value,proto3"`
+	Address *Person_Address  `protobuf:"bytes,5,opt,name=address,proto3" json:"address,omitempty"`
+	// How we know them.
+	Kind    Person_Kind               `protobuf:"varint,6,opt,name=kind,proto3,enum=demo.Person_Kind" json:"kind,omitempty"`
+	Height  *float64                  `protobuf:"fixed64,9,opt,name=height,proto3,oneof" json:"height,omitempty"`
+	Lucky   []uint32                  `protobuf:"fixed32,10,rep,packed,name=lucky,proto3" json:"lucky,omitempty"`
+	Counts  []int64                   `protobuf:"varint,11,rep,name=counts,proto3" json:"counts,omitempty"`
+	Photo   []byte                    `protobuf:"bytes,12,opt,name=photo,proto3" json:"photo,omitempty"`
+	Friends []*Person                 `protobuf:"bytes,13,rep,name=friends,proto3" json:"friends,omitempty"`
+	Homes   map[int32]*Person_Address `protobuf:"bytes,14,rep,name=homes,proto3" json:"homes,omitempty" protobuf_key:"varint,1,opt,name=key,proto3" protobuf_val:"bytes,2,opt,name=value,proto3"`
+	Active  bool                      `protobuf:"varint,15,opt,name=active,proto3" json:"active,omitempty"`
+	Type    string                    `protobuf:"bytes,16,opt,name=type,proto3" json:"type,omitempty"`
+	// Types that are assignable to Contact:
+	//
+	//	*Person_Phone
+	//	*Person_Office
+	Contact isPerson_Contact `protobuf_oneof:"contact"`
+}
+
+type isPerson_Contact interface {
+	isPerson_Contact()
+}
+
+type Person_Phone struct {
+	Phone string `protobuf:"bytes,7,opt,name=phone,proto3,oneof"`
+}
+
+type Person_Office struct {
+	Office *Person_Address `protobuf:"bytes,8,opt,name=office,proto3,oneof"`
+}
+
+func (*Person_Phone) isPerson_Contact() {}
+
+func (*Person_Office) isPerson_Contact() {}
+
+type Person_Kind int32
+
+const (
+	Person_KIND_UNKNOWN Person_Kind = 0
+	Person_KIND_FRIEND  Person_Kind = 1
+	Person_KIND_FAMILY  Person_Kind = 2
+)
+
+// String returns the value's name in the schema, or its number if
+// the schema doesn't name it.
+func (x Person_Kind) String() string {
+	switch x {
+	case Person_KIND_UNKNOWN:
+		return "KIND_UNKNOWN"
+	case Person_KIND_FRIEND:
+		return "KIND_FRIEND"
+	case Person_KIND_FAMILY:
+		return "KIND_FAMILY"
+	}
+	return strconv.Itoa(int(x))
+}
+
+type Person_Address struct {
+	City  string  `protobuf:"bytes,1,opt,name=city,proto3" json:"city,omitempty"`
+	Owner *Person `protobuf:"bytes,2,opt,name=owner,proto3" json:"owner,omitempty"`
+}
+
+type Empty struct{}
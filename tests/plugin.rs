@@ -0,0 +1,88 @@
+//! Runs the built-in generators as `protoc` plugins, when `protoc` is
+//! installed.
+
+use std::path::Path;
+use std::process::Command;
+
+fn has_protoc() -> bool {
+    Command::new("protoc").arg("--version").output().is_ok()
+}
+
+/// A `protoc-gen-protos_<lang>` wrapper that runs `protos plugin --lang
+/// <lang>`, named apart from `protoc`'s own generators.
+fn wrapper(dir: &Path, lang: &str) -> std::path::PathBuf {
+    let path = dir.join(format!("protoc-gen-protos_{}", lang));
+    let script = format!(
+        "#!/bin/sh\nexec '{}' plugin --lang {}\n",
+        env!("CARGO_BIN_EXE_protos"),
+        lang
+    );
+    std::fs::write(&path, script).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    path
+}
+
+#[test]
+fn generates_through_protoc() {
+    if !has_protoc() {
+        eprintln!("skipping generates_through_protoc: protoc is not installed");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("protos-plugin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cases = [
+        (
+            "go",
+            "tests/rust",
+            "demo.proto",
+            "demo.pb.go",
+            "tests/go/demo.pb.go",
+        ),
+        (
+            "openapi",
+            "tests/openapi",
+            "library.proto",
+            "library.openapi.json",
+            "tests/openapi/library.openapi.json",
+        ),
+    ];
+    for (lang, include, file, generated, golden) in cases {
+        let plugin = wrapper(&dir, lang);
+        let output = Command::new("protoc")
+            .arg(format!("-I{}", include))
+            .arg("-Isrc/well_known")
+            .arg(format!("--plugin={}", plugin.display()))
+            .arg(format!("--protos_{}_out={}", lang, dir.display()))
+            .arg(file)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join(generated)).unwrap(),
+            std::fs::read_to_string(golden).unwrap(),
+            "{}",
+            generated
+        );
+    }
+
+    let plugin = wrapper(&dir, "python");
+    let output = Command::new("protoc")
+        .arg("-Itests/rust")
+        .arg(format!("--plugin={}", plugin.display()))
+        .arg(format!("--protos_python_out=bogus:{}", dir.display()))
+        .arg("demo.proto")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("unknown python generator parameter bogus"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,215 @@
+# @generated by protos from demo.proto. Do not edit.
+
+from __future__ import annotations
+
+import dataclasses
+import enum
+from typing import Optional
+
+import protos_runtime as _protos_runtime
+
+
+class Color(enum.IntEnum):
+    RED = 0
+    CRIMSON = 0
+    GREEN = 1
+
+
+@dataclasses.dataclass
+class Person:
+    """A person in the address book."""
+
+    class Kind(enum.IntEnum):
+        KIND_UNKNOWN = 0
+        KIND_FRIEND = 1
+        KIND_FAMILY = 2
+
+    @dataclasses.dataclass
+    class Address:
+        city: str = ""
+        owner: Optional[Person] = None
+
+        def encode(self) -> bytes:
+            writer = _protos_runtime.Writer()
+            if self.city:
+                writer.tag(1, 2).string(self.city)
+            if self.owner is not None:
+                writer.tag(2, 2).bytes(self.owner.encode())
+            return writer.finish()
+
+        @classmethod
+        def decode(cls, data: bytes) -> Person.Address:
+            message = cls()
+            reader = _protos_runtime.Reader(data)
+            while not reader.done:
+                tag = reader.uint32()
+                number = tag >> 3
+                if number == 1:
+                    message.city = reader.string()
+                elif number == 2:
+                    message.owner = Person.decode(reader.bytes())
+                else:
+                    reader.skip(tag & 7)
+            return message
+
+    name: str = ""
+    id: int = 0
+    emails: list[str] = dataclasses.field(default_factory=list)
+    scores: dict[str, int] = dataclasses.field(default_factory=dict)
+    address: Optional[Person.Address] = None
+    # How we know them.
+    kind: Person.Kind = Kind.KIND_UNKNOWN
+    height: Optional[float] = None
+    lucky: list[int] = dataclasses.field(default_factory=list)
+    counts: list[int] = dataclasses.field(default_factory=list)
+    photo: bytes = b""
+    friends: list[Person] = dataclasses.field(default_factory=list)
+    homes: dict[int, Person.Address] = dataclasses.field(default_factory=dict)
+    active: bool = False
+    type: str = ""
+    phone: Optional[str] = None
+    office: Optional[Person.Address] = None
+
+    def which_contact(self) -> Optional[str]:
+        """The name of the member of `contact` that is set, if any."""
+        if self.phone is not None:
+            return "phone"
+        if self.office is not None:
+            return "office"
+        return None
+
+    def encode(self) -> bytes:
+        writer = _protos_runtime.Writer()
+        if self.name:
+            writer.tag(1, 2).string(self.name)
+        if self.id:
+            writer.tag(2, 0).int32(self.id)
+        for value in self.emails:
+            writer.tag(3, 2).string(value)
+        for key, value in self.scores.items():
+            entry = _protos_runtime.Writer()
+            entry.tag(1, 2).string(key)
+            entry.tag(2, 0).sint64(value)
+            writer.tag(4, 2).bytes(entry.finish())
+        if self.address is not None:
+            writer.tag(5, 2).bytes(self.address.encode())
+        if self.kind:
+            writer.tag(6, 0).int32(self.kind)
+        if self.phone is not None:
+            writer.tag(7, 2).string(self.phone)
+        if self.office is not None:
+            writer.tag(8, 2).bytes(self.office.encode())
+        if self.height is not None:
+            writer.tag(9, 1).double(self.height)
+        if self.lucky:
+            packed = _protos_runtime.Writer()
+            for value in self.lucky:
+                packed.fixed32(value)
+            writer.tag(10, 2).bytes(packed.finish())
+        for value in self.counts:
+            writer.tag(11, 0).int64(value)
+        if self.photo:
+            writer.tag(12, 2).bytes(self.photo)
+        for value in self.friends:
+            writer.tag(13, 2).bytes(value.encode())
+        for key, value in self.homes.items():
+            entry = _protos_runtime.Writer()
+            entry.tag(1, 0).int32(key)
+            entry.tag(2, 2).bytes(value.encode())
+            writer.tag(14, 2).bytes(entry.finish())
+        if self.active:
+            writer.tag(15, 0).bool(self.active)
+        if self.type:
+            writer.tag(16, 2).string(self.type)
+        return writer.finish()
+
+    @classmethod
+    def decode(cls, data: bytes) -> Person:
+        message = cls()
+        reader = _protos_runtime.Reader(data)
+        while not reader.done:
+            tag = reader.uint32()
+            number = tag >> 3
+            if number == 1:
+                message.name = reader.string()
+            elif number == 2:
+                message.id = reader.int32()
+            elif number == 3:
+                message.emails.append(reader.string())
+            elif number == 4:
+                entry = _protos_runtime.Reader(reader.bytes())
+                key, value = "", 0
+                while not entry.done:
+                    entry_tag = entry.uint32()
+                    if entry_tag >> 3 == 1:
+                        key = entry.string()
+                    elif entry_tag >> 3 == 2:
+                        value = entry.sint64()
+                    else:
+                        entry.skip(entry_tag & 7)
+                message.scores[key] = value
+            elif number == 5:
+                message.address = Person.Address.decode(reader.bytes())
+            elif number == 6:
+                message.kind = _protos_runtime.enum_value(Person.Kind, reader.int32())
+            elif number == 7:
+                message.phone = reader.string()
+                message.office = None
+            elif number == 8:
+                message.office = Person.Address.decode(reader.bytes())
+                message.phone = None
+            elif number == 9:
+                message.height = reader.double()
+            elif number == 10:
+                if tag & 7 == 2:
+                    end = reader.uint32() + reader.pos
+                    while reader.pos < end:
+                        message.lucky.append(reader.fixed32())
+                else:
+                    message.lucky.append(reader.fixed32())
+            elif number == 11:
+                if tag & 7 == 2:
+                    end = reader.uint32() + reader.pos
+                    while reader.pos < end:
+                        message.counts.append(reader.int64())
+                else:
+                    message.counts.append(reader.int64())
+            elif number == 12:
+                message.photo = reader.bytes()
+            elif number == 13:
+                message.friends.append(Person.decode(reader.bytes()))
+            elif number == 14:
+                entry = _protos_runtime.Reader(reader.bytes())
+                key, value = 0, Person.Address()
+                while not entry.done:
+                    entry_tag = entry.uint32()
+                    if entry_tag >> 3 == 1:
+                        key = entry.int32()
+                    elif entry_tag >> 3 == 2:
+                        value = Person.Address.decode(entry.bytes())
+                    else:
+                        entry.skip(entry_tag & 7)
+                message.homes[key] = value
+            elif number == 15:
+                message.active = reader.bool()
+            elif number == 16:
+                message.type = reader.string()
+            else:
+                reader.skip(tag & 7)
+        return message
+
+
+@dataclasses.dataclass
+class Empty:
+    def encode(self) -> bytes:
+        writer = _protos_runtime.Writer()
+        return writer.finish()
+
+    @classmethod
+    def decode(cls, data: bytes) -> Empty:
+        message = cls()
+        reader = _protos_runtime.Reader(data)
+        while not reader.done:
+            tag = reader.uint32()
+            reader.skip(tag & 7)
+        return message
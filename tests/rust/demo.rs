@@ -0,0 +1,302 @@
+// @generated by protos from demo.proto. Do not edit.
+
+/// A person in the address book.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Person {
+    pub name: ::std::string::String,
+    pub id: i32,
+    pub emails: ::std::vec::Vec<::std::string::String>,
+    pub scores: ::std::collections::HashMap<::std::string::String, i64>,
+    pub address: ::core::option::Option<::std::boxed::Box<person::Address>>,
+    /// How we know them.
+    pub kind: i32,
+    pub height: ::core::option::Option<f64>,
+    pub lucky: ::std::vec::Vec<u32>,
+    pub counts: ::std::vec::Vec<i64>,
+    pub photo: ::std::vec::Vec<u8>,
+    pub friends: ::std::vec::Vec<Person>,
+    pub homes: ::std::collections::HashMap<i32, person::Address>,
+    pub active: bool,
+    pub r#type: ::std::string::String,
+    pub contact: ::core::option::Option<person::Contact>,
+}
+
+impl Person {
+    /// `kind` as a `person::Kind`, or the enum's default if the value is unknown.
+    pub fn kind(&self) -> person::Kind {
+        <person::Kind as ::core::convert::TryFrom<i32>>::try_from(self.kind).unwrap_or_default()
+    }
+
+    pub fn set_kind(&mut self, value: person::Kind) {
+        self.kind = value as i32;
+    }
+}
+
+impl crate::wire::WireMessage for Person {
+    fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+        if !self.name.is_empty() {
+            crate::wire::encode_field::<crate::wire::types::String>(1, &self.name, buf);
+        }
+        if self.id != 0 {
+            crate::wire::encode_field::<crate::wire::types::Int32>(2, &self.id, buf);
+        }
+        crate::wire::encode_repeated::<crate::wire::types::String>(3, &self.emails, buf);
+        crate::wire::encode_map::<crate::wire::types::String, crate::wire::types::Sint64>(4, &self.scores, buf);
+        if let ::core::option::Option::Some(value) = &self.address {
+            crate::wire::encode_field::<crate::wire::types::Msg<person::Address>>(5, value, buf);
+        }
+        if self.kind != 0 {
+            crate::wire::encode_field::<crate::wire::types::Enum>(6, &self.kind, buf);
+        }
+        if let ::core::option::Option::Some(person::Contact::Phone(value)) = &self.contact {
+            crate::wire::encode_field::<crate::wire::types::String>(7, value, buf);
+        }
+        if let ::core::option::Option::Some(person::Contact::Office(value)) = &self.contact {
+            crate::wire::encode_field::<crate::wire::types::Msg<person::Address>>(8, value, buf);
+        }
+        if let ::core::option::Option::Some(value) = &self.height {
+            crate::wire::encode_field::<crate::wire::types::Double>(9, value, buf);
+        }
+        crate::wire::encode_packed::<crate::wire::types::Fixed32>(10, &self.lucky, buf);
+        crate::wire::encode_repeated::<crate::wire::types::Int64>(11, &self.counts, buf);
+        if !self.photo.is_empty() {
+            crate::wire::encode_field::<crate::wire::types::Bytes>(12, &self.photo, buf);
+        }
+        crate::wire::encode_repeated::<crate::wire::types::Msg<Person>>(13, &self.friends, buf);
+        crate::wire::encode_map::<crate::wire::types::Int32, crate::wire::types::Msg<person::Address>>(14, &self.homes, buf);
+        if self.active {
+            crate::wire::encode_field::<crate::wire::types::Bool>(15, &self.active, buf);
+        }
+        if !self.r#type.is_empty() {
+            crate::wire::encode_field::<crate::wire::types::String>(16, &self.r#type, buf);
+        }
+    }
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        match field_number {
+            1 => self.name = crate::wire::decode_field::<crate::wire::types::String>(wire_type, buf, depth)?,
+            2 => self.id = crate::wire::decode_field::<crate::wire::types::Int32>(wire_type, buf, depth)?,
+            3 => crate::wire::merge_repeated::<crate::wire::types::String>(wire_type, &mut self.emails, buf, depth)?,
+            4 => {
+                let (key, value) = crate::wire::decode_map_entry::<crate::wire::types::String, crate::wire::types::Sint64>(wire_type, buf, depth)?;
+                self.scores.insert(key, value);
+            }
+            5 => crate::wire::merge_message(wire_type, &mut **self.address.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            6 => self.kind = crate::wire::decode_field::<crate::wire::types::Enum>(wire_type, buf, depth)?,
+            7 => self.contact = ::core::option::Option::Some(person::Contact::Phone(crate::wire::decode_field::<crate::wire::types::String>(wire_type, buf, depth)?)),
+            8 => self.contact = ::core::option::Option::Some(person::Contact::Office(::std::boxed::Box::new(crate::wire::decode_field::<crate::wire::types::Msg<person::Address>>(wire_type, buf, depth)?))),
+            9 => self.height = ::core::option::Option::Some(crate::wire::decode_field::<crate::wire::types::Double>(wire_type, buf, depth)?),
+            10 => crate::wire::merge_repeated::<crate::wire::types::Fixed32>(wire_type, &mut self.lucky, buf, depth)?,
+            11 => crate::wire::merge_repeated::<crate::wire::types::Int64>(wire_type, &mut self.counts, buf, depth)?,
+            12 => self.photo = crate::wire::decode_field::<crate::wire::types::Bytes>(wire_type, buf, depth)?,
+            13 => crate::wire::merge_repeated::<crate::wire::types::Msg<Person>>(wire_type, &mut self.friends, buf, depth)?,
+            14 => {
+                let (key, value) = crate::wire::decode_map_entry::<crate::wire::types::Int32, crate::wire::types::Msg<person::Address>>(wire_type, buf, depth)?;
+                self.homes.insert(key, value);
+            }
+            15 => self.active = crate::wire::decode_field::<crate::wire::types::Bool>(wire_type, buf, depth)?,
+            16 => self.r#type = crate::wire::decode_field::<crate::wire::types::String>(wire_type, buf, depth)?,
+            _ => crate::wire::skip_field(field_number, wire_type, buf)?,
+        }
+        ::core::result::Result::Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if !self.name.is_empty() {
+            len += crate::wire::field_len::<crate::wire::types::String>(1, &self.name);
+        }
+        if self.id != 0 {
+            len += crate::wire::field_len::<crate::wire::types::Int32>(2, &self.id);
+        }
+        len += crate::wire::repeated_len::<crate::wire::types::String>(3, &self.emails);
+        len += crate::wire::map_len::<crate::wire::types::String, crate::wire::types::Sint64>(4, &self.scores);
+        if let ::core::option::Option::Some(value) = &self.address {
+            len += crate::wire::field_len::<crate::wire::types::Msg<person::Address>>(5, value);
+        }
+        if self.kind != 0 {
+            len += crate::wire::field_len::<crate::wire::types::Enum>(6, &self.kind);
+        }
+        if let ::core::option::Option::Some(person::Contact::Phone(value)) = &self.contact {
+            len += crate::wire::field_len::<crate::wire::types::String>(7, value);
+        }
+        if let ::core::option::Option::Some(person::Contact::Office(value)) = &self.contact {
+            len += crate::wire::field_len::<crate::wire::types::Msg<person::Address>>(8, value);
+        }
+        if let ::core::option::Option::Some(value) = &self.height {
+            len += crate::wire::field_len::<crate::wire::types::Double>(9, value);
+        }
+        len += crate::wire::packed_len::<crate::wire::types::Fixed32>(10, &self.lucky);
+        len += crate::wire::repeated_len::<crate::wire::types::Int64>(11, &self.counts);
+        if !self.photo.is_empty() {
+            len += crate::wire::field_len::<crate::wire::types::Bytes>(12, &self.photo);
+        }
+        len += crate::wire::repeated_len::<crate::wire::types::Msg<Person>>(13, &self.friends);
+        len += crate::wire::map_len::<crate::wire::types::Int32, crate::wire::types::Msg<person::Address>>(14, &self.homes);
+        if self.active {
+            len += crate::wire::field_len::<crate::wire::types::Bool>(15, &self.active);
+        }
+        if !self.r#type.is_empty() {
+            len += crate::wire::field_len::<crate::wire::types::String>(16, &self.r#type);
+        }
+        len
+    }
+}
+
+/// Types nested in `Person`.
+pub mod person {
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Contact {
+        Phone(::std::string::String),
+        Office(::std::boxed::Box<Address>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct Address {
+        pub city: ::std::string::String,
+        pub owner: ::core::option::Option<::std::boxed::Box<super::Person>>,
+    }
+
+    impl crate::wire::WireMessage for Address {
+        fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+            if !self.city.is_empty() {
+                crate::wire::encode_field::<crate::wire::types::String>(1, &self.city, buf);
+            }
+            if let ::core::option::Option::Some(value) = &self.owner {
+                crate::wire::encode_field::<crate::wire::types::Msg<super::Person>>(2, value, buf);
+            }
+        }
+
+        fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+            match field_number {
+                1 => self.city = crate::wire::decode_field::<crate::wire::types::String>(wire_type, buf, depth)?,
+                2 => crate::wire::merge_message(wire_type, &mut **self.owner.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+                _ => crate::wire::skip_field(field_number, wire_type, buf)?,
+            }
+            ::core::result::Result::Ok(())
+        }
+
+        fn encoded_len(&self) -> usize {
+            let mut len = 0;
+            if !self.city.is_empty() {
+                len += crate::wire::field_len::<crate::wire::types::String>(1, &self.city);
+            }
+            if let ::core::option::Option::Some(value) = &self.owner {
+                len += crate::wire::field_len::<crate::wire::types::Msg<super::Person>>(2, value);
+            }
+            len
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+    #[repr(i32)]
+    pub enum Kind {
+        #[default]
+        Unknown = 0,
+        Friend = 1,
+        Family = 2,
+    }
+
+    impl Kind {
+        /// The value's name in the schema.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Kind::Unknown => "KIND_UNKNOWN",
+                Kind::Friend => "KIND_FRIEND",
+                Kind::Family => "KIND_FAMILY",
+            }
+        }
+
+        pub fn from_str_name(name: &str) -> ::core::option::Option<Self> {
+            match name {
+                "KIND_UNKNOWN" => ::core::option::Option::Some(Kind::Unknown),
+                "KIND_FRIEND" => ::core::option::Option::Some(Kind::Friend),
+                "KIND_FAMILY" => ::core::option::Option::Some(Kind::Family),
+                _ => ::core::option::Option::None,
+            }
+        }
+    }
+
+    impl ::core::convert::From<Kind> for i32 {
+        fn from(value: Kind) -> i32 {
+            value as i32
+        }
+    }
+
+    /// Values the schema doesn't name are returned as the error.
+    impl ::core::convert::TryFrom<i32> for Kind {
+        type Error = i32;
+
+        fn try_from(value: i32) -> ::core::result::Result<Self, i32> {
+            match value {
+                0 => ::core::result::Result::Ok(Kind::Unknown),
+                1 => ::core::result::Result::Ok(Kind::Friend),
+                2 => ::core::result::Result::Ok(Kind::Family),
+                _ => ::core::result::Result::Err(value),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Empty {}
+
+impl crate::wire::WireMessage for Empty {
+    fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], _depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        crate::wire::skip_field(field_number, wire_type, buf)
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[repr(i32)]
+pub enum Color {
+    #[default]
+    Red = 0,
+    Green = 1,
+}
+
+impl Color {
+    pub const CRIMSON: Color = Color::Red;
+
+    /// The value's name in the schema.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Color::Red => "RED",
+            Color::Green => "GREEN",
+        }
+    }
+
+    pub fn from_str_name(name: &str) -> ::core::option::Option<Self> {
+        match name {
+            "RED" => ::core::option::Option::Some(Color::Red),
+            "CRIMSON" => ::core::option::Option::Some(Color::Red),
+            "GREEN" => ::core::option::Option::Some(Color::Green),
+            _ => ::core::option::Option::None,
+        }
+    }
+}
+
+impl ::core::convert::From<Color> for i32 {
+    fn from(value: Color) -> i32 {
+        value as i32
+    }
+}
+
+/// Values the schema doesn't name are returned as the error.
+impl ::core::convert::TryFrom<i32> for Color {
+    type Error = i32;
+
+    fn try_from(value: i32) -> ::core::result::Result<Self, i32> {
+        match value {
+            0 => ::core::result::Result::Ok(Color::Red),
+            1 => ::core::result::Result::Ok(Color::Green),
+            _ => ::core::result::Result::Err(value),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// @generated by protos from names.proto. Do not edit.
+
+/// Messages named after items of the standard prelude, and after keywords
+/// Rust doesn't allow as raw identifiers.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Result {
+    pub value: ::std::string::String,
+    pub option: ::core::option::Option<Option>,
+    pub items: ::std::vec::Vec<Vec>,
+    pub self_: ::core::option::Option<Self_>,
+    pub crate_: ::core::option::Option<result::Crate>,
+}
+
+impl crate::wire::WireMessage for Result {
+    fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+        if !self.value.is_empty() {
+            crate::wire::encode_field::<crate::wire::types::String>(1, &self.value, buf);
+        }
+        if let ::core::option::Option::Some(value) = &self.option {
+            crate::wire::encode_field::<crate::wire::types::Msg<Option>>(2, value, buf);
+        }
+        crate::wire::encode_repeated::<crate::wire::types::Msg<Vec>>(3, &self.items, buf);
+        if let ::core::option::Option::Some(value) = &self.self_ {
+            crate::wire::encode_field::<crate::wire::types::Msg<Self_>>(4, value, buf);
+        }
+        if let ::core::option::Option::Some(result::Crate::Super(value)) = &self.crate_ {
+            crate::wire::encode_field::<crate::wire::types::Bytes>(5, value, buf);
+        }
+        if let ::core::option::Option::Some(result::Crate::Nested(value)) = &self.crate_ {
+            crate::wire::encode_field::<crate::wire::types::Msg<Result>>(6, value, buf);
+        }
+    }
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        match field_number {
+            1 => self.value = crate::wire::decode_field::<crate::wire::types::String>(wire_type, buf, depth)?,
+            2 => crate::wire::merge_message(wire_type, self.option.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            3 => crate::wire::merge_repeated::<crate::wire::types::Msg<Vec>>(wire_type, &mut self.items, buf, depth)?,
+            4 => crate::wire::merge_message(wire_type, self.self_.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            5 => self.crate_ = ::core::option::Option::Some(result::Crate::Super(crate::wire::decode_field::<crate::wire::types::Bytes>(wire_type, buf, depth)?)),
+            6 => self.crate_ = ::core::option::Option::Some(result::Crate::Nested(::std::boxed::Box::new(crate::wire::decode_field::<crate::wire::types::Msg<Result>>(wire_type, buf, depth)?))),
+            _ => crate::wire::skip_field(field_number, wire_type, buf)?,
+        }
+        ::core::result::Result::Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if !self.value.is_empty() {
+            len += crate::wire::field_len::<crate::wire::types::String>(1, &self.value);
+        }
+        if let ::core::option::Option::Some(value) = &self.option {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Option>>(2, value);
+        }
+        len += crate::wire::repeated_len::<crate::wire::types::Msg<Vec>>(3, &self.items);
+        if let ::core::option::Option::Some(value) = &self.self_ {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Self_>>(4, value);
+        }
+        if let ::core::option::Option::Some(result::Crate::Super(value)) = &self.crate_ {
+            len += crate::wire::field_len::<crate::wire::types::Bytes>(5, value);
+        }
+        if let ::core::option::Option::Some(result::Crate::Nested(value)) = &self.crate_ {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Result>>(6, value);
+        }
+        len
+    }
+}
+
+/// Types nested in `Result`.
+pub mod result {
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Crate {
+        Super(::std::vec::Vec<u8>),
+        Nested(::std::boxed::Box<super::Result>),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Option {
+    pub default: ::core::option::Option<Default>,
+    pub r#box: ::core::option::Option<Box>,
+    pub string: ::core::option::Option<String>,
+}
+
+impl crate::wire::WireMessage for Option {
+    fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+        if let ::core::option::Option::Some(value) = &self.default {
+            crate::wire::encode_field::<crate::wire::types::Msg<Default>>(1, value, buf);
+        }
+        if let ::core::option::Option::Some(value) = &self.r#box {
+            crate::wire::encode_field::<crate::wire::types::Msg<Box>>(2, value, buf);
+        }
+        if let ::core::option::Option::Some(value) = &self.string {
+            crate::wire::encode_field::<crate::wire::types::Msg<String>>(3, value, buf);
+        }
+    }
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        match field_number {
+            1 => crate::wire::merge_message(wire_type, self.default.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            2 => crate::wire::merge_message(wire_type, self.r#box.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            3 => crate::wire::merge_message(wire_type, self.string.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            _ => crate::wire::skip_field(field_number, wire_type, buf)?,
+        }
+        ::core::result::Result::Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if let ::core::option::Option::Some(value) = &self.default {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Default>>(1, value);
+        }
+        if let ::core::option::Option::Some(value) = &self.r#box {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Box>>(2, value);
+        }
+        if let ::core::option::Option::Some(value) = &self.string {
+            len += crate::wire::field_len::<crate::wire::types::Msg<String>>(3, value);
+        }
+        len
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Vec {}
+
+impl crate::wire::WireMessage for Vec {
+    fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], _depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        crate::wire::skip_field(field_number, wire_type, buf)
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Box {}
+
+impl crate::wire::WireMessage for Box {
+    fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], _depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        crate::wire::skip_field(field_number, wire_type, buf)
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct String {}
+
+impl crate::wire::WireMessage for String {
+    fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], _depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        crate::wire::skip_field(field_number, wire_type, buf)
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Default {}
+
+impl crate::wire::WireMessage for Default {
+    fn encode(&self, _buf: &mut ::std::vec::Vec<u8>) {}
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], _depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        crate::wire::skip_field(field_number, wire_type, buf)
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Self_ {
+    pub parent: ::core::option::Option<::std::boxed::Box<Self_>>,
+    pub children: ::std::collections::HashMap<::std::string::String, Self_>,
+}
+
+impl crate::wire::WireMessage for Self_ {
+    fn encode(&self, buf: &mut ::std::vec::Vec<u8>) {
+        if let ::core::option::Option::Some(value) = &self.parent {
+            crate::wire::encode_field::<crate::wire::types::Msg<Self_>>(1, value, buf);
+        }
+        crate::wire::encode_map::<crate::wire::types::String, crate::wire::types::Msg<Self_>>(2, &self.children, buf);
+    }
+
+    fn merge_field(&mut self, field_number: u32, wire_type: crate::wire::WireType, buf: &mut &[u8], depth: usize) -> ::core::result::Result<(), crate::wire::Error> {
+        match field_number {
+            1 => crate::wire::merge_message(wire_type, &mut **self.parent.get_or_insert_with(::core::default::Default::default), buf, depth)?,
+            2 => {
+                let (key, value) = crate::wire::decode_map_entry::<crate::wire::types::String, crate::wire::types::Msg<Self_>>(wire_type, buf, depth)?;
+                self.children.insert(key, value);
+            }
+            _ => crate::wire::skip_field(field_number, wire_type, buf)?,
+        }
+        ::core::result::Result::Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if let ::core::option::Option::Some(value) = &self.parent {
+            len += crate::wire::field_len::<crate::wire::types::Msg<Self_>>(1, value);
+        }
+        len += crate::wire::map_len::<crate::wire::types::String, crate::wire::types::Msg<Self_>>(2, &self.children);
+        len
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[repr(i32)]
+pub enum Ok {
+    #[default]
+    Self_ = 0,
+    Err = 1,
+    Some = 2,
+}
+
+impl Ok {
+    /// The value's name in the schema.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Ok::Self_ => "SELF",
+            Ok::Err => "Err",
+            Ok::Some => "Some",
+        }
+    }
+
+    pub fn from_str_name(name: &str) -> ::core::option::Option<Self> {
+        match name {
+            "SELF" => ::core::option::Option::Some(Ok::Self_),
+            "Err" => ::core::option::Option::Some(Ok::Err),
+            "Some" => ::core::option::Option::Some(Ok::Some),
+            _ => ::core::option::Option::None,
+        }
+    }
+}
+
+impl ::core::convert::From<Ok> for i32 {
+    fn from(value: Ok) -> i32 {
+        value as i32
+    }
+}
+
+/// Values the schema doesn't name are returned as the error.
+impl ::core::convert::TryFrom<i32> for Ok {
+    type Error = i32;
+
+    fn try_from(value: i32) -> ::core::result::Result<Self, i32> {
+        match value {
+            0 => ::core::result::Result::Ok(Ok::Self_),
+            1 => ::core::result::Result::Ok(Ok::Err),
+            2 => ::core::result::Result::Ok(Ok::Some),
+            _ => ::core::result::Result::Err(value),
+        }
+    }
+}
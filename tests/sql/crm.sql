@@ -0,0 +1,92 @@
+-- @generated by protos from crm.proto. Do not edit.
+
+CREATE TABLE address (
+  id INTEGER,
+  street TEXT,
+  city TEXT,
+  PRIMARY KEY (id)
+);
+
+-- Extra lines, such as a building.
+CREATE TABLE address_lines (
+  address_id INTEGER NOT NULL,
+  position INTEGER NOT NULL,
+  value TEXT,
+  PRIMARY KEY (address_id, position),
+  FOREIGN KEY (address_id) REFERENCES address (id) ON DELETE CASCADE
+);
+
+CREATE TABLE phone (
+  id INTEGER,
+  number TEXT,
+  kind TEXT CHECK (kind IN ('KIND_UNSPECIFIED', 'MOBILE', 'HOME')),
+  PRIMARY KEY (id)
+);
+
+-- A customer account.
+CREATE TABLE customer (
+  email TEXT NOT NULL,
+  name TEXT,
+  tier TEXT CHECK (tier IN ('TIER_UNSPECIFIED', 'GOLD')),
+  billing_street TEXT,
+  billing_city TEXT,
+  created TEXT,
+  score INTEGER,
+  avatar BLOB,
+  fax TEXT,
+  office_street TEXT,
+  office_city TEXT,
+  PRIMARY KEY (email)
+);
+
+-- Extra lines, such as a building.
+CREATE TABLE customer_billing_lines (
+  customer_email TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  value TEXT,
+  PRIMARY KEY (customer_email, position),
+  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE
+);
+
+CREATE TABLE customer_tags (
+  customer_email TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  value TEXT,
+  PRIMARY KEY (customer_email, position),
+  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE
+);
+
+CREATE TABLE customer_phones (
+  customer_email TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  number TEXT,
+  kind TEXT CHECK (kind IN ('KIND_UNSPECIFIED', 'MOBILE', 'HOME')),
+  PRIMARY KEY (customer_email, position),
+  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE
+);
+
+-- Store credit by reason.
+CREATE TABLE customer_credits (
+  customer_email TEXT NOT NULL,
+  "key" TEXT NOT NULL,
+  value INTEGER,
+  PRIMARY KEY (customer_email, "key"),
+  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE
+);
+
+-- Extra lines, such as a building.
+CREATE TABLE customer_office_lines (
+  customer_email TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  value TEXT,
+  PRIMARY KEY (customer_email, position),
+  FOREIGN KEY (customer_email) REFERENCES customer (email) ON DELETE CASCADE
+);
+
+CREATE TABLE visit (
+  id INTEGER,
+  at INTEGER,
+  minutes REAL,
+  "returning" INTEGER,
+  PRIMARY KEY (id)
+);
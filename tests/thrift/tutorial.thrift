@@ -0,0 +1,67 @@
+/*
+ * A calculator service, after the Apache Thrift tutorial.
+ */
+
+include "shared.thrift"
+
+namespace * tutorial
+namespace java com.example.tutorial
+namespace py tutorial.gen
+
+typedef i32 MyInteger
+typedef list<string> Names
+
+const i32 INT32CONSTANT = 9853
+const map<string, string> MAPCONSTANT = {'hello': 'world', 'goodnight': 'moon'}
+
+enum Operation {
+  ADD = 1,
+  SUBTRACT = 2,
+  MULTIPLY = 3,
+  DIVIDE = 4
+}
+
+enum Status {
+  UNKNOWN,
+  OK,
+}
+
+enum Outcome {
+  UNKNOWN = 0;
+  SUCCESS = 1;
+}
+
+struct Work {
+  1: i32 num1 = 0,
+  2: i32 num2,
+  3: Operation op,
+  4: optional string comment,
+  5: required MyInteger priority
+  6: Names tags
+  7: set<i64> seen (go.tag = "json:\"seen\"")
+  8: map<string, double> weights
+  9: list<list<i32>> grid
+  10: map<Work, string> related
+  11: shared.SharedStruct shared
+  12: binary payload
+  i16 legacy
+}
+
+union Value {
+  1: string text
+  2: i64 number
+  3: list<string> words
+}
+
+exception InvalidOperation {
+  1: i32 whatOp,
+  2: string why
+}
+
+service Calculator extends shared.SharedService {
+  void ping(),
+  i32 add(1: i32 num1, 2: i32 num2),
+  i32 calculate(1: i32 logid, 2: Work w) throws (1: InvalidOperation ouch),
+  Work latest(),
+  oneway void zip()
+}
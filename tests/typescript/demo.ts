@@ -0,0 +1,424 @@
+// @generated by protos from demo.proto. Do not edit.
+
+import * as _runtime from "./runtime";
+
+/**
+ * A person in the address book.
+ */
+export interface Person {
+  name: string;
+  id: number;
+  emails: string[];
+  scores: { [key: string]: string };
+  address?: Person_Address | undefined;
+  /**
+   * How we know them.
+   */
+  kind: Person_Kind;
+  height?: number | undefined;
+  lucky: number[];
+  counts: string[];
+  photo: Uint8Array;
+  friends: Person[];
+  homes: { [key: number]: Person_Address };
+  active: boolean;
+  type: string;
+  contact?: { $case: "phone"; phone: string } | { $case: "office"; office: Person_Address } | undefined;
+}
+
+export const Person = {
+  encode(message: Person, writer: _runtime.Writer = new _runtime.Writer()): _runtime.Writer {
+    if (message.name !== "") {
+      writer.tag(1, 2).string(message.name);
+    }
+    if (message.id !== 0) {
+      writer.tag(2, 0).int32(message.id);
+    }
+    for (const value of message.emails) {
+      writer.tag(3, 2).string(value);
+    }
+    for (const [key, value] of Object.entries(message.scores)) {
+      const entry = new _runtime.Writer();
+      entry.tag(1, 2).string(key);
+      entry.tag(2, 0).sint64(value);
+      writer.tag(4, 2).bytes(entry.finish());
+    }
+    if (message.address !== undefined) {
+      writer.tag(5, 2).bytes(Person_Address.encode(message.address).finish());
+    }
+    if (message.kind !== 0) {
+      writer.tag(6, 0).int32(message.kind);
+    }
+    if (message.contact?.$case === "phone") {
+      writer.tag(7, 2).string(message.contact.phone);
+    }
+    if (message.contact?.$case === "office") {
+      writer.tag(8, 2).bytes(Person_Address.encode(message.contact.office).finish());
+    }
+    if (message.height !== undefined) {
+      writer.tag(9, 1).double(message.height);
+    }
+    if (message.lucky.length !== 0) {
+      const packed = new _runtime.Writer();
+      for (const value of message.lucky) {
+        packed.fixed32(value);
+      }
+      writer.tag(10, 2).bytes(packed.finish());
+    }
+    for (const value of message.counts) {
+      writer.tag(11, 0).int64(value);
+    }
+    if (message.photo.length !== 0) {
+      writer.tag(12, 2).bytes(message.photo);
+    }
+    for (const value of message.friends) {
+      writer.tag(13, 2).bytes(Person.encode(value).finish());
+    }
+    for (const [key, value] of Object.entries(message.homes)) {
+      const entry = new _runtime.Writer();
+      entry.tag(1, 0).int32(Number(key));
+      entry.tag(2, 2).bytes(Person_Address.encode(value).finish());
+      writer.tag(14, 2).bytes(entry.finish());
+    }
+    if (message.active) {
+      writer.tag(15, 0).bool(message.active);
+    }
+    if (message.type !== "") {
+      writer.tag(16, 2).string(message.type);
+    }
+    return writer;
+  },
+
+  decode(input: Uint8Array): Person {
+    const reader = new _runtime.Reader(input);
+    const message: Person = { name: "", id: 0, emails: [], scores: {}, kind: 0, lucky: [], counts: [], photo: new Uint8Array(0), friends: [], homes: {}, active: false, type: "" };
+    while (!reader.done) {
+      const tag = reader.uint32();
+      switch (tag >>> 3) {
+        case 1:
+          message.name = reader.string();
+          break;
+        case 2:
+          message.id = reader.int32();
+          break;
+        case 3:
+          message.emails.push(reader.string());
+          break;
+        case 4: {
+          const entry = new _runtime.Reader(reader.bytes());
+          let key = "";
+          let value = "0";
+          while (!entry.done) {
+            const entryTag = entry.uint32();
+            switch (entryTag >>> 3) {
+              case 1:
+                key = entry.string();
+                break;
+              case 2:
+                value = entry.sint64().toString();
+                break;
+              default:
+                entry.skip(entryTag & 7);
+            }
+          }
+          message.scores[key] = value;
+          break;
+        }
+        case 5:
+          message.address = Person_Address.decode(reader.bytes());
+          break;
+        case 6:
+          message.kind = reader.int32();
+          break;
+        case 7:
+          message.contact = { $case: "phone", phone: reader.string() };
+          break;
+        case 8:
+          message.contact = { $case: "office", office: Person_Address.decode(reader.bytes()) };
+          break;
+        case 9:
+          message.height = reader.double();
+          break;
+        case 10:
+          if ((tag & 7) === 2) {
+            const end = reader.uint32() + reader.pos;
+            while (reader.pos < end) {
+              message.lucky.push(reader.fixed32());
+            }
+          } else {
+            message.lucky.push(reader.fixed32());
+          }
+          break;
+        case 11:
+          if ((tag & 7) === 2) {
+            const end = reader.uint32() + reader.pos;
+            while (reader.pos < end) {
+              message.counts.push(reader.int64().toString());
+            }
+          } else {
+            message.counts.push(reader.int64().toString());
+          }
+          break;
+        case 12:
+          message.photo = reader.bytes();
+          break;
+        case 13:
+          message.friends.push(Person.decode(reader.bytes()));
+          break;
+        case 14: {
+          const entry = new _runtime.Reader(reader.bytes());
+          let key = 0;
+          let value = Person_Address.decode(new Uint8Array(0));
+          while (!entry.done) {
+            const entryTag = entry.uint32();
+            switch (entryTag >>> 3) {
+              case 1:
+                key = entry.int32();
+                break;
+              case 2:
+                value = Person_Address.decode(entry.bytes());
+                break;
+              default:
+                entry.skip(entryTag & 7);
+            }
+          }
+          message.homes[key] = value;
+          break;
+        }
+        case 15:
+          message.active = reader.bool();
+          break;
+        case 16:
+          message.type = reader.string();
+          break;
+        default:
+          reader.skip(tag & 7);
+      }
+    }
+    return message;
+  },
+
+  fromJSON(object: any): Person {
+    return {
+      name: _runtime.isSet(object.name) ? String(object.name) : "",
+      id: _runtime.isSet(object.id) ? Number(object.id) : 0,
+      emails: Array.isArray(object.emails) ? object.emails.map((value: any) => String(value)) : [],
+      scores: _runtime.isObject(object.scores) ? Object.fromEntries(Object.entries(object.scores).map(([key, value]) => [key, String(value)])) : {},
+      address: _runtime.isSet(object.address) ? Person_Address.fromJSON(object.address) : undefined,
+      kind: _runtime.isSet(object.kind) ? Person_KindFromJSON(object.kind) : 0,
+      height: _runtime.isSet(object.height) ? Number(object.height) : undefined,
+      lucky: Array.isArray(object.lucky) ? object.lucky.map((value: any) => Number(value)) : [],
+      counts: Array.isArray(object.counts) ? object.counts.map((value: any) => String(value)) : [],
+      photo: _runtime.isSet(object.photo) ? _runtime.base64Decode(object.photo) : new Uint8Array(0),
+      friends: Array.isArray(object.friends) ? object.friends.map((value: any) => Person.fromJSON(value)) : [],
+      homes: _runtime.isObject(object.homes) ? Object.fromEntries(Object.entries(object.homes).map(([key, value]) => [key, Person_Address.fromJSON(value)])) : {},
+      active: _runtime.isSet(object.active) ? Boolean(object.active) : false,
+      type: _runtime.isSet(object.type) ? String(object.type) : "",
+      contact: _runtime.isSet(object.phone) ? { $case: "phone", phone: String(object.phone) } : _runtime.isSet(object.office) ? { $case: "office", office: Person_Address.fromJSON(object.office) } : undefined,
+    };
+  },
+
+  toJSON(message: Person): unknown {
+    const object: any = {};
+    if (message.name !== "") {
+      object.name = message.name;
+    }
+    if (message.id !== 0) {
+      object.id = message.id;
+    }
+    if (message.emails.length !== 0) {
+      object.emails = message.emails;
+    }
+    if (Object.keys(message.scores).length !== 0) {
+      object.scores = Object.fromEntries(Object.entries(message.scores).map(([key, value]) => [key, String(value)]));
+    }
+    if (message.address !== undefined) {
+      object.address = Person_Address.toJSON(message.address);
+    }
+    if (message.kind !== 0) {
+      object.kind = Person_KindToJSON(message.kind);
+    }
+    if (message.height !== undefined) {
+      object.height = _runtime.jsonNumber(message.height);
+    }
+    if (message.lucky.length !== 0) {
+      object.lucky = message.lucky;
+    }
+    if (message.counts.length !== 0) {
+      object.counts = message.counts.map((value) => String(value));
+    }
+    if (message.photo.length !== 0) {
+      object.photo = _runtime.base64Encode(message.photo);
+    }
+    if (message.friends.length !== 0) {
+      object.friends = message.friends.map((value) => Person.toJSON(value));
+    }
+    if (Object.keys(message.homes).length !== 0) {
+      object.homes = Object.fromEntries(Object.entries(message.homes).map(([key, value]) => [key, Person_Address.toJSON(value)]));
+    }
+    if (message.active) {
+      object.active = message.active;
+    }
+    if (message.type !== "") {
+      object.type = message.type;
+    }
+    if (message.contact?.$case === "phone") {
+      object.phone = message.contact.phone;
+    }
+    if (message.contact?.$case === "office") {
+      object.office = Person_Address.toJSON(message.contact.office);
+    }
+    return object;
+  },
+};
+
+export interface Person_Address {
+  city: string;
+  owner?: Person | undefined;
+}
+
+export const Person_Address = {
+  encode(message: Person_Address, writer: _runtime.Writer = new _runtime.Writer()): _runtime.Writer {
+    if (message.city !== "") {
+      writer.tag(1, 2).string(message.city);
+    }
+    if (message.owner !== undefined) {
+      writer.tag(2, 2).bytes(Person.encode(message.owner).finish());
+    }
+    return writer;
+  },
+
+  decode(input: Uint8Array): Person_Address {
+    const reader = new _runtime.Reader(input);
+    const message: Person_Address = { city: "" };
+    while (!reader.done) {
+      const tag = reader.uint32();
+      switch (tag >>> 3) {
+        case 1:
+          message.city = reader.string();
+          break;
+        case 2:
+          message.owner = Person.decode(reader.bytes());
+          break;
+        default:
+          reader.skip(tag & 7);
+      }
+    }
+    return message;
+  },
+
+  fromJSON(object: any): Person_Address {
+    return {
+      city: _runtime.isSet(object.city) ? String(object.city) : "",
+      owner: _runtime.isSet(object.owner) ? Person.fromJSON(object.owner) : undefined,
+    };
+  },
+
+  toJSON(message: Person_Address): unknown {
+    const object: any = {};
+    if (message.city !== "") {
+      object.city = message.city;
+    }
+    if (message.owner !== undefined) {
+      object.owner = Person.toJSON(message.owner);
+    }
+    return object;
+  },
+};
+
+export enum Person_Kind {
+  KIND_UNKNOWN = 0,
+  KIND_FRIEND = 1,
+  KIND_FAMILY = 2,
+}
+
+export function Person_KindFromJSON(object: any): Person_Kind {
+  switch (object) {
+    case 0:
+    case "KIND_UNKNOWN":
+      return Person_Kind.KIND_UNKNOWN;
+    case 1:
+    case "KIND_FRIEND":
+      return Person_Kind.KIND_FRIEND;
+    case 2:
+    case "KIND_FAMILY":
+      return Person_Kind.KIND_FAMILY;
+    default:
+      return typeof object === "number" ? object : Person_Kind.KIND_UNKNOWN;
+  }
+}
+
+export function Person_KindToJSON(object: Person_Kind): string | number {
+  switch (object) {
+    case Person_Kind.KIND_UNKNOWN:
+      return "KIND_UNKNOWN";
+    case Person_Kind.KIND_FRIEND:
+      return "KIND_FRIEND";
+    case Person_Kind.KIND_FAMILY:
+      return "KIND_FAMILY";
+    default:
+      return object;
+  }
+}
+
+export interface Empty {}
+
+export const Empty = {
+  encode(_message: Empty, writer: _runtime.Writer = new _runtime.Writer()): _runtime.Writer {
+    return writer;
+  },
+
+  decode(input: Uint8Array): Empty {
+    const reader = new _runtime.Reader(input);
+    const message: Empty = {};
+    while (!reader.done) {
+      const tag = reader.uint32();
+      switch (tag >>> 3) {
+        default:
+          reader.skip(tag & 7);
+      }
+    }
+    return message;
+  },
+
+  fromJSON(_object: any): Empty {
+    return {};
+  },
+
+  toJSON(_message: Empty): unknown {
+    const object: any = {};
+    return object;
+  },
+};
+
+export enum Color {
+  RED = 0,
+  CRIMSON = 0,
+  GREEN = 1,
+}
+
+export function ColorFromJSON(object: any): Color {
+  switch (object) {
+    case 0:
+    case "RED":
+      return Color.RED;
+    case "CRIMSON":
+      return Color.RED;
+    case 1:
+    case "GREEN":
+      return Color.GREEN;
+    default:
+      return typeof object === "number" ? object : Color.RED;
+  }
+}
+
+export function ColorToJSON(object: Color): string | number {
+  switch (object) {
+    case Color.RED:
+      return "RED";
+    case Color.GREEN:
+      return "GREEN";
+    default:
+      return object;
+  }
+}
@@ -0,0 +1,204 @@
+// Node module hooks that run the TypeScript the generator writes, by
+// stripping its types, on versions of node that can't run TypeScript
+// themselves. They handle the constructs the generated modules and
+// `runtime.ts` use, not TypeScript in general. Register them with
+// `module.register` and import modules without the `.ts` extension, as
+// generated code does.
+
+export async function resolve(specifier, context, next) {
+  const relative = specifier.startsWith(".") && !/\.[cm]?[jt]s$/.test(specifier);
+  return next(relative ? specifier + ".ts" : specifier, context);
+}
+
+export async function load(url, context, next) {
+  if (!url.endsWith(".ts")) {
+    return next(url, context);
+  }
+  const { source } = await next(url, { ...context, format: "module" });
+  return { format: "module", source: strip(String(source)), shortCircuit: true };
+}
+
+const PUNCTUATION = [
+  ">>>=", "===", "!==", ">>>", "...", "<<=", ">>=", "=>", "==", "!=", "<=", ">=", "&&", "||",
+  "??", "?.", "++", "--", "+=", "-=", "*=", "/=", "|=", "&=", "^=", "<<", ">>",
+];
+// Tokens after which `/` starts a regular expression rather than dividing.
+const BEFORE_REGEX = new Set(["(", ",", "=", ":", "[", "!", "?", "{", "}", ";", "&&", "||", "=>", "return"]);
+const MODIFIERS = new Set(["private", "public", "protected", "readonly"]);
+const CONTROL = new Set(["if", "for", "while", "switch", "catch", "with"]);
+const OPEN = { "(": ")", "[": "]", "{": "}", "<": ">" };
+const CLOSE = new Set([")", "]", "}", ">"]);
+
+function tokenize(src) {
+  const tokens = [];
+  let last = null;
+  let i = 0;
+  while (i < src.length) {
+    const start = i;
+    const c = src[i];
+    let space = false;
+    if (/\s/.test(c)) {
+      while (i < src.length && /\s/.test(src[i])) i++;
+      space = true;
+    } else if (src.startsWith("//", i)) {
+      i = src.indexOf("\n", i);
+      if (i < 0) i = src.length;
+      space = true;
+    } else if (src.startsWith("/*", i)) {
+      i = src.indexOf("*/", i) + 2;
+      space = true;
+    } else if (c === '"' || c === "'" || c === "`") {
+      i++;
+      while (src[i] !== c) i += src[i] === "\\" ? 2 : 1;
+      i++;
+    } else if (/[A-Za-z_$]/.test(c)) {
+      while (i < src.length && /[\w$]/.test(src[i])) i++;
+    } else if (/[0-9]/.test(c)) {
+      while (i < src.length && /[\w.]/.test(src[i])) i++;
+    } else if (c === "/" && (last === null || BEFORE_REGEX.has(last))) {
+      i++;
+      for (let inClass = false; inClass || src[i] !== "/"; i++) {
+        if (src[i] === "\\") i++;
+        else if (src[i] === "[") inClass = true;
+        else if (src[i] === "]") inClass = false;
+      }
+      i++;
+      while (/[a-z]/.test(src[i])) i++;
+    } else {
+      i += (PUNCTUATION.find((p) => src.startsWith(p, i)) ?? c).length;
+    }
+    const text = src.slice(start, i);
+    tokens.push({ text, out: text, space });
+    if (!space) last = text;
+  }
+  return tokens;
+}
+
+/** Strips the types from `src`, leaving JavaScript. */
+export function strip(src) {
+  const tokens = tokenize(src);
+  const sig = [];
+  tokens.forEach((token, i) => token.space || sig.push(i));
+  const text = (k) => (k >= 0 && k < sig.length ? tokens[sig[k]].text : null);
+  const remove = (from, to) => {
+    for (let i = sig[from]; i <= sig[to]; i++) tokens[i].out = "";
+  };
+  const matching = (k) => {
+    let depth = 0;
+    for (; k < sig.length; k++) {
+      if (OPEN[text(k)] && text(k) !== "<") depth++;
+      else if (CLOSE.has(text(k)) && text(k) !== ">" && --depth === 0) return k;
+    }
+    return k;
+  };
+  // The end of the type starting at `k`: the last token before one of
+  // `stops` outside brackets.
+  const typeEnd = (k, stops) => {
+    let depth = 0;
+    for (; k < sig.length; k++) {
+      if (depth === 0 && stops.includes(text(k))) return k - 1;
+      if (OPEN[text(k)]) depth++;
+      else if (CLOSE.has(text(k))) depth--;
+    }
+    return k - 1;
+  };
+  const stripAnnotation = (k, stops) => {
+    if (text(k) === "?" && text(k + 1) === ":") remove(k, k++);
+    if (text(k) === ":") remove(k, typeEnd(k + 1, stops));
+  };
+
+  // What each open bracket is: "params", "class", "enum" or "other".
+  const stack = [];
+  let pending = null;
+  // The parameter properties of a constructor, assigned in its body.
+  let properties = null;
+  for (let k = 0; k < sig.length; k++) {
+    const t = text(k);
+    const prev = text(k - 1);
+    const top = stack[stack.length - 1];
+    const atStatement = prev === null || ["export", ";", "{", "}"].includes(prev);
+
+    if ((t === "interface" || t === "type") && atStatement && /^\w/.test(text(k + 1) ?? "")) {
+      const start = prev === "export" ? k - 1 : k;
+      const end = t === "interface" ? matching(k + 2) : typeEnd(k + 2, [";"]) + 1;
+      remove(start, end);
+      k = end;
+      continue;
+    }
+    if (t === "enum" && atStatement) {
+      tokens[sig[k]].out = "const";
+      tokens[sig[k + 1]].out += " =";
+      pending = "enum";
+      continue;
+    }
+    if (t === "class") {
+      pending = "class";
+    }
+    if (top?.kind === "enum" && t === "=" && ["{", ","].includes(text(k - 2))) {
+      tokens[sig[k]].out = ":";
+    }
+    if (top?.kind === "class" && ["{", ";", "}"].includes(prev)) {
+      let n = k;
+      while (MODIFIERS.has(text(n))) n++;
+      if (n > k) remove(k, n - 1);
+      if (text(n + 1) === ":" || (text(n + 1) === "?" && text(n + 2) === ":")) {
+        stripAnnotation(n + 1, ["=", ";"]);
+      }
+    }
+    if (["const", "let", "var"].includes(t) && /^\w/.test(text(k + 1))) {
+      stripAnnotation(k + 2, ["=", ";", ","]);
+    }
+    if (top?.kind === "params" && (prev === "(" || prev === ",") && /^\w/.test(t)) {
+      let n = k;
+      while (MODIFIERS.has(text(n))) n++;
+      if (n > k) {
+        remove(k, n - 1);
+        top.properties.push(text(n));
+      }
+      stripAnnotation(n + 1, [",", ")", "="]);
+    }
+
+    if (t === "(") {
+      const close = matching(k);
+      const after = text(close + 1);
+      const member =
+        ["{", ",", ";", "}", "get", "set", "static", "async"].includes(text(k - 2)) ||
+        MODIFIERS.has(text(k - 2));
+      const definition =
+        after === "=>" ||
+        (/^\w/.test(prev) && !CONTROL.has(prev) &&
+          (text(k - 2) === "function" || (member && ["class", "object"].includes(top?.kind))) &&
+          (after === "{" || after === ":"));
+      stack.push({ kind: definition ? "params" : "other", properties: [] });
+    } else if (t === "{") {
+      let kind = pending ?? "other";
+      if (!pending && ["=", "(", ",", ":", "?", "return", "=>"].includes(prev)) kind = "object";
+      pending = null;
+      stack.push({ kind });
+      if (properties) {
+        tokens[sig[k]].out += properties.map((name) => ` this.${name} = ${name};`).join("");
+        properties = null;
+      }
+    } else if (t === "[") {
+      stack.push({ kind: "other" });
+    } else if (t === ")" || t === "]" || t === "}") {
+      const closed = stack.pop();
+      if (t === "}" && closed?.kind === "enum") tokens[sig[k]].out += ";";
+      if (closed?.kind === "params") {
+        if (text(k + 1) === ":") remove(k + 1, typeEnd(k + 2, ["{", "=>"]));
+        if (closed.properties.length) properties = closed.properties;
+      }
+    }
+  }
+
+  const out = tokens.map((token) => token.out).join("");
+  // Names only used as types are no longer exported.
+  return out.replace(/import \{([^}]*)\} from ("[^"]*");/g, (statement, names, from) => {
+    const rest = out.replace(statement, "");
+    const used = names
+      .split(",")
+      .map((name) => name.trim())
+      .filter((name) => name && new RegExp(`\\b${name}\\b`).test(rest));
+    return used.length ? `import { ${used.join(", ")} } from ${from};` : `import ${from};`;
+  });
+}